@@ -0,0 +1,15 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D frame;
+
+layout(push_constant) uniform Filter {
+    mat3 matrix;
+} palette;
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+void main() {
+    vec4 sampled = texture(frame, uv);
+    color = vec4(palette.matrix * sampled.rgb, sampled.a);
+}
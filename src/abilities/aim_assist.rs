@@ -0,0 +1,141 @@
+use amethyst::{
+    core::math::Vector2,
+    input::{Axis, Button, InputHandler, StringBindings},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::player::binding_name;
+
+/// How much targeting favours enemies the players aim at, read from `aim_assist.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AimAssist {
+    /// Whether players moving with a gamepad get assisted.
+    pub gamepad: bool,
+    /// Whether players moving with the keyboard get assisted, switched in the options menu.
+    pub keyboard: bool,
+    /// Degrees either side of the aim an enemy may be to be favoured.
+    pub cone: f32,
+    /// From `0`, where aim makes no difference, to `1`, where an enemy being aimed at always
+    /// wins over those that aren't.
+    pub strength: f32,
+}
+
+impl Default for AimAssist {
+    fn default() -> Self {
+        AimAssist {
+            gamepad: true,
+            keyboard: false,
+            cone: 30.,
+            strength: 0.5,
+        }
+    }
+}
+
+impl AimAssist {
+    /// Whether the player at `index` gets assisted, going by the device they move with.
+    pub fn assists(&self, input: &InputHandler<StringBindings>, index: usize) -> bool {
+        if uses_gamepad(input, index) {
+            self.gamepad
+        } else {
+            self.keyboard
+        }
+    }
+
+    /// Whether `offset` from the player lies within the cone around the unit vector `aim`.
+    pub fn in_cone(&self, aim: Vector2<f32>, offset: Vector2<f32>) -> bool {
+        let distance = offset.norm();
+        distance > 0. && aim.dot(&offset) / distance >= self.cone.to_radians().cos()
+    }
+
+    /// How far away a target `offset` from the player counts as when picking the nearest,
+    /// given the unit vector `aim` if the player is aiming. Targets in the cone count as
+    /// closer by `strength`.
+    pub fn weighted_distance(&self, aim: Option<Vector2<f32>>, offset: Vector2<f32>) -> f32 {
+        let distance = offset.norm();
+        match aim {
+            Some(aim) if self.in_cone(aim, offset) => distance * (1. - self.strength.clamp(0., 1.)),
+            _ => distance,
+        }
+    }
+}
+
+/// Whether the player at `index` moves with a gamepad rather than the keyboard.
+pub fn uses_gamepad(input: &InputHandler<StringBindings>, index: usize) -> bool {
+    let is_controller = |button: &Button| matches!(button, Button::Controller(..));
+    input
+        .bindings
+        .axis(binding_name("horizontal", index).as_str())
+        .is_some_and(|axis| match axis {
+            Axis::Controller { .. } => true,
+            Axis::Emulated { pos, neg } => is_controller(pos) || is_controller(neg),
+            Axis::MouseWheel { .. } => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, World};
+
+    use super::*;
+    use crate::{abilities::auto_attack::nearest_target, sight::SightGrid};
+
+    fn right() -> Vector2<f32> {
+        Vector2::new(1., 0.)
+    }
+
+    #[test]
+    fn an_aimed_at_enemy_counts_as_closer_by_the_strength() {
+        let assist = AimAssist {
+            cone: 30.,
+            strength: 0.25,
+            ..AimAssist::default()
+        };
+        // About 10 degrees off the aim, so in the cone.
+        let inside = Vector2::new(80., 14.);
+        assert!(assist.in_cone(right(), inside));
+        let distance = inside.norm();
+        assert!((assist.weighted_distance(Some(right()), inside) - distance * 0.75).abs() < 1e-4);
+        assert_eq!(assist.weighted_distance(None, inside), distance);
+
+        // 45 degrees off, so outside it.
+        let outside = Vector2::new(40., 40.);
+        assert!(!assist.in_cone(right(), outside));
+        assert_eq!(
+            assist.weighted_distance(Some(right()), outside),
+            outside.norm()
+        );
+    }
+
+    #[test]
+    fn the_aimed_at_enemy_wins_over_a_nearer_one_outside_the_cone() {
+        let mut world = World::new();
+        let aimed = world.create_entity().build();
+        let nearer = world.create_entity().build();
+        let candidates = [
+            (aimed, Vector2::new(60., 5.)),
+            (nearer, Vector2::new(0., 40.)),
+        ];
+        let pick = |assist: AimAssist, aim| {
+            nearest_target(
+                Vector2::zeros(),
+                candidates.iter().copied(),
+                &SightGrid::default(),
+                |offset| assist.weighted_distance(aim, offset),
+            )
+            .map(|(entity, _)| entity)
+        };
+
+        let assist = AimAssist::default();
+        assert_eq!(pick(assist, Some(right())), Some(aimed));
+        assert_eq!(pick(assist, None), Some(nearer));
+        // Too weak a pull leaves the nearer one picked, aim or not.
+        let weak = AimAssist {
+            strength: 0.1,
+            ..assist
+        };
+        assert_eq!(pick(weak, Some(right())), Some(nearer));
+        // Aiming straight up puts the nearer one in the cone instead.
+        assert_eq!(pick(assist, Some(Vector2::new(0., 1.))), Some(nearer));
+    }
+}
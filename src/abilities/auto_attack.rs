@@ -0,0 +1,306 @@
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System, Write,
+        WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    shrev::EventChannel,
+};
+
+use super::{AimAssist, Cooldown};
+use crate::{
+    analog::AnalogResponse,
+    clock::GameClock,
+    combat::{DamageEvent, DamageKind, DamageType},
+    disabled::Disabled,
+    enemy::Enemy,
+    movement::{world_position, Facing},
+    player::{input_direction, Player},
+    sight::SightGrid,
+    sleep::Asleep,
+    spatial::SpatialGrid,
+};
+
+/// Strikes the nearest enemy in range and in sight each time the cooldown allows, while
+/// `AutoAttackMode` is on.
+#[derive(Clone, Copy, Debug)]
+pub struct AutoAttack {
+    /// World units an enemy may be away to be struck.
+    pub range: f32,
+    pub damage: f32,
+    pub damage_type: DamageType,
+    pub cooldown: Cooldown,
+}
+
+impl AutoAttack {
+    pub fn new(range: f32, damage: f32, cooldown: f32) -> Self {
+        AutoAttack {
+            range,
+            damage,
+            damage_type: DamageType::Physical,
+            cooldown: Cooldown::new(cooldown),
+        }
+    }
+}
+
+impl Component for AutoAttack {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Whether `AutoAttack`s fire, toggled with the `toggle_auto_attack` action.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AutoAttackMode {
+    pub enabled: bool,
+}
+
+/// Flips `AutoAttackMode::enabled` when the `toggle_auto_attack` action is pressed.
+#[derive(Default)]
+pub struct AutoAttackToggleSystem {
+    was_pressed: bool,
+}
+
+impl<'s> System<'s> for AutoAttackToggleSystem {
+    type SystemData = (
+        Write<'s, AutoAttackMode>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(&mut self, (mut mode, input): Self::SystemData) {
+        let pressed = input.action_is_down("toggle_auto_attack").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            mode.enabled = !mode.enabled;
+        }
+        self.was_pressed = pressed;
+    }
+}
+
+/// The closest of `candidates` to `origin` that `sight` can see from there, measuring how far
+/// each is with `distance` of its offset from `origin`.
+///
+/// Equally close candidates go to the lowest entity id, so the pick doesn't depend on the
+/// order the candidates come in.
+pub fn nearest_target(
+    origin: Vector2<f32>,
+    candidates: impl Iterator<Item = (Entity, Vector2<f32>)>,
+    sight: &SightGrid,
+    distance: impl Fn(Vector2<f32>) -> f32,
+) -> Option<(Entity, Vector2<f32>)> {
+    candidates
+        .filter(|(_, position)| sight.line_of_sight(origin, *position))
+        .min_by(|(a, a_position), (b, b_position)| {
+            let (a_distance, b_distance) =
+                (distance(a_position - origin), distance(b_position - origin));
+            a_distance
+                .partial_cmp(&b_distance)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.id().cmp(&b.id()))
+        })
+}
+
+/// Strikes with every ready `AutoAttack`. Players aiming with their movement input favour the
+/// enemies they aim at, as far as `AimAssist` lets them.
+pub struct AutoAttackSystem;
+
+impl<'s> System<'s> for AutoAttackSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Enemy>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Disabled>,
+        ReadStorage<'s, Asleep>,
+        WriteStorage<'s, AutoAttack>,
+        WriteStorage<'s, Facing>,
+        Read<'s, SpatialGrid>,
+        Read<'s, SightGrid>,
+        Read<'s, AutoAttackMode>,
+        Read<'s, AimAssist>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, AnalogResponse>,
+        Write<'s, EventChannel<DamageEvent>>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            enemies,
+            players,
+            transforms,
+            disabled,
+            asleep,
+            mut attacks,
+            mut facings,
+            grid,
+            sight,
+            mode,
+            assist,
+            input,
+            response,
+            mut damage,
+            clock,
+        ): Self::SystemData,
+    ) {
+        for (entity, attack, transform, facing, player, _, _) in (
+            &entities,
+            &mut attacks,
+            &transforms,
+            (&mut facings).maybe(),
+            players.maybe(),
+            !&disabled,
+            !&asleep,
+        )
+            .join()
+        {
+            attack.cooldown.tick(clock.delta_seconds());
+            if !mode.enabled || !attack.cooldown.is_ready() {
+                continue;
+            }
+
+            let origin = world_position(transform);
+            let candidates = grid
+                .within(origin, attack.range)
+                .filter(|(candidate, _)| *candidate != entity && enemies.contains(*candidate));
+            let aim = player
+                .filter(|player| assist.assists(&input, player.index))
+                .map(|player| input_direction(&input, &response, player.index))
+                .filter(|direction| *direction != Vector2::zeros())
+                .map(|direction| direction.normalize());
+            let distance = |offset| assist.weighted_distance(aim, offset);
+            let (target, position) = match nearest_target(origin, candidates, &sight, distance) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            if let Some(facing) = facing {
+                let direction = position - origin;
+                if direction != Vector2::zeros() {
+                    facing.0 = direction.normalize();
+                }
+            }
+            damage.single_write(DamageEvent {
+                source: Some(entity),
+                target,
+                amount: attack.damage,
+                kind: DamageKind::AutoAttack,
+                damage_type: attack.damage_type,
+            });
+            attack.cooldown.trigger();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+
+    /// A world with auto-attack on and an attacker at the origin.
+    fn world() -> (World, AutoAttackSystem, Entity) {
+        let mut world = World::new();
+        let mut system = AutoAttackSystem;
+        System::setup(&mut system, &mut world.res);
+        world.write_resource::<AutoAttackMode>().enabled = true;
+        let attacker = world
+            .create_entity()
+            .with(Transform::default())
+            .with(AutoAttack::new(100., 3., 0.5))
+            .build();
+        world
+            .write_resource::<SpatialGrid>()
+            .insert(attacker, Vector2::zeros());
+        (world, system, attacker)
+    }
+
+    fn spawn_at(world: &mut World, position: Vector2<f32>, enemy: bool) -> Entity {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(position.x, position.y, 0.);
+        let builder = world.create_entity().with(transform);
+        let entity = if enemy {
+            builder.with(Enemy).build()
+        } else {
+            builder.build()
+        };
+        world
+            .write_resource::<SpatialGrid>()
+            .insert(entity, position);
+        entity
+    }
+
+    /// Runs a quarter second step for each of `steps` and returns who was struck in each.
+    fn strikes(world: &mut World, system: &mut AutoAttackSystem, steps: usize) -> Vec<Vec<Entity>> {
+        let mut reader = world
+            .write_resource::<EventChannel<DamageEvent>>()
+            .register_reader();
+        (0..steps)
+            .map(|_| {
+                world.write_resource::<GameClock>().advance(0.25);
+                system.run_now(&world.res);
+                world
+                    .read_resource::<EventChannel<DamageEvent>>()
+                    .read(&mut reader)
+                    .map(|event| event.target)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_nearest_enemy_in_range_is_struck() {
+        let (mut world, mut system, _) = world();
+        spawn_at(&mut world, Vector2::new(30., 0.), true);
+        let nearest = spawn_at(&mut world, Vector2::new(-20., 10.), true);
+        spawn_at(&mut world, Vector2::new(5., 0.), false);
+        spawn_at(&mut world, Vector2::new(0., 150.), true);
+        assert_eq!(strikes(&mut world, &mut system, 1), [[nearest]]);
+    }
+
+    #[test]
+    fn equally_near_enemies_go_to_the_lowest_id() {
+        let (mut world, _, _) = world();
+        let first = spawn_at(&mut world, Vector2::new(0., 40.), true);
+        let second = spawn_at(&mut world, Vector2::new(40., 0.), true);
+        let candidates = vec![
+            (second, Vector2::new(40., 0.)),
+            (first, Vector2::new(0., 40.)),
+        ];
+        let picked = nearest_target(
+            Vector2::zeros(),
+            candidates.into_iter(),
+            &SightGrid::default(),
+            |offset| offset.norm(),
+        );
+        assert_eq!(picked.map(|(entity, _)| entity), Some(first));
+    }
+
+    #[test]
+    fn strikes_wait_for_the_cooldown_and_the_mode() {
+        let (mut world, mut system, _) = world();
+        let enemy = spawn_at(&mut world, Vector2::new(30., 0.), true);
+        let struck: Vec<bool> = strikes(&mut world, &mut system, 5)
+            .iter()
+            .map(|targets| targets == &[enemy])
+            .collect();
+        assert_eq!(struck, [true, false, true, false, true]);
+
+        world.write_resource::<AutoAttackMode>().enabled = false;
+        assert!(strikes(&mut world, &mut system, 4)
+            .iter()
+            .all(Vec::is_empty));
+    }
+
+    #[test]
+    fn disabled_attackers_hold_their_strikes() {
+        let (mut world, mut system, attacker) = world();
+        let enemy = spawn_at(&mut world, Vector2::new(30., 0.), true);
+        crate::disabled::disable(&mut world.write_storage(), attacker);
+        assert!(strikes(&mut world, &mut system, 2)
+            .iter()
+            .all(Vec::is_empty));
+        crate::disabled::enable(&mut world.write_storage(), attacker);
+        assert_eq!(strikes(&mut world, &mut system, 1), [[enemy]]);
+    }
+}
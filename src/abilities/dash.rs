@@ -0,0 +1,282 @@
+use amethyst::{
+    core::math::Vector2,
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Join, Read, ReadStorage, System, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+};
+
+use super::Cooldown;
+use crate::{
+    clock::GameClock,
+    combat::{grant_invulnerability, Invulnerable},
+    disabled::Disabled,
+    input_buffer::{ActionBuffer, InputBufferConfig},
+    movement::{Facing, Velocity},
+    player::{action_is_down, binding_name, Player},
+};
+
+/// A short burst of movement over a fixed distance, with invulnerability while it starts.
+///
+/// The dash only drives `Velocity`; the `MovementSystem` still resolves collisions, so a
+/// dash into a wall stops at the point of contact.
+#[derive(Clone, Copy, Debug)]
+pub struct Dash {
+    /// World units covered by a full, unobstructed dash.
+    pub distance: f32,
+    /// Seconds the dash takes to cover `distance`.
+    pub duration: f32,
+    /// Seconds of invulnerability granted when the dash starts.
+    pub invulnerability: f32,
+    pub cooldown: Cooldown,
+    remaining: f32,
+    direction: Vector2<f32>,
+}
+
+impl Dash {
+    pub fn new(distance: f32, duration: f32, invulnerability: f32, cooldown: f32) -> Self {
+        Dash {
+            distance,
+            duration,
+            invulnerability,
+            cooldown: Cooldown::new(cooldown),
+            remaining: 0.,
+            direction: Vector2::zeros(),
+        }
+    }
+
+    pub fn is_dashing(&self) -> bool {
+        self.remaining > 0.
+    }
+
+    fn start(&mut self, direction: Vector2<f32>) {
+        self.direction = direction;
+        self.remaining = self.duration;
+        self.cooldown.trigger();
+    }
+
+    /// The velocity that moves the entity this frame, then advances the dash by `delta`.
+    ///
+    /// The last frame is scaled down so the total distance never overshoots `distance`.
+    fn step(&mut self, delta: f32) -> Vector2<f32> {
+        if delta <= 0. {
+            return Vector2::zeros();
+        }
+        let active = delta.min(self.remaining);
+        self.remaining -= active;
+        self.direction * (self.distance / self.duration) * (active / delta)
+    }
+}
+
+impl Component for Dash {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Starts a dash in the input direction (or the facing direction when standing still) when
+/// the player's `dash` action is pressed, and overrides their velocity while it lasts. A press
+/// while the dash isn't ready is buffered, starting it once it is.
+#[derive(Default)]
+pub struct DashSystem {
+    presses: ActionBuffer,
+}
+
+impl<'s> System<'s> for DashSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Facing>,
+        ReadStorage<'s, Disabled>,
+        WriteStorage<'s, Dash>,
+        WriteStorage<'s, Velocity>,
+        WriteStorage<'s, Invulnerable>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, InputBufferConfig>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            players,
+            facings,
+            disabled,
+            mut dashes,
+            mut velocities,
+            mut invulnerables,
+            input,
+            buffer,
+            clock,
+        ): Self::SystemData,
+    ) {
+        let delta = clock.delta_seconds();
+        let now = clock.elapsed_seconds();
+        for (entity, player, facing, dash, velocity, _) in (
+            &entities,
+            &players,
+            facings.maybe(),
+            &mut dashes,
+            &mut velocities,
+            !&disabled,
+        )
+            .join()
+        {
+            let action = binding_name("dash", player.index);
+            let pressed = action_is_down(&input, "dash", player.index);
+            self.presses.update(&action, pressed, now);
+
+            dash.cooldown.tick(delta);
+
+            if !dash.is_dashing()
+                && dash.cooldown.is_ready()
+                && self.presses.consume(&action, now, buffer.window)
+            {
+                let direction = if velocity.0 != Vector2::zeros() {
+                    velocity.0.normalize()
+                } else {
+                    facing.copied().unwrap_or_default().0
+                };
+                dash.start(direction);
+                grant_invulnerability(&mut invulnerables, entity, dash.invulnerability);
+            }
+
+            if dash.is_dashing() {
+                velocity.0 = dash.step(delta);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        core::transform::Transform,
+        ecs::prelude::{Builder, RunNow, World},
+    };
+
+    use amethyst::{input::VirtualKeyCode, shrev::EventChannel};
+
+    use super::*;
+    use crate::{
+        collision::{Aabb, Collider, TileColliders},
+        combat::{
+            DamageEvent, DamageKind, DamageSystem, DamageType, Health, InvulnerabilitySystem,
+        },
+        movement::{world_position, MovementSystem},
+        player::tests::{bind, press_key},
+    };
+
+    /// Dashes right for `frames` steps of a tenth of a second, from the origin, past a wall
+    /// `wall` units away if there is one, and returns where the dash ends.
+    fn dash(frames: usize, wall: Option<f32>) -> Vector2<f32> {
+        let mut world = World::new();
+        let mut movement = MovementSystem;
+        System::setup(&mut movement, &mut world.res);
+        if let Some(x) = wall {
+            world.add_resource(TileColliders(vec![Aabb {
+                min: Vector2::new(x, -16.),
+                max: Vector2::new(x + 16., 16.),
+            }]));
+        }
+        world.write_resource::<GameClock>().advance(0.1);
+        let mover = world
+            .create_entity()
+            .with(Transform::default())
+            .with(Collider::new(4., 4.))
+            .with(Velocity(Vector2::zeros()))
+            .build();
+
+        let mut dash = Dash::new(48., 0.25, 0.1, 1.);
+        dash.start(Vector2::new(1., 0.));
+        for _ in 0..frames {
+            world.write_storage::<Velocity>().get_mut(mover).unwrap().0 = dash.step(0.1);
+            movement.run_now(&world.res);
+        }
+        let end = world_position(world.read_storage::<Transform>().get(mover).unwrap());
+        end
+    }
+
+    #[test]
+    fn a_dash_covers_its_distance_and_no_further() {
+        let end = dash(5, None);
+        assert!((end - Vector2::new(48., 0.)).norm() < 1e-4, "{:?}", end);
+    }
+
+    #[test]
+    fn a_dash_into_a_wall_stops_against_it() {
+        let end = dash(5, Some(20.));
+        assert!((end - Vector2::new(18., 0.)).norm() < 1e-4, "{:?}", end);
+    }
+
+    #[test]
+    fn a_dash_waits_out_its_cooldown() {
+        let mut dash = Dash::new(48., 0.25, 0.1, 1.);
+        dash.start(Vector2::new(1., 0.));
+        assert!(dash.is_dashing());
+        assert!(!dash.cooldown.is_ready());
+        dash.step(0.25);
+        assert!(!dash.is_dashing());
+        dash.cooldown.tick(1.);
+        assert!(dash.cooldown.is_ready());
+    }
+
+    #[test]
+    fn a_dash_holds_off_hits_for_its_invulnerability() {
+        let mut world = World::new();
+        let mut dashes = DashSystem::default();
+        let mut damage = DamageSystem::default();
+        let mut expiry = InvulnerabilitySystem;
+        System::setup(&mut dashes, &mut world.res);
+        System::setup(&mut damage, &mut world.res);
+        System::setup(&mut expiry, &mut world.res);
+        bind(
+            &mut world,
+            r#"(axes: {}, actions: {"dash": [[Key(Space)]]})"#,
+        );
+        let player = world
+            .create_entity()
+            .with(Player {
+                index: 0,
+                speed: 1.,
+            })
+            .with(Dash::new(48., 0.25, 0.5, 1.))
+            .with(Velocity(Vector2::new(1., 0.)))
+            .with(Health::new(10.))
+            .build();
+
+        let mut hit = |world: &World| {
+            world
+                .write_resource::<EventChannel<DamageEvent>>()
+                .single_write(DamageEvent {
+                    source: None,
+                    target: player,
+                    amount: 2.,
+                    kind: DamageKind::Debug,
+                    damage_type: DamageType::Physical,
+                });
+            damage.run_now(&world.res);
+            let health = world.read_storage::<Health>().get(player).unwrap().current;
+            health
+        };
+        let mut step = |world: &World| {
+            world.write_resource::<GameClock>().advance(0.25);
+            expiry.run_now(&world.res);
+        };
+
+        press_key(&mut world, VirtualKeyCode::Space, true);
+        world.write_resource::<GameClock>().advance(0.25);
+        dashes.run_now(&world.res);
+        let remaining = world
+            .read_storage::<Invulnerable>()
+            .get(player)
+            .map(|invulnerable| invulnerable.remaining);
+        assert_eq!(remaining, Some(0.5));
+        assert_eq!(hit(&world), 10.);
+
+        step(&world);
+        assert_eq!(hit(&world), 10.);
+        step(&world);
+        assert!(world.read_storage::<Invulnerable>().get(player).is_none());
+        assert_eq!(hit(&world), 8.);
+    }
+}
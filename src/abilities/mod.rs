@@ -0,0 +1,51 @@
+//! Player abilities and the cooldown bookkeeping they share.
+
+mod aim_assist;
+mod auto_attack;
+mod dash;
+
+pub use self::{
+    aim_assist::AimAssist,
+    auto_attack::{AutoAttack, AutoAttackSystem, AutoAttackToggleSystem},
+    dash::{Dash, DashSystem},
+};
+
+/// Tracks how long until an ability can be used again.
+#[derive(Clone, Copy, Debug)]
+pub struct Cooldown {
+    pub duration: f32,
+    remaining: f32,
+}
+
+impl Cooldown {
+    /// A cooldown of `duration` seconds that starts out ready.
+    pub fn new(duration: f32) -> Self {
+        Cooldown {
+            duration,
+            remaining: 0.,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.remaining <= 0.
+    }
+
+    /// Starts the cooldown over from its full duration.
+    pub fn trigger(&mut self) {
+        self.remaining = self.duration;
+    }
+
+    /// Makes the ability ready now.
+    pub fn reset(&mut self) {
+        self.remaining = 0.;
+    }
+
+    /// Seconds until the ability is ready again.
+    pub fn remaining(&self) -> f32 {
+        self.remaining
+    }
+
+    pub fn tick(&mut self, delta: f32) {
+        self.remaining = (self.remaining - delta).max(0.);
+    }
+}
@@ -0,0 +1,193 @@
+//! How analog sticks respond, read from `analog.ron`.
+//!
+//! Player movement reads its axes through `AnalogResponse::apply`, which ignores the small
+//! readings of a stick at rest, treats a stick pushed nearly all the way as pushed all the way,
+//! and bends the travel in between along a `ResponseCurve` for finer control near the centre.
+//! Keys always read as pushed all the way, so the response leaves them as they are.
+//!
+//! The response can be changed from the options menu. Changes are saved to
+//! `analog_saved.ron`, which is read instead of `analog.ron` from then on.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use amethyst::{
+    config::Config,
+    core::math::Vector2,
+    ecs::prelude::{Read, System},
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// The dead zones the options menu steps through.
+const DEAD_ZONE_STEP: f32 = 0.05;
+const MAX_INNER_DEAD_ZONE: f32 = 0.3;
+const MIN_OUTER_DEAD_ZONE: f32 = 0.7;
+
+/// How the travel between the dead zones maps to the strength of the input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    /// Squares the travel, giving finer control near the centre.
+    Quadratic,
+    /// `AnalogResponse::custom`.
+    Custom,
+}
+
+impl ResponseCurve {
+    /// The curve after this one when cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            ResponseCurve::Linear => ResponseCurve::Quadratic,
+            ResponseCurve::Quadratic => ResponseCurve::Custom,
+            ResponseCurve::Custom => ResponseCurve::Linear,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnalogResponse {
+    /// Stick readings up to this far from the centre read as no input.
+    pub inner_dead_zone: f32,
+    /// Stick readings from this far out read as pushed all the way.
+    pub outer_dead_zone: f32,
+    pub curve: ResponseCurve,
+    /// Points of the `ResponseCurve::Custom` curve as `(travel, strength)` pairs, both from 0 to
+    /// 1, in order of travel. The strength between them is interpolated.
+    pub custom: Vec<(f32, f32)>,
+}
+
+impl Default for AnalogResponse {
+    fn default() -> Self {
+        AnalogResponse {
+            inner_dead_zone: 0.15,
+            outer_dead_zone: 0.95,
+            curve: ResponseCurve::Linear,
+            custom: vec![(0., 0.), (1., 1.)],
+        }
+    }
+}
+
+impl AnalogResponse {
+    /// The response saved from the options menu, or the one at `path` if none has been saved.
+    pub fn initial(path: &Path, saved_path: &Path) -> Self {
+        Self::load_no_fallback(saved_path).unwrap_or_else(|_| Self::load(path))
+    }
+
+    /// The input `raw` stick readings amount to, no longer than one.
+    pub fn apply(&self, raw: Vector2<f32>) -> Vector2<f32> {
+        let distance = raw.norm();
+        if distance <= self.inner_dead_zone {
+            return Vector2::zeros();
+        }
+        let range = (self.outer_dead_zone - self.inner_dead_zone).max(f32::EPSILON);
+        let travel = ((distance - self.inner_dead_zone) / range).min(1.);
+        raw / distance * self.strength(travel)
+    }
+
+    /// The strength of input from a stick `travel` of the way from the inner dead zone to the
+    /// outer one, from 0 to 1 whatever the custom curve's points say.
+    pub fn strength(&self, travel: f32) -> f32 {
+        match self.curve {
+            ResponseCurve::Linear => travel,
+            ResponseCurve::Quadratic => travel * travel,
+            ResponseCurve::Custom => interpolate(&self.custom, travel).clamp(0., 1.),
+        }
+    }
+
+    /// Widens the inner dead zone by a step, going back to none after the widest.
+    pub fn cycle_inner_dead_zone(&mut self) {
+        self.inner_dead_zone = cycle(self.inner_dead_zone, 0., MAX_INNER_DEAD_ZONE);
+    }
+
+    /// Narrows the outer dead zone by a step, going back to none after the narrowest.
+    pub fn cycle_outer_dead_zone(&mut self) {
+        self.outer_dead_zone = 1. - cycle(1. - self.outer_dead_zone, 0., 1. - MIN_OUTER_DEAD_ZONE);
+    }
+}
+
+/// `value` rounded to a `DEAD_ZONE_STEP` and one step up, going back to `min` after `max`.
+fn cycle(value: f32, min: f32, max: f32) -> f32 {
+    let next = ((value / DEAD_ZONE_STEP).round() + 1.) * DEAD_ZONE_STEP;
+    if next > max + DEAD_ZONE_STEP / 2. {
+        min
+    } else {
+        next
+    }
+}
+
+/// The strength at `travel` along the line through `points`, holding the first and last
+/// strength before and after them.
+fn interpolate(points: &[(f32, f32)], travel: f32) -> f32 {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return travel,
+    };
+    if travel <= first.0 {
+        return first.1;
+    }
+    points
+        .windows(2)
+        .find(|pair| travel <= pair[1].0)
+        .map_or(last.1, |pair| {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if x1 <= x0 {
+                y1
+            } else {
+                y0 + (y1 - y0) * (travel - x0) / (x1 - x0)
+            }
+        })
+}
+
+/// Saves the `AnalogResponse` to `analog_saved.ron` whenever it changes.
+pub struct AnalogResponseSaveSystem {
+    saved_path: PathBuf,
+    saved: AnalogResponse,
+}
+
+impl AnalogResponseSaveSystem {
+    /// `saved` is the response as it was loaded.
+    pub fn new(saved_path: PathBuf, saved: AnalogResponse) -> Self {
+        AnalogResponseSaveSystem { saved_path, saved }
+    }
+}
+
+impl<'s> System<'s> for AnalogResponseSaveSystem {
+    type SystemData = Read<'s, AnalogResponse>;
+
+    fn run(&mut self, response: Self::SystemData) {
+        if *response == self.saved {
+            return;
+        }
+        self.saved = response.clone();
+        let result = ron::ser::to_string_pretty(&self.saved, Default::default())
+            .map_err(failure::Error::from)
+            .and_then(|text| fs::write(&self.saved_path, text).map_err(failure::Error::from));
+        if let Err(err) = result {
+            warn!("Failed to save the analog stick response: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_curves_are_kept_between_none_and_full() {
+        let response = AnalogResponse {
+            inner_dead_zone: 0.,
+            outer_dead_zone: 1.,
+            curve: ResponseCurve::Custom,
+            custom: vec![(0., -0.5), (0.5, 0.5), (1., 2.)],
+        };
+        assert_eq!(response.strength(0.), 0.);
+        assert_eq!(response.strength(0.5), 0.5);
+        assert_eq!(response.strength(1.), 1.);
+        assert_eq!(response.apply(Vector2::new(0., 1.)), Vector2::new(0., 1.));
+    }
+}
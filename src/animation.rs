@@ -0,0 +1,288 @@
+//! Looping sprite animations for entities, with events on the frames gameplay keys off.
+//!
+//! A `SpriteAnimation` is a list of sprites, each shown for `frame_duration` seconds of game
+//! time, like a tile animation. Any of its frames can be marked with named `events`, which are
+//! written to the `AnimationEvent` channel as the frame comes up, for systems to act on in
+//! step with what is drawn, such as a strike landing on the frame the blow does. A step long
+//! enough to pass several frames sends the events of all of them, in the order the frames came
+//! up, looping included.
+//!
+//! The `AnimationSoundSystem` plays the sounds `animation_sounds.ron` gives for events, from
+//! the entity they came from.
+
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    audio::Source,
+    core::transform::Transform,
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadExpect, ReadStorage,
+        Resources, System, SystemData, Write, WriteStorage,
+    },
+    renderer::SpriteRender,
+    shrev::{EventChannel, ReaderId},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock,
+    movement::world_position,
+    sleep::Asleep,
+    sound::{queue_sound_at, SoundQueue},
+};
+
+/// An event sent as the frame at `frame` in an animation's `frames` comes up.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FrameEvent {
+    pub frame: usize,
+    pub name: String,
+}
+
+/// A looping list of sprites from the entity's sprite sheet, some sending events.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpriteAnimation {
+    pub frames: Vec<usize>,
+    pub frame_duration: f32,
+    #[serde(default)]
+    pub events: Vec<FrameEvent>,
+}
+
+impl SpriteAnimation {
+    /// How many frames have come up after the first by `elapsed` seconds in, counting every
+    /// loop.
+    fn step(&self, elapsed: f64) -> u64 {
+        if self.frame_duration <= 0. {
+            return 0;
+        }
+        (elapsed / f64::from(self.frame_duration)) as u64
+    }
+
+    fn frame(&self, step: u64) -> usize {
+        (step % self.frames.len().max(1) as u64) as usize
+    }
+
+    /// The sprite to show `elapsed` seconds into the animation.
+    pub fn sprite_at(&self, elapsed: f64) -> Option<usize> {
+        self.frames.get(self.frame(self.step(elapsed))).copied()
+    }
+
+    /// The frames coming up after `from` seconds in, up to and including `to`, counting every
+    /// loop. With no `from`, the animation is starting and its first frame comes up too.
+    fn steps_between(&self, from: Option<f64>, to: f64) -> RangeInclusive<u64> {
+        let first = from.map_or(0, |from| self.step(from) + 1);
+        first..=self.step(to)
+    }
+
+    /// The names of the events of the frames coming up after `from` seconds in, up to and
+    /// including `to`, in the order they come up.
+    pub fn events_between(&self, from: Option<f64>, to: f64) -> Vec<&str> {
+        let mut names = Vec::new();
+        if self.events.is_empty() || self.frames.is_empty() {
+            return names;
+        }
+        for step in self.steps_between(from, to) {
+            let frame = self.frame(step);
+            names.extend(
+                self.events
+                    .iter()
+                    .filter(|event| event.frame == frame)
+                    .map(|event| event.name.as_str()),
+            );
+        }
+        names
+    }
+}
+
+/// Plays `animation` on the entity's sprite.
+#[derive(Clone, Debug)]
+pub struct Animated {
+    pub animation: SpriteAnimation,
+    /// Seconds of game time the animation has played, or `None` until it starts.
+    elapsed: Option<f64>,
+}
+
+impl Animated {
+    pub fn new(animation: SpriteAnimation) -> Self {
+        Animated {
+            animation,
+            elapsed: None,
+        }
+    }
+}
+
+impl Component for Animated {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Sent as a frame marked with the event `name` comes up on `entity`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub name: String,
+}
+
+/// Advances every `Animated` sprite with the `GameClock`, sending the events of the frames that
+/// come up. Sleeping enemies hold their frame.
+pub struct SpriteAnimationSystem;
+
+impl<'s> System<'s> for SpriteAnimationSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Animated>,
+        WriteStorage<'s, SpriteRender>,
+        ReadStorage<'s, Asleep>,
+        Write<'s, EventChannel<AnimationEvent>>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut animated, mut sprites, asleep, mut events, clock): Self::SystemData,
+    ) {
+        let delta = f64::from(clock.delta_seconds());
+        for (entity, animated, sprite, _) in
+            (&entities, &mut animated, &mut sprites, !&asleep).join()
+        {
+            let from = animated.elapsed;
+            let to = from.map_or(0., |from| from + delta);
+            animated.elapsed = Some(to);
+            for name in animated.animation.events_between(from, to) {
+                events.single_write(AnimationEvent {
+                    entity,
+                    name: name.to_string(),
+                });
+            }
+            if let Some(frame) = animated.animation.sprite_at(to) {
+                sprite.sprite_number = frame;
+            }
+        }
+    }
+}
+
+/// The sounds played for animation events, read from `animation_sounds.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnimationSounds {
+    /// The ogg or wav file, relative to `resources`, played for each event by name.
+    pub sounds: HashMap<String, String>,
+    /// From `0` for silent to `1` for as loud as they were recorded.
+    pub volume: f32,
+}
+
+impl Default for AnimationSounds {
+    fn default() -> Self {
+        AnimationSounds {
+            sounds: HashMap::new(),
+            volume: 1.,
+        }
+    }
+}
+
+/// Plays the sound for each `AnimationEvent` that has one, from where its entity is.
+#[derive(Default)]
+pub struct AnimationSoundSystem {
+    reader: Option<ReaderId<AnimationEvent>>,
+}
+
+impl<'s> System<'s> for AnimationSoundSystem {
+    type SystemData = (
+        Read<'s, EventChannel<AnimationEvent>>,
+        ReadStorage<'s, Transform>,
+        Read<'s, AnimationSounds>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<Source>>,
+        Write<'s, SoundQueue>,
+    );
+
+    fn run(&mut self, (events, transforms, config, loader, sources, mut queue): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("Set up");
+        for event in events.read(reader) {
+            let sound = config.sounds.get(&event.name);
+            let position = transforms.get(event.entity).map(world_position);
+            if let (Some(sound), Some(position)) = (sound, position) {
+                queue_sound_at(
+                    &loader,
+                    &sources,
+                    &mut queue,
+                    sound,
+                    config.volume,
+                    position,
+                );
+            }
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<AnimationEvent>>()
+                .register_reader(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Four frames a tenth of a second each, with two events on the third and one on the first.
+    fn swing() -> SpriteAnimation {
+        let event = |frame, name: &str| FrameEvent {
+            frame,
+            name: name.to_string(),
+        };
+        SpriteAnimation {
+            frames: vec![10, 11, 12, 13],
+            frame_duration: 0.1,
+            events: vec![event(2, "hit"), event(0, "windup"), event(2, "whoosh")],
+        }
+    }
+
+    /// The events sent over steps of `deltas` seconds, as the system advances the animation.
+    fn played<'a>(animation: &'a SpriteAnimation, deltas: &[f64]) -> Vec<Vec<&'a str>> {
+        let mut elapsed = None;
+        let mut steps = Vec::new();
+        for delta in std::iter::once(&0.).chain(deltas) {
+            let to = elapsed.map_or(0., |from: f64| from + delta);
+            steps.push(animation.events_between(elapsed, to));
+            elapsed = Some(to);
+        }
+        steps
+    }
+
+    #[test]
+    fn each_event_of_a_frame_is_sent_once() {
+        let animation = swing();
+        let steps = played(&animation, &[0.05, 0.1, 0.1, 0.03, 0.03, 0.03]);
+        assert_eq!(
+            steps,
+            vec![
+                vec!["windup"],
+                vec![],
+                vec![],
+                vec!["hit", "whoosh"],
+                vec![],
+                vec![],
+                vec![],
+            ]
+        );
+        assert_eq!(animation.sprite_at(0.25), Some(12));
+    }
+
+    #[test]
+    fn skipped_frames_still_send_their_events() {
+        let animation = swing();
+        // A long step from the first frame past the third, and one past the end of the loop and
+        // the third again.
+        let steps = played(&animation, &[0.35, 0.3]);
+        assert_eq!(
+            steps,
+            vec![
+                vec!["windup"],
+                vec!["hit", "whoosh"],
+                vec!["windup", "hit", "whoosh"],
+            ]
+        );
+    }
+}
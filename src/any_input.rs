@@ -0,0 +1,185 @@
+//! Whether anyone pressed anything this frame, for screens waiting on any key or button.
+//!
+//! A press is a key, mouse or gamepad button going down, or a gamepad stick or trigger being
+//! pushed past `axis_threshold`. Mouse movement doesn't count. A screen that starts waiting
+//! calls `AnyInput::rearm`, and presses in the `debounce` seconds after that are ignored, so
+//! the press that brought the screen up doesn't also get past it.
+
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{Read, Resources, System, SystemData, Write},
+    input::{ControllerAxis, InputEvent},
+    shrev::{EventChannel, ReaderId},
+};
+use serde::{Deserialize, Serialize};
+
+/// What counts as a press, read from `any_input.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnyInputConfig {
+    /// How far, from `0` to `1`, a stick or trigger has to be pushed to count as pressed.
+    pub axis_threshold: f32,
+    /// Seconds after rearming in which presses are ignored.
+    pub debounce: f32,
+}
+
+impl Default for AnyInputConfig {
+    fn default() -> Self {
+        AnyInputConfig {
+            axis_threshold: 0.5,
+            debounce: 0.2,
+        }
+    }
+}
+
+/// Whether there was a press this frame.
+#[derive(Clone, Debug, Default)]
+pub struct AnyInput {
+    pub config: AnyInputConfig,
+    pressed: bool,
+    /// Seconds since the last rearm, or `None` once past the debounce.
+    debouncing: Option<f32>,
+    /// The gamepad axes pushed past the threshold, which have to come back before they count
+    /// again.
+    pushed: Vec<(u32, ControllerAxis)>,
+}
+
+impl AnyInput {
+    pub fn new(config: AnyInputConfig) -> Self {
+        AnyInput {
+            config,
+            ..AnyInput::default()
+        }
+    }
+
+    /// Whether anything was pressed this frame, outside the debounce.
+    pub fn pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Ignores presses for the next `debounce` seconds.
+    pub fn rearm(&mut self) {
+        self.pressed = false;
+        self.debouncing = Some(0.);
+    }
+
+    /// Takes in a frame of `events`, `delta` seconds long.
+    pub fn update<'a>(&mut self, events: impl Iterator<Item = &'a InputEvent<String>>, delta: f32) {
+        let threshold = f64::from(self.config.axis_threshold);
+        let mut pressed = false;
+        for event in events {
+            match *event {
+                InputEvent::ButtonPressed(..) => pressed = true,
+                InputEvent::ControllerAxisMoved { which, axis, value } => {
+                    let index = self
+                        .pushed
+                        .iter()
+                        .position(|&pushed| pushed == (which, axis));
+                    match (value.abs() >= threshold, index) {
+                        (true, None) => {
+                            self.pushed.push((which, axis));
+                            pressed = true;
+                        }
+                        (false, Some(index)) => {
+                            self.pushed.swap_remove(index);
+                        }
+                        _ => {}
+                    }
+                }
+                InputEvent::ControllerDisconnected { which } => {
+                    self.pushed.retain(|&(pushed, _)| pushed != which);
+                }
+                _ => {}
+            }
+        }
+        self.debouncing = self
+            .debouncing
+            .map(|elapsed| elapsed + delta)
+            .filter(|&elapsed| elapsed < self.config.debounce);
+        self.pressed = pressed && self.debouncing.is_none();
+    }
+}
+
+/// Updates `AnyInput` with the frame's input events.
+#[derive(Default)]
+pub struct AnyInputSystem {
+    reader: Option<ReaderId<InputEvent<String>>>,
+}
+
+impl<'s> System<'s> for AnyInputSystem {
+    type SystemData = (
+        Read<'s, EventChannel<InputEvent<String>>>,
+        Write<'s, AnyInput>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (events, mut any_input, time): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("AnyInputSystem is set up");
+        any_input.update(events.read(reader), time.delta_real_seconds());
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<InputEvent<String>>>()
+                .register_reader(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::input::{Button, VirtualKeyCode};
+
+    use super::*;
+
+    fn key() -> InputEvent<String> {
+        InputEvent::ButtonPressed(Button::Key(VirtualKeyCode::Space))
+    }
+
+    fn stick(value: f64) -> InputEvent<String> {
+        InputEvent::ControllerAxisMoved {
+            which: 0,
+            axis: ControllerAxis::LeftX,
+            value,
+        }
+    }
+
+    /// Whether `input` counts a frame of `events` a tenth of a second long as a press.
+    fn press(input: &mut AnyInput, events: &[InputEvent<String>]) -> bool {
+        input.update(events.iter(), 0.1);
+        input.pressed()
+    }
+
+    #[test]
+    fn keys_and_sticks_pushed_far_enough_are_presses() {
+        let mut input = AnyInput::new(AnyInputConfig::default());
+        assert!(!press(&mut input, &[]));
+        assert!(press(&mut input, &[key()]));
+        assert!(!press(&mut input, &[]));
+        assert!(!press(&mut input, &[stick(0.3)]));
+        assert!(press(&mut input, &[stick(-0.8)]));
+    }
+
+    #[test]
+    fn a_stick_held_out_is_pressed_once() {
+        let mut input = AnyInput::new(AnyInputConfig::default());
+        assert!(press(&mut input, &[stick(0.8)]));
+        assert!(!press(&mut input, &[stick(0.9)]));
+        assert!(!press(&mut input, &[stick(0.1)]));
+        assert!(press(&mut input, &[stick(0.8)]));
+    }
+
+    #[test]
+    fn presses_right_after_rearming_are_ignored() {
+        let mut input = AnyInput::new(AnyInputConfig {
+            axis_threshold: 0.5,
+            debounce: 0.25,
+        });
+        input.rearm();
+        assert!(!input.pressed());
+        assert!(!press(&mut input, &[key()]));
+        assert!(!press(&mut input, &[key()]));
+        assert!(press(&mut input, &[key()]));
+    }
+}
@@ -0,0 +1,295 @@
+//! Picking sprite sheets drawn at the scale that suits the screen, so sprites stay crisp on
+//! high-DPI and high-resolution screens.
+//!
+//! A sheet may come at more scales than its own, as images and sprite lists named like it with
+//! a suffix, such as `packed@2x.png` and `packed@2x.ron` next to `packed.png` and `packed.ron`.
+//! `asset_scales.ron` lists the suffixes and what each scale is, and whether the screen's
+//! density is its DPI factor or its drawn height against a reference height. As the level
+//! loads, each sheet is read at the scale nearest that density that it has both files for,
+//! falling back to its own. A sheet read at another scale has its sprites shrunk to match, so
+//! they are as big in the world as at the sheet's own scale, and its handle is used just the
+//! same. Collision masks and the other data read from the sheets' pixels keep to their own
+//! scale.
+
+use std::path::PathBuf;
+
+use amethyst::{
+    assets::{Format, Handle},
+    renderer::{
+        sprite::{Sprite, SpriteList},
+        SpriteSheet, Texture,
+    },
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::loading::SpriteSheetAsset;
+
+/// What a screen's density is measured by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum ScaleBasis {
+    /// The window's DPI factor, two on most high-DPI screens.
+    #[default]
+    Dpi,
+    /// The height the scene is drawn at over this many pixels.
+    Resolution(u32),
+}
+
+/// One scale sheets may come at besides their own.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AssetScale {
+    pub scale: f32,
+    /// Added to the file names, before the extension.
+    pub suffix: String,
+}
+
+/// Which scales sheets come at and how one is picked, read from `asset_scales.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AssetScaleConfig {
+    /// Whether other scales are looked for at all. Without it, each sheet is read as named.
+    pub enabled: bool,
+    pub basis: ScaleBasis,
+    pub scales: Vec<AssetScale>,
+}
+
+impl Default for AssetScaleConfig {
+    fn default() -> Self {
+        AssetScaleConfig {
+            enabled: true,
+            basis: ScaleBasis::Dpi,
+            scales: vec![AssetScale {
+                scale: 2.,
+                suffix: "@2x".to_string(),
+            }],
+        }
+    }
+}
+
+impl AssetScaleConfig {
+    /// The density of a screen `dpi_factor` times denser than usual, with the scene drawn
+    /// `drawn_height` pixels high.
+    pub fn density(&self, dpi_factor: f32, drawn_height: f32) -> f32 {
+        match self.basis {
+            ScaleBasis::Dpi => dpi_factor,
+            ScaleBasis::Resolution(reference) => drawn_height / reference.max(1) as f32,
+        }
+    }
+}
+
+/// The scale out of `available` nearest `density`, the larger of two as near. One when there
+/// is none.
+pub fn choose_scale(density: f32, available: &[f32]) -> f32 {
+    let mut best: Option<f32> = None;
+    for &scale in available {
+        let nearer = best.is_none_or(|best| {
+            let (distance, best_distance) = ((scale - density).abs(), (best - density).abs());
+            distance < best_distance || (distance == best_distance && scale > best)
+        });
+        if nearer {
+            best = Some(scale);
+        }
+    }
+    best.unwrap_or(1.)
+}
+
+/// `path` with `suffix` added before its extension.
+pub fn variant_path(path: &str, suffix: &str) -> String {
+    match path.rfind('.') {
+        Some(dot) if !path[dot..].contains('/') => {
+            format!("{}{}{}", &path[..dot], suffix, &path[dot..])
+        }
+        _ => format!("{}{}", path, suffix),
+    }
+}
+
+/// A sheet as it is read, and the scale its files are drawn at.
+#[derive(Clone, Debug)]
+pub struct ScaledAsset {
+    pub asset: SpriteSheetAsset,
+    pub scale: f32,
+}
+
+/// Picks which scale of each sprite sheet is read.
+#[derive(Clone, Debug)]
+pub struct AssetScales {
+    config: AssetScaleConfig,
+    density: f32,
+    /// What the sheets' paths are relative to.
+    root: PathBuf,
+}
+
+impl Default for AssetScales {
+    /// Reads every sheet as named.
+    fn default() -> Self {
+        AssetScales {
+            config: AssetScaleConfig {
+                enabled: false,
+                ..AssetScaleConfig::default()
+            },
+            density: 1.,
+            root: PathBuf::new(),
+        }
+    }
+}
+
+impl AssetScales {
+    pub fn new(config: AssetScaleConfig, density: f32, root: PathBuf) -> Self {
+        AssetScales {
+            config,
+            density,
+            root,
+        }
+    }
+
+    /// The scale of `asset` nearest the density among those it has both files for.
+    pub fn select(&self, asset: &SpriteSheetAsset) -> ScaledAsset {
+        let own = ScaledAsset {
+            asset: asset.clone(),
+            scale: 1.,
+        };
+        if !self.config.enabled {
+            return own;
+        }
+        let mut variants = vec![own];
+        for scale in &self.config.scales {
+            let variant = SpriteSheetAsset {
+                texture: variant_path(&asset.texture, &scale.suffix),
+                sprites: variant_path(&asset.sprites, &scale.suffix),
+            };
+            if self.root.join(&variant.texture).exists()
+                && self.root.join(&variant.sprites).exists()
+            {
+                variants.push(ScaledAsset {
+                    asset: variant,
+                    scale: scale.scale,
+                });
+            }
+        }
+        let available: Vec<f32> = variants.iter().map(|variant| variant.scale).collect();
+        let chosen = choose_scale(self.density, &available);
+        variants
+            .into_iter()
+            .find(|variant| variant.scale == chosen)
+            .expect("The chosen scale is one of those available")
+    }
+}
+
+/// The sprites of `list`, shrunk from being drawn at `scale` to their size at the sheet's own.
+pub fn scaled_sprites(list: &SpriteList, scale: f32) -> Vec<Sprite> {
+    let mut sprites = list.build_sprites();
+    if scale > 0. && (scale - 1.).abs() > f32::EPSILON {
+        for sprite in &mut sprites {
+            sprite.width /= scale;
+            sprite.height /= scale;
+            sprite.offsets[0] /= scale;
+            sprite.offsets[1] /= scale;
+        }
+    }
+    sprites
+}
+
+/// Reads a sprite list drawn at `scale` into a sheet of `texture`, as `SpriteSheetFormat` does
+/// for one at the sheet's own scale.
+#[derive(Clone, Debug)]
+pub struct ScaledSpriteSheetFormat {
+    pub texture: Handle<Texture>,
+    pub scale: f32,
+}
+
+impl Format<SpriteSheet> for ScaledSpriteSheetFormat {
+    fn name(&self) -> &'static str {
+        "SCALED_SPRITE_SHEET"
+    }
+
+    fn import_simple(&self, bytes: Vec<u8>) -> Result<SpriteSheet, Error> {
+        let list: SpriteList = ron::de::from_bytes(&bytes)
+            .map_err(|err| Error::from_string(format!("Bad sprite list: {}", err)))?;
+        Ok(SpriteSheet {
+            texture: self.texture.clone(),
+            sprites: scaled_sprites(&list, self.scale),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn scales(scales: &[(f32, &str)], basis: ScaleBasis) -> AssetScaleConfig {
+        AssetScaleConfig {
+            enabled: true,
+            basis,
+            scales: scales
+                .iter()
+                .map(|&(scale, suffix)| AssetScale {
+                    scale,
+                    suffix: suffix.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn each_basis_picks_the_nearest_scale() {
+        let available = [1., 2., 3.];
+        let dpi = scales(&[], ScaleBasis::Dpi);
+        assert_eq!(choose_scale(dpi.density(1.25, 1440.), &available), 1.);
+        assert_eq!(choose_scale(dpi.density(1.75, 720.), &available), 2.);
+        // Halfway between two goes to the larger.
+        assert_eq!(choose_scale(dpi.density(2.5, 720.), &available), 3.);
+
+        let resolution = scales(&[], ScaleBasis::Resolution(720));
+        assert_eq!(choose_scale(resolution.density(2., 720.), &available), 1.);
+        assert_eq!(choose_scale(resolution.density(1., 1440.), &available), 2.);
+        assert_eq!(choose_scale(resolution.density(1., 2160.), &available), 3.);
+
+        assert_eq!(choose_scale(2., &[]), 1.);
+    }
+
+    #[test]
+    fn a_scale_missing_a_file_falls_back_to_one_that_has_both() {
+        let root = env::temp_dir().join(format!("shroud-asset-scale-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        // The 2x sheet is complete, the 3x one has no sprite list.
+        for file in &[
+            "hero.png",
+            "hero.ron",
+            "hero@2x.png",
+            "hero@2x.ron",
+            "hero@3x.png",
+        ] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+        let config = scales(&[(2., "@2x"), (3., "@3x")], ScaleBasis::Dpi);
+        let hero = SpriteSheetAsset {
+            texture: "hero.png".to_string(),
+            sprites: "hero.ron".to_string(),
+        };
+        let select =
+            |density| AssetScales::new(config.clone(), density, root.clone()).select(&hero);
+
+        let dense = select(3.);
+        assert_eq!(dense.scale, 2.);
+        assert_eq!(dense.asset.texture, "hero@2x.png");
+        assert_eq!(dense.asset.sprites, "hero@2x.ron");
+        let usual = select(1.);
+        assert_eq!(
+            (usual.scale, usual.asset.texture.as_str()),
+            (1., "hero.png")
+        );
+
+        // A sheet with no other scales at all is read as named.
+        let other = AssetScales::new(config.clone(), 2., root.clone()).select(&SpriteSheetAsset {
+            texture: "tiles.png".to_string(),
+            sprites: "tiles.ron".to_string(),
+        });
+        assert_eq!(
+            (other.scale, other.asset.texture.as_str()),
+            (1., "tiles.png")
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,407 @@
+//! Combining many small sprite sheets into a few big textures as the level loads.
+//!
+//! Each sheet named in `atlas.ron` is packed whole into a shared atlas, shelf by shelf, tallest
+//! first, and its sprites are moved to where it landed. Sprites drawn from the same atlas share
+//! a texture, so they don't need one bound each. When an atlas is full another is started, and
+//! an image too big for any atlas gets one of its own. The combined sheets are the
+//! `SpriteAtlases` resource, each found by the name it was given in `atlas.ron`. Sheets are
+//! read at the scale `asset_scale` picks for them, like any other.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    path::Path,
+};
+
+use amethyst::{
+    assets::{AssetStorage, Handle, Loader, ProgressCounter},
+    ecs::prelude::World,
+    renderer::{
+        rendy::{
+            hal::{
+                format::Format,
+                image::{Kind, ViewKind},
+            },
+            texture::TextureBuilder,
+        },
+        sprite::{Sprite, SpriteList, SpritePosition},
+        types::TextureData,
+        ImageFormat, SpriteSheet, Texture,
+    },
+};
+use image::{GenericImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_scale::{self, ScaledAsset},
+    loading::SpriteSheetAsset,
+};
+
+/// Which sprite sheets are combined and how big atlases may grow, read from `atlas.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AtlasConfig {
+    /// Whether the sheets are combined at all. Without it, each is loaded with its own texture.
+    pub enabled: bool,
+    /// The widest and tallest an atlas may be, in pixels.
+    pub max_size: u32,
+    /// Clear pixels left between the images, so filtering doesn't bleed one into the next.
+    pub padding: u32,
+    /// The sheets, by the name they are looked up by.
+    pub sheets: BTreeMap<String, SpriteSheetAsset>,
+}
+
+impl Default for AtlasConfig {
+    fn default() -> Self {
+        AtlasConfig {
+            enabled: true,
+            max_size: 2048,
+            padding: 1,
+            sheets: BTreeMap::new(),
+        }
+    }
+}
+
+/// Where one image went: the atlas it is in, and its top-left corner there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub atlas: usize,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// A row of images along an atlas, as tall as the first and tallest of them.
+#[derive(Clone, Copy, Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Where the next image on the shelf goes.
+    x: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+struct OpenAtlas {
+    shelves: Vec<Shelf>,
+    /// Where the next shelf goes.
+    y: u32,
+    /// Set for an atlas holding a single image too big for any other.
+    oversized: bool,
+}
+
+/// Packs images of the given `(width, height)` sizes into atlases no bigger than `max_size`
+/// across, with `padding` between them. Returns the placement of each image, in the same order,
+/// and the size of each atlas. Images bigger than `max_size` get an atlas of their own, sized
+/// to them.
+pub fn pack(
+    sizes: &[(u32, u32)],
+    max_size: u32,
+    padding: u32,
+) -> (Vec<Placement>, Vec<(u32, u32)>) {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&index| {
+        let (width, height) = sizes[index];
+        (std::cmp::Reverse(height), std::cmp::Reverse(width), index)
+    });
+
+    let mut atlases: Vec<OpenAtlas> = Vec::new();
+    let mut placements = vec![
+        Placement {
+            atlas: 0,
+            x: 0,
+            y: 0
+        };
+        sizes.len()
+    ];
+    for index in order {
+        let (width, height) = sizes[index];
+        if width > max_size || height > max_size {
+            placements[index] = Placement {
+                atlas: atlases.len(),
+                x: 0,
+                y: 0,
+            };
+            atlases.push(OpenAtlas {
+                oversized: true,
+                ..OpenAtlas::default()
+            });
+            continue;
+        }
+
+        let placed = atlases.iter_mut().enumerate().find_map(|(number, atlas)| {
+            if atlas.oversized {
+                return None;
+            }
+            let placement = |x, y| Placement {
+                atlas: number,
+                x,
+                y,
+            };
+            if let Some(shelf) = atlas
+                .shelves
+                .iter_mut()
+                .find(|shelf| height <= shelf.height && shelf.x + width <= max_size)
+            {
+                let placed = placement(shelf.x, shelf.y);
+                shelf.x += width + padding;
+                return Some(placed);
+            }
+            if atlas.y + height > max_size {
+                return None;
+            }
+            let placed = placement(0, atlas.y);
+            atlas.shelves.push(Shelf {
+                y: atlas.y,
+                height,
+                x: width + padding,
+            });
+            atlas.y += height + padding;
+            Some(placed)
+        });
+        placements[index] = placed.unwrap_or_else(|| {
+            atlases.push(OpenAtlas {
+                shelves: vec![Shelf {
+                    y: 0,
+                    height,
+                    x: width + padding,
+                }],
+                y: height + padding,
+                oversized: false,
+            });
+            Placement {
+                atlas: atlases.len() - 1,
+                x: 0,
+                y: 0,
+            }
+        });
+    }
+
+    let mut extents = vec![(0, 0); atlases.len()];
+    for (placement, &(width, height)) in placements.iter().zip(sizes) {
+        let extent = &mut extents[placement.atlas];
+        extent.0 = extent.0.max(placement.x + width);
+        extent.1 = extent.1.max(placement.y + height);
+    }
+    (placements, extents)
+}
+
+/// One sprite sheet to combine: its image, and its sprites within that image.
+#[derive(Clone, Debug)]
+pub struct SourceSheet {
+    pub name: String,
+    pub image: RgbaImage,
+    pub sprites: Vec<SpritePosition>,
+    /// The scale the image is drawn at, as `asset_scale` picks.
+    pub scale: f32,
+}
+
+impl SourceSheet {
+    /// Reads the sheet's image and sprite list, both relative to `root`.
+    pub fn load(name: &str, scaled: &ScaledAsset, root: &Path) -> Result<Self, failure::Error> {
+        let asset = &scaled.asset;
+        let list: SpriteList = ron::de::from_reader(File::open(root.join(&asset.sprites))?)?;
+        Ok(SourceSheet {
+            name: name.to_string(),
+            image: image::open(root.join(&asset.texture))?.to_rgba(),
+            sprites: list.sprites,
+            scale: scaled.scale,
+        })
+    }
+}
+
+/// One atlas's image, and the sheets in it with their sprites moved to match.
+#[derive(Clone, Debug)]
+pub struct CombinedAtlas {
+    pub image: RgbaImage,
+    pub sheets: Vec<(String, Vec<Sprite>)>,
+}
+
+/// Packs `sources` into as few atlases as `pack` can.
+pub fn combine(sources: Vec<SourceSheet>, max_size: u32, padding: u32) -> Vec<CombinedAtlas> {
+    let sizes: Vec<(u32, u32)> = sources
+        .iter()
+        .map(|source| source.image.dimensions())
+        .collect();
+    let (placements, extents) = pack(&sizes, max_size, padding);
+    let mut atlases: Vec<CombinedAtlas> = extents
+        .iter()
+        .map(|&(width, height)| CombinedAtlas {
+            image: RgbaImage::new(width, height),
+            sheets: Vec::new(),
+        })
+        .collect();
+
+    for (source, placement) in sources.into_iter().zip(placements) {
+        let atlas = &mut atlases[placement.atlas];
+        atlas
+            .image
+            .copy_from(&source.image, placement.x, placement.y);
+        let sprites = source
+            .sprites
+            .into_iter()
+            .map(|sprite| SpritePosition {
+                x: sprite.x + placement.x,
+                y: sprite.y + placement.y,
+                ..sprite
+            })
+            .collect();
+        let (texture_width, texture_height) = atlas.image.dimensions();
+        let list = SpriteList {
+            texture_width,
+            texture_height,
+            sprites,
+        };
+        atlas.sheets.push((
+            source.name,
+            asset_scale::scaled_sprites(&list, source.scale),
+        ));
+    }
+    atlases
+}
+
+/// The combined sprite sheets, by the names `atlas.ron` gives them.
+#[derive(Clone, Debug, Default)]
+pub struct SpriteAtlases {
+    sheets: HashMap<String, Handle<SpriteSheet>>,
+}
+
+impl SpriteAtlases {
+    pub fn get(&self, name: &str) -> Option<&Handle<SpriteSheet>> {
+        self.sheets.get(name)
+    }
+
+    pub fn insert(&mut self, name: String, sheet: Handle<SpriteSheet>) {
+        self.sheets.insert(name, sheet);
+    }
+}
+
+/// The texture of one loaded atlas, and how many sheets draw from it.
+pub struct AtlasTexture {
+    pub name: String,
+    pub handle: Handle<Texture>,
+    pub sheets: usize,
+}
+
+/// Loads `atlas` as a texture and each of its sheets as a sprite sheet drawing from it, added
+/// to `atlases`. Pixels are set up as for any image loaded from a file.
+pub fn load_atlas(
+    world: &World,
+    name: String,
+    atlas: CombinedAtlas,
+    atlases: &mut SpriteAtlases,
+    progress: &mut ProgressCounter,
+) -> AtlasTexture {
+    let config = ImageFormat::default().0;
+    let (width, height) = atlas.image.dimensions();
+    let mut image = atlas.image;
+    if config.premultiply_alpha {
+        for pixel in image.pixels_mut() {
+            let alpha = u16::from(pixel.data[3]);
+            for channel in &mut pixel.data[..3] {
+                *channel = (u16::from(*channel) * alpha / 255) as u8;
+            }
+        }
+    }
+    let builder = TextureBuilder::new()
+        .with_raw_data(image.into_raw(), Format::Rgba8Srgb)
+        .with_data_width(width)
+        .with_data_height(height)
+        .with_kind(Kind::D2(width, height, 1, 1))
+        .with_view_kind(ViewKind::D2)
+        .with_premultiplied_alpha(config.premultiply_alpha)
+        .with_sampler_info(config.sampler_info);
+
+    let loader = world.read_resource::<Loader>();
+    let texture = loader.load_from_data(
+        TextureData(builder),
+        &mut *progress,
+        &world.read_resource::<AssetStorage<Texture>>(),
+    );
+    let sheets = atlas.sheets.len();
+    for (sheet, sprites) in atlas.sheets {
+        let handle = loader.load_from_data(
+            SpriteSheet {
+                texture: texture.clone(),
+                sprites,
+            },
+            &mut *progress,
+            &world.read_resource::<AssetStorage<SpriteSheet>>(),
+        );
+        atlases.insert(sheet, handle);
+    }
+    AtlasTexture {
+        name,
+        handle: texture,
+        sheets,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that no two images in an atlas come within `padding` of each other and that
+    /// each fits its atlas.
+    fn assert_packed(
+        sizes: &[(u32, u32)],
+        placements: &[Placement],
+        extents: &[(u32, u32)],
+        padding: u32,
+    ) {
+        for (index, (a, &(a_width, a_height))) in placements.iter().zip(sizes).enumerate() {
+            let extent = extents[a.atlas];
+            assert!(a.x + a_width <= extent.0 && a.y + a_height <= extent.1);
+            for (b, &(b_width, b_height)) in placements.iter().zip(sizes).skip(index + 1) {
+                if a.atlas != b.atlas {
+                    continue;
+                }
+                let apart = a.x + a_width + padding <= b.x
+                    || b.x + b_width + padding <= a.x
+                    || a.y + a_height + padding <= b.y
+                    || b.y + b_height + padding <= a.y;
+                assert!(apart, "{:?} and {:?} are too close", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn images_are_packed_apart_by_the_padding() {
+        let sizes = [
+            (30, 20),
+            (64, 64),
+            (10, 60),
+            (50, 20),
+            (20, 20),
+            (33, 17),
+            (64, 10),
+            (5, 5),
+        ];
+        let (placements, extents) = pack(&sizes, 128, 2);
+        assert_packed(&sizes, &placements, &extents, 2);
+        assert_eq!(extents.len(), 1);
+        assert!(extents[0].0 <= 128 && extents[0].1 <= 128);
+    }
+
+    #[test]
+    fn a_full_atlas_opens_another() {
+        // Four of these fill a 64 pixel atlas, with a pixel of padding on the inside edges.
+        let sizes = vec![(31, 31); 6];
+        let (placements, extents) = pack(&sizes, 64, 1);
+        assert_packed(&sizes, &placements, &extents, 1);
+        assert_eq!(extents, [(63, 63), (63, 31)]);
+        let in_first = placements.iter().filter(|placement| placement.atlas == 0);
+        assert_eq!(in_first.count(), 4);
+    }
+
+    #[test]
+    fn an_oversized_image_gets_an_atlas_of_its_own() {
+        let sizes = [(16, 16), (300, 40), (16, 16)];
+        let (placements, extents) = pack(&sizes, 256, 1);
+        assert_packed(&sizes, &placements, &extents, 1);
+        assert_eq!(extents.len(), 2);
+        let big = placements[1];
+        assert_eq!((big.x, big.y), (0, 0));
+        assert_eq!(extents[big.atlas], (300, 40));
+        assert_ne!(placements[0].atlas, big.atlas);
+        assert_eq!(placements[0].atlas, placements[2].atlas);
+    }
+}
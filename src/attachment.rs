@@ -0,0 +1,290 @@
+//! Sprites attached to other entities, for characters drawn in parts, such as a body with a
+//! weapon in hand and a hat on top.
+//!
+//! An attached part is an entity of its own with an amethyst `Parent`, so its `Transform` is its
+//! offset and rotation from its parent, and the transform system places it wherever the parent
+//! is every frame. `attachments.ron` lists the parts every player is given as they spawn.
+//!
+//! A part is also given `Attached`, which the `AttachmentSystem` looks after once its parent is
+//! gone: it is either despawned along with it or, as `attachments.ron` says, detached and left
+//! where it last was, like a dropped weapon. The transform systems delete whatever leaves the
+//! hierarchy, parts whose parent has gone or whose `Parent` is taken off alike, so a detached
+//! part is left as a copy of its own, and the `AttachmentSystem` runs before them to make it
+//! while the part is still there.
+
+use amethyst::{
+    assets::Handle,
+    core::{math::Vector2, transform::Transform, Parent},
+    ecs::prelude::{
+        Builder, Component, Entities, Entity, Join, NullStorage, Read, ReadStorage, System, World,
+        WriteStorage,
+    },
+    renderer::{SpriteRender, SpriteSheet, Transparent},
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// What becomes of a part once its parent is despawned.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub enum OrphanPolicy {
+    Despawn,
+    /// Leaves the part in the world where it last was.
+    Detach,
+}
+
+/// A sprite drawn attached to its parent.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpritePart {
+    /// The sprite's index in the parent's sprite sheet.
+    pub sprite: usize,
+    /// How far the sprite's centre is from the parent's, in world units.
+    pub offset: (f32, f32),
+    /// How far in front of the parent the sprite is drawn, or behind it if negative.
+    pub depth: f32,
+    /// The sprite's rotation from the parent's, in degrees anticlockwise.
+    pub rotation: f32,
+}
+
+impl Default for SpritePart {
+    fn default() -> Self {
+        SpritePart {
+            sprite: 0,
+            offset: (0., 0.),
+            depth: 0.01,
+            rotation: 0.,
+        }
+    }
+}
+
+impl SpritePart {
+    /// The part's transform from its parent.
+    pub fn local_transform(&self) -> Transform {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(self.offset.0, self.offset.1, self.depth);
+        transform.set_rotation_2d(self.rotation.to_radians());
+        transform
+    }
+}
+
+/// Which parts entities are given and what becomes of them, read from `attachments.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AttachmentConfig {
+    pub on_parent_despawn: OrphanPolicy,
+    /// The parts every player is drawn with, from the players' sprite sheet.
+    pub player_parts: Vec<SpritePart>,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        AttachmentConfig {
+            on_parent_despawn: OrphanPolicy::Despawn,
+            player_parts: Vec::new(),
+        }
+    }
+}
+
+/// Marks a part attached to its `Parent`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Attached;
+
+impl Component for Attached {
+    type Storage = NullStorage<Self>;
+}
+
+/// Attaches `part`, a sprite from `sprite_sheet`, to `parent`, returning the part's entity.
+pub fn attach(
+    world: &mut World,
+    parent: Entity,
+    part: &SpritePart,
+    sprite_sheet: Handle<SpriteSheet>,
+) -> Entity {
+    world
+        .create_entity()
+        .with(SpriteRender {
+            sprite_sheet,
+            sprite_number: part.sprite,
+        })
+        .with(part.local_transform())
+        .with(Transparent)
+        .with(Parent { entity: parent })
+        .with(Attached)
+        .build()
+}
+
+/// Where a part `transform` was last drawn in the world, and its rotation there in radians.
+fn last_placement(transform: &Transform) -> (Vector2<f32>, f32) {
+    let global = transform.global_matrix();
+    let position = Vector2::new(global[(0, 3)].as_f32(), global[(1, 3)].as_f32());
+    let rotation = global[(1, 0)].as_f32().atan2(global[(0, 0)].as_f32());
+    (position, rotation)
+}
+
+/// Despawns or detaches the parts whose parents are gone, as `attachments.ron` says. Runs
+/// before the transform systems, as the module describes.
+pub struct AttachmentSystem;
+
+impl<'s> System<'s> for AttachmentSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Attached>,
+        ReadStorage<'s, Parent>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Transparent>,
+        Read<'s, AttachmentConfig>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, attached, parents, mut transforms, mut sprites, mut transparent, config): Self::SystemData,
+    ) {
+        let orphans: Vec<Entity> = (&entities, &attached, &parents)
+            .join()
+            .filter(|(_, _, parent)| !entities.is_alive(parent.entity))
+            .map(|(entity, _, _)| entity)
+            .collect();
+        if orphans.is_empty() {
+            return;
+        }
+        let fate = match config.on_parent_despawn {
+            OrphanPolicy::Despawn => "despawning",
+            OrphanPolicy::Detach => "detaching",
+        };
+        info!(
+            "{} attached parts lost their parent, {} them",
+            orphans.len(),
+            fate
+        );
+        for orphan in orphans {
+            if config.on_parent_despawn == OrphanPolicy::Detach {
+                if let Some(transform) = transforms.get(orphan) {
+                    let (position, rotation) = last_placement(transform);
+                    let mut placed = transform.clone();
+                    placed.set_translation_x(position.x);
+                    placed.set_translation_y(position.y);
+                    placed.set_rotation_2d(rotation);
+                    let sprite = sprites.get(orphan).cloned();
+                    let is_transparent = transparent.contains(orphan);
+                    let left = entities
+                        .build_entity()
+                        .with(placed, &mut transforms)
+                        .build();
+                    if let Some(sprite) = sprite {
+                        sprites
+                            .insert(left, sprite)
+                            .expect("Detached part is alive");
+                    }
+                    if is_transparent {
+                        transparent
+                            .insert(left, Transparent)
+                            .expect("Detached part is alive");
+                    }
+                }
+            }
+            entities.delete(orphan).expect("Attached part is alive");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        core::{SystemBundle, TransformBundle},
+        ecs::prelude::{Dispatcher, DispatcherBuilder},
+    };
+
+    use super::*;
+
+    fn part() -> SpritePart {
+        SpritePart {
+            offset: (3., 4.),
+            ..SpritePart::default()
+        }
+    }
+
+    /// A world with a parent at `10, 20` and a part attached to it, run through the
+    /// `AttachmentSystem` and then the transform systems, as the game runs them.
+    fn attached(policy: OrphanPolicy) -> (World, Dispatcher<'static, 'static>, Entity, Entity) {
+        let mut builder = DispatcherBuilder::new();
+        builder.add(AttachmentSystem, "attachment_system", &[]);
+        TransformBundle::new()
+            .with_dep(&["attachment_system"])
+            .build(&mut builder)
+            .unwrap();
+        let mut dispatcher = builder.build();
+        let mut world = World::new();
+        dispatcher.setup(&mut world.res);
+        world.add_resource(AttachmentConfig {
+            on_parent_despawn: policy,
+            player_parts: Vec::new(),
+        });
+        world.register::<Attached>();
+
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(10., 20., 0.);
+        let parent = world.create_entity().with(transform).build();
+        let part = world
+            .create_entity()
+            .with(part().local_transform())
+            .with(Parent { entity: parent })
+            .with(Attached)
+            .build();
+        dispatcher.dispatch(&world.res);
+        world.maintain();
+        (world, dispatcher, parent, part)
+    }
+
+    fn placement(world: &World, entity: Entity) -> Vector2<f32> {
+        last_placement(world.read_storage::<Transform>().get(entity).unwrap()).0
+    }
+
+    #[test]
+    fn parts_follow_their_parent() {
+        let (mut world, mut dispatcher, parent, part) = attached(OrphanPolicy::Despawn);
+        assert_eq!(placement(&world, part), Vector2::new(13., 24.));
+
+        world
+            .write_storage::<Transform>()
+            .get_mut(parent)
+            .unwrap()
+            .set_translation_xyz(30., 20., 0.);
+        dispatcher.dispatch(&world.res);
+        world.maintain();
+        assert_eq!(placement(&world, part), Vector2::new(33., 24.));
+    }
+
+    #[test]
+    fn orphaned_parts_are_despawned() {
+        let (mut world, mut dispatcher, parent, part) = attached(OrphanPolicy::Despawn);
+        world.delete_entity(parent).unwrap();
+        dispatcher.dispatch(&world.res);
+        world.maintain();
+        assert!(!world.is_alive(part));
+    }
+
+    #[test]
+    fn detached_parts_stay_where_they_were() {
+        let (mut world, mut dispatcher, parent, part) = attached(OrphanPolicy::Detach);
+        world.delete_entity(parent).unwrap();
+        dispatcher.dispatch(&world.res);
+        world.maintain();
+        assert!(!world.is_alive(part));
+        let transforms = world.read_storage::<Transform>();
+        let left: Vec<Vector2<f32>> = (&world.entities(), &transforms)
+            .join()
+            .map(|(_, transform)| {
+                let translation = transform.translation();
+                Vector2::new(translation.x.as_f32(), translation.y.as_f32())
+            })
+            .collect();
+        assert_eq!(left, [Vector2::new(13., 24.)]);
+
+        // The part is left for good, not deleted with the rest of the hierarchy later on.
+        drop(transforms);
+        dispatcher.dispatch(&world.res);
+        world.maintain();
+        assert_eq!(world.read_storage::<Transform>().join().count(), 1);
+    }
+}
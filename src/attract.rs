@@ -0,0 +1,332 @@
+//! Attract mode for unattended demo machines.
+//!
+//! After `AttractConfig::timeout` seconds without any input the game pauses and a demo plays
+//! through a camera of its own. Any input ends the demo, hands the view back and resumes play.
+//! The game has no replays yet, so the only demo is a tour of the map.
+
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Entities, Entity, Read, ReadExpect, Resources, System, SystemData, Write, WriteStorage,
+    },
+    input::InputEvent,
+    renderer::camera::{ActiveCamera, Camera},
+    shrev::{EventChannel, ReaderId},
+    window::ScreenDimensions,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{centered_projection, smoothstep, CameraZoom},
+    split_screen::SplitScreen,
+    tile_map::TileMap,
+};
+
+/// What plays while the game is unattended.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AttractDemo {
+    /// Pans from one `TourStops` point to the next, `stop_seconds` apart, at `zoom`.
+    CameraTour { stop_seconds: f32, zoom: f32 },
+}
+
+/// When attract mode starts and what it plays, read from `attract.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AttractConfig {
+    /// Seconds without input before the demo starts.
+    pub timeout: f32,
+    pub demo: AttractDemo,
+}
+
+impl Default for AttractConfig {
+    fn default() -> Self {
+        AttractConfig {
+            timeout: 60.,
+            demo: AttractDemo::CameraTour {
+                stop_seconds: 4.,
+                zoom: 1.,
+            },
+        }
+    }
+}
+
+/// Counts how long it has been since the last input.
+#[derive(Clone, Copy, Debug)]
+pub struct IdleTimer {
+    pub timeout: f32,
+    idle: f32,
+}
+
+impl IdleTimer {
+    pub fn new(timeout: f32) -> Self {
+        IdleTimer { timeout, idle: 0. }
+    }
+
+    pub fn reset(&mut self) {
+        self.idle = 0.;
+    }
+
+    /// Adds `delta` idle seconds. Returns whether the timeout has been reached.
+    pub fn advance(&mut self, delta: f32) -> bool {
+        self.idle += delta;
+        self.idle >= self.timeout
+    }
+}
+
+/// Whether the demo is playing. Gameplay holds still while it is.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AttractMode {
+    pub active: bool,
+}
+
+/// The points of the world the camera tour visits, in order.
+#[derive(Clone, Debug, Default)]
+pub struct TourStops(pub Vec<Vector2<f32>>);
+
+impl TourStops {
+    /// The centre of each of the map's rooms, or of each quarter of a map without rooms.
+    pub fn from_map(map: &TileMap) -> Self {
+        if !map.rooms.is_empty() {
+            return TourStops(
+                map.rooms
+                    .iter()
+                    .map(|room| room.center() + map.origin())
+                    .collect(),
+            );
+        }
+        let size = Vector2::new(map.width as f32, map.height as f32);
+        let quarter = |x: f32, y: f32| {
+            map.projection
+                .to_world(Vector2::new(size.x * x, size.y * y), map.tile_size)
+                + map.origin()
+        };
+        TourStops(vec![
+            quarter(0.25, 0.25),
+            quarter(0.75, 0.25),
+            quarter(0.75, 0.75),
+            quarter(0.25, 0.75),
+        ])
+    }
+
+    /// Where the tour is `elapsed` seconds in, easing from one stop to the next over each
+    /// `stop_seconds` and looping back to the first after the last.
+    pub fn position(&self, stop_seconds: f32, elapsed: f32) -> Option<Vector2<f32>> {
+        let stops = &self.0;
+        let first = *stops.first()?;
+        if stops.len() == 1 || stop_seconds <= 0. {
+            return Some(first);
+        }
+        let legs = elapsed / stop_seconds;
+        let leg = legs.floor() as usize % stops.len();
+        let (from, to) = (stops[leg], stops[(leg + 1) % stops.len()]);
+        Some(from + (to - from) * smoothstep(legs.fract()))
+    }
+}
+
+/// A demo in progress, and what to restore once it ends.
+struct Demo {
+    camera: Entity,
+    elapsed: f32,
+    previous_camera: Option<Entity>,
+    previous_split_screen: bool,
+}
+
+/// Starts the demo once the game has been left idle for long enough and ends it on any input.
+pub struct AttractModeSystem {
+    pub config: AttractConfig,
+    timer: IdleTimer,
+    demo: Option<Demo>,
+    reader: Option<ReaderId<InputEvent<String>>>,
+}
+
+impl AttractModeSystem {
+    pub fn new(config: AttractConfig) -> Self {
+        AttractModeSystem {
+            config,
+            timer: IdleTimer::new(config.timeout),
+            demo: None,
+            reader: None,
+        }
+    }
+}
+
+impl<'s> System<'s> for AttractModeSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, EventChannel<InputEvent<String>>>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Camera>,
+        WriteStorage<'s, CameraZoom>,
+        Write<'s, AttractMode>,
+        Write<'s, ActiveCamera>,
+        Write<'s, SplitScreen>,
+        Read<'s, TourStops>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            events,
+            mut transforms,
+            mut cameras,
+            mut zooms,
+            mut attract,
+            mut active_camera,
+            mut split_screen,
+            stops,
+            dimensions,
+            time,
+        ): Self::SystemData,
+    ) {
+        let reader = self.reader.as_mut().expect("AttractModeSystem is set up");
+        // Controllers being plugged in or out don't mean anyone is there.
+        let input = events.read(reader).any(|event| {
+            !matches!(
+                event,
+                InputEvent::ControllerConnected { .. } | InputEvent::ControllerDisconnected { .. }
+            )
+        });
+
+        if input {
+            self.timer.reset();
+            if let Some(demo) = self.demo.take() {
+                info!("Input received, leaving attract mode");
+                entities
+                    .delete(demo.camera)
+                    .expect("Attract camera is alive");
+                active_camera.entity = demo.previous_camera;
+                split_screen.enabled = demo.previous_split_screen;
+                attract.active = false;
+            }
+            return;
+        }
+
+        let AttractDemo::CameraTour { stop_seconds, zoom } = self.config.demo;
+        let screen = Vector2::new(dimensions.width(), dimensions.height());
+        if self.demo.is_none() && self.timer.advance(time.delta_seconds()) {
+            info!(
+                "No input for {}s, entering attract mode",
+                self.config.timeout
+            );
+            let mut transform = Transform::default();
+            // Above everything the tour passes over, like the game camera.
+            transform.set_translation_z(1.);
+            let camera = entities
+                .build_entity()
+                .with(transform, &mut transforms)
+                .with(
+                    Camera::from(centered_projection(screen, zoom)),
+                    &mut cameras,
+                )
+                .with(CameraZoom::new(zoom, zoom, zoom), &mut zooms)
+                .build();
+            self.demo = Some(Demo {
+                camera,
+                elapsed: 0.,
+                previous_camera: active_camera.entity.replace(camera),
+                previous_split_screen: split_screen.enabled,
+            });
+            split_screen.enabled = false;
+            attract.active = true;
+        }
+
+        if let Some(demo) = self.demo.as_mut() {
+            demo.elapsed += time.delta_seconds();
+            let position = stops.position(stop_seconds, demo.elapsed);
+            if let (Some(position), Some(transform)) = (position, transforms.get_mut(demo.camera)) {
+                transform.set_translation_x(position.x);
+                transform.set_translation_y(position.y);
+            }
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<InputEvent<String>>>()
+                .register_reader(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        ecs::prelude::{Builder, RunNow, World},
+        winit::MouseButton,
+    };
+
+    use super::*;
+
+    fn world() -> (World, AttractModeSystem) {
+        let mut world = World::new();
+        world.add_resource(ScreenDimensions::new(640, 480, 1.));
+        let mut system = AttractModeSystem::new(AttractConfig {
+            timeout: 10.,
+            ..AttractConfig::default()
+        });
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(TourStops(vec![
+            Vector2::new(0., 0.),
+            Vector2::new(100., 0.),
+        ]));
+        (world, system)
+    }
+
+    fn step(world: &mut World, system: &mut AttractModeSystem, delta: f32) {
+        world.write_resource::<Time>().set_delta_seconds(delta);
+        system.run_now(&world.res);
+        world.maintain();
+    }
+
+    fn send(world: &mut World, event: InputEvent<String>) {
+        world
+            .write_resource::<EventChannel<InputEvent<String>>>()
+            .single_write(event);
+    }
+
+    #[test]
+    fn the_demo_starts_after_the_timeout_and_any_input_ends_it() {
+        let (mut world, mut system) = world();
+        let player_camera = world.create_entity().build();
+        world.write_resource::<ActiveCamera>().entity = Some(player_camera);
+        world.write_resource::<SplitScreen>().enabled = true;
+
+        step(&mut world, &mut system, 6.);
+        assert!(!world.read_resource::<AttractMode>().active);
+
+        // Input partway through restarts the count.
+        send(&mut world, InputEvent::KeyTyped('a'));
+        step(&mut world, &mut system, 0.);
+        step(&mut world, &mut system, 6.);
+        assert!(!world.read_resource::<AttractMode>().active);
+
+        step(&mut world, &mut system, 4.);
+        assert!(world.read_resource::<AttractMode>().active);
+        let demo_camera = world.read_resource::<ActiveCamera>().entity.unwrap();
+        assert_ne!(demo_camera, player_camera);
+        assert!(!world.read_resource::<SplitScreen>().enabled);
+
+        // Plugging a controller in isn't someone playing.
+        send(&mut world, InputEvent::ControllerConnected { which: 0 });
+        step(&mut world, &mut system, 1.);
+        assert!(world.read_resource::<AttractMode>().active);
+
+        send(
+            &mut world,
+            InputEvent::MouseButtonPressed(MouseButton::Left),
+        );
+        step(&mut world, &mut system, 1.);
+        assert!(!world.read_resource::<AttractMode>().active);
+        assert_eq!(
+            world.read_resource::<ActiveCamera>().entity,
+            Some(player_camera)
+        );
+        assert!(world.read_resource::<SplitScreen>().enabled);
+        assert!(!world.is_alive(demo_camera));
+    }
+}
@@ -0,0 +1,113 @@
+//! Auto-tiling: picking each terrain tile's sprite from which of its neighbours are the same
+//! terrain, so walls and shores join up without placing every variant by hand.
+//!
+//! The neighbours form a bitmask that indexes into the sprites of an `AutoTileSet`. With
+//! `Neighbourhood::Edges` the bits are north 1, east 2, south 4 and west 8. With
+//! `Neighbourhood::EdgesAndCorners` they are north 1, north-east 2, east 4, south-east 8,
+//! south 16, south-west 32, west 64 and north-west 128, and a corner only counts when both
+//! edges beside it do, which leaves 47 distinct masks.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::tile_map::Tile;
+
+/// Which neighbours of a tile make up its bitmask.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Neighbourhood {
+    /// The four tiles sharing an edge.
+    #[default]
+    Edges,
+    /// All eight surrounding tiles.
+    EdgesAndCorners,
+}
+
+/// Offsets of the neighbours in each neighbourhood, in bit order, with `y` pointing north.
+const EDGES: [(i64, i64); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const EDGES_AND_CORNERS: [(i64, i64); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+/// The bitmask of the neighbours at which `connects` holds, given their offset from the tile
+/// with `y` pointing north.
+pub fn bitmask(neighbourhood: Neighbourhood, connects: impl Fn(i64, i64) -> bool) -> u8 {
+    match neighbourhood {
+        Neighbourhood::Edges => EDGES
+            .iter()
+            .enumerate()
+            .filter(|(_, &(x, y))| connects(x, y))
+            .fold(0, |mask, (bit, _)| mask | 1 << bit),
+        Neighbourhood::EdgesAndCorners => EDGES_AND_CORNERS
+            .iter()
+            .enumerate()
+            .filter(|(_, &(x, y))| {
+                // A corner only joins up when the edges either side of it do.
+                connects(x, y) && (x == 0 || y == 0 || connects(x, 0) && connects(0, y))
+            })
+            .fold(0, |mask, (bit, _)| mask | 1 << bit),
+    }
+}
+
+/// How to draw one auto-tiled terrain.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AutoTileSet {
+    /// The tile of `TileMap::tiles` that is drawn by this set.
+    pub terrain: Tile,
+    #[serde(default)]
+    pub neighbourhood: Neighbourhood,
+    /// Sprite from the map's sprite sheet for each bitmask.
+    pub sprites: BTreeMap<u8, usize>,
+    /// Sprite for bitmasks missing from `sprites`.
+    pub fallback: usize,
+    /// The tile left behind when this terrain is erased.
+    pub base: Tile,
+}
+
+impl AutoTileSet {
+    pub fn sprite(&self, mask: u8) -> usize {
+        self.sprites.get(&mask).copied().unwrap_or(self.fallback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_set_their_bits() {
+        let north_and_west = |x, y| (x, y) == (0, 1) || (x, y) == (-1, 0);
+        assert_eq!(bitmask(Neighbourhood::Edges, north_and_west), 1 | 8);
+        assert_eq!(bitmask(Neighbourhood::Edges, |_, _| true), 15);
+    }
+
+    #[test]
+    fn corners_only_count_beside_both_edges() {
+        let north_and_north_east = |x, y| (x, y) == (0, 1) || (x, y) == (1, 1);
+        assert_eq!(
+            bitmask(Neighbourhood::EdgesAndCorners, north_and_north_east),
+            1
+        );
+        assert_eq!(bitmask(Neighbourhood::EdgesAndCorners, |_, _| true), 255);
+    }
+
+    #[test]
+    fn missing_masks_use_the_fallback() {
+        let set = AutoTileSet {
+            terrain: Tile::Static(1),
+            neighbourhood: Neighbourhood::Edges,
+            sprites: vec![(15, 4)].into_iter().collect(),
+            fallback: 2,
+            base: Tile::Static(0),
+        };
+        assert_eq!(set.sprite(15), 4);
+        assert_eq!(set.sprite(3), 2);
+    }
+}
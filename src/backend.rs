@@ -0,0 +1,14 @@
+//! Selects the rendering backend from the enabled features.
+//!
+//! Exactly one of `vulkan`, `metal` and `empty` is normally enabled. If several are, for
+//! instance `--features vulkan` on top of the default `metal`, Vulkan is preferred over Metal,
+//! and either over `empty`.
+
+#[cfg(feature = "vulkan")]
+pub type GameBackend = amethyst::renderer::rendy::vulkan::Backend;
+
+#[cfg(all(feature = "metal", not(feature = "vulkan")))]
+pub type GameBackend = amethyst::renderer::rendy::metal::Backend;
+
+#[cfg(all(feature = "empty", not(any(feature = "vulkan", feature = "metal"))))]
+pub type GameBackend = amethyst::renderer::rendy::empty::Backend;
@@ -0,0 +1,290 @@
+//! Named enemy blueprints, so enemies placed again and again needn't repeat everything about
+//! them.
+//!
+//! `blueprints.ron` describes kinds of enemy by name. A blueprint can name a `base` it is a
+//! variant of, giving only what it changes: anything it leaves out is the base's, and its
+//! `extra_on_death` effects play after the base's. Bases can be variants themselves. The
+//! blueprints are resolved as they load, which fails on a base that isn't a blueprint or a
+//! blueprint that ends up its own base.
+//!
+//! An `EnemySpawn` naming a `blueprint` gets everything the blueprint gives from it, in place
+//! of its own, when a new game's level loads. Saved games keep their enemies as they were. A
+//! spawn naming a blueprint that isn't there is logged and placed as it is given, and an enemy
+//! left with no health, from itself or a blueprint, is logged and left out.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use failure::format_err;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::SpriteAnimation, boss::Boss, combat::Resistances, death::DeathEffect,
+    enemy::EnemySpawn, highlight::HighlightPulse, hitbox::Hitbox, script::ScriptAction,
+    tile_map::TileMap, wave::WaveConfig,
+};
+
+/// A kind of enemy, as given in `blueprints.ron`. Every field is optional, left to the base,
+/// or to the spawn for a blueprint without one.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EnemyBlueprint {
+    /// The blueprint this one is a variant of.
+    pub base: Option<String>,
+    pub health: Option<f32>,
+    pub sprite: Option<usize>,
+    /// Replaces the base's effects on death, its extra ones included.
+    pub on_death: Option<Vec<DeathEffect>>,
+    /// Effects on death played after the others, adding to the base's.
+    pub extra_on_death: Vec<DeathEffect>,
+    pub boss: Option<Boss>,
+    pub chases: Option<bool>,
+    pub resistances: Option<Resistances>,
+    pub highlight: Option<HighlightPulse>,
+    pub animation: Option<SpriteAnimation>,
+    pub hitbox: Option<Hitbox>,
+}
+
+impl EnemyBlueprint {
+    /// This blueprint as a variant of `base`, already resolved, leaving no base of its own.
+    pub fn over(&self, base: &EnemyBlueprint) -> EnemyBlueprint {
+        // Effects replacing the base's replace its extra ones too.
+        let (on_death, mut extra_on_death) = match &self.on_death {
+            Some(on_death) => (Some(on_death.clone()), Vec::new()),
+            None => (base.on_death.clone(), base.extra_on_death.clone()),
+        };
+        extra_on_death.extend(self.extra_on_death.iter().cloned());
+        EnemyBlueprint {
+            base: None,
+            health: self.health.or(base.health),
+            sprite: self.sprite.or(base.sprite),
+            on_death,
+            extra_on_death,
+            boss: self.boss.clone().or_else(|| base.boss.clone()),
+            chases: self.chases.or(base.chases),
+            resistances: self.resistances.or(base.resistances),
+            highlight: self.highlight.or(base.highlight),
+            animation: self.animation.clone().or_else(|| base.animation.clone()),
+            hitbox: self.hitbox.clone().or_else(|| base.hitbox.clone()),
+        }
+    }
+
+    /// Gives `spawn` everything this blueprint, resolved, gives.
+    fn apply(&self, spawn: &mut EnemySpawn) {
+        if let Some(health) = self.health {
+            spawn.health = health;
+        }
+        if let Some(sprite) = self.sprite {
+            spawn.sprite = sprite;
+        }
+        if let Some(on_death) = &self.on_death {
+            spawn.on_death = on_death.clone();
+        }
+        spawn.on_death.extend(self.extra_on_death.iter().cloned());
+        if let Some(boss) = &self.boss {
+            spawn.boss = Some(boss.clone());
+        }
+        if let Some(chases) = self.chases {
+            spawn.chases = chases;
+        }
+        if let Some(resistances) = self.resistances {
+            spawn.resistances = Some(resistances);
+        }
+        if let Some(highlight) = self.highlight {
+            spawn.highlight = Some(highlight);
+        }
+        if let Some(animation) = &self.animation {
+            spawn.animation = Some(animation.clone());
+        }
+        if let Some(hitbox) = &self.hitbox {
+            spawn.hitbox = Some(hitbox.clone());
+        }
+    }
+}
+
+/// Every blueprint, resolved, by name.
+#[derive(Clone, Debug, Default)]
+pub struct Blueprints(BTreeMap<String, EnemyBlueprint>);
+
+impl Blueprints {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, failure::Error> {
+        let blueprints = ron::de::from_reader(fs::File::open(path)?)?;
+        Blueprints::resolve(&blueprints)
+    }
+
+    /// Resolves every blueprint of `blueprints` over its bases, as the module describes.
+    pub fn resolve(blueprints: &BTreeMap<String, EnemyBlueprint>) -> Result<Self, failure::Error> {
+        let mut resolved = BTreeMap::new();
+        for name in blueprints.keys() {
+            resolve_one(name, blueprints, &mut resolved, &mut Vec::new())?;
+        }
+        Ok(Blueprints(resolved))
+    }
+
+    /// Gives every spawn of `spawns` naming a blueprint what the blueprint gives, leaving out
+    /// the enemies with no health, as the module describes.
+    pub fn apply(&self, spawns: &mut Vec<EnemySpawn>) {
+        for spawn in spawns.iter_mut() {
+            if let Some(name) = &spawn.blueprint {
+                match self.0.get(name) {
+                    Some(blueprint) => blueprint.apply(spawn),
+                    None => error!(
+                        "Enemy at ({}, {}) is made from {:?}, which is not a blueprint, placing it \
+                         as it is",
+                        spawn.x, spawn.y, name
+                    ),
+                }
+            }
+        }
+        spawns.retain(|spawn| {
+            if spawn.health <= 0. {
+                error!(
+                    "Enemy at ({}, {}) has no health, from itself or a blueprint, leaving it out",
+                    spawn.x, spawn.y
+                );
+            }
+            spawn.health > 0.
+        });
+    }
+
+    /// Applies the blueprints to the enemies the map places and its scripts spawn.
+    pub fn apply_to_map(&self, map: &mut TileMap) {
+        self.apply(&mut map.enemies);
+        for action in map.scripts.values_mut() {
+            if let ScriptAction::SpawnWave(spawns) = action {
+                self.apply(spawns);
+            }
+        }
+    }
+
+    /// Applies the blueprints to the enemies of every wave.
+    pub fn apply_to_waves(&self, waves: &mut WaveConfig) {
+        for wave in &mut waves.waves {
+            self.apply(&mut wave.enemies);
+        }
+    }
+}
+
+/// Resolves `name` into `resolved`, along with its bases. `chain` holds the blueprints being
+/// resolved that lead to it, variant first, for finding cycles.
+fn resolve_one<'a>(
+    name: &'a str,
+    blueprints: &'a BTreeMap<String, EnemyBlueprint>,
+    resolved: &mut BTreeMap<String, EnemyBlueprint>,
+    chain: &mut Vec<&'a str>,
+) -> Result<EnemyBlueprint, failure::Error> {
+    if let Some(blueprint) = resolved.get(name) {
+        return Ok(blueprint.clone());
+    }
+    if chain.contains(&name) {
+        chain.push(name);
+        return Err(format_err!(
+            "Blueprint {:?} is its own base, through {}",
+            name,
+            chain.join(" -> ")
+        ));
+    }
+    let blueprint = match blueprints.get(name) {
+        Some(blueprint) => blueprint,
+        None => {
+            return Err(format_err!(
+                "Blueprint {:?} is a variant of {:?}, which is not a blueprint",
+                chain.last().copied().unwrap_or_default(),
+                name
+            ))
+        }
+    };
+    let result = match &blueprint.base {
+        Some(base) => {
+            chain.push(name);
+            let base = resolve_one(base, blueprints, resolved, chain)?;
+            chain.pop();
+            blueprint.over(&base)
+        }
+        None => blueprint.over(&EnemyBlueprint::default()),
+    };
+    resolved.insert(name.to_string(), result.clone());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blueprints(text: &str) -> Result<Blueprints, failure::Error> {
+        Blueprints::resolve(&ron::de::from_str(text)?)
+    }
+
+    fn spawn(text: &str) -> EnemySpawn {
+        ron::de::from_str(text).unwrap()
+    }
+
+    const GOBLINS: &str = r#"{
+        "goblin": (
+            health: Some(30.0),
+            chases: Some(true),
+            on_death: Some([Corpse(sprite: 1, linger: 1.0)]),
+        ),
+        "fallen_goblin": (
+            base: Some("goblin"),
+            extra_on_death: [Corpse(sprite: 2, linger: 5.0)],
+        ),
+        "goblin_scout": (base: Some("goblin"), health: Some(20.0)),
+        "quiet_scout": (base: Some("goblin_scout"), on_death: Some([])),
+    }"#;
+
+    #[test]
+    fn variants_take_what_they_leave_out_from_their_base() {
+        let blueprints = blueprints(GOBLINS).unwrap();
+        let mut spawns = vec![spawn(
+            r#"(x: 1.0, y: 2.0, blueprint: Some("goblin_scout"))"#,
+        )];
+        blueprints.apply(&mut spawns);
+        assert_eq!(spawns[0].health, 20.);
+        assert!(spawns[0].chases);
+        assert_eq!(spawns[0].on_death.len(), 1);
+    }
+
+    #[test]
+    fn extra_effects_add_to_the_base_and_replacements_drop_them() {
+        let blueprints = blueprints(GOBLINS).unwrap();
+        let mut spawns = vec![
+            spawn(r#"(x: 0.0, y: 0.0, blueprint: Some("fallen_goblin"))"#),
+            spawn(r#"(x: 0.0, y: 0.0, blueprint: Some("quiet_scout"))"#),
+        ];
+        blueprints.apply(&mut spawns);
+        let lingers: Vec<f32> = spawns[0]
+            .on_death
+            .iter()
+            .map(|effect| match effect {
+                DeathEffect::Corpse { linger, .. } => *linger,
+                _ => 0.,
+            })
+            .collect();
+        assert_eq!(lingers, [1., 5.]);
+        assert!(spawns[1].on_death.is_empty());
+        assert_eq!(spawns[1].health, 20.);
+    }
+
+    #[test]
+    fn unknown_and_circular_bases_are_rejected() {
+        assert!(blueprints(r#"{"orc": (base: Some("troll"))}"#).is_err());
+        assert!(blueprints(
+            r#"{"orc": (base: Some("troll"), health: Some(1.0)), "troll": (base: Some("orc"))}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn unknown_blueprints_are_skipped_and_enemies_without_health_left_out() {
+        let blueprints = blueprints(GOBLINS).unwrap();
+        let mut spawns = vec![
+            spawn(r#"(x: 0.0, y: 0.0, blueprint: Some("ogre"), health: 5.0)"#),
+            spawn(r#"(x: 1.0, y: 0.0, blueprint: Some("ogre"))"#),
+            spawn(r#"(x: 2.0, y: 0.0, health: 10.0)"#),
+        ];
+        blueprints.apply(&mut spawns);
+        let placed: Vec<(f32, f32)> = spawns.iter().map(|spawn| (spawn.x, spawn.health)).collect();
+        assert_eq!(placed, [(0., 5.), (2., 10.)]);
+    }
+}
@@ -0,0 +1,361 @@
+//! A health bar across the top of the window for boss fights.
+//!
+//! A boss is an enemy the map gives a `Boss`. It becomes active once a player comes within
+//! `activation_range` of it or it is hurt, and from then until it dies the bar shows its name
+//! and health, split into segments and coloured by the phase its health is in.
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::{transform::Transform, Hidden},
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Entity, Join, NullStorage, Read, ReadExpect,
+        ReadStorage, System, World, WriteStorage,
+    },
+    ui::{Anchor, FontAsset, UiImage, UiText, UiTransform},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::Health,
+    death::Dead,
+    movement::world_position,
+    player::Player,
+    resource_bar::fill_fraction,
+    spatial::SpatialGrid,
+    strings::Strings,
+    ui_theme::{ThemedText, UiTheme},
+};
+
+/// How the boss bar looks, read from `boss_bar.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BossBarConfig {
+    pub width: f32,
+    pub height: f32,
+    /// Pixels between the bar and the top of the window.
+    pub margin: f32,
+    /// How many equal segments the bar is split into.
+    pub segments: usize,
+    /// Pixels between neighbouring segments.
+    pub segment_gap: f32,
+    pub background: [f32; 4],
+    /// The fill of a boss without a phase for its health.
+    pub fill: [f32; 4],
+    /// World units from a player at which a boss becomes active.
+    pub activation_range: f32,
+}
+
+impl Default for BossBarConfig {
+    fn default() -> Self {
+        BossBarConfig {
+            width: 480.,
+            height: 14.,
+            margin: 24.,
+            segments: 10,
+            segment_gap: 2.,
+            background: [0.1, 0.1, 0.1, 0.8],
+            fill: [0.8, 0.15, 0.15, 1.],
+            activation_range: 192.,
+        }
+    }
+}
+
+/// A stretch of a boss's health drawn in its own colour.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct BossPhase {
+    /// The phase starts once health falls to this fraction of the maximum.
+    pub below: f32,
+    pub color: [f32; 4],
+}
+
+/// Makes an enemy a boss, shown on the boss bar as `name`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Boss {
+    /// A string id, shown in the current locale, or else the name itself.
+    pub name: String,
+    #[serde(default)]
+    pub phases: Vec<BossPhase>,
+}
+
+impl Component for Boss {
+    type Storage = DenseVecStorage<Self>;
+}
+
+impl Boss {
+    /// The colour of the phase health at `fraction` of the maximum is in, or `fill` before the
+    /// first phase.
+    pub fn phase_color(&self, fraction: f32, fill: [f32; 4]) -> [f32; 4] {
+        self.phases
+            .iter()
+            .filter(|phase| fraction <= phase.below)
+            .min_by(|a, b| a.below.total_cmp(&b.below))
+            .map_or(fill, |phase| phase.color)
+    }
+}
+
+/// How full segment `index` of `segments` is, from `0` to `1`, with the bar at `fraction`.
+pub fn segment_fill(fraction: f32, index: usize, segments: usize) -> f32 {
+    (fraction * segments as f32 - index as f32).clamp(0., 1.)
+}
+
+/// Part of the boss bar, hidden while no boss is active.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BossBarPart;
+
+impl Component for BossBarPart {
+    type Storage = NullStorage<Self>;
+}
+
+/// The fill of one segment of the boss bar.
+#[derive(Clone, Copy, Debug)]
+pub struct BossBarSegment {
+    pub index: usize,
+    /// The segment's left edge relative to the bar's anchor, and its full width.
+    x: f32,
+    width: f32,
+}
+
+impl Component for BossBarSegment {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The text naming the active boss.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BossBarName;
+
+impl Component for BossBarName {
+    type Storage = NullStorage<Self>;
+}
+
+/// Creates the boss bar, hidden until a boss is active.
+pub fn spawn_boss_bar(world: &mut World) {
+    let config = *world.read_resource::<BossBarConfig>();
+    let theme = world.read_resource::<UiTheme>().clone();
+    let segments = config.segments.max(1);
+    let left = -config.width / 2.;
+    let y = -config.margin - config.height;
+    let transform = |id: String, x: f32, z: f32, width: f32| {
+        UiTransform::new(
+            id,
+            Anchor::TopMiddle,
+            Anchor::BottomLeft,
+            x,
+            y,
+            z,
+            width,
+            config.height,
+        )
+    };
+    world
+        .create_entity()
+        .with(transform(
+            "boss_bar_background".to_string(),
+            left,
+            1.,
+            config.width,
+        ))
+        .with(UiImage::SolidColor(config.background))
+        .with(BossBarPart)
+        .with(Hidden)
+        .build();
+    let gaps = config.segment_gap * (segments - 1) as f32;
+    let width = ((config.width - gaps) / segments as f32).max(0.);
+    for index in 0..segments {
+        let x = left + index as f32 * (width + config.segment_gap);
+        world
+            .create_entity()
+            .with(transform(
+                format!("boss_bar_segment_{}", index),
+                x,
+                2.,
+                width,
+            ))
+            .with(UiImage::SolidColor(config.fill))
+            .with(BossBarSegment { index, x, width })
+            .with(BossBarPart)
+            .with(Hidden)
+            .build();
+    }
+
+    let font = theme.font(
+        &world.read_resource::<Loader>(),
+        &world.read_resource::<AssetStorage<FontAsset>>(),
+    );
+    let mut text = UiText::new(font, String::new(), theme.text_color, theme.font_size);
+    let mut transform = UiTransform::new(
+        "boss_bar_name".to_string(),
+        Anchor::TopMiddle,
+        Anchor::TopMiddle,
+        0.,
+        y - theme.padding,
+        2.,
+        config.width,
+        theme.font_size * 1.2,
+    );
+    let themed = ThemedText::new(&text, &transform);
+    themed.apply(&theme, &mut text, &mut transform);
+    world
+        .create_entity()
+        .with(transform)
+        .with(text)
+        .with(themed)
+        .with(BossBarName)
+        .with(BossBarPart)
+        .with(Hidden)
+        .build();
+}
+
+/// Picks the active boss and shows it on the boss bar, hiding the bar once it dies.
+#[derive(Default)]
+pub struct BossBarSystem {
+    active: Option<Entity>,
+}
+
+impl<'s> System<'s> for BossBarSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Boss>,
+        ReadStorage<'s, Health>,
+        ReadStorage<'s, Dead>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, BossBarPart>,
+        ReadStorage<'s, BossBarSegment>,
+        ReadStorage<'s, BossBarName>,
+        WriteStorage<'s, Hidden>,
+        WriteStorage<'s, UiImage>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiText>,
+        Read<'s, BossBarConfig>,
+        Read<'s, SpatialGrid>,
+        ReadExpect<'s, Strings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            bosses,
+            healths,
+            dead,
+            players,
+            transforms,
+            parts,
+            segments,
+            names,
+            mut hidden,
+            mut images,
+            mut ui_transforms,
+            mut texts,
+            config,
+            spatial,
+            strings,
+        ): Self::SystemData,
+    ) {
+        let alive = |entity: Entity| {
+            entities.is_alive(entity)
+                && !dead.contains(entity)
+                && bosses.contains(entity)
+                && healths
+                    .get(entity)
+                    .is_some_and(|health| health.current > 0.)
+        };
+        if self.active.is_some_and(|boss| !alive(boss)) {
+            self.active = None;
+        }
+        if self.active.is_none() {
+            let range = config.activation_range;
+            self.active = (&entities, &bosses, &healths, &transforms)
+                .join()
+                .filter(|&(entity, ..)| alive(entity))
+                .find(|(_, _, health, transform)| {
+                    let position = world_position(transform);
+                    health.current < health.max
+                        || !spatial
+                            .query_radius(position, range, |entity| players.contains(entity))
+                            .is_empty()
+                })
+                .map(|(entity, ..)| entity);
+        }
+
+        let shown = self.active.and_then(|entity| {
+            let boss = bosses.get(entity)?;
+            let health = healths.get(entity)?;
+            Some((boss, fill_fraction(health.current, health.max)))
+        });
+        for (entity, _) in (&entities, &parts).join() {
+            if shown.is_some() {
+                hidden.remove(entity);
+            } else if !hidden.contains(entity) {
+                hidden
+                    .insert(entity, Hidden)
+                    .expect("Boss bar part is alive");
+            }
+        }
+        let (boss, fraction) = match shown {
+            Some(shown) => shown,
+            None => return,
+        };
+        let color = boss.phase_color(fraction, config.fill);
+        let count = config.segments.max(1);
+        for (segment, image, transform) in (&segments, &mut images, &mut ui_transforms).join() {
+            transform.local_x = segment.x;
+            transform.width = segment.width * segment_fill(fraction, segment.index, count);
+            *image = UiImage::SolidColor(color);
+        }
+        let name = strings.get_or(&boss.name);
+        for (_, text) in (&names, &mut texts).join() {
+            if text.text != name {
+                text.text.clone_from(&name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILL: [f32; 4] = [1., 1., 1., 1.];
+    const ENRAGED: [f32; 4] = [1., 0.5, 0., 1.];
+    const DESPERATE: [f32; 4] = [1., 0., 0., 1.];
+
+    #[test]
+    fn the_phase_follows_the_health_fraction() {
+        // Out of order, as a map could list them.
+        let boss = Boss {
+            name: "boss.warden".to_string(),
+            phases: vec![
+                BossPhase {
+                    below: 0.25,
+                    color: DESPERATE,
+                },
+                BossPhase {
+                    below: 0.6,
+                    color: ENRAGED,
+                },
+            ],
+        };
+        assert_eq!(boss.phase_color(1., FILL), FILL);
+        assert_eq!(boss.phase_color(0.61, FILL), FILL);
+        assert_eq!(boss.phase_color(0.6, FILL), ENRAGED);
+        assert_eq!(boss.phase_color(0.3, FILL), ENRAGED);
+        assert_eq!(boss.phase_color(0.25, FILL), DESPERATE);
+        assert_eq!(boss.phase_color(0., FILL), DESPERATE);
+
+        let plain = Boss {
+            name: "boss.warden".to_string(),
+            phases: Vec::new(),
+        };
+        assert_eq!(plain.phase_color(0.1, FILL), FILL);
+    }
+
+    #[test]
+    fn segments_fill_in_order() {
+        let fills: Vec<f32> = (0..4).map(|index| segment_fill(0.6, index, 4)).collect();
+        assert_eq!(fills[0], 1.);
+        assert_eq!(fills[1], 1.);
+        assert!((fills[2] - 0.4).abs() < 1e-5);
+        assert_eq!(fills[3], 0.);
+    }
+}
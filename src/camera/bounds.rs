@@ -0,0 +1,140 @@
+use amethyst::{
+    core::math::Vector2,
+    ecs::prelude::{Component, DenseVecStorage},
+};
+
+use super::view_half_extents;
+
+/// The rectangle of world space a camera may show, in world units.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraBounds {
+    pub min: Vector2<f32>,
+    pub max: Vector2<f32>,
+    /// Width of the band inside each edge in which a camera heading for the edge slows down,
+    /// in world units. `0` stops it dead at the edge.
+    pub soft_zone: f32,
+}
+
+impl CameraBounds {
+    pub fn new(min: Vector2<f32>, max: Vector2<f32>) -> Self {
+        CameraBounds {
+            min,
+            max,
+            soft_zone: 0.,
+        }
+    }
+
+    pub fn with_soft_zone(mut self, soft_zone: f32) -> Self {
+        self.soft_zone = soft_zone;
+        self
+    }
+
+    pub fn size(&self) -> Vector2<f32> {
+        self.max - self.min
+    }
+
+    /// The smallest zoom at which a `screen`-sized view fits inside the bounds.
+    pub fn min_zoom(&self, screen: Vector2<f32>) -> f32 {
+        let size = self.size();
+        (screen.x / size.x).max(screen.y / size.y)
+    }
+
+    /// Moves a view of `half` extents centred on `center` so it lies within the bounds. On axes
+    /// where the view is larger than the bounds it is centred on them instead.
+    pub fn clamp_center(&self, center: Vector2<f32>, half: Vector2<f32>) -> Vector2<f32> {
+        let clamp_axis = |axis: usize| {
+            let (min, max) = (self.min[axis] + half[axis], self.max[axis] - half[axis]);
+            if min > max {
+                (self.min[axis] + self.max[axis]) / 2.
+            } else {
+                center[axis].max(min).min(max)
+            }
+        };
+        Vector2::new(clamp_axis(0), clamp_axis(1))
+    }
+
+    /// Like `clamp_center`, but a centre within `soft_zone` of where the view would touch an
+    /// edge is eased towards that edge instead of following `center` all the way, so a camera
+    /// moving at a steady pace slows down before it stops. The result never passes the edge.
+    pub fn soft_clamp_center(&self, center: Vector2<f32>, half: Vector2<f32>) -> Vector2<f32> {
+        let soften_axis = |axis: usize| {
+            let (min, max) = (self.min[axis] + half[axis], self.max[axis] - half[axis]);
+            if min > max {
+                return (self.min[axis] + self.max[axis]) / 2.;
+            }
+            let zone = self.soft_zone.min((max - min) / 2.);
+            let value = center[axis];
+            if zone <= 0. {
+                value.clamp(min, max)
+            } else if value > max - zone {
+                max - zone + soften(value - (max - zone), zone)
+            } else if value < min + zone {
+                min + zone - soften(min + zone - value, zone)
+            } else {
+                value
+            }
+        };
+        Vector2::new(soften_axis(0), soften_axis(1))
+    }
+}
+
+/// How far into a soft zone `zone` wide a centre `distance` past its start ends up. Moves
+/// one for one at the start of the zone and slows towards its end, which it never reaches.
+fn soften(distance: f32, zone: f32) -> f32 {
+    zone * (1. - (-distance / zone).exp())
+}
+
+impl Component for CameraBounds {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Whether the visible area of a view centred on `center` contains `point`.
+pub fn in_view(center: Vector2<f32>, screen: Vector2<f32>, zoom: f32, point: Vector2<f32>) -> bool {
+    let half = view_half_extents(screen, zoom);
+    (point - center).x.abs() <= half.x && (point - center).y.abs() <= half.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds() -> CameraBounds {
+        CameraBounds::new(Vector2::new(0., 0.), Vector2::new(100., 50.))
+    }
+
+    #[test]
+    fn views_are_kept_inside_the_bounds() {
+        let half = Vector2::new(10., 10.);
+        assert_eq!(
+            bounds().clamp_center(Vector2::new(-5., 45.), half),
+            Vector2::new(10., 40.)
+        );
+        assert_eq!(
+            bounds().clamp_center(Vector2::new(50., 25.), half),
+            Vector2::new(50., 25.)
+        );
+    }
+
+    #[test]
+    fn views_larger_than_the_bounds_are_centred_on_them() {
+        assert_eq!(
+            bounds().clamp_center(Vector2::new(0., 0.), Vector2::new(10., 30.)),
+            Vector2::new(10., 25.)
+        );
+        assert_eq!(bounds().min_zoom(Vector2::new(200., 200.)), 4.);
+    }
+
+    #[test]
+    fn soft_zones_slow_views_without_passing_the_edge() {
+        let bounds = bounds().with_soft_zone(10.);
+        let half = Vector2::new(10., 10.);
+        let eased = bounds.soft_clamp_center(Vector2::new(85., 25.), half).x;
+        assert!(eased > 80. && eased < 85.);
+        let far = bounds.soft_clamp_center(Vector2::new(500., 25.), half).x;
+        assert!(far <= 90.);
+        assert_eq!(
+            bounds.soft_clamp_center(Vector2::new(50., 25.), half),
+            Vector2::new(50., 25.)
+        );
+    }
+}
@@ -0,0 +1,520 @@
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadExpect, ReadStorage, System,
+        WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::camera::ActiveCamera,
+    window::ScreenDimensions,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ease_factor, matrices::mouse_pixel, recenter::recentered, CameraMatrices, CameraPreset,
+    RoomBounds, RoomCamera,
+};
+use crate::{death::Dead, movement::world_position, player::Player, tile_map::TileMap};
+
+/// How following cameras filter their target's movement, read from `camera_follow.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CameraFollowConfig {
+    /// Whether cameras follow where the target is heading overall rather than where it is,
+    /// so jitters like attack lunges don't shake the view.
+    pub filter: bool,
+    /// How quickly the filtered position catches up with the target, per second. Movements
+    /// much quicker than this barely show.
+    pub responsiveness: f32,
+    /// Keeps following cameras scrolling along one axis, as for a side-scroller.
+    pub lock: Option<AxisLock>,
+    /// Seconds a camera takes to move over to another player when the one it follows dies.
+    pub handoff_time: f32,
+    /// Leads the camera towards the mouse, so the first player sees more of where they aim.
+    pub aim: Option<AimOffset>,
+}
+
+impl Default for CameraFollowConfig {
+    fn default() -> Self {
+        CameraFollowConfig {
+            filter: true,
+            responsiveness: 3.,
+            lock: None,
+            handoff_time: 0.8,
+            aim: None,
+        }
+    }
+}
+
+/// How far towards the mouse the camera following the first player is led. Only the active
+/// camera is, and only while the mouse is over the window.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct AimOffset {
+    /// How far the camera is led towards the mouse, as a share of how far the mouse is from the
+    /// centre of the view, from `0` to `1`.
+    pub bias: f32,
+    /// The furthest the camera is led from the player, in world units.
+    pub max_offset: f32,
+    /// How quickly the lead catches up with the mouse, per second.
+    pub smoothing: f32,
+}
+
+impl AimOffset {
+    /// How far from the player the camera is led with the mouse `mouse` from the centre of the
+    /// view, both in world units. Being measured on the screen, the lead doesn't feed back into
+    /// where the mouse is as the camera moves.
+    pub fn offset(&self, mouse: Vector2<f32>) -> Vector2<f32> {
+        let offset = mouse * self.bias.clamp(0., 1.);
+        let length = offset.norm();
+        if length > self.max_offset && length > 0. {
+            offset * (self.max_offset.max(0.) / length)
+        } else {
+            offset
+        }
+    }
+}
+
+/// The axis a locked camera still scrolls along.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ScrollAxis {
+    Horizontal,
+    Vertical,
+}
+
+/// Holds following cameras still on one axis, so they only scroll along the other however
+/// their target moves. Camera bounds still apply to them.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct AxisLock {
+    pub scroll: ScrollAxis,
+    /// Where the camera is held on the other axis, from the map's bottom-left corner. Without
+    /// one, it is held where it was when it started following.
+    #[serde(default)]
+    pub at: Option<f32>,
+}
+
+impl AxisLock {
+    /// `target` held on the locked axis, at `at` from the map's `origin` or else where `start`
+    /// is.
+    pub fn locked(
+        &self,
+        target: Vector2<f32>,
+        start: Vector2<f32>,
+        origin: Vector2<f32>,
+    ) -> Vector2<f32> {
+        let axis = match self.scroll {
+            ScrollAxis::Horizontal => 1,
+            ScrollAxis::Vertical => 0,
+        };
+        let mut locked = target;
+        locked[axis] = self.at.map_or(start[axis], |at| origin[axis] + at);
+        locked
+    }
+}
+
+/// Moves a low-pass filtered `current` position towards `sample` over `delta` seconds.
+pub fn low_pass(
+    current: Vector2<f32>,
+    sample: Vector2<f32>,
+    responsiveness: f32,
+    delta: f32,
+) -> Vector2<f32> {
+    current + (sample - current) * ease_factor(responsiveness, delta)
+}
+
+/// Keeps the camera centred on a single `target` entity. Set another with `set_follow_target`
+/// to move the camera over to it smoothly.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraFollow {
+    pub target: Entity,
+    /// How quickly the camera eases towards the target, per second.
+    pub smoothing: f32,
+    /// Where the target is heading overall, with the filter on.
+    intended: Option<Vector2<f32>>,
+    /// Where the camera was when it started following, for an axis lock to hold it at.
+    start: Option<Vector2<f32>>,
+    /// Where the camera was last headed, for a handoff to start from.
+    followed: Option<Vector2<f32>>,
+    handoff: Option<Handoff>,
+    /// How far towards the mouse the camera is led, eased.
+    aim: Vector2<f32>,
+}
+
+/// A camera moving over from the target it followed before to its new one.
+#[derive(Clone, Copy, Debug)]
+struct Handoff {
+    previous: Entity,
+    /// Where the previous target is, or was last seen if it is gone.
+    from: Vector2<f32>,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl CameraFollow {
+    pub fn new(target: Entity, smoothing: f32) -> Self {
+        CameraFollow {
+            target,
+            smoothing,
+            intended: None,
+            start: None,
+            followed: None,
+            handoff: None,
+            aim: Vector2::zeros(),
+        }
+    }
+
+    /// Follows `target` from now on, easing over from the current target for `transition_time`
+    /// seconds rather than cutting to it.
+    pub fn set_follow_target(&mut self, target: Entity, transition_time: f32) {
+        if target == self.target {
+            return;
+        }
+        self.handoff = self
+            .followed
+            .filter(|_| transition_time > 0.)
+            .map(|from| Handoff {
+                previous: self.target,
+                from,
+                elapsed: 0.,
+                duration: transition_time,
+            });
+        self.target = target;
+        self.intended = None;
+    }
+
+    /// Moves where the target is heading by `offset`, along with the target.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        if let Some(intended) = &mut self.intended {
+            *intended += offset;
+        }
+        if let Some(start) = &mut self.start {
+            *start += offset;
+        }
+        if let Some(followed) = &mut self.followed {
+            *followed += offset;
+        }
+        if let Some(handoff) = &mut self.handoff {
+            handoff.from += offset;
+        }
+    }
+}
+
+impl Component for CameraFollow {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Eases each `CameraFollow` camera towards its target, or where its target is heading with
+/// the filter on, led towards the mouse by the config's aim offset and held on one axis by its
+/// lock. A camera following a player who dies is handed over to the first player still
+/// playing. Cameras whose target no longer has a `Transform` hold still, and those held at a
+/// zoom preset or by a `RoomCamera` are left alone.
+pub struct CameraFollowSystem;
+
+impl<'s> System<'s> for CameraFollowSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, CameraFollow>,
+        ReadStorage<'s, CameraPreset>,
+        ReadStorage<'s, RoomCamera>,
+        WriteStorage<'s, RoomBounds>,
+        WriteStorage<'s, Transform>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Dead>,
+        Read<'s, CameraFollowConfig>,
+        Read<'s, TileMap>,
+        Read<'s, Time>,
+        Read<'s, ActiveCamera>,
+        Read<'s, CameraMatrices>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, ScreenDimensions>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut follows,
+            held,
+            room_cameras,
+            mut room_bounds,
+            mut transforms,
+            players,
+            dead,
+            config,
+            map,
+            time,
+            active,
+            matrices,
+            input,
+            dimensions,
+        ): Self::SystemData,
+    ) {
+        let delta = time.delta_seconds();
+        // How far the mouse is from the centre of the active camera's view, in world units.
+        let pixels_per_unit = matrices.pixels_per_unit();
+        let mouse = input
+            .mouse_position()
+            .filter(|_| pixels_per_unit > 0.)
+            .map(|mouse| {
+                (mouse_pixel(mouse, dimensions.height()) - matrices.viewport / 2.) / pixels_per_unit
+            });
+        let survivor = (&entities, &players, !&dead)
+            .join()
+            .min_by_key(|(_, player, _)| player.index)
+            .map(|(entity, _, _)| entity);
+        // Cameras held at a preset or by a room camera are left to those.
+        let targets: Vec<_> = (&entities, &mut follows, !&held, !&room_cameras)
+            .join()
+            .filter_map(|(camera, follow, _, _)| {
+                if let Some(survivor) = survivor.filter(|_| dead.contains(follow.target)) {
+                    follow.set_follow_target(survivor, config.handoff_time);
+                    // Kept to the survivor's room now, rather than the one they died in.
+                    if let Some(room_bounds) = room_bounds.get_mut(camera) {
+                        room_bounds.target = survivor;
+                    }
+                }
+                let position = world_position(transforms.get(follow.target)?);
+                let target = if config.filter {
+                    let intended = follow.intended.map_or(position, |intended| {
+                        low_pass(intended, position, config.responsiveness, delta)
+                    });
+                    follow.intended = Some(intended);
+                    intended
+                } else {
+                    position
+                };
+                let target = match &mut follow.handoff {
+                    Some(handoff) => {
+                        if let Some(previous) = transforms.get(handoff.previous) {
+                            handoff.from = world_position(previous);
+                        }
+                        handoff.elapsed += delta;
+                        let blended =
+                            recentered(handoff.from, target, handoff.elapsed, handoff.duration);
+                        if handoff.elapsed >= handoff.duration {
+                            follow.handoff = None;
+                        }
+                        blended
+                    }
+                    None => target,
+                };
+                follow.followed = Some(target);
+                if let Some(aim) = config.aim {
+                    let aiming = players.get(follow.target).map(|player| player.index) == Some(0)
+                        && active.entity == Some(camera);
+                    let goal = match mouse.filter(|_| aiming) {
+                        Some(mouse) => aim.offset(mouse),
+                        None => Vector2::zeros(),
+                    };
+                    follow.aim += (goal - follow.aim) * ease_factor(aim.smoothing, delta);
+                }
+                let target = target + follow.aim;
+                if follow.start.is_none() {
+                    follow.start = transforms.get(camera).map(world_position);
+                }
+                let target = match (config.lock, follow.start) {
+                    (Some(lock), Some(start)) => lock.locked(target, start, map.origin()),
+                    _ => target,
+                };
+                Some((camera, target, follow.smoothing))
+            })
+            .collect();
+
+        for (camera, target, smoothing) in targets {
+            if let Some(transform) = transforms.get_mut(camera) {
+                let current = world_position(transform);
+                let eased = current + (target - current) * ease_factor(smoothing, delta);
+                transform.set_translation_x(eased.x);
+                transform.set_translation_y(eased.y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, EntityBuilder, RunNow, World};
+
+    use super::*;
+
+    /// A world running `CameraFollowSystem` under `config` at a sixtieth of a second per
+    /// update.
+    fn world(config: CameraFollowConfig) -> (World, CameraFollowSystem) {
+        let mut world = World::new();
+        let mut system = CameraFollowSystem;
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(ScreenDimensions::new(600, 400, 1.));
+        world.add_resource(config);
+        world.write_resource::<Time>().set_delta_seconds(1. / 60.);
+        (world, system)
+    }
+
+    fn spawn_at(world: &mut World, position: Vector2<f32>) -> EntityBuilder<'_> {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(position.x, position.y, 0.);
+        world.create_entity().with(transform)
+    }
+
+    fn spawn_player(world: &mut World, index: usize, position: Vector2<f32>) -> Entity {
+        spawn_at(world, position)
+            .with(Player { index, speed: 60. })
+            .build()
+    }
+
+    /// A camera at `position` following `target` so eagerly it all but sits on where it is
+    /// headed after every update.
+    fn spawn_camera(world: &mut World, target: Entity, position: Vector2<f32>) -> Entity {
+        spawn_at(world, position)
+            .with(CameraFollow::new(target, 1000.))
+            .build()
+    }
+
+    fn position_of(world: &World, entity: Entity) -> Vector2<f32> {
+        world_position(world.read_storage::<Transform>().get(entity).unwrap())
+    }
+
+    fn move_to(world: &World, entity: Entity, position: Vector2<f32>) {
+        let mut transforms = world.write_storage::<Transform>();
+        let transform = transforms.get_mut(entity).unwrap();
+        transform.set_translation_x(position.x);
+        transform.set_translation_y(position.y);
+    }
+
+    /// How far the camera and the player travel while the player jitters back and forth.
+    fn jitter(filter: bool) -> (f32, f32) {
+        let (mut world, mut system) = world(CameraFollowConfig {
+            filter,
+            ..CameraFollowConfig::default()
+        });
+        let player = spawn_player(&mut world, 0, Vector2::zeros());
+        let camera = spawn_camera(&mut world, player, Vector2::zeros());
+        system.run_now(&world.res);
+        let (mut player_travel, mut camera_travel) = (0., 0.);
+        for frame in 0..60 {
+            let before = (position_of(&world, player), position_of(&world, camera));
+            let lunge = if frame % 2 == 0 { 20. } else { 0. };
+            move_to(&world, player, Vector2::new(lunge, 0.));
+            system.run_now(&world.res);
+            player_travel += (position_of(&world, player) - before.0).norm();
+            camera_travel += (position_of(&world, camera) - before.1).norm();
+        }
+        (player_travel, camera_travel)
+    }
+
+    #[test]
+    fn the_filter_smooths_out_jitter() {
+        let (player, camera) = jitter(true);
+        assert_eq!(player, 60. * 20.);
+        assert!(camera < player / 10., "{} of {}", camera, player);
+
+        let (player, camera) = jitter(false);
+        assert!((camera - player).abs() < 0.1);
+    }
+
+    fn aim(bias: f32) -> AimOffset {
+        AimOffset {
+            bias,
+            max_offset: 1000.,
+            smoothing: 6.,
+        }
+    }
+
+    /// Where a camera starting at `start` ends up under `lock` after following a player
+    /// walking from the origin to `(100, 80)`, and whether it held still on the locked axis
+    /// all the way.
+    fn locked_walk(lock: AxisLock, start: Vector2<f32>) -> (Vector2<f32>, bool) {
+        let (mut world, mut system) = world(CameraFollowConfig {
+            filter: false,
+            lock: Some(lock),
+            ..CameraFollowConfig::default()
+        });
+        let player = spawn_player(&mut world, 0, Vector2::zeros());
+        let camera = spawn_camera(&mut world, player, start);
+        let axis = match lock.scroll {
+            ScrollAxis::Horizontal => 1,
+            ScrollAxis::Vertical => 0,
+        };
+        system.run_now(&world.res);
+        let held = position_of(&world, camera)[axis];
+        let mut still = true;
+        for step in 1..=20 {
+            move_to(&world, player, Vector2::new(5., 4.) * step as f32);
+            system.run_now(&world.res);
+            still &= (position_of(&world, camera)[axis] - held).abs() < 1e-3;
+        }
+        (position_of(&world, camera), still)
+    }
+
+    #[test]
+    fn a_locked_axis_holds_at_its_position_or_the_start() {
+        let lock = |scroll, at| AxisLock { scroll, at };
+        let start = Vector2::new(-30., 25.);
+
+        let (end, still) = locked_walk(lock(ScrollAxis::Horizontal, Some(40.)), start);
+        assert!(still);
+        assert!((end - Vector2::new(100., 40.)).norm() < 1e-3);
+
+        let (end, still) = locked_walk(lock(ScrollAxis::Horizontal, None), start);
+        assert!(still);
+        assert!((end - Vector2::new(100., 25.)).norm() < 1e-3);
+
+        let (end, still) = locked_walk(lock(ScrollAxis::Vertical, None), start);
+        assert!(still);
+        assert!((end - Vector2::new(-30., 80.)).norm() < 1e-3);
+    }
+
+    #[test]
+    fn a_dead_players_camera_moves_over_to_the_next_player() {
+        let config = CameraFollowConfig {
+            filter: false,
+            handoff_time: 0.5,
+            ..CameraFollowConfig::default()
+        };
+        let (mut world, mut system) = world(config);
+        let first = spawn_player(&mut world, 0, Vector2::zeros());
+        let second = spawn_player(&mut world, 1, Vector2::new(120., 0.));
+        let camera = spawn_camera(&mut world, first, Vector2::zeros());
+        system.run_now(&world.res);
+
+        world.write_storage::<Dead>().insert(first, Dead).unwrap();
+        let mut frames = Vec::new();
+        for _ in 0..40 {
+            system.run_now(&world.res);
+            frames.push(position_of(&world, camera).x);
+        }
+        assert_eq!(
+            world
+                .read_storage::<CameraFollow>()
+                .get(camera)
+                .unwrap()
+                .target,
+            second
+        );
+        // Half way through the handoff's thirty frames, half way across.
+        assert!((frames[14] - 60.).abs() < 5., "{:?}", frames);
+        assert!(frames.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!(frames[28] < 120.);
+        assert!(frames[30..].iter().all(|x| (x - 120.).abs() < 1e-2));
+    }
+
+    #[test]
+    fn lead_is_bias_of_the_mouse_offset() {
+        let lead = aim(0.25).offset(Vector2::new(200., -40.));
+        assert!((lead - Vector2::new(50., -10.)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn lead_stays_short_of_the_mouse_as_bias_nears_one() {
+        let mouse = Vector2::new(120., 0.);
+        assert!((aim(0.99).offset(mouse).x - 118.8).abs() < 1e-3);
+        assert_eq!(aim(2.).offset(mouse), mouse);
+    }
+
+    #[test]
+    fn lead_is_capped() {
+        let capped = AimOffset {
+            max_offset: 30.,
+            ..aim(1.)
+        };
+        assert!((capped.offset(Vector2::new(0., 400.)) - Vector2::new(0., 30.)).norm() < 1e-4);
+    }
+}
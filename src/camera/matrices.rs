@@ -0,0 +1,185 @@
+use amethyst::{
+    core::{
+        math::{Matrix4, Vector2, Vector4},
+        transform::Transform,
+    },
+    ecs::prelude::{Entities, Join, Read, ReadExpect, ReadStorage, System, Write},
+    renderer::camera::{ActiveCamera, Camera},
+    window::ScreenDimensions,
+};
+
+use crate::split_screen::{SplitScreen, SplitView};
+
+use super::viewport_size;
+
+/// The active camera's view and projection as of this frame, for whatever needs to take points
+/// between the world and the screen the way the renderer does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraMatrices {
+    /// From the world into the camera's space, the inverse of its transform.
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    /// `projection * view`, from the world to clip space.
+    pub view_projection: Matrix4<f32>,
+    /// From clip space back to the world.
+    pub inverse_view_projection: Matrix4<f32>,
+    /// The size in pixels of the area the camera draws to.
+    pub viewport: Vector2<f32>,
+}
+
+impl Default for CameraMatrices {
+    fn default() -> Self {
+        CameraMatrices::new(
+            Matrix4::identity(),
+            Matrix4::identity(),
+            Vector2::new(1., 1.),
+        )
+    }
+}
+
+impl CameraMatrices {
+    pub fn new(view: Matrix4<f32>, projection: Matrix4<f32>, viewport: Vector2<f32>) -> Self {
+        let view_projection = projection * view;
+        CameraMatrices {
+            view,
+            projection,
+            view_projection,
+            inverse_view_projection: view_projection
+                .try_inverse()
+                .unwrap_or_else(Matrix4::identity),
+            viewport,
+        }
+    }
+
+    /// Where `point` appears in the viewport, in pixels from the bottom left.
+    pub fn world_to_screen(&self, point: Vector2<f32>) -> Vector2<f32> {
+        let clip = self.view_projection * Vector4::new(point.x, point.y, 0., 1.);
+        let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+        // Clip space runs from -1 to 1 across the viewport, with -1 at the top.
+        Vector2::new(
+            (x + 1.) / 2. * self.viewport.x,
+            (1. - y) / 2. * self.viewport.y,
+        )
+    }
+
+    /// The world point shown at `pixel`, measured from the bottom left of the viewport. The
+    /// inverse of `world_to_screen`.
+    pub fn screen_to_world(&self, pixel: Vector2<f32>) -> Vector2<f32> {
+        let clip = Vector4::new(
+            pixel.x / self.viewport.x * 2. - 1.,
+            1. - pixel.y / self.viewport.y * 2.,
+            0.,
+            1.,
+        );
+        let world = self.inverse_view_projection * clip;
+        Vector2::new(world.x / world.w, world.y / world.w)
+    }
+
+    /// The world point under the mouse at `mouse`, as `InputHandler::mouse_position` gives it
+    /// in a window `window_height` pixels tall.
+    pub fn mouse_to_world(&self, mouse: (f64, f64), window_height: f32) -> Vector2<f32> {
+        self.screen_to_world(mouse_pixel(mouse, window_height))
+    }
+
+    /// Screen pixels per world unit across the viewport.
+    pub fn pixels_per_unit(&self) -> f32 {
+        self.view_projection[(0, 0)].abs() * self.viewport.x / 2.
+    }
+}
+
+/// The pixel at `mouse`, as `InputHandler::mouse_position` gives it in a window
+/// `window_height` pixels tall, measured from the bottom left as `CameraMatrices` takes it. The
+/// mouse is measured from the top of the window.
+pub fn mouse_pixel((x, y): (f64, f64), window_height: f32) -> Vector2<f32> {
+    Vector2::new(x as f32, window_height - y as f32)
+}
+
+/// Keeps `CameraMatrices` up to date with the active camera, or the first if none is active,
+/// once cameras have moved and their projections have caught up with the window.
+pub struct CameraMatricesSystem;
+
+impl<'s> System<'s> for CameraMatricesSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Camera>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, SplitView>,
+        Read<'s, ActiveCamera>,
+        Read<'s, SplitScreen>,
+        ReadExpect<'s, ScreenDimensions>,
+        Write<'s, CameraMatrices>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            cameras,
+            transforms,
+            split_views,
+            active_camera,
+            split_screen,
+            dimensions,
+            mut matrices,
+        ): Self::SystemData,
+    ) {
+        let camera = active_camera
+            .entity
+            .filter(|&entity| cameras.contains(entity))
+            .or_else(|| {
+                (&entities, &cameras)
+                    .join()
+                    .map(|(entity, _)| entity)
+                    .next()
+            });
+        let camera = camera.and_then(|entity| {
+            Some((
+                cameras.get(entity)?,
+                transforms.get(entity)?,
+                split_views.get(entity),
+            ))
+        });
+        if let Some((camera, transform, split_view)) = camera {
+            let screen = Vector2::new(dimensions.width(), dimensions.height());
+            *matrices = CameraMatrices::new(
+                transform.view_matrix().map(|value| value.as_f32()),
+                *camera.as_matrix(),
+                viewport_size(split_view, &split_screen, screen),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::centered_projection;
+
+    fn matrices(center: Vector2<f32>, zoom: f32) -> CameraMatrices {
+        let screen = Vector2::new(640., 360.);
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(center.x, center.y, 10.);
+        CameraMatrices::new(
+            transform.view_matrix().map(|value| value.as_f32()),
+            *Camera::from(centered_projection(screen, zoom)).as_matrix(),
+            screen,
+        )
+    }
+
+    #[test]
+    fn world_to_screen_and_back_round_trips_at_any_zoom() {
+        let center = Vector2::new(40., -25.);
+        for &zoom in &[0.5, 1., 3.] {
+            let matrices = matrices(center, zoom);
+            assert!((matrices.pixels_per_unit() - zoom).abs() < 1e-4);
+            let middle = matrices.world_to_screen(center);
+            assert!((middle - Vector2::new(320., 180.)).norm() < 1e-3);
+            for &(x, y) in &[(0., 0.), (40., -25.), (-150., 80.), (123.5, -99.25)] {
+                let point = Vector2::new(x, y);
+                let pixel = matrices.world_to_screen(point);
+                assert!((pixel - middle - (point - center) * zoom).norm() < 1e-2);
+                assert!((matrices.screen_to_world(pixel) - point).norm() < 1e-3);
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Camera behaviours layered on top of the single orthographic game camera.
+//!
+//! The camera's `Transform` is the centre of the view and its `CameraZoom` decides how many
+//! world units fit on screen. Behaviours write those two; the `CameraProjectionSystem` turns
+//! them into the camera's projection. The active camera's view and projection matrices are then
+//! kept in `CameraMatrices`, which overlays place things on screen by.
+//!
+//! A camera sent to a zoom preset is held there by its `CameraPreset` until the preset's
+//! hotkey is pressed again, and its usual behaviour leaves it alone meanwhile. The recenter
+//! hotkey takes it off the preset and eases it back to the player it follows.
+//!
+//! With `room_bounds.ron` enabled, maps made of rooms get cameras that follow a player but are
+//! kept from showing past the walls of the room the player is in.
+//!
+//! With `speed_zoom.ron` enabled, game cameras zoom out a little as their targets speed up and
+//! back in as they slow down, whether they follow one player or frame them all.
+//!
+//! Hits and explosions add to a `Trauma` that shakes every camera and wears off. The shake is
+//! put on after the behaviours have had their say and taken off before they next do, so they
+//! never see it.
+
+mod bounds;
+mod follow;
+mod matrices;
+mod preset;
+mod recenter;
+mod room;
+mod room_bounds;
+mod shake;
+mod speed_zoom;
+mod zoom_fit;
+
+pub use self::{
+    bounds::CameraBounds,
+    follow::{CameraFollow, CameraFollowConfig, CameraFollowSystem},
+    matrices::{mouse_pixel, CameraMatrices, CameraMatricesSystem},
+    preset::{CameraPreset, ZoomPresetSystem, ZoomPresets},
+    recenter::{RecenterConfig, RecenterSystem, Recentering},
+    room::{Room, RoomCamera, RoomCameraSystem},
+    room_bounds::{RoomBounds, RoomBoundsConfig, RoomBoundsSystem},
+    shake::{CameraShakeSystem, CameraSteadySystem, ShakeConfig, Trauma},
+    speed_zoom::{SpeedZoomConfig, SpeedZoomSystem},
+    zoom_fit::{BoundsPolicyToggleSystem, CameraTarget, ZoomToFit, ZoomToFitSystem},
+};
+
+use amethyst::{
+    core::math::Vector2,
+    ecs::prelude::{
+        Component, DenseVecStorage, Join, Read, ReadExpect, ReadStorage, System, WriteStorage,
+    },
+    renderer::camera::{Camera, Projection},
+    window::ScreenDimensions,
+};
+
+use crate::split_screen::{view_size, SplitScreen, SplitView};
+
+/// Screen pixels per world unit, clamped to `[min, max]`.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraZoom {
+    level: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl CameraZoom {
+    pub fn new(level: f32, min: f32, max: f32) -> Self {
+        CameraZoom {
+            level: level.max(min).min(max),
+            min,
+            max,
+        }
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn set_level(&mut self, level: f32) {
+        self.level = self.clamp(level);
+    }
+
+    pub fn clamp(&self, level: f32) -> f32 {
+        level.max(self.min).min(self.max)
+    }
+}
+
+impl Component for CameraZoom {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Half the size of the visible area, in world units, for a screen of `screen` pixels.
+pub fn view_half_extents(screen: Vector2<f32>, zoom: f32) -> Vector2<f32> {
+    screen / (2. * zoom)
+}
+
+/// An orthographic projection centred on the camera's position.
+pub fn centered_projection(screen: Vector2<f32>, zoom: f32) -> Projection {
+    let half = view_half_extents(screen, zoom);
+    Projection::orthographic(-half.x, half.x, -half.y, half.y, 0.0, 20.0)
+}
+
+/// The size in pixels of the area a camera renders to: its half of the window if it has a
+/// `SplitView` and split-screen is enabled, otherwise the whole `screen`.
+pub fn viewport_size(
+    split_view: Option<&SplitView>,
+    split_screen: &SplitScreen,
+    screen: Vector2<f32>,
+) -> Vector2<f32> {
+    match split_view {
+        Some(view) if split_screen.enabled => view_size(view.0, screen),
+        _ => screen,
+    }
+}
+
+/// Keeps each zoomed camera's projection in sync with its zoom level and the window size.
+///
+/// While split-screen is enabled, cameras assigned to a `SplitView` only cover their half.
+pub struct CameraProjectionSystem;
+
+impl<'s> System<'s> for CameraProjectionSystem {
+    type SystemData = (
+        ReadStorage<'s, CameraZoom>,
+        ReadStorage<'s, SplitView>,
+        WriteStorage<'s, Camera>,
+        Read<'s, SplitScreen>,
+        ReadExpect<'s, ScreenDimensions>,
+    );
+
+    fn run(
+        &mut self,
+        (zooms, split_views, mut cameras, split_screen, dimensions): Self::SystemData,
+    ) {
+        let screen = Vector2::new(dimensions.width(), dimensions.height());
+        for (zoom, split_view, camera) in (&zooms, split_views.maybe(), &mut cameras).join() {
+            let size = viewport_size(split_view, &split_screen, screen);
+            camera.set_projection(centered_projection(size, zoom.level()));
+        }
+    }
+}
+
+/// Exponential smoothing factor for easing towards a target at `rate` per second.
+pub fn ease_factor(rate: f32, delta: f32) -> f32 {
+    1. - (-rate * delta).exp()
+}
+
+/// Eases in and out of a pan; `t` runs from `0` to `1`.
+pub fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
@@ -0,0 +1,242 @@
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Join, Read, ReadExpect, ReadStorage, System, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::camera::ActiveCamera,
+    window::ScreenDimensions,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{ease_factor, view_half_extents, CameraBounds, CameraZoom};
+use crate::{movement::world_position, tile_map::TileMap};
+
+/// A framing the camera can be sent to with a hotkey.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ZoomPreset {
+    pub name: String,
+    pub zoom: f32,
+    /// Point of the map to centre the view on, measured from its bottom-left corner like
+    /// everything else it places. Without one the camera zooms where it is.
+    #[serde(default)]
+    pub position: Option<(f32, f32)>,
+}
+
+/// The zoom presets, read from `zoom_presets.ron`. The `n`th in the list is picked with the
+/// `zoom_preset_n` action, so reordering them reassigns their hotkeys.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ZoomPresets {
+    pub presets: Vec<ZoomPreset>,
+    /// How quickly the camera eases towards a preset, per second.
+    pub smoothing: f32,
+}
+
+impl Default for ZoomPresets {
+    fn default() -> Self {
+        ZoomPresets {
+            presets: Vec::new(),
+            smoothing: 6.,
+        }
+    }
+}
+
+/// Holds a camera at the `n`th zoom preset, in place of its usual `ZoomToFit` or `RoomCamera`
+/// framing.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraPreset(pub usize);
+
+impl Component for CameraPreset {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Sends the active camera to a preset when its hotkey is pressed, and back to its usual
+/// framing when the same hotkey is pressed again. Eases every camera held at a preset towards
+/// it, within the camera's zoom clamp and bounds.
+pub struct ZoomPresetSystem {
+    presets: ZoomPresets,
+    was_pressed: Vec<bool>,
+}
+
+impl ZoomPresetSystem {
+    pub fn new(presets: ZoomPresets) -> Self {
+        let was_pressed = vec![false; presets.presets.len()];
+        ZoomPresetSystem {
+            presets,
+            was_pressed,
+        }
+    }
+}
+
+impl<'s> System<'s> for ZoomPresetSystem {
+    type SystemData = (
+        WriteStorage<'s, CameraPreset>,
+        ReadStorage<'s, CameraBounds>,
+        WriteStorage<'s, CameraZoom>,
+        WriteStorage<'s, Transform>,
+        Read<'s, ActiveCamera>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, Time>,
+        Read<'s, TileMap>,
+    );
+
+    fn run(
+        &mut self,
+        (mut held, bounds, mut zooms, mut transforms, active, input, dimensions, time, map): Self::SystemData,
+    ) {
+        for (index, was_pressed) in self.was_pressed.iter_mut().enumerate() {
+            let pressed = input
+                .action_is_down(&format!("zoom_preset_{}", index + 1))
+                .unwrap_or(false);
+            let picked = pressed && !*was_pressed;
+            *was_pressed = pressed;
+            let camera = match active.entity {
+                Some(camera) if picked => camera,
+                _ => continue,
+            };
+            if held.get(camera).is_some_and(|held| held.0 == index) {
+                held.remove(camera);
+            } else {
+                held.insert(camera, CameraPreset(index))
+                    .expect("Active camera is alive");
+            }
+        }
+
+        let screen = Vector2::new(dimensions.width(), dimensions.height());
+        let t = ease_factor(self.presets.smoothing, time.delta_seconds());
+        for (held, zoom, transform, bounds) in
+            (&held, &mut zooms, &mut transforms, bounds.maybe()).join()
+        {
+            let preset = match self.presets.presets.get(held.0) {
+                Some(preset) => preset,
+                None => continue,
+            };
+            let mut target_zoom = zoom.clamp(preset.zoom);
+            if let Some(bounds) = bounds {
+                target_zoom = target_zoom.max(bounds.min_zoom(screen)).min(zoom.max);
+            }
+            let level = zoom.level();
+            zoom.set_level(level + (target_zoom - level) * t);
+
+            let current = world_position(transform);
+            let goal = preset
+                .position
+                .map_or(current, |(x, y)| Vector2::new(x, y) + map.origin());
+            let mut eased = current + (goal - current) * t;
+            if let Some(bounds) = bounds {
+                eased = bounds.clamp_center(eased, view_half_extents(screen, zoom.level()));
+            }
+            transform.set_translation_x(eased.x);
+            transform.set_translation_y(eased.y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        ecs::prelude::{Builder, Entity, RunNow, World},
+        input::VirtualKeyCode,
+    };
+
+    use super::*;
+    use crate::player::tests::{bind, press_key};
+
+    /// A world with an active camera at the map's corner, zoomed in at 1, and a system for
+    /// two presets bound to the number keys.
+    fn world() -> (World, ZoomPresetSystem, Entity) {
+        let mut system = ZoomPresetSystem::new(
+            ron::de::from_str(
+                r#"(
+                    presets: [
+                        (name: "Close", zoom: 3.),
+                        (name: "Overview", zoom: 0.5, position: Some((200., 100.))),
+                    ],
+                    smoothing: 1000.,
+                )"#,
+            )
+            .unwrap(),
+        );
+        let mut world = World::new();
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(ScreenDimensions::new(600, 400, 1.));
+        world.write_resource::<Time>().set_delta_seconds(1.);
+        bind(
+            &mut world,
+            r#"(
+                axes: {},
+                actions: {
+                    "zoom_preset_1": [[Key(Key1)]],
+                    "zoom_preset_2": [[Key(Key2)]],
+                    "zoom_preset_3": [[Key(Key3)]],
+                },
+            )"#,
+        );
+        let camera = world
+            .create_entity()
+            .with(Transform::default())
+            .with(CameraZoom::new(1., 0.25, 4.))
+            .build();
+        world.write_resource::<ActiveCamera>().entity = Some(camera);
+        (world, system, camera)
+    }
+
+    fn tap(world: &mut World, system: &mut ZoomPresetSystem, key: VirtualKeyCode) {
+        press_key(world, key, true);
+        system.run_now(&world.res);
+        press_key(world, key, false);
+        system.run_now(&world.res);
+    }
+
+    fn framing(world: &World, camera: Entity) -> (Vector2<f32>, f32) {
+        let position = world_position(world.read_storage::<Transform>().get(camera).unwrap());
+        let zoom = world
+            .read_storage::<CameraZoom>()
+            .get(camera)
+            .unwrap()
+            .level();
+        (position, zoom)
+    }
+
+    #[test]
+    fn a_hotkey_applies_its_preset_and_a_second_press_releases_it() {
+        let (mut world, mut system, camera) = world();
+        tap(&mut world, &mut system, VirtualKeyCode::Key2);
+        assert_eq!(
+            world.read_storage::<CameraPreset>().get(camera).unwrap().0,
+            1
+        );
+        let (position, zoom) = framing(&world, camera);
+        assert!((position - Vector2::new(200., 100.)).norm() < 1e-3);
+        assert!((zoom - 0.5).abs() < 1e-3);
+
+        tap(&mut world, &mut system, VirtualKeyCode::Key1);
+        assert_eq!(
+            world.read_storage::<CameraPreset>().get(camera).unwrap().0,
+            0
+        );
+        let (position, zoom) = framing(&world, camera);
+        // Without a position of its own, the preset zooms where the camera is.
+        assert!((position - Vector2::new(200., 100.)).norm() < 1e-3);
+        assert!((zoom - 3.).abs() < 1e-3);
+
+        tap(&mut world, &mut system, VirtualKeyCode::Key1);
+        assert!(world.read_storage::<CameraPreset>().get(camera).is_none());
+    }
+
+    #[test]
+    fn a_preset_past_the_list_is_ignored() {
+        let (mut world, mut system, camera) = world();
+        tap(&mut world, &mut system, VirtualKeyCode::Key3);
+        assert!(world.read_storage::<CameraPreset>().get(camera).is_none());
+
+        world
+            .write_storage::<CameraPreset>()
+            .insert(camera, CameraPreset(2))
+            .unwrap();
+        system.run_now(&world.res);
+        assert_eq!(framing(&world, camera), (Vector2::zeros(), 1.));
+    }
+}
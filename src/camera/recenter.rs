@@ -0,0 +1,220 @@
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::camera::ActiveCamera,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{smoothstep, CameraFollow, CameraPreset};
+use crate::{movement::world_position, player::Player};
+
+/// How the camera is brought back to its target, read from `camera_recenter.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RecenterConfig {
+    /// Seconds the camera takes to ease back.
+    pub duration: f32,
+}
+
+impl Default for RecenterConfig {
+    fn default() -> Self {
+        RecenterConfig { duration: 0.4 }
+    }
+}
+
+/// A camera easing back onto `target` after the `recenter_camera` action, overriding whatever
+/// else would move it until it gets there.
+#[derive(Clone, Copy, Debug)]
+pub struct Recentering {
+    pub target: Entity,
+    from: Vector2<f32>,
+    elapsed: f32,
+}
+
+impl Recentering {
+    /// Moves where the camera eases from by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        self.from += offset;
+    }
+}
+
+impl Component for Recentering {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Where a camera easing from `from` towards `target` is, `elapsed` seconds into a recenter
+/// taking `duration`.
+pub fn recentered(
+    from: Vector2<f32>,
+    target: Vector2<f32>,
+    elapsed: f32,
+    duration: f32,
+) -> Vector2<f32> {
+    let t = if duration > 0. {
+        smoothstep(elapsed / duration)
+    } else {
+        1.
+    };
+    from + (target - from) * t
+}
+
+/// Brings the active camera back to the entity it follows, or the first player if it follows
+/// none, on the `recenter_camera` action. Takes the camera off any zoom preset it is held at
+/// and eases it to the target over the configured duration, after which its usual behaviour
+/// carries on from there. Runs after the behaviours moving the cameras, but before the room
+/// bounds keep them in their rooms and before the shake.
+pub struct RecenterSystem {
+    config: RecenterConfig,
+    was_pressed: bool,
+}
+
+impl RecenterSystem {
+    pub fn new(config: RecenterConfig) -> Self {
+        RecenterSystem {
+            config,
+            was_pressed: false,
+        }
+    }
+}
+
+impl<'s> System<'s> for RecenterSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Recentering>,
+        WriteStorage<'s, CameraPreset>,
+        ReadStorage<'s, CameraFollow>,
+        ReadStorage<'s, Player>,
+        WriteStorage<'s, Transform>,
+        Read<'s, ActiveCamera>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut recentering,
+            mut presets,
+            follows,
+            players,
+            mut transforms,
+            active,
+            input,
+            time,
+        ): Self::SystemData,
+    ) {
+        let pressed = input.action_is_down("recenter_camera").unwrap_or(false);
+        let recenter = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+
+        if let Some(camera) = active.entity.filter(|_| recenter) {
+            let target = follows.get(camera).map(|follow| follow.target).or_else(|| {
+                (&entities, &players)
+                    .join()
+                    .find(|(_, player)| player.index == 0)
+                    .map(|(entity, _)| entity)
+            });
+            let from = transforms.get(camera).map(world_position);
+            if let (Some(target), Some(from)) = (target, from) {
+                presets.remove(camera);
+                recentering
+                    .insert(
+                        camera,
+                        Recentering {
+                            target,
+                            from,
+                            elapsed: 0.,
+                        },
+                    )
+                    .expect("Active camera is alive");
+            }
+        }
+
+        let delta = time.delta_seconds();
+        let mut arrived = Vec::new();
+        for (camera, recenter) in (&entities, &mut recentering).join() {
+            let target = match transforms.get(recenter.target) {
+                Some(transform) => world_position(transform),
+                None => {
+                    arrived.push(camera);
+                    continue;
+                }
+            };
+            recenter.elapsed += delta;
+            let position = recentered(
+                recenter.from,
+                target,
+                recenter.elapsed,
+                self.config.duration,
+            );
+            if let Some(transform) = transforms.get_mut(camera) {
+                transform.set_translation_x(position.x);
+                transform.set_translation_y(position.y);
+            }
+            if recenter.elapsed >= self.config.duration {
+                arrived.push(camera);
+            }
+        }
+        for camera in arrived {
+            recentering.remove(camera);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        ecs::prelude::{Builder, RunNow, World},
+        input::VirtualKeyCode,
+    };
+
+    use super::*;
+    use crate::player::tests::{bind, press_key};
+
+    #[test]
+    fn the_camera_eases_back_onto_the_player_within_the_duration() {
+        let mut system = RecenterSystem::new(RecenterConfig { duration: 0.5 });
+        let mut world = World::new();
+        System::setup(&mut system, &mut world.res);
+        world.write_resource::<Time>().set_delta_seconds(0.125);
+        bind(
+            &mut world,
+            r#"(axes: {}, actions: {"recenter_camera": [[Key(R)]]})"#,
+        );
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(30., 20., 0.);
+        world
+            .create_entity()
+            .with(transform)
+            .with(Player {
+                index: 0,
+                speed: 60.,
+            })
+            .build();
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(130., -60., 0.);
+        let camera = world
+            .create_entity()
+            .with(transform)
+            .with(CameraPreset(0))
+            .build();
+        world.write_resource::<ActiveCamera>().entity = Some(camera);
+
+        press_key(&mut world, VirtualKeyCode::R, true);
+        let mut distances = Vec::new();
+        for _ in 0..4 {
+            system.run_now(&world.res);
+            let position = world_position(world.read_storage::<Transform>().get(camera).unwrap());
+            distances.push((position - Vector2::new(30., 20.)).norm());
+        }
+        assert!(world.read_storage::<CameraPreset>().get(camera).is_none());
+        assert!(distances.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(distances[2] > 0.);
+        assert!(distances[3] < 1e-4);
+        assert!(world.read_storage::<Recentering>().get(camera).is_none());
+    }
+}
@@ -0,0 +1,272 @@
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadExpect, ReadStorage, System,
+        WriteStorage,
+    },
+    window::ScreenDimensions,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{smoothstep, CameraPreset, CameraZoom};
+use crate::movement::world_position;
+
+/// A fixed camera framing, as a rectangle of world space given by its bottom-left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Room {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Room {
+    pub fn center(&self) -> Vector2<f32> {
+        Vector2::new(self.x + self.width / 2., self.y + self.height / 2.)
+    }
+
+    /// Whether `point` lies inside the room grown by `margin` on every side.
+    pub fn contains(&self, point: Vector2<f32>, margin: f32) -> bool {
+        point.x >= self.x - margin
+            && point.x <= self.x + self.width + margin
+            && point.y >= self.y - margin
+            && point.y <= self.y + self.height + margin
+    }
+
+    /// The room moved by `offset`.
+    pub fn translated(self, offset: Vector2<f32>) -> Room {
+        Room {
+            x: self.x + offset.x,
+            y: self.y + offset.y,
+            ..self
+        }
+    }
+
+    /// The view centre and unclamped zoom that show the whole room on a `screen`-sized view.
+    pub fn framing(&self, screen: Vector2<f32>) -> (Vector2<f32>, f32) {
+        let zoom = (screen.x / self.width).min(screen.y / self.height);
+        (self.center(), zoom)
+    }
+}
+
+/// The room the camera should show for a target at `position`, given the room it shows now.
+///
+/// The current room is kept until the target is more than `hysteresis` world units past its
+/// edge, so a target walking along a boundary doesn't flip the camera back and forth. A target
+/// outside every room also keeps the current room.
+pub fn room_at(
+    rooms: &[Room],
+    position: Vector2<f32>,
+    current: Option<usize>,
+    hysteresis: f32,
+) -> Option<usize> {
+    if let Some(index) = current {
+        if rooms
+            .get(index)
+            .is_some_and(|room| room.contains(position, hysteresis))
+        {
+            return current;
+        }
+    }
+    rooms
+        .iter()
+        .position(|room| room.contains(position, 0.))
+        .or(current)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Pan {
+    from_center: Vector2<f32>,
+    from_zoom: f32,
+    elapsed: f32,
+}
+
+/// Shows one room at a time, panning to the next room when `target` crosses into it and holding
+/// still otherwise.
+#[derive(Clone, Debug)]
+pub struct RoomCamera {
+    pub target: Entity,
+    pub rooms: Vec<Room>,
+    /// How long the pan between two rooms takes, in seconds.
+    pub transition: f32,
+    /// How far past a room's edge the target has to go before the camera leaves the room.
+    pub hysteresis: f32,
+    current: Option<usize>,
+    pan: Option<Pan>,
+}
+
+impl RoomCamera {
+    pub fn new(target: Entity, rooms: Vec<Room>, transition: f32, hysteresis: f32) -> Self {
+        RoomCamera {
+            target,
+            rooms,
+            transition,
+            hysteresis,
+            current: None,
+            pan: None,
+        }
+    }
+
+    /// Moves the rooms, and any pan between them, by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        for room in &mut self.rooms {
+            *room = room.translated(offset);
+        }
+        if let Some(pan) = &mut self.pan {
+            pan.from_center += offset;
+        }
+    }
+}
+
+impl Component for RoomCamera {
+    type Storage = DenseVecStorage<Self>;
+}
+
+pub struct RoomCameraSystem;
+
+impl<'s> System<'s> for RoomCameraSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, RoomCamera>,
+        ReadStorage<'s, CameraPreset>,
+        WriteStorage<'s, CameraZoom>,
+        WriteStorage<'s, Transform>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut room_cameras, held, mut zooms, mut transforms, dimensions, time): Self::SystemData,
+    ) {
+        let screen = Vector2::new(dimensions.width(), dimensions.height());
+        for (camera, room_camera, zoom, _) in
+            (&entities, &mut room_cameras, &mut zooms, !&held).join()
+        {
+            let target = match transforms.get(room_camera.target) {
+                Some(transform) => world_position(transform),
+                None => continue,
+            };
+            let current = match transforms.get(camera) {
+                Some(transform) => world_position(transform),
+                None => continue,
+            };
+
+            let room = room_at(
+                &room_camera.rooms,
+                target,
+                room_camera.current,
+                room_camera.hysteresis,
+            );
+            if room != room_camera.current {
+                // The first room is shown straight away; later ones are panned to.
+                room_camera.pan = room_camera.current.map(|_| Pan {
+                    from_center: current,
+                    from_zoom: zoom.level(),
+                    elapsed: 0.,
+                });
+                room_camera.current = room;
+            }
+            let (center, level) = match room.map(|index| room_camera.rooms[index]) {
+                Some(room) => room.framing(screen),
+                None => continue,
+            };
+
+            let (center, level) = match room_camera.pan.as_mut() {
+                Some(pan) => {
+                    pan.elapsed += time.delta_seconds();
+                    let t = if room_camera.transition > 0. {
+                        smoothstep(pan.elapsed / room_camera.transition)
+                    } else {
+                        1.
+                    };
+                    (
+                        pan.from_center + (center - pan.from_center) * t,
+                        pan.from_zoom + (zoom.clamp(level) - pan.from_zoom) * t,
+                    )
+                }
+                None => (center, level),
+            };
+            if room_camera
+                .pan
+                .is_some_and(|pan| pan.elapsed >= room_camera.transition)
+            {
+                room_camera.pan = None;
+            }
+
+            zoom.set_level(level);
+            if let Some(transform) = transforms.get_mut(camera) {
+                transform.set_translation_x(center.x);
+                transform.set_translation_y(center.y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::view_half_extents;
+
+    fn rooms() -> Vec<Room> {
+        vec![
+            Room {
+                x: 0.,
+                y: 0.,
+                width: 200.,
+                height: 100.,
+            },
+            Room {
+                x: 200.,
+                y: 0.,
+                width: 100.,
+                height: 150.,
+            },
+        ]
+    }
+
+    #[test]
+    fn a_point_maps_to_the_room_holding_it() {
+        let rooms = rooms();
+        assert_eq!(room_at(&rooms, Vector2::new(50., 50.), None, 10.), Some(0));
+        assert_eq!(
+            room_at(&rooms, Vector2::new(250., 120.), None, 10.),
+            Some(1)
+        );
+        assert_eq!(room_at(&rooms, Vector2::new(-50., 50.), None, 10.), None);
+        assert_eq!(room_at(&rooms, Vector2::new(100., 140.), None, 10.), None);
+    }
+
+    #[test]
+    fn the_current_room_is_kept_within_the_hysteresis() {
+        let rooms = rooms();
+        assert_eq!(
+            room_at(&rooms, Vector2::new(205., 50.), Some(0), 10.),
+            Some(0)
+        );
+        assert_eq!(
+            room_at(&rooms, Vector2::new(215., 50.), Some(0), 10.),
+            Some(1)
+        );
+        // Outside every room, the camera stays where it is.
+        assert_eq!(
+            room_at(&rooms, Vector2::new(100., 140.), Some(0), 10.),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn each_room_is_framed_whole() {
+        let screen = Vector2::new(640., 360.);
+        for room in rooms() {
+            let (center, zoom) = room.framing(screen);
+            assert_eq!(center, room.center());
+            let half = view_half_extents(screen, zoom);
+            assert!(half.x >= room.width / 2. - 1e-3 && half.y >= room.height / 2. - 1e-3);
+            // Zoomed in until one side fits exactly.
+            assert!(
+                (half.x - room.width / 2.).abs() < 1e-3 || (half.y - room.height / 2.).abs() < 1e-3
+            );
+        }
+    }
+}
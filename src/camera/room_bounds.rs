@@ -0,0 +1,234 @@
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadExpect, ReadStorage, System,
+        WriteStorage,
+    },
+    window::ScreenDimensions,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ease_factor, view_half_extents, viewport_size, CameraBounds, CameraPreset, CameraZoom, Room,
+};
+use crate::{
+    movement::world_position,
+    split_screen::{SplitScreen, SplitView},
+};
+
+/// Whether following cameras keep to the room their target is in, read from `room_bounds.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RoomBoundsConfig {
+    /// Whether maps made of rooms get a following camera kept to the current room, instead of
+    /// one framing each room whole.
+    pub enabled: bool,
+    /// How far either side of a room's edge, in world units, the bounds blend into those of
+    /// the room beyond it.
+    pub blend: f32,
+    /// How quickly the bounds ease to the current room's, per second.
+    pub smoothing: f32,
+}
+
+impl Default for RoomBoundsConfig {
+    fn default() -> Self {
+        RoomBoundsConfig {
+            enabled: false,
+            blend: 24.,
+            smoothing: 8.,
+        }
+    }
+}
+
+/// How far `point` is inside `room`, in world units to its nearest edge, or how far outside it
+/// is as a negative distance.
+fn depth(room: &Room, point: Vector2<f32>) -> f32 {
+    (point.x - room.x)
+        .min(room.x + room.width - point.x)
+        .min(point.y - room.y)
+        .min(room.y + room.height - point.y)
+}
+
+/// The bounds a camera on a target at `position` keeps to: those of the room it is in, or
+/// while it is within `blend` of a doorway those of the rooms either side, weighted by how
+/// far into each it is. `None` when it is in no room.
+pub fn blended_bounds(rooms: &[Room], position: Vector2<f32>, blend: f32) -> Option<CameraBounds> {
+    let weighted: Vec<_> = rooms
+        .iter()
+        .filter_map(|room| {
+            let depth = depth(room, position);
+            let weight = if blend > 0. {
+                ((depth + blend) / (2. * blend)).clamp(0., 1.)
+            } else if depth >= 0. {
+                1.
+            } else {
+                0.
+            };
+            if weight > 0. {
+                Some((room, weight))
+            } else {
+                None
+            }
+        })
+        .collect();
+    let total: f32 = weighted.iter().map(|(_, weight)| weight).sum();
+    if total <= 0. {
+        return None;
+    }
+    let (min, max) = weighted.iter().fold(
+        (Vector2::zeros(), Vector2::zeros()),
+        |(min, max), (room, weight)| {
+            let corner = Vector2::new(room.x, room.y);
+            let size = Vector2::new(room.width, room.height);
+            (min + corner * *weight, max + (corner + size) * *weight)
+        },
+    );
+    Some(CameraBounds::new(min / total, max / total))
+}
+
+/// Keeps a camera's view inside the room `target` is in, easing to the next room's bounds as
+/// it walks through a doorway. A target outside every room leaves the bounds as they were.
+#[derive(Clone, Debug)]
+pub struct RoomBounds {
+    pub target: Entity,
+    pub rooms: Vec<Room>,
+    bounds: Option<CameraBounds>,
+}
+
+impl RoomBounds {
+    pub fn new(target: Entity, rooms: Vec<Room>) -> Self {
+        RoomBounds {
+            target,
+            rooms,
+            bounds: None,
+        }
+    }
+
+    /// The bounds the camera is kept to as of this frame, if its target has been in a room.
+    pub fn bounds(&self) -> Option<CameraBounds> {
+        self.bounds
+    }
+
+    /// Moves the rooms and the bounds eased towards them by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        for room in &mut self.rooms {
+            *room = room.translated(offset);
+        }
+        if let Some(bounds) = &mut self.bounds {
+            bounds.min += offset;
+            bounds.max += offset;
+        }
+    }
+}
+
+impl Component for RoomBounds {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Clamps each `RoomBounds` camera to its current bounds, zooming in when a room is too small
+/// to fill the view. Runs after the cameras have moved.
+pub struct RoomBoundsSystem;
+
+impl<'s> System<'s> for RoomBoundsSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, RoomBounds>,
+        ReadStorage<'s, CameraPreset>,
+        ReadStorage<'s, SplitView>,
+        WriteStorage<'s, CameraZoom>,
+        WriteStorage<'s, Transform>,
+        Read<'s, RoomBoundsConfig>,
+        Read<'s, SplitScreen>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut room_bounds,
+            held,
+            split_views,
+            mut zooms,
+            mut transforms,
+            config,
+            split_screen,
+            dimensions,
+            time,
+        ): Self::SystemData,
+    ) {
+        let screen = Vector2::new(dimensions.width(), dimensions.height());
+        for (camera, room_bounds, zoom, split_view, _) in (
+            &entities,
+            &mut room_bounds,
+            &mut zooms,
+            split_views.maybe(),
+            !&held,
+        )
+            .join()
+        {
+            let goal = transforms.get(room_bounds.target).and_then(|transform| {
+                blended_bounds(&room_bounds.rooms, world_position(transform), config.blend)
+            });
+            room_bounds.bounds = match (room_bounds.bounds, goal) {
+                (Some(bounds), Some(goal)) => {
+                    let t = ease_factor(config.smoothing, time.delta_seconds());
+                    Some(CameraBounds::new(
+                        bounds.min + (goal.min - bounds.min) * t,
+                        bounds.max + (goal.max - bounds.max) * t,
+                    ))
+                }
+                (bounds, goal) => goal.or(bounds),
+            };
+            let bounds = match room_bounds.bounds {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+
+            let size = viewport_size(split_view, &split_screen, screen);
+            zoom.set_level(zoom.level().max(bounds.min_zoom(size)));
+            let half = view_half_extents(size, zoom.level());
+            if let Some(transform) = transforms.get_mut(camera) {
+                let center = bounds.clamp_center(world_position(transform), half);
+                transform.set_translation_x(center.x);
+                transform.set_translation_y(center.y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(x: f32, y: f32, width: f32, height: f32) -> Room {
+        Room {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn view_never_shows_beyond_the_room() {
+        let rooms = [room(0., 0., 400., 300.), room(400., 0., 400., 300.)];
+        let half = Vector2::new(100., 75.);
+        let target = Vector2::new(200., 150.);
+        let bounds = blended_bounds(&rooms, target, 24.).expect("Target is in a room");
+        for &(x, y) in &[(20f32, 20f32), (390., 150.), (200., 290.), (-50., 500.)] {
+            let center = bounds.clamp_center(Vector2::new(x, y), half);
+            assert!(center.x - half.x >= -1e-3 && center.x + half.x <= 400. + 1e-3);
+            assert!(center.y - half.y >= -1e-3 && center.y + half.y <= 300. + 1e-3);
+        }
+    }
+
+    #[test]
+    fn bounds_blend_across_a_doorway() {
+        let rooms = [room(0., 0., 400., 300.), room(400., 0., 400., 300.)];
+        let bounds = blended_bounds(&rooms, Vector2::new(400., 150.), 24.).unwrap();
+        assert!((bounds.min.x - 200.).abs() < 1e-3 && (bounds.max.x - 600.).abs() < 1e-3);
+        assert!(blended_bounds(&rooms, Vector2::new(-100., 150.), 24.).is_none());
+    }
+}
@@ -0,0 +1,272 @@
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Join, Read, ReadExpect, ReadStorage, Resources,
+        System, SystemData, Write, WriteStorage,
+    },
+    shrev::{EventChannel, ReaderId},
+    window::ScreenDimensions,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{view_half_extents, viewport_size, CameraBounds, CameraZoom, RoomBounds};
+use crate::{
+    movement::world_position,
+    rumble::{RumbleEvent, RumbleKind},
+    split_screen::{SplitScreen, SplitView},
+    texel_snap::CameraSnap,
+};
+
+/// How the cameras shake, read from `shake.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ShakeConfig {
+    pub enabled: bool,
+    /// How far the view moves at full trauma, in world units.
+    pub max_offset: f32,
+    /// How far the view rolls at full trauma, in degrees.
+    pub max_roll: f32,
+    /// Trauma lost per second.
+    pub decay: f32,
+    /// How quickly the shake wobbles, in wobbles per second.
+    pub frequency: f32,
+    /// Trauma added when a player is hurt.
+    pub hit: f32,
+    /// Trauma added when something bursts apart.
+    pub explosion: f32,
+}
+
+impl Default for ShakeConfig {
+    fn default() -> Self {
+        ShakeConfig {
+            enabled: true,
+            max_offset: 8.,
+            max_roll: 2.,
+            decay: 1.5,
+            frequency: 12.,
+            hit: 0.3,
+            explosion: 0.5,
+        }
+    }
+}
+
+/// How shaken the cameras are, from `0` for still to `1`. Anything may add to it, and it wears
+/// off over time; the shake felt is the square of it, so small knocks barely show while
+/// several landing together build into a heavy shake.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Trauma(pub f32);
+
+impl Trauma {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.0 = (self.0 + amount.max(0.)).min(1.);
+    }
+
+    /// Wears off `rate` per second for `delta` seconds.
+    pub fn decay(&mut self, rate: f32, delta: f32) {
+        self.0 = (self.0 - rate * delta).max(0.);
+    }
+
+    /// How hard the cameras shake, from `0` to `1`.
+    pub fn intensity(&self) -> f32 {
+        let trauma = self.0.clamp(0., 1.);
+        trauma * trauma
+    }
+}
+
+/// A smooth wobble in `[-1, 1]` at time `t`, in wobbles, a different one for each `seed`.
+pub fn wobble(t: f32, seed: f32) -> f32 {
+    let phase = seed * 12.9898;
+    ((t * std::f32::consts::TAU + phase).sin()
+        + (t * 2.3 * std::f32::consts::TAU + phase * 1.7).sin())
+        / 2.
+}
+
+/// The offset and roll, in radians, of a camera shaken at `intensity` at time `t`.
+pub fn shake(config: &ShakeConfig, intensity: f32, t: f32) -> (Vector2<f32>, f32) {
+    let t = t * config.frequency;
+    let offset = Vector2::new(wobble(t, 1.), wobble(t, 2.)) * config.max_offset * intensity;
+    let roll = config.max_roll.to_radians() * intensity * wobble(t, 3.);
+    (offset, roll)
+}
+
+/// The shake a camera was given this frame, taken off again before anything moves it next
+/// frame.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraShake {
+    offset: Vector2<f32>,
+    roll: f32,
+}
+
+impl Component for CameraShake {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Takes last frame's shake and texel snap off the cameras, so the behaviours moving them see
+/// where they really are. Runs before them all.
+pub struct CameraSteadySystem;
+
+impl<'s> System<'s> for CameraSteadySystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, CameraShake>,
+        WriteStorage<'s, CameraSnap>,
+        WriteStorage<'s, Transform>,
+    );
+
+    fn run(&mut self, (entities, mut shakes, mut snaps, mut transforms): Self::SystemData) {
+        // The snap went on last, so it comes off first.
+        for (_, snap, transform) in (&entities, snaps.drain(), &mut transforms).join() {
+            let unsnapped = world_position(transform) + snap.offset;
+            transform.set_translation_x(unsnapped.x);
+            transform.set_translation_y(unsnapped.y);
+        }
+        for (_, shake, transform) in (&entities, shakes.drain(), &mut transforms).join() {
+            transform.append_rotation_z_axis(-shake.roll);
+            let steady = world_position(transform) - shake.offset;
+            transform.set_translation_x(steady.x);
+            transform.set_translation_y(steady.y);
+        }
+    }
+}
+
+/// Adds trauma for hits and explosions, wears it off, and shakes every zoomed camera by it.
+/// Runs after everything moving the cameras. A camera kept to bounds, its own or its room's, is
+/// shaken only as far as it stays inside them.
+pub struct CameraShakeSystem {
+    config: ShakeConfig,
+    reader: Option<ReaderId<RumbleEvent>>,
+    elapsed: f32,
+}
+
+impl CameraShakeSystem {
+    pub fn new(config: ShakeConfig) -> Self {
+        CameraShakeSystem {
+            config,
+            reader: None,
+            elapsed: 0.,
+        }
+    }
+}
+
+impl<'s> System<'s> for CameraShakeSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, EventChannel<RumbleEvent>>,
+        Write<'s, Trauma>,
+        ReadStorage<'s, CameraZoom>,
+        ReadStorage<'s, CameraBounds>,
+        ReadStorage<'s, RoomBounds>,
+        ReadStorage<'s, SplitView>,
+        WriteStorage<'s, CameraShake>,
+        WriteStorage<'s, Transform>,
+        Read<'s, SplitScreen>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            events,
+            mut trauma,
+            zooms,
+            camera_bounds,
+            room_bounds,
+            split_views,
+            mut shakes,
+            mut transforms,
+            split_screen,
+            dimensions,
+            time,
+        ): Self::SystemData,
+    ) {
+        let reader = self.reader.as_mut().expect("CameraShakeSystem is set up");
+        for event in events.read(reader) {
+            trauma.add_trauma(match event.kind {
+                RumbleKind::Hit => self.config.hit,
+                RumbleKind::Explosion => self.config.explosion,
+                // Running into an enemy is only felt, not seen.
+                RumbleKind::Bump => 0.,
+            });
+        }
+        let delta = time.delta_seconds();
+        trauma.decay(self.config.decay, delta);
+        self.elapsed += delta;
+        if !self.config.enabled || trauma.intensity() <= 0. {
+            return;
+        }
+
+        let (offset, roll) = shake(&self.config, trauma.intensity(), self.elapsed);
+        let screen = Vector2::new(dimensions.width(), dimensions.height());
+        for (camera, zoom, split_view, transform) in
+            (&entities, &zooms, split_views.maybe(), &mut transforms).join()
+        {
+            let steady = world_position(transform);
+            let bounds = room_bounds
+                .get(camera)
+                .and_then(RoomBounds::bounds)
+                .or_else(|| camera_bounds.get(camera).copied());
+            let shaken = match bounds {
+                Some(bounds) => {
+                    let size = viewport_size(split_view, &split_screen, screen);
+                    bounds.clamp_center(steady + offset, view_half_extents(size, zoom.level()))
+                }
+                None => steady + offset,
+            };
+            // What was actually put on, to take back off.
+            let offset = shaken - steady;
+            transform.set_translation_x(shaken.x);
+            transform.set_translation_y(shaken.y);
+            transform.append_rotation_z_axis(roll);
+            shakes
+                .insert(camera, CameraShake { offset, roll })
+                .expect("Camera is alive");
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<RumbleEvent>>()
+                .register_reader(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shake_is_trauma_squared() {
+        assert_eq!(Trauma(0.5).intensity(), 0.25);
+        assert_eq!(Trauma(1.).intensity(), 1.);
+        assert_eq!(Trauma(0.).intensity(), 0.);
+    }
+
+    #[test]
+    fn trauma_is_capped_and_wears_off() {
+        let mut trauma = Trauma::default();
+        trauma.add_trauma(0.7);
+        trauma.add_trauma(0.7);
+        assert_eq!(trauma, Trauma(1.));
+        trauma.decay(1.5, 0.5);
+        assert!((trauma.0 - 0.25).abs() < 1e-6);
+        trauma.decay(1.5, 1.);
+        assert_eq!(trauma, Trauma(0.));
+    }
+
+    #[test]
+    fn shaken_view_is_kept_inside_bounds() {
+        let bounds = CameraBounds::new(Vector2::new(0., 0.), Vector2::new(200., 100.));
+        let half = Vector2::new(50., 25.);
+        let edge = Vector2::new(150., 75.);
+        let config = ShakeConfig::default();
+        for step in 0..20 {
+            let (offset, _) = shake(&config, 1., step as f32 * 0.05);
+            let shaken = bounds.clamp_center(edge + offset, half);
+            assert!(shaken.x <= 150. && shaken.y <= 75.);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Join, Read, ReadStorage, System, WriteStorage,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use super::{ease_factor, CameraFollow, CameraPreset, CameraTarget, CameraZoom, ZoomToFit};
+use crate::movement::Velocity;
+
+/// How game cameras zoom out with their target's speed, read from `speed_zoom.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpeedZoomConfig {
+    pub enabled: bool,
+    /// The zoom with the target at rest, or moving no faster than `min_speed`.
+    pub rest_zoom: f32,
+    /// The zoom with the target moving at `max_speed` or faster.
+    pub fast_zoom: f32,
+    /// World units per second below which the camera stays at `rest_zoom`.
+    pub min_speed: f32,
+    /// World units per second from which the camera is all the way out at `fast_zoom`.
+    pub max_speed: f32,
+    /// How quickly the zoom eases towards the one for the target's speed, per second.
+    pub responsiveness: f32,
+}
+
+impl Default for SpeedZoomConfig {
+    fn default() -> Self {
+        SpeedZoomConfig {
+            enabled: false,
+            rest_zoom: 1.,
+            fast_zoom: 0.85,
+            min_speed: 60.,
+            max_speed: 400.,
+            responsiveness: 2.,
+        }
+    }
+}
+
+impl SpeedZoomConfig {
+    /// The zoom for a target moving at `speed`, from `rest_zoom` to `fast_zoom` in proportion
+    /// to how far `speed` is from `min_speed` to `max_speed`.
+    pub fn zoom_for(&self, speed: f32) -> f32 {
+        let range = self.max_speed - self.min_speed;
+        let t = if range > 0. {
+            ((speed - self.min_speed) / range).clamp(0., 1.)
+        } else if speed >= self.max_speed {
+            1.
+        } else {
+            0.
+        };
+        self.rest_zoom + (self.fast_zoom - self.rest_zoom) * t
+    }
+
+    /// `zoom_for(speed)` as a share of `rest_zoom`, for cameras whose zoom is set some other
+    /// way and only scaled for speed.
+    pub fn scale_for(&self, speed: f32) -> f32 {
+        if self.rest_zoom > 0. {
+            self.zoom_for(speed) / self.rest_zoom
+        } else {
+            1.
+        }
+    }
+}
+
+/// The share of its fitted zoom a `ZoomToFit` camera is zoomed to for how fast its targets are
+/// moving, eased by the `SpeedZoomSystem`.
+#[derive(Clone, Copy, Debug)]
+pub struct SpeedZoomScale(pub f32);
+
+impl Default for SpeedZoomScale {
+    fn default() -> Self {
+        SpeedZoomScale(1.)
+    }
+}
+
+impl Component for SpeedZoomScale {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Eases each `CameraFollow` camera's zoom towards the one for how fast its target is moving,
+/// within the camera's zoom clamp, while the config has it enabled.
+///
+/// A `ZoomToFit` camera, such as the default main camera, works out its own zoom every frame,
+/// so it is given a `SpeedZoomScale` instead, eased towards the scale for its fastest target
+/// and applied to the fitted zoom on the next frame. Cameras held on a zoom preset keep the
+/// preset's zoom. Runs after the cameras have followed their targets and before their bounds
+/// are applied, which zoom them back in where a room is too small.
+pub struct SpeedZoomSystem {
+    config: SpeedZoomConfig,
+}
+
+impl SpeedZoomSystem {
+    pub fn new(config: SpeedZoomConfig) -> Self {
+        SpeedZoomSystem { config }
+    }
+}
+
+impl<'s> System<'s> for SpeedZoomSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, CameraFollow>,
+        ReadStorage<'s, ZoomToFit>,
+        ReadStorage<'s, CameraTarget>,
+        ReadStorage<'s, CameraPreset>,
+        ReadStorage<'s, Velocity>,
+        WriteStorage<'s, CameraZoom>,
+        WriteStorage<'s, SpeedZoomScale>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, follows, fits, targets, held, velocities, mut zooms, mut scales, time): Self::SystemData,
+    ) {
+        if !self.config.enabled {
+            scales.clear();
+            return;
+        }
+        let t = ease_factor(self.config.responsiveness, time.delta_seconds());
+        for (follow, zoom, _) in (&follows, &mut zooms, !&held).join() {
+            let speed = velocities
+                .get(follow.target)
+                .map_or(0., |velocity| velocity.0.norm());
+            let goal = zoom.clamp(self.config.zoom_for(speed));
+            zoom.set_level(zoom.level() + (goal - zoom.level()) * t);
+        }
+
+        let fastest = (&targets, &velocities)
+            .join()
+            .map(|(_, velocity)| velocity.0.norm())
+            .fold(0., f32::max);
+        let goal = self.config.scale_for(fastest);
+        let fitting: Vec<_> = (&entities, &fits, !&follows, !&held)
+            .join()
+            .map(|(entity, _, _, _)| entity)
+            .collect();
+        for camera in fitting {
+            let scale = scales
+                .entry(camera)
+                .expect("Fitting camera is alive")
+                .or_insert_with(SpeedZoomScale::default);
+            scale.0 += (goal - scale.0) * t;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SpeedZoomConfig {
+        SpeedZoomConfig {
+            enabled: true,
+            ..SpeedZoomConfig::default()
+        }
+    }
+
+    #[test]
+    fn zoom_eases_out_between_min_and_max_speed() {
+        let config = config();
+        assert_eq!(config.zoom_for(0.), config.rest_zoom);
+        assert_eq!(config.zoom_for(config.min_speed), config.rest_zoom);
+        assert_eq!(config.zoom_for(config.max_speed * 2.), config.fast_zoom);
+        let halfway = (config.min_speed + config.max_speed) / 2.;
+        let expected = (config.rest_zoom + config.fast_zoom) / 2.;
+        assert!((config.zoom_for(halfway) - expected).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scale_is_the_share_of_the_rest_zoom() {
+        let config = SpeedZoomConfig {
+            rest_zoom: 2.,
+            fast_zoom: 1.5,
+            ..config()
+        };
+        assert_eq!(config.scale_for(0.), 1.);
+        assert!((config.scale_for(config.max_speed) - 0.75).abs() < 1e-5);
+    }
+}
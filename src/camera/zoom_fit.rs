@@ -0,0 +1,371 @@
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, NullStorage, Read, ReadExpect,
+        ReadStorage, System, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    window::ScreenDimensions,
+};
+
+use super::{
+    bounds::in_view, ease_factor, speed_zoom::SpeedZoomScale, view_half_extents, CameraBounds,
+    CameraPreset, CameraZoom,
+};
+use crate::movement::world_position;
+
+/// Marks an entity the `ZoomToFit` camera should keep in view.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CameraTarget;
+
+impl Component for CameraTarget {
+    type Storage = NullStorage<Self>;
+}
+
+/// Pans and zooms the camera so every `CameraTarget` stays on screen.
+///
+/// The zoom is limited by the camera's `CameraZoom` clamp, so with a single target the camera
+/// simply centres on it at the maximum zoom. With no targets the camera holds still. A
+/// `SpeedZoomScale` on the camera scales the clamped fitted zoom, within the same clamp.
+///
+/// If the camera also has `CameraBounds`, `policy` decides what gives way when the targets are
+/// spread too far apart to frame without showing space outside them.
+#[derive(Clone, Debug)]
+pub struct ZoomToFit {
+    /// World units kept free between the outermost targets and the screen edge.
+    pub padding: f32,
+    /// How quickly the camera eases towards the fitted framing, per second.
+    pub smoothing: f32,
+    pub policy: BoundsPolicy,
+    /// Targets that were left off screen by the last update.
+    pub clipped: Vec<Entity>,
+}
+
+impl ZoomToFit {
+    pub fn new(padding: f32, smoothing: f32) -> Self {
+        ZoomToFit {
+            padding,
+            smoothing,
+            policy: BoundsPolicy::default(),
+            clipped: Vec::new(),
+        }
+    }
+}
+
+/// How a `ZoomToFit` camera resolves a conflict between its targets and its `CameraBounds`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoundsPolicy {
+    /// Never show anything outside the bounds, even if some targets end up off screen.
+    #[default]
+    KeepInBounds,
+    /// Stay inside the bounds only as far as that keeps every target on screen.
+    KeepTargetsVisible,
+}
+
+impl BoundsPolicy {
+    pub fn toggled(self) -> Self {
+        match self {
+            BoundsPolicy::KeepInBounds => BoundsPolicy::KeepTargetsVisible,
+            BoundsPolicy::KeepTargetsVisible => BoundsPolicy::KeepInBounds,
+        }
+    }
+}
+
+/// Switches every `ZoomToFit` camera's `BoundsPolicy` when the `toggle_bounds_policy` action is
+/// pressed.
+#[derive(Default)]
+pub struct BoundsPolicyToggleSystem {
+    was_pressed: bool,
+}
+
+impl<'s> System<'s> for BoundsPolicyToggleSystem {
+    type SystemData = (
+        WriteStorage<'s, ZoomToFit>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(&mut self, (mut fits, input): Self::SystemData) {
+        let pressed = input
+            .action_is_down("toggle_bounds_policy")
+            .unwrap_or(false);
+        if pressed && !self.was_pressed {
+            for fit in (&mut fits).join() {
+                fit.policy = fit.policy.toggled();
+            }
+        }
+        self.was_pressed = pressed;
+    }
+}
+
+impl Component for ZoomToFit {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The view centre and unclamped zoom that frames all `targets` with `padding` on a screen of
+/// `screen` pixels. Returns `None` when there is nothing to frame.
+pub fn fit_targets(
+    targets: &[Vector2<f32>],
+    padding: f32,
+    screen: Vector2<f32>,
+) -> Option<(Vector2<f32>, f32)> {
+    let first = *targets.first()?;
+    let (min, max) = targets.iter().fold((first, first), |(min, max), target| {
+        (
+            Vector2::new(min.x.min(target.x), min.y.min(target.y)),
+            Vector2::new(max.x.max(target.x), max.y.max(target.y)),
+        )
+    });
+    let center = (min + max) / 2.;
+    let size = max - min + Vector2::new(padding, padding) * 2.;
+    let zoom = if size.x > 0. && size.y > 0. {
+        (screen.x / size.x).min(screen.y / size.y)
+    } else {
+        f32::INFINITY
+    };
+    Some((center, zoom))
+}
+
+/// `bounded`, a view centre moved inside the camera bounds from `center`, except on axes where
+/// that would push one of `targets` off a view of `half` extents and `policy` asks to keep them
+/// visible.
+fn bounded_center(
+    center: Vector2<f32>,
+    bounded: Vector2<f32>,
+    half: Vector2<f32>,
+    policy: BoundsPolicy,
+    targets: &[Vector2<f32>],
+) -> Vector2<f32> {
+    if policy == BoundsPolicy::KeepInBounds {
+        return bounded;
+    }
+    let keeps_targets = |axis: usize| {
+        targets
+            .iter()
+            .all(|target| (target[axis] - bounded[axis]).abs() <= half[axis])
+    };
+    Vector2::new(
+        if keeps_targets(0) {
+            bounded.x
+        } else {
+            center.x
+        },
+        if keeps_targets(1) {
+            bounded.y
+        } else {
+            center.y
+        },
+    )
+}
+
+pub struct ZoomToFitSystem;
+
+impl<'s> System<'s> for ZoomToFitSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, CameraTarget>,
+        ReadStorage<'s, CameraBounds>,
+        ReadStorage<'s, CameraPreset>,
+        ReadStorage<'s, SpeedZoomScale>,
+        WriteStorage<'s, ZoomToFit>,
+        WriteStorage<'s, CameraZoom>,
+        WriteStorage<'s, Transform>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            targets,
+            bounds,
+            held,
+            scales,
+            mut fits,
+            mut zooms,
+            mut transforms,
+            dimensions,
+            time,
+        ): Self::SystemData,
+    ) {
+        let (target_entities, positions): (Vec<Entity>, Vec<Vector2<f32>>) =
+            (&entities, &targets, &transforms)
+                .join()
+                .map(|(entity, _, transform)| (entity, world_position(transform)))
+                .unzip();
+        let screen = Vector2::new(dimensions.width(), dimensions.height());
+
+        for (fit, zoom, transform, bounds, scale, _) in (
+            &mut fits,
+            &mut zooms,
+            &mut transforms,
+            bounds.maybe(),
+            scales.maybe(),
+            !&held,
+        )
+            .join()
+        {
+            let (center, target_zoom) = match fit_targets(&positions, fit.padding, screen) {
+                Some(framing) => framing,
+                None => continue,
+            };
+            let scale = scale.map_or(1., |scale| scale.0);
+            let mut target_zoom = zoom.clamp(zoom.clamp(target_zoom) * scale);
+            if let Some(bounds) = bounds {
+                if fit.policy == BoundsPolicy::KeepInBounds {
+                    // Zooming in past the fitted level is what keeps the void off screen.
+                    target_zoom = target_zoom.max(bounds.min_zoom(screen)).min(zoom.max);
+                }
+            }
+
+            let t = ease_factor(fit.smoothing, time.delta_seconds());
+            let level = zoom.level();
+            zoom.set_level(level + (target_zoom - level) * t);
+
+            let half = view_half_extents(screen, zoom.level());
+            let current = world_position(transform);
+            let eased = match bounds {
+                // Easing towards a softly bounded centre slows the camera near the edges; the
+                // hard clamp after it still has the final say.
+                Some(bounds) => {
+                    let soft = bounds.soft_clamp_center(center, half);
+                    let goal = bounded_center(center, soft, half, fit.policy, &positions);
+                    let eased = current + (goal - current) * t;
+                    let hard = bounds.clamp_center(eased, half);
+                    bounded_center(eased, hard, half, fit.policy, &positions)
+                }
+                None => current + (center - current) * t,
+            };
+            transform.set_translation_x(eased.x);
+            transform.set_translation_y(eased.y);
+
+            fit.clipped = target_entities
+                .iter()
+                .zip(&positions)
+                .filter(|(_, position)| !in_view(eased, screen, zoom.level(), **position))
+                .map(|(entity, _)| *entity)
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+
+    fn screen() -> Vector2<f32> {
+        Vector2::new(600., 400.)
+    }
+
+    #[test]
+    fn a_single_target_is_centred_at_the_maximum_zoom() {
+        let (center, zoom) = fit_targets(&[Vector2::new(30., -20.)], 50., screen()).unwrap();
+        assert_eq!(center, Vector2::new(30., -20.));
+        assert_eq!(CameraZoom::new(1., 0.5, 4.).clamp(zoom), 4.);
+        assert_eq!(fit_targets(&[], 50., screen()), None);
+    }
+
+    #[test]
+    fn spreading_targets_zooms_out() {
+        let near = [Vector2::new(-100., -50.), Vector2::new(100., 50.)];
+        let (center, zoom) = fit_targets(&near, 50., screen()).unwrap();
+        assert_eq!(center, Vector2::new(0., 0.));
+        // 300 x 200 units, padding included, on 600 x 400 pixels.
+        assert_eq!(zoom, 2.);
+
+        let far = [Vector2::new(-250., 100.), Vector2::new(250., 100.)];
+        let (center, zoom) = fit_targets(&far, 50., screen()).unwrap();
+        assert_eq!(center, Vector2::new(0., 100.));
+        // The wider axis decides.
+        assert_eq!(zoom, 1.);
+    }
+
+    #[test]
+    fn the_fitted_zoom_is_clamped() {
+        let zoom = CameraZoom::new(1., 1.5, 3.);
+        let far = [Vector2::new(-250., 0.), Vector2::new(250., 0.)];
+        let near = [Vector2::new(-25., 0.), Vector2::new(25., 0.)];
+        let fitted = |targets: &[Vector2<f32>]| fit_targets(targets, 50., screen()).unwrap().1;
+        assert_eq!(zoom.clamp(fitted(&far)), 1.5);
+        assert_eq!(zoom.clamp(fitted(&near)), 3.);
+        assert_eq!(zoom.clamp(fitted(&[far[0], Vector2::new(-50., 0.)])), 2.);
+    }
+
+    /// Runs one update of a camera bounded to 600 x 400 units on a screen of the same size,
+    /// framing targets at `near` and `far` under `policy` with at least `min_zoom`. Returns the
+    /// camera's centre and whether each target was clipped.
+    fn frame_in_bounds(
+        policy: BoundsPolicy,
+        min_zoom: f32,
+        near: Vector2<f32>,
+        far: Vector2<f32>,
+    ) -> (Vector2<f32>, [bool; 2]) {
+        let mut world = World::new();
+        let mut system = ZoomToFitSystem;
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(ScreenDimensions::new(600, 400, 1.));
+        // Fast enough smoothing to settle on the framing in a single update.
+        world.write_resource::<Time>().set_delta_seconds(1.);
+        let target = |world: &mut World, position: Vector2<f32>| {
+            let mut transform = Transform::default();
+            transform.set_translation_xyz(position.x, position.y, 0.);
+            world
+                .create_entity()
+                .with(transform)
+                .with(CameraTarget)
+                .build()
+        };
+        let targets = [target(&mut world, near), target(&mut world, far)];
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(300., 200., 0.);
+        let camera = world
+            .create_entity()
+            .with(transform)
+            .with(ZoomToFit {
+                policy,
+                ..ZoomToFit::new(50., 1000.)
+            })
+            .with(CameraZoom::new(1., min_zoom, 4.))
+            .with(CameraBounds::new(
+                Vector2::new(0., 0.),
+                Vector2::new(600., 400.),
+            ))
+            .build();
+
+        system.run_now(&world.res);
+        let center = world_position(world.read_storage::<Transform>().get(camera).unwrap());
+        let fits = world.read_storage::<ZoomToFit>();
+        let clipped = &fits.get(camera).unwrap().clipped;
+        (
+            center,
+            [clipped.contains(&targets[0]), clipped.contains(&targets[1])],
+        )
+    }
+
+    #[test]
+    fn tight_bounds_keep_the_camera_in_and_report_the_clipped_target() {
+        let (near, far) = (Vector2::new(50., 200.), Vector2::new(700., 200.));
+        let (center, clipped) = frame_in_bounds(BoundsPolicy::KeepInBounds, 1., near, far);
+        // Zoomed out as far as the clamp allows, the view fills the bounds exactly.
+        assert_eq!(center, Vector2::new(300., 200.));
+        assert_eq!(clipped, [false, true]);
+
+        let inside = Vector2::new(450., 200.);
+        let (_, clipped) = frame_in_bounds(BoundsPolicy::KeepInBounds, 1., near, inside);
+        assert_eq!(clipped, [false, false]);
+    }
+
+    #[test]
+    fn keeping_targets_visible_lets_the_view_leave_the_bounds() {
+        let (near, far) = (Vector2::new(50., 200.), Vector2::new(700., 200.));
+        let (center, clipped) = frame_in_bounds(BoundsPolicy::KeepTargetsVisible, 0.5, near, far);
+        assert_eq!(center, Vector2::new(375., 200.));
+        assert_eq!(clipped, [false, false]);
+
+        let (center, clipped) = frame_in_bounds(BoundsPolicy::KeepInBounds, 0.5, near, far);
+        assert_eq!(center, Vector2::new(300., 200.));
+        assert_eq!(clipped, [false, true]);
+    }
+}
@@ -0,0 +1,276 @@
+//! Enemies that chase the players.
+//!
+//! A `Chaser` walks an A* path towards the nearest living player in sight, steered away from
+//! other chasers near it so a pack spreads out around its target instead of stacking on one
+//! spot. Like click-to-move, chasing only sets the `Velocity`, leaving collisions to the
+//! `MovementSystem`.
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Join, Read, ReadStorage, System, WriteStorage,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock,
+    death::Dead,
+    disabled::Disabled,
+    movement::{world_position, Velocity},
+    navigation::{NavGrid, TileCoord},
+    player::Player,
+    sleep::Asleep,
+    spatial::SpatialGrid,
+};
+
+/// How chasing enemies pick and approach their target, read from `chase.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChaseConfig {
+    /// World units within which an enemy notices a player.
+    pub sight_range: f32,
+    /// World units per second.
+    pub speed: f32,
+    /// World units within which chasers push away from each other.
+    pub separation_radius: f32,
+    /// How strongly chasers push away from each other, against their pull towards the target.
+    pub separation_weight: f32,
+    /// Seconds between paths to a target that hasn't changed tile.
+    pub repath_interval: f32,
+}
+
+impl Default for ChaseConfig {
+    fn default() -> Self {
+        ChaseConfig {
+            sight_range: 160.,
+            speed: 60.,
+            separation_radius: 40.,
+            separation_weight: 1.5,
+            repath_interval: 0.5,
+        }
+    }
+}
+
+/// Makes an enemy chase the nearest player in sight.
+#[derive(Clone, Debug, Default)]
+pub struct Chaser {
+    /// The rest of the path to the target, nearest point first.
+    waypoints: Vec<Vector2<f32>>,
+    /// The target's tile when the path was found.
+    goal: Option<TileCoord>,
+    /// Seconds until the path is found again.
+    repath: f32,
+}
+
+impl Chaser {
+    /// Moves the path to the target by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        for waypoint in &mut self.waypoints {
+            *waypoint += offset;
+        }
+    }
+}
+
+impl Component for Chaser {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The push away from the `neighbours` within `radius` of `position`, strongest for the
+/// closest. Neighbours on the very same spot push along `x`, so they still come apart.
+pub fn separation(
+    position: Vector2<f32>,
+    neighbours: impl IntoIterator<Item = Vector2<f32>>,
+    radius: f32,
+) -> Vector2<f32> {
+    let mut push = Vector2::zeros();
+    if radius <= 0. {
+        return push;
+    }
+    for neighbour in neighbours {
+        let offset = position - neighbour;
+        let distance = offset.norm();
+        if distance >= radius {
+            continue;
+        }
+        let away = if distance > f32::EPSILON {
+            offset / distance
+        } else {
+            Vector2::new(1., 0.)
+        };
+        push += away * (1. - distance / radius);
+    }
+    push
+}
+
+/// Steers every living `Chaser` towards the nearest living player in sight along a path over
+/// the `NavGrid`, and away from the other chasers around it. Chasers with no player in sight
+/// stop.
+pub struct ChaseSystem;
+
+impl<'s> System<'s> for ChaseSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Chaser>,
+        WriteStorage<'s, Velocity>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Dead>,
+        ReadStorage<'s, Disabled>,
+        ReadStorage<'s, Asleep>,
+        Read<'s, NavGrid>,
+        Read<'s, SpatialGrid>,
+        Read<'s, ChaseConfig>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut chasers,
+            mut velocities,
+            transforms,
+            players,
+            dead,
+            disabled,
+            asleep,
+            grid,
+            spatial,
+            config,
+            clock,
+        ): Self::SystemData,
+    ) {
+        let delta = clock.delta_seconds();
+        let pack: Vec<_> = (&entities, &chasers, &transforms, !&dead)
+            .join()
+            .map(|(entity, _, transform, _)| (entity, world_position(transform)))
+            .collect();
+
+        for (entity, chaser, velocity, transform, _, _) in (
+            &entities,
+            &mut chasers,
+            &mut velocities,
+            &transforms,
+            !&disabled,
+            !&asleep,
+        )
+            .join()
+        {
+            if dead.contains(entity) {
+                velocity.0 = Vector2::zeros();
+                continue;
+            }
+            let position = world_position(transform);
+            let target = spatial
+                .query_radius(position, config.sight_range, |entity| {
+                    players.contains(entity) && !dead.contains(entity)
+                })
+                .into_iter()
+                .filter_map(|player| transforms.get(player).map(world_position))
+                .min_by(|a, b| {
+                    (a - position)
+                        .norm_squared()
+                        .total_cmp(&(b - position).norm_squared())
+                });
+            let target = match target {
+                Some(target) => target,
+                None => {
+                    chaser.waypoints.clear();
+                    chaser.goal = None;
+                    velocity.0 = Vector2::zeros();
+                    continue;
+                }
+            };
+
+            chaser.repath -= delta;
+            let goal = grid.tile_at(target);
+            if chaser.repath <= 0. || goal != chaser.goal {
+                chaser.repath = config.repath_interval;
+                chaser.goal = goal;
+                let start = grid.tile_at(position);
+                chaser.waypoints = match (start, goal) {
+                    (Some(start), Some(goal)) => grid
+                        .find_path(start, goal)
+                        .map(|path| {
+                            // The first tile is the one the chaser is on.
+                            path.iter()
+                                .skip(1)
+                                .map(|&tile| grid.tile_center(tile))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    _ => Vec::new(),
+                };
+            }
+            while chaser
+                .waypoints
+                .first()
+                .is_some_and(|waypoint| (waypoint - position).norm() <= 0.5)
+            {
+                chaser.waypoints.remove(0);
+            }
+            // On the target's tile, or without a path, head straight for the target.
+            let toward = chaser.waypoints.first().copied().unwrap_or(target) - position;
+            let pull = if toward.norm() > f32::EPSILON {
+                toward.normalize()
+            } else {
+                Vector2::zeros()
+            };
+            let neighbours = pack
+                .iter()
+                .filter(|&&(other, _)| other != entity)
+                .map(|&(_, neighbour)| neighbour);
+            let push = separation(position, neighbours, config.separation_radius);
+            let steer = pull + push * config.separation_weight;
+            velocity.0 = if steer.norm() > f32::EPSILON {
+                steer.normalize() * config.speed
+            } else {
+                Vector2::zeros()
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbours_push_away_harder_up_close() {
+        let near = separation(Vector2::zeros(), vec![Vector2::new(-10., 0.)], 40.);
+        let far = separation(Vector2::zeros(), vec![Vector2::new(-30., 0.)], 40.);
+        assert_eq!(near, Vector2::new(0.75, 0.));
+        assert_eq!(far, Vector2::new(0.25, 0.));
+    }
+
+    #[test]
+    fn neighbours_out_of_range_do_not_push() {
+        let push = separation(
+            Vector2::zeros(),
+            vec![Vector2::new(40., 0.), Vector2::new(0., -50.)],
+            40.,
+        );
+        assert_eq!(push, Vector2::zeros());
+        assert_eq!(
+            separation(Vector2::zeros(), vec![Vector2::new(1., 0.)], 0.),
+            Vector2::zeros()
+        );
+    }
+
+    #[test]
+    fn pushes_from_either_side_cancel_out() {
+        let push = separation(
+            Vector2::zeros(),
+            vec![Vector2::new(-20., 0.), Vector2::new(20., 0.)],
+            40.,
+        );
+        assert_eq!(push, Vector2::zeros());
+    }
+
+    #[test]
+    fn neighbours_on_the_same_spot_still_come_apart() {
+        let push = separation(Vector2::new(5., 5.), vec![Vector2::new(5., 5.)], 40.);
+        assert_eq!(push, Vector2::new(1., 0.));
+    }
+}
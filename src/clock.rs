@@ -0,0 +1,385 @@
+use std::collections::VecDeque;
+
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{Read, System, World, Write},
+    input::{InputHandler, StringBindings},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::attract::AttractMode;
+
+/// Game time, advanced by one fixed step each time the gameplay systems run.
+///
+/// Gameplay reads this instead of `Time`, so it advances in the same fixed steps however fast
+/// frames are rendered, and stands still while `FrameStep` holds it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GameClock {
+    delta: f32,
+    elapsed: f64,
+    ticks: u64,
+}
+
+impl GameClock {
+    /// Seconds of game time covered by the current step.
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta
+    }
+
+    /// Seconds of game time since the game started.
+    pub fn elapsed_seconds(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Number of steps run since the game started.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        self.delta = delta;
+        self.elapsed += f64::from(delta);
+        self.ticks += 1;
+    }
+}
+
+/// Real seconds spent playing the current game, carried over from the save it was loaded from.
+///
+/// `GameState` adds each frame it is the top state for with `count_playtime`, so time spent in the pause menu and the
+/// menus over it, and in attract mode, is left out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Playtime {
+    seconds: f64,
+}
+
+impl Playtime {
+    pub fn new(seconds: f64) -> Self {
+        Playtime { seconds }
+    }
+
+    pub fn seconds(&self) -> f64 {
+        self.seconds
+    }
+
+    pub fn add(&mut self, delta: f32) {
+        self.seconds += f64::from(delta);
+    }
+}
+
+/// Adds the real time of the frame just run to the `Playtime`, unless attract mode is playing.
+pub fn count_playtime(world: &World) {
+    if !world.read_resource::<AttractMode>().active {
+        let delta = world.read_resource::<Time>().delta_real_seconds();
+        world.write_resource::<Playtime>().add(delta);
+    }
+}
+
+/// Moves the `GameClock` on by one fixed step. Runs first among the gameplay systems.
+pub struct GameClockSystem;
+
+impl<'s> System<'s> for GameClockSystem {
+    type SystemData = (Write<'s, GameClock>, Read<'s, Time>);
+
+    fn run(&mut self, (mut clock, time): Self::SystemData) {
+        clock.advance(time.fixed_seconds());
+    }
+}
+
+/// Whether the gameplay steps are paced by smoothed frame times, read from
+/// `frame_smoothing.ron`.
+///
+/// Off by default, so steps run exactly when real time calls for them.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FrameSmoothing {
+    pub enabled: bool,
+    /// Number of frames whose times are averaged.
+    pub window: usize,
+}
+
+impl Default for FrameSmoothing {
+    fn default() -> Self {
+        FrameSmoothing {
+            enabled: false,
+            window: 4,
+        }
+    }
+}
+
+/// Paces the fixed gameplay steps by the average time of the last few frames, so one long
+/// frame is made up for over the next few instead of by a burst of steps at once.
+///
+/// Every frame's time counts in full once it has passed through the window. Only the frames
+/// still in it count in part, and the first frame also stands in for the frames before it, so
+/// the time run as steps stays within about a window of frames of real time, ahead or behind,
+/// and never drifts further.
+#[derive(Clone, Debug)]
+pub struct SmoothedSteps {
+    window: usize,
+    recent: VecDeque<f32>,
+    /// Smoothed time not yet run as a step.
+    accumulated: f32,
+}
+
+impl SmoothedSteps {
+    pub fn new(window: usize) -> Self {
+        SmoothedSteps {
+            window: window.max(1),
+            recent: VecDeque::new(),
+            accumulated: 0.,
+        }
+    }
+
+    /// Paces the steps as `config` asks. With smoothing off, the window is a single frame, so
+    /// each frame's own time is run as steps.
+    pub fn from_config(config: &FrameSmoothing) -> Self {
+        SmoothedSteps::new(if config.enabled { config.window } else { 1 })
+    }
+
+    /// The average time of the last `window` frames, the latest taking `delta` seconds.
+    pub fn smooth(&mut self, delta: f32) -> f32 {
+        // Start from a window of frames like the first, so early frames aren't averaged with
+        // frames that never happened.
+        if self.recent.is_empty() {
+            self.recent.resize(self.window - 1, delta);
+        }
+        self.recent.push_back(delta);
+        while self.recent.len() > self.window {
+            self.recent.pop_front();
+        }
+        self.recent.iter().sum::<f32>() / self.recent.len() as f32
+    }
+
+    /// How many steps of `step` seconds to run for a frame that took `delta` seconds.
+    pub fn steps(&mut self, delta: f32, step: f32) -> u32 {
+        self.accumulated += self.smooth(delta);
+        if step <= 0. {
+            return 0;
+        }
+        let steps = (self.accumulated / step).floor();
+        self.accumulated -= steps * step;
+        steps as u32
+    }
+}
+
+/// Debug mode that holds the gameplay steps until they are requested one at a time.
+///
+/// Rendering and the camera keep running every frame while paused, so the held state stays on
+/// screen.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStep {
+    paused: bool,
+    requested: u32,
+}
+
+impl FrameStep {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle(&mut self) {
+        self.paused = !self.paused;
+        self.requested = 0;
+    }
+
+    /// Asks for one more step to run while paused. Does nothing when not paused.
+    pub fn request_step(&mut self) {
+        if self.paused {
+            self.requested += 1;
+        }
+    }
+
+    /// Whether the next gameplay step may run, consuming a requested step if paused.
+    pub fn take_step(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        if self.requested > 0 {
+            self.requested -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Toggles `FrameStep` with the `toggle_frame_step` action and requests single steps with the
+/// `step_frame` action.
+#[derive(Default)]
+pub struct FrameStepSystem {
+    was_toggle_pressed: bool,
+    was_step_pressed: bool,
+}
+
+impl<'s> System<'s> for FrameStepSystem {
+    type SystemData = (Write<'s, FrameStep>, Read<'s, InputHandler<StringBindings>>);
+
+    fn run(&mut self, (mut frame_step, input): Self::SystemData) {
+        let toggle = input.action_is_down("toggle_frame_step").unwrap_or(false);
+        if toggle && !self.was_toggle_pressed {
+            frame_step.toggle();
+        }
+        self.was_toggle_pressed = toggle;
+
+        let step = input.action_is_down("step_frame").unwrap_or(false);
+        if step && !self.was_step_pressed {
+            frame_step.request_step();
+        }
+        self.was_step_pressed = step;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        ecs::prelude::RunNow, input::VirtualKeyCode, State, StateData, StateMachine, Trans,
+    };
+
+    use super::*;
+    use crate::player::tests::{bind, press_key};
+
+    #[test]
+    fn a_long_frame_is_spread_over_the_window() {
+        let mut steps = SmoothedSteps::new(4);
+        assert_eq!(steps.smooth(0.25), 0.25);
+        assert_eq!(steps.smooth(1.25), 0.5);
+        for _ in 0..3 {
+            assert_eq!(steps.smooth(0.25), 0.5);
+        }
+        assert_eq!(steps.smooth(0.25), 0.25);
+    }
+
+    #[test]
+    fn smoothed_steps_keep_up_with_real_time() {
+        let (window, step) = (4, 0.25);
+        let mut steps = SmoothedSteps::new(window);
+        let (mut real, mut run) = (0., 0.);
+        for frame in 0..1000 {
+            let delta = if frame % 7 == 0 { 0.75 } else { 0.25 };
+            real += delta;
+            run += steps.steps(delta, step) as f32 * step;
+            // A step, and a window of the longest frames, either way.
+            assert!((real - run).abs() <= step + window as f32 * 0.75);
+        }
+    }
+
+    #[test]
+    fn a_frame_step_runs_exactly_one_tick() {
+        let mut world = World::new();
+        let mut frame_stepping = FrameStepSystem::default();
+        let mut clock = GameClockSystem;
+        System::setup(&mut frame_stepping, &mut world.res);
+        System::setup(&mut clock, &mut world.res);
+        bind(
+            &mut world,
+            r#"(
+                axes: {},
+                actions: {
+                    "toggle_frame_step": [[Key(F5)]],
+                    "step_frame": [[Key(F6)]],
+                },
+            )"#,
+        );
+        // A frame, running the gameplay step only when `FrameStep` lets it, as `GameState` does.
+        let mut frame = |world: &mut World| {
+            frame_stepping.run_now(&world.res);
+            if world.write_resource::<FrameStep>().take_step() {
+                clock.run_now(&world.res);
+            }
+            world.read_resource::<GameClock>().ticks()
+        };
+
+        assert_eq!(frame(&mut world), 1);
+        press_key(&mut world, VirtualKeyCode::F5, true);
+        assert_eq!(frame(&mut world), 1);
+        press_key(&mut world, VirtualKeyCode::F5, false);
+        for _ in 0..3 {
+            assert_eq!(frame(&mut world), 1);
+        }
+
+        press_key(&mut world, VirtualKeyCode::F6, true);
+        assert_eq!(frame(&mut world), 2);
+        // Holding the key down is still a single step.
+        for _ in 0..3 {
+            assert_eq!(frame(&mut world), 2);
+        }
+        press_key(&mut world, VirtualKeyCode::F6, false);
+        assert_eq!(frame(&mut world), 2);
+        press_key(&mut world, VirtualKeyCode::F6, true);
+        assert_eq!(frame(&mut world), 3);
+        assert_eq!(
+            world.read_resource::<GameClock>().elapsed_seconds(),
+            3. * f64::from(world.read_resource::<Time>().fixed_seconds())
+        );
+    }
+
+    /// Stands in for `GameState`, counting playtime each frame it updates. Any event pauses it.
+    struct Playing;
+
+    impl State<(), ()> for Playing {
+        fn handle_event(&mut self, _: StateData<'_, ()>, _: ()) -> Trans<(), ()> {
+            Trans::Push(Box::new(Paused))
+        }
+
+        fn update(&mut self, data: StateData<'_, ()>) -> Trans<(), ()> {
+            count_playtime(data.world);
+            Trans::None
+        }
+    }
+
+    /// Stands in for `PauseState`. Any event resumes the game.
+    struct Paused;
+
+    impl State<(), ()> for Paused {
+        fn handle_event(&mut self, _: StateData<'_, ()>, _: ()) -> Trans<(), ()> {
+            Trans::Pop
+        }
+    }
+
+    #[test]
+    fn playtime_leaves_out_time_paused_and_attracting() {
+        let mut world = World::new();
+        world.add_resource(Time::default());
+        world.add_resource(AttractMode::default());
+        world.add_resource(Playtime::new(10.));
+        let mut states = StateMachine::new(Playing);
+        states.start(StateData::new(&mut world, &mut ())).unwrap();
+        fn frames(
+            states: &mut StateMachine<(), ()>,
+            world: &mut World,
+            count: usize,
+            delta: f32,
+        ) -> f64 {
+            for _ in 0..count {
+                world.write_resource::<Time>().set_delta_seconds(delta);
+                states.update(StateData::new(world, &mut ()));
+            }
+            world.read_resource::<Playtime>().seconds()
+        }
+
+        assert_eq!(frames(&mut states, &mut world, 4, 0.25), 11.);
+        states.handle_event(StateData::new(&mut world, &mut ()), ());
+        assert_eq!(frames(&mut states, &mut world, 4, 0.5), 11.);
+        states.handle_event(StateData::new(&mut world, &mut ()), ());
+        assert_eq!(frames(&mut states, &mut world, 2, 0.25), 11.5);
+        world.write_resource::<AttractMode>().active = true;
+        assert_eq!(frames(&mut states, &mut world, 4, 0.25), 11.5);
+    }
+
+    #[test]
+    fn frame_times_pass_through_with_smoothing_off() {
+        let config = FrameSmoothing {
+            enabled: false,
+            window: 4,
+        };
+        let mut steps = SmoothedSteps::from_config(&config);
+        for &delta in &[0.25, 1.25, 0.5, 0.25, 2.] {
+            assert_eq!(steps.smooth(delta), delta);
+        }
+        let mut steps = SmoothedSteps::from_config(&config);
+        assert_eq!(steps.steps(0.75, 0.25), 3);
+        assert_eq!(steps.steps(0.125, 0.25), 0);
+        assert_eq!(steps.steps(0.125, 0.25), 1);
+    }
+}
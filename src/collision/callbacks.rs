@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+
+use amethyst::ecs::{
+    prelude::{Component, Entity, World},
+    storage::MaskedStorage,
+};
+
+use super::Contacts;
+
+/// Which entities one side of a registered handler is called for.
+#[derive(Clone, Copy)]
+pub struct CollisionFilter(fn(&World, Entity) -> bool);
+
+impl CollisionFilter {
+    /// Matches entities with a `C`.
+    pub fn with<C: Component>() -> Self {
+        CollisionFilter(has::<C>)
+    }
+
+    pub fn matches(&self, world: &World, entity: Entity) -> bool {
+        (self.0)(world, entity)
+    }
+}
+
+/// Whether `entity` has a `C`. No storage has been made for a component nothing has used yet,
+/// in which case no entity can have one.
+fn has<C: Component>(world: &World, entity: Entity) -> bool {
+    world.res.has_value::<MaskedStorage<C>>() && world.read_storage::<C>().contains(entity)
+}
+
+/// Two colliders touching, handed to a registered handler with `first` matching its first
+/// filter and `second` its second.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Collision {
+    pub first: Entity,
+    pub second: Entity,
+    /// Whether the two only started touching this step.
+    pub started: bool,
+}
+
+pub type CollisionHandler = Box<dyn FnMut(&mut World, Collision) + Send + Sync>;
+
+struct Registered {
+    first: CollisionFilter,
+    second: CollisionFilter,
+    handler: CollisionHandler,
+}
+
+impl Registered {
+    /// The pair `(a, b)` in the order matching the filters, if it matches them either way
+    /// round. A pair matching both ways is taken in the order it was found.
+    fn matching(&self, world: &World, (a, b): (Entity, Entity)) -> Option<(Entity, Entity)> {
+        if self.first.matches(world, a) && self.second.matches(world, b) {
+            Some((a, b))
+        } else if self.first.matches(world, b) && self.second.matches(world, a) {
+            Some((b, a))
+        } else {
+            None
+        }
+    }
+}
+
+/// Handlers called with the colliders touching after each gameplay step, so gameplay reacting
+/// to contacts needn't each go through `Contacts` for the pairs it cares about.
+///
+/// Each handler is registered with a filter for either side of the pair and is called for
+/// every pair matching them, with the entities in the order of its filters. Handlers are
+/// called in the order they were registered, each for its pairs in the order `Contacts` lists
+/// them, so the same contacts always come out the same. A handler is given the world and may
+/// change anything in it; an entity deleted by one isn't handed to those after it.
+#[derive(Default)]
+pub struct CollisionCallbacks {
+    registered: Vec<Registered>,
+    /// The pairs touching as of the last step, as `Contacts` listed them.
+    touching: HashSet<(Entity, Entity)>,
+}
+
+impl CollisionCallbacks {
+    /// Calls `handler` for every pair of touching colliders matching `first` and `second`.
+    pub fn register(
+        &mut self,
+        first: CollisionFilter,
+        second: CollisionFilter,
+        handler: impl FnMut(&mut World, Collision) + Send + Sync + 'static,
+    ) {
+        self.registered.push(Registered {
+            first,
+            second,
+            handler: Box::new(handler),
+        });
+    }
+}
+
+/// Calls the registered handlers for this step's `Contacts`, as `CollisionCallbacks`
+/// describes.
+pub fn run_collision_callbacks(world: &mut World) {
+    let contacts = world.read_resource::<Contacts>().0.clone();
+    // The handlers are taken out of the world while they run, so they can be handed it.
+    let mut callbacks = std::mem::take(&mut *world.write_resource::<CollisionCallbacks>());
+    for registered in &mut callbacks.registered {
+        for &contact in &contacts {
+            if !world.is_alive(contact.0) || !world.is_alive(contact.1) {
+                continue;
+            }
+            if let Some((first, second)) = registered.matching(world, contact) {
+                let started = !callbacks.touching.contains(&contact);
+                (registered.handler)(
+                    world,
+                    Collision {
+                        first,
+                        second,
+                        started,
+                    },
+                );
+            }
+        }
+    }
+    callbacks.touching = contacts.into_iter().collect();
+
+    // Handlers registered by the handlers themselves are kept, after those already there.
+    let mut current = world.write_resource::<CollisionCallbacks>();
+    callbacks.registered.append(&mut current.registered);
+    *current = callbacks;
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, NullStorage};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Hero;
+
+    impl Component for Hero {
+        type Storage = NullStorage<Self>;
+    }
+
+    #[derive(Default)]
+    struct Spike;
+
+    impl Component for Spike {
+        type Storage = NullStorage<Self>;
+    }
+
+    #[derive(Default)]
+    struct Coin;
+
+    impl Component for Coin {
+        type Storage = NullStorage<Self>;
+    }
+
+    #[derive(Default)]
+    struct Seen(Vec<Collision>);
+
+    #[test]
+    fn a_handler_fires_only_for_the_pairs_its_filters_match() {
+        let mut world = World::new();
+        world.register::<Hero>();
+        world.register::<Spike>();
+        world.register::<Coin>();
+        world.add_resource(Seen::default());
+        let hero = world.create_entity().with(Hero).build();
+        let spike = world.create_entity().with(Spike).build();
+        let coin = world.create_entity().with(Coin).build();
+        let mut callbacks = CollisionCallbacks::default();
+        callbacks.register(
+            CollisionFilter::with::<Hero>(),
+            CollisionFilter::with::<Spike>(),
+            |world, collision| world.write_resource::<Seen>().0.push(collision),
+        );
+        world.add_resource(callbacks);
+        // Listed the other way round from the filters, next to pairs they don't match.
+        world.add_resource(Contacts(vec![(coin, hero), (spike, hero), (spike, coin)]));
+
+        run_collision_callbacks(&mut world);
+        run_collision_callbacks(&mut world);
+        let seen = |started| Collision {
+            first: hero,
+            second: spike,
+            started,
+        };
+        assert_eq!(world.read_resource::<Seen>().0, [seen(true), seen(false)]);
+    }
+}
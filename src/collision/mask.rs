@@ -0,0 +1,161 @@
+use amethyst::{
+    core::math::Vector2,
+    ecs::prelude::{Component, NullStorage},
+};
+
+use crate::map_export::TileAtlas;
+
+/// Alpha values above this count as solid when building masks.
+const ALPHA_THRESHOLD: u8 = 127;
+
+/// Which pixels of a sprite are solid, row by row from its top-left corner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CollisionMask {
+    width: u32,
+    height: u32,
+    solid: Vec<bool>,
+}
+
+impl CollisionMask {
+    /// A mask of the `width` by `height` pixels of `image` starting at `(x, y)`.
+    pub fn from_alpha(image: &image::RgbaImage, x: u32, y: u32, width: u32, height: u32) -> Self {
+        let mut solid = Vec::with_capacity((width * height) as usize);
+        for row in y..y + height {
+            for column in x..x + width {
+                solid.push(image.get_pixel(column, row).data[3] > ALPHA_THRESHOLD);
+            }
+        }
+        CollisionMask {
+            width,
+            height,
+            solid,
+        }
+    }
+
+    /// Whether the pixel at `(x, y)` is solid. Pixels outside the mask never are.
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return false;
+        }
+        self.solid[y as usize * self.width as usize + x as usize]
+    }
+
+    /// The world-space pixel coordinates of the mask's top-left corner when the sprite is drawn
+    /// centred on `center`, one world unit per pixel.
+    fn top_left(&self, center: Vector2<f32>) -> (i32, i32) {
+        (
+            (center.x - self.width as f32 / 2.).round() as i32,
+            (center.y + self.height as f32 / 2.).round() as i32,
+        )
+    }
+}
+
+/// Whether any solid pixel of `a`, drawn centred on `a_center`, lies on a solid pixel of `b`,
+/// drawn centred on `b_center`.
+///
+/// Sprites are assumed to be drawn unscaled and unflipped, with positions rounded to whole
+/// pixels.
+pub fn masks_overlap(
+    a: &CollisionMask,
+    a_center: Vector2<f32>,
+    b: &CollisionMask,
+    b_center: Vector2<f32>,
+) -> bool {
+    let (a_left, a_top) = a.top_left(a_center);
+    let (b_left, b_top) = b.top_left(b_center);
+    // World x grows to the right like pixel columns, but world y grows up while rows grow down.
+    let left = a_left.max(b_left);
+    let right = (a_left + a.width as i32).min(b_left + b.width as i32);
+    let top = a_top.min(b_top);
+    let bottom = (a_top - a.height as i32).max(b_top - b.height as i32);
+
+    (bottom..top).any(|y| {
+        (left..right)
+            .any(|x| a.is_solid(x - a_left, a_top - 1 - y) && b.is_solid(x - b_left, b_top - 1 - y))
+    })
+}
+
+/// The collision mask of every sprite in the sprite sheet, by sprite number.
+#[derive(Clone, Debug, Default)]
+pub struct SpriteMasks(pub Vec<CollisionMask>);
+
+impl SpriteMasks {
+    /// Builds masks from the alpha channel of the sprites in `atlas`.
+    pub fn from_atlas(atlas: &TileAtlas) -> Self {
+        SpriteMasks(
+            atlas
+                .sprites()
+                .iter()
+                .map(|sprite| {
+                    CollisionMask::from_alpha(
+                        atlas.image(),
+                        sprite.x,
+                        sprite.y,
+                        sprite.width,
+                        sprite.height,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, sprite_number: usize) -> Option<&CollisionMask> {
+        self.0.get(sprite_number)
+    }
+}
+
+/// Opts a collider into pixel-perfect contact tests against other `PixelPerfect` colliders,
+/// using the mask of the sprite it is currently showing.
+///
+/// Mask tests are expensive, so they only run for pairs where both entities carry this.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PixelPerfect;
+
+impl Component for PixelPerfect {
+    type Storage = NullStorage<Self>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mask drawn with `#` for opaque pixels, top row first.
+    fn mask(rows: &[&str]) -> CollisionMask {
+        let (width, height) = (rows[0].len() as u32, rows.len() as u32);
+        let image = image::RgbaImage::from_fn(width, height, |x, y| {
+            let opaque = rows[y as usize].as_bytes()[x as usize] == b'#';
+            image::Rgba([255, 255, 255, if opaque { 255 } else { 0 }])
+        });
+        CollisionMask::from_alpha(&image, 0, 0, width, height)
+    }
+
+    #[test]
+    fn overlapping_boxes_only_touch_where_opaque_pixels_meet() {
+        let left = mask(&["##..", "##..", "##..", "##.."]);
+        let right = mask(&["..##", "..##", "..##", "..##"]);
+        let at = |x, y| Vector2::new(x, y);
+        // The boxes share two columns, both of them clear on one side or the other.
+        assert!(!masks_overlap(&left, at(0., 0.), &right, at(2., 0.)));
+        assert!(masks_overlap(&left, at(0., 0.), &right, at(-1., 0.)));
+        assert!(!masks_overlap(&left, at(0., 0.), &right, at(-4., 0.)));
+    }
+
+    #[test]
+    fn rows_run_down_the_screen() {
+        let top = mask(&["####", "####", "....", "...."]);
+        let bottom = mask(&["....", "....", "####", "####"]);
+        // The bottom half of the higher sprite lands on the top half of the lower one.
+        assert!(masks_overlap(
+            &top,
+            Vector2::new(0., 0.),
+            &bottom,
+            Vector2::new(0., 2.)
+        ));
+        assert!(!masks_overlap(
+            &top,
+            Vector2::new(0., 0.),
+            &bottom,
+            Vector2::new(0., -2.)
+        ));
+    }
+}
@@ -0,0 +1,320 @@
+//! Box colliders, the walls of the map's solid tiles, the sweep that keeps movers out of walls
+//! and contact detection between colliders, with handlers registered for the contacts that
+//! matter to them.
+//!
+//! Movers already inside obstacles, when spawned or pushed there, are pushed back out one
+//! obstacle at a time in a fixed order set by `OverlapConfig`, so the same overlaps always
+//! resolve the same way and replays play back exactly.
+
+mod callbacks;
+mod mask;
+mod tiles;
+
+pub use self::{
+    callbacks::{run_collision_callbacks, CollisionCallbacks, CollisionFilter},
+    mask::{masks_overlap, PixelPerfect, SpriteMasks},
+    tiles::{TileColliders, TileCollisionConfig},
+};
+
+use std::cmp::Ordering;
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System, Write,
+    },
+    renderer::SpriteRender,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::movement::world_position;
+
+/// Axis-aligned box collider centred on the entity's translation.
+///
+/// Entities with a `Collider` but no `Velocity` are treated as static walls by the
+/// `MovementSystem`.
+#[derive(Clone, Copy, Debug)]
+pub struct Collider {
+    pub half_extents: Vector2<f32>,
+}
+
+impl Collider {
+    pub fn new(width: f32, height: f32) -> Self {
+        Collider {
+            half_extents: Vector2::new(width / 2., height / 2.),
+        }
+    }
+}
+
+impl Component for Collider {
+    type Storage = DenseVecStorage<Self>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector2<f32>,
+    pub max: Vector2<f32>,
+}
+
+impl Aabb {
+    pub fn from_center(center: Vector2<f32>, half_extents: Vector2<f32>) -> Self {
+        Aabb {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x
+            && other.min.x < self.max.x
+            && self.min.y < other.max.y
+            && other.min.y < self.max.y
+    }
+
+    pub fn translated(&self, offset: Vector2<f32>) -> Self {
+        Aabb {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+}
+
+/// Moves `aabb` by `motion` one axis at a time, stopping at the first obstacle hit on each
+/// axis. Returns the displacement that can actually be applied.
+///
+/// Because the whole swept span is checked, fast movers cannot skip over thin walls.
+pub fn sweep(aabb: &Aabb, motion: Vector2<f32>, obstacles: &[Aabb]) -> Vector2<f32> {
+    let dx = sweep_axis(aabb, motion.x, 0, obstacles);
+    let moved = aabb.translated(Vector2::new(dx, 0.));
+    let dy = sweep_axis(&moved, motion.y, 1, obstacles);
+    Vector2::new(dx, dy)
+}
+
+fn sweep_axis(aabb: &Aabb, delta: f32, axis: usize, obstacles: &[Aabb]) -> f32 {
+    let other = 1 - axis;
+    let mut allowed = delta;
+    for obstacle in obstacles {
+        if aabb.max[other] <= obstacle.min[other] || aabb.min[other] >= obstacle.max[other] {
+            continue;
+        }
+        if delta > 0. && obstacle.min[axis] >= aabb.max[axis] {
+            allowed = allowed.min(obstacle.min[axis] - aabb.max[axis]);
+        } else if delta < 0. && obstacle.max[axis] <= aabb.min[axis] {
+            allowed = allowed.max(obstacle.max[axis] - aabb.min[axis]);
+        }
+    }
+    allowed
+}
+
+/// Which overlapping obstacle is pushed out of first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum OverlapOrder {
+    /// The one the mover is furthest inside.
+    DeepestFirst,
+    /// The one the mover is least inside.
+    ShallowestFirst,
+}
+
+/// How movers inside obstacles are pushed out, read from `overlap.ron`.
+///
+/// Obstacles as deep as each other are taken in the order they are listed: static colliders
+/// by entity id, then walls by their place in the map.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OverlapConfig {
+    pub enabled: bool,
+    pub order: OverlapOrder,
+    /// The most obstacles a mover is pushed out of in one step.
+    pub max_pushes: u32,
+}
+
+impl Default for OverlapConfig {
+    fn default() -> Self {
+        OverlapConfig {
+            enabled: true,
+            order: OverlapOrder::DeepestFirst,
+            max_pushes: 4,
+        }
+    }
+}
+
+/// The shortest move taking `aabb` out of `obstacle`, or `None` if they don't overlap. The
+/// move is along whichever axis is shallower, `x` when both are as deep, and away from the
+/// obstacle's centre, or towards positive when the centres line up.
+pub fn penetration(aabb: &Aabb, obstacle: &Aabb) -> Option<Vector2<f32>> {
+    if !aabb.overlaps(obstacle) {
+        return None;
+    }
+    let push = |axis: usize| {
+        let towards_positive =
+            aabb.min[axis] + aabb.max[axis] >= obstacle.min[axis] + obstacle.max[axis];
+        if towards_positive {
+            obstacle.max[axis] - aabb.min[axis]
+        } else {
+            obstacle.min[axis] - aabb.max[axis]
+        }
+    };
+    let (x, y) = (push(0), push(1));
+    Some(if x.abs() <= y.abs() {
+        Vector2::new(x, 0.)
+    } else {
+        Vector2::new(0., y)
+    })
+}
+
+/// The move taking `aabb` out of the `obstacles` it is inside, pushing it out of one at a time
+/// in `config`'s order and looking again after each push, as a push may move it into another.
+pub fn push_out(aabb: &Aabb, obstacles: &[Aabb], config: &OverlapConfig) -> Vector2<f32> {
+    let mut total = Vector2::zeros();
+    if !config.enabled {
+        return total;
+    }
+    for _ in 0..config.max_pushes {
+        let moved = aabb.translated(total);
+        let first = obstacles
+            .iter()
+            .enumerate()
+            .filter_map(|(index, obstacle)| penetration(&moved, obstacle).map(|push| (index, push)))
+            .min_by(|(a_index, a), (b_index, b)| {
+                let by_depth = a.norm().partial_cmp(&b.norm()).unwrap_or(Ordering::Equal);
+                let by_depth = match config.order {
+                    OverlapOrder::DeepestFirst => by_depth.reverse(),
+                    OverlapOrder::ShallowestFirst => by_depth,
+                };
+                by_depth.then(a_index.cmp(b_index))
+            });
+        match first {
+            Some((_, push)) => total += push,
+            None => break,
+        }
+    }
+    total
+}
+
+/// Pairs of colliders touching each other this frame, each pair listed once.
+#[derive(Clone, Debug, Default)]
+pub struct Contacts(pub Vec<(Entity, Entity)>);
+
+/// Finds every pair of overlapping colliders and records it in `Contacts`.
+///
+/// Boxes are the only test unless both colliders are `PixelPerfect`, in which case boxes that
+/// overlap are then checked against the `SpriteMasks` of the sprites both are showing. A
+/// `PixelPerfect` entity without a sprite or a mask falls back to its box.
+pub struct ContactSystem;
+
+impl<'s> System<'s> for ContactSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Collider>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, PixelPerfect>,
+        ReadStorage<'s, SpriteRender>,
+        Read<'s, SpriteMasks>,
+        Write<'s, Contacts>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, colliders, transforms, pixel_perfect, sprites, masks, mut contacts): Self::SystemData,
+    ) {
+        let bodies: Vec<_> = (&entities, &colliders, &transforms)
+            .join()
+            .map(|(entity, collider, transform)| {
+                let center = world_position(transform);
+                let mask = pixel_perfect
+                    .get(entity)
+                    .and(sprites.get(entity))
+                    .and_then(|sprite| masks.get(sprite.sprite_number));
+                (
+                    entity,
+                    Aabb::from_center(center, collider.half_extents),
+                    center,
+                    mask,
+                )
+            })
+            .collect();
+
+        contacts.0.clear();
+        for (i, (a, a_box, a_center, a_mask)) in bodies.iter().enumerate() {
+            for (b, b_box, b_center, b_mask) in &bodies[i + 1..] {
+                if !a_box.overlaps(b_box) {
+                    continue;
+                }
+                let touching = match (a_mask, b_mask) {
+                    (Some(a_mask), Some(b_mask)) => {
+                        masks_overlap(a_mask, *a_center, b_mask, *b_center)
+                    }
+                    _ => true,
+                };
+                if touching {
+                    contacts.0.push((*a, *b));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aabb(min: (f32, f32), max: (f32, f32)) -> Aabb {
+        Aabb {
+            min: Vector2::new(min.0, min.1),
+            max: Vector2::new(max.0, max.1),
+        }
+    }
+
+    #[test]
+    fn a_mover_wedged_in_a_corner_comes_out_the_same_way_every_time() {
+        // Two units into the wall on its left and as far into the floor below it.
+        let mover = aabb((-2., -2.), (6., 6.));
+        let wall = aabb((-10., -10.), (0., 10.));
+        let floor = aabb((-10., -10.), (10., 0.));
+        for &order in &[OverlapOrder::DeepestFirst, OverlapOrder::ShallowestFirst] {
+            let config = OverlapConfig {
+                order,
+                ..OverlapConfig::default()
+            };
+            let pushes = [
+                push_out(&mover, &[wall, floor], &config),
+                push_out(&mover, &[floor, wall], &config),
+            ];
+            assert_eq!(pushes, [Vector2::new(2., 2.); 2], "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn the_deepest_or_shallowest_obstacle_is_pushed_out_of_first() {
+        let mover = aabb((0., 0.), (4., 4.));
+        let shallow = aabb((3., -10.), (13., 10.));
+        let deep = aabb((2., -10.), (10., 10.));
+        let config = |order| OverlapConfig {
+            order,
+            max_pushes: 1,
+            ..OverlapConfig::default()
+        };
+        assert_eq!(
+            push_out(
+                &mover,
+                &[shallow, deep],
+                &config(OverlapOrder::DeepestFirst)
+            ),
+            Vector2::new(-2., 0.)
+        );
+        assert_eq!(
+            push_out(
+                &mover,
+                &[shallow, deep],
+                &config(OverlapOrder::ShallowestFirst)
+            ),
+            Vector2::new(-1., 0.)
+        );
+        let disabled = OverlapConfig {
+            enabled: false,
+            ..OverlapConfig::default()
+        };
+        assert_eq!(push_out(&mover, &[deep], &disabled), Vector2::zeros());
+    }
+}
@@ -0,0 +1,194 @@
+use amethyst::core::math::Vector2;
+use serde::{Deserialize, Serialize};
+
+use super::Aabb;
+use crate::{
+    navigation::TileCoord,
+    tile_map::{MapProjection, TileMap},
+};
+
+/// How the map's solid tiles become walls, read from `tile_collision.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TileCollisionConfig {
+    pub enabled: bool,
+    /// Whether touching solid tiles are merged into as few rectangles as can cover them, for
+    /// fewer boxes to check. Movers are stopped at the same places either way, but one already
+    /// inside the walls is pushed out of a merged rectangle by its shallowest side, where tile
+    /// by tile it may be pushed into the next tile along and out of that.
+    pub merge: bool,
+}
+
+impl Default for TileCollisionConfig {
+    fn default() -> Self {
+        TileCollisionConfig {
+            enabled: true,
+            merge: true,
+        }
+    }
+}
+
+/// A rectangle of tiles, from its bottom-left tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRect {
+    pub column: usize,
+    pub row: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Covers the tiles of a `width` by `height` grid that are `solid` with rectangles, each solid
+/// tile in exactly one. Merged greedily, each rectangle grows as far right as it can from the
+/// lowest, leftmost tile not yet covered, then as far up as whole rows of that width allow.
+/// Unmerged, every tile is a rectangle of its own.
+pub fn merge_tiles(
+    width: usize,
+    height: usize,
+    solid: impl Fn(TileCoord) -> bool,
+    merge: bool,
+) -> Vec<TileRect> {
+    let mut covered = vec![false; width * height];
+    let open = |covered: &[bool], (column, row): TileCoord| {
+        !covered[row * width + column] && solid((column, row))
+    };
+    let mut rects = Vec::new();
+    for row in 0..height {
+        for column in 0..width {
+            if !open(&covered, (column, row)) {
+                continue;
+            }
+            let (mut rect_width, mut rect_height) = (1, 1);
+            if merge {
+                while column + rect_width < width && open(&covered, (column + rect_width, row)) {
+                    rect_width += 1;
+                }
+                while row + rect_height < height
+                    && (column..column + rect_width).all(|x| open(&covered, (x, row + rect_height)))
+                {
+                    rect_height += 1;
+                }
+            }
+            for y in row..row + rect_height {
+                for x in column..column + rect_width {
+                    covered[y * width + x] = true;
+                }
+            }
+            rects.push(TileRect {
+                column,
+                row,
+                width: rect_width,
+                height: rect_height,
+            });
+        }
+    }
+    rects
+}
+
+/// The walls of the map's solid tiles, which the `MovementSystem` stops movers at along with
+/// the static colliders.
+///
+/// Only top-down maps get walls, as the diamonds of an isometric map aren't boxes.
+#[derive(Clone, Debug, Default)]
+pub struct TileColliders(pub Vec<Aabb>);
+
+impl TileColliders {
+    pub fn from_map(map: &TileMap, config: &TileCollisionConfig) -> Self {
+        if !config.enabled || map.projection != MapProjection::TopDown {
+            return TileColliders::default();
+        }
+        let solid = |coord| {
+            map.tile(coord)
+                .is_some_and(|tile| map.solid.contains(&tile))
+        };
+        let rects = merge_tiles(map.width, map.height, solid, config.merge);
+        TileColliders(
+            rects
+                .iter()
+                .map(|rect| {
+                    let min = Vector2::new(rect.column as f32, rect.row as f32) * map.tile_size
+                        + map.origin();
+                    let size = Vector2::new(rect.width as f32, rect.height as f32) * map.tile_size;
+                    Aabb {
+                        min,
+                        max: min + size,
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::sweep;
+
+    /// The walls of a map of 1-unit tiles, rows from the bottom, with `#` solid.
+    fn walls(rows: &[&str], merge: bool) -> Vec<Aabb> {
+        let solid = |(column, row): TileCoord| rows[row].as_bytes()[column] == b'#';
+        merge_tiles(rows[0].len(), rows.len(), solid, merge)
+            .iter()
+            .map(|rect| {
+                let min = Vector2::new(rect.column as f32, rect.row as f32);
+                Aabb {
+                    min,
+                    max: min + Vector2::new(rect.width as f32, rect.height as f32),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_solid_block_is_one_rectangle() {
+        let rects = merge_tiles(10, 10, |_| true, true);
+        assert_eq!(
+            rects,
+            vec![TileRect {
+                column: 0,
+                row: 0,
+                width: 10,
+                height: 10,
+            }]
+        );
+        assert_eq!(merge_tiles(10, 10, |_| true, false).len(), 100);
+    }
+
+    #[test]
+    fn every_solid_tile_is_covered_once() {
+        let rows = ["##..", "###.", ".#.#"];
+        let solid = |(column, row): TileCoord| rows[row].as_bytes()[column] == b'#';
+        let rects = merge_tiles(4, 3, solid, true);
+        for row in 0..3 {
+            for column in 0..4 {
+                let covering = rects
+                    .iter()
+                    .filter(|rect| {
+                        (rect.column..rect.column + rect.width).contains(&column)
+                            && (rect.row..rect.row + rect.height).contains(&row)
+                    })
+                    .count();
+                assert_eq!(covering, usize::from(solid((column, row))));
+            }
+        }
+    }
+
+    #[test]
+    fn merged_walls_stop_movers_where_tiles_do() {
+        let rows = ["#...#", "#...#", "#####"];
+        let mover = Aabb::from_center(Vector2::new(2.5, 1.5), Vector2::new(0.4, 0.4));
+        for motion in &[
+            Vector2::new(3., 0.),
+            Vector2::new(-3., -3.),
+            Vector2::new(0.5, -2.),
+        ] {
+            let merged = sweep(&mover, *motion, &walls(&rows, true));
+            let tiled = sweep(&mover, *motion, &walls(&rows, false));
+            assert!(
+                (merged - tiled).norm() < 1e-5,
+                "{:?} vs {:?}",
+                merged,
+                tiled
+            );
+        }
+    }
+}
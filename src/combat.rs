@@ -0,0 +1,376 @@
+use amethyst::{
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, Resources, System,
+        SystemData, Write, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    shrev::{EventChannel, ReaderId},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock,
+    difficulty::Difficulty,
+    god_mode::GodMode,
+    player::Player,
+    rumble::{RumbleEvent, RumbleKind},
+};
+
+/// Hit points of an entity that can take damage.
+#[derive(Clone, Copy, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Health { current: max, max }
+    }
+}
+
+impl Component for Health {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Makes an entity ignore incoming damage until `remaining` runs out.
+#[derive(Clone, Copy, Debug)]
+pub struct Invulnerable {
+    pub remaining: f32,
+}
+
+impl Component for Invulnerable {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Grants `duration` seconds of invulnerability to `entity`, keeping any longer window it
+/// already has.
+pub fn grant_invulnerability(
+    invulnerables: &mut WriteStorage<'_, Invulnerable>,
+    entity: Entity,
+    duration: f32,
+) {
+    if let Some(existing) = invulnerables.get_mut(entity) {
+        existing.remaining = existing.remaining.max(duration);
+    } else if duration > 0. {
+        invulnerables
+            .insert(
+                entity,
+                Invulnerable {
+                    remaining: duration,
+                },
+            )
+            .expect("Failed to insert Invulnerable for a live entity");
+    }
+}
+
+/// Counts down `Invulnerable` windows and removes them once they expire.
+pub struct InvulnerabilitySystem;
+
+impl<'s> System<'s> for InvulnerabilitySystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Invulnerable>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(&mut self, (entities, mut invulnerables, clock): Self::SystemData) {
+        let delta = clock.delta_seconds();
+        let mut expired = Vec::new();
+        for (entity, invulnerable) in (&entities, &mut invulnerables).join() {
+            invulnerable.remaining -= delta;
+            if invulnerable.remaining <= 0. {
+                expired.push(entity);
+            }
+        }
+        for entity in expired {
+            invulnerables.remove(entity);
+        }
+    }
+}
+
+/// What dealt a hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DamageKind {
+    AutoAttack,
+    /// A swing of a `Hitbox`.
+    Hitbox,
+    /// Damage dealt through `debug_damage`.
+    Debug,
+}
+
+/// What a hit is made of, which `Resistances` tell apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DamageType {
+    #[default]
+    Physical,
+    Fire,
+    Poison,
+}
+
+/// How much of each `DamageType` an entity shrugs off, as a fraction of the hit. Zero takes
+/// hits in full and one is immune, while below zero is a weakness, so `-0.5` takes half again
+/// as much. Entities without one take every hit in full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Resistances {
+    pub physical: f32,
+    pub fire: f32,
+    pub poison: f32,
+}
+
+impl Resistances {
+    /// What a hit of `damage_type` is multiplied by, never below zero.
+    pub fn multiplier(&self, damage_type: DamageType) -> f32 {
+        let resisted = match damage_type {
+            DamageType::Physical => self.physical,
+            DamageType::Fire => self.fire,
+            DamageType::Poison => self.poison,
+        };
+        (1. - resisted).max(0.)
+    }
+}
+
+impl Component for Resistances {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Asks for `amount` hit points to be taken from `target`, on behalf of `source` if it has one.
+#[derive(Clone, Copy, Debug)]
+pub struct DamageEvent {
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub amount: f32,
+    pub kind: DamageKind,
+    pub damage_type: DamageType,
+}
+
+/// Hit points `target` actually lost to a `DamageEvent`.
+#[derive(Clone, Copy, Debug)]
+pub struct DamageTaken {
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub amount: f32,
+    pub kind: DamageKind,
+    pub damage_type: DamageType,
+}
+
+/// Applies `DamageEvent`s to `Health`, ignoring those against invulnerable entities, and
+/// reports what was taken as `DamageTaken`. Damage is scaled by the target's `Resistances` to
+/// its type. Damage to players is then scaled by the `Difficulty`, and rumbles their gamepads,
+/// unless `GodMode` makes them invulnerable.
+#[derive(Default)]
+pub struct DamageSystem {
+    reader: Option<ReaderId<DamageEvent>>,
+}
+
+impl<'s> System<'s> for DamageSystem {
+    type SystemData = (
+        Read<'s, EventChannel<DamageEvent>>,
+        Write<'s, EventChannel<DamageTaken>>,
+        WriteStorage<'s, Health>,
+        ReadStorage<'s, Invulnerable>,
+        ReadStorage<'s, Resistances>,
+        ReadStorage<'s, Player>,
+        Read<'s, Difficulty>,
+        Read<'s, GodMode>,
+        Write<'s, EventChannel<RumbleEvent>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            events,
+            mut taken,
+            mut healths,
+            invulnerables,
+            resistances,
+            players,
+            difficulty,
+            god_mode,
+            mut rumbles,
+        ): Self::SystemData,
+    ) {
+        let to_players = difficulty.multipliers().enemy_damage;
+        let reader = self.reader.as_mut().expect("DamageSystem is set up");
+        for event in events.read(reader) {
+            if invulnerables.contains(event.target)
+                || god_mode.invulnerable && players.contains(event.target)
+            {
+                continue;
+            }
+            if let Some(health) = healths.get_mut(event.target) {
+                let before = health.current;
+                let player = players.get(event.target);
+                let amount = resistances
+                    .get(event.target)
+                    .map_or(event.amount, |resistances| {
+                        event.amount * resistances.multiplier(event.damage_type)
+                    });
+                let amount = match player {
+                    Some(_) => amount * to_players,
+                    None => amount,
+                };
+                health.current = (health.current - amount).max(0.);
+                if let Some(player) = player.filter(|_| health.current < before) {
+                    rumbles.single_write(RumbleEvent {
+                        kind: RumbleKind::Hit,
+                        player: Some(player.index),
+                    });
+                }
+                taken.single_write(DamageTaken {
+                    source: event.source,
+                    target: event.target,
+                    amount: before - health.current,
+                    kind: event.kind,
+                    damage_type: event.damage_type,
+                });
+            }
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<DamageEvent>>()
+                .register_reader(),
+        );
+    }
+}
+
+/// Deals `amount` damage to every player each step the `debug_damage` action is held, for
+/// trying out damage feedback.
+pub struct DebugDamageSystem {
+    pub amount: f32,
+}
+
+impl<'s> System<'s> for DebugDamageSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Player>,
+        Write<'s, EventChannel<DamageEvent>>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(&mut self, (entities, players, mut events, input): Self::SystemData) {
+        if !input.action_is_down("debug_damage").unwrap_or(false) {
+            return;
+        }
+        for (target, _) in (&entities, &players).join() {
+            events.single_write(DamageEvent {
+                source: None,
+                target,
+                amount: self.amount,
+                kind: DamageKind::Debug,
+                damage_type: DamageType::Physical,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+
+    fn world() -> (World, DamageSystem) {
+        let mut world = World::new();
+        let mut system = DamageSystem::default();
+        System::setup(&mut system, &mut world.res);
+        (world, system)
+    }
+
+    /// Hits `target` for `amount` of `damage_type` and returns the health it has left.
+    fn hit(
+        world: &World,
+        system: &mut DamageSystem,
+        target: Entity,
+        amount: f32,
+        damage_type: DamageType,
+    ) -> f32 {
+        world
+            .write_resource::<EventChannel<DamageEvent>>()
+            .single_write(DamageEvent {
+                source: None,
+                target,
+                amount,
+                kind: DamageKind::Debug,
+                damage_type,
+            });
+        system.run_now(&world.res);
+        world.read_storage::<Health>().get(target).unwrap().current
+    }
+
+    fn resisting(world: &mut World, resistances: Resistances) -> Entity {
+        world
+            .create_entity()
+            .with(Health::new(10.))
+            .with(resistances)
+            .build()
+    }
+
+    #[test]
+    fn weaknesses_take_more_of_their_type_and_immunities_none() {
+        let (mut world, mut system) = world();
+        let fire = |fire| Resistances {
+            fire,
+            ..Resistances::default()
+        };
+        let weak = resisting(&mut world, fire(-0.5));
+        let immune = resisting(&mut world, fire(1.));
+        assert_eq!(hit(&world, &mut system, weak, 2., DamageType::Fire), 7.);
+        assert_eq!(hit(&world, &mut system, weak, 2., DamageType::Physical), 5.);
+        assert_eq!(hit(&world, &mut system, immune, 2., DamageType::Fire), 10.);
+        assert_eq!(hit(&world, &mut system, immune, 2., DamageType::Poison), 8.);
+    }
+
+    #[test]
+    fn invulnerability_holds_off_hits_until_it_wears_off() {
+        let (mut world, mut system) = world();
+        let mut expiry = InvulnerabilitySystem;
+        System::setup(&mut expiry, &mut world.res);
+        let target = world.create_entity().with(Health::new(10.)).build();
+        grant_invulnerability(&mut world.write_storage(), target, 0.5);
+        assert_eq!(
+            hit(&world, &mut system, target, 2., DamageType::Physical),
+            10.
+        );
+
+        world.write_resource::<GameClock>().advance(0.5);
+        expiry.run_now(&world.res);
+        assert_eq!(
+            hit(&world, &mut system, target, 2., DamageType::Physical),
+            8.
+        );
+    }
+
+    #[test]
+    fn god_mode_keeps_players_from_harm() {
+        let (mut world, mut system) = world();
+        world.write_resource::<GodMode>().invulnerable = true;
+        let player = world
+            .create_entity()
+            .with(Health::new(10.))
+            .with(Player {
+                index: 0,
+                speed: 1.,
+            })
+            .build();
+        let enemy = world.create_entity().with(Health::new(10.)).build();
+        assert_eq!(
+            hit(&world, &mut system, player, 2., DamageType::Physical),
+            10.
+        );
+        assert_eq!(
+            hit(&world, &mut system, enemy, 2., DamageType::Physical),
+            8.
+        );
+
+        world.write_resource::<GodMode>().invulnerable = false;
+        assert_eq!(
+            hit(&world, &mut system, player, 2., DamageType::Physical),
+            8.
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! Floating numbers showing the damage each entity takes.
+//!
+//! Rapid hits on the same entity, such as the ticks of a burn, are added into one growing
+//! number for as long as they keep landing within `CombatTextConfig::window`, so fast damage
+//! doesn't bury the screen in text.
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadExpect, ReadStorage,
+        Resources, System, SystemData, WriteStorage,
+    },
+    shrev::{EventChannel, ReaderId},
+    ui::{Anchor, FontAsset, FontHandle, UiText, UiTransform},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::CameraMatrices, combat::DamageTaken, movement::world_position, ui_theme::UiTheme,
+};
+
+/// Size and colour of the numbers, and size of the box holding each, at a text scale of 1.
+const FONT_SIZE: f32 = 20.;
+const COLOR: [f32; 4] = [1., 0.3, 0.2, 1.];
+const WIDTH: f32 = 120.;
+const HEIGHT: f32 = 24.;
+
+/// What a hit has to be close to for it to join the number already shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AggregateReset {
+    /// Each hit reopens the window, so a steady stream of hits keeps adding to one number.
+    #[default]
+    FromLastHit,
+    /// The window runs from the first hit, so a long stream is shown as several numbers.
+    FromFirstHit,
+}
+
+/// How combat text is aggregated and shown, read from `combat_text.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CombatTextConfig {
+    /// Seconds within which hits on the same entity are added together.
+    pub window: f32,
+    pub reset: AggregateReset,
+    /// Seconds a number stays up after its last hit.
+    pub lifetime: f32,
+    /// World units per second a number floats up after its last hit.
+    pub rise_speed: f32,
+}
+
+impl Default for CombatTextConfig {
+    fn default() -> Self {
+        CombatTextConfig {
+            window: 0.4,
+            reset: AggregateReset::FromLastHit,
+            lifetime: 1.,
+            rise_speed: 32.,
+        }
+    }
+}
+
+/// Damage added up from the hits on one entity.
+#[derive(Clone, Copy, Debug)]
+pub struct DamageAggregate {
+    pub total: f32,
+    pub hits: u32,
+    since_first: f32,
+    since_last: f32,
+}
+
+impl DamageAggregate {
+    pub fn new(amount: f32) -> Self {
+        DamageAggregate {
+            total: amount,
+            hits: 1,
+            since_first: 0.,
+            since_last: 0.,
+        }
+    }
+
+    /// Whether a hit landing now should be added to this aggregate.
+    pub fn accepts(&self, config: &CombatTextConfig) -> bool {
+        let elapsed = match config.reset {
+            AggregateReset::FromLastHit => self.since_last,
+            AggregateReset::FromFirstHit => self.since_first,
+        };
+        elapsed <= config.window
+    }
+
+    pub fn add(&mut self, amount: f32) {
+        self.total += amount;
+        self.hits += 1;
+        self.since_last = 0.;
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        self.since_first += delta;
+        self.since_last += delta;
+    }
+
+    pub fn since_last(&self) -> f32 {
+        self.since_last
+    }
+}
+
+/// A floating damage number for `target`.
+#[derive(Clone, Copy, Debug)]
+pub struct CombatText {
+    pub target: Entity,
+    pub aggregate: DamageAggregate,
+    /// Where the target was when it was last hit.
+    anchor: Vector2<f32>,
+}
+
+impl CombatText {
+    /// Moves where the number is anchored by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        self.anchor += offset;
+    }
+}
+
+impl Component for CombatText {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Shows `DamageTaken` as floating numbers above the damaged entities.
+pub struct CombatTextSystem {
+    pub config: CombatTextConfig,
+    reader: Option<ReaderId<DamageTaken>>,
+    font: Option<FontHandle>,
+}
+
+impl CombatTextSystem {
+    pub fn new(config: CombatTextConfig) -> Self {
+        CombatTextSystem {
+            config,
+            reader: None,
+            font: None,
+        }
+    }
+}
+
+impl<'s> System<'s> for CombatTextSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, EventChannel<DamageTaken>>,
+        WriteStorage<'s, CombatText>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiText>,
+        ReadStorage<'s, Transform>,
+        Read<'s, CameraMatrices>,
+        Read<'s, Time>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<FontAsset>>,
+        Read<'s, UiTheme>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            events,
+            mut combat_texts,
+            mut ui_transforms,
+            mut ui_texts,
+            transforms,
+            camera,
+            time,
+            loader,
+            fonts,
+            theme,
+        ): Self::SystemData,
+    ) {
+        for combat_text in (&mut combat_texts).join() {
+            combat_text.aggregate.advance(time.delta_seconds());
+        }
+
+        let reader = self.reader.as_mut().expect("CombatTextSystem is set up");
+        for event in events.read(reader) {
+            let anchor = match transforms.get(event.target) {
+                Some(transform) => world_position(transform),
+                None => continue,
+            };
+            let config = &self.config;
+            let open = (&mut combat_texts).join().find(|combat_text| {
+                combat_text.target == event.target && combat_text.aggregate.accepts(config)
+            });
+            if let Some(combat_text) = open {
+                combat_text.aggregate.add(event.amount);
+                combat_text.anchor = anchor;
+                continue;
+            }
+
+            let font = self
+                .font
+                .get_or_insert_with(|| theme.font(&loader, &fonts))
+                .clone();
+            entities
+                .build_entity()
+                .with(
+                    CombatText {
+                        target: event.target,
+                        aggregate: DamageAggregate::new(event.amount),
+                        anchor,
+                    },
+                    &mut combat_texts,
+                )
+                .with(
+                    UiTransform::new(
+                        "combat_text".to_string(),
+                        Anchor::BottomLeft,
+                        Anchor::Middle,
+                        0.,
+                        0.,
+                        5.,
+                        WIDTH,
+                        HEIGHT,
+                    ),
+                    &mut ui_transforms,
+                )
+                .with(
+                    UiText::new(font, String::new(), COLOR, FONT_SIZE),
+                    &mut ui_texts,
+                )
+                .build();
+        }
+
+        for (entity, combat_text, ui_transform, ui_text) in
+            (&entities, &combat_texts, &mut ui_transforms, &mut ui_texts).join()
+        {
+            let since_last = combat_text.aggregate.since_last();
+            if since_last >= self.config.lifetime {
+                entities
+                    .delete(entity)
+                    .expect("Combat text entity is alive");
+                continue;
+            }
+
+            let point =
+                combat_text.anchor + Vector2::new(0., 24. + self.config.rise_speed * since_last);
+            let position = camera.world_to_screen(point);
+            ui_transform.local_x = position.x;
+            ui_transform.local_y = position.y;
+            ui_transform.width = WIDTH * theme.text_scale;
+            ui_transform.height = HEIGHT * theme.text_scale;
+            ui_text.text = format!("{:.0}", combat_text.aggregate.total);
+            ui_text.font_size = theme.text_size(FONT_SIZE);
+            ui_text.color = theme.text_color(COLOR);
+            ui_text.color[3] *= 1. - since_last / self.config.lifetime;
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<DamageTaken>>()
+                .register_reader(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The numbers shown for one entity taking a point of damage on each step of a quarter
+    /// second marked in `hits`, added up as `CombatTextSystem` does.
+    fn numbers(reset: AggregateReset, hits: &[bool]) -> Vec<f32> {
+        let config = CombatTextConfig {
+            window: 0.4,
+            reset,
+            ..CombatTextConfig::default()
+        };
+        let mut shown: Vec<DamageAggregate> = Vec::new();
+        for &hit in hits {
+            for aggregate in &mut shown {
+                aggregate.advance(0.25);
+            }
+            if !hit {
+                continue;
+            }
+            match shown
+                .iter_mut()
+                .find(|aggregate| aggregate.accepts(&config))
+            {
+                Some(aggregate) => aggregate.add(1.),
+                None => shown.push(DamageAggregate::new(1.)),
+            }
+        }
+        shown.iter().map(|aggregate| aggregate.total).collect()
+    }
+
+    #[test]
+    fn hits_within_the_window_of_the_last_add_up() {
+        let steady = [true; 6];
+        assert_eq!(numbers(AggregateReset::FromLastHit, &steady), [6.]);
+        // Half a second without a hit closes the window.
+        let gap = [true, true, false, true];
+        assert_eq!(numbers(AggregateReset::FromLastHit, &gap), [2., 1.]);
+    }
+
+    #[test]
+    fn hits_within_the_window_of_the_first_add_up() {
+        let steady = [true; 6];
+        assert_eq!(numbers(AggregateReset::FromFirstHit, &steady), [2., 2., 2.]);
+        let gap = [true, true, false, true];
+        assert_eq!(numbers(AggregateReset::FromFirstHit, &gap), [2., 1.]);
+    }
+}
@@ -0,0 +1,349 @@
+//! Preset control schemes the players can switch between while playing.
+//!
+//! `bindings.ron` holds the bindings every scheme shares, such as the debug toggles, and a set
+//! of named presets for movement and abilities. Gameplay only reads axis and action names, so
+//! swapping the preset changes which keys drive a player without touching any system. The
+//! chosen preset is saved to `control_scheme.ron` and picked again on the next start.
+//!
+//! Controls rebound from the controls menu are kept in `CustomBindings` by preset and saved to
+//! `bindings_saved.ron`, which is used over `bindings.ron` for those presets from then on.
+//! `bindings.ron` is left as it is, so a preset can always be reset to its defaults.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use amethyst::{
+    ecs::prelude::{Read, Resources, System, SystemData, Write},
+    input::{Bindings, InputHandler, StringBindings},
+};
+use failure::format_err;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::rebinding::check_combos;
+
+/// The contents of `bindings.ron`.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ControlSchemes {
+    /// The preset used until the players pick one.
+    pub default: String,
+    /// Bindings kept whichever preset is active.
+    pub shared: Bindings<StringBindings>,
+    pub presets: BTreeMap<String, Bindings<StringBindings>>,
+}
+
+/// The preset saved by the last run.
+#[derive(Debug, Deserialize, Serialize)]
+struct SavedControlScheme {
+    active: String,
+}
+
+impl ControlSchemes {
+    /// Reads `bindings.ron`, failing if any preset's bindings clash with each other or the
+    /// shared ones, combos holding a button bound alone included.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, failure::Error> {
+        let schemes: ControlSchemes = ron::de::from_reader(fs::File::open(path)?)?;
+        schemes.check()?;
+        Ok(schemes)
+    }
+
+    fn check(&self) -> Result<(), failure::Error> {
+        if !self.presets.contains_key(&self.default) {
+            return Err(format_err!(
+                "Default control scheme {:?} is not one of the presets",
+                self.default
+            ));
+        }
+        for preset in self.presets.keys() {
+            check_combos(&self.bindings(preset)?)
+                .map_err(|err| format_err!("Control scheme {:?}: {}", preset, err))?;
+        }
+        Ok(())
+    }
+
+    /// The shared bindings together with those of `preset`.
+    pub fn bindings(&self, preset: &str) -> Result<Bindings<StringBindings>, failure::Error> {
+        let preset_bindings = self
+            .presets
+            .get(preset)
+            .ok_or_else(|| format_err!("No control scheme named {:?}", preset))?;
+        let mut bindings = self.shared.clone();
+        for axis in preset_bindings.axes() {
+            let binding = preset_bindings.axis(axis).cloned().unwrap();
+            bindings
+                .insert_axis(axis.clone(), binding)
+                .map_err(|err| format_err!("Control scheme {:?}: {}", preset, err))?;
+        }
+        for action in preset_bindings.actions() {
+            for combo in preset_bindings.action_bindings(action) {
+                bindings
+                    .insert_action_binding(action.clone(), combo.iter().cloned())
+                    .map_err(|err| format_err!("Control scheme {:?}: {}", preset, err))?;
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// The preset after `preset` in name order, wrapping around.
+    pub fn next(&self, preset: &str) -> String {
+        self.presets
+            .keys()
+            .skip_while(|name| name.as_str() != preset)
+            .nth(1)
+            .or_else(|| self.presets.keys().next())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The preset saved at `saved_path`, falling back to the default when there is none.
+    pub fn initial(&self, saved_path: &Path) -> String {
+        fs::File::open(saved_path)
+            .ok()
+            .and_then(|file| ron::de::from_reader::<_, SavedControlScheme>(file).ok())
+            .map(|saved| saved.active)
+            .filter(|active| self.presets.contains_key(active))
+            .unwrap_or_else(|| self.default.clone())
+    }
+}
+
+/// The presets the players rebound, with all their bindings, shared ones included.
+#[derive(Clone, Default)]
+pub struct CustomBindings {
+    presets: BTreeMap<String, Bindings<StringBindings>>,
+    /// Counts changes, for the `ControlSchemeSystem` to notice them.
+    revision: u64,
+}
+
+impl CustomBindings {
+    /// The bindings saved at `saved_path`, or none when there are none. Presets whose combos
+    /// hold a button bound alone are left out, to be played with their defaults.
+    pub fn load(saved_path: &Path) -> Self {
+        let file = match fs::File::open(saved_path) {
+            Ok(file) => file,
+            Err(_) => return CustomBindings::default(),
+        };
+        match ron::de::from_reader::<_, BTreeMap<String, Bindings<StringBindings>>>(file) {
+            Ok(mut presets) => {
+                presets.retain(|preset, bindings| match check_combos(bindings) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        warn!("Ignoring the rebound controls of {:?}: {}", preset, err);
+                        false
+                    }
+                });
+                CustomBindings {
+                    presets,
+                    revision: 0,
+                }
+            }
+            Err(err) => {
+                warn!("Ignoring the rebound controls: {}", err);
+                CustomBindings::default()
+            }
+        }
+    }
+
+    pub fn get(&self, preset: &str) -> Option<&Bindings<StringBindings>> {
+        self.presets.get(preset)
+    }
+
+    pub fn set(&mut self, preset: &str, bindings: Bindings<StringBindings>) {
+        self.presets.insert(preset.to_string(), bindings);
+        self.revision += 1;
+    }
+
+    /// Goes back to `bindings.ron`'s bindings for `preset`.
+    pub fn reset(&mut self, preset: &str) {
+        if self.presets.remove(preset).is_some() {
+            self.revision += 1;
+        }
+    }
+
+    /// The bindings `preset` is played with: the rebound ones if there are any, or else the
+    /// defaults in `schemes`.
+    pub fn bindings(
+        &self,
+        schemes: &ControlSchemes,
+        preset: &str,
+    ) -> Result<Bindings<StringBindings>, failure::Error> {
+        match self.get(preset) {
+            Some(bindings) => Ok(bindings.clone()),
+            None => schemes.bindings(preset),
+        }
+    }
+}
+
+/// The name of the preset driving the players. Change it to switch presets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ActiveControlScheme(pub String);
+
+/// Moves to the next preset on the `cycle_control_scheme` action, and swaps the bindings in and
+/// saves the choice whenever `ActiveControlScheme` changes. Swaps them in again, and saves
+/// them, whenever the `CustomBindings` change.
+pub struct ControlSchemeSystem {
+    schemes: ControlSchemes,
+    saved_path: PathBuf,
+    saved_bindings_path: PathBuf,
+    /// Until set up, when they become a resource.
+    custom: Option<CustomBindings>,
+    applied: String,
+    applied_revision: u64,
+    was_pressed: bool,
+}
+
+impl ControlSchemeSystem {
+    /// `applied` is the preset the input bindings were built from, with `custom` as loaded
+    /// from `saved_bindings_path`.
+    pub fn new(
+        schemes: ControlSchemes,
+        custom: CustomBindings,
+        saved_path: PathBuf,
+        saved_bindings_path: PathBuf,
+        applied: String,
+    ) -> Self {
+        ControlSchemeSystem {
+            schemes,
+            saved_path,
+            saved_bindings_path,
+            applied_revision: custom.revision,
+            custom: Some(custom),
+            applied,
+            was_pressed: false,
+        }
+    }
+
+    fn save_bindings(&self, custom: &CustomBindings) {
+        let result = ron::ser::to_string_pretty(&custom.presets, Default::default())
+            .map_err(failure::Error::from)
+            .and_then(|text| {
+                fs::write(&self.saved_bindings_path, text).map_err(failure::Error::from)
+            });
+        if let Err(err) = result {
+            warn!("Failed to save the rebound controls: {}", err);
+        }
+    }
+
+    fn save(&self) {
+        let saved = SavedControlScheme {
+            active: self.applied.clone(),
+        };
+        let result = ron::ser::to_string_pretty(&saved, Default::default())
+            .map_err(failure::Error::from)
+            .and_then(|text| fs::write(&self.saved_path, text).map_err(failure::Error::from));
+        if let Err(err) = result {
+            warn!("Failed to save the control scheme: {}", err);
+        }
+    }
+}
+
+impl<'s> System<'s> for ControlSchemeSystem {
+    type SystemData = (
+        Write<'s, InputHandler<StringBindings>>,
+        Write<'s, ActiveControlScheme>,
+        Read<'s, CustomBindings>,
+    );
+
+    fn run(&mut self, (mut input, mut active, custom): Self::SystemData) {
+        let pressed = input
+            .action_is_down("cycle_control_scheme")
+            .unwrap_or(false);
+        if pressed && !self.was_pressed {
+            active.0 = self.schemes.next(&active.0);
+        }
+        self.was_pressed = pressed;
+
+        let rebound = custom.revision != self.applied_revision;
+        if active.0 == self.applied && !rebound {
+            return;
+        }
+        if rebound {
+            self.applied_revision = custom.revision;
+            self.save_bindings(&custom);
+        }
+        match custom.bindings(&self.schemes, &active.0) {
+            Ok(bindings) => {
+                input.bindings = bindings;
+                if active.0 != self.applied {
+                    self.applied = active.0.clone();
+                    info!("Switched to the {} control scheme", self.applied);
+                    self.save();
+                }
+            }
+            Err(err) => {
+                warn!("Keeping the {} control scheme: {}", self.applied, err);
+                active.0 = self.applied.clone();
+            }
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        res.insert(ActiveControlScheme(self.applied.clone()));
+        if let Some(custom) = self.custom.take() {
+            res.insert(custom);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::input::{Button, VirtualKeyCode};
+
+    use super::*;
+
+    fn schemes(shared: &str) -> ControlSchemes {
+        ron::de::from_str(&format!(
+            r#"(
+                default: "WASD",
+                shared: (axes: {{}}, actions: {{{}}}),
+                presets: {{
+                    "WASD": (
+                        axes: {{"horizontal": Emulated(pos: Key(D), neg: Key(A))}},
+                        actions: {{}},
+                    ),
+                }},
+            )"#,
+            shared
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn the_shipped_bindings_load() {
+        ControlSchemes::load("resources/bindings.ron").unwrap();
+    }
+
+    #[test]
+    fn combos_holding_a_preset_button_are_rejected() {
+        assert!(schemes(r#""pause": [[Key(Escape)]]"#).check().is_ok());
+        assert!(schemes(r#""cheat": [[Key(LControl), Key(D)]]"#)
+            .check()
+            .is_err());
+    }
+
+    #[test]
+    fn resetting_goes_back_to_the_defaults() {
+        let schemes = schemes(r#""pause": [[Key(Escape)]]"#);
+        let mut custom = CustomBindings::default();
+        let defaults = custom.bindings(&schemes, "WASD").unwrap();
+        let mut rebound = defaults.clone();
+        rebound
+            .insert_action_binding("dash".to_string(), vec![Button::Key(VirtualKeyCode::Space)])
+            .unwrap();
+        custom.set("WASD", rebound);
+        assert_eq!(
+            custom.bindings(&schemes, "WASD").unwrap().actions().count(),
+            2
+        );
+
+        custom.reset("WASD");
+        assert!(custom.get("WASD").is_none());
+        assert_eq!(
+            custom.bindings(&schemes, "WASD").unwrap().actions().count(),
+            1
+        );
+    }
+}
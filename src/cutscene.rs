@@ -0,0 +1,564 @@
+//! Scripted sequences played out in the level, started by a level script's `PlayCutscene`.
+//!
+//! `cutscenes.ron` describes each cutscene by name as a timeline of steps, each an action: a
+//! camera sweep, a player walking somewhere, an animation, a conversation, a pause or a sound.
+//! A step starts once every step before it has finished, or, given an `at`, that many seconds into
+//! the cutscene even if steps before it are still going, as long as they have started. The
+//! cutscene ends once every step has finished.
+//!
+//! While a cutscene plays, the players are disabled, so their input does nothing, and are only
+//! moved by the cutscene. Players something else has disabled already, such as a stun, are left
+//! as they are, and only held by the cutscene if that wears off before it ends, so the
+//! cutscene never cuts a stun short or frees a player it didn't disable itself. Camera sweeps play through a camera of the cutscene's own, as the
+//! level intro's do, which holds on its last shot until the cutscene ends. Once it ends, the
+//! view and control are handed back. If `cutscenes.ron` makes them skippable, the
+//! `skip_cutscene` action ends one straight away, finishing its walks and animations at once
+//! and dropping the rest.
+//!
+//! Positions are in world units from the map's bottom-left corner.
+
+use std::collections::BTreeMap;
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    audio::Source,
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Entities, Entity, Join, Read, ReadExpect, ReadStorage, System, Write, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    prelude::{GameData, StateEvent, Trans, TransEvent},
+    renderer::camera::{ActiveCamera, Camera},
+    shrev::EventChannel,
+    window::ScreenDimensions,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{Animated, SpriteAnimation},
+    camera::{centered_projection, CameraZoom},
+    clock::GameClock,
+    dialogue::DialogueOpen,
+    disabled::{disable, enable, Disabled},
+    intro::{framing, IntroShot},
+    menu::DialogueState,
+    movement::world_position,
+    navigation::NavGrid,
+    player::Player,
+    sound::{queue_sound, queue_sound_at, SoundQueue},
+    split_screen::SplitScreen,
+    tile_map::TileMap,
+};
+
+/// Something a cutscene does.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum CutsceneAction {
+    /// Sweeps the cutscene's camera through these shots, as the level intro does. Finishes
+    /// once it is holding on the last.
+    MoveCamera(Vec<IntroShot>),
+    /// Walks the player at `player` to `x`, `y` at `speed` world units per second, around the
+    /// walls in the way. Finishes once the player gets there.
+    MoveTo {
+        player: usize,
+        x: f32,
+        y: f32,
+        speed: f32,
+    },
+    /// Plays `animation` on the sprite of the player at `player` from now on.
+    PlayAnimation {
+        player: usize,
+        animation: SpriteAnimation,
+    },
+    /// Holds the conversation starting from this node of the `DialogueGraph`. Finishes once
+    /// the conversation ends.
+    ShowDialogue(String),
+    /// Finishes after this many seconds.
+    Wait(f32),
+    /// Plays the ogg or wav file at this path, relative to `resources`.
+    PlaySound(String),
+    /// Plays the sound at `path` like `PlaySound`, from `x` and `y` on the map, where walls
+    /// between it and the players muffle it.
+    PlaySoundAt { path: String, x: f32, y: f32 },
+}
+
+/// One step of a cutscene's timeline.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CutsceneStep {
+    /// Seconds into the cutscene the step starts at, without waiting for the one before it to
+    /// finish.
+    #[serde(default)]
+    pub at: Option<f32>,
+    pub action: CutsceneAction,
+}
+
+/// Every cutscene by name, read from `cutscenes.ron`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Cutscenes {
+    /// Whether the `skip_cutscene` action ends a cutscene.
+    pub skippable: bool,
+    pub cutscenes: BTreeMap<String, Vec<CutsceneStep>>,
+}
+
+/// A step that has started and not yet finished.
+struct RunningStep {
+    action: CutsceneAction,
+    elapsed: f32,
+    /// The rest of a walk's path, from the map's bottom-left corner.
+    waypoints: Vec<Vector2<f32>>,
+}
+
+/// The cutscene being played, and what to hand back once it ends.
+struct Playing {
+    name: String,
+    steps: Vec<CutsceneStep>,
+    /// The first step still to start.
+    next: usize,
+    elapsed: f32,
+    running: Vec<RunningStep>,
+    /// The players the cutscene has disabled itself, to enable again once it ends.
+    held: Vec<Entity>,
+    camera: Option<Entity>,
+    previous_camera: Option<Entity>,
+    previous_split_screen: bool,
+}
+
+impl Playing {
+    /// Whether the next step can start now.
+    fn next_is_ready(&self) -> bool {
+        match self.steps.get(self.next) {
+            Some(step) => match step.at {
+                Some(at) => self.elapsed >= at,
+                None => self.running.is_empty(),
+            },
+            None => false,
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.next >= self.steps.len() && self.running.is_empty()
+    }
+
+    /// Disables the players that aren't disabled already, holding them until the cutscene
+    /// ends.
+    fn hold_players(
+        &mut self,
+        entities: &Entities<'_>,
+        players: &ReadStorage<'_, Player>,
+        disabled: &mut WriteStorage<'_, Disabled>,
+    ) {
+        let free: Vec<Entity> = (entities, players, !&*disabled)
+            .join()
+            .map(|(entity, _, _)| entity)
+            .collect();
+        for entity in free {
+            disable(disabled, entity);
+            if !self.held.contains(&entity) {
+                self.held.push(entity);
+            }
+        }
+    }
+
+    /// Enables the players the cutscene held.
+    fn release_players(&self, disabled: &mut WriteStorage<'_, Disabled>) {
+        for &entity in &self.held {
+            enable(disabled, entity);
+        }
+    }
+}
+
+/// The cutscene being played, if any.
+#[derive(Default)]
+pub struct Cutscene {
+    playing: Option<Playing>,
+    /// Starts pressed, in case `skip_cutscene` is held as the cutscene starts.
+    was_skip_pressed: bool,
+}
+
+impl Cutscene {
+    /// Starts playing the cutscene `name` of `cutscenes`, in place of any already playing.
+    pub fn start(&mut self, name: &str, cutscenes: &Cutscenes) {
+        let steps = match cutscenes.cutscenes.get(name) {
+            Some(steps) => steps.clone(),
+            None => {
+                warn!("Started unknown cutscene {:?}", name);
+                return;
+            }
+        };
+        info!("Playing cutscene {:?}", name);
+        // Whatever was playing is left where it had got to, beyond its own camera and the
+        // players it held, which are picked up by the new one.
+        let (held, camera, previous_camera, previous_split_screen) = match self.playing.take() {
+            Some(playing) => (
+                playing.held,
+                playing.camera,
+                playing.previous_camera,
+                playing.previous_split_screen,
+            ),
+            None => (Vec::new(), None, None, false),
+        };
+        self.playing = Some(Playing {
+            name: name.to_string(),
+            steps,
+            next: 0,
+            elapsed: 0.,
+            running: Vec::new(),
+            held,
+            camera,
+            previous_camera,
+            previous_split_screen,
+        });
+        self.was_skip_pressed = true;
+    }
+}
+
+/// The player at `index`, if there is one.
+fn player_entity(
+    entities: &Entities<'_>,
+    players: &ReadStorage<'_, Player>,
+    index: usize,
+) -> Option<Entity> {
+    (entities, players)
+        .join()
+        .find(|(_, player)| player.index == index)
+        .map(|(entity, _)| entity)
+}
+
+/// Plays the `Cutscene` with the `GameClock`, as the module describes.
+pub struct CutsceneSystem;
+
+impl<'s> System<'s> for CutsceneSystem {
+    type SystemData = (
+        Entities<'s>,
+        Write<'s, Cutscene>,
+        Read<'s, Cutscenes>,
+        ReadStorage<'s, Player>,
+        WriteStorage<'s, Disabled>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Animated>,
+        WriteStorage<'s, Camera>,
+        WriteStorage<'s, CameraZoom>,
+        Write<'s, ActiveCamera>,
+        Write<'s, SplitScreen>,
+        Write<'s, DialogueOpen>,
+        Write<'s, EventChannel<TransEvent<GameData<'static, 'static>, StateEvent>>>,
+        Write<'s, SoundQueue>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<Source>>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, NavGrid>,
+        Read<'s, TileMap>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut cutscene,
+            cutscenes,
+            players,
+            mut disabled,
+            mut transforms,
+            mut animated,
+            mut cameras,
+            mut zooms,
+            mut active_camera,
+            mut split_screen,
+            mut dialogue,
+            mut transitions,
+            mut sounds,
+            loader,
+            sources,
+            dimensions,
+            grid,
+            map,
+            input,
+            clock,
+        ): Self::SystemData,
+    ) {
+        let pressed = input.action_is_down("skip_cutscene").unwrap_or(false);
+        let skip = pressed && !cutscene.was_skip_pressed && cutscenes.skippable;
+        cutscene.was_skip_pressed = pressed;
+        let playing = match cutscene.playing.as_mut() {
+            Some(playing) => playing,
+            None => return,
+        };
+
+        playing.hold_players(&entities, &players, &mut disabled);
+        let origin = map.origin();
+        let mut delta = clock.delta_seconds();
+        playing.elapsed += delta;
+
+        loop {
+            // Skipping starts everything left, to finish it all at once.
+            while skip || playing.next_is_ready() {
+                let action = match playing.steps.get(playing.next) {
+                    Some(step) => step.action.clone(),
+                    None => break,
+                };
+                playing.next += 1;
+                let mut waypoints = Vec::new();
+                match &action {
+                    CutsceneAction::MoveCamera(_) if playing.camera.is_none() => {
+                        let mut transform = Transform::default();
+                        // Above everything the camera passes over, like the game camera.
+                        transform.set_translation_z(1.);
+                        let screen = Vector2::new(dimensions.width(), dimensions.height());
+                        let camera = entities
+                            .build_entity()
+                            .with(transform, &mut transforms)
+                            .with(Camera::from(centered_projection(screen, 1.)), &mut cameras)
+                            .with(CameraZoom::new(1., 0.1, 10.), &mut zooms)
+                            .build();
+                        playing.camera = Some(camera);
+                        playing.previous_camera = active_camera.entity.replace(camera);
+                        playing.previous_split_screen = split_screen.enabled;
+                        split_screen.enabled = false;
+                    }
+                    CutsceneAction::MoveTo { player, x, y, .. } => {
+                        let goal = Vector2::new(*x, *y);
+                        let from = player_entity(&entities, &players, *player)
+                            .and_then(|entity| transforms.get(entity))
+                            .map(world_position);
+                        let path = from.and_then(|from| {
+                            let start = grid.tile_at(from)?;
+                            let end = grid.tile_at(goal + origin)?;
+                            grid.find_path(start, end)
+                        });
+                        // The player's own tile is skipped, and the path ends on the point
+                        // itself rather than its tile's centre.
+                        if let Some(path) = path {
+                            waypoints.extend(
+                                path.iter()
+                                    .skip(1)
+                                    .map(|&tile| grid.tile_center(tile) - origin),
+                            );
+                            waypoints.pop();
+                        }
+                        waypoints.push(goal);
+                    }
+                    CutsceneAction::ShowDialogue(node) if !skip => {
+                        let node = node.clone();
+                        dialogue.0 = true;
+                        transitions.single_write(Box::new(move || {
+                            Trans::Push(Box::new(DialogueState::new(node.clone())))
+                        }));
+                    }
+                    CutsceneAction::PlaySound(path) if !skip => {
+                        queue_sound(&loader, &sources, &mut sounds, path, 1.)
+                    }
+                    CutsceneAction::PlaySoundAt { path, x, y } if !skip => queue_sound_at(
+                        &loader,
+                        &sources,
+                        &mut sounds,
+                        path,
+                        1.,
+                        Vector2::new(*x, *y) + origin,
+                    ),
+                    _ => {}
+                }
+                playing.running.push(RunningStep {
+                    action,
+                    elapsed: 0.,
+                    waypoints,
+                });
+            }
+
+            let mut finished = Vec::new();
+            for (index, step) in playing.running.iter_mut().enumerate() {
+                step.elapsed += delta;
+                let done = match &step.action {
+                    CutsceneAction::MoveCamera(shots) => {
+                        let framed = framing(shots, step.elapsed).filter(|_| !skip);
+                        let camera = playing.camera;
+                        if let Some((center, level)) = framed {
+                            let center = center + origin;
+                            if let Some(transform) = camera.and_then(|c| transforms.get_mut(c)) {
+                                transform.set_translation_x(center.x);
+                                transform.set_translation_y(center.y);
+                            }
+                            if let Some(zoom) = camera.and_then(|c| zooms.get_mut(c)) {
+                                zoom.set_level(level);
+                            }
+                        }
+                        framed.is_none()
+                    }
+                    CutsceneAction::MoveTo { player, speed, .. } => {
+                        let transform = player_entity(&entities, &players, *player)
+                            .and_then(|entity| transforms.get_mut(entity));
+                        match transform {
+                            Some(transform) => {
+                                let mut position = world_position(transform) - origin;
+                                let mut left = if skip { f32::INFINITY } else { speed * delta };
+                                while let Some(&waypoint) = step.waypoints.first() {
+                                    let distance = (waypoint - position).norm();
+                                    if distance > left {
+                                        position += (waypoint - position) / distance * left;
+                                        break;
+                                    }
+                                    left -= distance;
+                                    position = waypoint;
+                                    step.waypoints.remove(0);
+                                }
+                                let position = position + origin;
+                                transform.set_translation_x(position.x);
+                                transform.set_translation_y(position.y);
+                                step.waypoints.is_empty()
+                            }
+                            None => true,
+                        }
+                    }
+                    CutsceneAction::PlayAnimation { player, animation } => {
+                        if let Some(entity) = player_entity(&entities, &players, *player) {
+                            animated
+                                .insert(entity, Animated::new(animation.clone()))
+                                .expect("Player is alive");
+                        }
+                        true
+                    }
+                    CutsceneAction::ShowDialogue(_) => skip || !dialogue.0,
+                    CutsceneAction::Wait(seconds) => skip || step.elapsed >= *seconds,
+                    CutsceneAction::PlaySound(_) | CutsceneAction::PlaySoundAt { .. } => true,
+                };
+                if done {
+                    finished.push(index);
+                }
+            }
+            for index in finished.into_iter().rev() {
+                playing.running.remove(index);
+            }
+            // Steps that finish straight away let the next start in the same step, without
+            // any more time passing.
+            if !playing.next_is_ready() {
+                break;
+            }
+            delta = 0.;
+        }
+
+        if !playing.is_over() {
+            return;
+        }
+        info!("Cutscene {:?} over, handing back control", playing.name);
+        playing.release_players(&mut disabled);
+        if let Some(camera) = playing.camera {
+            if entities.is_alive(camera) {
+                entities.delete(camera).expect("Cutscene camera is alive");
+            }
+            active_camera.entity = playing.previous_camera;
+            split_screen.enabled = playing.previous_split_screen;
+        }
+        cutscene.playing = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, World};
+
+    use super::*;
+
+    fn wait(at: Option<f32>, seconds: f32) -> CutsceneStep {
+        CutsceneStep {
+            at,
+            action: CutsceneAction::Wait(seconds),
+        }
+    }
+
+    fn playing(steps: Vec<CutsceneStep>) -> Playing {
+        Playing {
+            name: "test".to_string(),
+            steps,
+            next: 0,
+            elapsed: 0.,
+            running: Vec::new(),
+            held: Vec::new(),
+            camera: None,
+            previous_camera: None,
+            previous_split_screen: false,
+        }
+    }
+
+    /// Starts the next step, as the system does.
+    fn start_next(playing: &mut Playing) {
+        let action = playing.steps[playing.next].action.clone();
+        playing.next += 1;
+        playing.running.push(RunningStep {
+            action,
+            elapsed: 0.,
+            waypoints: Vec::new(),
+        });
+    }
+
+    #[test]
+    fn a_step_waits_for_the_one_before_it() {
+        let mut playing = playing(vec![wait(None, 1.), wait(None, 1.)]);
+        assert!(playing.next_is_ready());
+        start_next(&mut playing);
+        playing.elapsed = 5.;
+        assert!(!playing.next_is_ready());
+        playing.running.clear();
+        assert!(playing.next_is_ready());
+        start_next(&mut playing);
+        assert!(!playing.next_is_ready());
+        assert!(!playing.is_over());
+        playing.running.clear();
+        assert!(playing.is_over());
+    }
+
+    #[test]
+    fn a_timed_step_starts_alongside_the_one_before_it() {
+        let mut playing = playing(vec![wait(None, 2.), wait(Some(0.5), 1.)]);
+        start_next(&mut playing);
+        playing.elapsed = 0.25;
+        assert!(!playing.next_is_ready());
+        playing.elapsed = 0.5;
+        assert!(playing.next_is_ready());
+    }
+
+    #[test]
+    fn only_the_players_the_cutscene_disabled_are_enabled_again() {
+        let mut world = World::new();
+        world.register::<Player>();
+        world.register::<Disabled>();
+        let mut player = |index, disabled: Option<Disabled>| {
+            let builder = world.create_entity().with(Player { index, speed: 1. });
+            match disabled {
+                Some(disabled) => builder.with(disabled).build(),
+                None => builder.build(),
+            }
+        };
+        let free = player(0, None);
+        let held_elsewhere = player(1, Some(Disabled { remaining: None }));
+        let stunned = player(
+            2,
+            Some(Disabled {
+                remaining: Some(2.),
+            }),
+        );
+
+        let mut playing = playing(vec![wait(None, 1.)]);
+        {
+            let (entities, players, mut disabled) = world.system_data::<(
+                Entities<'_>,
+                ReadStorage<'_, Player>,
+                WriteStorage<'_, Disabled>,
+            )>();
+            playing.hold_players(&entities, &players, &mut disabled);
+            assert_eq!(playing.held, [free]);
+            assert_eq!(disabled.get(stunned).unwrap().remaining, Some(2.));
+
+            // The stun wears off while the cutscene still plays, so it holds the player.
+            enable(&mut disabled, stunned);
+            playing.hold_players(&entities, &players, &mut disabled);
+            assert_eq!(playing.held, [free, stunned]);
+
+            playing.release_players(&mut disabled);
+            assert!(disabled.get(free).is_none());
+            assert!(disabled.get(stunned).is_none());
+            assert!(disabled.get(held_elsewhere).is_some());
+        }
+    }
+}
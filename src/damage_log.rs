@@ -0,0 +1,254 @@
+//! A record of the most recent hits, for tuning combat balance.
+//!
+//! Every `DamageTaken` is kept, up to `DamageLog::capacity` of them, with its source, target,
+//! kind, damage type and the game time it landed at. The debug overlay shows a summary, and the
+//! `dump_damage_log` action writes the whole log out as CSV.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Write as _},
+    path::PathBuf,
+};
+
+use amethyst::{
+    ecs::prelude::{Entity, Read, Resources, System, SystemData, Write},
+    input::{InputHandler, StringBindings},
+    shrev::{EventChannel, ReaderId},
+};
+use log::{info, warn};
+
+use crate::{
+    clock::GameClock,
+    combat::{DamageKind, DamageTaken, DamageType},
+    debug_overlay::DebugOverlay,
+};
+
+/// Seconds of recent damage the overlay's damage per second is averaged over.
+const DPS_WINDOW: f64 = 5.;
+
+/// One hit in the `DamageLog`.
+#[derive(Clone, Copy, Debug)]
+pub struct DamageLogEntry {
+    /// Game time the hit landed at, in seconds.
+    pub time: f64,
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub amount: f32,
+    pub kind: DamageKind,
+    pub damage_type: DamageType,
+}
+
+/// The most recent hits, oldest first. Once `capacity` is reached each new hit pushes out the
+/// oldest.
+#[derive(Clone, Debug)]
+pub struct DamageLog {
+    pub capacity: usize,
+    entries: VecDeque<DamageLogEntry>,
+}
+
+impl Default for DamageLog {
+    fn default() -> Self {
+        DamageLog::new(1024)
+    }
+}
+
+impl DamageLog {
+    pub fn new(capacity: usize) -> Self {
+        DamageLog {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn record(&mut self, entry: DamageLogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Damage per second dealt over the `window` seconds up to `now`.
+    pub fn dps(&self, now: f64, window: f64) -> f32 {
+        if window <= 0. {
+            return 0.;
+        }
+        let total: f32 = self
+            .entries
+            .iter()
+            .rev()
+            .take_while(|entry| now - entry.time <= window)
+            .map(|entry| entry.amount)
+            .sum();
+        (f64::from(total) / window) as f32
+    }
+
+    /// Writes every entry as a line of CSV, after a header.
+    pub fn write_csv(&self, out: &mut impl io::Write) -> io::Result<()> {
+        writeln!(out, "time,source,target,amount,kind,type")?;
+        for entry in &self.entries {
+            let source = entry.source.map(|source| source.id().to_string());
+            writeln!(
+                out,
+                "{:.3},{},{},{},{:?},{:?}",
+                entry.time,
+                source.as_deref().unwrap_or(""),
+                entry.target.id(),
+                entry.amount,
+                entry.kind,
+                entry.damage_type,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records every `DamageTaken` in the `DamageLog` and keeps the overlay's summary current.
+#[derive(Default)]
+pub struct DamageLogSystem {
+    reader: Option<ReaderId<DamageTaken>>,
+}
+
+impl<'s> System<'s> for DamageLogSystem {
+    type SystemData = (
+        Read<'s, EventChannel<DamageTaken>>,
+        Write<'s, DamageLog>,
+        Write<'s, DebugOverlay>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(&mut self, (events, mut log, mut overlay, clock): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("DamageLogSystem is set up");
+        let time = clock.elapsed_seconds();
+        for event in events.read(reader) {
+            log.record(DamageLogEntry {
+                time,
+                source: event.source,
+                target: event.target,
+                amount: event.amount,
+                kind: event.kind,
+                damage_type: event.damage_type,
+            });
+        }
+        overlay.set(
+            "damage",
+            format!(
+                "Damage: {} hits logged, {:.1} per second over the last {}s",
+                log.entries.len(),
+                log.dps(time, DPS_WINDOW),
+                DPS_WINDOW,
+            ),
+        );
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<DamageTaken>>()
+                .register_reader(),
+        );
+    }
+}
+
+/// Writes the `DamageLog` to `path` on the `dump_damage_log` action.
+pub struct DamageLogDumpSystem {
+    pub path: PathBuf,
+    was_pressed: bool,
+}
+
+impl DamageLogDumpSystem {
+    pub fn new(path: PathBuf) -> Self {
+        DamageLogDumpSystem {
+            path,
+            was_pressed: false,
+        }
+    }
+
+    fn dump(&self, log: &DamageLog) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        log.write_csv(&mut out)?;
+        out.flush()
+    }
+}
+
+impl<'s> System<'s> for DamageLogDumpSystem {
+    type SystemData = (Read<'s, InputHandler<StringBindings>>, Read<'s, DamageLog>);
+
+    fn run(&mut self, (input, log): Self::SystemData) {
+        let pressed = input.action_is_down("dump_damage_log").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            match self.dump(&log) {
+                Ok(()) => info!(
+                    "Wrote {} damage log entries to {}",
+                    log.entries.len(),
+                    self.path.display()
+                ),
+                Err(err) => warn!(
+                    "Failed to write the damage log to {}: {}",
+                    self.path.display(),
+                    err
+                ),
+            }
+        }
+        self.was_pressed = pressed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, World};
+
+    use super::*;
+
+    fn hit(time: f64, source: Option<Entity>, target: Entity, amount: f32) -> DamageLogEntry {
+        DamageLogEntry {
+            time,
+            source,
+            target,
+            amount,
+            kind: DamageKind::Hitbox,
+            damage_type: DamageType::Fire,
+        }
+    }
+
+    #[test]
+    fn past_capacity_the_oldest_hits_are_dropped() {
+        let mut world = World::new();
+        let target = world.create_entity().build();
+        let mut log = DamageLog::new(3);
+        for i in 0..5 {
+            log.record(hit(f64::from(i), None, target, i as f32));
+        }
+
+        let amounts: Vec<f32> = log.entries.iter().map(|entry| entry.amount).collect();
+        assert_eq!(amounts, vec![2., 3., 4.]);
+
+        let mut empty = DamageLog::new(0);
+        empty.record(hit(0., None, target, 1.));
+        assert!(empty.entries.is_empty());
+    }
+
+    #[test]
+    fn the_dump_is_a_header_then_one_line_per_hit() {
+        let mut world = World::new();
+        let source = world.create_entity().build();
+        let target = world.create_entity().build();
+        let mut log = DamageLog::default();
+        log.record(hit(1.25, Some(source), target, 12.5));
+        log.record(hit(2., None, target, 3.));
+
+        let mut out = Vec::new();
+        log.write_csv(&mut out).unwrap();
+        let expected = format!(
+            "time,source,target,amount,kind,type\n\
+             1.250,{source},{target},12.5,Hitbox,Fire\n\
+             2.000,,{target},3,Hitbox,Fire\n",
+            source = source.id(),
+            target = target.id(),
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
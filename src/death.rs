@@ -0,0 +1,459 @@
+//! What happens when an entity runs out of health.
+//!
+//! An entity with an `OnDeath` dies once its `Health` reaches zero: its effects are played where
+//! it stood and it is despawned. Entities without one, such as the players, are left at zero.
+
+use amethyst::{
+    core::{
+        math::{Point3, Vector2},
+        timing::Time,
+        transform::Transform,
+    },
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, NullStorage, Read, ReadStorage, System,
+        Write, WriteStorage,
+    },
+    renderer::{debug_drawing::DebugLinesComponent, palette::Srgba, SpriteRender, Transparent},
+    shrev::EventChannel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock,
+    combat::Health,
+    difficulty::Difficulty,
+    limits::{EntityKind, Limited, SpawnOrder},
+    movement::world_position,
+    quality::QualityTier,
+    rng::Rng,
+    rumble::{RumbleEvent, RumbleKind},
+};
+
+/// Depth of the burst sparks, in front of the tiles and sprites.
+const BURST_DEPTH: f32 = 0.5;
+
+/// How far from where an entity died the things it drops land, at most, in world units.
+const DROP_SPREAD: f32 = 16.;
+
+/// Mixed into the gameplay seed for the bursts' streams, so they don't repeat the gameplay
+/// `Rng`'s numbers.
+const BURST_SEED: u64 = 0x0042_5552_5354;
+
+/// One thing that happens where an entity dies.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum DeathEffect {
+    /// Sparks flying out in every direction at `speed` world units per second, fading over
+    /// `lifetime` seconds.
+    Burst {
+        count: usize,
+        speed: f32,
+        lifetime: f32,
+        color: [f32; 4],
+    },
+    /// Leaves `sprite` of the entity's sprite sheet behind for `linger` seconds.
+    Corpse { sprite: usize, linger: f32 },
+    /// Scatters `count` of `sprite` from the entity's sprite sheet around where it died, more
+    /// or fewer as the `Difficulty` says, each left for `linger` seconds.
+    Drop {
+        sprite: usize,
+        count: usize,
+        linger: f32,
+    },
+}
+
+/// The effects played when this entity's `Health` reaches zero, before it is despawned.
+#[derive(Clone, Debug, Default)]
+pub struct OnDeath {
+    pub effects: Vec<DeathEffect>,
+}
+
+impl Component for OnDeath {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Marks an entity that has died, so it only dies once while it waits to be despawned.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Dead;
+
+impl Component for Dead {
+    type Storage = NullStorage<Self>;
+}
+
+/// Deletes the entity once `remaining` seconds of game time have passed.
+#[derive(Clone, Copy, Debug)]
+pub struct Despawn {
+    pub remaining: f32,
+}
+
+impl Component for Despawn {
+    type Storage = DenseVecStorage<Self>;
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Spark {
+    position: Vector2<f32>,
+    velocity: Vector2<f32>,
+    age: f32,
+    lifetime: f32,
+    color: [f32; 4],
+}
+
+/// The sparks of every burst still fading out.
+///
+/// Each dying entity is an emitter with a stream of its own drawn from the gameplay seed and
+/// its spawn order, so its sparks fly the same way run after run with the same seed, whatever
+/// else has burst before it.
+#[derive(Debug)]
+pub struct DeathBursts {
+    /// The most sparks alive at once. Bursts past it are cut short.
+    pub spark_cap: usize,
+    /// The seed of the game being played.
+    pub seed: u64,
+    sparks: Vec<Spark>,
+}
+
+impl Default for DeathBursts {
+    fn default() -> Self {
+        DeathBursts {
+            spark_cap: usize::MAX,
+            seed: 0,
+            sparks: Vec::new(),
+        }
+    }
+}
+
+impl DeathBursts {
+    /// Moves every spark still fading out by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        for spark in &mut self.sparks {
+            spark.position += offset;
+        }
+    }
+
+    /// The stream the sparks of `emitter` are drawn from.
+    pub fn emitter_rng(&self, emitter: u64) -> Rng {
+        Rng::stream(self.seed ^ BURST_SEED, emitter)
+    }
+
+    /// Adds `count` sparks at `position` heading out in directions drawn from `rng`.
+    pub fn burst(
+        &mut self,
+        rng: &mut Rng,
+        position: Vector2<f32>,
+        count: usize,
+        speed: f32,
+        lifetime: f32,
+        color: [f32; 4],
+    ) {
+        let room = self.spark_cap.saturating_sub(self.sparks.len());
+        for _ in 0..count.min(room) {
+            let angle = rng.range(0., std::f32::consts::TAU);
+            self.sparks.push(Spark {
+                position,
+                velocity: Vector2::new(angle.cos(), angle.sin()) * speed,
+                age: 0.,
+                lifetime,
+                color,
+            });
+        }
+    }
+
+    /// Moves the sparks on by `delta` seconds and drops those that have faded out.
+    pub fn update(&mut self, delta: f32) {
+        for spark in &mut self.sparks {
+            spark.position += spark.velocity * delta;
+            spark.age += delta;
+        }
+        self.sparks.retain(|spark| spark.age < spark.lifetime);
+    }
+
+    fn draw(&self, lines: &mut DebugLinesComponent) {
+        let point = |position: Vector2<f32>| Point3::new(position.x, position.y, BURST_DEPTH);
+        for spark in &self.sparks {
+            let [r, g, b, a] = spark.color;
+            let fade = 1. - spark.age / spark.lifetime;
+            // Streak back along the direction of travel, shrinking as the spark fades.
+            let tail = -spark.velocity * 0.05 * fade;
+            lines.add_line(
+                point(spark.position),
+                point(spark.position + tail),
+                Srgba::new(r, g, b, a * fade),
+            );
+        }
+    }
+}
+
+/// Kills every entity with an `OnDeath` whose `Health` has reached zero: plays its effects and
+/// despawns it at the end of the step. Bursts rumble every gamepad.
+pub struct DeathSystem;
+
+impl<'s> System<'s> for DeathSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Health>,
+        ReadStorage<'s, OnDeath>,
+        WriteStorage<'s, Dead>,
+        WriteStorage<'s, Despawn>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Transparent>,
+        WriteStorage<'s, Limited>,
+        Write<'s, DeathBursts>,
+        Write<'s, EventChannel<RumbleEvent>>,
+        Write<'s, SpawnOrder>,
+        Read<'s, Difficulty>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            healths,
+            on_deaths,
+            mut deads,
+            mut despawns,
+            mut transforms,
+            mut sprites,
+            mut transparents,
+            mut limited,
+            mut bursts,
+            mut rumbles,
+            mut spawn_order,
+            difficulty,
+        ): Self::SystemData,
+    ) {
+        let dying: Vec<(Entity, OnDeath)> = (&entities, &healths, &on_deaths, !&deads)
+            .join()
+            .filter(|(_, health, _, _)| health.current <= 0.)
+            .map(|(entity, _, on_death, _)| (entity, on_death.clone()))
+            .collect();
+
+        for (entity, on_death) in dying {
+            let transform = transforms.get(entity).cloned();
+            let position = transform
+                .as_ref()
+                .map_or_else(Vector2::zeros, world_position);
+            // Every entity that can die is spawned in order, which identical runs share.
+            let emitter = limited
+                .get(entity)
+                .map_or(u64::from(entity.id()), |limited| limited.order);
+            let mut rng = bursts.emitter_rng(emitter);
+            for effect in on_death.effects {
+                match effect {
+                    DeathEffect::Burst {
+                        count,
+                        speed,
+                        lifetime,
+                        color,
+                    } => {
+                        bursts.burst(&mut rng, position, count, speed, lifetime, color);
+                        rumbles.single_write(RumbleEvent {
+                            kind: RumbleKind::Explosion,
+                            player: None,
+                        });
+                    }
+                    DeathEffect::Corpse { sprite, linger } => {
+                        let sprite_sheet = match sprites.get(entity) {
+                            Some(render) => render.sprite_sheet.clone(),
+                            None => continue,
+                        };
+                        entities
+                            .build_entity()
+                            .with(transform.clone().unwrap_or_default(), &mut transforms)
+                            .with(
+                                SpriteRender {
+                                    sprite_sheet,
+                                    sprite_number: sprite,
+                                },
+                                &mut sprites,
+                            )
+                            .with(Transparent, &mut transparents)
+                            .with(Despawn { remaining: linger }, &mut despawns)
+                            .with(spawn_order.next(EntityKind::Corpse), &mut limited)
+                            .build();
+                    }
+                    DeathEffect::Drop {
+                        sprite,
+                        count,
+                        linger,
+                    } => {
+                        let sprite_sheet = match sprites.get(entity) {
+                            Some(render) => render.sprite_sheet.clone(),
+                            None => continue,
+                        };
+                        for _ in 0..difficulty.multipliers().drops(count) {
+                            let angle = rng.range(0., std::f32::consts::TAU);
+                            let distance = rng.range(0., DROP_SPREAD);
+                            let mut placed = transform.clone().unwrap_or_default();
+                            placed.prepend_translation_x(angle.cos() * distance);
+                            placed.prepend_translation_y(angle.sin() * distance);
+                            entities
+                                .build_entity()
+                                .with(placed, &mut transforms)
+                                .with(
+                                    SpriteRender {
+                                        sprite_sheet: sprite_sheet.clone(),
+                                        sprite_number: sprite,
+                                    },
+                                    &mut sprites,
+                                )
+                                .with(Transparent, &mut transparents)
+                                .with(Despawn { remaining: linger }, &mut despawns)
+                                .build();
+                        }
+                    }
+                }
+            }
+            deads
+                .insert(entity, Dead)
+                .expect("Failed to insert Dead for a live entity");
+            despawns
+                .insert(entity, Despawn { remaining: 0. })
+                .expect("Failed to insert Despawn for a live entity");
+        }
+    }
+}
+
+/// Counts down `Despawn`s and deletes their entities once they run out.
+pub struct DespawnSystem;
+
+impl<'s> System<'s> for DespawnSystem {
+    type SystemData = (Entities<'s>, WriteStorage<'s, Despawn>, Read<'s, GameClock>);
+
+    fn run(&mut self, (entities, mut despawns, clock): Self::SystemData) {
+        let delta = clock.delta_seconds();
+        for (entity, despawn) in (&entities, &mut despawns).join() {
+            despawn.remaining -= delta;
+            if despawn.remaining <= 0. {
+                entities.delete(entity).expect("Despawned entity is alive");
+            }
+        }
+    }
+}
+
+/// Marks the entity whose debug lines draw the death bursts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeathBurstOverlay;
+
+impl Component for DeathBurstOverlay {
+    type Storage = NullStorage<Self>;
+}
+
+/// Moves the sparks of the `DeathBursts` on and redraws them, capped as the `QualityTier` says.
+pub struct DeathBurstSystem;
+
+impl<'s> System<'s> for DeathBurstSystem {
+    type SystemData = (
+        ReadStorage<'s, DeathBurstOverlay>,
+        WriteStorage<'s, DebugLinesComponent>,
+        Write<'s, DeathBursts>,
+        Read<'s, QualityTier>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (overlays, mut lines, mut bursts, quality, time): Self::SystemData) {
+        bursts.spark_cap = quality.max_particles;
+        bursts.update(time.delta_seconds());
+        for (_, lines) in (&overlays, &mut lines).join() {
+            lines.clear();
+            bursts.draw(lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+
+    #[test]
+    fn reaching_zero_health_plays_the_effects_once_and_despawns() {
+        let mut world = World::new();
+        let (mut death, mut despawn) = (DeathSystem, DespawnSystem);
+        System::setup(&mut death, &mut world.res);
+        System::setup(&mut despawn, &mut world.res);
+        let mut rumbles = world
+            .write_resource::<EventChannel<RumbleEvent>>()
+            .register_reader();
+        let on_death = OnDeath {
+            effects: vec![DeathEffect::Burst {
+                count: 6,
+                speed: 40.,
+                lifetime: 0.5,
+                color: [1.; 4],
+            }],
+        };
+        let dying = world
+            .create_entity()
+            .with(Transform::default())
+            .with(Health::new(10.))
+            .with(on_death)
+            .build();
+        // Players have no `OnDeath`, and are left standing at zero.
+        let player = world
+            .create_entity()
+            .with(Transform::default())
+            .with(Health::new(10.))
+            .build();
+        let mut step = |world: &mut World| {
+            world.write_resource::<GameClock>().advance(0.25);
+            death.run_now(&world.res);
+            despawn.run_now(&world.res);
+            world.maintain();
+            world
+                .read_resource::<EventChannel<RumbleEvent>>()
+                .read(&mut rumbles)
+                .count()
+        };
+
+        world
+            .write_storage::<Health>()
+            .get_mut(dying)
+            .unwrap()
+            .current = 4.;
+        assert_eq!(step(&mut world), 0);
+        assert!(!world.read_storage::<Dead>().contains(dying));
+
+        for entity in &[dying, player] {
+            world
+                .write_storage::<Health>()
+                .get_mut(*entity)
+                .unwrap()
+                .current = 0.;
+        }
+        assert_eq!(step(&mut world), 1);
+        assert_eq!(world.read_resource::<DeathBursts>().sparks.len(), 6);
+        assert!(!world.is_alive(dying));
+        assert_eq!(step(&mut world), 0);
+        assert!(world.is_alive(player));
+        assert!(!world.read_storage::<Dead>().contains(player));
+
+        // Until it is despawned, `Dead` keeps it from dying again.
+        let again = world
+            .create_entity()
+            .with(Health::new(0.5))
+            .with(OnDeath {
+                effects: vec![DeathEffect::Burst {
+                    count: 1,
+                    speed: 40.,
+                    lifetime: 0.5,
+                    color: [1.; 4],
+                }],
+            })
+            .build();
+        world
+            .write_storage::<Health>()
+            .get_mut(again)
+            .unwrap()
+            .current = 0.;
+        death.run_now(&world.res);
+        death.run_now(&world.res);
+        assert!(world.read_storage::<Dead>().contains(again));
+        let rumbled = world
+            .read_resource::<EventChannel<RumbleEvent>>()
+            .read(&mut rumbles)
+            .count();
+        assert_eq!(rumbled, 1);
+    }
+}
@@ -0,0 +1,122 @@
+//! On-screen text for debugging, toggled with the `toggle_debug_overlay` action.
+//!
+//! Anything can put a section of text on the overlay through the `DebugOverlay` resource;
+//! sections are shown in the order of their names.
+
+use std::collections::BTreeMap;
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    ecs::prelude::{
+        Builder, Component, Join, NullStorage, Read, ReadStorage, System, World, Write,
+        WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    ui::{Anchor, FontAsset, LineMode, UiText, UiTransform},
+};
+
+use crate::ui_theme::{ThemedText, UiTheme};
+
+/// The text sections shown on the overlay.
+#[derive(Clone, Debug, Default)]
+pub struct DebugOverlay {
+    pub visible: bool,
+    sections: BTreeMap<&'static str, String>,
+}
+
+impl DebugOverlay {
+    /// Replaces the text of the section `name`.
+    pub fn set(&mut self, name: &'static str, text: String) {
+        self.sections.insert(name, text);
+    }
+
+    /// Takes the section `name` off the overlay.
+    pub fn remove(&mut self, name: &'static str) {
+        self.sections.remove(name);
+    }
+
+    /// Every section, one after the other.
+    pub fn text(&self) -> String {
+        self.sections
+            .values()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Marks the UI text the overlay is drawn into.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DebugOverlayText;
+
+impl Component for DebugOverlayText {
+    type Storage = NullStorage<Self>;
+}
+
+/// Creates the UI text the overlay is drawn into, along the top left of the window.
+pub fn spawn_debug_overlay(world: &mut World) {
+    let theme = world.read_resource::<UiTheme>().clone();
+    let font = theme.font(
+        &world.read_resource::<Loader>(),
+        &world.read_resource::<AssetStorage<FontAsset>>(),
+    );
+    let mut text = UiText::new(font, String::new(), theme.text_color, theme.font_size);
+    text.line_mode = LineMode::Wrap;
+    text.align = Anchor::TopLeft;
+    let mut transform = UiTransform::new(
+        "debug_overlay".to_string(),
+        Anchor::TopLeft,
+        Anchor::TopLeft,
+        theme.padding,
+        -theme.padding,
+        10.,
+        640.,
+        320.,
+    );
+    let themed = ThemedText::new(&text, &transform);
+    themed.apply(&theme, &mut text, &mut transform);
+
+    world
+        .create_entity()
+        .with(transform)
+        .with(text)
+        .with(themed)
+        .with(DebugOverlayText)
+        .build();
+}
+
+/// Shows or hides the overlay on the `toggle_debug_overlay` action and keeps its text current.
+#[derive(Default)]
+pub struct DebugOverlaySystem {
+    was_pressed: bool,
+}
+
+impl<'s> System<'s> for DebugOverlaySystem {
+    type SystemData = (
+        Read<'s, InputHandler<StringBindings>>,
+        Write<'s, DebugOverlay>,
+        ReadStorage<'s, DebugOverlayText>,
+        WriteStorage<'s, UiText>,
+    );
+
+    fn run(&mut self, (input, mut overlay, markers, mut texts): Self::SystemData) {
+        let pressed = input
+            .action_is_down("toggle_debug_overlay")
+            .unwrap_or(false);
+        if pressed && !self.was_pressed {
+            overlay.visible = !overlay.visible;
+        }
+        self.was_pressed = pressed;
+
+        let text = if overlay.visible {
+            overlay.text()
+        } else {
+            String::new()
+        };
+        for (_, ui_text) in (&markers, &mut texts).join() {
+            if ui_text.text != text {
+                ui_text.text = text.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Branching conversations, started by level scripts and answered from a menu.
+//!
+//! `dialogue.ron` describes the conversations as a graph of nodes by name. Each node is a line
+//! of text, by a `speaker` if it has one, followed by either the `choices` the players can
+//! answer it with, each leading to the node `next` or ending the conversation, or else just
+//! the `next` node to carry on to. Choices can also `set` flags in `DialogueFlags`, which other
+//! systems read, such as script triggers waiting on one. Texts and speakers are looked up in
+//! the string table as for `Strings::get_or`.
+//!
+//! The graph is checked as it loads, which fails on a node leading to one that isn't there or
+//! a conversation that can lead back round to a node it has been through, so every one ends.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+/// One answer to a line of dialogue.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    /// The node the choice leads to. Without one, it ends the conversation.
+    #[serde(default)]
+    pub next: Option<String>,
+    /// Flags set in `DialogueFlags` when the choice is made.
+    #[serde(default)]
+    pub set: Vec<String>,
+}
+
+/// A line of dialogue.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DialogueNode {
+    #[serde(default)]
+    pub speaker: Option<String>,
+    pub text: String,
+    /// The answers to the line. Without any, the line is carried on from to `next`.
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+    /// The node after a line without choices. Without one, the line ends the conversation.
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+impl DialogueNode {
+    /// The nodes this one can lead to.
+    fn leads_to(&self) -> impl Iterator<Item = &String> {
+        self.choices
+            .iter()
+            .filter_map(|choice| choice.next.as_ref())
+            .chain(self.next.as_ref())
+    }
+}
+
+/// Every conversation, as nodes by name, read from `dialogue.ron`.
+#[derive(Clone, Debug, Default)]
+pub struct DialogueGraph(BTreeMap<String, DialogueNode>);
+
+impl DialogueGraph {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, failure::Error> {
+        let nodes = ron::de::from_reader(fs::File::open(path)?)?;
+        DialogueGraph::new(nodes)
+    }
+
+    /// The graph of `nodes`, checked as the module describes.
+    pub fn new(nodes: BTreeMap<String, DialogueNode>) -> Result<Self, failure::Error> {
+        let mut checked = BTreeSet::new();
+        for name in nodes.keys() {
+            check(name, &nodes, &mut checked, &mut Vec::new())?;
+        }
+        Ok(DialogueGraph(nodes))
+    }
+
+    pub fn node(&self, name: &str) -> Option<&DialogueNode> {
+        self.0.get(name)
+    }
+
+    /// Where answering the node `name` with its `choice`th choice leads, or carrying on from
+    /// it for a node without choices: the next node, or `None` at the end of the conversation,
+    /// along with the flags the answer sets.
+    pub fn answer<'a>(&'a self, name: &'a str, choice: usize) -> (Option<&'a str>, &'a [String]) {
+        match self.node(name) {
+            Some(node) if node.choices.is_empty() => (node.next.as_deref(), &[]),
+            Some(node) => match node.choices.get(choice) {
+                Some(choice) => (choice.next.as_deref(), &choice.set),
+                None => (Some(name), &[]),
+            },
+            None => (None, &[]),
+        }
+    }
+}
+
+/// Checks that every node `name` can lead to is there and none of them lead back round to a
+/// node on the `path` to it, skipping the already `checked` ones.
+fn check<'a>(
+    name: &'a str,
+    nodes: &'a BTreeMap<String, DialogueNode>,
+    checked: &mut BTreeSet<&'a str>,
+    path: &mut Vec<&'a str>,
+) -> Result<(), failure::Error> {
+    if checked.contains(name) {
+        return Ok(());
+    }
+    if path.contains(&name) {
+        path.push(name);
+        return Err(format_err!(
+            "Dialogue node {:?} leads back round to itself, through {}",
+            name,
+            path.join(" -> ")
+        ));
+    }
+    let node = match nodes.get(name) {
+        Some(node) => node,
+        None => {
+            return Err(format_err!(
+                "Dialogue node {:?} leads to {:?}, which is not a node",
+                path.last().copied().unwrap_or_default(),
+                name
+            ))
+        }
+    };
+    path.push(name);
+    for next in node.leads_to() {
+        check(next, nodes, checked, path)?;
+    }
+    path.pop();
+    checked.insert(name);
+    Ok(())
+}
+
+/// Whether a conversation is being held, for telling when one has ended.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DialogueOpen(pub bool);
+
+/// The flags the players' answers have set, kept in saved games.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DialogueFlags(BTreeSet<String>);
+
+impl DialogueFlags {
+    pub fn set(&mut self, flag: &str) {
+        self.0.insert(flag.to_string());
+    }
+
+    pub fn is_set(&self, flag: &str) -> bool {
+        self.0.contains(flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, next: Option<&str>) -> DialogueNode {
+        DialogueNode {
+            speaker: None,
+            text: text.to_string(),
+            choices: Vec::new(),
+            next: next.map(str::to_string),
+        }
+    }
+
+    fn choice(text: &str, next: Option<&str>, set: &[&str]) -> DialogueChoice {
+        DialogueChoice {
+            text: text.to_string(),
+            next: next.map(str::to_string),
+            set: set.iter().map(|flag| flag.to_string()).collect(),
+        }
+    }
+
+    fn graph(nodes: Vec<(&str, DialogueNode)>) -> Result<DialogueGraph, failure::Error> {
+        DialogueGraph::new(
+            nodes
+                .into_iter()
+                .map(|(name, node)| (name.to_string(), node))
+                .collect(),
+        )
+    }
+
+    fn conversation() -> DialogueGraph {
+        let mut question = line("Will you help?", None);
+        question.choices = vec![
+            choice("Yes", Some("thanks"), &["helping"]),
+            choice("No", None, &[]),
+        ];
+        graph(vec![
+            ("greeting", line("Hello.", Some("question"))),
+            ("question", question),
+            ("thanks", line("Thank you.", None)),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn lines_without_choices_carry_on_to_next() {
+        let graph = conversation();
+        assert_eq!(graph.answer("greeting", 0), (Some("question"), &[][..]));
+        assert_eq!(graph.answer("thanks", 0), (None, &[][..]));
+    }
+
+    #[test]
+    fn choices_lead_on_and_set_their_flags() {
+        let graph = conversation();
+        let (next, set) = graph.answer("question", 0);
+        assert_eq!(next, Some("thanks"));
+        assert_eq!(set, ["helping"]);
+        assert_eq!(graph.answer("question", 1), (None, &[][..]));
+    }
+
+    #[test]
+    fn out_of_range_choices_stay_on_the_node() {
+        assert_eq!(
+            conversation().answer("question", 5),
+            (Some("question"), &[][..])
+        );
+    }
+
+    #[test]
+    fn conversations_that_lead_back_round_are_rejected() {
+        let mut ask = line("Again?", None);
+        ask.choices = vec![choice("Yes", Some("start"), &[]), choice("No", None, &[])];
+        let looped = graph(vec![("start", line("Hi.", Some("ask"))), ("ask", ask)]);
+        assert!(looped.is_err());
+        assert!(graph(vec![("alone", line("Me again.", Some("alone")))]).is_err());
+    }
+
+    #[test]
+    fn nodes_leading_nowhere_are_rejected() {
+        let dangling = graph(vec![("start", line("Hi.", Some("missing")))]);
+        let message = dangling.unwrap_err().to_string();
+        assert!(message.contains("\"missing\""), "{}", message);
+
+        let mut ask = line("Which?", None);
+        ask.choices = vec![choice("This", Some("gone"), &[])];
+        assert!(graph(vec![("ask", ask)]).is_err());
+    }
+
+    #[test]
+    fn flags_stay_set() {
+        let mut flags = DialogueFlags::default();
+        assert!(!flags.is_set("helping"));
+        flags.set("helping");
+        flags.set("helping");
+        assert!(flags.is_set("helping"));
+    }
+}
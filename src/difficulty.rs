@@ -0,0 +1,139 @@
+//! How hard the game is, picked on the main menu before a game starts.
+//!
+//! The level is kept in saved games, and a loaded game is played at the level it was saved at.
+//! It can't be changed during a game: the main menu is the only place to pick it, so enemies
+//! already spawned and those still to come are always scaled alike.
+
+use serde::{Deserialize, Serialize};
+
+/// The difficulty levels, from easiest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DifficultyLevel {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl DifficultyLevel {
+    /// The level after this one, wrapping back to the easiest.
+    pub fn next(self) -> Self {
+        match self {
+            DifficultyLevel::Easy => DifficultyLevel::Normal,
+            DifficultyLevel::Normal => DifficultyLevel::Hard,
+            DifficultyLevel::Hard => DifficultyLevel::Easy,
+        }
+    }
+}
+
+/// What a difficulty level multiplies.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DifficultyMultipliers {
+    /// The health enemies spawn with.
+    pub enemy_health: f32,
+    /// The damage players take.
+    pub enemy_damage: f32,
+    /// How many enemies each wave spawns, rounded, but never below one of each.
+    pub enemy_count: f32,
+    /// How many things enemies drop as they die, rounded.
+    pub enemy_drops: f32,
+}
+
+impl Default for DifficultyMultipliers {
+    fn default() -> Self {
+        DifficultyMultipliers {
+            enemy_health: 1.,
+            enemy_damage: 1.,
+            enemy_count: 1.,
+            enemy_drops: 1.,
+        }
+    }
+}
+
+impl DifficultyMultipliers {
+    /// How many of a `count` things an enemy drops are dropped.
+    pub fn drops(&self, count: usize) -> usize {
+        (count as f32 * self.enemy_drops).round() as usize
+    }
+}
+
+/// The multipliers of each level, read from `difficulty.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DifficultyConfig {
+    pub easy: DifficultyMultipliers,
+    pub normal: DifficultyMultipliers,
+    pub hard: DifficultyMultipliers,
+}
+
+impl Default for DifficultyConfig {
+    fn default() -> Self {
+        DifficultyConfig {
+            easy: DifficultyMultipliers {
+                enemy_health: 0.75,
+                enemy_damage: 0.5,
+                enemy_count: 0.75,
+                enemy_drops: 1.5,
+            },
+            normal: DifficultyMultipliers::default(),
+            hard: DifficultyMultipliers {
+                enemy_health: 1.5,
+                enemy_damage: 1.5,
+                enemy_count: 1.5,
+                enemy_drops: 0.75,
+            },
+        }
+    }
+}
+
+/// The level games are played at, with the multipliers of each.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Difficulty {
+    pub level: DifficultyLevel,
+    config: DifficultyConfig,
+}
+
+impl Difficulty {
+    pub fn new(config: DifficultyConfig) -> Self {
+        Difficulty {
+            level: DifficultyLevel::default(),
+            config,
+        }
+    }
+
+    /// The multipliers of the current level.
+    pub fn multipliers(&self) -> DifficultyMultipliers {
+        match self.level {
+            DifficultyLevel::Easy => self.config.easy,
+            DifficultyLevel::Normal => self.config.normal,
+            DifficultyLevel::Hard => self.config.hard,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_are_rounded_and_may_run_out() {
+        let config = DifficultyConfig::default();
+        assert_eq!(config.easy.drops(3), 5);
+        assert_eq!(config.normal.drops(3), 3);
+        assert_eq!(config.hard.drops(1), 1);
+        let none = DifficultyMultipliers {
+            enemy_drops: 0.,
+            ..DifficultyMultipliers::default()
+        };
+        assert_eq!(none.drops(3), 0);
+    }
+
+    #[test]
+    fn levels_cycle_from_the_easiest() {
+        assert_eq!(DifficultyLevel::Hard.next(), DifficultyLevel::Easy);
+        let mut difficulty = Difficulty::new(DifficultyConfig::default());
+        difficulty.level = DifficultyLevel::default().next();
+        assert_eq!(difficulty.multipliers().enemy_damage, 1.5);
+    }
+}
@@ -0,0 +1,148 @@
+//! Holding single entities still, for cutscenes, stuns and the like, while the rest of the game
+//! plays on.
+//!
+//! An entity with a `Disabled` is skipped by the gameplay systems that move it or have it act:
+//! it doesn't move, chase, follow a path, dash, attack or hurt anyone it touches, though it is
+//! still drawn and still blocks other movers. Unlike pausing, this only applies to the entities
+//! it is put on. A `Disabled` can be given a number of seconds of game time, after which the
+//! `DisabledSystem` takes it off again.
+
+use amethyst::ecs::prelude::{
+    Component, DenseVecStorage, Entities, Entity, Join, Read, System, WriteStorage,
+};
+use log::error;
+
+use crate::clock::GameClock;
+
+/// Keeps an entity from moving or acting, as the module describes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Disabled {
+    /// Seconds of game time until the entity is enabled again, or `None` to stay disabled until
+    /// `enable` is called.
+    pub remaining: Option<f32>,
+}
+
+impl Component for Disabled {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Disables `entity` until it is enabled again.
+pub fn disable(disabled: &mut WriteStorage<'_, Disabled>, entity: Entity) {
+    insert(disabled, entity, Disabled { remaining: None });
+}
+
+/// Disables `entity` for `seconds` of game time. An entity already disabled for longer, or
+/// until enabled, stays so.
+pub fn disable_for(disabled: &mut WriteStorage<'_, Disabled>, entity: Entity, seconds: f32) {
+    let remaining = match disabled.get(entity) {
+        Some(Disabled { remaining: None }) => None,
+        Some(Disabled {
+            remaining: Some(left),
+        }) => Some(left.max(seconds)),
+        None => Some(seconds),
+    };
+    insert(disabled, entity, Disabled { remaining });
+}
+
+/// Lets `entity` move and act again.
+pub fn enable(disabled: &mut WriteStorage<'_, Disabled>, entity: Entity) {
+    disabled.remove(entity);
+}
+
+fn insert(disabled: &mut WriteStorage<'_, Disabled>, entity: Entity, component: Disabled) {
+    if let Err(err) = disabled.insert(entity, component) {
+        error!("Failed to disable {:?}: {}", entity, err);
+    }
+}
+
+/// Enables entities again once the time they were disabled for is up.
+pub struct DisabledSystem;
+
+impl<'s> System<'s> for DisabledSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Disabled>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(&mut self, (entities, mut disabled, clock): Self::SystemData) {
+        let delta = clock.delta_seconds();
+        let mut expired = Vec::new();
+        for (entity, disabled) in (&entities, &mut disabled).join() {
+            if let Some(remaining) = &mut disabled.remaining {
+                *remaining -= delta;
+                if *remaining <= 0. {
+                    expired.push(entity);
+                }
+            }
+        }
+        for entity in expired {
+            enable(&mut disabled, entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        core::{math::Vector2, Transform},
+        ecs::prelude::{Builder, RunNow, World},
+    };
+
+    use super::*;
+    use crate::movement::{world_position, MovementSystem, Velocity};
+
+    fn x(world: &World, entity: Entity) -> f32 {
+        world_position(world.read_storage().get(entity).unwrap()).x
+    }
+
+    #[test]
+    fn disabled_movers_stand_still() {
+        let mut world = World::new();
+        let mut system = MovementSystem;
+        System::setup(&mut system, &mut world.res);
+        let spawn = |world: &mut World| {
+            world
+                .create_entity()
+                .with(Transform::default())
+                .with(Velocity(Vector2::new(10., 0.)))
+                .build()
+        };
+        let held = spawn(&mut world);
+        let free = spawn(&mut world);
+        disable(&mut world.write_storage(), held);
+
+        world.write_resource::<GameClock>().advance(1.);
+        system.run_now(&world.res);
+        assert_eq!(x(&world, held), 0.);
+        assert_eq!(x(&world, free), 10.);
+
+        enable(&mut world.write_storage(), held);
+        system.run_now(&world.res);
+        assert_eq!(x(&world, held), 10.);
+    }
+
+    #[test]
+    fn timed_disables_keep_the_longer_time_and_run_out() {
+        let mut world = World::new();
+        let mut system = DisabledSystem;
+        System::setup(&mut system, &mut world.res);
+        let timed = world.create_entity().build();
+        let held = world.create_entity().build();
+        {
+            let mut disabled = world.write_storage();
+            disable_for(&mut disabled, timed, 2.);
+            disable_for(&mut disabled, timed, 1.);
+            disable(&mut disabled, held);
+            disable_for(&mut disabled, held, 1.);
+        }
+        let disabled_after = |world: &mut World, system: &mut DisabledSystem| {
+            world.write_resource::<GameClock>().advance(1.5);
+            system.run_now(&world.res);
+            let disabled = world.read_storage::<Disabled>();
+            (disabled.contains(timed), disabled.contains(held))
+        };
+        assert_eq!(disabled_after(&mut world, &mut system), (true, true));
+        assert_eq!(disabled_after(&mut world, &mut system), (false, true));
+    }
+}
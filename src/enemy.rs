@@ -0,0 +1,172 @@
+//! Enemies placed by the map.
+
+use amethyst::{
+    assets::Handle,
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{Builder, Component, Entity, NullStorage, World},
+    renderer::{SpriteRender, SpriteSheet, Transparent},
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{Animated, SpriteAnimation},
+    boss::Boss,
+    chase::Chaser,
+    collision::Collider,
+    combat::{Health, Resistances},
+    death::{DeathEffect, OnDeath},
+    difficulty::Difficulty,
+    highlight::HighlightPulse,
+    hitbox::{Hitbox, Hurtbox},
+    iso_sort::IsoSorted,
+    lighting::Lit,
+    limits::{EntityKind, SpawnOrder},
+    movement::Velocity,
+    shadow::Shadow,
+    sleep::NeverSleep,
+    spawn::enemy_spawn_position,
+    tile_map::TileMap,
+};
+
+/// Marks an entity the players fight.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Enemy;
+
+impl Component for Enemy {
+    type Storage = NullStorage<Self>;
+}
+
+/// Where the map places an enemy, and how it starts out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EnemySpawn {
+    pub x: f32,
+    pub y: f32,
+    /// The blueprint in `blueprints.ron` the enemy is made from. Whatever it gives is used over
+    /// what the spawn gives.
+    #[serde(default)]
+    pub blueprint: Option<String>,
+    /// Needed unless the blueprint gives it.
+    #[serde(default)]
+    pub health: f32,
+    /// Sprite from the map's sprite sheet.
+    #[serde(default)]
+    pub sprite: usize,
+    /// What happens where the enemy dies. Without any, it still despawns.
+    #[serde(default)]
+    pub on_death: Vec<DeathEffect>,
+    /// Makes the enemy a boss, shown on the boss bar while it is fought.
+    #[serde(default)]
+    pub boss: Option<Boss>,
+    /// Whether the enemy chases players that come into sight, instead of standing still.
+    #[serde(default)]
+    pub chases: bool,
+    /// How much of each type of damage the enemy shrugs off. Without any, it takes every hit in
+    /// full.
+    #[serde(default)]
+    pub resistances: Option<Resistances>,
+    /// A pulse drawing the eye to the enemy, such as one the players are sent to defeat.
+    #[serde(default)]
+    pub highlight: Option<HighlightPulse>,
+    /// Plays in place of `sprite`, with any events marked on its frames.
+    #[serde(default)]
+    pub animation: Option<SpriteAnimation>,
+    /// Where the enemy's attack lands, on the frames of `animation` its events mark.
+    #[serde(default)]
+    pub hitbox: Option<Hitbox>,
+}
+
+impl EnemySpawn {
+    /// The health the enemy spawns with at `difficulty`.
+    pub fn starting_health(&self, difficulty: &Difficulty) -> Health {
+        Health::new(self.health * difficulty.multipliers().enemy_health)
+    }
+}
+
+/// Creates an entity for every enemy the map places, moving any placed in a wall or on top of
+/// another collider to the nearest spot with room, and leaving out any with no room nearby.
+/// Returns the enemies.
+pub fn spawn_enemies(
+    world: &mut World,
+    spawns: &[EnemySpawn],
+    sprite_sheet: Handle<SpriteSheet>,
+) -> Vec<Entity> {
+    let difficulty = *world.read_resource::<Difficulty>();
+    let origin = world.read_resource::<TileMap>().origin();
+    let mut enemies = Vec::with_capacity(spawns.len());
+    for spawn in spawns {
+        let collider = Collider::new(32., 32.);
+        let desired = Vector2::new(spawn.x, spawn.y) + origin;
+        let position = match enemy_spawn_position(world, desired, &collider) {
+            Some(position) => position,
+            None => {
+                warn!(
+                    "No room to spawn an enemy near ({}, {}), leaving it out",
+                    desired.x, desired.y
+                );
+                continue;
+            }
+        };
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(position.x, position.y, 0.);
+        let limited = world.write_resource::<SpawnOrder>().next(EntityKind::Enemy);
+        let mut enemy = world
+            .create_entity()
+            .with(transform)
+            .with(SpriteRender {
+                sprite_sheet: sprite_sheet.clone(),
+                sprite_number: spawn.sprite,
+            })
+            .with(Transparent)
+            .with(Enemy)
+            .with(spawn.starting_health(&difficulty))
+            .with(collider)
+            .with(Hurtbox::new(32., 32.))
+            .with(Shadow::default())
+            .with(IsoSorted::default())
+            .with(Lit)
+            .with(OnDeath {
+                effects: spawn.on_death.clone(),
+            })
+            .with(limited);
+        if spawn.chases {
+            enemy = enemy.with(Chaser::default()).with(Velocity::default());
+        }
+        if let Some(highlight) = spawn.highlight {
+            enemy = enemy.with(highlight);
+        }
+        if let Some(resistances) = spawn.resistances {
+            enemy = enemy.with(resistances);
+        }
+        // Bosses are fought from far off, and are few enough to keep awake.
+        if let Some(boss) = &spawn.boss {
+            enemy = enemy.with(boss.clone()).with(NeverSleep);
+        }
+        if let Some(animation) = &spawn.animation {
+            enemy = enemy.with(Animated::new(animation.clone()));
+        }
+        if let Some(hitbox) = &spawn.hitbox {
+            enemy = enemy.with(hitbox.clone());
+        }
+        enemies.push(enemy.build());
+    }
+    enemies
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::difficulty::{DifficultyConfig, DifficultyLevel};
+
+    use super::*;
+
+    #[test]
+    fn enemy_health_scales_by_the_difficulty() {
+        let spawn: EnemySpawn = ron::de::from_str("(x: 0.0, y: 0.0, health: 20.0)").unwrap();
+        let mut difficulty = Difficulty::new(DifficultyConfig::default());
+        assert_eq!(spawn.starting_health(&difficulty).current, 20.);
+        difficulty.level = DifficultyLevel::Hard;
+        assert_eq!(spawn.starting_health(&difficulty).current, 30.);
+        difficulty.level = DifficultyLevel::Easy;
+        assert_eq!(spawn.starting_health(&difficulty).max, 15.);
+    }
+}
@@ -0,0 +1,249 @@
+//! Footstep sounds for players on the move, picked by the terrain underfoot.
+//!
+//! Each player takes a step every `stride` world units they walk, and the map's `terrain` says
+//! which sound the tile they are on makes. Steps vary a little in loudness, drawn from an `Rng`
+//! of their own so they replay identically without touching the gameplay one. The audio output
+//! can only change how loud a sound plays, not its pitch, so loudness is all that varies.
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    audio::Source,
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Join, Read, ReadExpect, ReadStorage, System, Write,
+        WriteStorage,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    movement::world_position,
+    navigation::NavGrid,
+    player::Player,
+    rng::Rng,
+    sound::{queue_sound_at, SoundQueue},
+    tile_map::{Tile, TileMap},
+};
+
+/// Seed of the footsteps' own `Rng`.
+const FOOTSTEP_SEED: u64 = 0x0053_5445_5053;
+
+/// What a tile is made of, as far as walking on it sounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Terrain {
+    Grass,
+    Stone,
+    Water,
+}
+
+/// The tiles of a map made of `terrain`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TerrainTiles {
+    pub terrain: Terrain,
+    pub tiles: Vec<Tile>,
+}
+
+/// The sound of each terrain and how often players step, read from `footsteps.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FootstepConfig {
+    pub enabled: bool,
+    /// World units walked between steps.
+    pub stride: f32,
+    /// From `0` for silent to `1` for as loud as the sounds were recorded.
+    pub volume: f32,
+    /// How much quieter or louder than `volume` a step may be, as a fraction of it.
+    pub volume_variance: f32,
+    /// Sounds relative to the `resources` directory, with none for silent steps.
+    pub grass: Option<String>,
+    pub stone: Option<String>,
+    pub water: Option<String>,
+    /// The sound of tiles of no terrain in particular.
+    pub default: Option<String>,
+}
+
+impl Default for FootstepConfig {
+    fn default() -> Self {
+        FootstepConfig {
+            enabled: true,
+            stride: 24.,
+            volume: 0.6,
+            volume_variance: 0.15,
+            grass: None,
+            stone: None,
+            water: None,
+            default: None,
+        }
+    }
+}
+
+impl FootstepConfig {
+    /// The sound of stepping on `terrain`, or on no terrain in particular without one.
+    pub fn sound(&self, terrain: Option<Terrain>) -> Option<&str> {
+        match terrain {
+            Some(Terrain::Grass) => self.grass.as_deref(),
+            Some(Terrain::Stone) => self.stone.as_deref(),
+            Some(Terrain::Water) => self.water.as_deref(),
+            None => self.default.as_deref(),
+        }
+    }
+
+    /// The sound of stepping at `position` on `map`.
+    pub fn sound_at(&self, map: &TileMap, grid: &NavGrid, position: Vector2<f32>) -> Option<&str> {
+        self.sound(grid.tile_at(position).and_then(|coord| map.terrain(coord)))
+    }
+}
+
+/// How far a player has walked since their last step.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Footsteps {
+    /// Where the player was last step of the game.
+    last: Option<Vector2<f32>>,
+    travelled: f32,
+}
+
+impl Component for Footsteps {
+    type Storage = DenseVecStorage<Self>;
+}
+
+impl Footsteps {
+    /// Moves where the player was last step by `offset`, so moving them with it isn't a stride.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        if let Some(last) = &mut self.last {
+            *last += offset;
+        }
+    }
+
+    /// Walks `distance` further, returning whether that takes a step. Standing still starts
+    /// the next stride over, so a player stopping short of a step never takes it.
+    pub fn walk(&mut self, distance: f32, stride: f32) -> bool {
+        if distance <= 0. || stride <= 0. {
+            self.travelled = 0.;
+            return false;
+        }
+        self.travelled += distance;
+        if self.travelled >= stride {
+            self.travelled %= stride;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Plays a footstep for every stride a player walks. Runs after the `MovementSystem`.
+pub struct FootstepSystem {
+    rng: Rng,
+}
+
+impl Default for FootstepSystem {
+    fn default() -> Self {
+        FootstepSystem {
+            rng: Rng::new(FOOTSTEP_SEED),
+        }
+    }
+}
+
+impl<'s> System<'s> for FootstepSystem {
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Transform>,
+        WriteStorage<'s, Footsteps>,
+        Read<'s, TileMap>,
+        Read<'s, NavGrid>,
+        Read<'s, FootstepConfig>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<Source>>,
+        Write<'s, SoundQueue>,
+    );
+
+    fn run(
+        &mut self,
+        (players, transforms, mut footsteps, map, nav_grid, config, loader, sources, mut queue): Self::SystemData,
+    ) {
+        for (_, transform, steps) in (&players, &transforms, &mut footsteps).join() {
+            let position = world_position(transform);
+            let distance = steps
+                .last
+                .replace(position)
+                .map_or(0., |last| (position - last).norm());
+            if !steps.walk(distance, config.stride) || !config.enabled {
+                continue;
+            }
+            if let Some(sound) = config.sound_at(&map, &nav_grid, position) {
+                let variance = config.volume_variance.abs();
+                let volume = config.volume * (1. + self.rng.range(-variance, variance));
+                queue_sound_at(
+                    &loader,
+                    &sources,
+                    &mut queue,
+                    sound,
+                    volume.clamp(0., 1.),
+                    position,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_step_is_taken_every_stride_with_the_sound_underfoot() {
+        // Grass, stone and then a tile of no terrain, left to right.
+        let map = TileMap {
+            width: 3,
+            height: 1,
+            tile_size: 32.,
+            tiles: vec![Tile::Static(0), Tile::Static(1), Tile::Static(2)],
+            terrain: vec![
+                TerrainTiles {
+                    terrain: Terrain::Grass,
+                    tiles: vec![Tile::Static(0)],
+                },
+                TerrainTiles {
+                    terrain: Terrain::Stone,
+                    tiles: vec![Tile::Static(1)],
+                },
+            ],
+            ..TileMap::default()
+        };
+        let grid = NavGrid::from_map(&map);
+        let config = FootstepConfig {
+            stride: 24.,
+            grass: Some("audio/grass.ogg".to_string()),
+            stone: Some("audio/stone.ogg".to_string()),
+            default: Some("audio/step.ogg".to_string()),
+            ..FootstepConfig::default()
+        };
+
+        // Walking 4 units a step of the game from one end of the map to the other.
+        let mut footsteps = Footsteps::default();
+        let mut steps = Vec::new();
+        for x in 1..24 {
+            let position = Vector2::new(x as f32 * 4., 16.);
+            if footsteps.walk(4., config.stride) {
+                steps.push((position.x, config.sound_at(&map, &grid, position)));
+            }
+        }
+        assert_eq!(
+            steps,
+            [
+                (24., Some("audio/grass.ogg")),
+                (48., Some("audio/stone.ogg")),
+                (72., Some("audio/step.ogg")),
+            ]
+        );
+    }
+
+    #[test]
+    fn stopping_short_of_a_stride_starts_it_over() {
+        let mut footsteps = Footsteps::default();
+        assert!(!footsteps.walk(20., 24.));
+        assert!(!footsteps.walk(0., 24.));
+        assert!(!footsteps.walk(20., 24.));
+        assert!(footsteps.walk(4., 24.));
+    }
+}
@@ -0,0 +1,371 @@
+//! Checking what a scene looks like against a reference picture, to catch rendering
+//! regressions.
+//!
+//! With `frame_check.ron` enabled, the game skips the menus, hides its window and plays the
+//! level from `seed` with gameplay held by `FrameStep`. The `FrameCheckSystem` lets exactly
+//! `ticks` `GameClock` steps run, so the scene is the same however fast frames come, waits
+//! `settle_frames` for the camera to come to rest, and then reads a frame back through the same
+//! `FrameReadbackDesc` node recordings use. The frame is compared with the scene's reference
+//! picture in `directory`, pixel by pixel within `tolerance`, and the game quits, exiting with
+//! an error if too many pixels differ or there is no reference yet. A failed check saves what
+//! was drawn and a picture of the differing pixels next to the reference. With `update` set,
+//! the frame is saved as the new reference instead.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use amethyst::ecs::prelude::{Read, System, Write};
+use image::{Rgba, RgbaImage};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{FrameStep, GameClock},
+    recording::{FrameCapture, RecordedFrame},
+};
+
+/// Which scene is checked and how closely, read from `frame_check.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FrameCheckConfig {
+    pub enabled: bool,
+    /// Saves the frame as the scene's reference instead of checking it.
+    pub update: bool,
+    /// The name of the scene, which its reference picture is saved under.
+    pub scene: String,
+    /// The gameplay seed the scene is played from.
+    pub seed: u64,
+    /// Where reference pictures are kept, relative to the game's folder.
+    pub directory: PathBuf,
+    /// Gameplay steps run before the frame is taken.
+    pub ticks: u64,
+    /// Frames drawn after the last step before the frame is taken.
+    pub settle_frames: u32,
+    /// The largest the frame may be, in pixels, as for recordings.
+    pub max_width: u32,
+    pub max_height: u32,
+    /// How far each colour channel of a pixel may be from the reference and still match.
+    pub tolerance: u8,
+    /// The fraction of pixels that may differ before the check fails.
+    pub max_mismatched: f32,
+}
+
+impl Default for FrameCheckConfig {
+    fn default() -> Self {
+        FrameCheckConfig {
+            enabled: false,
+            update: false,
+            scene: "sample".to_string(),
+            seed: 0x5eed,
+            directory: PathBuf::from("references"),
+            ticks: 30,
+            settle_frames: 60,
+            max_width: 320,
+            max_height: 240,
+            tolerance: 8,
+            max_mismatched: 0.001,
+        }
+    }
+}
+
+/// How a frame differs from its reference.
+#[derive(Clone, Debug)]
+pub struct FrameDiff {
+    pub mismatched: usize,
+    pub pixels: usize,
+    /// The differing pixels in red over a faded copy of the reference.
+    pub picture: RgbaImage,
+}
+
+impl FrameDiff {
+    /// The fraction of pixels that differ.
+    pub fn fraction(&self) -> f32 {
+        if self.pixels == 0 {
+            return 0.;
+        }
+        self.mismatched as f32 / self.pixels as f32
+    }
+}
+
+/// `frame` as a picture.
+fn picture(frame: &RecordedFrame) -> Option<RgbaImage> {
+    RgbaImage::from_raw(frame.width, frame.height, frame.pixels.clone())
+}
+
+/// Compares `frame` with `reference`, each channel within `tolerance`. Frames of another size
+/// than the reference differ everywhere.
+pub fn compare(reference: &RgbaImage, frame: &RgbaImage, tolerance: u8) -> FrameDiff {
+    let pixels = (reference.width() * reference.height()) as usize;
+    let mut picture = RgbaImage::new(reference.width(), reference.height());
+    if reference.dimensions() != frame.dimensions() {
+        for pixel in picture.pixels_mut() {
+            *pixel = Rgba {
+                data: [255, 0, 0, 255],
+            };
+        }
+        return FrameDiff {
+            mismatched: pixels.max(1),
+            pixels: pixels.max(1),
+            picture,
+        };
+    }
+
+    let mut mismatched = 0;
+    for (x, y, expected) in reference.enumerate_pixels() {
+        let drawn = frame.get_pixel(x, y);
+        let matches = expected
+            .data
+            .iter()
+            .zip(&drawn.data)
+            .all(|(&a, &b)| (i16::from(a) - i16::from(b)).abs() <= i16::from(tolerance));
+        picture.put_pixel(
+            x,
+            y,
+            if matches {
+                let faded = |channel: u8| channel / 4;
+                Rgba {
+                    data: [
+                        faded(expected.data[0]),
+                        faded(expected.data[1]),
+                        faded(expected.data[2]),
+                        255,
+                    ],
+                }
+            } else {
+                mismatched += 1;
+                Rgba {
+                    data: [255, 0, 0, 255],
+                }
+            },
+        );
+    }
+    FrameDiff {
+        mismatched,
+        pixels,
+        picture,
+    }
+}
+
+/// Where the check has got to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    /// Not yet holding gameplay.
+    Waiting,
+    /// Letting the steps run.
+    Stepping,
+    /// Drawing frames with gameplay held, this many more until the frame is taken.
+    Settling(u32),
+    /// Waiting for the frame to be read back.
+    Capturing,
+    Finished,
+}
+
+/// Whether a frame check is running and how it went. Clones share whether it failed, so `main`
+/// can tell once the game has quit.
+#[derive(Clone, Debug, Default)]
+pub struct FrameCheck {
+    enabled: bool,
+    seed: u64,
+    finished: bool,
+    failed: Arc<AtomicBool>,
+}
+
+impl FrameCheck {
+    pub fn new(config: &FrameCheckConfig) -> Self {
+        FrameCheck {
+            enabled: config.enabled,
+            seed: config.seed,
+            ..FrameCheck::default()
+        }
+    }
+
+    /// Whether the game is being played for a frame check.
+    pub fn is_running(&self) -> bool {
+        self.enabled
+    }
+
+    /// The seed the checked scene is played from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Whether the check is done, and the game can quit.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    fn finish(&mut self, passed: bool) {
+        self.finished = true;
+        self.failed.store(!passed, Ordering::SeqCst);
+    }
+}
+
+/// Plays the scene up to the checked frame and checks it, as `FrameCheckConfig` says.
+pub struct FrameCheckSystem {
+    config: FrameCheckConfig,
+    directory: PathBuf,
+    stage: Stage,
+}
+
+impl FrameCheckSystem {
+    /// Keeps references in `root` joined with the configured directory.
+    pub fn new(config: FrameCheckConfig, root: &Path) -> Self {
+        FrameCheckSystem {
+            directory: root.join(&config.directory),
+            config,
+            stage: Stage::Waiting,
+        }
+    }
+
+    fn reference_path(&self, suffix: &str) -> PathBuf {
+        self.directory
+            .join(format!("{}{}.png", self.config.scene, suffix))
+    }
+
+    /// Saves `frame` as the reference, or checks it against the one saved. Returns whether it
+    /// passed.
+    fn check(&self, frame: &RecordedFrame) -> Result<bool, failure::Error> {
+        let drawn = picture(frame)
+            .ok_or_else(|| failure::format_err!("The frame read back is cut short"))?;
+        let reference_path = self.reference_path("");
+        if self.config.update {
+            fs::create_dir_all(&self.directory)?;
+            drawn.save(&reference_path)?;
+            info!("Saved the reference for {}", self.config.scene);
+            return Ok(true);
+        }
+
+        if !reference_path.exists() {
+            // Kept for a look before it is saved as the reference with `update`.
+            drawn.save(self.reference_path(".actual"))?;
+            failure::bail!(
+                "There is no reference at {}; the frame drawn is saved next to where it goes, \
+                 and `update` saves it as the reference",
+                reference_path.display()
+            );
+        }
+        let reference = image::open(&reference_path)?.to_rgba();
+        let diff = compare(&reference, &drawn, self.config.tolerance);
+        if diff.fraction() <= self.config.max_mismatched {
+            info!(
+                "{} matches its reference, {} of {} pixels differing",
+                self.config.scene, diff.mismatched, diff.pixels
+            );
+            return Ok(true);
+        }
+        drawn.save(self.reference_path(".actual"))?;
+        diff.picture.save(self.reference_path(".diff"))?;
+        error!(
+            "{} differs from its reference in {} of {} pixels, saved next to it",
+            self.config.scene, diff.mismatched, diff.pixels
+        );
+        Ok(false)
+    }
+}
+
+impl<'s> System<'s> for FrameCheckSystem {
+    type SystemData = (
+        Write<'s, FrameCheck>,
+        Write<'s, FrameStep>,
+        Write<'s, FrameCapture>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(&mut self, (mut check, mut frame_step, mut capture, clock): Self::SystemData) {
+        if !self.config.enabled {
+            return;
+        }
+        match self.stage {
+            Stage::Waiting => {
+                if !frame_step.is_paused() {
+                    frame_step.toggle();
+                }
+                for _ in clock.ticks()..self.config.ticks {
+                    frame_step.request_step();
+                }
+                self.stage = Stage::Stepping;
+            }
+            Stage::Stepping => {
+                if clock.ticks() >= self.config.ticks {
+                    self.stage = Stage::Settling(self.config.settle_frames);
+                }
+            }
+            Stage::Settling(0) => {
+                capture.frame = None;
+                capture.wanted = true;
+                self.stage = Stage::Capturing;
+            }
+            Stage::Settling(frames) => self.stage = Stage::Settling(frames - 1),
+            Stage::Capturing => {
+                if let Some(frame) = capture.frame.take() {
+                    let passed = self.check(&frame).unwrap_or_else(|err| {
+                        error!("Frame check of {} failed: {}", self.config.scene, err);
+                        false
+                    });
+                    check.finish(passed);
+                    self.stage = Stage::Finished;
+                }
+            }
+            Stage::Finished => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(width: u32, height: u32, data: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba { data })
+    }
+
+    #[test]
+    fn identical_frames_match() {
+        let reference = filled(4, 3, [10, 20, 30, 255]);
+        let diff = compare(&reference, &reference.clone(), 0);
+        assert_eq!(diff.mismatched, 0);
+        assert_eq!(diff.pixels, 12);
+        assert_eq!(diff.fraction(), 0.);
+    }
+
+    #[test]
+    fn only_pixels_beyond_the_tolerance_differ() {
+        let reference = filled(4, 4, [100, 100, 100, 255]);
+        let mut frame = reference.clone();
+        frame.put_pixel(
+            0,
+            0,
+            Rgba {
+                data: [108, 100, 100, 255],
+            },
+        );
+        frame.put_pixel(
+            1,
+            0,
+            Rgba {
+                data: [100, 91, 100, 255],
+            },
+        );
+        let diff = compare(&reference, &frame, 8);
+        assert_eq!(diff.mismatched, 1);
+        assert_eq!(diff.picture.get_pixel(1, 0).data, [255, 0, 0, 255]);
+        assert_eq!(diff.picture.get_pixel(0, 0).data, [25, 25, 25, 255]);
+    }
+
+    #[test]
+    fn frames_of_another_size_differ_everywhere() {
+        let reference = filled(4, 4, [0, 0, 0, 255]);
+        let diff = compare(&reference, &filled(2, 2, [0, 0, 0, 255]), 255);
+        assert_eq!(diff.fraction(), 1.);
+    }
+}
@@ -0,0 +1,245 @@
+//! Cheats for testing, each toggled on its own.
+//!
+//! `god_mode.ron` sets which are on at the start, and the `toggle_god_mode`,
+//! `toggle_infinite_resources` and `toggle_no_clip` actions flip them while playing. The debug
+//! overlay lists whichever are on.
+//!
+//! No-clip also lets the players fly about faster, to explore maps quickly. Passing through
+//! walls, they can leave the map: the camera bounds still hold the view within it, and tile
+//! lookups off the map find nothing. When no-clip is turned off, players inside a wall or off
+//! the map are put on the nearest open tile, so nobody is left stuck.
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{Join, Read, ReadExpect, ReadStorage, System, Write, WriteStorage},
+    input::{InputHandler, StringBindings},
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abilities::{AutoAttack, Dash},
+    collision::{Aabb, Collider},
+    debug_overlay::DebugOverlay,
+    movement::world_position,
+    navigation::NavGrid,
+    player::Player,
+    strings::Strings,
+};
+
+/// Which cheats are on, read from `god_mode.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GodMode {
+    /// Players take no damage.
+    pub invulnerable: bool,
+    /// Players' abilities are never cooling down.
+    pub infinite_resources: bool,
+    /// Players move through walls and static colliders.
+    pub no_clip: bool,
+    /// How many times faster than walking players move with no-clip on.
+    pub no_clip_speed: f32,
+}
+
+impl Default for GodMode {
+    fn default() -> Self {
+        GodMode {
+            invulnerable: false,
+            infinite_resources: false,
+            no_clip: false,
+            no_clip_speed: 2.,
+        }
+    }
+}
+
+impl GodMode {
+    /// The cheats that are on, named as in `god_mode.ron`.
+    fn active(&self) -> Vec<&'static str> {
+        let mut active = Vec::new();
+        if self.invulnerable {
+            active.push("invulnerable");
+        }
+        if self.infinite_resources {
+            active.push("infinite_resources");
+        }
+        if self.no_clip {
+            active.push("no_clip");
+        }
+        active
+    }
+}
+
+/// Flips each cheat on its action, and lists those on in the debug overlay.
+#[derive(Default)]
+pub struct GodModeToggleSystem {
+    was_pressed: [bool; 3],
+}
+
+impl<'s> System<'s> for GodModeToggleSystem {
+    type SystemData = (
+        Write<'s, GodMode>,
+        Write<'s, DebugOverlay>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, Strings>,
+    );
+
+    fn run(&mut self, (mut god_mode, mut overlay, input, strings): Self::SystemData) {
+        let actions = [
+            "toggle_god_mode",
+            "toggle_infinite_resources",
+            "toggle_no_clip",
+        ];
+        let mut changed = false;
+        for (cheat, &action) in actions.iter().enumerate() {
+            let pressed = input.action_is_down(action).unwrap_or(false);
+            if pressed && !self.was_pressed[cheat] {
+                let enabled = match cheat {
+                    0 => &mut god_mode.invulnerable,
+                    1 => &mut god_mode.infinite_resources,
+                    _ => &mut god_mode.no_clip,
+                };
+                *enabled = !*enabled;
+                changed = true;
+            }
+            self.was_pressed[cheat] = pressed;
+        }
+
+        let active = god_mode.active();
+        if changed {
+            info!("God mode: {:?}", active);
+        }
+        if active.is_empty() {
+            overlay.remove("god_mode");
+        } else {
+            let active: Vec<String> = active
+                .iter()
+                .map(|cheat| strings.get(&format!("god_mode.{}", cheat)))
+                .collect();
+            overlay.set(
+                "god_mode",
+                strings.format("god_mode.active", &[&active.join(", ")]),
+            );
+        }
+    }
+}
+
+/// Where to put a mover taking up `area` for it to be clear of walls: the centre of the
+/// nearest open tile if `area` is off the map or over one that isn't, or `None` if it is
+/// clear already or the map has no open tile.
+pub fn reentry(grid: &NavGrid, area: &Aabb) -> Option<Vector2<f32>> {
+    let center = (area.min + area.max) / 2.;
+    if grid.tile_at(center).is_some() && grid.is_open(area) {
+        return None;
+    }
+    grid.nearest_walkable(grid.clamped_tile_at(center))
+        .map(|tile| grid.tile_center(tile))
+}
+
+/// Keeps the players' abilities ready while resources are infinite, and puts players back on
+/// open ground as no-clip is turned off. Runs before the abilities and movement.
+#[derive(Default)]
+pub struct GodModeSystem {
+    was_no_clip: bool,
+}
+
+impl<'s> System<'s> for GodModeSystem {
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Collider>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Dash>,
+        WriteStorage<'s, AutoAttack>,
+        Read<'s, NavGrid>,
+        Read<'s, GodMode>,
+    );
+
+    fn run(
+        &mut self,
+        (players, colliders, mut transforms, mut dashes, mut attacks, grid, god_mode): Self::SystemData,
+    ) {
+        if self.was_no_clip && !god_mode.no_clip {
+            for (_, collider, transform) in (&players, &colliders, &mut transforms).join() {
+                let area = Aabb::from_center(world_position(transform), collider.half_extents);
+                if let Some(open) = reentry(&grid, &area) {
+                    transform.set_translation_x(open.x);
+                    transform.set_translation_y(open.y);
+                }
+            }
+        }
+        self.was_no_clip = god_mode.no_clip;
+
+        if !god_mode.infinite_resources {
+            return;
+        }
+        for (_, dash) in (&players, &mut dashes).join() {
+            dash.cooldown.reset();
+        }
+        for (_, attack) in (&players, &mut attacks).join() {
+            attack.cooldown.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+    use crate::tile_map::{Tile, TileMap};
+
+    /// A row of one open tile and two walls, sixteen units across.
+    fn grid() -> NavGrid {
+        let (floor, wall) = (Tile::Static(0), Tile::Static(1));
+        NavGrid::from_map(&TileMap {
+            width: 3,
+            height: 1,
+            tile_size: 16.,
+            tiles: vec![floor, wall, wall],
+            solid: vec![wall],
+            ..TileMap::default()
+        })
+    }
+
+    fn area(x: f32) -> Aabb {
+        Aabb::from_center(Vector2::new(x, 8.), Vector2::new(4., 4.))
+    }
+
+    #[test]
+    fn movers_in_walls_or_off_the_map_are_put_on_the_nearest_open_tile() {
+        let grid = grid();
+        let open = Some(Vector2::new(8., 8.));
+        assert_eq!(reentry(&grid, &area(40.)), open);
+        assert_eq!(reentry(&grid, &area(-20.)), open);
+        // Only just over the wall's edge is still in it.
+        assert_eq!(reentry(&grid, &area(14.)), open);
+        assert_eq!(reentry(&grid, &area(8.)), None);
+    }
+
+    #[test]
+    fn turning_no_clip_off_in_a_wall_puts_players_back_on_open_ground() {
+        let mut world = World::new();
+        let mut system = GodModeSystem::default();
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(grid());
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(40., 8., 0.);
+        let player = world
+            .create_entity()
+            .with(Player {
+                index: 0,
+                speed: 1.,
+            })
+            .with(Collider::new(8., 8.))
+            .with(transform)
+            .build();
+        let position =
+            |world: &World| world_position(world.read_storage::<Transform>().get(player).unwrap());
+
+        world.write_resource::<GodMode>().no_clip = true;
+        system.run_now(&world.res);
+        assert_eq!(position(&world), Vector2::new(40., 8.));
+        world.write_resource::<GodMode>().no_clip = false;
+        system.run_now(&world.res);
+        assert_eq!(position(&world), Vector2::new(8., 8.));
+    }
+}
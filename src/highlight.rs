@@ -0,0 +1,303 @@
+//! Gentle pulsing highlights, drawing the eye to objectives and the like.
+//!
+//! A sprite with a `HighlightPulse` brightens and swells a little and settles back, over and
+//! over, in game time, so it holds still while gameplay is paused. `highlight.ron` says whether
+//! the pulse brightens the sprite's tint, grows its scale, or both. The pulse only changes how
+//! the sprite is drawn: colliders keep their own size, so the swelling never makes the entity
+//! any easier to hit or harder to pass. A pulse switched off leaves the sprite as it was.
+//!
+//! The swell is the sprite's alone. Its children, such as the parts attached to a character,
+//! would grow along with it and drift out from it through their parent's transform, so each is
+//! given the swell's undoing, shrunk and drawn in as much as the parent grows, and is drawn just
+//! where and as big as it would be without the pulse.
+
+use std::collections::BTreeMap;
+
+use amethyst::{
+    core::{math::Vector3, transform::Transform, Parent},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System, WriteStorage,
+    },
+    renderer::{palette::Srgba, resources::Tint},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::GameClock;
+
+/// What a pulse changes, read from `highlight.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    /// Whether the pulse brightens the sprite's tint.
+    pub brightness: bool,
+    /// Whether the pulse grows the sprite.
+    pub scale: bool,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            brightness: true,
+            scale: true,
+        }
+    }
+}
+
+/// Pulses an entity's sprite, as the module describes.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct HighlightPulse {
+    /// Pulses per second.
+    pub speed: f32,
+    /// How much brighter and bigger the sprite is at the height of a pulse, as a fraction.
+    pub amount: f32,
+    #[serde(default = "HighlightPulse::default_enabled")]
+    pub enabled: bool,
+    /// The tint and scale the pulse was last drawn from, and what it drew, so the sprite can
+    /// be put back and a tint set by something else since is pulsed from instead.
+    #[serde(skip)]
+    drawn: Option<Drawn>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Drawn {
+    base_tint: Option<Srgba>,
+    tint: Srgba,
+    base_scale: Vector3<f32>,
+    scale: Vector3<f32>,
+}
+
+impl HighlightPulse {
+    fn default_enabled() -> bool {
+        true
+    }
+}
+
+impl Component for HighlightPulse {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The child of a pulsing sprite, with its transform as it was before the swell was undone on
+/// it and as it was drawn since, so it can be put back and a change made by something else
+/// since is kept.
+#[derive(Clone, Copy, Debug)]
+pub struct PulsedChild {
+    base: (Vector3<f32>, Vector3<f32>),
+    drawn: (Vector3<f32>, Vector3<f32>),
+}
+
+impl Component for PulsedChild {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The translation and scale of a child at `translation` and `scale` from a parent swollen by
+/// `swell`, undoing the swell so the child is drawn as it would be without it.
+pub fn unswollen(
+    translation: Vector3<f32>,
+    scale: Vector3<f32>,
+    swell: f32,
+) -> (Vector3<f32>, Vector3<f32>) {
+    (translation / swell, scale / swell)
+}
+
+/// How far into a pulse `speed` pulses a second are at `elapsed` seconds, from `0` at rest to
+/// `1` at the height of the pulse.
+pub fn pulse(elapsed: f64, speed: f32) -> f32 {
+    let phase = (elapsed * f64::from(speed)).fract();
+    ((1. - (phase * std::f64::consts::TAU).cos()) / 2.) as f32
+}
+
+/// `color` brightened by `factor`, keeping its alpha.
+fn brightened(color: Srgba, factor: f32) -> Srgba {
+    Srgba::new(
+        color.red * factor,
+        color.green * factor,
+        color.blue * factor,
+        color.alpha,
+    )
+}
+
+/// Pulses every `HighlightPulse` sprite, and undoes the swell on their children. Runs after
+/// systems setting tints, so it pulses the tint they set this frame.
+pub struct HighlightPulseSystem {
+    config: HighlightConfig,
+}
+
+impl HighlightPulseSystem {
+    pub fn new(config: HighlightConfig) -> Self {
+        HighlightPulseSystem { config }
+    }
+}
+
+impl<'s> System<'s> for HighlightPulseSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, HighlightPulse>,
+        WriteStorage<'s, Tint>,
+        WriteStorage<'s, Transform>,
+        ReadStorage<'s, Parent>,
+        WriteStorage<'s, PulsedChild>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut pulses, mut tints, mut transforms, parents, mut children, clock): Self::SystemData,
+    ) {
+        let elapsed = clock.elapsed_seconds();
+        let mut swells: BTreeMap<Entity, f32> = BTreeMap::new();
+        for (entity, pulse_of, transform) in (&entities, &mut pulses, &mut transforms).join() {
+            let current_tint = tints.get(entity).map(|tint| tint.0);
+            let current_scale = transform.scale().map(|value| value.as_f32());
+            // Whatever changed the sprite since the pulse last drew it is pulsed from instead.
+            let (base_tint, base_scale) = match pulse_of.drawn {
+                Some(drawn) => (
+                    if current_tint == Some(drawn.tint) {
+                        drawn.base_tint
+                    } else {
+                        current_tint
+                    },
+                    if current_scale == drawn.scale {
+                        drawn.base_scale
+                    } else {
+                        current_scale
+                    },
+                ),
+                None => (current_tint, current_scale),
+            };
+
+            if !pulse_of.enabled {
+                if pulse_of.drawn.take().is_some() {
+                    match base_tint {
+                        Some(tint) => {
+                            tints.insert(entity, Tint(tint)).expect("Entity is alive");
+                        }
+                        None => {
+                            tints.remove(entity);
+                        }
+                    }
+                    transform.set_scale(base_scale);
+                }
+                continue;
+            }
+
+            let factor = 1. + pulse_of.amount * pulse(elapsed, pulse_of.speed);
+            let white = Srgba::new(1., 1., 1., 1.);
+            let tint = if self.config.brightness {
+                brightened(base_tint.unwrap_or(white), factor)
+            } else {
+                base_tint.unwrap_or(white)
+            };
+            let scale = if self.config.scale {
+                swells.insert(entity, factor);
+                base_scale * factor
+            } else {
+                base_scale
+            };
+            if self.config.brightness {
+                tints.insert(entity, Tint(tint)).expect("Entity is alive");
+            }
+            transform.set_scale(scale);
+            pulse_of.drawn = Some(Drawn {
+                base_tint,
+                tint,
+                base_scale,
+                scale,
+            });
+        }
+
+        for (entity, parent, transform) in (&entities, &parents, &mut transforms).join() {
+            let current = (
+                transform.translation().map(|value| value.as_f32()),
+                transform.scale().map(|value| value.as_f32()),
+            );
+            let base = match children.get(entity) {
+                Some(child) if child.drawn == current => child.base,
+                Some(_) => current,
+                None if swells.contains_key(&parent.entity) => current,
+                None => continue,
+            };
+            let drawn = match swells.get(&parent.entity) {
+                Some(&swell) => unswollen(base.0, base.1, swell),
+                None => base,
+            };
+            transform.set_translation(drawn.0);
+            transform.set_scale(drawn.1);
+            if swells.contains_key(&parent.entity) {
+                children
+                    .insert(entity, PulsedChild { base, drawn })
+                    .expect("Entity is alive");
+            } else {
+                children.remove(entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+
+    fn vector(transform: &Transform) -> (Vector3<f32>, Vector3<f32>) {
+        (
+            transform.translation().map(|value| value.as_f32()),
+            transform.scale().map(|value| value.as_f32()),
+        )
+    }
+
+    #[test]
+    fn pulses_peak_halfway_through() {
+        assert_eq!(pulse(0., 2.), 0.);
+        assert!((pulse(0.25, 2.) - 1.).abs() < 1e-6);
+        assert!(pulse(0.5, 2.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn children_are_drawn_as_without_the_swell() {
+        let mut world = World::new();
+        let mut system = HighlightPulseSystem::new(HighlightConfig::default());
+        System::setup(&mut system, &mut world.res);
+        world.write_resource::<GameClock>().advance(0.5);
+
+        let pulsing = world
+            .create_entity()
+            .with(Transform::default())
+            .with(HighlightPulse {
+                speed: 1.,
+                amount: 0.25,
+                enabled: true,
+                drawn: None,
+            })
+            .build();
+        let mut offset = Transform::default();
+        offset.set_translation_xyz(4., 2., 0.01);
+        let child = world
+            .create_entity()
+            .with(offset.clone())
+            .with(Parent { entity: pulsing })
+            .build();
+
+        system.run_now(&world.res);
+        {
+            let transforms = world.read_storage::<Transform>();
+            let (_, parent_scale) = vector(transforms.get(pulsing).unwrap());
+            let (translation, scale) = vector(transforms.get(child).unwrap());
+            assert!((parent_scale.x - 1.25).abs() < 1e-5);
+            // What the parent's scale passes on comes back to the child's own offset and size.
+            assert!((translation.x * parent_scale.x - 4.).abs() < 1e-5);
+            assert!((translation.y * parent_scale.y - 2.).abs() < 1e-5);
+            assert!((scale.x * parent_scale.x - 1.).abs() < 1e-5);
+        }
+
+        world
+            .write_storage::<HighlightPulse>()
+            .get_mut(pulsing)
+            .unwrap()
+            .enabled = false;
+        system.run_now(&world.res);
+        let transforms = world.read_storage::<Transform>();
+        assert_eq!(vector(transforms.get(child).unwrap()), vector(&offset));
+        assert!(world.read_storage::<PulsedChild>().get(child).is_none());
+    }
+}
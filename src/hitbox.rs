@@ -0,0 +1,365 @@
+//! Attack volumes and the volumes they can land on, kept apart from the `Collider` that
+//! entities move and bump with.
+//!
+//! A `Hurtbox` is where an entity can be hit. A `Hitbox` is where an attack of its entity
+//! lands, and is only out while its animation is swinging: from the frame marked with its
+//! `start` event up to the one marked with its `end`, as the entity's `Animated` sends them.
+//! Each swing hits every hurtbox it reaches once, however long it stays over it, and the next
+//! swing can hit them all again. Hitboxes land on entities of the other side, players on
+//! anything else and everything else on players, and never on their own entity. The hitboxes
+//! of disabled and sleeping entities land on nothing.
+//!
+//! Boxes are placed from the entity's translation by their `offset`, in world units.
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, Resources, System,
+        SystemData, Write, WriteStorage,
+    },
+    shrev::{EventChannel, ReaderId},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::AnimationEvent,
+    collision::Aabb,
+    combat::{DamageEvent, DamageKind, DamageType},
+    death::Dead,
+    disabled::Disabled,
+    movement::world_position,
+    player::Player,
+    sleep::Asleep,
+};
+
+/// Where an entity can be hit by a `Hitbox`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Hurtbox {
+    pub size: (f32, f32),
+    #[serde(default)]
+    pub offset: (f32, f32),
+}
+
+impl Hurtbox {
+    pub fn new(width: f32, height: f32) -> Self {
+        Hurtbox {
+            size: (width, height),
+            offset: (0., 0.),
+        }
+    }
+
+    fn aabb(&self, position: Vector2<f32>) -> Aabb {
+        box_at(position, self.size, self.offset)
+    }
+}
+
+impl Component for Hurtbox {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Where an attack of the entity lands while it swings, and how hard.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Hitbox {
+    pub size: (f32, f32),
+    #[serde(default)]
+    pub offset: (f32, f32),
+    pub damage: f32,
+    #[serde(default)]
+    pub damage_type: DamageType,
+    /// The animation event a swing starts on.
+    pub start: String,
+    /// The animation event a swing ends on.
+    pub end: String,
+    #[serde(skip)]
+    active: bool,
+    /// The entities this swing has hit already.
+    #[serde(skip)]
+    hit: Vec<Entity>,
+}
+
+impl Hitbox {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Starts a swing, which can hit everything again.
+    fn start_swing(&mut self) {
+        self.active = true;
+        self.hit.clear();
+    }
+
+    fn end_swing(&mut self) {
+        self.active = false;
+    }
+
+    fn aabb(&self, position: Vector2<f32>) -> Aabb {
+        box_at(position, self.size, self.offset)
+    }
+
+    /// Whether this swing has yet to hit `target`, counting it hit from now on.
+    fn lands_on(&mut self, target: Entity) -> bool {
+        if self.hit.contains(&target) {
+            return false;
+        }
+        self.hit.push(target);
+        true
+    }
+}
+
+impl Component for Hitbox {
+    type Storage = DenseVecStorage<Self>;
+}
+
+fn box_at(position: Vector2<f32>, size: (f32, f32), offset: (f32, f32)) -> Aabb {
+    Aabb::from_center(
+        position + Vector2::new(offset.0, offset.1),
+        Vector2::new(size.0, size.1) / 2.,
+    )
+}
+
+/// Starts and ends swings on the animation events of their hitboxes, and deals the damage of
+/// every swing to the hurtboxes it reaches, as the module describes.
+#[derive(Default)]
+pub struct HitboxSystem {
+    reader: Option<ReaderId<AnimationEvent>>,
+}
+
+impl<'s> System<'s> for HitboxSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Hitbox>,
+        ReadStorage<'s, Hurtbox>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Dead>,
+        ReadStorage<'s, Disabled>,
+        ReadStorage<'s, Asleep>,
+        Read<'s, EventChannel<AnimationEvent>>,
+        Write<'s, EventChannel<DamageEvent>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut hitboxes,
+            hurtboxes,
+            transforms,
+            players,
+            dead,
+            disabled,
+            asleep,
+            events,
+            mut damage,
+        ): Self::SystemData,
+    ) {
+        // A swing starting and ending within one step still gets to land, where it is at the
+        // end of the step.
+        let mut swung = Vec::new();
+        let reader = self.reader.as_mut().expect("HitboxSystem is set up");
+        for event in events.read(reader) {
+            if let Some(hitbox) = hitboxes.get_mut(event.entity) {
+                if event.name == hitbox.start {
+                    hitbox.start_swing();
+                    swung.push(event.entity);
+                } else if event.name == hitbox.end {
+                    hitbox.end_swing();
+                }
+            }
+        }
+
+        let targets: Vec<_> = (&entities, &hurtboxes, &transforms, !&dead)
+            .join()
+            .map(|(entity, hurtbox, transform, _)| {
+                (
+                    entity,
+                    hurtbox.aabb(world_position(transform)),
+                    players.contains(entity),
+                )
+            })
+            .collect();
+        for (entity, hitbox, transform, _, _, _) in (
+            &entities,
+            &mut hitboxes,
+            &transforms,
+            !&dead,
+            !&disabled,
+            !&asleep,
+        )
+            .join()
+        {
+            if !hitbox.is_active() && !swung.contains(&entity) {
+                continue;
+            }
+            let reach = hitbox.aabb(world_position(transform));
+            let player = players.contains(entity);
+            for (target, hurtbox, target_player) in &targets {
+                if *target == entity || *target_player == player || !reach.overlaps(hurtbox) {
+                    continue;
+                }
+                if hitbox.lands_on(*target) {
+                    damage.single_write(DamageEvent {
+                        source: Some(entity),
+                        target: *target,
+                        amount: hitbox.damage,
+                        kind: DamageKind::Hitbox,
+                        damage_type: hitbox.damage_type,
+                    });
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<AnimationEvent>>()
+                .register_reader(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+
+    /// A world with the hitbox system set up, an enemy swinging `hitbox` and a player standing
+    /// in its reach.
+    fn arena(hitbox: Hitbox) -> (World, HitboxSystem, Entity, Entity) {
+        let mut world = World::new();
+        let mut system = HitboxSystem::default();
+        System::setup(&mut system, &mut world.res);
+        let enemy = world
+            .create_entity()
+            .with(hitbox)
+            .with(Transform::default())
+            .build();
+        let player = world
+            .create_entity()
+            .with(Hurtbox::new(16., 16.))
+            .with(Transform::default())
+            .with(Player {
+                index: 0,
+                speed: 0.,
+            })
+            .build();
+        (world, system, enemy, player)
+    }
+
+    fn send(world: &World, entity: Entity, name: &str) {
+        world
+            .write_resource::<EventChannel<AnimationEvent>>()
+            .single_write(AnimationEvent {
+                entity,
+                name: name.to_string(),
+            });
+    }
+
+    /// Runs a step, returning the damage dealt in it.
+    fn step(
+        world: &World,
+        system: &mut HitboxSystem,
+        reader: &mut ReaderId<DamageEvent>,
+    ) -> Vec<DamageEvent> {
+        system.run_now(&world.res);
+        world
+            .read_resource::<EventChannel<DamageEvent>>()
+            .read(reader)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn a_swing_lands_once_between_its_events() {
+        let (world, mut system, enemy, player) = arena(hitbox());
+        let mut reader = world
+            .write_resource::<EventChannel<DamageEvent>>()
+            .register_reader();
+        assert!(step(&world, &mut system, &mut reader).is_empty());
+
+        send(&world, enemy, "swing");
+        let hits = step(&world, &mut system, &mut reader);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].target, player);
+        assert!(step(&world, &mut system, &mut reader).is_empty());
+
+        send(&world, enemy, "recover");
+        assert!(step(&world, &mut system, &mut reader).is_empty());
+        send(&world, enemy, "swing");
+        assert_eq!(step(&world, &mut system, &mut reader).len(), 1);
+    }
+
+    #[test]
+    fn disabled_and_sleeping_hitboxes_land_on_nothing() {
+        let (mut world, mut system, enemy, _) = arena(hitbox());
+        world.register::<Disabled>();
+        world.register::<Asleep>();
+        let mut reader = world
+            .write_resource::<EventChannel<DamageEvent>>()
+            .register_reader();
+        world
+            .write_storage::<Disabled>()
+            .insert(enemy, Disabled::default())
+            .unwrap();
+        send(&world, enemy, "swing");
+        assert!(step(&world, &mut system, &mut reader).is_empty());
+
+        world.write_storage::<Disabled>().remove(enemy);
+        world
+            .write_storage::<Asleep>()
+            .insert(enemy, Asleep)
+            .unwrap();
+        send(&world, enemy, "swing");
+        assert!(step(&world, &mut system, &mut reader).is_empty());
+    }
+
+    fn hitbox() -> Hitbox {
+        Hitbox {
+            size: (16., 16.),
+            offset: (0., 0.),
+            damage: 1.,
+            damage_type: DamageType::default(),
+            start: "swing".to_string(),
+            end: "recover".to_string(),
+            active: false,
+            hit: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn hitboxes_are_out_only_while_swinging() {
+        let mut hitbox = hitbox();
+        assert!(!hitbox.is_active());
+        hitbox.start_swing();
+        assert!(hitbox.is_active());
+        hitbox.end_swing();
+        assert!(!hitbox.is_active());
+    }
+
+    #[test]
+    fn each_swing_hits_a_target_once() {
+        let mut world = World::new();
+        let (first, second) = (world.create_entity().build(), world.create_entity().build());
+        let mut hitbox = hitbox();
+        hitbox.start_swing();
+        assert!(hitbox.lands_on(first));
+        assert!(!hitbox.lands_on(first));
+        assert!(hitbox.lands_on(second));
+        hitbox.end_swing();
+        hitbox.start_swing();
+        assert!(hitbox.lands_on(first));
+    }
+
+    #[test]
+    fn boxes_are_placed_by_their_offset() {
+        let hurtbox = Hurtbox {
+            size: (10., 4.),
+            offset: (5., -2.),
+        };
+        let aabb = hurtbox.aabb(Vector2::new(100., 50.));
+        assert_eq!(aabb.min, Vector2::new(100., 46.));
+        assert_eq!(aabb.max, Vector2::new(110., 50.));
+    }
+}
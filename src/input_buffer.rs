@@ -0,0 +1,86 @@
+//! Remembering action presses for a moment, so a press just before an action can be taken
+//! still takes it as soon as it can.
+//!
+//! Without the buffer, pressing dash a fraction of a second before its cooldown runs out does
+//! nothing, and the press has to be timed again. With it, a press is kept for the `window`
+//! of `input_buffer.ron` and used by the first step in it the action is ready, once. Presses
+//! are timed in game time, so the window holds still while gameplay is paused.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// How long presses are remembered, read from `input_buffer.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct InputBufferConfig {
+    /// Seconds a press is kept for. `0` only takes presses as they happen.
+    pub window: f32,
+}
+
+impl Default for InputBufferConfig {
+    fn default() -> Self {
+        InputBufferConfig { window: 0.15 }
+    }
+}
+
+/// The last press of each action, by binding name, not yet used.
+#[derive(Clone, Debug, Default)]
+pub struct ActionBuffer {
+    held: HashMap<String, bool>,
+    pressed_at: HashMap<String, f64>,
+}
+
+impl ActionBuffer {
+    /// Notes whether `action` is held at `now`, in seconds, remembering when it went down.
+    pub fn update(&mut self, action: &str, held: bool, now: f64) {
+        let was_held = self.held.insert(action.to_string(), held).unwrap_or(false);
+        if held && !was_held {
+            self.pressed_at.insert(action.to_string(), now);
+        }
+    }
+
+    /// Whether `action` was pressed at most `window` seconds before `now`, using up the press
+    /// if so. Call once the action is ready to be taken.
+    pub fn consume(&mut self, action: &str, now: f64, window: f32) -> bool {
+        match self.pressed_at.get(action) {
+            Some(&pressed) if now - pressed <= f64::from(window) => {
+                self.pressed_at.remove(action);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presses_are_kept_for_the_window_and_used_once() {
+        let mut buffer = ActionBuffer::default();
+        buffer.update("dash", true, 1.);
+        assert!(buffer.consume("dash", 1.1, 0.15));
+        assert!(!buffer.consume("dash", 1.1, 0.15));
+    }
+
+    #[test]
+    fn old_presses_are_forgotten() {
+        let mut buffer = ActionBuffer::default();
+        buffer.update("dash", true, 1.);
+        assert!(!buffer.consume("dash", 1.2, 0.15));
+    }
+
+    #[test]
+    fn holding_an_action_presses_it_once() {
+        let mut buffer = ActionBuffer::default();
+        buffer.update("dash", true, 1.);
+        assert!(buffer.consume("dash", 1., 0.15));
+        buffer.update("dash", true, 1.1);
+        assert!(!buffer.consume("dash", 1.1, 0.15));
+        buffer.update("dash", false, 1.2);
+        buffer.update("dash", true, 1.3);
+        assert!(buffer.consume("dash", 1.3, 0.15));
+    }
+}
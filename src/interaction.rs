@@ -0,0 +1,232 @@
+//! Things in the world the players can use, such as signs, and the highlight showing which are
+//! within reach.
+//!
+//! The `InteractionSystem` checks every `Interactable` against each player's reach and marks
+//! those in range as `Highlighted`: only the closest to each player, or all of them, as
+//! `interaction.ron` picks. World text that is highlighted pulses towards the highlight colour.
+
+use amethyst::{
+    core::{timing::Time, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, NullStorage, Read, ReadStorage, System,
+        WriteStorage,
+    },
+    ui::UiText,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{movement::world_position, player::Player, world_text::WorldTextLabel};
+
+/// Which interactables in range are highlighted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum HighlightMode {
+    /// The closest to each player.
+    Closest,
+    All,
+}
+
+/// How far players reach and how what they can reach is shown, read from `interaction.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct InteractionConfig {
+    /// World units a player reaches, for interactables without a range of their own.
+    pub range: f32,
+    pub highlight: HighlightMode,
+    pub color: [f32; 4],
+    /// Pulses per second.
+    pub pulse_rate: f32,
+}
+
+impl Default for InteractionConfig {
+    fn default() -> Self {
+        InteractionConfig {
+            range: 48.,
+            highlight: HighlightMode::Closest,
+            color: [1., 0.85, 0.4, 1.],
+            pulse_rate: 1.5,
+        }
+    }
+}
+
+/// Something a player can use once in range.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Interactable {
+    /// World units a player has to be within, or the configured range without one.
+    pub range: Option<f32>,
+}
+
+impl Component for Interactable {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Marks an interactable a player is in range of.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Highlighted;
+
+impl Component for Highlighted {
+    type Storage = NullStorage<Self>;
+}
+
+/// How far into a pulse the highlight is after `seconds`, from `0` to `1` and back.
+pub fn pulse(seconds: f64, rate: f32) -> f32 {
+    let phase = (seconds * f64::from(rate)).fract() as f32;
+    0.5 - 0.5 * (phase * std::f32::consts::TAU).cos()
+}
+
+/// Highlights the interactables in the players' reach and clears those left, then pulses the
+/// text of highlighted world text. Runs after the `WorldTextSystem`, which resets their colour.
+pub struct InteractionSystem;
+
+impl<'s> System<'s> for InteractionSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Interactable>,
+        WriteStorage<'s, Highlighted>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, WorldTextLabel>,
+        WriteStorage<'s, UiText>,
+        Read<'s, InteractionConfig>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            interactables,
+            mut highlighted,
+            players,
+            transforms,
+            labels,
+            mut texts,
+            config,
+            time,
+        ): Self::SystemData,
+    ) {
+        let mut reached: Vec<Entity> = Vec::new();
+        for (_, transform) in (&players, &transforms).join() {
+            let origin = world_position(transform);
+            let in_range = (&entities, &interactables, &transforms).join().filter_map(
+                |(entity, interactable, transform)| {
+                    let distance = (world_position(transform) - origin).norm();
+                    let range = interactable.range.unwrap_or(config.range);
+                    if distance <= range {
+                        Some((entity, distance))
+                    } else {
+                        None
+                    }
+                },
+            );
+            match config.highlight {
+                HighlightMode::All => reached.extend(in_range.map(|(entity, _)| entity)),
+                HighlightMode::Closest => reached.extend(
+                    in_range
+                        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                        .map(|(entity, _)| entity),
+                ),
+            }
+        }
+        for (entity, _) in (&entities, &interactables).join() {
+            if reached.contains(&entity) {
+                if !highlighted.contains(entity) {
+                    highlighted
+                        .insert(entity, Highlighted)
+                        .expect("Interactable is alive");
+                }
+            } else {
+                highlighted.remove(entity);
+            }
+        }
+
+        let amount = pulse(time.absolute_time_seconds(), config.pulse_rate);
+        for (label, text) in (&labels, &mut texts).join() {
+            if !highlighted.contains(label.owner) {
+                continue;
+            }
+            for (channel, target) in text.color.iter_mut().zip(config.color.iter()) {
+                *channel += (target - *channel) * amount;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+
+    fn world(highlight: HighlightMode) -> (World, InteractionSystem) {
+        let mut world = World::new();
+        let mut system = InteractionSystem;
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(InteractionConfig {
+            range: 48.,
+            highlight,
+            ..InteractionConfig::default()
+        });
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(100., 100., 0.);
+        world
+            .create_entity()
+            .with(Player {
+                index: 0,
+                speed: 100.,
+            })
+            .with(transform)
+            .build();
+        (world, system)
+    }
+
+    fn spawn_at(world: &mut World, x: f32, range: Option<f32>) -> Entity {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(x, 100., 0.);
+        world
+            .create_entity()
+            .with(Interactable { range })
+            .with(transform)
+            .build()
+    }
+
+    fn highlighted(world: &World, entities: &[Entity]) -> Vec<bool> {
+        let highlighted = world.read_storage::<Highlighted>();
+        entities
+            .iter()
+            .map(|&entity| highlighted.contains(entity))
+            .collect()
+    }
+
+    #[test]
+    fn closest_highlights_only_the_nearest_within_range() {
+        let (mut world, mut system) = world(HighlightMode::Closest);
+        let farther = spawn_at(&mut world, 130., None);
+        let nearest = spawn_at(&mut world, 80., None);
+        let out_of_range = spawn_at(&mut world, 160., None);
+        let things = [farther, nearest, out_of_range];
+        system.run_now(&world.res);
+        assert_eq!(highlighted(&world, &things), [false, true, false]);
+
+        // Once the nearest is gone from reach the next closest takes the highlight.
+        world
+            .write_storage::<Transform>()
+            .get_mut(nearest)
+            .unwrap()
+            .set_translation_x(0.);
+        system.run_now(&world.res);
+        assert_eq!(highlighted(&world, &things), [true, false, false]);
+    }
+
+    #[test]
+    fn all_highlights_everything_within_range() {
+        let (mut world, mut system) = world(HighlightMode::All);
+        let near = spawn_at(&mut world, 130., None);
+        let far = spawn_at(&mut world, 160., None);
+        let long_reach = spawn_at(&mut world, 200., Some(120.));
+        system.run_now(&world.res);
+        assert_eq!(
+            highlighted(&world, &[near, far, long_reach]),
+            [true, false, true]
+        );
+    }
+}
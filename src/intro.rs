@@ -0,0 +1,320 @@
+//! A camera sweep over a level's key points when it starts, before the players take over.
+//!
+//! The map's `intro` lists the shots: where the camera looks, how long it pans there from the
+//! shot before and how long it holds. The first shot is cut to. The sweep plays through a
+//! camera of its own while gameplay holds still, and ends after the last shot or on any press,
+//! handing the view back. Games loaded from a save skip it.
+
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{Entities, Entity, Read, ReadExpect, System, Write, WriteStorage},
+    renderer::camera::{ActiveCamera, Camera},
+    window::ScreenDimensions,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    any_input::AnyInput,
+    camera::{centered_projection, smoothstep, CameraZoom},
+    split_screen::SplitScreen,
+};
+
+/// One point of the sweep.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IntroShot {
+    /// The world position the camera centres on.
+    pub position: (f32, f32),
+    pub zoom: f32,
+    /// Seconds the camera takes to pan here from the shot before.
+    pub pan: f32,
+    /// Seconds the camera holds here.
+    pub hold: f32,
+}
+
+impl Default for IntroShot {
+    fn default() -> Self {
+        IntroShot {
+            position: (0., 0.),
+            zoom: 1.,
+            pan: 1.,
+            hold: 1.5,
+        }
+    }
+}
+
+impl IntroShot {
+    fn center(&self) -> Vector2<f32> {
+        Vector2::new(self.position.0, self.position.1)
+    }
+}
+
+/// Where the camera centres and how far it zooms `elapsed` seconds into a sweep through
+/// `shots`, or `None` once the sweep is over.
+pub fn framing(shots: &[IntroShot], elapsed: f32) -> Option<(Vector2<f32>, f32)> {
+    let mut start = 0.;
+    let mut previous: Option<&IntroShot> = None;
+    for shot in shots {
+        let pan = if previous.is_some() {
+            shot.pan.max(0.)
+        } else {
+            0.
+        };
+        let end = start + pan + shot.hold.max(0.);
+        if elapsed < end {
+            let t = if pan > 0. {
+                smoothstep((elapsed - start) / pan)
+            } else {
+                1.
+            };
+            let (from, from_zoom) = previous.map_or((shot.center(), shot.zoom), |previous| {
+                (previous.center(), previous.zoom)
+            });
+            return Some((
+                from + (shot.center() - from) * t,
+                from_zoom + (shot.zoom - from_zoom) * t,
+            ));
+        }
+        start = end;
+        previous = Some(shot);
+    }
+    None
+}
+
+/// The sweep of the level being played. Gameplay holds still while it is `pending` or playing.
+#[derive(Clone, Debug, Default)]
+pub struct IntroSweep {
+    pub shots: Vec<IntroShot>,
+    /// Whether the sweep is still to start.
+    pub pending: bool,
+    pub playing: bool,
+}
+
+impl IntroSweep {
+    /// A sweep through `shots`, to start on the next frame.
+    pub fn new(shots: Vec<IntroShot>) -> Self {
+        IntroSweep {
+            pending: !shots.is_empty(),
+            shots,
+            playing: false,
+        }
+    }
+
+    pub fn is_holding(&self) -> bool {
+        self.pending || self.playing
+    }
+}
+
+/// A sweep in progress, and what to restore once it ends.
+struct Sweep {
+    camera: Entity,
+    elapsed: f32,
+    previous_camera: Option<Entity>,
+    previous_split_screen: bool,
+}
+
+/// Plays the `IntroSweep` and ends it after the last shot or on any press.
+#[derive(Default)]
+pub struct IntroSystem {
+    sweep: Option<Sweep>,
+}
+
+impl<'s> System<'s> for IntroSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, AnyInput>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Camera>,
+        WriteStorage<'s, CameraZoom>,
+        Write<'s, IntroSweep>,
+        Write<'s, ActiveCamera>,
+        Write<'s, SplitScreen>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            any_input,
+            mut transforms,
+            mut cameras,
+            mut zooms,
+            mut intro,
+            mut active_camera,
+            mut split_screen,
+            dimensions,
+            time,
+        ): Self::SystemData,
+    ) {
+        // A game left during its sweep takes the camera with it.
+        self.sweep = self
+            .sweep
+            .take()
+            .filter(|sweep| entities.is_alive(sweep.camera));
+        let skipped = any_input.pressed();
+
+        if intro.pending && !skipped {
+            info!("Playing the level's intro sweep");
+            let mut transform = Transform::default();
+            // Above everything the sweep passes over, like the game camera.
+            transform.set_translation_z(1.);
+            let screen = Vector2::new(dimensions.width(), dimensions.height());
+            let camera = entities
+                .build_entity()
+                .with(transform, &mut transforms)
+                .with(Camera::from(centered_projection(screen, 1.)), &mut cameras)
+                .with(CameraZoom::new(1., 0.1, 10.), &mut zooms)
+                .build();
+            self.sweep = Some(Sweep {
+                camera,
+                elapsed: 0.,
+                previous_camera: active_camera.entity.replace(camera),
+                previous_split_screen: split_screen.enabled,
+            });
+            split_screen.enabled = false;
+            intro.playing = true;
+        }
+        intro.pending = false;
+
+        let sweep = match self.sweep.as_mut() {
+            Some(sweep) => sweep,
+            None => return,
+        };
+        sweep.elapsed += time.delta_seconds();
+        match framing(&intro.shots, sweep.elapsed).filter(|_| !skipped) {
+            Some((center, level)) => {
+                if let Some(transform) = transforms.get_mut(sweep.camera) {
+                    transform.set_translation_x(center.x);
+                    transform.set_translation_y(center.y);
+                }
+                if let Some(zoom) = zooms.get_mut(sweep.camera) {
+                    zoom.set_level(level);
+                }
+            }
+            None => {
+                info!("Intro sweep over, handing over to the players");
+                entities
+                    .delete(sweep.camera)
+                    .expect("Intro camera is alive");
+                active_camera.entity = sweep.previous_camera;
+                split_screen.enabled = sweep.previous_split_screen;
+                intro.playing = false;
+                self.sweep = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        ecs::prelude::{Builder, RunNow, World},
+        input::{Button, InputEvent},
+        winit::VirtualKeyCode,
+    };
+
+    use super::*;
+
+    fn shots() -> Vec<IntroShot> {
+        vec![
+            IntroShot {
+                position: (0., 0.),
+                zoom: 1.,
+                pan: 5.,
+                hold: 1.,
+            },
+            IntroShot {
+                position: (100., 0.),
+                zoom: 2.,
+                pan: 2.,
+                hold: 1.,
+            },
+        ]
+    }
+
+    #[test]
+    fn the_first_shot_is_cut_to_and_the_next_panned_to() {
+        let shots = shots();
+        // The first shot's pan is ignored, so it holds from the start.
+        assert_eq!(framing(&shots, 0.), Some((Vector2::new(0., 0.), 1.)));
+        assert_eq!(framing(&shots, 0.9), Some((Vector2::new(0., 0.), 1.)));
+        // Halfway through the pan, which eases in and out.
+        assert_eq!(framing(&shots, 2.), Some((Vector2::new(50., 0.), 1.5)));
+        assert_eq!(framing(&shots, 3.), Some((Vector2::new(100., 0.), 2.)));
+        assert_eq!(framing(&shots, 3.9), Some((Vector2::new(100., 0.), 2.)));
+        assert_eq!(framing(&shots, 4.), None);
+        assert_eq!(framing(&[], 0.), None);
+    }
+
+    fn sweeping() -> (World, IntroSystem, Entity) {
+        let mut world = World::new();
+        let mut system = IntroSystem::default();
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(ScreenDimensions::new(640, 480, 1.));
+        world.add_resource(IntroSweep::new(shots()));
+        let game_camera = world.create_entity().build();
+        world.write_resource::<ActiveCamera>().entity = Some(game_camera);
+        (world, system, game_camera)
+    }
+
+    fn step(world: &mut World, system: &mut IntroSystem, pressed: bool) {
+        world.write_resource::<Time>().set_delta_seconds(0.5);
+        let events = if pressed {
+            vec![InputEvent::ButtonPressed(Button::Key(
+                VirtualKeyCode::Space,
+            ))]
+        } else {
+            Vec::new()
+        };
+        world
+            .write_resource::<AnyInput>()
+            .update(events.iter(), 0.5);
+        system.run_now(&world.res);
+        world.maintain();
+    }
+
+    #[test]
+    fn the_sweep_ends_after_the_last_shot() {
+        let (mut world, mut system, game_camera) = sweeping();
+        step(&mut world, &mut system, false);
+        assert!(world.read_resource::<IntroSweep>().playing);
+        let sweep_camera = world.read_resource::<ActiveCamera>().entity.unwrap();
+        assert_ne!(sweep_camera, game_camera);
+
+        for _ in 0..6 {
+            step(&mut world, &mut system, false);
+        }
+        assert!(world.read_resource::<IntroSweep>().playing);
+        step(&mut world, &mut system, false);
+        assert!(!world.read_resource::<IntroSweep>().is_holding());
+        assert_eq!(
+            world.read_resource::<ActiveCamera>().entity,
+            Some(game_camera)
+        );
+        assert!(!world.is_alive(sweep_camera));
+    }
+
+    #[test]
+    fn a_press_skips_the_sweep() {
+        let (mut world, mut system, game_camera) = sweeping();
+        step(&mut world, &mut system, false);
+        step(&mut world, &mut system, true);
+        assert!(!world.read_resource::<IntroSweep>().is_holding());
+        assert_eq!(
+            world.read_resource::<ActiveCamera>().entity,
+            Some(game_camera)
+        );
+
+        // Pressing before it starts skips it altogether.
+        let (mut world, mut system, game_camera) = sweeping();
+        step(&mut world, &mut system, true);
+        assert!(!world.read_resource::<IntroSweep>().is_holding());
+        assert_eq!(
+            world.read_resource::<ActiveCamera>().entity,
+            Some(game_camera)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Draw order for isometric maps.
+//!
+//! On an isometric map whatever stands nearer the bottom of the screen is in front, so each
+//! `IsoSorted` entity's depth is worked out from its place on the map and written to its `z`.
+//! The sprite sorting then draws them back to front like any other sprites. Entities on the
+//! same spot are kept a hair apart in depth by their ids, so they don't swap places from one
+//! frame to the next. Top-down maps leave every `z` as it was spawned.
+
+use std::cmp::Ordering;
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Join, Read, ReadStorage, System, WriteStorage,
+    },
+};
+
+use crate::{
+    movement::world_position,
+    tile_map::{MapProjection, TileMap},
+};
+
+/// How far forward and back the sorted sprites' depths reach, keeping them in front of the
+/// shadows and behind the overlays.
+const DEPTH_RANGE: f32 = 0.4;
+
+/// How much further back each later entity on the same sort key is drawn. Well below what a
+/// world unit of difference in keys moves the depth on any map that fits in memory.
+const TIE_STEP: f32 = 1e-6;
+
+/// An entity drawn in front of or behind the others by where it stands on an isometric map.
+#[derive(Clone, Copy, Debug)]
+pub struct IsoSorted {
+    /// Tiles taken up by the entity, in columns and rows, centred on its position. Entities
+    /// spanning several tiles sort by the corner of their footprint nearest the front.
+    pub footprint: Vector2<f32>,
+    /// How far, in world units, the entity stands above the ground. Raised entities draw in
+    /// front of those on the ground beneath them.
+    pub height: f32,
+}
+
+impl Default for IsoSorted {
+    fn default() -> Self {
+        IsoSorted {
+            footprint: Vector2::new(1., 1.),
+            height: 0.,
+        }
+    }
+}
+
+impl IsoSorted {
+    /// The entity's sort key at `position` on `map`: lower keys are nearer the front. This is
+    /// the height of the front corner of its footprint on screen, less its height above the
+    /// ground.
+    pub fn key(&self, map: &TileMap, position: Vector2<f32>) -> f32 {
+        let tile = map
+            .projection
+            .to_tile(position - map.origin(), map.tile_size);
+        let front = map
+            .projection
+            .to_world(tile - self.footprint / 2., map.tile_size);
+        front.y - self.height
+    }
+}
+
+impl Component for IsoSorted {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The depth for sort `key` on `map`, from 0 at the front of the map to `-DEPTH_RANGE` at the
+/// back. Keys off the map are kept to the ends of that range.
+pub fn iso_depth(map: &TileMap, key: f32) -> f32 {
+    // The front corner of tile (0, 0) is the lowest point of the map, the back corner of the
+    // opposite tile the highest.
+    let extent = map
+        .projection
+        .to_world(
+            Vector2::new(map.width as f32, map.height as f32),
+            map.tile_size,
+        )
+        .y;
+    if extent <= 0. {
+        return 0.;
+    }
+    -(key / extent).clamp(0., 1.) * DEPTH_RANGE
+}
+
+/// The depths for `keys`, each an entity id and its sort key, in the same order. Entities on
+/// the same key are drawn lowest id in front.
+pub fn iso_depths(map: &TileMap, keys: &[(u32, f32)]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|&a, &b| {
+        let ((a_id, a_key), (b_id, b_key)) = (keys[a], keys[b]);
+        a_key
+            .partial_cmp(&b_key)
+            .unwrap_or(Ordering::Equal)
+            .then(a_id.cmp(&b_id))
+    });
+    let mut depths = vec![0.; keys.len()];
+    let mut ties = 0;
+    for (rank, &index) in order.iter().enumerate() {
+        let key = keys[index].1;
+        ties = if rank > 0 && keys[order[rank - 1]].1 == key {
+            ties + 1
+        } else {
+            0
+        };
+        depths[index] = iso_depth(map, key) - ties as f32 * TIE_STEP;
+    }
+    depths
+}
+
+/// Moves every `IsoSorted` entity to the depth of where it stands on an isometric map.
+pub struct IsoSortSystem;
+
+impl<'s> System<'s> for IsoSortSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, IsoSorted>,
+        WriteStorage<'s, Transform>,
+        Read<'s, TileMap>,
+    );
+
+    fn run(&mut self, (entities, sorted, mut transforms, map): Self::SystemData) {
+        if map.projection != MapProjection::Isometric {
+            return;
+        }
+        let keys: Vec<_> = (&entities, &sorted, &transforms)
+            .join()
+            .map(|(entity, sorted, transform)| {
+                (entity.id(), sorted.key(&map, world_position(transform)))
+            })
+            .collect();
+        let depths = iso_depths(&map, &keys);
+        for ((_, transform), depth) in (&sorted, &mut transforms).join().zip(depths) {
+            transform.set_translation_z(depth);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, Entity, RunNow, World};
+
+    use super::*;
+
+    /// Sorts entities standing on the centres of `tiles` of a 10 by 10 isometric map, each
+    /// `height` above the ground, and returns their depths.
+    fn depths(placed: &[((usize, usize), f32)]) -> Vec<f32> {
+        let mut world = World::new();
+        let mut system = IsoSortSystem;
+        System::setup(&mut system, &mut world.res);
+        let map = TileMap {
+            width: 10,
+            height: 10,
+            tile_size: 32.,
+            projection: MapProjection::Isometric,
+            ..TileMap::default()
+        };
+        let entities: Vec<Entity> = placed
+            .iter()
+            .map(|&(tile, height)| {
+                let center = map.projection.tile_center(tile, map.tile_size);
+                let mut transform = Transform::default();
+                transform.set_translation_xyz(center.x, center.y, 0.);
+                world
+                    .create_entity()
+                    .with(transform)
+                    .with(IsoSorted {
+                        height,
+                        ..IsoSorted::default()
+                    })
+                    .build()
+            })
+            .collect();
+        world.add_resource(map);
+        system.run_now(&world.res);
+        let transforms = world.read_storage::<Transform>();
+        entities
+            .iter()
+            .map(|&entity| transforms.get(entity).unwrap().translation().z.as_f32())
+            .collect()
+    }
+
+    #[test]
+    fn nearer_the_bottom_of_the_screen_draws_in_front() {
+        let depths = depths(&[((1, 1), 0.), ((4, 4), 0.), ((4, 1), 0.), ((8, 8), 0.)]);
+        assert!(depths[0] > depths[2] && depths[2] > depths[1] && depths[1] > depths[3]);
+        assert!(depths
+            .iter()
+            .all(|depth| (-DEPTH_RANGE..=0.).contains(depth)));
+    }
+
+    #[test]
+    fn height_brings_an_entity_forward() {
+        let depths = depths(&[((4, 4), 0.), ((5, 5), 0.), ((5, 5), 20.), ((3, 3), 0.)]);
+        // Raised by more than one tile's step up the screen, but less than two.
+        assert!(depths[2] > depths[1] && depths[2] > depths[0]);
+        assert!(depths[3] > depths[2]);
+    }
+
+    #[test]
+    fn ties_go_to_the_earlier_entity_the_same_way_every_time() {
+        let placed = [((2, 3), 0.), ((2, 3), 0.), ((2, 3), 0.), ((1, 1), 0.)];
+        let first = depths(&placed);
+        assert!(first[0] > first[1] && first[1] > first[2]);
+        assert!(first[3] > first[0]);
+        assert_eq!(depths(&placed), first);
+    }
+}
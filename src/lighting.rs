@@ -0,0 +1,605 @@
+//! Light spread over the map's tiles, for lit dungeons.
+//!
+//! Each `LightSource` floods light out from the tile it is on, one tile to the next, losing the
+//! map's `falloff` with every step. Opaque tiles are lit themselves but stop the light going any
+//! further. Every tile is tinted by the ambient light plus all the light reaching it, and so is
+//! every `Lit` sprite by the light of the tile it stands on.
+//!
+//! With fog of war, only the tiles the players can see now are lit. Tiles they saw before but
+//! can't see now are drawn in the fog's dim memory colour, whatever the light on them, and
+//! tiles they never saw are black. `Lit` sprites are only drawn on tiles the players can see.
+//!
+//! With the map's `entity_shadows` on, entities that `BlockLight`, the players among them, stop
+//! the light like opaque tiles on every tile they stand on, casting shadows that move with
+//! them.
+//!
+//! Only sources that moved to another tile or changed their light are spread again, along with
+//! those whose light reached a tile an entity blocking light came onto or left, and sight
+//! is only worked out again when a player moves to another tile. Maps without lighting enabled
+//! leave everything at full brightness.
+
+use std::collections::{HashMap, VecDeque};
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Entity, Join, NullStorage, Read,
+        ReadStorage, System, World, Write, WriteStorage,
+    },
+    renderer::{palette::Srgba, resources::Tint},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::{Aabb, Collider},
+    movement::world_position,
+    navigation::{NavGrid, TileCoord},
+    player::Player,
+    sight::SightGrid,
+    tile_map::{MapProjection, MapTile, Tile, TileMap},
+};
+
+/// Light given off from an entity's tile.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LightSource {
+    /// Brightness on the source's own tile. The light reaches `intensity / falloff` tiles.
+    pub intensity: f32,
+    pub color: [f32; 3],
+}
+
+impl Component for LightSource {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Light given off by every tile of a kind, such as torches.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct TileLight {
+    pub tile: Tile,
+    pub light: LightSource,
+}
+
+/// The lighting of a level, as given in its tile map.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LightingConfig {
+    pub enabled: bool,
+    /// Light on every tile, whether or not any source reaches it.
+    pub ambient: [f32; 3],
+    /// Brightness lost with each tile light spreads to.
+    pub falloff: f32,
+    pub tile_lights: Vec<TileLight>,
+    pub fog_of_war: FogOfWar,
+    /// Whether entities that `BlockLight` cast shadows.
+    pub entity_shadows: bool,
+}
+
+impl Default for LightingConfig {
+    fn default() -> Self {
+        LightingConfig {
+            enabled: false,
+            ambient: [0.15, 0.15, 0.2],
+            falloff: 0.25,
+            tile_lights: Vec::new(),
+            fog_of_war: FogOfWar::default(),
+            entity_shadows: false,
+        }
+    }
+}
+
+/// Hides the map beyond what the players can see.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FogOfWar {
+    pub enabled: bool,
+    /// How far, in world units, a player sees.
+    pub sight_radius: f32,
+    /// Colour of the tiles seen before but out of sight now.
+    pub memory: [f32; 3],
+}
+
+impl Default for FogOfWar {
+    fn default() -> Self {
+        FogOfWar {
+            enabled: false,
+            sight_radius: 160.,
+            memory: [0.2, 0.2, 0.25],
+        }
+    }
+}
+
+/// Whether the players can see a tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileVisibility {
+    Unseen,
+    /// Seen before, but out of sight now.
+    Seen,
+    Visible,
+}
+
+/// Marks a sprite tinted by the light on its tile.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lit;
+
+impl Component for Lit {
+    type Storage = NullStorage<Self>;
+}
+
+/// Marks an entity that stops light on the tiles under its `Collider`, or under its position
+/// without one, while the map has `entity_shadows` on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlockLight;
+
+impl Component for BlockLight {
+    type Storage = NullStorage<Self>;
+}
+
+/// The light on each tile of the map.
+#[derive(Clone, Debug, Default)]
+pub struct LightGrid {
+    lighting: bool,
+    width: usize,
+    height: usize,
+    tile_size: f32,
+    projection: MapProjection,
+    /// Where the map's bottom-left corner is in the world.
+    origin: (f32, f32),
+    ambient: [f32; 3],
+    falloff: f32,
+    fog_of_war: FogOfWar,
+    entity_shadows: bool,
+    opaque: Vec<bool>,
+    /// The tiles entities blocking light stand on, by index.
+    blocked: Vec<bool>,
+    /// Light from the sources on each tile, not counting the ambient light.
+    light: Vec<[f32; 3]>,
+    /// What the players have seen of each tile. Every tile is visible without fog of war.
+    visibility: Vec<TileVisibility>,
+    /// Whether the sources' light has been spread over this grid. Grids rebuilt after a tile
+    /// edit start over, since walls may have come or gone.
+    spread: bool,
+}
+
+impl LightGrid {
+    fn origin(&self) -> Vector2<f32> {
+        Vector2::new(self.origin.0, self.origin.1)
+    }
+
+    pub fn from_map(map: &TileMap) -> Self {
+        let opaque = map.tile_mask(&map.opaque);
+        let fog_of_war = map.lighting.fog_of_war;
+        let visibility = if fog_of_war.enabled {
+            TileVisibility::Unseen
+        } else {
+            TileVisibility::Visible
+        };
+        LightGrid {
+            lighting: map.lighting.enabled,
+            width: map.width,
+            height: map.height,
+            tile_size: map.tile_size,
+            projection: map.projection,
+            origin: map.origin,
+            ambient: map.lighting.ambient,
+            falloff: map.lighting.falloff,
+            fog_of_war,
+            entity_shadows: map.lighting.entity_shadows,
+            opaque,
+            blocked: vec![false; map.width * map.height],
+            light: vec![[0.; 3]; map.width * map.height],
+            visibility: vec![visibility; map.width * map.height],
+            spread: false,
+        }
+    }
+
+    /// Rebuilds the grid for the edited `map`, keeping what the players have seen of it.
+    pub fn rebuild(&mut self, map: &TileMap) {
+        let visibility = std::mem::take(&mut self.visibility);
+        *self = LightGrid::from_map(map);
+        if visibility.len() == self.visibility.len() {
+            self.visibility = visibility;
+        }
+    }
+
+    fn index(&self, (column, row): TileCoord) -> Option<usize> {
+        if column < self.width && row < self.height {
+            Some(row * self.width + column)
+        } else {
+            None
+        }
+    }
+
+    /// The light `source` on the tile `from` spreads to each tile, by the tile's index. Tiles
+    /// are reached through their edges, so light goes around walls rather than through them.
+    pub fn spread(&self, from: TileCoord, source: &LightSource) -> Vec<(usize, [f32; 3])> {
+        let mut lit = Vec::new();
+        let start = match self.index(from) {
+            Some(start) => start,
+            None => return lit,
+        };
+        let mut reached = vec![false; self.light.len()];
+        reached[start] = true;
+        let mut queue = VecDeque::new();
+        queue.push_back((from, source.intensity));
+        while let Some(((column, row), brightness)) = queue.pop_front() {
+            let index = row * self.width + column;
+            lit.push((index, source.color.map(|channel| channel * brightness)));
+            // An opaque source still lights its surroundings.
+            if (self.opaque[index] || self.blocked[index]) && index != start {
+                continue;
+            }
+            let next = brightness - self.falloff;
+            if next <= 0. || self.falloff <= 0. {
+                continue;
+            }
+            let neighbours = [
+                column.checked_sub(1).map(|column| (column, row)),
+                Some((column + 1, row)),
+                row.checked_sub(1).map(|row| (column, row)),
+                Some((column, row + 1)),
+            ];
+            for tile in IntoIterator::into_iter(neighbours).flatten() {
+                if let Some(neighbour) = self.index(tile) {
+                    if !reached[neighbour] {
+                        reached[neighbour] = true;
+                        queue.push_back((tile, next));
+                    }
+                }
+            }
+        }
+        lit
+    }
+
+    /// Blocks the light on the tiles of `blocked`, by index, and on no others. Returns the
+    /// tiles that changed.
+    pub fn set_blocked(&mut self, blocked: &[usize]) -> Vec<usize> {
+        let mut now = vec![false; self.blocked.len()];
+        for &index in blocked {
+            if let Some(tile) = now.get_mut(index) {
+                *tile = true;
+            }
+        }
+        let changed = (0..now.len())
+            .filter(|&index| now[index] != self.blocked[index])
+            .collect();
+        self.blocked = now;
+        changed
+    }
+
+    /// The light on `tile`, each channel from 0 to 1. Off the map there is only the ambient
+    /// light, and without lighting everything is fully lit.
+    pub fn level(&self, tile: Option<TileCoord>) -> [f32; 3] {
+        if !self.lighting {
+            return [1.; 3];
+        }
+        let light = tile
+            .and_then(|tile| self.index(tile))
+            .map_or([0.; 3], |index| self.light[index]);
+        let mut level = self.ambient;
+        for (level, light) in level.iter_mut().zip(&light) {
+            *level = (*level + light).min(1.);
+        }
+        level
+    }
+
+    /// What the players have seen of `tile`. Off the map is never seen under fog of war.
+    pub fn visibility(&self, tile: Option<TileCoord>) -> TileVisibility {
+        match tile.and_then(|tile| self.index(tile)) {
+            Some(index) => self.visibility[index],
+            None if self.fog_of_war.enabled => TileVisibility::Unseen,
+            None => TileVisibility::Visible,
+        }
+    }
+
+    /// The colour `tile` is drawn in: black if it was never seen, the fog's memory colour if
+    /// it is out of sight, and the light on it if it is in sight.
+    pub fn shade(&self, tile: Option<TileCoord>) -> [f32; 3] {
+        match self.visibility(tile) {
+            TileVisibility::Unseen => [0.; 3],
+            TileVisibility::Seen => self.fog_of_war.memory,
+            TileVisibility::Visible => self.level(tile),
+        }
+    }
+
+    /// Works out which tiles can be seen from the centres of the `viewers`' tiles. Tiles
+    /// falling out of sight are remembered as seen.
+    pub fn reveal(&mut self, sight: &SightGrid, viewers: &[TileCoord]) {
+        if !self.fog_of_war.enabled {
+            return;
+        }
+        let eyes: Vec<Vector2<f32>> = viewers
+            .iter()
+            .map(|&viewer| self.projection.tile_center(viewer, self.tile_size) + self.origin())
+            .collect();
+        let radius = self.fog_of_war.sight_radius;
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let index = row * self.width + column;
+                let center =
+                    self.projection.tile_center((column, row), self.tile_size) + self.origin();
+                let in_sight = eyes.iter().any(|&eye| {
+                    (center - eye).norm() <= radius && sight.line_of_sight(eye, center)
+                });
+                let visibility = &mut self.visibility[index];
+                if in_sight {
+                    *visibility = TileVisibility::Visible;
+                } else if *visibility == TileVisibility::Visible {
+                    *visibility = TileVisibility::Seen;
+                }
+            }
+        }
+    }
+}
+
+/// Creates a `LightSource` on every tile of `map` its `TileLight`s give light to.
+pub fn spawn_tile_lights(world: &mut World, map: &TileMap) {
+    if !map.lighting.enabled {
+        return;
+    }
+    for row in 0..map.height {
+        for column in 0..map.width {
+            let light = map
+                .lighting
+                .tile_lights
+                .iter()
+                .find(|light| map.tile((column, row)) == Some(light.tile));
+            if let Some(light) = light {
+                let center =
+                    map.projection.tile_center((column, row), map.tile_size) + map.origin();
+                let mut transform = Transform::default();
+                transform.set_translation_xyz(center.x, center.y, 0.);
+                world
+                    .create_entity()
+                    .with(transform)
+                    .with(light.light)
+                    .build();
+            }
+        }
+    }
+}
+
+/// A source's light as it was last spread.
+struct Spread {
+    tile: TileCoord,
+    source: LightSource,
+    lit: Vec<(usize, [f32; 3])>,
+}
+
+/// Spreads the light of every `LightSource` that moved or changed over the `LightGrid`, works out
+/// what the players can see after they move, and tints the tiles and `Lit` sprites with both.
+#[derive(Default)]
+pub struct LightingSystem {
+    spreads: HashMap<Entity, Spread>,
+    /// The players' tiles when sight was last worked out.
+    viewers: Vec<TileCoord>,
+}
+
+impl<'s> System<'s> for LightingSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, LightSource>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, MapTile>,
+        ReadStorage<'s, Lit>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, BlockLight>,
+        ReadStorage<'s, Collider>,
+        WriteStorage<'s, Tint>,
+        Write<'s, LightGrid>,
+        Read<'s, NavGrid>,
+        Read<'s, SightGrid>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            sources,
+            transforms,
+            map_tiles,
+            lits,
+            players,
+            blockers,
+            colliders,
+            mut tints,
+            mut grid,
+            nav,
+            sight,
+        ): Self::SystemData,
+    ) {
+        if !grid.lighting && !grid.fog_of_war.enabled {
+            self.spreads.clear();
+            return;
+        }
+
+        if !grid.spread {
+            self.spreads.clear();
+            self.viewers.clear();
+        }
+        let before = self.spreads.len();
+        self.spreads
+            .retain(|&entity, _| entities.is_alive(entity) && sources.contains(entity));
+        let mut changed = self.spreads.len() != before || !grid.spread;
+
+        // Light that reached a tile something blocking it came onto or left is spread again.
+        // Blocked tiles are lit themselves, so any light they stop has reached them.
+        let mut blocked = Vec::new();
+        if grid.entity_shadows {
+            for (_, transform, collider) in (&blockers, &transforms, colliders.maybe()).join() {
+                let position = world_position(transform);
+                match collider {
+                    Some(collider) => blocked.extend(
+                        nav.tiles_under(&Aabb::from_center(position, collider.half_extents))
+                            .filter_map(|tile| grid.index(tile)),
+                    ),
+                    None => blocked.extend(nav.tile_at(position).and_then(|tile| grid.index(tile))),
+                }
+            }
+        }
+        let changed_tiles = grid.set_blocked(&blocked);
+        if !changed_tiles.is_empty() {
+            self.spreads.retain(|_, spread| {
+                !spread
+                    .lit
+                    .iter()
+                    .any(|(index, _)| changed_tiles.contains(index))
+            });
+            changed = true;
+        }
+
+        for (entity, source, transform) in (&entities, &sources, &transforms).join() {
+            let tile = match nav.tile_at(world_position(transform)) {
+                Some(tile) => tile,
+                None => {
+                    changed |= self.spreads.remove(&entity).is_some();
+                    continue;
+                }
+            };
+            let current = self.spreads.get(&entity);
+            if current.is_some_and(|spread| spread.tile == tile && spread.source == *source) {
+                continue;
+            }
+            let lit = grid.spread(tile, source);
+            self.spreads.insert(
+                entity,
+                Spread {
+                    tile,
+                    source: *source,
+                    lit,
+                },
+            );
+            changed = true;
+        }
+
+        let mut viewers: Vec<TileCoord> = (&players, &transforms)
+            .join()
+            .filter_map(|(_, transform)| nav.tile_at(world_position(transform)))
+            .collect();
+        viewers.sort_unstable();
+        if viewers != self.viewers || !grid.spread {
+            grid.reveal(&sight, &viewers);
+            self.viewers = viewers;
+            changed = true;
+        }
+
+        if changed {
+            let mut light = vec![[0.; 3]; grid.light.len()];
+            for spread in self.spreads.values() {
+                for &(index, color) in &spread.lit {
+                    for (total, channel) in light[index].iter_mut().zip(&color) {
+                        *total += channel;
+                    }
+                }
+            }
+            grid.light = light;
+            grid.spread = true;
+            for (entity, &MapTile(coord)) in (&entities, &map_tiles).join() {
+                tints
+                    .insert(entity, tint(grid.shade(Some(coord)), 1.))
+                    .expect("Tile is alive");
+            }
+        }
+
+        // Lit sprites move from tile to tile, so they are tinted every frame. Out of sight
+        // they are faded out altogether rather than left standing in the memory of the tile.
+        for (entity, _, transform) in (&entities, &lits, &transforms).join() {
+            let tile = nav.tile_at(world_position(transform));
+            let alpha = match grid.visibility(tile) {
+                TileVisibility::Visible => 1.,
+                TileVisibility::Seen | TileVisibility::Unseen => 0.,
+            };
+            tints
+                .insert(entity, tint(grid.level(tile), alpha))
+                .expect("Lit sprite is alive");
+        }
+    }
+}
+
+fn tint([r, g, b]: [f32; 3], alpha: f32) -> Tint {
+    Tint(Srgba::new(r, g, b, alpha))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row of four open tiles under fog of war, with a torch on the first and the players
+    /// standing on the second.
+    fn grid() -> (LightGrid, SightGrid) {
+        let floor = Tile::Static(0);
+        let mut map = TileMap {
+            width: 4,
+            height: 1,
+            tile_size: 16.,
+            tiles: vec![floor; 4],
+            ..TileMap::default()
+        };
+        map.lighting.enabled = true;
+        map.lighting.fog_of_war.enabled = true;
+        map.lighting.fog_of_war.sight_radius = 20.;
+        let mut grid = LightGrid::from_map(&map);
+        let torch = LightSource {
+            intensity: 0.5,
+            color: [1.; 3],
+        };
+        for (index, color) in grid.spread((0, 0), &torch) {
+            grid.light[index] = color;
+        }
+        let sight = SightGrid::from_map(&map);
+        grid.reveal(&sight, &[(1, 0)]);
+        (grid, sight)
+    }
+
+    #[test]
+    fn light_fades_with_each_tile_it_spreads_to() {
+        let (grid, _) = grid();
+        let levels: Vec<f32> = (0..4)
+            .map(|column| grid.level(Some((column, 0)))[0])
+            .collect();
+        assert_eq!(levels, [0.65, 0.4, 0.15, 0.15]);
+    }
+
+    #[test]
+    fn visible_tiles_are_shaded_by_their_light() {
+        let (grid, _) = grid();
+        let lit = grid.shade(Some((0, 0)));
+        let unlit = grid.shade(Some((2, 0)));
+        assert_eq!(grid.visibility(Some((2, 0))), TileVisibility::Visible);
+        assert_ne!(lit, unlit);
+        assert_eq!(unlit, grid.ambient);
+    }
+
+    #[test]
+    fn tiles_out_of_sight_are_remembered_or_black() {
+        let (mut grid, sight) = grid();
+        assert_eq!(grid.shade(Some((3, 0))), [0.; 3]);
+        grid.reveal(&sight, &[(3, 0)]);
+        // The torch's tile has gone out of sight, and is drawn from memory however lit.
+        assert_eq!(grid.visibility(Some((0, 0))), TileVisibility::Seen);
+        assert_eq!(grid.shade(Some((0, 0))), grid.fog_of_war.memory);
+        assert_eq!(grid.shade(None), [0.; 3]);
+    }
+
+    #[test]
+    fn entities_blocking_light_cast_shadows_that_move_with_them() {
+        let (mut grid, _) = grid();
+        let torch = LightSource {
+            intensity: 1.,
+            color: [1.; 3],
+        };
+        let reached = |grid: &LightGrid| -> Vec<usize> {
+            grid.spread((0, 0), &torch)
+                .iter()
+                .map(|&(index, _)| index)
+                .collect()
+        };
+        assert_eq!(reached(&grid), [0, 1, 2, 3]);
+
+        // Lit itself, the blocked tile stops the light going any further.
+        assert_eq!(grid.set_blocked(&[1]), [1]);
+        assert_eq!(reached(&grid), [0, 1]);
+
+        assert_eq!(grid.set_blocked(&[2]), [1, 2]);
+        assert_eq!(reached(&grid), [0, 1, 2]);
+        assert!(grid.set_blocked(&[2]).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! Caps on how many entities of a kind there may be, against runaway spawning.
+//!
+//! Spawners tag what they make with a `Limited` numbered in spawn order. Once a kind is over its
+//! cap the `EntityLimitSystem` despawns the oldest of that kind until it is back at the cap,
+//! through the same `Despawn` queue as everything else, so spawning never fails.
+
+use amethyst::ecs::prelude::{
+    Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System, WriteStorage,
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::death::Despawn;
+
+/// The kinds of entity with a cap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EntityKind {
+    Enemy,
+    /// Bodies left behind by enemies that died.
+    Corpse,
+}
+
+/// The most entities of each kind there may be, read from `entity_limits.ron`. Kinds without
+/// a cap may grow without bound.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EntityLimits {
+    pub enemies: Option<usize>,
+    pub corpses: Option<usize>,
+}
+
+impl Default for EntityLimits {
+    fn default() -> Self {
+        EntityLimits {
+            enemies: Some(200),
+            corpses: Some(50),
+        }
+    }
+}
+
+impl EntityLimits {
+    pub fn cap(&self, kind: EntityKind) -> Option<usize> {
+        match kind {
+            EntityKind::Enemy => self.enemies,
+            EntityKind::Corpse => self.corpses,
+        }
+    }
+}
+
+/// Counts spawns, to number each `Limited` entity in the order it was made.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpawnOrder(u64);
+
+impl SpawnOrder {
+    /// A `Limited` of `kind` for the entity spawning now.
+    pub fn next(&mut self, kind: EntityKind) -> Limited {
+        self.0 += 1;
+        Limited {
+            kind,
+            order: self.0,
+        }
+    }
+}
+
+/// An entity counted against the cap of its `kind`, the `order`th spawned.
+#[derive(Clone, Copy, Debug)]
+pub struct Limited {
+    pub kind: EntityKind,
+    pub order: u64,
+}
+
+impl Component for Limited {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The entities of `alive` to despawn to bring them down to `cap`: the oldest, by spawn order.
+pub fn over_cap(mut alive: Vec<(Entity, u64)>, cap: usize) -> Vec<Entity> {
+    if alive.len() <= cap {
+        return Vec::new();
+    }
+    alive.sort_by_key(|&(_, order)| order);
+    let excess = alive.len() - cap;
+    alive
+        .into_iter()
+        .take(excess)
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+/// Despawns the oldest entities of every kind over its cap. Runs before the `DespawnSystem`.
+pub struct EntityLimitSystem;
+
+impl<'s> System<'s> for EntityLimitSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Limited>,
+        WriteStorage<'s, Despawn>,
+        Read<'s, EntityLimits>,
+    );
+
+    fn run(&mut self, (entities, limited, mut despawns, limits): Self::SystemData) {
+        for &kind in &[EntityKind::Enemy, EntityKind::Corpse] {
+            let cap = match limits.cap(kind) {
+                Some(cap) => cap,
+                None => continue,
+            };
+            // Entities despawning this step don't count, but those lingering before they go do.
+            let alive: Vec<_> = (&entities, &limited)
+                .join()
+                .filter(|(entity, limited)| {
+                    limited.kind == kind
+                        && despawns
+                            .get(*entity)
+                            .is_none_or(|despawn| despawn.remaining > 0.)
+                })
+                .map(|(entity, limited)| (entity, limited.order))
+                .collect();
+            let count = alive.len();
+            let excess = over_cap(alive, cap);
+            if excess.is_empty() {
+                continue;
+            }
+            warn!(
+                "{} {:?} entities, over the cap of {}, despawning the oldest {}",
+                count,
+                kind,
+                cap,
+                excess.len()
+            );
+            for entity in excess {
+                despawns
+                    .insert(entity, Despawn { remaining: 0. })
+                    .expect("Limited entity is alive");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow, World};
+
+    use super::*;
+
+    fn despawning(world: &World) -> Vec<Entity> {
+        (&world.entities(), &world.read_storage::<Despawn>())
+            .join()
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    #[test]
+    fn the_oldest_over_the_cap_are_despawned() {
+        let mut world = World::new();
+        let mut system = EntityLimitSystem;
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(EntityLimits {
+            enemies: Some(3),
+            corpses: None,
+        });
+        // Spawned in the opposite order to their entities, so the last two made are oldest.
+        let mut order = SpawnOrder::default();
+        let mut limited: Vec<Limited> = (0..5).map(|_| order.next(EntityKind::Enemy)).collect();
+        limited.reverse();
+        let enemies: Vec<Entity> = limited
+            .into_iter()
+            .map(|limited| world.create_entity().with(limited).build())
+            .collect();
+        for _ in 0..10 {
+            world
+                .create_entity()
+                .with(order.next(EntityKind::Corpse))
+                .build();
+        }
+
+        system.run_now(&world.res);
+        assert_eq!(despawning(&world), [enemies[3], enemies[4]]);
+        // Those already on their way out aren't counted again.
+        system.run_now(&world.res);
+        assert_eq!(despawning(&world).len(), 2);
+    }
+
+    #[test]
+    fn kinds_at_their_cap_are_left_alone() {
+        let mut world = World::new();
+        let mut system = EntityLimitSystem;
+        System::setup(&mut system, &mut world.res);
+        let mut order = SpawnOrder::default();
+        let cap = EntityLimits::default().corpses.unwrap();
+        for _ in 0..cap {
+            world
+                .create_entity()
+                .with(order.next(EntityKind::Corpse))
+                .build();
+        }
+        system.run_now(&world.res);
+        assert!(despawning(&world).is_empty());
+    }
+}
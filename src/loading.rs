@@ -0,0 +1,487 @@
+//! The state each game starts in: loads the level and its sprite sheets, then warms them up
+//! before play begins.
+//!
+//! Everything declared in the level's `warmup` list, plus the main sprite sheet, is drawn for a
+//! few frames by near-invisible sprites. That way texture uploads and first-draw setup happen
+//! behind the loading screen instead of on the first frame they appear in game.
+//!
+//! The sprite sheets named in `atlas.ron` are combined into shared atlases and warmed up too.
+//! So are the background layers' sheets, named in `parallax.ron`. Every sheet is read at the
+//! scale `asset_scales.ron` picks for the screen.
+//!
+//! Games loaded from a save slot start from the saved level instead of the one on disk.
+
+use std::path::Path;
+
+use amethyst::{
+    assets::{AssetStorage, Completion, Handle, Loader, ProgressCounter},
+    config::Config,
+    core::{math::Vector3, timing::Time, transform::Transform},
+    ecs::prelude::{Builder, Entity, World},
+    prelude::*,
+    renderer::{ImageFormat, SpriteRender, SpriteSheet, SpriteSheetFormat, Texture, Transparent},
+    utils::application_root_dir,
+    window::ScreenDimensions,
+};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::AnimationSounds,
+    asset_scale::{AssetScaleConfig, AssetScales, ScaledSpriteSheetFormat},
+    atlas::{self, AtlasConfig, SourceSheet, SpriteAtlases},
+    attachment::AttachmentConfig,
+    backend::GameBackend,
+    blueprint::Blueprints,
+    boss::BossBarConfig,
+    camera::{CameraFollowConfig, RoomBoundsConfig},
+    chase::ChaseConfig,
+    clock::FrameSmoothing,
+    collision::{OverlapConfig, SpriteMasks, TileCollisionConfig},
+    cutscene::{Cutscene, Cutscenes},
+    debug_overlay::DebugOverlay,
+    dialogue::{DialogueGraph, DialogueOpen},
+    footsteps::FootstepConfig,
+    input_buffer::InputBufferConfig,
+    interaction::InteractionConfig,
+    limits::EntityLimits,
+    map_export::TileAtlas,
+    minimap::MinimapConfig,
+    movement::MovementSubsteps,
+    origin::RebaseConfig,
+    parallax::{ParallaxBackground, ParallaxConfig},
+    render_scale::RenderScale,
+    rewind::RewindConfig,
+    save::{SaveGame, SpriteColors},
+    sleep::SleepConfig,
+    spatial::{SpatialConfig, SpatialGrid},
+    spawn::SpawnConfig,
+    sprite_viewer::LoadedSpriteSheets,
+    texture_memory::{TextureBudget, TextureMemory, TextureUsage},
+    tile_map::TileMap,
+    wave::WaveConfig,
+    GameState,
+};
+
+/// Number of frames the warmup sprites are drawn for.
+const WARMUP_FRAMES: usize = 3;
+
+/// The level played, relative to the `resources` directory.
+const LEVEL: &str = "maps/sample.ron";
+
+/// The name of the level played, as save slots show it.
+pub fn level_name() -> String {
+    Path::new(LEVEL)
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+}
+
+/// A sprite sheet's image and sprite list, relative to the `resources` directory.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpriteSheetAsset {
+    pub texture: String,
+    pub sprites: String,
+}
+
+impl SpriteSheetAsset {
+    fn sample() -> Self {
+        SpriteSheetAsset {
+            texture: "textures/sample/packed.png".to_string(),
+            sprites: "textures/sample/packed.ron".to_string(),
+        }
+    }
+}
+
+/// A texture loaded for one or more sprite sheets.
+struct LoadedTexture {
+    path: String,
+    handle: Handle<Texture>,
+    sheets: usize,
+}
+
+/// Picks sheet scales for the screen as it is now, as `asset_scales.ron` says.
+fn asset_scales(world: &World, resources: &Path) -> AssetScales {
+    let config = AssetScaleConfig::load(resources.join("asset_scales.ron"));
+    let dimensions = world.read_resource::<ScreenDimensions>();
+    let drawn_height = dimensions.height() * world.read_resource::<RenderScale>().clamped();
+    let density = config.density(dimensions.hidpi_factor() as f32, drawn_height);
+    if config.enabled {
+        info!(
+            "Picking sprite sheet scales for a density of {:.2}",
+            density
+        );
+    }
+    AssetScales::new(config, density, resources.to_path_buf())
+}
+
+pub struct LoadingState {
+    local_players: usize,
+    seed: u64,
+    /// The saved game being picked up, if any.
+    save: Option<SaveGame>,
+    progress: ProgressCounter,
+    /// Which scale of each sprite sheet is read.
+    scales: AssetScales,
+    texture_budget: TextureBudget,
+    textures: Vec<LoadedTexture>,
+    loaded: Option<(Handle<SpriteSheet>, TileMap)>,
+    warmup_sheets: Vec<Handle<SpriteSheet>>,
+    warmup_entities: Vec<Entity>,
+    /// Real frame times, in seconds, of the frames drawn while warming up.
+    warmup_frames: Vec<f32>,
+}
+
+impl LoadingState {
+    /// Loads a sprite sheet at the scale picked for it, reusing the texture of an earlier sheet
+    /// that names the same image.
+    fn load_sprite_sheet(
+        &mut self,
+        world: &World,
+        asset: &SpriteSheetAsset,
+    ) -> Handle<SpriteSheet> {
+        let scaled = self.scales.select(asset);
+        let asset = &scaled.asset;
+        if scaled.scale != 1. {
+            info!("Reading {} at {}x", asset.texture, scaled.scale);
+        }
+        let loader = world.read_resource::<Loader>();
+        let texture_handle = match self
+            .textures
+            .iter_mut()
+            .find(|texture| texture.path == asset.texture)
+        {
+            Some(texture) => {
+                texture.sheets += 1;
+                texture.handle.clone()
+            }
+            None => {
+                let handle = loader.load(
+                    asset.texture.as_str(),
+                    ImageFormat::default(),
+                    &mut self.progress,
+                    &world.read_resource::<AssetStorage<Texture>>(),
+                );
+                self.textures.push(LoadedTexture {
+                    path: asset.texture.clone(),
+                    handle: handle.clone(),
+                    sheets: 1,
+                });
+                handle
+            }
+        };
+        let storage = world.read_resource::<AssetStorage<SpriteSheet>>();
+        if scaled.scale == 1. {
+            return loader.load(
+                asset.sprites.as_str(),
+                SpriteSheetFormat(texture_handle),
+                &mut self.progress,
+                &storage,
+            );
+        }
+        loader.load(
+            asset.sprites.as_str(),
+            ScaledSpriteSheetFormat {
+                texture: texture_handle,
+                scale: scaled.scale,
+            },
+            &mut self.progress,
+            &storage,
+        )
+    }
+
+    /// Loads the sheets of `config`, combined into atlases if it says to or each on its own
+    /// otherwise, and warms them up with the rest.
+    fn load_atlases(
+        &mut self,
+        world: &World,
+        config: &AtlasConfig,
+        resources: &Path,
+    ) -> SpriteAtlases {
+        let mut atlases = SpriteAtlases::default();
+        if !config.enabled {
+            for (name, asset) in &config.sheets {
+                let handle = self.load_sprite_sheet(world, asset);
+                self.warmup_sheets.push(handle.clone());
+                atlases.insert(name.clone(), handle);
+            }
+            return atlases;
+        }
+
+        let mut sources = Vec::new();
+        for (name, asset) in &config.sheets {
+            match SourceSheet::load(name, &self.scales.select(asset), resources) {
+                Ok(source) => sources.push(source),
+                Err(err) => error!("Failed to load {} for its atlas: {}", name, err),
+            }
+        }
+        let combined = atlas::combine(sources, config.max_size, config.padding);
+        info!(
+            "Combined {} sprite sheets into {} atlases",
+            config.sheets.len(),
+            combined.len()
+        );
+        for (number, combined) in combined.into_iter().enumerate() {
+            let names: Vec<String> = combined
+                .sheets
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect();
+            let texture = atlas::load_atlas(
+                world,
+                format!("atlas {}", number),
+                combined,
+                &mut atlases,
+                &mut self.progress,
+            );
+            self.warmup_sheets
+                .extend(names.iter().filter_map(|name| atlases.get(name)).cloned());
+            self.textures.push(LoadedTexture {
+                path: texture.name,
+                handle: texture.handle,
+                sheets: texture.sheets,
+            });
+        }
+        atlases
+    }
+
+    /// Estimates the memory taken by the loaded textures and puts it on the debug overlay.
+    fn measure_textures(&self, world: &mut World) {
+        let mut memory = TextureMemory::new(self.texture_budget);
+        {
+            let storage = world.read_resource::<AssetStorage<Texture>>();
+            for texture in &self.textures {
+                let usage = storage.get(&texture.handle).and_then(|loaded| {
+                    TextureUsage::measure::<GameBackend>(
+                        texture.path.clone(),
+                        loaded,
+                        texture.sheets,
+                    )
+                });
+                if let Some(usage) = usage {
+                    memory.record(usage);
+                }
+            }
+        }
+        world
+            .write_resource::<DebugOverlay>()
+            .set("textures", memory.report());
+        world.add_resource(memory);
+    }
+
+    pub fn new(local_players: usize, seed: u64) -> Self {
+        LoadingState {
+            local_players,
+            seed,
+            save: None,
+            progress: ProgressCounter::new(),
+            scales: AssetScales::default(),
+            texture_budget: TextureBudget::default(),
+            textures: Vec::new(),
+            loaded: None,
+            warmup_sheets: Vec::new(),
+            warmup_entities: Vec::new(),
+            warmup_frames: Vec::new(),
+        }
+    }
+
+    /// Picks up `save` where it was left.
+    pub fn from_save(local_players: usize, save: SaveGame) -> Self {
+        LoadingState {
+            save: Some(save.clone()),
+            ..LoadingState::new(local_players, save.seed)
+        }
+    }
+
+    /// Puts two tiny sprites from every warmup sheet on screen, one opaque and one transparent,
+    /// so both sprite pipelines get to draw each texture.
+    fn start_warmup(&mut self, world: &mut World) {
+        for sprite_sheet in &self.warmup_sheets {
+            for &transparent in &[false, true] {
+                let mut transform = Transform::default();
+                transform.set_scale(Vector3::new(0.001, 0.001, 1.));
+                let builder = world.create_entity().with(transform).with(SpriteRender {
+                    sprite_sheet: sprite_sheet.clone(),
+                    sprite_number: 0,
+                });
+                let builder = if transparent {
+                    builder.with(Transparent)
+                } else {
+                    builder
+                };
+                self.warmup_entities.push(builder.build());
+            }
+        }
+    }
+}
+
+impl SimpleState for LoadingState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let app_root = application_root_dir().expect("Could not load app root directory");
+        let resources = app_root.join("resources");
+        let blueprints = Blueprints::load(resources.join("blueprints.ron")).unwrap_or_else(|err| {
+            error!(
+                "Failed to load blueprints, placing enemies as given: {}",
+                err
+            );
+            Blueprints::default()
+        });
+        data.world.add_resource(
+            DialogueGraph::load(resources.join("dialogue.ron")).expect("Dialogue must load"),
+        );
+        // A new game's answers start over, and a loaded one's are as they were saved.
+        let flags = self
+            .save
+            .as_ref()
+            .map(|save| save.dialogue_flags.clone())
+            .unwrap_or_default();
+        data.world.add_resource(flags);
+        data.world.add_resource(DialogueOpen::default());
+        data.world
+            .add_resource(Cutscenes::load(resources.join("cutscenes.ron")));
+        data.world.add_resource(Cutscene::default());
+        let map = match &self.save {
+            Some(save) => save.map.clone(),
+            None => {
+                let mut map =
+                    TileMap::load(resources.join(LEVEL)).expect("Sample tile map must load");
+                blueprints.apply_to_map(&mut map);
+                map
+            }
+        };
+
+        let sample = SpriteSheetAsset::sample();
+        assert!(
+            resources.join(&sample.texture).exists(),
+            "Desert packed image path must exist"
+        );
+        assert!(
+            resources.join(&sample.sprites).exists(),
+            "Desert packed image tile info path must exist"
+        );
+        self.texture_budget = TextureBudget::load(resources.join("texture_budget.ron"));
+        self.scales = asset_scales(data.world, &resources);
+        let sprite_sheet = self.load_sprite_sheet(data.world, &sample);
+        self.warmup_sheets.push(sprite_sheet.clone());
+        for asset in &map.warmup {
+            let handle = self.load_sprite_sheet(data.world, asset);
+            self.warmup_sheets.push(handle);
+        }
+        let parallax = ParallaxConfig::load(resources.join("parallax.ron"));
+        let mut background = ParallaxBackground::default();
+        for layer in parallax.layers {
+            let handle = self.load_sprite_sheet(data.world, &layer.sheet);
+            self.warmup_sheets.push(handle.clone());
+            background.layers.push((layer, handle));
+        }
+        data.world.add_resource(background);
+        let atlas_config = AtlasConfig::load(resources.join("atlas.ron"));
+        let atlases = self.load_atlases(data.world, &atlas_config, &resources);
+        data.world.add_resource(atlases);
+
+        // The sheet's image is decoded once here, for everything drawn or tested from its
+        // pixels rather than by the renderer.
+        let tile_atlas = TileAtlas::load(
+            resources.join(&sample.texture),
+            resources.join(&sample.sprites),
+        )
+        .expect("Tile atlas must load");
+        data.world
+            .add_resource(SpriteMasks::from_atlas(&tile_atlas));
+        data.world
+            .add_resource(SpriteColors::from_atlas(&tile_atlas));
+        data.world.add_resource(tile_atlas);
+        data.world
+            .add_resource(MovementSubsteps::load(resources.join("movement.ron")));
+        data.world
+            .add_resource(OverlapConfig::load(resources.join("overlap.ron")));
+        data.world
+            .add_resource(FrameSmoothing::load(resources.join("frame_smoothing.ron")));
+        data.world
+            .add_resource(SpawnConfig::load(resources.join("spawn.ron")));
+        data.world
+            .add_resource(InputBufferConfig::load(resources.join("input_buffer.ron")));
+        data.world
+            .add_resource(SleepConfig::load(resources.join("sleep.ron")));
+        data.world
+            .add_resource(AttachmentConfig::load(resources.join("attachments.ron")));
+        data.world.add_resource(AnimationSounds::load(
+            resources.join("animation_sounds.ron"),
+        ));
+        data.world
+            .add_resource(MinimapConfig::load(resources.join("minimap.ron")));
+        data.world
+            .add_resource(BossBarConfig::load(resources.join("boss_bar.ron")));
+        data.world
+            .add_resource(ChaseConfig::load(resources.join("chase.ron")));
+        let mut waves = WaveConfig::load(resources.join("waves.ron"));
+        blueprints.apply_to_waves(&mut waves);
+        data.world.add_resource(waves);
+        data.world
+            .add_resource(RewindConfig::load(resources.join("rewind.ron")));
+        data.world
+            .add_resource(InteractionConfig::load(resources.join("interaction.ron")));
+        data.world
+            .add_resource(FootstepConfig::load(resources.join("footsteps.ron")));
+        data.world
+            .add_resource(RoomBoundsConfig::load(resources.join("room_bounds.ron")));
+        data.world.add_resource(CameraFollowConfig::load(
+            resources.join("camera_follow.ron"),
+        ));
+        data.world.add_resource(TileCollisionConfig::load(
+            resources.join("tile_collision.ron"),
+        ));
+        data.world
+            .add_resource(EntityLimits::load(resources.join("entity_limits.ron")));
+        data.world
+            .add_resource(RebaseConfig::load(resources.join("origin_rebase.ron")));
+        let spatial = SpatialConfig::load(resources.join("spatial.ron"));
+        data.world.add_resource(SpatialGrid::new(spatial.cell_size));
+
+        data.world
+            .add_resource(LoadedSpriteSheets(self.warmup_sheets.clone()));
+
+        self.loaded = Some((sprite_sheet, map));
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        if let Completion::Loading = self.progress.complete() {
+            return Trans::None;
+        }
+
+        if self.warmup_entities.is_empty() {
+            // Missing assets are drawn blank in game, which is better than not starting at all.
+            for failure in self.progress.errors() {
+                error!("Failed to load {}: {}", failure.asset_name, failure.error);
+            }
+            self.measure_textures(data.world);
+            self.start_warmup(data.world);
+            return Trans::None;
+        }
+        self.warmup_frames
+            .push(data.world.read_resource::<Time>().delta_real_seconds());
+        if self.warmup_frames.len() < WARMUP_FRAMES {
+            return Trans::None;
+        }
+
+        data.world
+            .delete_entities(&self.warmup_entities)
+            .expect("Warmup sprites must still be alive");
+        // The first frame pays for getting every sheet ready; the last shows what it costs
+        // once that is done.
+        info!(
+            "Warmed up {} sprite sheets: first frame took {:.1} ms, last {:.1} ms",
+            self.warmup_sheets.len(),
+            self.warmup_frames[0] * 1000.,
+            self.warmup_frames[WARMUP_FRAMES - 1] * 1000.,
+        );
+
+        let (sprite_sheet, map) = self.loaded.take().expect("Level loads in on_start");
+        Trans::Switch(Box::new(GameState::new(
+            self.local_players,
+            self.seed,
+            sprite_sheet,
+            map,
+            self.save.take(),
+        )))
+    }
+}
@@ -0,0 +1,381 @@
+//! A panel of recent log messages, so warnings and errors can be seen without a terminal.
+//!
+//! The logger hands every message it lets through to the `LogHistory` too, which keeps the last
+//! `capacity` of them. The `toggle_log_viewer` action shows the newest along the bottom left of
+//! the window, coloured by level. `scroll_log_up` and `scroll_log_down` page back through older
+//! ones and forward again, and `cycle_log_level` picks the least severe level shown.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Join, Read, ReadExpect, ReadStorage, System, World,
+        WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    ui::{Anchor, FontAsset, LineMode, UiText, UiTransform},
+    LogLevelFilter,
+};
+use log::{Level, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    strings::Strings,
+    ui_theme::{ThemedText, UiTheme},
+};
+
+/// How much the log viewer keeps and shows, read from `log_viewer.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogViewerConfig {
+    /// How many messages are kept, dropping the oldest past it.
+    pub capacity: usize,
+    /// How many messages the panel shows at once.
+    pub lines: usize,
+    /// The least severe level shown when the game starts.
+    pub level: LogLevelFilter,
+}
+
+impl Default for LogViewerConfig {
+    fn default() -> Self {
+        LogViewerConfig {
+            capacity: 500,
+            lines: 16,
+            level: LogLevelFilter::Info,
+        }
+    }
+}
+
+/// One logged message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+/// The most recent log messages, shared between the logger and the game.
+#[derive(Clone, Debug)]
+pub struct LogHistory {
+    capacity: usize,
+    entries: Arc<Mutex<VecDeque<LogEntry>>>,
+}
+
+impl LogHistory {
+    pub fn new(capacity: usize) -> Self {
+        LogHistory {
+            capacity,
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Keeps `entry`, dropping the oldest entries past the capacity.
+    pub fn push(&self, entry: LogEntry) {
+        let mut entries = self.entries.lock().expect("Log history lock is poisoned");
+        entries.push_back(entry);
+        while entries.len() > self.capacity {
+            entries.pop_front();
+        }
+    }
+
+    /// The entries at `filter` or more severe, oldest first.
+    pub fn filtered(&self, filter: LogLevelFilter) -> Vec<LogEntry> {
+        self.entries
+            .lock()
+            .expect("Log history lock is poisoned")
+            .iter()
+            .filter(|entry| entry.level <= filter)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Log for LogHistory {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        self.push(LogEntry {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// The level shown after `filter` by `cycle_log_level`, from only errors to everything and
+/// back.
+pub fn next_level(filter: LogLevelFilter) -> LogLevelFilter {
+    match filter {
+        LogLevelFilter::Error => LogLevelFilter::Warn,
+        LogLevelFilter::Warn => LogLevelFilter::Info,
+        LogLevelFilter::Info => LogLevelFilter::Debug,
+        LogLevelFilter::Debug => LogLevelFilter::Trace,
+        LogLevelFilter::Trace | LogLevelFilter::Off => LogLevelFilter::Error,
+    }
+}
+
+/// The colour messages of `level` are shown in, with `normal` for information.
+fn level_color(level: Level, normal: [f32; 4]) -> [f32; 4] {
+    match level {
+        Level::Error => [1., 0.35, 0.35, 1.],
+        Level::Warn => [1., 0.8, 0.3, 1.],
+        Level::Info => normal,
+        Level::Debug => [0.7, 0.7, 0.7, 1.],
+        Level::Trace => [0.5, 0.5, 0.5, 1.],
+    }
+}
+
+/// The entries of `entries` shown `scroll` lines back from the newest, in a panel of `lines`,
+/// along with the scroll kept within what there is to scroll through.
+pub fn visible_entries(entries: &[LogEntry], lines: usize, scroll: usize) -> (&[LogEntry], usize) {
+    let scroll = scroll.min(entries.len().saturating_sub(lines));
+    let end = entries.len() - scroll;
+    (&entries[end.saturating_sub(lines)..end], scroll)
+}
+
+/// One line of the panel, counted from the top. The heading is line `0`.
+#[derive(Clone, Copy, Debug)]
+pub struct LogViewerLine(pub usize);
+
+impl Component for LogViewerLine {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Creates the heading and the `lines` lines of the panel, empty until it is shown.
+pub fn spawn_log_viewer(world: &mut World, lines: usize) {
+    let theme = world.read_resource::<UiTheme>().clone();
+    let font = theme.font(
+        &world.read_resource::<Loader>(),
+        &world.read_resource::<AssetStorage<FontAsset>>(),
+    );
+    for line in 0..=lines {
+        let mut text = UiText::new(
+            font.clone(),
+            String::new(),
+            theme.text_color,
+            theme.font_size,
+        );
+        text.line_mode = LineMode::Single;
+        text.align = Anchor::MiddleLeft;
+        let mut transform = UiTransform::new(
+            format!("log_viewer_{}", line),
+            Anchor::BottomLeft,
+            Anchor::BottomLeft,
+            theme.padding,
+            0.,
+            10.,
+            960.,
+            theme.font_size * 1.25,
+        );
+        let themed = ThemedText::new(&text, &transform);
+        themed.apply(&theme, &mut text, &mut transform);
+        world
+            .create_entity()
+            .with(transform)
+            .with(text)
+            .with(themed)
+            .with(LogViewerLine(line))
+            .build();
+    }
+}
+
+/// Shows, scrolls and filters the panel on its actions, as the module describes, and keeps its
+/// lines current while it is shown.
+pub struct LogViewerSystem {
+    lines: usize,
+    level: LogLevelFilter,
+    visible: bool,
+    /// Lines back from the newest message.
+    scroll: usize,
+    was_toggle_pressed: bool,
+    was_up_pressed: bool,
+    was_down_pressed: bool,
+    was_level_pressed: bool,
+}
+
+impl LogViewerSystem {
+    pub fn new(config: LogViewerConfig) -> Self {
+        LogViewerSystem {
+            lines: config.lines,
+            level: config.level,
+            visible: false,
+            scroll: 0,
+            was_toggle_pressed: false,
+            was_up_pressed: false,
+            was_down_pressed: false,
+            was_level_pressed: false,
+        }
+    }
+}
+
+impl<'s> System<'s> for LogViewerSystem {
+    type SystemData = (
+        ReadStorage<'s, LogViewerLine>,
+        WriteStorage<'s, ThemedText>,
+        WriteStorage<'s, UiText>,
+        WriteStorage<'s, UiTransform>,
+        ReadExpect<'s, LogHistory>,
+        ReadExpect<'s, Strings>,
+        Read<'s, UiTheme>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(
+        &mut self,
+        (lines, mut themed, mut texts, mut transforms, history, strings, theme, input): Self::SystemData,
+    ) {
+        let pressed = |action| input.action_is_down(action).unwrap_or(false);
+        let toggle = pressed("toggle_log_viewer");
+        if toggle && !self.was_toggle_pressed {
+            self.visible = !self.visible;
+            self.scroll = 0;
+        }
+        self.was_toggle_pressed = toggle;
+        let up = pressed("scroll_log_up");
+        if up && !self.was_up_pressed && self.visible {
+            self.scroll += self.lines;
+        }
+        self.was_up_pressed = up;
+        let down = pressed("scroll_log_down");
+        if down && !self.was_down_pressed && self.visible {
+            self.scroll = self.scroll.saturating_sub(self.lines);
+        }
+        self.was_down_pressed = down;
+        let level = pressed("cycle_log_level");
+        if level && !self.was_level_pressed && self.visible {
+            self.level = next_level(self.level);
+            self.scroll = 0;
+        }
+        self.was_level_pressed = level;
+
+        if !self.visible {
+            for (_, text) in (&lines, &mut texts).join() {
+                if !text.text.is_empty() {
+                    text.text.clear();
+                }
+            }
+            return;
+        }
+
+        let entries = history.filtered(self.level);
+        let (shown, scroll) = visible_entries(&entries, self.lines, self.scroll);
+        self.scroll = scroll;
+        let heading = if scroll > 0 {
+            strings.format(
+                "log_viewer.heading_scrolled",
+                &[&self.level, &shown.len(), &entries.len(), &scroll],
+            )
+        } else {
+            strings.format(
+                "log_viewer.heading",
+                &[&self.level, &shown.len(), &entries.len()],
+            )
+        };
+        // The newest message is at the bottom, with older ones above it and the heading on top.
+        let height = theme.text_size(theme.font_size) * 1.25;
+        let padding = (self.lines - shown.len()) + 1;
+        for (line, themed, text, transform) in
+            (&lines, &mut themed, &mut texts, &mut transforms).join()
+        {
+            let (content, color) = match line.0 {
+                0 => (heading.clone(), theme.text_color),
+                index => match index
+                    .checked_sub(padding)
+                    .and_then(|index| shown.get(index))
+                {
+                    Some(entry) => (
+                        format!("[{}][{}] {}", entry.level, entry.target, entry.message),
+                        level_color(entry.level, theme.text_color),
+                    ),
+                    None => (String::new(), theme.text_color),
+                },
+            };
+            themed.color = color;
+            text.color = theme.text_color(color);
+            if text.text != content {
+                text.text = content;
+            }
+            transform.local_y = theme.padding + (self.lines - line.0) as f32 * height;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: Level, message: &str) -> LogEntry {
+        LogEntry {
+            level,
+            target: "shroud".to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn messages(entries: &[LogEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.message.as_str()).collect()
+    }
+
+    #[test]
+    fn the_oldest_messages_are_dropped_past_capacity() {
+        let history = LogHistory::new(3);
+        for message in &["a", "b", "c", "d", "e"] {
+            history.push(entry(Level::Info, message));
+        }
+        assert_eq!(
+            messages(&history.filtered(LogLevelFilter::Trace)),
+            ["c", "d", "e"]
+        );
+    }
+
+    #[test]
+    fn cycling_the_level_hides_less_severe_messages() {
+        let history = LogHistory::new(10);
+        let levels = [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ];
+        for level in &levels {
+            history.push(entry(*level, &level.to_string()));
+        }
+        let mut filter = LogLevelFilter::Debug;
+        let mut shown = Vec::new();
+        for _ in 0..5 {
+            filter = next_level(filter);
+            shown.push(history.filtered(filter).len());
+        }
+        // Trace, back round to errors only, then one more level each time.
+        assert_eq!(shown, [5, 1, 2, 3, 4]);
+        assert_eq!(
+            messages(&history.filtered(LogLevelFilter::Warn)),
+            ["ERROR", "WARN"]
+        );
+        assert_eq!(next_level(LogLevelFilter::Off), LogLevelFilter::Error);
+    }
+
+    #[test]
+    fn scrolling_stops_at_the_oldest_page() {
+        let entries: Vec<LogEntry> = (0..5)
+            .map(|index| entry(Level::Info, &index.to_string()))
+            .collect();
+        let (shown, scroll) = visible_entries(&entries, 2, 0);
+        assert_eq!((messages(shown), scroll), (vec!["3", "4"], 0));
+        let (shown, scroll) = visible_entries(&entries, 2, 2);
+        assert_eq!((messages(shown), scroll), (vec!["1", "2"], 2));
+        let (shown, scroll) = visible_entries(&entries, 2, 10);
+        assert_eq!((messages(shown), scroll), (vec!["0", "1"], 3));
+        let (shown, scroll) = visible_entries(&entries[..1], 2, 4);
+        assert_eq!((messages(shown), scroll), (vec!["0"], 0));
+    }
+}
@@ -0,0 +1,265 @@
+//! How much the game logs, and where to, read from `logging.ron`.
+//!
+//! Logs go to the terminal, to the `LogHistory` for the log viewer and, given a `file`, to that
+//! file too. Each module logs at the most specific level given for it: its own entry or its
+//! parent's in `modules`, then the level for its group, gameplay for the game's own modules and
+//! rendering for the renderer's crates, then the overall `level`. The `SHROUD_LOG` environment
+//! variable overrides the config the same way as `RUST_LOG` does for other programs, with a
+//! comma-separated list of an overall level and `module=level` entries, such as
+//! `warn,shroud::lighting=debug`.
+//!
+//! Nothing can be logged before the logger starts, so a `logging.ron` that can't be read, like
+//! a bad `SHROUD_LOG`, is only reported once it has, and the defaults are used meanwhile.
+
+use std::{
+    collections::BTreeMap,
+    env, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use amethyst::{config::Config, LogLevelFilter, StdoutLog};
+use failure::format_err;
+use fern::{colors::ColoredLevelConfig, Dispatch, FormatCallback};
+use log::{Log, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::log_viewer::LogHistory;
+
+/// The environment variable overriding the config's levels.
+pub const LOG_ENV: &str = "SHROUD_LOG";
+
+/// The game's own modules.
+const GAMEPLAY_MODULES: &[&str] = &["shroud"];
+
+/// The crates drawing the game, which log a lot more than the rest.
+const RENDERING_MODULES: &[&str] = &[
+    "amethyst_rendy",
+    "rendy",
+    "rendy_memory",
+    "rendy_resource",
+    "gfx_backend_metal",
+    "gfx_backend_vulkan",
+    "gfx_device_gl",
+];
+
+/// The logging setup read from `logging.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// The level of everything not given one of its own.
+    pub level: LogLevelFilter,
+    /// The level of the game's own modules.
+    pub gameplay: Option<LogLevelFilter>,
+    /// The level of the renderer's crates.
+    pub rendering: Option<LogLevelFilter>,
+    /// Levels for modules by path, such as `shroud::lighting`, over their group's.
+    pub modules: BTreeMap<String, LogLevelFilter>,
+    /// Whether logs are printed to the terminal, in colour or plain, or not at all.
+    pub stdout: StdoutLog,
+    /// Where logs are also written to, relative to the game's folder.
+    pub file: Option<PathBuf>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: LogLevelFilter::Info,
+            gameplay: None,
+            rendering: Some(LogLevelFilter::Warn),
+            modules: BTreeMap::new(),
+            stdout: StdoutLog::Colored,
+            file: None,
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Applies a filter list like `SHROUD_LOG`'s over the config.
+    pub fn apply_filters(&mut self, spec: &str) -> Result<(), failure::Error> {
+        let filters = parse_filters(spec)?;
+        if let Some(level) = filters.level {
+            self.level = level;
+        }
+        self.modules.extend(filters.modules);
+        Ok(())
+    }
+
+    /// The level of every module given one, from the groups and `modules`, the latter taking
+    /// precedence.
+    pub fn module_levels(&self) -> BTreeMap<String, LogLevelFilter> {
+        let mut levels = BTreeMap::new();
+        let groups = [
+            (self.gameplay, GAMEPLAY_MODULES),
+            (self.rendering, RENDERING_MODULES),
+        ];
+        for (level, modules) in groups.iter() {
+            if let Some(level) = level {
+                for module in modules.iter() {
+                    levels.insert(module.to_string(), *level);
+                }
+            }
+        }
+        levels.extend(self.modules.clone());
+        levels
+    }
+}
+
+/// The levels a filter list gives.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogFilters {
+    /// The overall level, if the list gives one.
+    pub level: Option<LogLevelFilter>,
+    /// The modules' levels, in the order given.
+    pub modules: Vec<(String, LogLevelFilter)>,
+}
+
+/// Parses a comma-separated filter list of an optional overall level and `module=level`
+/// entries. A later overall level replaces an earlier one.
+pub fn parse_filters(spec: &str) -> Result<LogFilters, failure::Error> {
+    let parse_level = |level: &str| {
+        LogLevelFilter::from_str(level.trim())
+            .map_err(|_| format_err!("{:?} is not a log level", level.trim()))
+    };
+    let mut filters = LogFilters::default();
+    for entry in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        match entry.find('=') {
+            Some(split) => {
+                let module = entry[..split].trim();
+                if module.is_empty() {
+                    return Err(format_err!("{:?} names no module", entry));
+                }
+                let level = parse_level(&entry[split + 1..])?;
+                filters.modules.push((module.to_string(), level));
+            }
+            None => filters.level = Some(parse_level(entry)?),
+        }
+    }
+    Ok(filters)
+}
+
+fn format(out: FormatCallback<'_>, message: &std::fmt::Arguments<'_>, record: &Record<'_>) {
+    out.finish(format_args!(
+        "[{}][{}] {}",
+        record.level(),
+        record.target(),
+        message
+    ))
+}
+
+/// Starts logging as the config at `config_path` and `SHROUD_LOG` say, with the file relative
+/// to `app_root`, and every message let through kept in `history` as well.
+pub fn start_logging(config_path: &Path, app_root: &Path, history: LogHistory) {
+    let (mut config, config_error) = match LoggingConfig::load_no_fallback(config_path) {
+        Ok(config) => (config, None),
+        Err(err) => (LoggingConfig::default(), Some(err)),
+    };
+    let env_error = env::var(LOG_ENV)
+        .ok()
+        .and_then(|spec| config.apply_filters(&spec).err());
+
+    let mut logger = Dispatch::new().level(config.level);
+    for (module, level) in config.module_levels() {
+        logger = logger.level_for(module, level);
+    }
+    match config.stdout {
+        StdoutLog::Off => {}
+        StdoutLog::Plain => {
+            logger = logger.chain(Dispatch::new().format(format).chain(io::stdout()))
+        }
+        StdoutLog::Colored => {
+            let colors = ColoredLevelConfig::new();
+            logger = logger.chain(
+                Dispatch::new()
+                    .format(move |out, message, record| {
+                        out.finish(format_args!(
+                            "\x1B[{}m[{}][{}] {}\x1B[0m",
+                            colors.get_color(&record.level()).to_fg_str(),
+                            record.level(),
+                            record.target(),
+                            message
+                        ))
+                    })
+                    .chain(io::stdout()),
+            )
+        }
+    }
+    let mut file_error = None;
+    if let Some(file) = &config.file {
+        match fern::log_file(app_root.join(file)) {
+            Ok(file) => logger = logger.chain(Dispatch::new().format(format).chain(file)),
+            Err(err) => file_error = Some((file, err)),
+        }
+    }
+    let history: Box<dyn Log> = Box::new(history);
+    if let Err(err) = logger.chain(history).apply() {
+        eprintln!("Failed to start logging: {}", err);
+        return;
+    }
+
+    if let Some(err) = config_error {
+        log::warn!(
+            "Logging as by default, failed to read {}: {}",
+            config_path.display(),
+            err
+        );
+    }
+    if let Some(err) = env_error {
+        log::warn!("Ignoring {}: {}", LOG_ENV, err);
+    }
+    if let Some((file, err)) = file_error {
+        log::warn!("Not logging to {}: {}", file.display(), err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_give_an_overall_level_and_a_level_per_module() {
+        let filters =
+            parse_filters(" warn, shroud::lighting=debug ,amethyst_rendy = error,").unwrap();
+        assert_eq!(filters.level, Some(LogLevelFilter::Warn));
+        assert_eq!(
+            filters.modules,
+            [
+                ("shroud::lighting".to_string(), LogLevelFilter::Debug),
+                ("amethyst_rendy".to_string(), LogLevelFilter::Error),
+            ]
+        );
+        assert_eq!(parse_filters("").unwrap(), LogFilters::default());
+        assert_eq!(
+            parse_filters("info,trace").unwrap().level,
+            Some(LogLevelFilter::Trace)
+        );
+    }
+
+    #[test]
+    fn bad_filters_are_rejected() {
+        assert!(parse_filters("loud").is_err());
+        assert!(parse_filters("=debug").is_err());
+        assert!(parse_filters("shroud=loud").is_err());
+    }
+
+    #[test]
+    fn modules_take_precedence_over_their_group() {
+        let mut config = LoggingConfig {
+            gameplay: Some(LogLevelFilter::Debug),
+            ..LoggingConfig::default()
+        };
+        config
+            .apply_filters("error,shroud::lighting=trace,rendy=info")
+            .unwrap();
+        assert_eq!(config.level, LogLevelFilter::Error);
+        let levels = config.module_levels();
+        assert_eq!(levels["shroud"], LogLevelFilter::Debug);
+        assert_eq!(levels["shroud::lighting"], LogLevelFilter::Trace);
+        assert_eq!(levels["rendy"], LogLevelFilter::Info);
+        assert_eq!(levels["amethyst_rendy"], LogLevelFilter::Warn);
+    }
+}
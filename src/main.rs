@@ -1,197 +1,1284 @@
+mod abilities;
+mod analog;
+mod animation;
+mod any_input;
+mod asset_scale;
+mod atlas;
+mod attachment;
+mod attract;
+mod auto_tile;
+mod backend;
+mod blueprint;
+mod boss;
+mod camera;
+mod chase;
+mod clock;
+mod collision;
+mod combat;
+mod combat_text;
+mod control_scheme;
+mod cutscene;
+mod damage_log;
+mod death;
+mod debug_overlay;
+mod dialogue;
+mod difficulty;
+mod disabled;
+mod enemy;
+mod footsteps;
+mod frame_check;
+mod god_mode;
+mod highlight;
+mod hitbox;
+mod input_buffer;
+mod interaction;
+mod intro;
+mod iso_sort;
+mod join_benchmark;
+mod lighting;
+mod limits;
+mod loading;
+mod log_viewer;
+mod logging;
+mod map_export;
+mod menu;
+mod minimap;
+mod movement;
+mod navigation;
+mod origin;
+mod outline;
+mod palette;
+mod parallax;
+mod player;
+mod profiler;
+mod quality;
+mod rebinding;
+mod recording;
+mod render_recovery;
+mod render_scale;
+mod resource_bar;
+mod rewind;
+mod rng;
+mod rumble;
+mod save;
+mod script;
+mod shadow;
+mod sight;
+mod sleep;
+mod sound;
+mod spatial;
+mod spawn;
+mod split_screen;
+mod sprite_sort;
+mod sprite_viewer;
+mod step_button;
+mod strings;
+mod telemetry;
+mod texel_snap;
+mod texture_memory;
+mod tile_cursor;
+mod tile_map;
+mod ui_theme;
+mod wave;
+mod weather;
+mod world_hash;
+mod world_text;
+
 use amethyst::{
-    assets::{AssetStorage, Handle, Loader, Processor, ProgressCounter},
-    core::transform::{Transform, TransformBundle},
-    ecs::prelude::{ReadExpect, Resources, SystemData},
+    assets::{Handle, Processor},
+    audio::{output::init_output, Source},
+    config::Config,
+    core::{
+        math::Vector2,
+        timing::Time,
+        transform::{Transform, TransformBundle},
+        ArcThreadPool,
+    },
+    ecs::prelude::{Dispatcher, DispatcherBuilder, Join, ReadExpect, Resources, SystemData},
+    input::{InputBundle, InputHandler, StringBindings},
     prelude::*,
-    ui::{DrawUiDesc, UiBundle},
-    input::{InputBundle, StringBindings},
     renderer::{
-        sprite_visibility::SpriteVisibilitySortingSystem,
-        pass::{DrawShadedDesc, DrawFlat2DDesc, DrawFlat2DTransparentDesc},
-        camera::{Camera, Projection},
+        camera::{ActiveCamera, Camera},
+        debug_drawing::DebugLinesComponent,
+        pass::{DrawDebugLinesDesc, DrawFlat2DDesc, DrawFlat2DTransparentDesc},
         rendy::{
             factory::Factory,
             graph::{
-                render::{RenderGroupDesc, SubpassBuilder},
-                GraphBuilder,
+                render::{RenderGroupDesc, SimpleGraphicsPipelineDesc, SubpassBuilder},
+                GraphBuilder, NodeDesc,
             },
             hal::{format::Format, image},
         },
-        types::DefaultBackend,
-        GraphCreator, ImageFormat, RenderingSystem, SpriteRender, SpriteSheet, SpriteSheetFormat,
-        Texture, Transparent,
+        types::Backend,
+        GraphCreator, RenderingSystem, SpriteRender, SpriteSheet, Transparent,
     },
+    shrev::{EventChannel, ReaderId},
+    ui::{Anchor, DrawUiDesc, UiBundle},
     utils::application_root_dir,
-    window::{ScreenDimensions, Window, WindowBundle},
+    window::{DisplayConfig, ScreenDimensions, Window, WindowBundle},
 };
+use log::warn;
 
-struct GameState {}
+use crate::{
+    abilities::{
+        AimAssist, AutoAttack, AutoAttackSystem, AutoAttackToggleSystem, Dash, DashSystem,
+    },
+    analog::{AnalogResponse, AnalogResponseSaveSystem},
+    animation::{AnimationSoundSystem, SpriteAnimationSystem},
+    any_input::{AnyInput, AnyInputConfig, AnyInputSystem},
+    attachment::{attach, AttachmentConfig, AttachmentSystem},
+    attract::{AttractConfig, AttractMode, AttractModeSystem, TourStops},
+    backend::GameBackend,
+    boss::{spawn_boss_bar, BossBarSystem},
+    camera::{
+        centered_projection, BoundsPolicyToggleSystem, CameraBounds, CameraFollow,
+        CameraFollowSystem, CameraMatricesSystem, CameraProjectionSystem, CameraShakeSystem,
+        CameraSteadySystem, CameraTarget, CameraZoom, RecenterConfig, RecenterSystem, Room,
+        RoomBounds, RoomBoundsConfig, RoomBoundsSystem, RoomCamera, RoomCameraSystem, ShakeConfig,
+        SpeedZoomConfig, SpeedZoomSystem, Trauma, ZoomPresetSystem, ZoomPresets, ZoomToFit,
+        ZoomToFitSystem,
+    },
+    chase::ChaseSystem,
+    clock::{
+        count_playtime, FrameSmoothing, FrameStep, FrameStepSystem, GameClock, GameClockSystem,
+        Playtime, SmoothedSteps,
+    },
+    collision::{
+        run_collision_callbacks, Collider, CollisionCallbacks, ContactSystem, PixelPerfect,
+        TileColliders,
+    },
+    combat::{DamageSystem, DebugDamageSystem, Health, InvulnerabilitySystem},
+    combat_text::{CombatTextConfig, CombatTextSystem},
+    control_scheme::{ControlSchemeSystem, ControlSchemes, CustomBindings},
+    cutscene::CutsceneSystem,
+    damage_log::{DamageLogDumpSystem, DamageLogSystem},
+    death::{DeathBurstOverlay, DeathBurstSystem, DeathBursts, DeathSystem, DespawnSystem},
+    debug_overlay::{spawn_debug_overlay, DebugOverlaySystem},
+    difficulty::{Difficulty, DifficultyConfig},
+    disabled::DisabledSystem,
+    enemy::spawn_enemies,
+    footsteps::{FootstepSystem, Footsteps},
+    frame_check::{FrameCheck, FrameCheckConfig, FrameCheckSystem},
+    god_mode::{GodMode, GodModeSystem, GodModeToggleSystem},
+    highlight::{HighlightConfig, HighlightPulseSystem},
+    hitbox::{HitboxSystem, Hurtbox},
+    interaction::InteractionSystem,
+    intro::{IntroSweep, IntroSystem},
+    iso_sort::{IsoSortSystem, IsoSorted},
+    join_benchmark::{JoinBenchmarkConfig, JoinBenchmarkSystem},
+    lighting::{spawn_tile_lights, BlockLight, LightGrid, LightingSystem, Lit},
+    limits::EntityLimitSystem,
+    loading::level_name,
+    log_viewer::{spawn_log_viewer, LogHistory, LogViewerConfig, LogViewerSystem},
+    logging::start_logging,
+    map_export::{MapExportConfig, MapExportSystem},
+    menu::{MainMenuState, MenuFocusSystem, PauseState},
+    minimap::{spawn_minimap, MinimapSystem},
+    movement::{Facing, MovementSystem, Velocity},
+    navigation::{ClickToMoveSystem, NavGrid, NavPathOverlay, PathFollowSystem},
+    origin::rebase_if_far,
+    outline::{AlwaysVisibleOutline, OutlineConfig, OutlineOverlay, OutlineSystem},
+    palette::{DrawPaletteFilterDesc, PaletteFilter, PaletteFilterSystem},
+    parallax::ParallaxSystem,
+    player::{Player, PlayerMovementSystem},
+    profiler::{Profiled, ProfiledLocal, ProfilerConfig, ProfilerSystem},
+    quality::{AdaptiveQualitySystem, QualityConfig},
+    recording::{FrameReadbackDesc, RecordingConfig, RecordingSystem},
+    render_recovery::{RecoveringRenderer, RecoveryPolicy, RenderRecovery},
+    render_scale::{DrawUpscaleDesc, RenderScale},
+    resource_bar::{FillDirection, ResourceBarBuilder, ResourceBarSystem},
+    rewind::{RewindBuffer, RewindRecordSystem, RewindSystem},
+    rng::Rng,
+    rumble::{register_bump_rumble, RumbleConfig, RumbleSystem},
+    save::{SaveConfig, SaveGame, SaveSlots, SaveTag, UnsavedProgressSystem},
+    script::{run_script, show_message, ScriptEvent, ScriptMessageSystem, ScriptTriggerSystem},
+    shadow::{Shadow, ShadowOverlay, ShadowSystem},
+    sight::SightGrid,
+    sleep::SleepSystem,
+    sound::{SoundOcclusionConfig, SoundSystem},
+    spatial::SpatialGridSystem,
+    spawn::{protect_spawn, spawn_position, SpawnProtection, SpawnProtectionSystem},
+    split_screen::{
+        create_split_views, split_widths, SplitScreen, SplitScreenCompositeDesc,
+        SplitScreenToggleSystem, UiPlacement, ViewGroupDesc,
+    },
+    sprite_sort::{SpriteSortConfig, SpriteSortSystem},
+    sprite_viewer::{SpriteViewerConfig, SpriteViewerSystem},
+    step_button::{spawn_step_button, StepButtonSystem},
+    strings::{LocalizedTextSystem, Strings, StringsConfig},
+    telemetry::{Telemetry, TelemetryConfig, TelemetrySystem},
+    texel_snap::{TexelSnap, TexelSnapSystem},
+    tile_cursor::{TileCursorConfig, TileCursorOverlay, TileCursorSystem},
+    tile_map::{spawn_tile_map, TileAnimationSystem, TileEditSystem, TileMap},
+    ui_theme::{TextAccessibilitySystem, ThemedButtonSystem, UiTheme},
+    wave::{WaveEvent, WaveMember, WaveSpawns, WaveSystem},
+    weather::{Weather, WeatherOverlay, WeatherSystem},
+    world_hash::WorldHashSystem,
+    world_text::{spawn_signs, GlyphMetrics, WorldTextSystem},
+};
 
-impl SimpleState for GameState {
-    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
-        let sprite_sheet_handle = {
-            let app_root = application_root_dir().expect("Could not load app root directory");
-            let desert_images_path = app_root.join("resources/textures/sample/");
-            let packed_image_path = desert_images_path.join("packed.png");
-            let packed_image_info_path = desert_images_path.join("packed.ron");
-
-            assert_eq!(
-                packed_image_path.exists(),
-                true,
-                "Desert packed image path must exist"
-            );
-            assert_eq!(
-                packed_image_info_path.exists(),
-                true,
-                "Desert packed image tile info path must exist"
-            );
+struct GameState<'a, 'b> {
+    /// Number of players sharing this machine, each with their own bindings.
+    local_players: usize,
+    /// Seed of the gameplay `Rng`. Runs with the same seed and inputs play out identically.
+    seed: u64,
+    /// The level's main sprite sheet, shared by the tiles and the players.
+    sprite_sheet: Handle<SpriteSheet>,
+    map: TileMap,
+    /// The save a loaded game was picked up from.
+    saved: Option<SaveGame>,
+    /// Systems that advance the game itself, run once per fixed step rather than once per frame.
+    gameplay: Option<Dispatcher<'a, 'b>>,
+    /// Paces the gameplay steps, by smoothed frame times when `FrameSmoothing` is enabled.
+    smoothed_steps: Option<SmoothedSteps>,
+    was_pause_pressed: bool,
+    script_events: Option<ReaderId<ScriptEvent>>,
+    wave_events: Option<ReaderId<WaveEvent>>,
+}
 
-            let desert_image_path = packed_image_path
-                .to_str()
-                .expect("Expected image path string");
-            let desert_ron_path = packed_image_info_path
-                .to_str()
-                .expect("Expected ron path string");
-
-            let loader = data.world.read_resource::<Loader>();
-            let texture_handle = loader.load(
-                desert_image_path.to_string(),
-                ImageFormat::default(),
-                (),
-                &data.world.read_resource::<AssetStorage<Texture>>(),
-            );
+impl<'a, 'b> SimpleState for GameState<'a, 'b> {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let mut gameplay = DispatcherBuilder::new()
+            .with_pool(data.world.read_resource::<ArcThreadPool>().clone())
+            .with(GameClockSystem, "game_clock_system", &[])
+            .with(DisabledSystem, "disabled_system", &["game_clock_system"])
+            .with(SleepSystem, "sleep_system", &["game_clock_system"])
+            .with(
+                TileAnimationSystem,
+                "tile_animation_system",
+                &["game_clock_system"],
+            )
+            .with(
+                SpriteAnimationSystem,
+                "sprite_animation_system",
+                &["game_clock_system"],
+            )
+            .with(
+                AnimationSoundSystem::default(),
+                "animation_sound_system",
+                &["sprite_animation_system"],
+            )
+            .with(CutsceneSystem, "cutscene_system", &["disabled_system"])
+            .with(
+                PlayerMovementSystem,
+                "player_movement_system",
+                &["cutscene_system"],
+            )
+            .with(
+                PathFollowSystem,
+                "path_follow_system",
+                &["player_movement_system"],
+            )
+            .with(
+                GodModeSystem::default(),
+                "god_mode_system",
+                &["game_clock_system"],
+            )
+            .with(
+                DashSystem::default(),
+                "dash_system",
+                &["path_follow_system", "god_mode_system"],
+            )
+            .with(
+                Profiled::new("chase_system", ChaseSystem),
+                "chase_system",
+                &["sleep_system"],
+            )
+            .with(
+                Profiled::new("movement_system", MovementSystem),
+                "movement_system",
+                &[
+                    "dash_system",
+                    "chase_system",
+                    "disabled_system",
+                    "sleep_system",
+                ],
+            )
+            .with(
+                Profiled::new("contact_system", ContactSystem),
+                "contact_system",
+                &["movement_system"],
+            )
+            .with(
+                FootstepSystem::default(),
+                "footstep_system",
+                &["movement_system"],
+            )
+            .with(IsoSortSystem, "iso_sort_system", &["movement_system"])
+            .with(
+                Profiled::new("spatial_grid_system", SpatialGridSystem),
+                "spatial_grid_system",
+                &["movement_system"],
+            )
+            .with(
+                ScriptTriggerSystem::default(),
+                "script_trigger_system",
+                &["spatial_grid_system"],
+            )
+            .with(
+                Profiled::new("auto_attack_system", AutoAttackSystem),
+                "auto_attack_system",
+                &["spatial_grid_system", "god_mode_system"],
+            )
+            .with(
+                InvulnerabilitySystem,
+                "invulnerability_system",
+                &["game_clock_system"],
+            )
+            .with(
+                SpawnProtectionSystem,
+                "spawn_protection_system",
+                &["game_clock_system"],
+            )
+            .with(DebugDamageSystem { amount: 1. }, "debug_damage_system", &[])
+            .with(
+                HitboxSystem::default(),
+                "hitbox_system",
+                &["sprite_animation_system", "movement_system"],
+            )
+            .with(
+                Profiled::new("damage_system", DamageSystem::default()),
+                "damage_system",
+                &[
+                    "invulnerability_system",
+                    "debug_damage_system",
+                    "auto_attack_system",
+                    "hitbox_system",
+                ],
+            )
+            .with(DeathSystem, "death_system", &["damage_system"])
+            .with(
+                DamageLogSystem::default(),
+                "damage_log_system",
+                &["damage_system"],
+            )
+            .with(
+                UnsavedProgressSystem::default(),
+                "unsaved_progress_system",
+                &["movement_system", "damage_system"],
+            )
+            .with(EntityLimitSystem, "entity_limit_system", &["death_system"])
+            .with(
+                DespawnSystem,
+                "despawn_system",
+                &["death_system", "entity_limit_system"],
+            )
+            .with(WaveSystem::default(), "wave_system", &["death_system"])
+            .with(
+                TelemetrySystem::default(),
+                "telemetry_system",
+                &["damage_system", "wave_system"],
+            )
+            .with_barrier()
+            .with(WorldHashSystem, "world_hash_system", &[])
+            .with(RewindRecordSystem, "rewind_record_system", &[])
+            .build();
+        gameplay.setup(&mut data.world.res);
+        self.script_events = Some(
+            data.world
+                .write_resource::<EventChannel<ScriptEvent>>()
+                .register_reader(),
+        );
+        self.wave_events = Some(
+            data.world
+                .write_resource::<EventChannel<WaveEvent>>()
+                .register_reader(),
+        );
+        // Without an audio device, scripted sounds are dropped.
+        init_output(&mut data.world.res);
+        self.gameplay = Some(gameplay);
+        // Handlers from the last game go with it.
+        let mut callbacks = CollisionCallbacks::default();
+        register_bump_rumble(&mut callbacks);
+        data.world.add_resource(callbacks);
+        let smoothing = *data.world.read_resource::<FrameSmoothing>();
+        self.smoothed_steps = Some(SmoothedSteps::from_config(&smoothing));
+        data.world.add_resource(Rng::new(self.seed));
+        data.world.write_resource::<DeathBursts>().seed = self.seed;
+        data.world.add_resource(RewindBuffer::default());
+        let playtime = self.saved.as_ref().map_or(0., |saved| saved.playtime);
+        if let Some(saved) = &self.saved {
+            data.world.write_resource::<Difficulty>().level = saved.difficulty;
+        }
+        data.world.add_resource(Playtime::new(playtime));
+        let now = data.world.read_resource::<GameClock>().elapsed_seconds();
+        data.world
+            .write_resource::<Telemetry>()
+            .start_level(now, &level_name());
 
-            let sprite_sheet_handle = loader.load(
-                desert_ron_path.to_string(),
-                SpriteSheetFormat(texture_handle),
-                (),
-                &data.world.read_resource::<AssetStorage<SpriteSheet>>(),
-            );
-            sprite_sheet_handle
+        let sprite_sheet_handle = self.sprite_sheet.clone();
+        // Spawns are kept out of the map's walls, so it has to be walkable first.
+        data.world.add_resource(NavGrid::from_map(&self.map));
+        // Enemies are placed from where the map is, which starts at the world's origin.
+        data.world.add_resource(self.map.clone());
+        data.world.add_resource(SpawnProtection::default());
+        spawn_tile_map(data.world, &self.map, sprite_sheet_handle.clone());
+        spawn_signs(data.world, &self.map.signs);
+        spawn_enemies(data.world, &self.map.enemies, sprite_sheet_handle.clone());
+        data.world.add_resource(SightGrid::from_map(&self.map));
+        data.world.add_resource(LightGrid::from_map(&self.map));
+        let walls = TileColliders::from_map(&self.map, &data.world.read_resource());
+        data.world.add_resource(walls);
+        spawn_tile_lights(data.world, &self.map);
+        spawn_minimap(data.world, &self.map);
+        spawn_boss_bar(data.world);
+        data.world
+            .create_entity()
+            .with(NavPathOverlay)
+            .with(DebugLinesComponent::new())
+            .build();
+        data.world.add_resource(TourStops::from_map(&self.map));
+        // A loaded game picks up where it was, without showing the level off again.
+        let intro = match self.saved {
+            Some(_) => Vec::new(),
+            None => self.map.intro.clone(),
         };
+        data.world.add_resource(IntroSweep::new(intro));
+        // The press starting the game shouldn't skip its intro too.
+        data.world.write_resource::<AnyInput>().rearm();
+        // Nor should the last game's shake carry over into it.
+        data.world.add_resource(Trauma::default());
+        data.world.add_resource(Weather::new(self.map.weather));
+        data.world
+            .create_entity()
+            .with(WeatherOverlay)
+            .with(DebugLinesComponent::new())
+            .build();
+        data.world
+            .create_entity()
+            .with(DeathBurstOverlay)
+            .with(DebugLinesComponent::new())
+            .build();
+        data.world
+            .create_entity()
+            .with(TileCursorOverlay)
+            .with(DebugLinesComponent::new())
+            .build();
+        data.world
+            .create_entity()
+            .with(ShadowOverlay)
+            .with(DebugLinesComponent::new())
+            .build();
+        data.world
+            .create_entity()
+            .with(OutlineOverlay)
+            .with(DebugLinesComponent::new())
+            .build();
+
+        // No system reads save tags, so their storage isn't set up along with the systems.
+        data.world.register::<SaveTag>();
         self.initialize_game_textures(data.world, sprite_sheet_handle);
-        self.initialise_camera(data.world);
+        let rooms = self.map.rooms.clone();
+        self.initialise_camera(data.world, &rooms);
+        create_split_views(data.world, &rooms);
+        spawn_debug_overlay(data.world);
+        let log_lines = data.world.read_resource::<LogViewerConfig>().lines;
+        spawn_log_viewer(data.world, log_lines);
+        spawn_step_button(data.world);
+    }
+
+    /// Leaves nothing of the game behind for the state replacing it.
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let now = data.world.read_resource::<GameClock>().elapsed_seconds();
+        data.world.write_resource::<Telemetry>().end_level(now);
+        data.world.delete_all();
+        data.world.write_resource::<ActiveCamera>().entity = None;
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        if data.world.read_resource::<FrameCheck>().is_finished() {
+            return Trans::Quit;
+        }
+        count_playtime(data.world);
+        if let Some(smoothed_steps) = self.smoothed_steps.as_mut() {
+            let steps = {
+                let time = data.world.read_resource::<Time>();
+                smoothed_steps.steps(time.delta_seconds(), time.fixed_seconds())
+            };
+            for _ in 0..steps {
+                self.step_gameplay(data.world);
+            }
+        }
+        self.run_scripts(data.world);
+        self.run_waves(data.world);
+
+        let pressed = data
+            .world
+            .read_resource::<InputHandler<StringBindings>>()
+            .action_is_down("pause")
+            .unwrap_or(false);
+        let pause = pressed && !self.was_pause_pressed;
+        self.was_pause_pressed = pressed;
+        if pause {
+            Trans::Push(Box::new(PauseState::new(self.local_players, self.seed)))
+        } else {
+            Trans::None
+        }
     }
 }
 
-impl GameState {
+impl<'a, 'b> GameState<'a, 'b> {
+    fn new(
+        local_players: usize,
+        seed: u64,
+        sprite_sheet: Handle<SpriteSheet>,
+        map: TileMap,
+        saved: Option<SaveGame>,
+    ) -> Self {
+        GameState {
+            local_players,
+            seed,
+            sprite_sheet,
+            map,
+            saved,
+            gameplay: None,
+            smoothed_steps: None,
+            was_pause_pressed: false,
+            script_events: None,
+            wave_events: None,
+        }
+    }
+
+    /// Runs the scripts the triggers have asked for since the last frame.
+    fn run_scripts(&mut self, world: &mut World) {
+        let reader = self.script_events.as_mut().expect("Game is started");
+        let actions: Vec<String> = world
+            .read_resource::<EventChannel<ScriptEvent>>()
+            .read(reader)
+            .map(|event| event.action.clone())
+            .collect();
+        for action in actions {
+            run_script(world, &action, &self.sprite_sheet);
+        }
+    }
+
+    /// Spawns the enemies the waves have asked for since the last frame, and announces the
+    /// waves starting and being cleared.
+    fn run_waves(&mut self, world: &mut World) {
+        let spawns = std::mem::take(&mut world.write_resource::<WaveSpawns>().0);
+        if !spawns.is_empty() {
+            let enemies = spawn_enemies(world, &spawns, self.sprite_sheet.clone());
+            let mut members = world.write_storage::<WaveMember>();
+            for enemy in enemies {
+                members.insert(enemy, WaveMember).expect("Enemy is alive");
+            }
+        }
+        let reader = self.wave_events.as_mut().expect("Game is started");
+        let events: Vec<WaveEvent> = world
+            .read_resource::<EventChannel<WaveEvent>>()
+            .read(reader)
+            .copied()
+            .collect();
+        for event in events {
+            let text = {
+                let strings = world.read_resource::<Strings>();
+                match event {
+                    WaveEvent::Started(wave) => strings.format("wave.started", &[&wave]),
+                    WaveEvent::Cleared(wave) => strings.format("wave.cleared", &[&wave]),
+                }
+            };
+            show_message(world, text, 2.);
+        }
+    }
+
+    /// Runs one gameplay step, unless attract mode, the intro sweep or `FrameStep` is holding
+    /// the game.
+    fn step_gameplay(&mut self, world: &mut World) {
+        if let Some(gameplay) = self.gameplay.as_mut() {
+            let attracting = world.read_resource::<AttractMode>().active;
+            let intro = world.read_resource::<IntroSweep>().is_holding();
+            if !attracting && !intro && world.write_resource::<FrameStep>().take_step() {
+                rebase_if_far(world);
+                gameplay.dispatch(&world.res);
+                run_collision_callbacks(world);
+            }
+        }
+    }
+
     fn initialize_game_textures(
         &mut self,
         world: &mut World,
-        sprite_sheet_handle: Handle<SpriteSheet>
+        sprite_sheet_handle: Handle<SpriteSheet>,
     ) {
         let (width, height) = {
             let dimensions = world.read_resource::<ScreenDimensions>();
             (dimensions.width(), dimensions.height())
         };
 
-        let mut sprite_transform = Transform::default();
-        sprite_transform.set_translation_xyz(width / 2., height / 2., 0.);
+        for index in 0..self.local_players {
+            // Line the players up either side of the centre of the screen.
+            let offset = (index as f32 - (self.local_players as f32 - 1.) / 2.) * 64.;
+            let collider = Collider::new(32., 32.);
+            let saved = self
+                .saved
+                .as_ref()
+                .and_then(|saved| saved.player(index))
+                .unwrap_or_default();
+            let position = match saved.position {
+                Some((x, y)) => Vector2::new(x, y),
+                None => {
+                    let desired = Vector2::new(width / 2. + offset, height / 2.);
+                    // A player can't be left out like an enemy, so one with no room spawns
+                    // where it was meant to all the same.
+                    spawn_position(world, desired, &collider).unwrap_or_else(|| {
+                        warn!(
+                            "No room to spawn player {} near ({}, {}), spawning it there anyway",
+                            index + 1,
+                            desired.x,
+                            desired.y
+                        );
+                        desired
+                    })
+                }
+            };
+            let mut health = Health::new(100.);
+            if let Some(saved) = saved.health {
+                health.current = saved.min(health.max);
+            }
+            let mut sprite_transform = Transform::default();
+            sprite_transform.set_translation_xyz(position.x, position.y, 0.);
 
-        let sprite_render = SpriteRender {
-            sprite_sheet: sprite_sheet_handle,
-            sprite_number: 0,
-        };
+            let sprite_render = SpriteRender {
+                sprite_sheet: sprite_sheet_handle.clone(),
+                sprite_number: 0,
+            };
 
-        world
-            .create_entity()
-            .with(sprite_render)
-            .with(sprite_transform)
-            .with(Transparent)
-            .build();
+            let player = world
+                .create_entity()
+                .with(sprite_render)
+                .with(sprite_transform)
+                .with(Transparent)
+                .with(Player { index, speed: 150. })
+                .with(health)
+                .with(Velocity::default())
+                .with(Facing::default())
+                .with(collider)
+                .with(Hurtbox::new(32., 32.))
+                .with(PixelPerfect)
+                .with(Dash::new(96., 0.15, 0.25, 0.8))
+                .with(AutoAttack::new(80., 5., 0.6))
+                .with(Shadow::default())
+                .with(IsoSorted::default())
+                .with(Lit)
+                .with(BlockLight)
+                .with(CameraTarget)
+                .with(Footsteps::default())
+                .with(AlwaysVisibleOutline)
+                .with(SaveTag)
+                .build();
+            protect_spawn(world, player);
+            let parts = world
+                .read_resource::<AttachmentConfig>()
+                .player_parts
+                .clone();
+            for part in &parts {
+                attach(world, player, part, sprite_sheet_handle.clone());
+            }
+
+            // Each player's health along the bottom of the window, with a small gauge beside
+            // it refilling as the dash recovers. The first player's are on the left and the
+            // second's mirrored on the right.
+            let (anchor, health_x, dash_x, direction) = match index {
+                0 => (Anchor::BottomLeft, 8., 176., FillDirection::LeftToRight),
+                _ => (
+                    Anchor::BottomRight,
+                    -168.,
+                    -184.,
+                    FillDirection::LeftToRight.mirrored(),
+                ),
+            };
+            ResourceBarBuilder::new(player, |health: &Health| (health.current, health.max))
+                .with_position(anchor.clone(), Vector2::new(health_x, 8.))
+                .with_direction(direction)
+                .build(world);
+            ResourceBarBuilder::new(player, |dash: &Dash| {
+                let cooldown = dash.cooldown;
+                (cooldown.duration - cooldown.remaining(), cooldown.duration)
+            })
+            .with_position(anchor, Vector2::new(dash_x, 8.))
+            .with_size(Vector2::new(8., 24.))
+            .with_direction(FillDirection::BottomToTop)
+            .with_colors([0.15, 0.15, 0.15, 0.8], [0.3, 0.6, 0.9, 1.])
+            .build(world);
+        }
     }
 
     /// This method initialises a camera which will view our sprite.
-    fn initialise_camera(&mut self, world: &mut World) {
+    fn initialise_camera(&mut self, world: &mut World, rooms: &[Room]) {
         let (width, height) = {
             let dim = world.read_resource::<ScreenDimensions>();
             (dim.width(), dim.height())
         };
+        let first_player = {
+            let entities = world.entities();
+            let players = world.read_storage::<Player>();
+            (&entities, &players)
+                .join()
+                .find(|(_, player)| player.index == 0)
+                .map(|(entity, _)| entity)
+        };
 
+        let room_bounds = world.read_resource::<RoomBoundsConfig>().enabled;
+        let (map_min, map_max) = world.read_resource::<TileMap>().world_bounds();
+
+        let zoom = CameraZoom::new(1., 0.5, 1.);
         let mut camera_transform = Transform::default();
-        camera_transform.set_translation_xyz(0.0, height, 1.);
+        camera_transform.set_translation_xyz(width / 2., height / 2., 1.);
 
-        world
+        let builder = world
             .create_entity()
             .with(camera_transform)
-            // Define the view that the camera can see. It makes sense to keep the `near` value as
-            // 0.0, as this means it starts seeing anything that is 0 units in front of it. The
-            // `far` value is the distance the camera can see facing the origin.
-            .with(Camera::from(Projection::orthographic(
-                0.,
-                width,
-                0.,
-                height,
-                0.0,
-                20.0,
+            // Define the view that the camera can see, centred on the camera's position. The
+            // projection is kept in sync with `zoom` by the `CameraProjectionSystem`.
+            .with(Camera::from(centered_projection(
+                Vector2::new(width, height),
+                zoom.level(),
             )))
-            .build();
-
+            .with(zoom);
+        // Maps made of rooms get a room-by-room camera following the first player, or one
+        // following them within the room they are in, others a camera keeping everyone in view
+        // without straying off the map.
+        let camera = match first_player {
+            Some(player) if !rooms.is_empty() && room_bounds => builder
+                .with(CameraFollow::new(player, 6.))
+                .with(RoomBounds::new(player, rooms.to_vec()))
+                .build(),
+            Some(player) if !rooms.is_empty() => builder
+                .with(RoomCamera::new(player, rooms.to_vec(), 0.4, 8.))
+                .build(),
+            _ => builder
+                .with(ZoomToFit::new(64., 4.))
+                .with(CameraBounds::new(map_min, map_max).with_soft_zone(48.))
+                .build(),
+        };
+        world.add_resource(ActiveCamera {
+            entity: Some(camera),
+        });
     }
 }
 
 fn main() -> amethyst::Result<()> {
-    amethyst::start_logger(Default::default());
-
     let app_root = application_root_dir()?;
 
     let resources_dir = app_root.join("resources/");
+    let log_viewer = LogViewerConfig::load(resources_dir.join("log_viewer.ron"));
+    let log_history = LogHistory::new(log_viewer.capacity);
+    start_logging(
+        &resources_dir.join("logging.ron"),
+        &app_root,
+        log_history.clone(),
+    );
     let display_config_path = resources_dir.join("display_config.ron");
+    let control_schemes =
+        ControlSchemes::load(resources_dir.join("bindings.ron")).map_err(failure::Error::compat)?;
+    let saved_scheme_path = resources_dir.join("control_scheme.ron");
+    let control_scheme = control_schemes.initial(&saved_scheme_path);
+    let saved_bindings_path = resources_dir.join("bindings_saved.ron");
+    let custom_bindings = CustomBindings::load(&saved_bindings_path);
+    let bindings = custom_bindings
+        .bindings(&control_schemes, &control_scheme)
+        .map_err(failure::Error::compat)?;
+    let saved_analog_path = resources_dir.join("analog_saved.ron");
+    let analog_response =
+        AnalogResponse::initial(&resources_dir.join("analog.ron"), &saved_analog_path);
+
+    // Read by the rendering graph as well as the recording system.
+    let mut recording = RecordingConfig::load(resources_dir.join("recording.ron"));
+    let mut quality = QualityConfig::load(resources_dir.join("quality.ron"));
+    let mut display_config = DisplayConfig::load(display_config_path);
+    // A frame check reads frames back itself, at the first quality tier, and needs no window
+    // on screen.
+    let frame_check_config = FrameCheckConfig::load(resources_dir.join("frame_check.ron"));
+    let frame_check = FrameCheck::new(&frame_check_config);
+    if frame_check.is_running() {
+        recording.enabled = false;
+        quality.enabled = false;
+        display_config.visibility = false;
+    }
 
     let game_data = GameDataBuilder::default()
-        .with_bundle(WindowBundle::from_config_path(display_config_path))?
-        .with_bundle(TransformBundle::new())?
+        .with_bundle(WindowBundle::from_config(display_config))?
+        // Ahead of the transform systems, which delete the parts of despawned parents.
+        .with(AttachmentSystem, "attachment_system", &[])
+        .with_bundle(TransformBundle::new().with_dep(&["attachment_system"]))?
+        .with_bundle(InputBundle::<StringBindings>::new().with_bindings(bindings))?
+        .with(
+            ControlSchemeSystem::new(
+                control_schemes,
+                custom_bindings,
+                saved_scheme_path,
+                saved_bindings_path,
+                control_scheme,
+            ),
+            "control_scheme_system",
+            &["input_system"],
+        )
+        .with(
+            AnalogResponseSaveSystem::new(saved_analog_path, analog_response.clone()),
+            "analog_response_save_system",
+            &[],
+        )
+        .with(
+            MenuFocusSystem::default(),
+            "menu_focus_system",
+            &["input_system"],
+        )
+        .with(
+            TextAccessibilitySystem::default(),
+            "text_accessibility_system",
+            &["input_system"],
+        )
+        .with(
+            ThemedButtonSystem::default(),
+            "themed_button_system",
+            &["text_accessibility_system", "menu_focus_system"],
+        )
+        .with(
+            FrameStepSystem::default(),
+            "frame_step_system",
+            &["input_system"],
+        )
+        .with(
+            StepButtonSystem::default(),
+            "step_button_system",
+            &["themed_button_system", "frame_step_system"],
+        )
+        .with(CameraSteadySystem, "camera_steady_system", &[])
+        .with(
+            AttractModeSystem::new(AttractConfig::load(resources_dir.join("attract.ron"))),
+            "attract_mode_system",
+            &["input_system", "camera_steady_system"],
+        )
+        .with(
+            AnyInputSystem::default(),
+            "any_input_system",
+            &["input_system"],
+        )
+        .with(
+            IntroSystem::default(),
+            "intro_system",
+            &["any_input_system", "camera_steady_system"],
+        )
+        .with(
+            DamageLogDumpSystem::new(app_root.join("damage_log.csv")),
+            "damage_log_dump_system",
+            &["input_system"],
+        )
+        .with(
+            RecordingSystem::new(recording.clone(), &app_root),
+            "recording_system",
+            &["input_system"],
+        )
+        .with(
+            FrameCheckSystem::new(frame_check_config.clone(), &app_root),
+            "frame_check_system",
+            &[],
+        )
+        .with(
+            MapExportSystem::new(
+                MapExportConfig::load(resources_dir.join("map_export.ron")),
+                app_root.clone(),
+            ),
+            "map_export_system",
+            &["input_system"],
+        )
+        .with(
+            AdaptiveQualitySystem::new(quality),
+            "adaptive_quality_system",
+            &[],
+        )
+        .with(
+            DebugOverlaySystem::default(),
+            "debug_overlay_system",
+            &["input_system"],
+        )
+        .with(
+            LogViewerSystem::new(log_viewer),
+            "log_viewer_system",
+            &["input_system"],
+        )
+        .with(
+            ProfilerSystem::new(ProfilerConfig::load(resources_dir.join("profiler.ron"))),
+            "profiler_system",
+            &["input_system"],
+        )
+        .with(RewindSystem::default(), "rewind_system", &["input_system"])
+        .with(
+            PaletteFilterSystem::default(),
+            "palette_filter_system",
+            &["input_system"],
+        )
+        .with(
+            AutoAttackToggleSystem::default(),
+            "auto_attack_toggle_system",
+            &["input_system"],
+        )
+        .with(
+            GodModeToggleSystem::default(),
+            "god_mode_toggle_system",
+            &["input_system"],
+        )
+        .with(
+            JoinBenchmarkSystem::new(JoinBenchmarkConfig::load(
+                resources_dir.join("join_benchmark.ron"),
+            )),
+            "join_benchmark_system",
+            &["input_system"],
+        )
+        .with(
+            BoundsPolicyToggleSystem::default(),
+            "bounds_policy_toggle_system",
+            &["input_system"],
+        )
+        .with(
+            ZoomToFitSystem,
+            "zoom_to_fit_system",
+            &["bounds_policy_toggle_system", "camera_steady_system"],
+        )
+        .with(
+            CameraFollowSystem,
+            "camera_follow_system",
+            &["camera_steady_system"],
+        )
+        .with(
+            RoomCameraSystem,
+            "room_camera_system",
+            &["camera_steady_system"],
+        )
+        .with(
+            SpeedZoomSystem::new(SpeedZoomConfig::load(resources_dir.join("speed_zoom.ron"))),
+            "speed_zoom_system",
+            &["camera_follow_system"],
+        )
+        .with(
+            ZoomPresetSystem::new(ZoomPresets::load(resources_dir.join("zoom_presets.ron"))),
+            "zoom_preset_system",
+            &["input_system", "zoom_to_fit_system", "room_camera_system"],
+        )
+        .with(
+            RecenterSystem::new(RecenterConfig::load(
+                resources_dir.join("camera_recenter.ron"),
+            )),
+            "camera_recenter_system",
+            &[
+                "input_system",
+                "zoom_to_fit_system",
+                "camera_follow_system",
+                "speed_zoom_system",
+                "room_camera_system",
+                "zoom_preset_system",
+            ],
+        )
+        // After the recenter, so easing back to the player can't take the view out of the room.
+        .with(
+            RoomBoundsSystem,
+            "room_bounds_system",
+            &[
+                "camera_follow_system",
+                "speed_zoom_system",
+                "camera_recenter_system",
+            ],
+        )
+        .with(
+            SplitScreenToggleSystem::default(),
+            "split_screen_toggle_system",
+            &["input_system"],
+        )
+        .with(
+            CameraShakeSystem::new(ShakeConfig::load(resources_dir.join("shake.ron"))),
+            "camera_shake_system",
+            &[
+                "zoom_to_fit_system",
+                "camera_follow_system",
+                "speed_zoom_system",
+                "room_camera_system",
+                "room_bounds_system",
+                "zoom_preset_system",
+                "camera_recenter_system",
+                "attract_mode_system",
+                "intro_system",
+            ],
+        )
+        .with(
+            CameraProjectionSystem,
+            "camera_projection_system",
+            &[
+                "camera_shake_system",
+                "zoom_to_fit_system",
+                "camera_follow_system",
+                "speed_zoom_system",
+                "room_camera_system",
+                "room_bounds_system",
+                "zoom_preset_system",
+                "split_screen_toggle_system",
+                "attract_mode_system",
+                "intro_system",
+            ],
+        )
+        .with(
+            Profiled::new("camera_matrices_system", CameraMatricesSystem),
+            "camera_matrices_system",
+            &["camera_projection_system"],
+        )
+        .with(
+            WeatherSystem,
+            "weather_system",
+            &["camera_projection_system"],
+        )
+        .with(DeathBurstSystem, "death_burst_system", &[])
+        .with(ShadowSystem::default(), "shadow_system", &["input_system"])
+        .with(
+            ClickToMoveSystem::default(),
+            "click_to_move_system",
+            &["camera_projection_system"],
+        )
+        .with(
+            TileCursorSystem::new(TileCursorConfig::load(
+                resources_dir.join("tile_cursor.ron"),
+            )),
+            "tile_cursor_system",
+            &["camera_matrices_system"],
+        )
+        .with(
+            SpriteViewerSystem::new(SpriteViewerConfig::load(
+                resources_dir.join("sprite_viewer.ron"),
+            )),
+            "sprite_viewer_system",
+            &["camera_projection_system"],
+        )
+        .with(
+            TexelSnapSystem::default(),
+            "texel_snap_system",
+            &[
+                "camera_matrices_system",
+                "weather_system",
+                "click_to_move_system",
+                "sprite_viewer_system",
+            ],
+        )
+        .with(
+            Profiled::new("parallax_system", ParallaxSystem),
+            "parallax_system",
+            &["texel_snap_system"],
+        )
+        .with(
+            TileEditSystem::default(),
+            "tile_edit_system",
+            &["tile_cursor_system"],
+        )
+        .with(
+            Profiled::new("lighting_system", LightingSystem::default()),
+            "lighting_system",
+            &["tile_edit_system"],
+        )
+        .with(
+            HighlightPulseSystem::new(HighlightConfig::load(resources_dir.join("highlight.ron"))),
+            "highlight_pulse_system",
+            &["lighting_system"],
+        )
+        .with(
+            OutlineSystem::new(OutlineConfig::load(resources_dir.join("outline.ron"))),
+            "outline_system",
+            &["lighting_system"],
+        )
+        .with(
+            MinimapSystem::default(),
+            "minimap_system",
+            &["input_system", "lighting_system"],
+        )
+        .with(
+            WorldTextSystem::new(GlyphMetrics::default()),
+            "world_text_system",
+            &["camera_matrices_system"],
+        )
+        .with(
+            InteractionSystem,
+            "interaction_system",
+            &["world_text_system"],
+        )
+        .with(LocalizedTextSystem::default(), "localized_text_system", &[])
+        .with(
+            CombatTextSystem::new(CombatTextConfig::load(
+                resources_dir.join("combat_text.ron"),
+            )),
+            "combat_text_system",
+            &["camera_matrices_system"],
+        )
+        .with(BossBarSystem::default(), "boss_bar_system", &[])
+        .with(
+            ResourceBarSystem::<Health>::default(),
+            "health_bar_system",
+            &[],
+        )
+        .with(ResourceBarSystem::<Dash>::default(), "dash_bar_system", &[])
         .with(
             Processor::<SpriteSheet>::new(),
             "sprite_sheet_processor",
             &[],
         )
+        .with(Processor::<Source>::new(), "source_processor", &[])
+        .with(
+            SoundSystem::new(SoundOcclusionConfig::load(
+                resources_dir.join("sound_occlusion.ron"),
+            )),
+            "sound_system",
+            &["source_processor"],
+        )
+        .with(RumbleSystem::default(), "rumble_system", &[])
+        .with(ScriptMessageSystem, "script_message_system", &[])
         .with(
-            SpriteVisibilitySortingSystem::new(),
+            Profiled::new("sprite_visibility_system", SpriteSortSystem::default()),
             "sprite_visibility_system",
             &[],
         )
-        .with_bundle(UiBundle::<DefaultBackend, StringBindings>::new())?
-        .with_thread_local(RenderingSystem::<DefaultBackend, _>::new(
-            RenderingGraph::default(),
+        .with_bundle(UiBundle::<GameBackend, StringBindings>::new())?
+        .with_thread_local(RecoveringRenderer::new(
+            ProfiledLocal::new(
+                "rendering_system",
+                RenderingSystem::<GameBackend, _>::new(RenderingGraph::default()),
+            ),
+            RecoveryPolicy::default(),
         ));
 
-    let mut game = Application::new(
-        resources_dir,
-        GameState {},
-        game_data,
-    )?;
+    // Loaded up front, as the menus are themed too and can change both.
+    let ui_theme = UiTheme::load(resources_dir.join("ui_theme.ron"));
+    let palette_filter = PaletteFilter::load(resources_dir.join("palette.ron"));
+    let texel_snap = TexelSnap::load(resources_dir.join("texel_snap.ron"));
+    let aim_assist = AimAssist::load(resources_dir.join("aim_assist.ron"));
+    let god_mode = GodMode::load(resources_dir.join("god_mode.ron"));
+    let rumble = RumbleConfig::load(resources_dir.join("rumble.ron"));
+    let sprite_sort = SpriteSortConfig::load(resources_dir.join("sprite_sort.ron"));
+    let telemetry = Telemetry::start(
+        &TelemetryConfig::load(resources_dir.join("telemetry.ron")),
+        &app_root,
+    );
+    let any_input = AnyInput::new(AnyInputConfig::load(resources_dir.join("any_input.ron")));
+    let difficulty = Difficulty::new(DifficultyConfig::load(resources_dir.join("difficulty.ron")));
+    let strings = Strings::load(
+        resources_dir.join("locales"),
+        StringsConfig::load(resources_dir.join("strings.ron")),
+    );
+    let save_slots = SaveSlots::new(
+        resources_dir.join("saves"),
+        SaveConfig::load(resources_dir.join("saves.ron")),
+    );
+    let mut game = Application::build(resources_dir, MainMenuState::new(2, 0x5eed))?
+        .with_resource(ui_theme)
+        .with_resource(palette_filter)
+        .with_resource(texel_snap)
+        .with_resource(strings)
+        .with_resource(save_slots)
+        .with_resource(difficulty)
+        .with_resource(rumble)
+        .with_resource(sprite_sort)
+        .with_resource(any_input)
+        .with_resource(recording)
+        .with_resource(frame_check_config)
+        .with_resource(frame_check.clone())
+        .with_resource(telemetry)
+        .with_resource(aim_assist)
+        .with_resource(god_mode)
+        .with_resource(analog_response)
+        .with_resource(log_viewer)
+        .with_resource(log_history)
+        .build(game_data)?;
     game.run();
 
+    if frame_check.failed() {
+        return Err(amethyst::Error::from_string("Frame check failed"));
+    }
     Ok(())
 }
 
+const CLEAR_COLOR: [f32; 4] = [0.34, 0.36, 0.52, 1.0];
+
 #[derive(Default)]
 struct RenderingGraph {
     dimensions: Option<ScreenDimensions>,
     surface_format: Option<Format>,
+    split_screen: SplitScreen,
+    palette_filter: bool,
+    texel_snap: bool,
+    /// The size the scene was last drawn at.
+    scene_size: Option<(u32, u32)>,
     dirty: bool,
 }
 
-impl GraphCreator<DefaultBackend> for RenderingGraph {
+impl RenderingGraph {
+    /// The size the scene is to be drawn at, once the window's size is known.
+    fn scene_size(&self, res: &Resources) -> Option<(u32, u32)> {
+        let dimensions = self.dimensions.as_ref()?;
+        let (width, height) = (dimensions.width() as u32, dimensions.height() as u32);
+        // The UI drawn in each view is drawn at the scene's size, so the scene isn't scaled.
+        if self.split_screen.enabled && self.split_screen.ui == UiPlacement::PerView {
+            return Some((width, height));
+        }
+        let scale = res
+            .try_fetch::<RenderScale>()
+            .map(|scale| *scale)
+            .unwrap_or_default();
+        Some(scale.scene_size(width, height))
+    }
+}
+
+impl<B: Backend> GraphCreator<B> for RenderingGraph {
     fn rebuild(&mut self, res: &Resources) -> bool {
+        // Rebuild straight away after a failed frame, which may have lost the surface.
+        if res
+            .try_fetch_mut::<RenderRecovery>()
+            .is_some_and(|mut recovery| recovery.take_rebuild())
+        {
+            return true;
+        }
+
+        // Rebuild straight away when split-screen is toggled.
+        let split_screen = res
+            .try_fetch::<SplitScreen>()
+            .map(|split_screen| *split_screen)
+            .unwrap_or_default();
+        if self.split_screen != split_screen {
+            self.split_screen = split_screen;
+            self.dirty = true;
+        }
+
+        // Rebuild when the palette filter is switched on or off, but not between its modes.
+        let palette_filter = res
+            .try_fetch::<PaletteFilter>()
+            .is_some_and(|filter| filter.enabled());
+        if self.palette_filter != palette_filter {
+            self.palette_filter = palette_filter;
+            self.dirty = true;
+        }
+
+        // Rebuild when texel snapping is switched on or off, which routes the scene through the
+        // upscale pass.
+        let texel_snap = res
+            .try_fetch::<TexelSnap>()
+            .is_some_and(|snap| snap.enabled);
+        if self.texel_snap != texel_snap {
+            self.texel_snap = texel_snap;
+            self.dirty = true;
+        }
+
         // Rebuild when dimensions change, but wait until at least two frames have the same.
         let new_dimensions = res.try_fetch::<ScreenDimensions>();
-        use std::ops::Deref;
-        if self.dimensions.as_ref() != new_dimensions.as_ref().map(|d| d.deref()) {
+        if self.dimensions.as_ref() != new_dimensions.as_deref() {
             self.dirty = true;
             self.dimensions = new_dimensions.map(|d| d.clone());
             return false;
         }
 
+        // Rebuild when the render scale changes the size the scene is drawn at.
+        let scene_size = self.scene_size(res);
+        if self.scene_size != scene_size {
+            self.scene_size = scene_size;
+            self.dirty = true;
+        }
+
         self.dirty
     }
 
-    fn builder(
-        &mut self,
-        factory: &mut Factory<DefaultBackend>,
-        res: &Resources,
-    ) -> GraphBuilder<DefaultBackend, Resources> {
+    fn builder(&mut self, factory: &mut Factory<B>, res: &Resources) -> GraphBuilder<B, Resources> {
         use amethyst::renderer::rendy::{
             graph::present::PresentNode,
             hal::command::{ClearDepthStencil, ClearValue},
@@ -205,35 +1292,187 @@ impl GraphCreator<DefaultBackend> for RenderingGraph {
             .surface_format
             .get_or_insert_with(|| factory.get_surface_format(&surface));
         let dimensions = self.dimensions.as_ref().unwrap();
-        let window_kind = image::Kind::D2(dimensions.width() as u32, dimensions.height() as u32, 1, 1);
+        let window_kind =
+            image::Kind::D2(dimensions.width() as u32, dimensions.height() as u32, 1, 1);
+        // Below full scale, the scene is drawn small and stretched over the window for the UI.
+        let (scene_width, scene_height) = self.scene_size(res).unwrap();
+        let scene_kind = image::Kind::D2(scene_width, scene_height, 1, 1);
+        let scaled = scene_kind != window_kind;
+        // Texel snapping shifts the scene as the upscale pass draws it, so needs it even at full
+        // scale.
+        let upscale = scaled || (self.texel_snap && !self.split_screen.enabled);
 
         let mut graph_builder = GraphBuilder::new();
+        // When split, the views are composited into `color` before anything else draws on it,
+        // so it must not be cleared.
         let color = graph_builder.create_image(
-            window_kind,
+            scene_kind,
             1,
             surface_format,
-            Some(ClearValue::Color([0.34, 0.36, 0.52, 1.0].into())),
+            if self.split_screen.enabled {
+                None
+            } else {
+                Some(ClearValue::Color(CLEAR_COLOR.into()))
+            },
         );
 
         let depth = graph_builder.create_image(
-            window_kind,
+            scene_kind,
             1,
             Format::D32Sfloat,
             Some(ClearValue::DepthStencil(ClearDepthStencil(1.0, 0))),
         );
 
-        let pass = graph_builder.add_node(
-            SubpassBuilder::new()
+        let pass = if self.split_screen.enabled {
+            let ui_per_view = self.split_screen.ui == UiPlacement::PerView;
+            let (left, right) = split_widths(scene_width);
+            let views: Vec<_> = [left, right]
+                .iter()
+                .enumerate()
+                .map(|(view, &width)| {
+                    let view_kind = image::Kind::D2(width, scene_height, 1, 1);
+                    let view_color = graph_builder.create_image(
+                        view_kind,
+                        1,
+                        surface_format,
+                        Some(ClearValue::Color(CLEAR_COLOR.into())),
+                    );
+                    let view_depth = graph_builder.create_image(
+                        view_kind,
+                        1,
+                        Format::D32Sfloat,
+                        Some(ClearValue::DepthStencil(ClearDepthStencil(1.0, 0))),
+                    );
+                    let mut subpass = SubpassBuilder::new()
+                        .with_group(ViewGroupDesc::new(view, DrawFlat2DDesc::new()).builder())
+                        .with_group(
+                            ViewGroupDesc::new(view, DrawFlat2DTransparentDesc::new()).builder(),
+                        )
+                        .with_group(ViewGroupDesc::new(view, DrawDebugLinesDesc::new()).builder());
+                    if ui_per_view {
+                        subpass = subpass.with_group(DrawUiDesc::new().builder());
+                    }
+                    let view_pass = graph_builder.add_node(
+                        subpass
+                            .with_color(view_color)
+                            .with_depth_stencil(view_depth)
+                            .into_pass(),
+                    );
+                    (view_color, view_pass)
+                })
+                .collect();
+
+            let composite = graph_builder.add_node(
+                SplitScreenCompositeDesc
+                    .builder()
+                    .with_image(views[0].0)
+                    .with_image(views[1].0)
+                    .with_image(color)
+                    .with_dependency(views[0].1)
+                    .with_dependency(views[1].1),
+            );
+
+            if ui_per_view || upscale {
+                composite
+            } else {
+                graph_builder.add_node(
+                    SubpassBuilder::new()
+                        .with_group(DrawUiDesc::new().builder()) // Draws UI components
+                        .with_color(color)
+                        .with_depth_stencil(depth)
+                        .with_dependency(composite)
+                        .into_pass(),
+                )
+            }
+        } else {
+            let mut subpass = SubpassBuilder::new()
                 .with_group(DrawFlat2DDesc::new().builder()) // Draws sprites
                 .with_group(DrawFlat2DTransparentDesc::new().builder()) // Draws UI components
-                .with_group(DrawUiDesc::new().builder()) // Draws UI components
-                .with_color(color)
-                .with_depth_stencil(depth)
-                .into_pass(),
-        );
+                .with_group(DrawDebugLinesDesc::new().builder()); // Draws weather
+            if !upscale {
+                subpass = subpass.with_group(DrawUiDesc::new().builder()); // Draws UI components
+            }
+            graph_builder.add_node(
+                subpass
+                    .with_color(color)
+                    .with_depth_stencil(depth)
+                    .into_pass(),
+            )
+        };
+
+        // The scaled down or shifted scene is drawn over an image the window's size, and the UI
+        // drawn on it there.
+        let (color, pass) = if upscale {
+            let upscaled = graph_builder.create_image(
+                window_kind,
+                1,
+                surface_format,
+                Some(ClearValue::Color(CLEAR_COLOR.into())),
+            );
+            let ui_depth = graph_builder.create_image(
+                window_kind,
+                1,
+                Format::D32Sfloat,
+                Some(ClearValue::DepthStencil(ClearDepthStencil(1.0, 0))),
+            );
+            let upscale = graph_builder.add_node(
+                SubpassBuilder::new()
+                    .with_group(DrawUpscaleDesc.builder().with_image(color))
+                    .with_group(DrawUiDesc::new().builder()) // Draws UI components
+                    .with_color(upscaled)
+                    .with_depth_stencil(ui_depth)
+                    .with_dependency(pass)
+                    .into_pass(),
+            );
+            (upscaled, upscale)
+        } else {
+            (color, pass)
+        };
+
+        // The palette filter redraws the finished frame into an image of its own.
+        let (presented, pass) = if self.palette_filter {
+            let filtered = graph_builder.create_image(
+                window_kind,
+                1,
+                surface_format,
+                Some(ClearValue::Color(CLEAR_COLOR.into())),
+            );
+            let filter = graph_builder.add_node(
+                SubpassBuilder::new()
+                    .with_group(DrawPaletteFilterDesc.builder().with_image(color))
+                    .with_color(filtered)
+                    .with_dependency(pass)
+                    .into_pass(),
+            );
+            (filtered, filter)
+        } else {
+            (color, pass)
+        };
+
+        // Frames to record or check are copied out before they are presented.
+        let frame_check = res
+            .try_fetch::<FrameCheckConfig>()
+            .filter(|config| config.enabled)
+            .map(|config| (config.max_width, config.max_height));
+        let recording = res
+            .try_fetch::<RecordingConfig>()
+            .filter(|config| config.enabled)
+            .map(|config| (config.max_width, config.max_height));
+        let pass = match frame_check.or(recording) {
+            Some(max_size) => graph_builder.add_node(
+                FrameReadbackDesc {
+                    format: surface_format,
+                    max_size,
+                }
+                .builder()
+                .with_image(presented)
+                .with_dependency(pass),
+            ),
+            None => pass,
+        };
 
         let _present = graph_builder
-            .add_node(PresentNode::builder(factory, surface, color).with_dependency(pass));
+            .add_node(PresentNode::builder(factory, surface, presented).with_dependency(pass));
 
         graph_builder
     }
@@ -0,0 +1,210 @@
+//! A top-down picture of the whole map, for level previews, saved on the `export_map` action.
+//!
+//! The map is drawn from the tiles' sprites in the main sprite sheet's image, away from the
+//! renderer, so what the cameras show doesn't matter. Every map is drawn as a grid seen from
+//! above, isometric ones included. Maps too big to fit `map_export.ron`'s largest size at a
+//! pixel per world unit are shrunk to fit, sampling the sprites rather than drawing them whole,
+//! so even very large maps take no more memory than the picture itself.
+
+use std::{fs::File, path::PathBuf};
+
+use amethyst::{
+    ecs::prelude::{Read, System},
+    input::{InputHandler, StringBindings},
+    renderer::sprite::{SpriteList, SpritePosition},
+};
+use image::{Rgba, RgbaImage};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::tile_map::TileMap;
+
+/// How big map pictures may be and where they are saved, read from `map_export.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MapExportConfig {
+    /// The largest a picture may be, in pixels.
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Where the picture is saved, relative to the game's folder.
+    pub path: PathBuf,
+}
+
+impl Default for MapExportConfig {
+    fn default() -> Self {
+        MapExportConfig {
+            max_width: 1024,
+            max_height: 1024,
+            path: PathBuf::from("map.png"),
+        }
+    }
+}
+
+/// The main sprite sheet's image and where each sprite is in it, decoded once as the game
+/// loads. Map pictures and save thumbnails are drawn from it, and the `SpriteColors` and
+/// `SpriteMasks` worked out from it.
+#[derive(Clone, Debug)]
+pub struct TileAtlas {
+    image: RgbaImage,
+    sprites: Vec<SpritePosition>,
+}
+
+impl Default for TileAtlas {
+    fn default() -> Self {
+        TileAtlas {
+            image: RgbaImage::new(0, 0),
+            sprites: Vec::new(),
+        }
+    }
+}
+
+impl TileAtlas {
+    pub fn load(image_path: PathBuf, sprite_list_path: PathBuf) -> Result<Self, failure::Error> {
+        let list: SpriteList = ron::de::from_reader(File::open(sprite_list_path)?)?;
+        Ok(TileAtlas {
+            image: image::open(image_path)?.to_rgba(),
+            sprites: list.sprites,
+        })
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Where each sprite is in the image, by sprite number.
+    pub fn sprites(&self) -> &[SpritePosition] {
+        &self.sprites
+    }
+
+    /// The pixel of `sprite_number` at `u` and `v` across it, each from `0` to `1` from the
+    /// top left, or clear if there's no such sprite.
+    fn sample(&self, sprite_number: usize, u: f32, v: f32) -> Rgba<u8> {
+        let clear = Rgba { data: [0; 4] };
+        let sprite = match self.sprites.get(sprite_number) {
+            Some(sprite) => sprite,
+            None => return clear,
+        };
+        let x = sprite.x + ((u * sprite.width as f32) as u32).min(sprite.width.saturating_sub(1));
+        let y = sprite.y + ((v * sprite.height as f32) as u32).min(sprite.height.saturating_sub(1));
+        if x < self.image.width() && y < self.image.height() {
+            *self.image.get_pixel(x, y)
+        } else {
+            clear
+        }
+    }
+}
+
+/// The size in pixels of a picture of a `width` by `height` tile map at `tile_pixels` a tile,
+/// shrunk to fit within `max_width` by `max_height`, and how many pixels a tile takes in it.
+pub fn picture_size(
+    (width, height): (usize, usize),
+    tile_pixels: f32,
+    (max_width, max_height): (u32, u32),
+) -> ((u32, u32), f32) {
+    if width == 0 || height == 0 {
+        return ((0, 0), 0.);
+    }
+    let scale = tile_pixels
+        .min(max_width as f32 / width as f32)
+        .min(max_height as f32 / height as f32)
+        .max(0.);
+    let size = |tiles: usize| (tiles as f32 * scale).floor() as u32;
+    ((size(width), size(height)), scale)
+}
+
+/// Draws `map` from above with the sprites in `atlas`, fitting within `max_size` pixels.
+pub fn render_map(map: &TileMap, atlas: &TileAtlas, max_size: (u32, u32)) -> RgbaImage {
+    let ((width, height), scale) = picture_size((map.width, map.height), map.tile_size, max_size);
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (across, down) = ((x as f32 + 0.5) / scale, (y as f32 + 0.5) / scale);
+        let (column, from_top) = (across as usize, down as usize);
+        // Pictures run from the top, tile rows from the bottom.
+        let row = match map.height.checked_sub(from_top + 1) {
+            Some(row) => row,
+            None => return Rgba { data: [0; 4] },
+        };
+        match map.sprite_at((column, row)) {
+            Some((sprite, _)) => atlas.sample(sprite, across.fract(), down.fract()),
+            None => Rgba { data: [0; 4] },
+        }
+    })
+}
+
+/// Saves a picture of the map on the `export_map` action.
+pub struct MapExportSystem {
+    config: MapExportConfig,
+    path: PathBuf,
+    was_pressed: bool,
+}
+
+impl MapExportSystem {
+    /// Saves pictures at `root` joined with the configured path.
+    pub fn new(config: MapExportConfig, root: PathBuf) -> Self {
+        MapExportSystem {
+            path: root.join(&config.path),
+            config,
+            was_pressed: false,
+        }
+    }
+}
+
+impl<'s> System<'s> for MapExportSystem {
+    type SystemData = (
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, TileMap>,
+        Read<'s, TileAtlas>,
+    );
+
+    fn run(&mut self, (input, map, atlas): Self::SystemData) {
+        let pressed = input.action_is_down("export_map").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            let picture = render_map(
+                &map,
+                &atlas,
+                (self.config.max_width, self.config.max_height),
+            );
+            match picture.save(&self.path) {
+                Ok(()) => info!(
+                    "Saved a {}x{} picture of the map to {}",
+                    picture.width(),
+                    picture.height(),
+                    self.path.display()
+                ),
+                Err(err) => warn!(
+                    "Failed to save a picture of the map to {}: {}",
+                    self.path.display(),
+                    err
+                ),
+            }
+        }
+        self.was_pressed = pressed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pictures_shrink_to_fit_and_keep_their_shape() {
+        // Small enough to draw at full size.
+        assert_eq!(picture_size((10, 5), 32., (1024, 1024)), ((320, 160), 32.));
+
+        // Too wide: the width fills the limit and the height follows.
+        assert_eq!(
+            picture_size((200, 50), 32., (1000, 1000)),
+            ((1000, 250), 5.)
+        );
+
+        // Too tall: the height fills it instead.
+        assert_eq!(picture_size((40, 400), 32., (1000, 800)), ((80, 800), 2.));
+
+        // Shrunk in both, the tighter limit wins.
+        let ((width, height), scale) = picture_size((300, 200), 16., (600, 300));
+        assert_eq!((width, height), (450, 300));
+        assert_eq!(scale, 1.5);
+        assert_eq!(width * 200, height * 300);
+
+        assert_eq!(picture_size((0, 10), 32., (1024, 1024)), ((0, 0), 0.));
+    }
+}
@@ -0,0 +1,290 @@
+use amethyst::{
+    input::{Button, InputEvent, InputHandler, StringBindings},
+    prelude::*,
+    shrev::{EventChannel, ReaderId},
+};
+use log::warn;
+
+use super::Menu;
+use crate::{
+    control_scheme::{ActiveControlScheme, CustomBindings},
+    rebinding::{self, button_name, combo_name, BindingSlot},
+    strings::Strings,
+    ui_theme::ButtonClick,
+};
+
+/// How many bindings are listed on a page.
+const PAGE_SIZE: usize = 10;
+
+/// The first button when asking about a conflict.
+const SWAP: usize = 0;
+
+/// What the menu is showing.
+#[derive(Clone, Debug)]
+enum Mode {
+    /// A page of the bindings.
+    Listing,
+    /// Waiting on the next key or gamepad button for `slot`.
+    Capturing(BindingSlot),
+    /// Asking whether to swap `slot` with the `conflicts` already bound to `button`.
+    Conflict {
+        slot: BindingSlot,
+        button: Button,
+        conflicts: Vec<BindingSlot>,
+    },
+}
+
+/// Lists every binding of the active control scheme, a page at a time, and rebinds the one
+/// clicked to the next key or gamepad button pressed. The `pause` action cancels instead of
+/// being captured, and mouse buttons are left to click the menu. A button already bound
+/// elsewhere asks whether to swap the two bindings. The scheme can be reset to its defaults
+/// from `bindings.ron`, and `Back` or the `pause` action returns to the menu below.
+pub struct ControlsState {
+    mode: Mode,
+    page: usize,
+    slots: Vec<BindingSlot>,
+    menu: Option<Menu>,
+    clicks: Option<ReaderId<ButtonClick>>,
+    inputs: Option<ReaderId<InputEvent<String>>>,
+    /// Set when the bindings were changed, to list them again once they are swapped in.
+    stale: bool,
+    /// Starts pressed, in case the `pause` action was held to get here.
+    was_pressed: bool,
+}
+
+impl Default for ControlsState {
+    fn default() -> Self {
+        ControlsState {
+            mode: Mode::Listing,
+            page: 0,
+            slots: Vec::new(),
+            menu: None,
+            clicks: None,
+            inputs: None,
+            stale: false,
+            was_pressed: true,
+        }
+    }
+}
+
+impl ControlsState {
+    fn pages(&self) -> usize {
+        self.slots.len().div_ceil(PAGE_SIZE).max(1)
+    }
+
+    /// The slots listed on the current page.
+    fn page_slots(&self) -> &[BindingSlot] {
+        let start = (self.page * PAGE_SIZE).min(self.slots.len());
+        let end = (start + PAGE_SIZE).min(self.slots.len());
+        &self.slots[start..end]
+    }
+
+    /// Shows the menu for the current mode, replacing the one shown before.
+    fn show(&mut self, world: &mut World, focus: usize) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(world);
+        }
+        let strings = world.read_resource::<Strings>();
+        let (title, labels, focus) = match &self.mode {
+            Mode::Listing => {
+                let labels: Vec<String> = {
+                    let input = world.read_resource::<InputHandler<StringBindings>>();
+                    self.slots = rebinding::slots(&input.bindings);
+                    self.page = self.page.min(self.pages() - 1);
+                    self.page_slots()
+                        .iter()
+                        .map(|slot| {
+                            let binding = slot.binding(&input.bindings).unwrap_or_default();
+                            strings.format(
+                                "controls.binding",
+                                &[&slot.name(), &combo_name(&binding, &strings)],
+                            )
+                        })
+                        .chain(vec![
+                            strings.get("controls.next_page"),
+                            strings.get("controls.reset"),
+                            strings.get("menu.back"),
+                        ])
+                        .collect()
+                };
+                let title = strings.format("controls.title", &[&(self.page + 1), &self.pages()]);
+                (title, labels, focus)
+            }
+            Mode::Capturing(slot) => (
+                strings.format("controls.press_key", &[&slot.name()]),
+                vec![strings.get("menu.cancel")],
+                0,
+            ),
+            Mode::Conflict {
+                button, conflicts, ..
+            } => {
+                let names: Vec<String> = conflicts.iter().map(BindingSlot::name).collect();
+                (
+                    strings.format(
+                        "controls.conflict",
+                        &[&button_name(*button, &strings), &names.join(", ")],
+                    ),
+                    vec![strings.get("controls.swap"), strings.get("menu.cancel")],
+                    SWAP,
+                )
+            }
+        };
+        drop(strings);
+        self.menu = Some(Menu::build(world, &title, labels, focus));
+    }
+
+    /// Goes back to listing the bindings, with `slot` focused if it is on the page.
+    fn list(&mut self, world: &mut World, slot: Option<&BindingSlot>) {
+        self.mode = Mode::Listing;
+        let focus = slot
+            .and_then(|slot| self.page_slots().iter().position(|listed| listed == slot))
+            .unwrap_or(0);
+        self.show(world, focus);
+    }
+
+    /// Binds `slot` to `button`, swapping it with any conflicts if `swap` is set, for the
+    /// active control scheme.
+    fn rebind(&mut self, world: &mut World, slot: &BindingSlot, button: Button, swap: bool) {
+        let rebound = {
+            let input = world.read_resource::<InputHandler<StringBindings>>();
+            rebinding::rebind(&input.bindings, slot, button, swap)
+        };
+        match rebound {
+            Ok(bindings) => {
+                let active = world.read_resource::<ActiveControlScheme>().0.clone();
+                world
+                    .write_resource::<CustomBindings>()
+                    .set(&active, bindings);
+                self.stale = true;
+            }
+            Err(err) => warn!("Failed to rebind {}: {}", slot.name(), err),
+        }
+    }
+
+    /// The first key or gamepad button pressed since `inputs` was last read.
+    fn pressed(&mut self, world: &World) -> Option<Button> {
+        let reader = self.inputs.as_mut().expect("Controls menu is started");
+        world
+            .read_resource::<EventChannel<InputEvent<String>>>()
+            .read(reader)
+            .filter_map(|event| match *event {
+                InputEvent::ButtonPressed(button @ Button::Key(_))
+                | InputEvent::ButtonPressed(button @ Button::Controller(..)) => Some(button),
+                _ => None,
+            })
+            .next()
+    }
+}
+
+impl SimpleState for ControlsState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.clicks = Some(
+            data.world
+                .write_resource::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+        self.inputs = Some(
+            data.world
+                .write_resource::<EventChannel<InputEvent<String>>>()
+                .register_reader(),
+        );
+        self.show(data.world, 0);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(data.world);
+        }
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        // Read every frame, so presses from before capturing started aren't taken for it.
+        let pressed_button = self.pressed(data.world);
+        let reader = self.clicks.as_mut().expect("Controls menu is started");
+        let clicked = self
+            .menu
+            .as_ref()
+            .and_then(|menu| menu.clicked(data.world, reader));
+
+        let pressed = data
+            .world
+            .read_resource::<InputHandler<StringBindings>>()
+            .action_is_down("pause")
+            .unwrap_or(false);
+        let back = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+
+        // The rebound controls are swapped in after this update, and listed the next.
+        if self.stale {
+            self.stale = false;
+            let slot = match &self.mode {
+                Mode::Capturing(slot) | Mode::Conflict { slot, .. } => Some(slot.clone()),
+                Mode::Listing => None,
+            };
+            self.list(data.world, slot.as_ref());
+            return Trans::None;
+        }
+
+        match self.mode.clone() {
+            Mode::Listing => {
+                if back {
+                    return Trans::Pop;
+                }
+                let clicked = match clicked {
+                    Some(clicked) => clicked,
+                    None => return Trans::None,
+                };
+                let listed = self.page_slots().len();
+                if clicked < listed {
+                    self.mode = Mode::Capturing(self.page_slots()[clicked].clone());
+                    self.show(data.world, 0);
+                } else if clicked == listed {
+                    self.page = (self.page + 1) % self.pages();
+                    self.show(data.world, self.page_slots().len());
+                } else if clicked == listed + 1 {
+                    let active = data.world.read_resource::<ActiveControlScheme>().0.clone();
+                    data.world.write_resource::<CustomBindings>().reset(&active);
+                    self.stale = true;
+                } else {
+                    return Trans::Pop;
+                }
+            }
+            Mode::Capturing(slot) => {
+                // Pressing `menu_select` to click `Cancel` binds it instead.
+                if back || clicked.is_some() && pressed_button.is_none() {
+                    self.list(data.world, Some(&slot));
+                } else if let Some(button) = pressed_button {
+                    let conflicts = {
+                        let input = data.world.read_resource::<InputHandler<StringBindings>>();
+                        rebinding::conflicts(&input.bindings, &slot, button)
+                    };
+                    if conflicts.is_empty() {
+                        self.rebind(data.world, &slot, button, false);
+                        if !self.stale {
+                            self.list(data.world, Some(&slot));
+                        }
+                    } else {
+                        self.mode = Mode::Conflict {
+                            slot,
+                            button,
+                            conflicts,
+                        };
+                        self.show(data.world, SWAP);
+                    }
+                }
+            }
+            Mode::Conflict { slot, button, .. } => match clicked {
+                Some(SWAP) if !back => {
+                    self.rebind(data.world, &slot, button, true);
+                    if !self.stale {
+                        self.list(data.world, Some(&slot));
+                    }
+                }
+                Some(_) => self.list(data.world, Some(&slot)),
+                None if back => self.list(data.world, Some(&slot)),
+                None => {}
+            },
+        }
+        Trans::None
+    }
+}
@@ -0,0 +1,129 @@
+use amethyst::{
+    prelude::*,
+    shrev::{EventChannel, ReaderId},
+};
+use log::warn;
+
+use super::Menu;
+use crate::{
+    dialogue::{DialogueFlags, DialogueGraph, DialogueOpen},
+    save::UnsavedProgress,
+    strings::Strings,
+    ui_theme::ButtonClick,
+};
+
+/// Pushed over the game to hold a conversation, from the node `node` of the `DialogueGraph`.
+/// Each line shows as the menu's title, with a button for each of its choices, or one to carry
+/// on from a line without any. Gameplay holds still underneath until the conversation ends, and
+/// the answer has to be picked: the `pause` action doesn't leave it.
+pub struct DialogueState {
+    node: String,
+    menu: Option<Menu>,
+    clicks: Option<ReaderId<ButtonClick>>,
+}
+
+impl DialogueState {
+    pub fn new(node: impl Into<String>) -> Self {
+        DialogueState {
+            node: node.into(),
+            menu: None,
+            clicks: None,
+        }
+    }
+
+    /// Shows the current node, replacing the one shown before. Returns whether there is such
+    /// a node.
+    fn show(&mut self, world: &mut World) -> bool {
+        self.hide(world);
+        let (title, labels) = {
+            let graph = world.read_resource::<DialogueGraph>();
+            let node = match graph.node(&self.node) {
+                Some(node) => node,
+                None => {
+                    warn!("Started unknown dialogue node {:?}", self.node);
+                    return false;
+                }
+            };
+            let strings = world.read_resource::<Strings>();
+            let text = strings.get_or(&node.text);
+            let title = match &node.speaker {
+                Some(speaker) => {
+                    strings.format("dialogue.spoken", &[&strings.get_or(speaker), &text])
+                }
+                None => text,
+            };
+            let labels: Vec<String> = if node.choices.is_empty() {
+                vec![strings.get("dialogue.continue")]
+            } else {
+                node.choices
+                    .iter()
+                    .map(|choice| strings.get_or(&choice.text))
+                    .collect()
+            };
+            (title, labels)
+        };
+        self.menu = Some(Menu::build(world, &title, labels, 0));
+        true
+    }
+
+    fn hide(&mut self, world: &mut World) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(world);
+        }
+    }
+}
+
+impl SimpleState for DialogueState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.clicks = Some(
+            data.world
+                .write_resource::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+        data.world.write_resource::<DialogueOpen>().0 = true;
+        self.show(data.world);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.hide(data.world);
+        data.world.write_resource::<DialogueOpen>().0 = false;
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        if self.menu.is_none() {
+            return Trans::Pop;
+        }
+        let reader = self.clicks.as_mut().expect("Dialogue is started");
+        let clicked = match self
+            .menu
+            .as_ref()
+            .and_then(|menu| menu.clicked(data.world, reader))
+        {
+            Some(clicked) => clicked,
+            None => return Trans::None,
+        };
+        let next = {
+            let graph = data.world.read_resource::<DialogueGraph>();
+            let (next, flags) = graph.answer(&self.node, clicked);
+            let mut set = data.world.write_resource::<DialogueFlags>();
+            for flag in flags {
+                set.set(flag);
+            }
+            if !flags.is_empty() {
+                data.world.write_resource::<UnsavedProgress>().mark();
+            }
+            next.map(str::to_string)
+        };
+        match next {
+            Some(next) => {
+                self.node = next;
+                if self.show(data.world) {
+                    Trans::None
+                } else {
+                    Trans::Pop
+                }
+            }
+            None => Trans::Pop,
+        }
+    }
+}
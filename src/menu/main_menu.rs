@@ -0,0 +1,127 @@
+use amethyst::{
+    prelude::*,
+    shrev::{EventChannel, ReaderId},
+};
+
+use super::{Menu, OptionsState, SaveSlotsState};
+use crate::{
+    difficulty::Difficulty, frame_check::FrameCheck, loading::LoadingState, save::UnsavedProgress,
+    strings::Strings, ui_theme::ButtonClick,
+};
+
+const PLAY: usize = 0;
+const DIFFICULTY: usize = 1;
+const LOAD: usize = 2;
+const OPTIONS: usize = 3;
+const QUIT: usize = 4;
+
+/// The state the game opens in, and returns to when quitting a game from the pause menu. The
+/// difficulty of new games is picked here.
+pub struct MainMenuState {
+    /// What the games started from here are played with, as for `LoadingState::new`.
+    local_players: usize,
+    seed: u64,
+    menu: Option<Menu>,
+    /// The button that opened the submenu shown over this one, focused again on its return.
+    submenu: usize,
+    clicks: Option<ReaderId<ButtonClick>>,
+}
+
+impl MainMenuState {
+    pub fn new(local_players: usize, seed: u64) -> Self {
+        MainMenuState {
+            local_players,
+            seed,
+            menu: None,
+            submenu: PLAY,
+            clicks: None,
+        }
+    }
+
+    fn show(&mut self, world: &mut World, focus: usize) {
+        let (title, labels) = {
+            let strings = world.read_resource::<Strings>();
+            let level = strings.get(&format!(
+                "difficulty.{:?}",
+                world.read_resource::<Difficulty>().level
+            ));
+            (
+                strings.get("main_menu.title"),
+                vec![
+                    strings.get("main_menu.play"),
+                    strings.format("main_menu.difficulty", &[&level]),
+                    strings.get("main_menu.load"),
+                    strings.get("menu.options"),
+                    strings.get("main_menu.quit"),
+                ],
+            )
+        };
+        self.menu = Some(Menu::build(world, &title, labels, focus));
+    }
+
+    fn hide(&mut self, world: &mut World) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(world);
+        }
+    }
+}
+
+impl SimpleState for MainMenuState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        // Games started from here have nothing to save until they are played.
+        data.world.add_resource(UnsavedProgress::default());
+        self.clicks = Some(
+            data.world
+                .write_resource::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+        self.show(data.world, PLAY);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.hide(data.world);
+    }
+
+    fn on_pause(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.hide(data.world);
+    }
+
+    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.show(data.world, self.submenu);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        // A frame check plays the level straight away.
+        let check_seed = {
+            let check = data.world.read_resource::<FrameCheck>();
+            Some(check.seed()).filter(|_| check.is_running())
+        };
+        if let Some(seed) = check_seed {
+            return Trans::Switch(Box::new(LoadingState::new(self.local_players, seed)));
+        }
+        let reader = self.clicks.as_mut().expect("Main menu is started");
+        let clicked = self
+            .menu
+            .as_ref()
+            .and_then(|menu| menu.clicked(data.world, reader));
+        if let Some(clicked) = clicked {
+            self.submenu = clicked;
+        }
+        match clicked {
+            Some(DIFFICULTY) => {
+                {
+                    let mut difficulty = data.world.write_resource::<Difficulty>();
+                    difficulty.level = difficulty.level.next();
+                }
+                self.hide(data.world);
+                self.show(data.world, DIFFICULTY);
+                Trans::None
+            }
+            Some(PLAY) => Trans::Switch(Box::new(LoadingState::new(self.local_players, self.seed))),
+            Some(LOAD) => Trans::Push(Box::new(SaveSlotsState::from_main_menu(self.local_players))),
+            Some(OPTIONS) => Trans::Push(Box::new(OptionsState::default())),
+            Some(QUIT) => Trans::Quit,
+            _ => Trans::None,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Menus: columns of `ThemedButton`s that work with the keyboard or a gamepad as well as the
+//! mouse, and the states showing them.
+//!
+//! The buttons of a `Menu` each carry a `MenuItem`. The `MenuFocusSystem` moves the focus up and
+//! down them with the players' `vertical` axes or the gamepad's `menu_vertical` one, and
+//! `menu_select` clicks the focused button, sending the same `ButtonClick` the mouse would.
+
+mod controls;
+mod dialogue;
+mod main_menu;
+mod options;
+mod pause;
+mod quit_prompt;
+mod save_slots;
+
+pub use self::{
+    controls::ControlsState, dialogue::DialogueState, main_menu::MainMenuState,
+    options::OptionsState, pause::PauseState, quit_prompt::QuitPromptState,
+    save_slots::SaveSlotsState,
+};
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System,
+        World, Write, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    shrev::{EventChannel, ReaderId},
+    ui::{Anchor, FontAsset, UiText, UiTransform},
+};
+
+use crate::ui_theme::{ButtonClick, ThemedButton, ThemedButtonBuilder, ThemedText, UiTheme};
+
+/// How far an axis has to be pushed to move the focus.
+const AXIS_THRESHOLD: f64 = 0.5;
+
+/// The axes that move the focus, each pointing up the menu.
+const FOCUS_AXES: [&str; 3] = ["vertical", "vertical_p2", "menu_vertical"];
+
+/// Size of a menu's buttons at a text scale of 1.
+const BUTTON_WIDTH: f32 = 200.;
+const BUTTON_HEIGHT: f32 = 32.;
+
+/// A button taking part in keyboard and gamepad navigation, `order`th from the top.
+#[derive(Clone, Copy, Debug)]
+pub struct MenuItem {
+    pub order: usize,
+}
+
+impl Component for MenuItem {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// A title above a column of buttons in the middle of the window.
+pub struct Menu {
+    title: Entity,
+    buttons: Vec<Entity>,
+}
+
+impl Menu {
+    /// Builds the menu with a button for each of `labels`, from the top, and the `focus`th
+    /// focused. The column is spaced for the current text scale.
+    pub fn build(
+        world: &mut World,
+        title: &str,
+        labels: impl IntoIterator<Item = impl Into<String>>,
+        focus: usize,
+    ) -> Self {
+        let theme = world.read_resource::<UiTheme>().clone();
+        let labels: Vec<String> = labels.into_iter().map(Into::into).collect();
+        let step = BUTTON_HEIGHT * theme.text_scale + theme.padding;
+        let top = (labels.len() as f32 - 1.) / 2. * step;
+
+        let buttons: Vec<_> = labels
+            .into_iter()
+            .enumerate()
+            .map(|(order, label)| {
+                let button = ThemedButtonBuilder::new(label)
+                    .with_position(Anchor::Middle, 0., top - order as f32 * step)
+                    .with_size(BUTTON_WIDTH, BUTTON_HEIGHT)
+                    .build(world);
+                world
+                    .write_storage::<MenuItem>()
+                    .insert(button, MenuItem { order })
+                    .expect("Menu button is alive");
+                if let Some(button) = world.write_storage::<ThemedButton>().get_mut(button) {
+                    button.focused = order == focus;
+                }
+                button
+            })
+            .collect();
+
+        let font = theme.font(
+            &world.read_resource::<Loader>(),
+            &world.read_resource::<AssetStorage<FontAsset>>(),
+        );
+        let mut text = UiText::new(
+            font,
+            title.to_string(),
+            theme.text_color,
+            theme.font_size * 2.,
+        );
+        let mut transform = UiTransform::new(
+            format!("menu_{}", title),
+            Anchor::Middle,
+            Anchor::Middle,
+            0.,
+            top + BUTTON_HEIGHT * theme.text_scale * 1.5 + theme.padding,
+            10.,
+            BUTTON_WIDTH * 2.,
+            BUTTON_HEIGHT * 1.5,
+        );
+        let themed = ThemedText::new(&text, &transform);
+        themed.apply(&theme, &mut text, &mut transform);
+        let title = world
+            .create_entity()
+            .with(transform)
+            .with(text)
+            .with(themed)
+            .build();
+
+        Menu { title, buttons }
+    }
+
+    /// The `index`th button from the top.
+    pub fn button(&self, index: usize) -> Entity {
+        self.buttons[index]
+    }
+
+    /// The index of the last of the menu's buttons clicked since `reader` was last read.
+    pub fn clicked(&self, world: &World, reader: &mut ReaderId<ButtonClick>) -> Option<usize> {
+        world
+            .read_resource::<EventChannel<ButtonClick>>()
+            .read(reader)
+            .filter_map(|click| {
+                self.buttons
+                    .iter()
+                    .position(|&button| button == click.button)
+            })
+            .next_back()
+    }
+
+    /// Deletes the title and the buttons with their labels.
+    pub fn delete(self, world: &mut World) {
+        let mut entities = vec![self.title];
+        {
+            let buttons = world.read_storage::<ThemedButton>();
+            for &entity in &self.buttons {
+                entities.push(entity);
+                entities.extend(buttons.get(entity).map(ThemedButton::label));
+            }
+        }
+        world
+            .delete_entities(&entities)
+            .expect("Menu entities are alive");
+    }
+}
+
+/// Moves the focus between the enabled `MenuItem` buttons, wrapping around at either end, and
+/// clicks the focused one on the `menu_select` action.
+#[derive(Default)]
+pub struct MenuFocusSystem {
+    /// The direction the focus was last moved in: up -1, down 1, or 0 once the axes are let go.
+    was_moving: i32,
+    was_selecting: bool,
+}
+
+impl<'s> System<'s> for MenuFocusSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, MenuItem>,
+        WriteStorage<'s, ThemedButton>,
+        Write<'s, EventChannel<ButtonClick>>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(&mut self, (entities, items, mut buttons, mut clicks, input): Self::SystemData) {
+        let vertical: f64 = FOCUS_AXES
+            .iter()
+            .filter_map(|&axis| input.axis_value(axis))
+            .sum();
+        let moving = if vertical > AXIS_THRESHOLD {
+            -1
+        } else if vertical < -AXIS_THRESHOLD {
+            1
+        } else {
+            0
+        };
+        let moved = moving != 0 && moving != self.was_moving;
+        self.was_moving = moving;
+
+        let selecting = input.action_is_down("menu_select").unwrap_or(false);
+        let selected = selecting && !self.was_selecting;
+        self.was_selecting = selecting;
+
+        let mut menu: Vec<(usize, Entity)> = (&entities, &items, &buttons)
+            .join()
+            .filter(|(_, _, button)| button.enabled)
+            .map(|(entity, item, _)| (item.order, entity))
+            .collect();
+        if menu.is_empty() {
+            return;
+        }
+        menu.sort();
+
+        let focused = menu
+            .iter()
+            .position(|&(_, entity)| buttons.get(entity).is_some_and(|button| button.focused));
+        let focus = match (focused, moved) {
+            (Some(index), true) => Some((index as i32 + moving).rem_euclid(menu.len() as i32)),
+            (None, true) => Some(0),
+            (focused, false) => focused.map(|index| index as i32),
+        };
+        for (index, &(_, entity)) in menu.iter().enumerate() {
+            if let Some(button) = buttons.get_mut(entity) {
+                button.focused = focus == Some(index as i32);
+            }
+        }
+
+        if let (true, Some(index)) = (selected, focus) {
+            clicks.single_write(ButtonClick {
+                button: menu[index as usize].1,
+            });
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use amethyst::{
+    input::{InputHandler, StringBindings},
+    prelude::*,
+    shrev::{EventChannel, ReaderId},
+};
+
+use super::{ControlsState, Menu};
+use crate::{
+    abilities::AimAssist,
+    analog::AnalogResponse,
+    palette::PaletteFilter,
+    rumble::RumbleConfig,
+    strings::Strings,
+    ui_theme::{ButtonClick, UiTheme},
+};
+
+const PALETTE: usize = 0;
+const TEXT_SIZE: usize = 1;
+const HIGH_CONTRAST: usize = 2;
+const INNER_DEAD_ZONE: usize = 3;
+const OUTER_DEAD_ZONE: usize = 4;
+const RESPONSE_CURVE: usize = 5;
+const RUMBLE: usize = 6;
+const KEYBOARD_AIM_ASSIST: usize = 7;
+const LANGUAGE: usize = 8;
+const CONTROLS: usize = 9;
+
+/// Settings reachable from the main and pause menus. Each setting's button steps it to its next
+/// value, `Language` switches to the next locale, `Controls` opens the `ControlsState`, and `Back` or the `pause` action returns to the
+/// menu below.
+pub struct OptionsState {
+    menu: Option<Menu>,
+    /// The button that opened the submenu shown over this one, focused again on its return.
+    submenu: usize,
+    clicks: Option<ReaderId<ButtonClick>>,
+    /// Starts pressed, in case the `pause` action was held to get here.
+    was_pressed: bool,
+}
+
+impl Default for OptionsState {
+    fn default() -> Self {
+        OptionsState {
+            menu: None,
+            submenu: CONTROLS,
+            clicks: None,
+            was_pressed: true,
+        }
+    }
+}
+
+impl OptionsState {
+    /// Shows the menu with each setting's current value, replacing the one shown before.
+    fn show(&mut self, world: &mut World, focus: usize) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(world);
+        }
+        let (title, labels) = {
+            let strings = world.read_resource::<Strings>();
+            let theme = world.read_resource::<UiTheme>();
+            let analog = world.read_resource::<AnalogResponse>();
+            let on_off = |on| strings.get(if on { "menu.on" } else { "menu.off" });
+            let percent = |fraction: f32| format!("{:.0}", fraction * 100.);
+            let palette = strings.get(&format!(
+                "palette.{:?}",
+                world.read_resource::<PaletteFilter>().mode
+            ));
+            let curve = strings.get(&format!("curve.{:?}", analog.curve));
+            (
+                strings.get("options.title"),
+                vec![
+                    strings.format("options.palette", &[&palette]),
+                    strings.format("options.text_size", &[&percent(theme.text_scale)]),
+                    strings.format("options.high_contrast", &[&on_off(theme.high_contrast)]),
+                    strings.format(
+                        "options.inner_dead_zone",
+                        &[&percent(analog.inner_dead_zone)],
+                    ),
+                    strings.format(
+                        "options.outer_dead_zone",
+                        &[&percent(analog.outer_dead_zone)],
+                    ),
+                    strings.format("options.response_curve", &[&curve]),
+                    strings.format(
+                        "options.rumble",
+                        &[&on_off(world.read_resource::<RumbleConfig>().enabled)],
+                    ),
+                    strings.format(
+                        "options.keyboard_aim_assist",
+                        &[&on_off(world.read_resource::<AimAssist>().keyboard)],
+                    ),
+                    strings.format("options.language", &[&strings.get("language")]),
+                    strings.get("options.controls"),
+                    strings.get("menu.back"),
+                ],
+            )
+        };
+        self.menu = Some(Menu::build(world, &title, labels, focus));
+    }
+}
+
+impl SimpleState for OptionsState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.clicks = Some(
+            data.world
+                .write_resource::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+        self.show(data.world, PALETTE);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(data.world);
+        }
+    }
+
+    fn on_pause(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(data.world);
+        }
+    }
+
+    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.was_pressed = true;
+        self.show(data.world, self.submenu);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let pressed = data
+            .world
+            .read_resource::<InputHandler<StringBindings>>()
+            .action_is_down("pause")
+            .unwrap_or(false);
+        let back = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+        if back {
+            return Trans::Pop;
+        }
+
+        let reader = self.clicks.as_mut().expect("Options menu is started");
+        let clicked = match self
+            .menu
+            .as_ref()
+            .and_then(|menu| menu.clicked(data.world, reader))
+        {
+            Some(clicked) => clicked,
+            None => return Trans::None,
+        };
+        match clicked {
+            PALETTE => {
+                let mut filter = data.world.write_resource::<PaletteFilter>();
+                filter.mode = filter.mode.next();
+            }
+            TEXT_SIZE => data.world.write_resource::<UiTheme>().cycle_text_scale(),
+            HIGH_CONTRAST => {
+                let mut theme = data.world.write_resource::<UiTheme>();
+                theme.high_contrast = !theme.high_contrast;
+            }
+            INNER_DEAD_ZONE => data
+                .world
+                .write_resource::<AnalogResponse>()
+                .cycle_inner_dead_zone(),
+            OUTER_DEAD_ZONE => data
+                .world
+                .write_resource::<AnalogResponse>()
+                .cycle_outer_dead_zone(),
+            RESPONSE_CURVE => {
+                let mut analog = data.world.write_resource::<AnalogResponse>();
+                analog.curve = analog.curve.next();
+            }
+            RUMBLE => {
+                let mut rumble = data.world.write_resource::<RumbleConfig>();
+                rumble.enabled = !rumble.enabled;
+            }
+            KEYBOARD_AIM_ASSIST => {
+                let mut assist = data.world.write_resource::<AimAssist>();
+                assist.keyboard = !assist.keyboard;
+            }
+            LANGUAGE => data.world.write_resource::<Strings>().cycle_locale(),
+            CONTROLS => {
+                self.submenu = CONTROLS;
+                return Trans::Push(Box::new(ControlsState::default()));
+            }
+            _ => return Trans::Pop,
+        }
+        // Relabel the buttons, and space them out again should the text have been resized.
+        self.show(data.world, clicked);
+        Trans::None
+    }
+}
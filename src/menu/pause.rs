@@ -0,0 +1,252 @@
+use amethyst::{
+    input::{InputHandler, StringBindings},
+    prelude::*,
+    shrev::{EventChannel, ReaderId},
+};
+
+use super::{MainMenuState, Menu, OptionsState, QuitPromptState, SaveSlotsState};
+use crate::{
+    save::{SaveSlots, UnsavedProgress},
+    strings::Strings,
+    ui_theme::ButtonClick,
+};
+
+const RESUME: usize = 0;
+const SAVE: usize = 1;
+const OPTIONS: usize = 2;
+const QUIT: usize = 3;
+
+/// Pushed over the game on the `pause` action. Gameplay holds still underneath, as only the top
+/// state's fixed update runs.
+pub struct PauseState {
+    /// What the main menu starts its next game with, as for `MainMenuState::new`.
+    local_players: usize,
+    seed: u64,
+    menu: Option<Menu>,
+    /// The button that opened the submenu shown over this one, focused again on its return.
+    submenu: usize,
+    clicks: Option<ReaderId<ButtonClick>>,
+    /// Starts pressed, as the `pause` action that opened the menu is still held.
+    was_pressed: bool,
+}
+
+impl PauseState {
+    pub fn new(local_players: usize, seed: u64) -> Self {
+        PauseState {
+            local_players,
+            seed,
+            menu: None,
+            submenu: RESUME,
+            clicks: None,
+            was_pressed: true,
+        }
+    }
+
+    fn show(&mut self, world: &mut World, focus: usize) {
+        let (title, labels) = {
+            let strings = world.read_resource::<Strings>();
+            (
+                strings.get("pause.title"),
+                [
+                    "pause.resume",
+                    "pause.save_or_load",
+                    "menu.options",
+                    "pause.quit",
+                ]
+                .iter()
+                .map(|id| strings.get(id))
+                .collect::<Vec<_>>(),
+            )
+        };
+        self.menu = Some(Menu::build(world, &title, labels, focus));
+    }
+
+    fn hide(&mut self, world: &mut World) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(world);
+        }
+    }
+}
+
+/// Leaves the `menus` shown over the game and then the game for the main menu. The game's
+/// `on_stop` cleans up everything it spawned.
+pub(super) fn quit_to_menu(world: &World, menus: usize, local_players: usize, seed: u64) {
+    let mut transitions =
+        world.write_resource::<EventChannel<TransEvent<GameData<'static, 'static>, StateEvent>>>();
+    for _ in 0..menus {
+        transitions.single_write(Box::new(|| Trans::Pop));
+    }
+    transitions.single_write(Box::new(move || {
+        Trans::Switch(Box::new(MainMenuState::new(local_players, seed)))
+    }));
+}
+
+impl SimpleState for PauseState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.clicks = Some(
+            data.world
+                .write_resource::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+        self.show(data.world, RESUME);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.hide(data.world);
+    }
+
+    fn on_pause(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.hide(data.world);
+    }
+
+    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.show(data.world, self.submenu);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let pressed = data
+            .world
+            .read_resource::<InputHandler<StringBindings>>()
+            .action_is_down("pause")
+            .unwrap_or(false);
+        let unpause = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+        if unpause {
+            return Trans::Pop;
+        }
+
+        let reader = self.clicks.as_mut().expect("Pause menu is started");
+        let clicked = self
+            .menu
+            .as_ref()
+            .and_then(|menu| menu.clicked(data.world, reader));
+        if let Some(clicked) = clicked {
+            self.submenu = clicked;
+        }
+        match clicked {
+            Some(RESUME) => Trans::Pop,
+            Some(SAVE) => Trans::Push(Box::new(SaveSlotsState::from_game(
+                self.local_players,
+                self.seed,
+            ))),
+            Some(OPTIONS) => Trans::Push(Box::new(OptionsState::default())),
+            // Unsaved progress is asked about first.
+            Some(QUIT) => {
+                let prompt = data
+                    .world
+                    .read_resource::<SaveSlots>()
+                    .prompts_on_quit(&data.world.read_resource::<UnsavedProgress>());
+                if prompt {
+                    return Trans::Push(Box::new(QuitPromptState::new(
+                        self.local_players,
+                        self.seed,
+                    )));
+                }
+                quit_to_menu(data.world, 1, self.local_players, self.seed);
+                Trans::None
+            }
+            _ => Trans::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use amethyst::{ecs::prelude::DispatcherBuilder, winit::VirtualKeyCode};
+
+    use super::*;
+    use crate::{
+        player::tests::{bind, press_key},
+        save::SaveConfig,
+    };
+
+    type Transitions = EventChannel<TransEvent<GameData<'static, 'static>, StateEvent>>;
+
+    /// The world and a pause menu over the game, without the UI the menu would build.
+    fn paused() -> (World, PauseState) {
+        let mut world = World::new();
+        world.add_resource(EventChannel::<ButtonClick>::new());
+        world.add_resource(Transitions::new());
+        world.add_resource(SaveSlots::new(
+            PathBuf::from("saves"),
+            SaveConfig::default(),
+        ));
+        world.add_resource(UnsavedProgress::default());
+        bind(
+            &mut world,
+            r#"(axes: {}, actions: {"pause": [[Key(Escape)]]})"#,
+        );
+
+        let mut state = PauseState::new(1, 7);
+        state.clicks = Some(
+            world
+                .write_resource::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+        state.menu = Some(Menu {
+            title: world.create_entity().build(),
+            buttons: (0..4).map(|_| world.create_entity().build()).collect(),
+        });
+        (world, state)
+    }
+
+    fn update(world: &mut World, state: &mut PauseState) -> SimpleTrans {
+        let mut data = GameData::new(DispatcherBuilder::new().build());
+        SimpleState::update(state, &mut StateData::new(world, &mut data))
+    }
+
+    fn click(world: &mut World, state: &PauseState, index: usize) {
+        let button = state.menu.as_ref().unwrap().buttons[index];
+        world
+            .write_resource::<EventChannel<ButtonClick>>()
+            .single_write(ButtonClick { button });
+    }
+
+    #[test]
+    fn resume_and_the_pause_action_go_back_to_the_game() {
+        let (mut world, mut state) = paused();
+        click(&mut world, &state, RESUME);
+        assert!(matches!(update(&mut world, &mut state), Trans::Pop));
+
+        // The press that paused is still held when the menu opens, so only a new one unpauses.
+        let (mut world, mut state) = paused();
+        press_key(&mut world, VirtualKeyCode::Escape, true);
+        assert!(matches!(update(&mut world, &mut state), Trans::None));
+        press_key(&mut world, VirtualKeyCode::Escape, false);
+        assert!(matches!(update(&mut world, &mut state), Trans::None));
+        press_key(&mut world, VirtualKeyCode::Escape, true);
+        assert!(matches!(update(&mut world, &mut state), Trans::Pop));
+    }
+
+    #[test]
+    fn quitting_leaves_for_the_main_menu_or_asks_to_save_first() {
+        let (mut world, mut state) = paused();
+        let mut reader = world.write_resource::<Transitions>().register_reader();
+        click(&mut world, &state, QUIT);
+        assert!(matches!(update(&mut world, &mut state), Trans::None));
+        let transitions: Vec<_> = world
+            .read_resource::<Transitions>()
+            .read(&mut reader)
+            .map(|transition| transition())
+            .collect();
+        assert_eq!(transitions.len(), 2);
+        assert!(matches!(transitions[0], Trans::Pop));
+        assert!(matches!(transitions[1], Trans::Switch(_)));
+
+        let (mut world, mut state) = paused();
+        let mut reader = world.write_resource::<Transitions>().register_reader();
+        world.write_resource::<UnsavedProgress>().mark();
+        click(&mut world, &state, QUIT);
+        assert!(matches!(update(&mut world, &mut state), Trans::Push(_)));
+        assert_eq!(
+            world
+                .read_resource::<Transitions>()
+                .read(&mut reader)
+                .count(),
+            0
+        );
+        assert_eq!(state.submenu, QUIT);
+    }
+}
@@ -0,0 +1,148 @@
+use amethyst::{
+    input::{InputHandler, StringBindings},
+    prelude::*,
+    shrev::{EventChannel, ReaderId},
+};
+use log::warn;
+
+use super::{pause::quit_to_menu, save_slots::save_game, Menu};
+use crate::{
+    save::{SaveSlots, UnsavedProgress},
+    strings::Strings,
+    ui_theme::{ButtonClick, ThemedButton},
+};
+
+const SAVE_AND_QUIT: usize = 0;
+const QUIT: usize = 1;
+const CANCEL: usize = 2;
+
+/// Pushed over the pause menu when quitting a game with unsaved progress. Saves to the slot the
+/// game was last saved to or loaded from, or else the first empty one, before quitting, or
+/// quits without saving. `Cancel` or the `pause` action returns to the pause menu. With no slot
+/// to save to, only quitting without saving is offered.
+pub struct QuitPromptState {
+    /// What the main menu starts its next game with, as for `MainMenuState::new`.
+    local_players: usize,
+    seed: u64,
+    slot: Option<usize>,
+    menu: Option<Menu>,
+    clicks: Option<ReaderId<ButtonClick>>,
+    /// Starts pressed, in case the `pause` action was held to get here.
+    was_pressed: bool,
+}
+
+impl QuitPromptState {
+    pub fn new(local_players: usize, seed: u64) -> Self {
+        QuitPromptState {
+            local_players,
+            seed,
+            slot: None,
+            menu: None,
+            clicks: None,
+            was_pressed: true,
+        }
+    }
+
+    fn show(&mut self, world: &mut World) {
+        self.slot = world
+            .read_resource::<SaveSlots>()
+            .quick_save_slot(&world.read_resource::<UnsavedProgress>());
+        let (title, labels) = {
+            let strings = world.read_resource::<Strings>();
+            let save = match self.slot {
+                Some(slot) => strings.format("quit_prompt.save", &[&(slot + 1)]),
+                None => strings.get("quit_prompt.no_slot"),
+            };
+            (
+                strings.get("quit_prompt.title"),
+                vec![
+                    save,
+                    strings.get("quit_prompt.quit"),
+                    strings.get("menu.cancel"),
+                ],
+            )
+        };
+        let focus = if self.slot.is_some() {
+            SAVE_AND_QUIT
+        } else {
+            QUIT
+        };
+        let menu = Menu::build(world, &title, labels, focus);
+        if self.slot.is_none() {
+            if let Some(button) = world
+                .write_storage::<ThemedButton>()
+                .get_mut(menu.button(SAVE_AND_QUIT))
+            {
+                button.enabled = false;
+            }
+        }
+        self.menu = Some(menu);
+    }
+
+    fn hide(&mut self, world: &mut World) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(world);
+        }
+    }
+}
+
+impl SimpleState for QuitPromptState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.clicks = Some(
+            data.world
+                .write_resource::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+        self.show(data.world);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.hide(data.world);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let pressed = data
+            .world
+            .read_resource::<InputHandler<StringBindings>>()
+            .action_is_down("pause")
+            .unwrap_or(false);
+        let back = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+        if back {
+            return Trans::Pop;
+        }
+
+        let reader = self.clicks.as_mut().expect("Quit prompt is started");
+        let clicked = self
+            .menu
+            .as_ref()
+            .and_then(|menu| menu.clicked(data.world, reader));
+        match clicked {
+            Some(SAVE_AND_QUIT) => {
+                let slot = match self.slot {
+                    Some(slot) => slot,
+                    None => return Trans::None,
+                };
+                let saved = save_game(
+                    data.world,
+                    &data.world.read_resource::<SaveSlots>(),
+                    self.seed,
+                    slot,
+                );
+                // A game that failed to save is kept, rather than lost.
+                if let Err(err) = saved {
+                    warn!("Failed to save to slot {}: {}", slot + 1, err);
+                    return Trans::Pop;
+                }
+                quit_to_menu(data.world, 2, self.local_players, self.seed);
+                Trans::None
+            }
+            Some(QUIT) => {
+                quit_to_menu(data.world, 2, self.local_players, self.seed);
+                Trans::None
+            }
+            Some(CANCEL) => Trans::Pop,
+            _ => Trans::None,
+        }
+    }
+}
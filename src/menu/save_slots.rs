@@ -0,0 +1,309 @@
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    ecs::prelude::{Builder, Entity},
+    input::{InputHandler, StringBindings},
+    prelude::*,
+    renderer::{ImageFormat, Texture},
+    shrev::{EventChannel, ReaderId},
+    ui::{Anchor, UiImage, UiTransform},
+};
+use log::warn;
+
+use super::{Menu, BUTTON_HEIGHT, BUTTON_WIDTH};
+use crate::{
+    loading::{level_name, LoadingState},
+    save::{SaveGame, SaveSlots, SlotMetadata, SlotStatus, UnsavedProgress},
+    strings::Strings,
+    ui_theme::{ButtonClick, UiTheme},
+};
+
+/// Saves the game being played in `world`, started from `seed`, to `slot`, and notes that it
+/// has no unsaved progress once it is.
+pub(super) fn save_game(
+    world: &World,
+    slots: &SaveSlots,
+    seed: u64,
+    slot: usize,
+) -> Result<(), failure::Error> {
+    let game = SaveGame::capture(world, seed, slots.saved_components());
+    let metadata = SlotMetadata::now(&game, &level_name());
+    slots.save(slot, &game, &metadata, &slots.thumbnail(world))?;
+    world.write_resource::<UnsavedProgress>().saved(slot);
+    Ok(())
+}
+
+/// What clicking a slot does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SlotAction {
+    Save,
+    Load,
+    Delete,
+}
+
+/// Lists the save slots with what is in them, and saves to, loads from or empties the one
+/// clicked. Below the slots, a button picks which of those clicking a slot does, and `Back` or
+/// the `pause` action returns to the menu below.
+///
+/// Loading a corrupt slot with a backup first offers the backup in the slot's place, and loads
+/// it if the slot is clicked again.
+pub struct SaveSlotsState {
+    /// What a loaded game is played with, as for `LoadingState::new`.
+    local_players: usize,
+    /// The seed of the game being played, or `None` from the main menu, where there is nothing
+    /// to save.
+    playing: Option<u64>,
+    action: SlotAction,
+    /// The corrupt slot whose backup is being offered to load.
+    offered_backup: Option<usize>,
+    slots: Vec<SlotStatus>,
+    menu: Option<Menu>,
+    thumbnails: Vec<Entity>,
+    clicks: Option<ReaderId<ButtonClick>>,
+    /// Starts pressed, in case the `pause` action was held to get here.
+    was_pressed: bool,
+}
+
+impl SaveSlotsState {
+    /// The slots as reached from the main menu.
+    pub fn from_main_menu(local_players: usize) -> Self {
+        SaveSlotsState::new(local_players, None, SlotAction::Load)
+    }
+
+    /// The slots as reached from the pause menu of the game started from `seed`.
+    pub fn from_game(local_players: usize, seed: u64) -> Self {
+        SaveSlotsState::new(local_players, Some(seed), SlotAction::Save)
+    }
+
+    fn new(local_players: usize, playing: Option<u64>, action: SlotAction) -> Self {
+        SaveSlotsState {
+            local_players,
+            playing,
+            action,
+            offered_backup: None,
+            slots: Vec::new(),
+            menu: None,
+            thumbnails: Vec::new(),
+            clicks: None,
+            was_pressed: true,
+        }
+    }
+
+    /// The action after the current one, skipping saving when there is no game to save.
+    fn next_action(&self) -> SlotAction {
+        match (self.action, self.playing) {
+            (SlotAction::Save, _) => SlotAction::Load,
+            (SlotAction::Load, _) => SlotAction::Delete,
+            (SlotAction::Delete, Some(_)) => SlotAction::Save,
+            (SlotAction::Delete, None) => SlotAction::Load,
+        }
+    }
+
+    /// Shows the slots as they are on disk now, replacing what was shown before.
+    fn show(&mut self, world: &mut World, focus: usize) {
+        self.hide(world);
+        self.slots = world.read_resource::<SaveSlots>().list();
+        let (title, labels) = {
+            let strings = world.read_resource::<Strings>();
+            let mut labels: Vec<String> = self
+                .slots
+                .iter()
+                .enumerate()
+                .map(|(slot, status)| match status {
+                    SlotStatus::Empty => strings.format("save_slots.empty", &[&(slot + 1)]),
+                    SlotStatus::Corrupt(None) => {
+                        strings.format("save_slots.corrupt", &[&(slot + 1)])
+                    }
+                    SlotStatus::Corrupt(Some(backup)) => strings.format(
+                        if self.offered_backup == Some(slot) {
+                            "save_slots.offer_backup"
+                        } else {
+                            "save_slots.corrupt_backup"
+                        },
+                        &[&(slot + 1), &backup.timestamp()],
+                    ),
+                    SlotStatus::Saved(metadata) => strings.format(
+                        "save_slots.saved",
+                        &[
+                            &(slot + 1),
+                            &metadata.level,
+                            &metadata.playtime_text(),
+                            &metadata.timestamp(),
+                        ],
+                    ),
+                })
+                .collect();
+            let action = strings.get(&format!("save_slots.{:?}", self.action));
+            labels.push(strings.format("save_slots.action", &[&action]));
+            labels.push(strings.get("menu.back"));
+            (strings.get("save_slots.title"), labels)
+        };
+        let menu = Menu::build(world, &title, labels, focus);
+
+        // Each saved slot's thumbnail, or a corrupt slot's backup's, goes to the left of its
+        // button.
+        let (height, padding) = {
+            let theme = world.read_resource::<UiTheme>();
+            (BUTTON_HEIGHT * theme.text_scale, theme.padding)
+        };
+        for (slot, status) in self.slots.iter().enumerate() {
+            let (metadata, backup) = match status {
+                SlotStatus::Saved(metadata) => (metadata, false),
+                SlotStatus::Corrupt(Some(metadata)) => (metadata, true),
+                _ => continue,
+            };
+            let y = world
+                .read_storage::<UiTransform>()
+                .get(menu.button(slot))
+                .map_or(0., |transform| transform.local_y);
+            let texture = world.read_resource::<Loader>().load(
+                world
+                    .read_resource::<SaveSlots>()
+                    .thumbnail_asset(slot, backup),
+                ImageFormat::default(),
+                (),
+                &world.read_resource::<AssetStorage<Texture>>(),
+            );
+            let (columns, rows) = metadata.map_size;
+            let width = height * columns as f32 / rows.max(1) as f32;
+            let thumbnail = world
+                .create_entity()
+                .with(UiTransform::new(
+                    format!("slot_thumbnail_{}", slot),
+                    Anchor::Middle,
+                    Anchor::Middle,
+                    -(BUTTON_WIDTH + width) / 2. - padding,
+                    y,
+                    10.,
+                    width,
+                    height,
+                ))
+                .with(UiImage::Texture(texture))
+                .build();
+            self.thumbnails.push(thumbnail);
+        }
+        self.menu = Some(menu);
+    }
+
+    fn hide(&mut self, world: &mut World) {
+        if let Some(menu) = self.menu.take() {
+            menu.delete(world);
+        }
+        world
+            .delete_entities(&self.thumbnails)
+            .expect("Thumbnails are alive");
+        self.thumbnails.clear();
+    }
+
+    /// Does the current action to `slot`. Returns the game to load, if it is loaded.
+    fn act(&mut self, world: &World, slot: usize) -> Option<SaveGame> {
+        let offered = self.offered_backup.take() == Some(slot);
+        let slots = world.read_resource::<SaveSlots>();
+        let result = match self.action {
+            SlotAction::Save => match self.playing {
+                Some(seed) => save_game(world, &slots, seed, slot),
+                None => Ok(()),
+            },
+            SlotAction::Load => match self.slots.get(slot) {
+                Some(SlotStatus::Saved(_)) => match slots.load(slot) {
+                    Ok(game) => {
+                        world.write_resource::<UnsavedProgress>().saved(slot);
+                        return Some(game);
+                    }
+                    Err(err) => Err(err),
+                },
+                // A corrupt slot offers its backup instead, when it has one that can be read,
+                // and loads it once the offer is taken.
+                Some(SlotStatus::Corrupt(Some(_))) if !offered => {
+                    self.offered_backup = Some(slot);
+                    Ok(())
+                }
+                Some(SlotStatus::Corrupt(Some(_))) => match slots.load_backup(slot) {
+                    Ok(game) => {
+                        warn!("Slot {} is corrupt, loading its backup", slot + 1);
+                        world.write_resource::<UnsavedProgress>().saved(slot);
+                        return Some(game);
+                    }
+                    Err(err) => Err(err),
+                },
+                // Empty slots, and corrupt ones without a backup, have nothing to load.
+                _ => Ok(()),
+            },
+            SlotAction::Delete => slots.delete(slot),
+        };
+        if let Err(err) = result {
+            warn!("Failed to {:?} slot {}: {}", self.action, slot + 1, err);
+        }
+        None
+    }
+
+    /// Leaves the menus, and the game if one is being played, for `game`.
+    fn load(&self, world: &World, game: SaveGame) {
+        let local_players = self.local_players;
+        let mut transitions = world
+            .write_resource::<EventChannel<TransEvent<GameData<'static, 'static>, StateEvent>>>();
+        transitions.single_write(Box::new(|| Trans::Pop));
+        if self.playing.is_some() {
+            // The pause menu is between this and the game.
+            transitions.single_write(Box::new(|| Trans::Pop));
+        }
+        transitions.single_write(Box::new(move || {
+            Trans::Switch(Box::new(LoadingState::from_save(
+                local_players,
+                game.clone(),
+            )))
+        }));
+    }
+}
+
+impl SimpleState for SaveSlotsState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.clicks = Some(
+            data.world
+                .write_resource::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+        self.show(data.world, 0);
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        self.hide(data.world);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let pressed = data
+            .world
+            .read_resource::<InputHandler<StringBindings>>()
+            .action_is_down("pause")
+            .unwrap_or(false);
+        let back = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+        if back {
+            return Trans::Pop;
+        }
+
+        let reader = self.clicks.as_mut().expect("Save slot menu is started");
+        let clicked = match self
+            .menu
+            .as_ref()
+            .and_then(|menu| menu.clicked(data.world, reader))
+        {
+            Some(clicked) => clicked,
+            None => return Trans::None,
+        };
+        let slots = self.slots.len();
+        if clicked < slots {
+            if let Some(game) = self.act(data.world, clicked) {
+                self.load(data.world, game);
+                return Trans::None;
+            }
+        } else if clicked == slots {
+            self.offered_backup = None;
+            self.action = self.next_action();
+        } else {
+            return Trans::Pop;
+        }
+        // Show what the slots hold now.
+        self.show(data.world, clicked);
+        Trans::None
+    }
+}
@@ -0,0 +1,335 @@
+//! A small map of the level in the top-right corner of the window.
+//!
+//! The minimap is a grid of coloured cells, each covering a block of tiles so that large maps
+//! still fit in `max_cells` cells a side. A cell shows what the fog of war shows of its tiles:
+//! nothing until one of them is seen, the colour of its tiles dimmed once they have been, and
+//! at full brightness while one is in sight. Players and `MinimapPing`s are marked on top.
+
+use amethyst::{
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Join, Read, ReadStorage, System, World,
+        WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::SpriteRender,
+    ui::{Anchor, UiImage, UiTransform},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lighting::{LightGrid, TileVisibility},
+    movement::world_position,
+    navigation::{NavGrid, TileCoord},
+    player::Player,
+    save::SpriteColors,
+    tile_map::{MapTile, TileMap},
+};
+
+/// How the minimap is laid out, read from `minimap.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MinimapConfig {
+    pub enabled: bool,
+    /// Most cells along either side. Maps wider or taller than this share cells between tiles.
+    pub max_cells: usize,
+    /// Width and height of a cell in pixels.
+    pub cell_size: f32,
+    /// Pixels between the minimap and the corner of the window.
+    pub margin: f32,
+    /// How much of their colour explored cells out of sight keep.
+    pub explored_brightness: f32,
+    /// Seconds a ping stays on the minimap.
+    pub ping_duration: f32,
+}
+
+impl Default for MinimapConfig {
+    fn default() -> Self {
+        MinimapConfig {
+            enabled: true,
+            max_cells: 48,
+            cell_size: 4.,
+            margin: 8.,
+            explored_brightness: 0.4,
+            ping_duration: 3.,
+        }
+    }
+}
+
+const PLAYER_COLOR: [f32; 4] = [1., 1., 1., 1.];
+const PING_COLOR: [f32; 4] = [1., 0.85, 0.2, 1.];
+const HIDDEN: [f32; 4] = [0., 0., 0., 0.];
+
+/// Columns and rows of minimap cells for a map of `map_size` tiles, keeping its shape but no
+/// more than `max_cells` along either side.
+pub fn cell_grid(map_size: (usize, usize), max_cells: usize) -> (usize, usize) {
+    let (width, height) = map_size;
+    let longest = width.max(height);
+    if longest <= max_cells || longest == 0 {
+        return map_size;
+    }
+    let scale = |side: usize| (side * max_cells).div_ceil(longest).max(1);
+    (scale(width), scale(height))
+}
+
+/// The cell of a `cells`-sized minimap showing `tile` of a map of `map_size` tiles.
+pub fn cell_of(tile: TileCoord, map_size: (usize, usize), cells: (usize, usize)) -> TileCoord {
+    let along = |tile: usize, side: usize, cells: usize| {
+        (tile * cells)
+            .checked_div(side)
+            .map_or(0, |cell| cell.min(cells.saturating_sub(1)))
+    };
+    (
+        along(tile.0, map_size.0, cells.0),
+        along(tile.1, map_size.1, cells.1),
+    )
+}
+
+/// A cell of the minimap, by its column and row from the bottom left.
+#[derive(Clone, Copy, Debug)]
+pub struct MinimapCell(pub TileCoord);
+
+impl Component for MinimapCell {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// A spot marked on the minimap until `remaining` runs out.
+#[derive(Clone, Copy, Debug)]
+pub struct MinimapPing {
+    pub position: Vector2<f32>,
+    pub remaining: f32,
+}
+
+impl Component for MinimapPing {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Creates the minimap's cells for `map`.
+pub fn spawn_minimap(world: &mut World, map: &TileMap) {
+    let config = *world.read_resource::<MinimapConfig>();
+    if !config.enabled {
+        return;
+    }
+    let (columns, rows) = cell_grid((map.width, map.height), config.max_cells);
+    for row in 0..rows {
+        for column in 0..columns {
+            let x = -config.margin - (columns - column) as f32 * config.cell_size;
+            let y = -config.margin - (rows - row) as f32 * config.cell_size;
+            world
+                .create_entity()
+                .with(UiTransform::new(
+                    format!("minimap_{}_{}", column, row),
+                    Anchor::TopRight,
+                    Anchor::BottomLeft,
+                    x,
+                    y,
+                    5.,
+                    config.cell_size,
+                    config.cell_size,
+                ))
+                .with(UiImage::SolidColor(HIDDEN))
+                .with(MinimapCell((column, row)))
+                .build();
+        }
+    }
+}
+
+/// What a cell shows of its tiles.
+#[derive(Clone, Copy)]
+struct CellShade {
+    visibility: TileVisibility,
+    color: [f32; 4],
+}
+
+impl CellShade {
+    /// Shows a tile of the cell with `visibility` and sprite colour `color` if it is better
+    /// seen than the tiles shown so far.
+    fn show(&mut self, visibility: TileVisibility, color: [u8; 4], explored_brightness: f32) {
+        if rank(visibility) <= rank(self.visibility) {
+            return;
+        }
+        let brightness = match visibility {
+            TileVisibility::Visible => 1.,
+            _ => explored_brightness,
+        };
+        let channel = |value: u8| f32::from(value) / 255. * brightness;
+        *self = CellShade {
+            visibility,
+            color: [channel(color[0]), channel(color[1]), channel(color[2]), 1.],
+        };
+    }
+}
+
+/// Colours the minimap's cells from the fog of war and the tiles' sprites, and marks the
+/// players and pings on it. The `minimap_ping` action pings where the first player stands.
+#[derive(Default)]
+pub struct MinimapSystem {
+    was_pinging: bool,
+}
+
+impl<'s> System<'s> for MinimapSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, MapTile>,
+        ReadStorage<'s, SpriteRender>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, MinimapCell>,
+        WriteStorage<'s, MinimapPing>,
+        WriteStorage<'s, UiImage>,
+        Read<'s, MinimapConfig>,
+        Read<'s, TileMap>,
+        Read<'s, LightGrid>,
+        Read<'s, NavGrid>,
+        Read<'s, SpriteColors>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            map_tiles,
+            sprites,
+            players,
+            transforms,
+            cells,
+            mut pings,
+            mut images,
+            config,
+            map,
+            light,
+            nav,
+            colors,
+            input,
+            time,
+        ): Self::SystemData,
+    ) {
+        let pinging = input.action_is_down("minimap_ping").unwrap_or(false);
+        let ping = pinging && !self.was_pinging;
+        self.was_pinging = pinging;
+        if ping {
+            let first = (&players, &transforms)
+                .join()
+                .find(|(player, _)| player.index == 0)
+                .map(|(_, transform)| world_position(transform));
+            if let Some(position) = first {
+                entities
+                    .build_entity()
+                    .with(
+                        MinimapPing {
+                            position,
+                            remaining: config.ping_duration,
+                        },
+                        &mut pings,
+                    )
+                    .build();
+            }
+        }
+        for (entity, ping) in (&entities, &mut pings).join() {
+            ping.remaining -= time.delta_seconds();
+            if ping.remaining <= 0. {
+                entities.delete(entity).expect("Ping is alive");
+            }
+        }
+
+        if !config.enabled {
+            return;
+        }
+        let map_size = (map.width, map.height);
+        let grid = cell_grid(map_size, config.max_cells);
+        let mut shades = vec![
+            CellShade {
+                visibility: TileVisibility::Unseen,
+                color: HIDDEN,
+            };
+            grid.0 * grid.1
+        ];
+        // One pass over the tiles, each cell showing the best seen of its tiles.
+        for (&MapTile(tile), sprite) in (&map_tiles, &sprites).join() {
+            let (column, row) = cell_of(tile, map_size, grid);
+            shades[row * grid.0 + column].show(
+                light.visibility(Some(tile)),
+                colors.get(sprite.sprite_number).data,
+                config.explored_brightness,
+            );
+        }
+        let mut mark = |position: Vector2<f32>, color: [f32; 4]| {
+            if let Some(tile) = nav.tile_at(position) {
+                let (column, row) = cell_of(tile, map_size, grid);
+                shades[row * grid.0 + column].color = color;
+            }
+        };
+        for (_, transform) in (&players, &transforms).join() {
+            mark(world_position(transform), PLAYER_COLOR);
+        }
+        for ping in (&pings).join() {
+            mark(ping.position, PING_COLOR);
+        }
+
+        for (&MinimapCell((column, row)), image) in (&cells, &mut images).join() {
+            if let Some(shade) = shades.get(row * grid.0 + column) {
+                *image = UiImage::SolidColor(shade.color);
+            }
+        }
+    }
+}
+
+/// Orders visibilities from least to most seen.
+fn rank(visibility: TileVisibility) -> u8 {
+    match visibility {
+        TileVisibility::Unseen => 0,
+        TileVisibility::Seen => 1,
+        TileVisibility::Visible => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_large_map_packs_blocks_of_tiles_into_each_cell() {
+        let map_size = (100, 50);
+        let grid = cell_grid(map_size, 20);
+        assert_eq!(grid, (20, 10));
+
+        let mut tiles_per_cell = vec![0; grid.0 * grid.1];
+        for x in 0..map_size.0 {
+            for y in 0..map_size.1 {
+                let (column, row) = cell_of((x, y), map_size, grid);
+                tiles_per_cell[row * grid.0 + column] += 1;
+            }
+        }
+        assert!(tiles_per_cell.iter().all(|&tiles| tiles == 25));
+        assert_eq!(cell_of((4, 4), map_size, grid), (0, 0));
+        assert_eq!(cell_of((5, 9), map_size, grid), (1, 1));
+        assert_eq!(cell_of((99, 49), map_size, grid), (19, 9));
+
+        // A map that already fits keeps a cell per tile.
+        assert_eq!(cell_grid((12, 8), 20), (12, 8));
+        assert_eq!(cell_of((11, 7), (12, 8), (12, 8)), (11, 7));
+    }
+
+    #[test]
+    fn a_shared_cell_shows_its_best_seen_tile() {
+        let mut shade = CellShade {
+            visibility: TileVisibility::Unseen,
+            color: HIDDEN,
+        };
+        shade.show(TileVisibility::Unseen, [255, 0, 0, 255], 0.5);
+        assert_eq!(shade.color, HIDDEN);
+
+        shade.show(TileVisibility::Seen, [255, 0, 0, 255], 0.5);
+        assert_eq!(shade.color, [0.5, 0., 0., 1.]);
+
+        shade.show(TileVisibility::Visible, [0, 255, 0, 255], 0.5);
+        assert_eq!(shade.color, [0., 1., 0., 1.]);
+
+        // Tiles seen less well than one already shown don't change the cell.
+        shade.show(TileVisibility::Seen, [0, 0, 255, 255], 0.5);
+        shade.show(TileVisibility::Visible, [0, 0, 255, 255], 0.5);
+        assert_eq!(shade.color, [0., 1., 0., 1.]);
+    }
+}
@@ -0,0 +1,228 @@
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{Component, DenseVecStorage, Join, Read, ReadStorage, System, WriteStorage},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock,
+    collision::{push_out, sweep, Aabb, Collider, OverlapConfig, TileColliders},
+    disabled::Disabled,
+    god_mode::GodMode,
+    player::Player,
+    sleep::Asleep,
+};
+
+/// World units per second, integrated into the entity's `Transform` by the `MovementSystem`.
+#[derive(Clone, Copy, Debug)]
+pub struct Velocity(pub Vector2<f32>);
+
+impl Default for Velocity {
+    fn default() -> Self {
+        Velocity(Vector2::zeros())
+    }
+}
+
+impl Component for Velocity {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The unit direction an entity last moved in, used when an action needs a direction and
+/// there is no input to take it from.
+#[derive(Clone, Copy, Debug)]
+pub struct Facing(pub Vector2<f32>);
+
+impl Default for Facing {
+    fn default() -> Self {
+        Facing(Vector2::new(1., 0.))
+    }
+}
+
+impl Component for Facing {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The translation of `transform` projected onto the 2D play field.
+pub fn world_position(transform: &Transform) -> Vector2<f32> {
+    let translation = transform.translation();
+    Vector2::new(translation.x.as_f32(), translation.y.as_f32())
+}
+
+/// When movement is split into substeps, read from `movement.ron`.
+///
+/// Each axis of a step is swept on its own, so a diagonal move much longer than the mover can
+/// cut past the corner of an obstacle. Splitting it keeps each piece short next to the mover.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MovementSubsteps {
+    /// The longest a step may be, as a fraction of the mover's smallest dimension.
+    pub threshold: f32,
+    /// The most steps one move is split into. Movement beyond that is left in longer steps.
+    pub max_substeps: u32,
+}
+
+impl Default for MovementSubsteps {
+    fn default() -> Self {
+        MovementSubsteps {
+            threshold: 0.5,
+            max_substeps: 8,
+        }
+    }
+}
+
+impl MovementSubsteps {
+    /// How many steps a move of `distance` by a mover `size` units across is split into.
+    pub fn count(&self, distance: f32, size: f32) -> u32 {
+        let longest = self.threshold * size;
+        if longest <= 0. {
+            return 1;
+        }
+        ((distance / longest).ceil() as u32).clamp(1, self.max_substeps.max(1))
+    }
+
+    /// The displacement `aabb` can make of `motion`, moved in substeps and stopped at the
+    /// first of the `obstacles` in its way.
+    pub fn integrate(&self, aabb: &Aabb, motion: Vector2<f32>, obstacles: &[Aabb]) -> Vector2<f32> {
+        let extent = aabb.max - aabb.min;
+        let size = extent.x.min(extent.y);
+        let steps = self.count(motion.norm(), size);
+        let step = motion / steps as f32;
+        let mut moved = *aabb;
+        let mut total = Vector2::zeros();
+        for _ in 0..steps {
+            let allowed = sweep(&moved, step, obstacles);
+            moved = moved.translated(allowed);
+            total += allowed;
+        }
+        total
+    }
+}
+
+/// Applies `Velocity` to `Transform`, stopping entities that have a `Collider` at the first
+/// static collider or wall in their way. Long moves are split into `MovementSubsteps`.
+///
+/// Movers are moved in entity order, and those already inside obstacles are first pushed out
+/// as the `OverlapConfig` says, so the same world always moves the same way. Players pass
+/// through everything while `GodMode` has no-clip on.
+pub struct MovementSystem;
+
+impl<'s> System<'s> for MovementSystem {
+    type SystemData = (
+        ReadStorage<'s, Velocity>,
+        ReadStorage<'s, Collider>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Disabled>,
+        ReadStorage<'s, Asleep>,
+        WriteStorage<'s, Transform>,
+        Read<'s, TileColliders>,
+        Read<'s, MovementSubsteps>,
+        Read<'s, OverlapConfig>,
+        Read<'s, GodMode>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            velocities,
+            colliders,
+            players,
+            disabled,
+            asleep,
+            mut transforms,
+            walls,
+            substeps,
+            overlap,
+            god_mode,
+            clock,
+        ): Self::SystemData,
+    ) {
+        // Disabled and sleeping movers stand still, so they are in the way like anything else
+        // that does.
+        let obstacles: Vec<Aabb> = (
+            &colliders,
+            &transforms,
+            velocities.maybe(),
+            disabled.maybe(),
+            asleep.maybe(),
+        )
+            .join()
+            .filter(|(_, _, velocity, disabled, asleep)| {
+                velocity.is_none() || disabled.is_some() || asleep.is_some()
+            })
+            .map(|(collider, transform, _, _, _)| {
+                Aabb::from_center(world_position(transform), collider.half_extents)
+            })
+            .chain(walls.0.iter().copied())
+            .collect();
+
+        let delta = clock.delta_seconds();
+        for (velocity, transform, collider, player, _, _) in (
+            &velocities,
+            &mut transforms,
+            colliders.maybe(),
+            players.maybe(),
+            !&disabled,
+            !&asleep,
+        )
+            .join()
+        {
+            let mut motion = velocity.0 * delta;
+            let no_clip = god_mode.no_clip && player.is_some();
+            if let Some(collider) = collider.filter(|_| !no_clip) {
+                let aabb = Aabb::from_center(world_position(transform), collider.half_extents);
+                let pushed = push_out(&aabb, &obstacles, &overlap);
+                motion = pushed + substeps.integrate(&aabb.translated(pushed), motion, &obstacles);
+            }
+            transform.prepend_translation_x(motion.x);
+            transform.prepend_translation_y(motion.y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4 unit mover at the origin heading up and right past the bottom-left corner of a
+    /// block, and the block.
+    fn corner() -> (Aabb, Aabb) {
+        let mover = Aabb::from_center(Vector2::zeros(), Vector2::new(2., 2.));
+        let block = Aabb {
+            min: Vector2::new(10., 20.),
+            max: Vector2::new(30., 40.),
+        };
+        (mover, block)
+    }
+
+    #[test]
+    fn fast_diagonal_moves_stop_at_a_wall_corner() {
+        let (mover, block) = corner();
+        let motion = Vector2::new(40., 40.);
+        // Caught on the corner, the mover slides along under the block until it is past it.
+        let substeps = MovementSubsteps::default();
+        assert_eq!(
+            substeps.integrate(&mover, motion, &[block]),
+            Vector2::new(40., 28.)
+        );
+        // In one step it would cut straight through the corner.
+        let whole = MovementSubsteps {
+            max_substeps: 1,
+            ..substeps
+        };
+        assert_eq!(whole.integrate(&mover, motion, &[block]), motion);
+    }
+
+    #[test]
+    fn substeps_are_capped() {
+        let substeps = MovementSubsteps::default();
+        assert_eq!(substeps.count(1., 4.), 1);
+        assert_eq!(substeps.count(3., 4.), 2);
+        assert_eq!(substeps.count(100., 4.), 8);
+        let none = MovementSubsteps {
+            max_substeps: 0,
+            ..substeps
+        };
+        assert_eq!(none.count(100., 4.), 1);
+    }
+}
@@ -0,0 +1,545 @@
+//! Click-to-move: with the mode on, right-clicking a tile walks the first player there along
+//! an A* path over the tile map.
+//!
+//! The path only steers the player's `Velocity`, so the `MovementSystem` still resolves
+//! collisions. Any movement input takes over from the path, and a player that stops making
+//! progress gives up on it.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use amethyst::{
+    core::{
+        math::{Point3, Vector2},
+        transform::Transform,
+    },
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Join, NullStorage, Read, ReadExpect, ReadStorage,
+        System, Write, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::{debug_drawing::DebugLinesComponent, palette::Srgba},
+    window::ScreenDimensions,
+    winit::MouseButton,
+};
+use log::debug;
+
+use crate::{
+    analog::AnalogResponse,
+    camera::CameraMatrices,
+    clock::GameClock,
+    collision::Aabb,
+    disabled::Disabled,
+    movement::{world_position, Velocity},
+    player::{input_direction, Player},
+    tile_map::{MapProjection, TileMap},
+};
+
+/// Column and row of a tile, counted from the bottom-left corner of the map.
+pub type TileCoord = (usize, usize);
+
+/// Seconds a player may go without making progress along a path before giving up on it.
+const STALL_TIMEOUT: f32 = 0.3;
+
+/// Depth at which paths are drawn, in front of everything.
+const PATH_DEPTH: f32 = 0.5;
+
+/// Which tiles of the map can be walked on.
+#[derive(Clone, Debug, Default)]
+pub struct NavGrid {
+    width: usize,
+    height: usize,
+    tile_size: f32,
+    projection: MapProjection,
+    /// Where the map's bottom-left corner is in the world.
+    origin: (f32, f32),
+    walkable: Vec<bool>,
+}
+
+impl NavGrid {
+    fn origin(&self) -> Vector2<f32> {
+        Vector2::new(self.origin.0, self.origin.1)
+    }
+
+    pub fn from_map(map: &TileMap) -> Self {
+        let walkable = map
+            .tile_mask(&map.solid)
+            .iter()
+            .map(|solid| !solid)
+            .collect();
+        NavGrid {
+            width: map.width,
+            height: map.height,
+            tile_size: map.tile_size,
+            projection: map.projection,
+            origin: map.origin,
+            walkable,
+        }
+    }
+
+    pub fn is_walkable(&self, (column, row): TileCoord) -> bool {
+        column < self.width && row < self.height && self.walkable[row * self.width + column]
+    }
+
+    /// The tile under `point`, or `None` when `point` is outside the map.
+    pub fn tile_at(&self, point: Vector2<f32>) -> Option<TileCoord> {
+        if self.tile_size <= 0. {
+            return None;
+        }
+        let tile = self
+            .projection
+            .to_tile(point - self.origin(), self.tile_size);
+        if tile.x < 0. || tile.y < 0. {
+            return None;
+        }
+        let (column, row) = (tile.x as usize, tile.y as usize);
+        if column < self.width && row < self.height {
+            Some((column, row))
+        } else {
+            None
+        }
+    }
+
+    /// The tile under `point`, or the closest edge tile when `point` is outside the map.
+    pub fn clamped_tile_at(&self, point: Vector2<f32>) -> TileCoord {
+        let tile = self
+            .projection
+            .to_tile(point - self.origin(), self.tile_size);
+        let clamp =
+            |value: f32, count: usize| (value.max(0.) as usize).min(count.saturating_sub(1));
+        (clamp(tile.x, self.width), clamp(tile.y, self.height))
+    }
+
+    pub fn tile_center(&self, tile: TileCoord) -> Vector2<f32> {
+        self.projection.tile_center(tile, self.tile_size) + self.origin()
+    }
+
+    /// The corners of `tile` in the world, going around it.
+    pub fn tile_corners(&self, tile: TileCoord) -> [Vector2<f32>; 4] {
+        self.projection
+            .tile_corners(tile, self.tile_size)
+            .map(|corner| corner + self.origin())
+    }
+
+    /// Every tile of the map, row by row from the bottom.
+    pub fn tiles(&self) -> impl Iterator<Item = TileCoord> + '_ {
+        (0..self.height).flat_map(move |row| (0..self.width).map(move |column| (column, row)))
+    }
+
+    /// Whether every tile of the map under `area` can be walked on. Ground off the map isn't
+    /// blocked by anything, so it counts as open.
+    pub fn is_open(&self, area: &Aabb) -> bool {
+        self.tiles_under(area).all(|tile| self.is_walkable(tile))
+    }
+
+    /// The tiles of the map under `area`.
+    pub fn tiles_under(&self, area: &Aabb) -> impl Iterator<Item = TileCoord> {
+        // Every tile the area's bounds in tile space reach, which for isometric maps includes
+        // a few only beside its corners. Tiles only touching the edge of the bounds aren't
+        // under it.
+        let corners = [
+            area.min,
+            Vector2::new(area.max.x, area.min.y),
+            area.max,
+            Vector2::new(area.min.x, area.max.y),
+        ]
+        .map(|corner| {
+            self.projection
+                .to_tile(corner - self.origin(), self.tile_size)
+        });
+        let bound = |axis: usize, pick: fn(f32, f32) -> f32| {
+            corners
+                .iter()
+                .map(|corner| corner[axis])
+                .fold(corners[0][axis], pick)
+        };
+        let first = |axis: usize| bound(axis, f32::min).floor().max(0.) as usize;
+        let last = |axis: usize, count: usize| {
+            (bound(axis, f32::max).ceil() as i64).clamp(0, count as i64) as usize
+        };
+        let (rows, columns) = if self.tile_size > 0. {
+            (
+                first(1)..last(1, self.height),
+                first(0)..last(0, self.width),
+            )
+        } else {
+            (0..0, 0..0)
+        };
+        rows.flat_map(move |row| columns.clone().map(move |column| (column, row)))
+    }
+
+    /// The walkable tile closest to `tile`, which is `tile` itself if it is walkable.
+    pub fn nearest_walkable(&self, tile: TileCoord) -> Option<TileCoord> {
+        let distance = |(column, row): TileCoord| {
+            let dx = column as i64 - tile.0 as i64;
+            let dy = row as i64 - tile.1 as i64;
+            dx * dx + dy * dy
+        };
+        self.tiles()
+            .filter(|&candidate| self.is_walkable(candidate))
+            .min_by_key(|&candidate| distance(candidate))
+    }
+
+    fn neighbours(&self, (column, row): TileCoord) -> impl Iterator<Item = TileCoord> + '_ {
+        let candidates = [
+            column.checked_sub(1).map(|column| (column, row)),
+            Some((column + 1, row)),
+            row.checked_sub(1).map(|row| (column, row)),
+            Some((column, row + 1)),
+        ];
+        IntoIterator::into_iter(candidates)
+            .flatten()
+            .filter(move |&tile| self.is_walkable(tile))
+    }
+
+    /// The shortest path of walkable tiles from `start` to `goal`, both included, moving
+    /// between tiles that share an edge. `None` if either end can't be walked on or `goal`
+    /// can't be reached.
+    pub fn find_path(&self, start: TileCoord, goal: TileCoord) -> Option<Vec<TileCoord>> {
+        if !self.is_walkable(start) || !self.is_walkable(goal) {
+            return None;
+        }
+        let index = |(column, row): TileCoord| row * self.width + column;
+        let heuristic = |(column, row): TileCoord| {
+            (column as i64 - goal.0 as i64).unsigned_abs()
+                + (row as i64 - goal.1 as i64).unsigned_abs()
+        };
+
+        let mut cost = vec![u64::MAX; self.walkable.len()];
+        let mut came_from = vec![None; self.walkable.len()];
+        let mut open = BinaryHeap::new();
+        cost[index(start)] = 0;
+        open.push(Reverse((heuristic(start), start)));
+
+        while let Some(Reverse((_, tile))) = open.pop() {
+            if tile == goal {
+                let mut path = vec![goal];
+                while let Some(previous) = came_from[index(*path.last().unwrap())] {
+                    path.push(previous);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let next_cost = cost[index(tile)] + 1;
+            for neighbour in self.neighbours(tile) {
+                if next_cost < cost[index(neighbour)] {
+                    cost[index(neighbour)] = next_cost;
+                    came_from[index(neighbour)] = Some(tile);
+                    open.push(Reverse((next_cost + heuristic(neighbour), neighbour)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Whether right-clicking moves the first player, toggled with the `toggle_click_to_move`
+/// action.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClickToMove {
+    pub enabled: bool,
+}
+
+/// The rest of a clicked path, nearest point first.
+#[derive(Clone, Debug)]
+pub struct NavPath {
+    pub goal: TileCoord,
+    pub waypoints: Vec<Vector2<f32>>,
+    last_position: Option<Vector2<f32>>,
+    stalled: f32,
+}
+
+impl NavPath {
+    pub fn new(grid: &NavGrid, path: &[TileCoord]) -> Option<Self> {
+        Some(NavPath {
+            goal: *path.last()?,
+            waypoints: path.iter().map(|&tile| grid.tile_center(tile)).collect(),
+            last_position: None,
+            stalled: 0.,
+        })
+    }
+
+    /// Moves the rest of the path by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        for waypoint in &mut self.waypoints {
+            *waypoint += offset;
+        }
+        if let Some(last) = &mut self.last_position {
+            *last += offset;
+        }
+    }
+}
+
+impl Component for NavPath {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Marks the entity whose debug lines show the paths being walked.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NavPathOverlay;
+
+impl Component for NavPathOverlay {
+    type Storage = NullStorage<Self>;
+}
+
+/// Toggles click-to-move, turns right-clicks into paths for the first player and draws the
+/// paths. Runs every frame so no click is missed between gameplay steps.
+#[derive(Default)]
+pub struct ClickToMoveSystem {
+    was_toggle_pressed: bool,
+    was_clicked: bool,
+}
+
+impl<'s> System<'s> for ClickToMoveSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Player>,
+        WriteStorage<'s, NavPath>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, NavPathOverlay>,
+        WriteStorage<'s, DebugLinesComponent>,
+        Write<'s, ClickToMove>,
+        Read<'s, NavGrid>,
+        Read<'s, CameraMatrices>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, ScreenDimensions>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            players,
+            mut paths,
+            transforms,
+            overlays,
+            mut debug_lines,
+            mut click_to_move,
+            grid,
+            camera,
+            input,
+            dimensions,
+        ): Self::SystemData,
+    ) {
+        let toggle = input
+            .action_is_down("toggle_click_to_move")
+            .unwrap_or(false);
+        if toggle && !self.was_toggle_pressed {
+            click_to_move.enabled = !click_to_move.enabled;
+            if !click_to_move.enabled {
+                paths.clear();
+            }
+        }
+        self.was_toggle_pressed = toggle;
+
+        let clicked = input.mouse_button_is_down(MouseButton::Right);
+        let click = clicked && !self.was_clicked;
+        self.was_clicked = clicked;
+        if click && click_to_move.enabled {
+            let player = (&entities, &players, &transforms)
+                .join()
+                .find(|(_, player, _)| player.index == 0)
+                .map(|(entity, _, transform)| (entity, world_position(transform)));
+            if let (Some((player, position)), Some(mouse)) = (player, input.mouse_position()) {
+                let clicked = camera.mouse_to_world(mouse, dimensions.height());
+                let clicked_tile = grid.clamped_tile_at(clicked);
+                let goal = grid.nearest_walkable(clicked_tile);
+                let start = grid.nearest_walkable(grid.clamped_tile_at(position));
+                let heading_there = paths
+                    .get(player)
+                    .is_some_and(|path| Some(path.goal) == goal);
+                // Clicking where the player is already going stops them instead.
+                let path = match (start, goal) {
+                    (Some(start), Some(goal)) if !heading_there => grid
+                        .find_path(start, goal)
+                        .and_then(|path| NavPath::new(&grid, &path)),
+                    _ => None,
+                };
+                match path {
+                    Some(path) => {
+                        paths.insert(player, path).expect("Player entity is alive");
+                    }
+                    None => {
+                        paths.remove(player);
+                    }
+                }
+            }
+        }
+
+        let point = |position: Vector2<f32>| Point3::new(position.x, position.y, PATH_DEPTH);
+        let color = Srgba::new(0.3, 1., 0.4, 1.);
+        for (_, lines) in (&overlays, &mut debug_lines).join() {
+            lines.clear();
+            for (path, transform) in (&paths, &transforms).join() {
+                let mut from = world_position(transform);
+                for &waypoint in &path.waypoints {
+                    lines.add_line(point(from), point(waypoint), color);
+                    from = waypoint;
+                }
+                let goal = grid.tile_center(path.goal);
+                let arm = Vector2::new(grid.tile_size / 4., grid.tile_size / 4.);
+                let flipped = Vector2::new(arm.x, -arm.y);
+                lines.add_line(point(goal - arm), point(goal + arm), color);
+                lines.add_line(point(goal - flipped), point(goal + flipped), color);
+            }
+        }
+    }
+}
+
+/// Steers players along their `NavPath`, dropping it once they arrive, take over with their
+/// own input or stop making progress.
+pub struct PathFollowSystem;
+
+impl<'s> System<'s> for PathFollowSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Disabled>,
+        WriteStorage<'s, NavPath>,
+        WriteStorage<'s, Velocity>,
+        ReadStorage<'s, Transform>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, AnalogResponse>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            players,
+            disabled,
+            mut paths,
+            mut velocities,
+            transforms,
+            input,
+            response,
+            clock,
+        ): Self::SystemData,
+    ) {
+        let delta = clock.delta_seconds();
+        let mut finished = Vec::new();
+        for (entity, player, path, velocity, transform, _) in (
+            &entities,
+            &players,
+            &mut paths,
+            &mut velocities,
+            &transforms,
+            !&disabled,
+        )
+            .join()
+        {
+            if input_direction(&input, &response, player.index) != Vector2::zeros() {
+                finished.push(entity);
+                continue;
+            }
+
+            let position = world_position(transform);
+            let step = player.speed * delta;
+            if let Some(last) = path.last_position {
+                if (position - last).norm() < step * 0.25 {
+                    path.stalled += delta;
+                } else {
+                    path.stalled = 0.;
+                }
+            }
+            path.last_position = Some(position);
+            if path.stalled > STALL_TIMEOUT {
+                debug!("Path to {:?} is blocked, stopping", path.goal);
+                velocity.0 = Vector2::zeros();
+                finished.push(entity);
+                continue;
+            }
+
+            while path
+                .waypoints
+                .first()
+                .is_some_and(|waypoint| (waypoint - position).norm() <= 0.5)
+            {
+                path.waypoints.remove(0);
+            }
+            match path.waypoints.first() {
+                Some(waypoint) => {
+                    let offset = waypoint - position;
+                    let distance = offset.norm();
+                    // Slow down for the last bit so the waypoint is reached, not overshot.
+                    let speed = if delta > 0. {
+                        player.speed.min(distance / delta)
+                    } else {
+                        0.
+                    };
+                    velocity.0 = offset / distance * speed;
+                }
+                None => {
+                    velocity.0 = Vector2::zeros();
+                    finished.push(entity);
+                }
+            }
+        }
+        for entity in finished {
+            paths.remove(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tile_map::Tile;
+
+    use super::*;
+
+    /// A 3 by 3 grid with a wall up the middle of its bottom two rows.
+    fn grid() -> NavGrid {
+        let (floor, wall) = (Tile::Static(0), Tile::Static(1));
+        NavGrid::from_map(&TileMap {
+            width: 3,
+            height: 3,
+            tile_size: 16.,
+            tiles: vec![
+                floor, floor, floor, //
+                floor, wall, floor, //
+                floor, wall, floor,
+            ],
+            solid: vec![wall],
+            ..TileMap::default()
+        })
+    }
+
+    #[test]
+    fn paths_go_around_walls() {
+        assert_eq!(
+            grid().find_path((0, 0), (2, 0)),
+            Some(vec![(0, 0), (0, 1), (0, 2), (1, 2), (2, 2), (2, 1), (2, 0)])
+        );
+        assert_eq!(grid().find_path((0, 0), (0, 0)), Some(vec![(0, 0)]));
+    }
+
+    #[test]
+    fn walls_and_tiles_off_the_map_have_no_path() {
+        assert_eq!(grid().find_path((0, 0), (1, 0)), None);
+        assert_eq!(grid().find_path((0, 0), (3, 0)), None);
+    }
+
+    #[test]
+    fn walls_are_walked_to_from_beside_them() {
+        assert_eq!(grid().nearest_walkable((1, 0)), Some((0, 0)));
+        assert_eq!(grid().nearest_walkable((2, 2)), Some((2, 2)));
+    }
+
+    #[test]
+    fn points_are_found_on_their_tiles() {
+        let grid = grid();
+        assert_eq!(grid.tile_at(Vector2::new(40., 8.)), Some((2, 0)));
+        assert_eq!(grid.tile_at(Vector2::new(-1., 8.)), None);
+        assert_eq!(grid.clamped_tile_at(Vector2::new(-1., 100.)), (0, 2));
+        assert_eq!(grid.tile_center((2, 0)), Vector2::new(40., 8.));
+    }
+
+    #[test]
+    fn areas_over_walls_are_not_open() {
+        let grid = grid();
+        let half = Vector2::new(4., 4.);
+        assert!(grid.is_open(&Aabb::from_center(Vector2::new(8., 8.), half)));
+        assert!(!grid.is_open(&Aabb::from_center(Vector2::new(16., 8.), half)));
+        assert!(grid.is_open(&Aabb::from_center(Vector2::new(-20., 8.), half)));
+    }
+}
@@ -0,0 +1,208 @@
+//! Moving the world back to its origin as play goes far from it, for large maps.
+//!
+//! Positions are `f32`s, which get coarser the further they are from zero: thousands of units
+//! out, sprites and the camera no longer move smoothly. With rebasing on, once the first player
+//! is more than `threshold` from the world's origin on either axis, everything is moved back by
+//! the multiple of `step` nearest to where they are, between two gameplay steps, leaving them
+//! near the origin again. With `step` a power of two the shift is exact, and so is taking it
+//! from any position between half and twice it, on each axis. That takes in the first player
+//! and what is around them, which keep exactly the distances between them. Positions further
+//! out are rounded to the nearest `f32` where they end up, as if they had been placed there.
+//!
+//! The map moves along with everything on it: its `origin` is where its corner has got to, and
+//! whatever places things from the map's own coordinates, like its triggers, enemy spawns and
+//! zoom presets, places them from there. Saves store positions from the map's corner too, so a
+//! game saved after rebasing loads the same as one saved before.
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform, Parent},
+    ecs::{
+        prelude::{Join, World},
+        RunNow,
+    },
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    attract::TourStops,
+    camera::{CameraBounds, CameraFollow, Recentering, RoomBounds, RoomCamera},
+    chase::Chaser,
+    collision::{TileColliders, TileCollisionConfig},
+    combat_text::CombatText,
+    death::DeathBursts,
+    footsteps::Footsteps,
+    intro::IntroSweep,
+    lighting::LightGrid,
+    movement::world_position,
+    navigation::{NavGrid, NavPath},
+    player::Player,
+    rewind::RewindBuffer,
+    sight::SightGrid,
+    sound::SoundQueue,
+    spatial::SpatialGridSystem,
+    tile_map::TileMap,
+    weather::Weather,
+};
+
+/// When the world is moved back to its origin, read from `origin_rebase.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RebaseConfig {
+    pub enabled: bool,
+    /// How far the first player may get from the origin on either axis, in world units.
+    pub threshold: f32,
+    /// The world is moved by multiples of this, a power of two.
+    pub step: f32,
+}
+
+impl Default for RebaseConfig {
+    fn default() -> Self {
+        RebaseConfig {
+            enabled: false,
+            threshold: 4096.,
+            step: 1024.,
+        }
+    }
+}
+
+/// How far the world is moved back with the first player at `position`, or `None` while they
+/// are within the threshold.
+pub fn rebase_shift(position: Vector2<f32>, config: &RebaseConfig) -> Option<Vector2<f32>> {
+    let far = position.x.abs() > config.threshold || position.y.abs() > config.threshold;
+    if !config.enabled || config.step <= 0. || !far {
+        return None;
+    }
+    Some(position.map(|value| (value / config.step).round() * config.step))
+}
+
+/// Moves the world back to its origin if the first player has gone far enough from it.
+pub fn rebase_if_far(world: &mut World) {
+    let config = *world.read_resource::<RebaseConfig>();
+    let position = (
+        &world.read_storage::<Player>(),
+        &world.read_storage::<Transform>(),
+    )
+        .join()
+        .find(|(player, _)| player.index == 0)
+        .map(|(_, transform)| world_position(transform));
+    if let Some(shift) = position.and_then(|position| rebase_shift(position, &config)) {
+        info!(
+            "Moving the world back by ({}, {}) to keep it near the origin",
+            shift.x, shift.y
+        );
+        rebase(world, shift);
+    }
+}
+
+/// Moves everything in the world back by `shift`, as the module describes.
+pub fn rebase(world: &mut World, shift: Vector2<f32>) {
+    let offset = -shift;
+    // Attached parts are placed from their parents, so move with them.
+    for (transform, _) in (
+        &mut world.write_storage::<Transform>(),
+        !&world.read_storage::<Parent>(),
+    )
+        .join()
+    {
+        let position = world_position(transform) + offset;
+        transform.set_translation_x(position.x);
+        transform.set_translation_y(position.y);
+    }
+
+    let map = {
+        let mut map = world.write_resource::<TileMap>();
+        map.origin = (map.origin.0 + offset.x, map.origin.1 + offset.y);
+        map.clone()
+    };
+    world.add_resource(NavGrid::from_map(&map));
+    world.add_resource(SightGrid::from_map(&map));
+    world.write_resource::<LightGrid>().rebuild(&map);
+    let walls = TileColliders::from_map(&map, &world.read_resource::<TileCollisionConfig>());
+    world.add_resource(walls);
+    SpatialGridSystem.run_now(&world.res);
+
+    for bounds in (&mut world.write_storage::<CameraBounds>()).join() {
+        bounds.min += offset;
+        bounds.max += offset;
+    }
+    for follow in (&mut world.write_storage::<CameraFollow>()).join() {
+        follow.translate(offset);
+    }
+    for room_camera in (&mut world.write_storage::<RoomCamera>()).join() {
+        room_camera.translate(offset);
+    }
+    for room_bounds in (&mut world.write_storage::<RoomBounds>()).join() {
+        room_bounds.translate(offset);
+    }
+    for recentering in (&mut world.write_storage::<Recentering>()).join() {
+        recentering.translate(offset);
+    }
+    for path in (&mut world.write_storage::<NavPath>()).join() {
+        path.translate(offset);
+    }
+    for chaser in (&mut world.write_storage::<Chaser>()).join() {
+        chaser.translate(offset);
+    }
+    for footsteps in (&mut world.write_storage::<Footsteps>()).join() {
+        footsteps.translate(offset);
+    }
+    for text in (&mut world.write_storage::<CombatText>()).join() {
+        text.translate(offset);
+    }
+
+    for stop in &mut world.write_resource::<TourStops>().0 {
+        *stop += offset;
+    }
+    for shot in &mut world.write_resource::<IntroSweep>().shots {
+        shot.position = (shot.position.0 + offset.x, shot.position.1 + offset.y);
+    }
+    world.write_resource::<RewindBuffer>().translate(offset);
+    world.write_resource::<Weather>().translate(offset);
+    world.write_resource::<DeathBursts>().translate(offset);
+    world.write_resource::<SoundQueue>().translate(offset);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RebaseConfig {
+        RebaseConfig {
+            enabled: true,
+            threshold: 4096.,
+            step: 1024.,
+        }
+    }
+
+    #[test]
+    fn the_world_is_moved_by_the_nearest_step_once_far_out() {
+        assert_eq!(rebase_shift(Vector2::new(4000., -4000.), &config()), None);
+        assert_eq!(
+            rebase_shift(Vector2::new(5000., -300.), &config()),
+            Some(Vector2::new(5120., 0.))
+        );
+        let disabled = RebaseConfig {
+            enabled: false,
+            ..config()
+        };
+        assert_eq!(rebase_shift(Vector2::new(9000., 0.), &disabled), None);
+    }
+
+    #[test]
+    fn positions_around_the_player_keep_their_distances() {
+        let player = Vector2::new(5000.3, -6000.7);
+        let shift = rebase_shift(player, &config()).unwrap();
+        let offsets = [
+            Vector2::new(0.123, 37.5),
+            Vector2::new(-1999.9, 1.0e-3),
+            Vector2::new(2500.01, -2500.01),
+        ];
+        for offset in offsets.iter() {
+            let neighbour = player + offset;
+            let before = neighbour - player;
+            let after = (neighbour - shift) - (player - shift);
+            assert_eq!(before, after, "{:?}", offset);
+        }
+    }
+}
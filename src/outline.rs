@@ -0,0 +1,176 @@
+//! Outlines of the entities that matter, drawn over the overhead tiles hiding them.
+//!
+//! An entity with an `AlwaysVisibleOutline` standing under one of the map's overhead tiles has
+//! the box of its sprite outlined with debug lines on the `OutlineOverlay` entity, over the
+//! tiles, so it can still be found. Unflagged entities stay hidden, as do flagged ones out in
+//! the open, which are drawn as usual, and ones faded out by the lighting.
+
+use amethyst::{
+    assets::AssetStorage,
+    core::{
+        math::{Point3, Vector2},
+        transform::Transform,
+    },
+    ecs::prelude::{Component, Join, NullStorage, Read, ReadStorage, System, WriteStorage},
+    renderer::{
+        debug_drawing::DebugLinesComponent, palette::Srgba, resources::Tint, SpriteRender,
+        SpriteSheet,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{movement::world_position, navigation::NavGrid, tile_map::TileMap};
+
+/// Depth of the outlines, in front of the overhead tiles.
+const OUTLINE_DEPTH: f32 = 0.55;
+
+/// How outlines look, read from `outline.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OutlineConfig {
+    pub enabled: bool,
+    pub color: [f32; 4],
+    /// World units between a sprite and its outline.
+    pub padding: f32,
+}
+
+impl Default for OutlineConfig {
+    fn default() -> Self {
+        OutlineConfig {
+            enabled: true,
+            color: [1., 1., 1., 0.8],
+            padding: 1.,
+        }
+    }
+}
+
+/// Marks an entity outlined whenever an overhead tile hides it, like the players.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AlwaysVisibleOutline;
+
+impl Component for AlwaysVisibleOutline {
+    type Storage = NullStorage<Self>;
+}
+
+/// Marks the entity whose debug lines draw the outlines.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OutlineOverlay;
+
+impl Component for OutlineOverlay {
+    type Storage = NullStorage<Self>;
+}
+
+/// Whether an entity at `position` is under one of `map`'s overhead tiles.
+pub fn is_occluded(map: &TileMap, nav: &NavGrid, position: Vector2<f32>) -> bool {
+    nav.tile_at(position)
+        .is_some_and(|coord| map.is_overhead(coord))
+}
+
+/// Whether a flagged entity at `position`, tinted `tint`, is outlined: when it is hidden under
+/// an overhead tile without being faded out.
+pub fn is_outlined(
+    map: &TileMap,
+    nav: &NavGrid,
+    position: Vector2<f32>,
+    tint: Option<&Tint>,
+) -> bool {
+    !tint.is_some_and(|tint| tint.0.alpha <= 0.) && is_occluded(map, nav, position)
+}
+
+/// Redraws the outline of every flagged entity an overhead tile hides.
+pub struct OutlineSystem {
+    config: OutlineConfig,
+}
+
+impl OutlineSystem {
+    pub fn new(config: OutlineConfig) -> Self {
+        OutlineSystem { config }
+    }
+}
+
+impl<'s> System<'s> for OutlineSystem {
+    type SystemData = (
+        ReadStorage<'s, AlwaysVisibleOutline>,
+        ReadStorage<'s, SpriteRender>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Tint>,
+        ReadStorage<'s, OutlineOverlay>,
+        WriteStorage<'s, DebugLinesComponent>,
+        Read<'s, TileMap>,
+        Read<'s, NavGrid>,
+        Read<'s, AssetStorage<SpriteSheet>>,
+    );
+
+    fn run(
+        &mut self,
+        (flagged, sprites, transforms, tints, overlays, mut lines, map, nav, sheets): Self::SystemData,
+    ) {
+        let [r, g, b, a] = self.config.color;
+        let color = Srgba::new(r, g, b, a);
+        for (_, lines) in (&overlays, &mut lines).join() {
+            lines.clear();
+            if !self.config.enabled {
+                continue;
+            }
+            for (_, sprite, transform, tint) in
+                (&flagged, &sprites, &transforms, tints.maybe()).join()
+            {
+                let position = world_position(transform);
+                if !is_outlined(&map, &nav, position, tint) {
+                    continue;
+                }
+                let size = match sheets
+                    .get(&sprite.sprite_sheet)
+                    .and_then(|sheet| sheet.sprites.get(sprite.sprite_number))
+                {
+                    Some(sprite) => Vector2::new(sprite.width, sprite.height),
+                    None => continue,
+                };
+                let half = size / 2. + Vector2::repeat(self.config.padding);
+                let corners = [
+                    Vector2::new(-half.x, -half.y),
+                    Vector2::new(half.x, -half.y),
+                    Vector2::new(half.x, half.y),
+                    Vector2::new(-half.x, half.y),
+                ]
+                .map(|corner| {
+                    let corner = position + corner;
+                    Point3::new(corner.x, corner.y, OUTLINE_DEPTH)
+                });
+                for i in 0..4 {
+                    lines.add_line(corners[i], corners[(i + 1) % 4], color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile_map::Tile;
+
+    #[test]
+    fn only_entities_under_overhead_tiles_are_outlined() {
+        // A roof on the left and open ground on the right.
+        let map = TileMap {
+            width: 2,
+            height: 1,
+            tile_size: 32.,
+            tiles: vec![Tile::Static(7), Tile::Static(0)],
+            overhead: vec![Tile::Static(7)],
+            ..TileMap::default()
+        };
+        let nav = NavGrid::from_map(&map);
+        let under_roof = Vector2::new(16., 16.);
+
+        assert!(is_outlined(&map, &nav, under_roof, None));
+        assert!(!is_outlined(&map, &nav, Vector2::new(48., 16.), None));
+        assert!(!is_outlined(&map, &nav, Vector2::new(-16., 16.), None));
+
+        // Faded out by the lighting, it stays hidden even under the roof.
+        let tint = |alpha| Tint(Srgba::new(1., 1., 1., alpha));
+        assert!(!is_outlined(&map, &nav, under_roof, Some(&tint(0.))));
+        assert!(is_outlined(&map, &nav, under_roof, Some(&tint(0.5))));
+    }
+}
@@ -0,0 +1,364 @@
+//! Colour-blind palette filters, applied to the whole frame after everything else has drawn.
+//!
+//! The frame is drawn as usual and then redrawn through `DrawPaletteFilterDesc`, which
+//! multiplies every pixel's colour by the `PaletteFilter`'s matrix. The standard modes simulate
+//! each kind of dichromacy, so the game can be checked for colours that can't be told apart,
+//! and `PaletteMode::Custom` takes any matrix, such as one remapping the colours instead.
+//!
+//! The filter only adds a pass to the render graph while a mode other than
+//! `PaletteMode::Normal` is selected. Switching between the other modes just changes the matrix.
+
+use amethyst::{
+    core::math::Matrix3,
+    ecs::prelude::{Read, ReadExpect, Resources, System, Write},
+    input::{InputHandler, StringBindings},
+    renderer::{
+        rendy::{
+            command::{QueueId, RenderPassEncoder},
+            factory::Factory,
+            graph::{
+                render::{Layout, SetLayout, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc},
+                GraphContext, ImageAccess, NodeBuffer, NodeImage,
+            },
+            hal::{
+                self,
+                device::Device,
+                format::Swizzle,
+                image::{Filter, SamplerInfo, ViewKind, WrapMode},
+                pso::{
+                    BlendState, ColorBlendDesc, ColorMask, DepthStencilDesc, Descriptor,
+                    DescriptorSetLayoutBinding, DescriptorSetWrite, DescriptorType,
+                    ShaderStageFlags,
+                },
+            },
+            resource::{
+                DescriptorSet, DescriptorSetLayout, Escape, Handle, ImageView, ImageViewInfo,
+                Sampler,
+            },
+            shader::{ShaderSet, ShaderSetBuilder, SpirvShader},
+        },
+        types::Backend,
+    },
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{debug_overlay::DebugOverlay, strings::Strings};
+
+/// Simulates missing red cones, from Machado, Oliveira and Fernandes (2009) at full severity.
+pub const PROTANOPIA: [[f32; 3]; 3] = [
+    [0.152_286, 1.052_583, -0.204_868],
+    [0.114_503, 0.786_281, 0.099_216],
+    [-0.003_882, -0.048_116, 1.051_998],
+];
+
+/// Simulates missing green cones, from the same source as `PROTANOPIA`.
+pub const DEUTERANOPIA: [[f32; 3]; 3] = [
+    [0.367_322, 0.860_646, -0.227_968],
+    [0.280_085, 0.672_501, 0.047_413],
+    [-0.011_820, 0.042_940, 0.968_881],
+];
+
+/// Simulates missing blue cones, from the same source as `PROTANOPIA`.
+pub const TRITANOPIA: [[f32; 3]; 3] = [
+    [1.255_528, -0.076_749, -0.178_779],
+    [-0.078_411, 0.930_809, 0.147_602],
+    [0.004_733, 0.691_367, 0.303_900],
+];
+
+const IDENTITY: [[f32; 3]; 3] = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+
+/// Which matrix the frame's colours go through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PaletteMode {
+    /// Colours are left as they are, and the filter isn't drawn at all.
+    #[default]
+    Normal,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+    /// `PaletteFilter::custom`.
+    Custom,
+}
+
+impl PaletteMode {
+    /// The mode after this one when cycling through them.
+    pub fn next(self) -> Self {
+        match self {
+            PaletteMode::Normal => PaletteMode::Protanopia,
+            PaletteMode::Protanopia => PaletteMode::Deuteranopia,
+            PaletteMode::Deuteranopia => PaletteMode::Tritanopia,
+            PaletteMode::Tritanopia => PaletteMode::Custom,
+            PaletteMode::Custom => PaletteMode::Normal,
+        }
+    }
+}
+
+/// The selected palette filter, read from `palette.ron`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PaletteFilter {
+    pub mode: PaletteMode,
+    /// Matrix for `PaletteMode::Custom`, as rows, mapping a linear RGB colour to the one drawn.
+    pub custom: [[f32; 3]; 3],
+}
+
+impl Default for PaletteFilter {
+    fn default() -> Self {
+        PaletteFilter {
+            mode: PaletteMode::Normal,
+            custom: IDENTITY,
+        }
+    }
+}
+
+impl PaletteFilter {
+    /// Whether the filter pass has to be drawn.
+    pub fn enabled(&self) -> bool {
+        self.mode != PaletteMode::Normal
+    }
+
+    pub fn matrix(&self) -> Matrix3<f32> {
+        let rows = match self.mode {
+            PaletteMode::Normal => IDENTITY,
+            PaletteMode::Protanopia => PROTANOPIA,
+            PaletteMode::Deuteranopia => DEUTERANOPIA,
+            PaletteMode::Tritanopia => TRITANOPIA,
+            PaletteMode::Custom => self.custom,
+        };
+        Matrix3::from_fn(|row, column| rows[row][column])
+    }
+
+    /// The matrix laid out as the filter shader's push constants: three columns, each padded to
+    /// four floats.
+    fn push_constants(&self) -> [u32; 12] {
+        let matrix = self.matrix();
+        let mut constants = [0; 12];
+        for column in 0..3 {
+            for row in 0..3 {
+                constants[column * 4 + row] = matrix[(row, column)].to_bits();
+            }
+        }
+        constants
+    }
+}
+
+/// Cycles through the `PaletteMode`s on the `cycle_palette` action and shows the current one on
+/// the debug overlay.
+#[derive(Default)]
+pub struct PaletteFilterSystem {
+    was_pressed: bool,
+}
+
+impl<'s> System<'s> for PaletteFilterSystem {
+    type SystemData = (
+        Write<'s, PaletteFilter>,
+        Write<'s, DebugOverlay>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, Strings>,
+    );
+
+    fn run(&mut self, (mut filter, mut overlay, input, strings): Self::SystemData) {
+        let pressed = input.action_is_down("cycle_palette").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            filter.mode = filter.mode.next();
+            info!("Palette filter: {:?}", filter.mode);
+        }
+        self.was_pressed = pressed;
+        let mode = strings.get(&format!("palette.{:?}", filter.mode));
+        overlay.set("palette", strings.format("options.palette", &[&mode]));
+    }
+}
+
+fn shader(spirv: &[u8], stage: ShaderStageFlags) -> SpirvShader {
+    SpirvShader::new(spirv.to_vec(), stage, "main")
+}
+
+/// Draws the image it is given over the whole target, through the `PaletteFilter`'s matrix.
+///
+/// The image is sampled, so it must be a different one from the subpass's colour attachment.
+#[derive(Clone, Debug, Default)]
+pub struct DrawPaletteFilterDesc;
+
+impl<B: Backend> SimpleGraphicsPipelineDesc<B, Resources> for DrawPaletteFilterDesc {
+    type Pipeline = DrawPaletteFilter<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: hal::image::Access::SHADER_READ,
+            layout: hal::image::Layout::ShaderReadOnlyOptimal,
+            usage: hal::image::Usage::SAMPLED,
+            stages: hal::pso::PipelineStage::FRAGMENT_SHADER,
+        }]
+    }
+
+    fn colors(&self) -> Vec<ColorBlendDesc> {
+        vec![ColorBlendDesc(ColorMask::ALL, BlendState::Off)]
+    }
+
+    fn depth_stencil(&self) -> Option<DepthStencilDesc> {
+        None
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: vec![SetLayout {
+                bindings: vec![DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: DescriptorType::CombinedImageSampler,
+                    count: 1,
+                    stage_flags: ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                }],
+            }],
+            // A column-major 3x3 matrix with each column padded to four floats.
+            push_constants: vec![(ShaderStageFlags::FRAGMENT, 0..12)],
+        }
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _res: &Resources) -> ShaderSet<B> {
+        ShaderSetBuilder::default()
+            .with_vertex(&shader(
+                include_bytes!("../shaders/compiled/palette_filter.vert.spv"),
+                ShaderStageFlags::VERTEX,
+            ))
+            .expect("Palette filter vertex shader must load")
+            .with_fragment(&shader(
+                include_bytes!("../shaders/compiled/palette_filter.frag.spv"),
+                ShaderStageFlags::FRAGMENT,
+            ))
+            .expect("Palette filter fragment shader must load")
+            .build(factory, Default::default())
+            .expect("Palette filter shaders must build")
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        _res: &Resources,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<DrawPaletteFilter<B>, failure::Error> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 1);
+
+        let source = &images[0];
+        let image = ctx.get_image(source.id).expect("Image does not exist");
+        let view = factory.create_image_view(
+            image.clone(),
+            ImageViewInfo {
+                view_kind: ViewKind::D2,
+                format: image.format(),
+                swizzle: Swizzle::NO,
+                range: source.range.clone(),
+            },
+        )?;
+        let sampler = factory.create_sampler(SamplerInfo::new(Filter::Nearest, WrapMode::Clamp))?;
+        let set = factory.create_descriptor_set(set_layouts[0].clone())?;
+        unsafe {
+            factory
+                .device()
+                .write_descriptor_sets(Some(DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(Descriptor::CombinedImageSampler(
+                        view.raw(),
+                        source.layout,
+                        sampler.raw(),
+                    )),
+                }));
+        }
+
+        Ok(DrawPaletteFilter {
+            set,
+            _view: view,
+            _sampler: sampler,
+        })
+    }
+}
+
+fn current_filter(res: &Resources) -> PaletteFilter {
+    res.try_fetch::<PaletteFilter>()
+        .map(|filter| *filter)
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub struct DrawPaletteFilter<B: Backend> {
+    set: Escape<DescriptorSet<B>>,
+    /// Kept alive for as long as `set` refers to them.
+    _view: Escape<ImageView<B>>,
+    _sampler: Escape<Sampler<B>>,
+}
+
+impl<B: Backend> SimpleGraphicsPipeline<B, Resources> for DrawPaletteFilter<B> {
+    type Desc = DrawPaletteFilterDesc;
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        res: &Resources,
+    ) {
+        let constants = current_filter(res).push_constants();
+        unsafe {
+            encoder.bind_graphics_descriptor_sets(layout, 0, Some(self.set.raw()), None);
+            encoder.push_constants(layout, ShaderStageFlags::FRAGMENT, 0, &constants);
+            // One triangle covering the whole target, made up by the vertex shader.
+            encoder.draw(0..3, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _res: &Resources) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::core::math::Vector3;
+
+    use super::*;
+
+    fn filter(mode: PaletteMode) -> PaletteFilter {
+        PaletteFilter {
+            mode,
+            ..PaletteFilter::default()
+        }
+    }
+
+    #[test]
+    fn normal_leaves_colours_alone_and_draws_no_pass() {
+        let normal = filter(PaletteMode::Normal);
+        assert_eq!(normal.matrix(), Matrix3::identity());
+        assert!(!normal.enabled());
+        let color = Vector3::new(0.25, 0.5, 0.75);
+        assert_eq!(normal.matrix() * color, color);
+    }
+
+    #[test]
+    fn protanopia_turns_red_into_a_dark_olive() {
+        let protanopia = filter(PaletteMode::Protanopia);
+        assert!(protanopia.enabled());
+        let red = protanopia.matrix() * Vector3::new(1., 0., 0.);
+        assert_eq!(red, Vector3::new(0.152_286, 0.114_503, -0.003_882));
+
+        // Red and green, far apart to most players, are close together without red cones.
+        let green = protanopia.matrix() * Vector3::new(0., 1., 0.);
+        assert!((red.x / red.y - green.x / green.y).abs() < 0.1);
+    }
+
+    #[test]
+    fn push_constants_are_padded_columns() {
+        let constants = filter(PaletteMode::Protanopia).push_constants();
+        let value = |index: usize| f32::from_bits(constants[index]);
+        assert_eq!(value(0), PROTANOPIA[0][0]);
+        assert_eq!(value(1), PROTANOPIA[1][0]);
+        assert_eq!(value(4), PROTANOPIA[0][1]);
+        assert_eq!(value(10), PROTANOPIA[2][2]);
+        assert_eq!(constants[3], 0);
+    }
+}
@@ -0,0 +1,359 @@
+//! Layered backgrounds behind the world, scrolling past slower than it for depth.
+//!
+//! `parallax.ron` lists the layers, furthest first. Each is one sprite, drawn at the depth it
+//! gives and moved with the camera by its `factor` on each axis: a layer with a factor of `1`
+//! scrolls past like the tiles, one of `0.5` at half their speed, and one of `0` stays put on
+//! screen. A layer can also drift on its own, in game time, and repeat its sprite side by side
+//! across the view horizontally, vertically or both. Repeated sprites sit exactly their own
+//! size apart, so an image whose edges meet tiles without a seam.
+//!
+//! The sprites are ordinary entities, placed for the camera the `ParallaxSystem` finds each
+//! frame: the active camera, or with split-screen on the first player's view. Both views draw
+//! the same sprites, so the layers scroll with the first view in the second as well. Repeated
+//! layers are repeated across both views, so they cover the second view wherever it is, but a
+//! layer that isn't repeated is only where the first view puts it, and can be out of the
+//! second's sight.
+
+use std::{collections::BTreeSet, ops::RangeInclusive};
+
+use amethyst::{
+    assets::{AssetStorage, Handle},
+    core::{math::Vector2, transform::Transform, Hidden},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Entity, Join, Read, ReadExpect, ReadStorage, System,
+        WriteStorage,
+    },
+    renderer::{camera::ActiveCamera, SpriteRender, SpriteSheet, Transparent},
+    window::ScreenDimensions,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{view_half_extents, viewport_size, CameraZoom},
+    clock::GameClock,
+    loading::SpriteSheetAsset,
+    movement::world_position,
+    split_screen::{SplitScreen, SplitView},
+    tile_map::TileMap,
+};
+
+/// One background layer, as given in `parallax.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ParallaxLayer {
+    pub sheet: SpriteSheetAsset,
+    /// The sprite of `sheet` the layer is drawn with.
+    #[serde(default)]
+    pub sprite: usize,
+    /// How much of the camera's movement the layer scrolls by, on each axis.
+    pub factor: (f32, f32),
+    /// World units per second the layer drifts by on its own, on each axis.
+    #[serde(default)]
+    pub scroll: (f32, f32),
+    /// Where the centre of the layer's sprite is with the camera at the map's bottom-left
+    /// corner, measured from there.
+    #[serde(default)]
+    pub position: (f32, f32),
+    /// Whether the sprite is repeated side by side across the view, on each axis.
+    #[serde(default)]
+    pub repeat: (bool, bool),
+    /// How far back the layer is drawn. Anything behind the tiles at `-1` and in front of `-19`,
+    /// which is as far as the camera sees.
+    pub depth: f32,
+}
+
+impl ParallaxLayer {
+    /// Where the centre of the layer's sprite, or of one of its repeats, is drawn for a camera
+    /// at `camera`, `elapsed` seconds of game time in.
+    pub fn origin(&self, camera: Vector2<f32>, elapsed: f64) -> Vector2<f32> {
+        let factor = Vector2::new(self.factor.0, self.factor.1);
+        let scroll = Vector2::new(self.scroll.0, self.scroll.1);
+        Vector2::new(self.position.0, self.position.1)
+            + camera.component_mul(&factor.map(|factor| 1. - factor))
+            + scroll * elapsed as f32
+    }
+
+    /// Which repeats of the sprite, `size` big with one centred on `origin`, are needed to cover
+    /// each of `views`, given as their centres and half extents. Counted in columns and rows
+    /// from that one, which is all that is needed on an axis the layer isn't repeated on.
+    pub fn repeats(
+        &self,
+        origin: Vector2<f32>,
+        size: Vector2<f32>,
+        views: &[(Vector2<f32>, Vector2<f32>)],
+    ) -> BTreeSet<(i32, i32)> {
+        let mut repeats = BTreeSet::new();
+        for (center, half) in views {
+            let (min, max) = (center - half, center + half);
+            let columns = if self.repeat.0 {
+                covering_tiles(origin.x, size.x, min.x, max.x)
+            } else {
+                0..=0
+            };
+            let rows = if self.repeat.1 {
+                covering_tiles(origin.y, size.y, min.y, max.y)
+            } else {
+                0..=0
+            };
+            for column in columns {
+                repeats.extend(rows.clone().map(|row| (column, row)));
+            }
+        }
+        repeats
+    }
+}
+
+/// The background layers read from `parallax.ron`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ParallaxConfig {
+    pub layers: Vec<ParallaxLayer>,
+}
+
+/// The layers of the level being played, each with the sprite sheet it was loaded with.
+#[derive(Clone, Default)]
+pub struct ParallaxBackground {
+    pub layers: Vec<(ParallaxLayer, Handle<SpriteSheet>)>,
+}
+
+/// Which repeats of a sprite `size` wide with one centred on `origin` are needed to cover from
+/// `min` to `max`, counted from that one, along one axis.
+pub fn covering_tiles(origin: f32, size: f32, min: f32, max: f32) -> RangeInclusive<i32> {
+    if size <= 0. {
+        return 0..=0;
+    }
+    let first = ((min - origin) / size + 0.5).floor() as i32;
+    let last = ((max - origin) / size - 0.5).ceil() as i32;
+    first..=last.max(first)
+}
+
+/// One sprite of a background layer.
+#[derive(Clone, Copy, Debug)]
+pub struct ParallaxTile {
+    pub layer: usize,
+}
+
+impl Component for ParallaxTile {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Places the background layers' sprites for the camera, as the module describes, making more
+/// as the view needs them and hiding those it doesn't. Runs after the cameras are done moving.
+pub struct ParallaxSystem;
+
+impl<'s> System<'s> for ParallaxSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, ParallaxTile>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Transparent>,
+        WriteStorage<'s, Hidden>,
+        ReadStorage<'s, CameraZoom>,
+        ReadStorage<'s, SplitView>,
+        Read<'s, ActiveCamera>,
+        Read<'s, SplitScreen>,
+        Read<'s, ParallaxBackground>,
+        Read<'s, AssetStorage<SpriteSheet>>,
+        Read<'s, GameClock>,
+        Read<'s, TileMap>,
+        ReadExpect<'s, ScreenDimensions>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut tiles,
+            mut sprites,
+            mut transforms,
+            mut transparent,
+            mut hidden,
+            zooms,
+            split_views,
+            active,
+            split_screen,
+            background,
+            sheets,
+            clock,
+            map,
+            dimensions,
+        ): Self::SystemData,
+    ) {
+        // No camera is active outside of a game, where nothing of the background is left. The
+        // first of the cameras is the one the layers are placed for.
+        let mut cameras: Vec<(usize, Entity)> = match (split_screen.enabled, active.entity) {
+            (true, _) => (&entities, &split_views)
+                .join()
+                .map(|(camera, view)| (view.0, camera))
+                .collect(),
+            (false, camera) => camera.into_iter().map(|camera| (0, camera)).collect(),
+        };
+        cameras.sort();
+        let views: Vec<(Vector2<f32>, Vector2<f32>)> = cameras
+            .into_iter()
+            .filter_map(|(_, camera)| {
+                let transform = transforms.get(camera)?;
+                let zoom = zooms.get(camera)?;
+                let screen = Vector2::new(dimensions.width(), dimensions.height());
+                let half = view_half_extents(
+                    viewport_size(split_views.get(camera), &split_screen, screen),
+                    zoom.level(),
+                );
+                Some((world_position(transform), half))
+            })
+            .collect();
+        let view = views.first().map(|&(center, _)| center);
+
+        let mut spare: Vec<Vec<Entity>> = vec![Vec::new(); background.layers.len()];
+        let mut unused = Vec::new();
+        for (entity, tile) in (&entities, &tiles).join() {
+            match spare.get_mut(tile.layer) {
+                Some(layer) if view.is_some() => layer.push(entity),
+                _ => unused.push(entity),
+            }
+        }
+
+        if let Some(center) = view {
+            let elapsed = clock.elapsed_seconds();
+            for (index, (layer, sheet)) in background.layers.iter().enumerate() {
+                let size = match sheets
+                    .get(sheet)
+                    .and_then(|loaded| loaded.sprites.get(layer.sprite))
+                {
+                    Some(sprite) => Vector2::new(sprite.width, sprite.height),
+                    None => continue,
+                };
+                // Layers are placed from the map, so they hold still as the world is moved back
+                // to its origin.
+                let origin = layer.origin(center - map.origin(), elapsed) + map.origin();
+                for (column, row) in layer.repeats(origin, size, &views) {
+                    let entity = match spare[index].pop() {
+                        Some(entity) => entity,
+                        None => {
+                            let entity = entities.create();
+                            let created = tiles
+                                .insert(entity, ParallaxTile { layer: index })
+                                .and_then(|_| {
+                                    sprites.insert(
+                                        entity,
+                                        SpriteRender {
+                                            sprite_sheet: sheet.clone(),
+                                            sprite_number: layer.sprite,
+                                        },
+                                    )
+                                })
+                                .and_then(|_| transforms.insert(entity, Transform::default()))
+                                .and_then(|_| transparent.insert(entity, Transparent));
+                            if let Err(err) = created {
+                                error!("Failed to create a background sprite: {}", err);
+                                continue;
+                            }
+                            entity
+                        }
+                    };
+                    let position =
+                        origin + Vector2::new(column as f32 * size.x, row as f32 * size.y);
+                    if let Some(transform) = transforms.get_mut(entity) {
+                        transform.set_translation_xyz(position.x, position.y, layer.depth);
+                    }
+                    hidden.remove(entity);
+                }
+            }
+        }
+
+        // Sprites this frame's view doesn't need are kept, out of sight, for a later one. With no
+        // view at all, or no layer left for them, they are deleted.
+        for entity in spare.into_iter().flatten() {
+            if !hidden.contains(entity) {
+                hidden
+                    .insert(entity, Hidden)
+                    .expect("Background sprite is alive");
+            }
+        }
+        for entity in unused {
+            if let Err(err) = entities.delete(entity) {
+                error!("Failed to delete a background sprite: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(factor: (f32, f32), repeat: (bool, bool)) -> ParallaxLayer {
+        ParallaxLayer {
+            sheet: SpriteSheetAsset {
+                texture: String::new(),
+                sprites: String::new(),
+            },
+            sprite: 0,
+            factor,
+            scroll: (0., 0.),
+            position: (10., 20.),
+            repeat,
+            depth: -10.,
+        }
+    }
+
+    #[test]
+    fn layers_scroll_by_their_factor() {
+        let camera = Vector2::new(100., 40.);
+        // On screen, a layer moves by the camera's movement times its factor.
+        let on_screen = |layer: &ParallaxLayer| layer.origin(camera, 0.) - camera;
+        let start = Vector2::new(10., 20.);
+        assert_eq!(on_screen(&layer((1., 1.), (false, false))), start - camera);
+        assert_eq!(
+            on_screen(&layer((0.5, 0.), (false, false))),
+            start - Vector2::new(50., 0.)
+        );
+        assert_eq!(on_screen(&layer((0., 0.), (false, false))), start);
+
+        let drifting = ParallaxLayer {
+            scroll: (-4., 2.),
+            ..layer((1., 1.), (false, false))
+        };
+        assert_eq!(
+            drifting.origin(Vector2::zeros(), 2.5),
+            Vector2::new(0., 25.)
+        );
+    }
+
+    #[test]
+    fn repeats_tile_across_every_view() {
+        let size = Vector2::new(64., 32.);
+        let origin = Vector2::zeros();
+        let view = (Vector2::new(100., 0.), Vector2::new(80., 40.));
+        // The view runs from 20 to 180 across, so sprites centred 0 to 192 cover it, and -40 to
+        // 40 up, needing the rows at -32 to 32.
+        assert_eq!(covering_tiles(origin.x, size.x, 20., 180.), 0..=3);
+        assert_eq!(covering_tiles(origin.y, size.y, -40., 40.), -1..=1);
+        let across = layer((1., 1.), (true, false)).repeats(origin, size, &[view]);
+        assert_eq!(
+            across.into_iter().collect::<Vec<_>>(),
+            [(0, 0), (1, 0), (2, 0), (3, 0)]
+        );
+        let both = layer((1., 1.), (true, true)).repeats(origin, size, &[view]);
+        assert_eq!(both.len(), 12);
+        assert!(both.contains(&(3, -1)) && both.contains(&(0, 1)));
+        assert_eq!(
+            layer((1., 1.), (false, false))
+                .repeats(origin, size, &[view])
+                .len(),
+            1
+        );
+
+        // A second view far off is covered too, without repeating the sprites both need.
+        let second = (Vector2::new(300., 0.), Vector2::new(80., 4.));
+        let split = layer((1., 1.), (true, false)).repeats(origin, size, &[view, second]);
+        assert_eq!(
+            split
+                .into_iter()
+                .map(|(column, _)| column)
+                .collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4, 5, 6]
+        );
+    }
+}
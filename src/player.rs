@@ -0,0 +1,210 @@
+use amethyst::{
+    core::math::Vector2,
+    ecs::prelude::{Component, DenseVecStorage, Join, Read, ReadStorage, System, WriteStorage},
+    input::{InputHandler, StringBindings},
+};
+
+use crate::{
+    analog::AnalogResponse,
+    god_mode::GodMode,
+    movement::{Facing, Velocity},
+};
+
+/// Marks an entity controlled by one of the local players.
+#[derive(Clone, Copy, Debug)]
+pub struct Player {
+    /// Which local player this is, starting from `0`. Selects the input bindings used.
+    pub index: usize,
+    /// Walking speed in world units per second.
+    pub speed: f32,
+}
+
+impl Component for Player {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The binding name of `base` for the player at `index`: the first player uses `base` as is,
+/// later players use `base_p2`, `base_p3` and so on.
+pub fn binding_name(base: &str, index: usize) -> String {
+    if index == 0 {
+        base.to_string()
+    } else {
+        format!("{}_p{}", base, index + 1)
+    }
+}
+
+/// The movement direction requested by the player at `index`, no longer than one, after the
+/// `response` of the stick.
+///
+/// Missing bindings and disconnected controllers both read as no input, so a player whose
+/// controller drops out simply stands still until it comes back.
+pub fn input_direction(
+    input: &InputHandler<StringBindings>,
+    response: &AnalogResponse,
+    index: usize,
+) -> Vector2<f32> {
+    let axis = |base| {
+        input
+            .axis_value(binding_name(base, index).as_str())
+            .unwrap_or(0.) as f32
+    };
+    // Also keeps diagonal movement from being faster than moving along one axis.
+    response.apply(Vector2::new(axis("horizontal"), axis("vertical")))
+}
+
+/// Whether the player at `index` is holding the action `base`.
+pub fn action_is_down(input: &InputHandler<StringBindings>, base: &str, index: usize) -> bool {
+    input
+        .action_is_down(binding_name(base, index).as_str())
+        .unwrap_or(false)
+}
+
+/// Turns each player's `horizontal` and `vertical` input axes into their `Velocity`, sped up
+/// while `GodMode` has no-clip on.
+pub struct PlayerMovementSystem;
+
+impl<'s> System<'s> for PlayerMovementSystem {
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        WriteStorage<'s, Velocity>,
+        WriteStorage<'s, Facing>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, AnalogResponse>,
+        Read<'s, GodMode>,
+    );
+
+    fn run(
+        &mut self,
+        (players, mut velocities, mut facings, input, response, god_mode): Self::SystemData,
+    ) {
+        let boost = if god_mode.no_clip {
+            god_mode.no_clip_speed
+        } else {
+            1.
+        };
+        for (player, velocity, facing) in (&players, &mut velocities, (&mut facings).maybe()).join()
+        {
+            let direction = input_direction(&input, &response, player.index);
+            velocity.0 = direction * player.speed * boost;
+            if let Some(facing) = facing {
+                if direction != Vector2::zeros() {
+                    facing.0 = direction.normalize();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use amethyst::{
+        ecs::prelude::{Builder, Entity, RunNow, World},
+        input::{InputEvent, VirtualKeyCode},
+        shrev::EventChannel,
+        winit::{
+            DeviceId, ElementState, Event, KeyboardInput, ModifiersState, WindowEvent, WindowId,
+        },
+    };
+
+    use super::*;
+
+    /// Replaces the input bindings of `world` with `bindings`, written as in `bindings.ron`.
+    pub fn bind(world: &mut World, bindings: &str) {
+        world
+            .res
+            .entry::<InputHandler<StringBindings>>()
+            .or_insert_with(InputHandler::new)
+            .bindings = ron::de::from_str(bindings).unwrap();
+    }
+
+    /// Presses or lets go of `key` in `world`, as the window would.
+    pub fn press_key(world: &mut World, key: VirtualKeyCode, pressed: bool) {
+        let state = if pressed {
+            ElementState::Pressed
+        } else {
+            ElementState::Released
+        };
+        // Neither id is ever looked at, so the test's key presses come from no window at all.
+        let event = Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event: WindowEvent::KeyboardInput {
+                device_id: unsafe { DeviceId::dummy() },
+                input: KeyboardInput {
+                    scancode: 0,
+                    state,
+                    virtual_keycode: Some(key),
+                    modifiers: ModifiersState::default(),
+                },
+            },
+        };
+        world
+            .res
+            .entry::<EventChannel<InputEvent<String>>>()
+            .or_insert_with(EventChannel::new);
+        world
+            .res
+            .entry::<InputHandler<StringBindings>>()
+            .or_insert_with(InputHandler::new);
+        world
+            .write_resource::<InputHandler<StringBindings>>()
+            .send_event(&event, &mut world.write_resource(), 1.);
+    }
+
+    #[test]
+    fn each_player_moves_only_by_their_own_bindings() {
+        let mut world = World::new();
+        let mut system = PlayerMovementSystem;
+        System::setup(&mut system, &mut world.res);
+        bind(
+            &mut world,
+            r#"(
+                axes: {
+                    "horizontal": Emulated(pos: Key(D), neg: Key(A)),
+                    "vertical": Emulated(pos: Key(W), neg: Key(S)),
+                    "horizontal_p2": Emulated(pos: Key(Right), neg: Key(Left)),
+                    "vertical_p2": Emulated(pos: Key(Up), neg: Key(Down)),
+                },
+                actions: {},
+            )"#,
+        );
+        let players: Vec<Entity> = (0..2)
+            .map(|index| {
+                world
+                    .create_entity()
+                    .with(Player { index, speed: 60. })
+                    .with(Velocity::default())
+                    .build()
+            })
+            .collect();
+        let mut velocities = |world: &mut World| {
+            system.run_now(&world.res);
+            let velocities = world.read_storage::<Velocity>();
+            let velocities: Vec<_> = players
+                .iter()
+                .map(|&player| velocities.get(player).unwrap().0)
+                .collect();
+            velocities
+        };
+
+        press_key(&mut world, VirtualKeyCode::D, true);
+        assert_eq!(
+            velocities(&mut world),
+            [Vector2::new(60., 0.), Vector2::zeros()]
+        );
+        press_key(&mut world, VirtualKeyCode::D, false);
+        press_key(&mut world, VirtualKeyCode::Up, true);
+        assert_eq!(
+            velocities(&mut world),
+            [Vector2::zeros(), Vector2::new(0., 60.)]
+        );
+        press_key(&mut world, VirtualKeyCode::S, true);
+        press_key(&mut world, VirtualKeyCode::Left, true);
+        assert_eq!(
+            velocities(&mut world),
+            [
+                Vector2::new(0., -60.),
+                Vector2::new(-1., 1.).normalize() * 60.
+            ]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! A breakdown of how long the major systems take each frame, on the debug overlay.
+//!
+//! Systems registered wrapped in `Profiled` time each run of theirs while profiling is on, and
+//! the `ProfilerSystem` gathers the times every frame, averaging them over the last `window`
+//! frames and listing them slowest first. Gameplay systems stepping more than once in a frame
+//! count every step towards it. Rendering is profiled too, though it runs after the
+//! `ProfilerSystem` and so is counted the frame after it draws. Profiling is toggled with the
+//! `toggle_profiler` action, which also shows the overlay, and starts as `profiler.ron` says.
+//! While it is off, a profiled system only checks that it is before running as usual.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use amethyst::{
+    ecs::prelude::{Read, Resources, System, SystemData, Write},
+    input::{InputHandler, StringBindings},
+    shred::{RunNow, RunningTime},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::debug_overlay::DebugOverlay;
+
+/// How the systems are profiled, read from `profiler.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProfilerConfig {
+    /// Whether profiling is on from the start.
+    pub enabled: bool,
+    /// How many frames the times are averaged over.
+    pub window: usize,
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        ProfilerConfig {
+            enabled: false,
+            window: 60,
+        }
+    }
+}
+
+/// Whether profiling is on, and the milliseconds each profiled system has taken so far this
+/// frame. Only ever read, so the systems timing themselves still run side by side.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    enabled: AtomicBool,
+    frame: Mutex<BTreeMap<&'static str, f32>>,
+}
+
+impl Profiler {
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Adds `milliseconds` to the time `name` has taken this frame.
+    pub fn record(&self, name: &'static str, milliseconds: f32) {
+        let mut frame = self.frame.lock().expect("Profiler lock is poisoned");
+        *frame.entry(name).or_insert(0.) += milliseconds;
+    }
+
+    /// The times recorded since the last call, leaving none.
+    pub fn take_frame(&self) -> BTreeMap<&'static str, f32> {
+        std::mem::take(&mut *self.frame.lock().expect("Profiler lock is poisoned"))
+    }
+}
+
+/// Runs `inner`, timing it as `name` while profiling is on.
+pub struct Profiled<S> {
+    name: &'static str,
+    inner: S,
+}
+
+impl<S> Profiled<S> {
+    pub fn new(name: &'static str, inner: S) -> Self {
+        Profiled { name, inner }
+    }
+}
+
+impl<'s, S> System<'s> for Profiled<S>
+where
+    S: System<'s>,
+    S::SystemData: SystemData<'s>,
+{
+    type SystemData = (S::SystemData, Read<'s, Profiler>);
+
+    fn run(&mut self, (data, profiler): Self::SystemData) {
+        if !profiler.enabled() {
+            self.inner.run(data);
+            return;
+        }
+        let started = Instant::now();
+        self.inner.run(data);
+        profiler.record(self.name, started.elapsed().as_secs_f32() * 1000.);
+    }
+
+    fn running_time(&self) -> RunningTime {
+        self.inner.running_time()
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Read::<Profiler>::setup(res);
+        self.inner.setup(res);
+    }
+
+    fn dispose(self, res: &mut Resources) {
+        self.inner.dispose(res);
+    }
+}
+
+/// Runs the thread-local `inner`, such as the renderer, timing it as `name` while profiling is
+/// on, as `Profiled` does for the systems dispatched alongside each other.
+pub struct ProfiledLocal<S> {
+    name: &'static str,
+    inner: S,
+}
+
+impl<S> ProfiledLocal<S> {
+    pub fn new(name: &'static str, inner: S) -> Self {
+        ProfiledLocal { name, inner }
+    }
+}
+
+impl<'a, S: RunNow<'a>> RunNow<'a> for ProfiledLocal<S> {
+    fn run_now(&mut self, res: &'a Resources) {
+        if !res.fetch::<Profiler>().enabled() {
+            self.inner.run_now(res);
+            return;
+        }
+        let started = Instant::now();
+        self.inner.run_now(res);
+        res.fetch::<Profiler>()
+            .record(self.name, started.elapsed().as_secs_f32() * 1000.);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Read::<Profiler>::setup(res);
+        self.inner.setup(res);
+    }
+
+    fn dispose(self: Box<Self>, res: &mut Resources) {
+        Box::new(self.inner).dispose(res);
+    }
+}
+
+/// The times of the last few frames, averaged for the breakdown.
+#[derive(Clone, Debug)]
+pub struct SystemTimings {
+    window: usize,
+    frames: VecDeque<BTreeMap<&'static str, f32>>,
+}
+
+impl SystemTimings {
+    pub fn new(window: usize) -> Self {
+        SystemTimings {
+            window: window.max(1),
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Adds a frame's times, forgetting the oldest frame past the window.
+    pub fn push_frame(&mut self, frame: BTreeMap<&'static str, f32>) {
+        self.frames.push_back(frame);
+        while self.frames.len() > self.window {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Every system's average milliseconds per frame over the window, slowest first. A system
+    /// missing from some frames counts as having taken no time in them.
+    pub fn breakdown(&self) -> Vec<(&'static str, f32)> {
+        let mut totals = BTreeMap::new();
+        for frame in &self.frames {
+            for (&name, &milliseconds) in frame {
+                *totals.entry(name).or_insert(0.) += milliseconds;
+            }
+        }
+        let frames = self.frames.len().max(1) as f32;
+        let mut breakdown: Vec<_> = totals
+            .into_iter()
+            .map(|(name, total)| (name, total / frames))
+            .collect();
+        breakdown.sort_by(|a, b| b.1.total_cmp(&a.1));
+        breakdown
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+/// Toggles profiling on the `toggle_profiler` action and puts the breakdown on the overlay
+/// while it is on.
+pub struct ProfilerSystem {
+    config: ProfilerConfig,
+    timings: SystemTimings,
+    was_pressed: bool,
+}
+
+impl ProfilerSystem {
+    pub fn new(config: ProfilerConfig) -> Self {
+        ProfilerSystem {
+            config,
+            timings: SystemTimings::new(config.window),
+            was_pressed: false,
+        }
+    }
+}
+
+impl<'s> System<'s> for ProfilerSystem {
+    type SystemData = (
+        Read<'s, Profiler>,
+        Write<'s, DebugOverlay>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(&mut self, (profiler, mut overlay, input): Self::SystemData) {
+        let pressed = input.action_is_down("toggle_profiler").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            profiler.set_enabled(!profiler.enabled());
+            if profiler.enabled() {
+                overlay.visible = true;
+            }
+        }
+        self.was_pressed = pressed;
+
+        // Some profiled systems run after this one in the frame, so their times are taken the
+        // next, though each frame still holds one of every run.
+        let frame = profiler.take_frame();
+        if !profiler.enabled() {
+            self.timings.clear();
+            overlay.remove("profiler");
+            return;
+        }
+        self.timings.push_frame(frame);
+
+        let mut text = format!(
+            "Systems (ms per frame, last {} frames):",
+            self.config.window
+        );
+        for (name, milliseconds) in self.timings.breakdown() {
+            text.push_str(&format!("\n  {:<28}{:.3}", name, milliseconds));
+        }
+        overlay.set("profiler", text);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        res.fetch::<Profiler>().set_enabled(self.config.enabled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(times: &[(&'static str, f32)]) -> BTreeMap<&'static str, f32> {
+        times.iter().copied().collect()
+    }
+
+    #[test]
+    fn breakdown_averages_over_the_window_slowest_first() {
+        let mut timings = SystemTimings::new(2);
+        timings.push_frame(frame(&[("movement", 9.), ("chase", 1.)]));
+        timings.push_frame(frame(&[("movement", 1.), ("chase", 2.)]));
+        timings.push_frame(frame(&[("movement", 3.)]));
+        // The first frame has fallen out of the window, and chase is missing from the last.
+        assert_eq!(timings.breakdown(), vec![("movement", 2.), ("chase", 1.)]);
+    }
+
+    #[test]
+    fn a_system_running_twice_in_a_frame_counts_both_runs() {
+        let profiler = Profiler::default();
+        profiler.record("movement", 1.5);
+        profiler.record("movement", 2.);
+        assert_eq!(profiler.take_frame(), frame(&[("movement", 3.5)]));
+        assert!(profiler.take_frame().is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+//! Trading looks for framerate on weaker hardware.
+//!
+//! `quality.ron` lists quality tiers from best to cheapest. The `AdaptiveQualitySystem` keeps
+//! the last `window` frame times, and once their median runs well over the frame budget it
+//! steps down a tier, or once there is plenty of headroom again steps back up. Going by the
+//! median, a hitch of a frame or two, such as a level loading, is never enough to step. The gap
+//! between the two thresholds and the `hold` after every step keep it from flipping back and
+//! forth.
+//! The current tier is the `QualityTier` resource, which the particle effects and shadows
+//! follow, and is shown on the debug overlay. Each tier also sets the `RenderScale` the scene
+//! is drawn at.
+
+use std::collections::VecDeque;
+
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{Read, System, Write},
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{debug_overlay::DebugOverlay, render_scale::RenderScale};
+
+/// What one quality tier draws.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QualityTier {
+    pub name: String,
+    /// The most weather particles, and the most death burst sparks, alive at once.
+    pub max_particles: usize,
+    pub shadows: bool,
+    /// The `RenderScale` the scene is drawn at.
+    pub render_scale: f32,
+}
+
+impl Default for QualityTier {
+    fn default() -> Self {
+        QualityTier {
+            name: "High".to_string(),
+            max_particles: 4000,
+            shadows: true,
+            render_scale: 1.,
+        }
+    }
+}
+
+/// When quality is stepped down and back up, read from `quality.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QualityConfig {
+    /// Whether quality adapts at all. Without it, the first tier is kept.
+    pub enabled: bool,
+    /// Milliseconds a frame may take.
+    pub budget_ms: f32,
+    /// How many frames the median is taken over.
+    pub window: usize,
+    /// Steps down when the median is over the budget times this.
+    pub step_down_at: f32,
+    /// Steps up when the median is under the budget times this.
+    pub step_up_at: f32,
+    /// Seconds after a step before quality may step again.
+    pub hold: f32,
+    /// The tiers, from best to cheapest.
+    pub tiers: Vec<QualityTier>,
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        QualityConfig {
+            enabled: true,
+            budget_ms: 16.7,
+            window: 60,
+            step_down_at: 1.15,
+            step_up_at: 0.7,
+            hold: 3.,
+            tiers: vec![
+                QualityTier::default(),
+                QualityTier {
+                    name: "Medium".to_string(),
+                    max_particles: 1500,
+                    shadows: true,
+                    render_scale: 0.85,
+                },
+                QualityTier {
+                    name: "Low".to_string(),
+                    max_particles: 400,
+                    shadows: false,
+                    render_scale: 0.7,
+                },
+            ],
+        }
+    }
+}
+
+/// Which tier the frame times call for.
+#[derive(Clone, Debug, Default)]
+pub struct QualityController {
+    tier: usize,
+    /// The last frame times, in milliseconds.
+    frame_times: VecDeque<f32>,
+    /// Seconds since the last step.
+    held: f32,
+}
+
+impl QualityController {
+    /// The index of the current tier in the config's list, `0` being the best.
+    pub fn tier(&self) -> usize {
+        self.tier
+    }
+
+    /// The median of the last frame times, in milliseconds.
+    pub fn median_ms(&self) -> f32 {
+        let mut sorted: Vec<f32> = self.frame_times.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        match sorted.len() {
+            0 => 0.,
+            len if len % 2 == 0 => (sorted[len / 2 - 1] + sorted[len / 2]) / 2.,
+            len => sorted[len / 2],
+        }
+    }
+
+    /// Takes in a frame that took `frame_seconds`. Returns the new tier if it steps.
+    pub fn sample(&mut self, frame_seconds: f32, config: &QualityConfig) -> Option<usize> {
+        self.held += frame_seconds;
+        self.frame_times.push_back(frame_seconds * 1000.);
+        while self.frame_times.len() > config.window.max(1) {
+            self.frame_times.pop_front();
+        }
+        if self.frame_times.len() < config.window.max(1) || self.held < config.hold {
+            return None;
+        }
+
+        let median = self.median_ms();
+        let cheapest = config.tiers.len().saturating_sub(1);
+        let tier = if median > config.budget_ms * config.step_down_at && self.tier < cheapest {
+            self.tier + 1
+        } else if median < config.budget_ms * config.step_up_at && self.tier > 0 {
+            self.tier - 1
+        } else {
+            return None;
+        };
+        self.tier = tier;
+        self.frame_times.clear();
+        self.held = 0.;
+        Some(tier)
+    }
+}
+
+/// Steps the `QualityTier` and `RenderScale` up and down with the frame time. Runs once a frame.
+pub struct AdaptiveQualitySystem {
+    config: QualityConfig,
+    controller: QualityController,
+    applied: bool,
+}
+
+impl AdaptiveQualitySystem {
+    pub fn new(config: QualityConfig) -> Self {
+        AdaptiveQualitySystem {
+            config,
+            controller: QualityController::default(),
+            applied: false,
+        }
+    }
+}
+
+impl<'s> System<'s> for AdaptiveQualitySystem {
+    type SystemData = (
+        Write<'s, QualityTier>,
+        Write<'s, RenderScale>,
+        Write<'s, DebugOverlay>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (mut current, mut scale, mut overlay, time): Self::SystemData) {
+        let median = self.controller.median_ms();
+        let stepped = if self.config.enabled {
+            self.controller
+                .sample(time.delta_real_seconds(), &self.config)
+        } else {
+            None
+        };
+        if stepped.is_some() || !self.applied {
+            if let Some(tier) = self.config.tiers.get(self.controller.tier()) {
+                if self.applied {
+                    info!(
+                        "Quality set to {}, frames taking {:.1} ms",
+                        tier.name, median
+                    );
+                }
+                *current = tier.clone();
+                *scale = RenderScale(tier.render_scale);
+            }
+            self.applied = true;
+        }
+        let text = if self.config.enabled {
+            format!(
+                "Quality: {} at {:.0}%, frames taking {:.1} ms of {:.1}",
+                current.name,
+                scale.clamped() * 100.,
+                self.controller.median_ms(),
+                self.config.budget_ms,
+            )
+        } else {
+            format!(
+                "Quality: {} at {:.0}% (fixed)",
+                current.name,
+                scale.clamped() * 100.
+            )
+        };
+        overlay.set("quality", text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> QualityConfig {
+        QualityConfig {
+            window: 10,
+            hold: 0.,
+            ..QualityConfig::default()
+        }
+    }
+
+    /// Feeds `frames` frames of `milliseconds` each, returning the steps taken.
+    fn run(
+        controller: &mut QualityController,
+        config: &QualityConfig,
+        frames: usize,
+        milliseconds: f32,
+    ) -> Vec<usize> {
+        (0..frames)
+            .filter_map(|_| controller.sample(milliseconds / 1000., config))
+            .collect()
+    }
+
+    #[test]
+    fn steps_down_once_a_window_of_frames_runs_slow() {
+        let (config, mut controller) = (config(), QualityController::default());
+        assert!(run(&mut controller, &config, 9, 30.).is_empty());
+        assert_eq!(run(&mut controller, &config, 1, 30.), [1]);
+        assert_eq!(run(&mut controller, &config, 10, 30.), [2]);
+        // Already at the cheapest tier.
+        assert!(run(&mut controller, &config, 20, 30.).is_empty());
+    }
+
+    #[test]
+    fn a_hitch_does_not_step() {
+        let (config, mut controller) = (config(), QualityController::default());
+        run(&mut controller, &config, 8, 16.);
+        assert!(run(&mut controller, &config, 2, 500.).is_empty());
+        assert_eq!(controller.tier(), 0);
+    }
+
+    #[test]
+    fn frames_between_the_thresholds_keep_the_tier() {
+        let (config, mut controller) = (config(), QualityController::default());
+        assert_eq!(run(&mut controller, &config, 10, 30.), [1]);
+        assert!(run(&mut controller, &config, 100, 16.).is_empty());
+        assert_eq!(controller.tier(), 1);
+    }
+
+    #[test]
+    fn steps_back_up_with_headroom_after_the_hold() {
+        let config = QualityConfig {
+            hold: 1.,
+            ..config()
+        };
+        let mut controller = QualityController::default();
+        assert!(run(&mut controller, &config, 33, 30.).is_empty());
+        assert_eq!(run(&mut controller, &config, 1, 30.), [1]);
+        // A second of 5 ms frames is 200 of them.
+        assert!(run(&mut controller, &config, 150, 5.).is_empty());
+        assert_eq!(run(&mut controller, &config, 100, 5.), [0]);
+    }
+}
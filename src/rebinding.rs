@@ -0,0 +1,378 @@
+//! Rebinding one control at a time, as the controls menu does.
+//!
+//! Each combo of an action and each direction of an emulated axis is a `BindingSlot`. Giving a
+//! slot a button another slot already uses is a conflict, settled by swapping what the two are
+//! bound to or by leaving both as they were. The two directions of an axis are slots of their
+//! own, so a key can be moved from one to the other, which turns the axis around, but one key
+//! never drives both.
+//!
+//! A combo holding a button some other slot is bound to alone is a conflict too, as pressing
+//! the combo would set off both. Bindings with one are rejected as they are loaded, and
+//! rebinding never leaves one behind.
+
+use amethyst::input::{Axis, Bindings, Button, StringBindings};
+use failure::format_err;
+
+use crate::strings::Strings;
+
+/// One binding the players can change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BindingSlot {
+    /// The `combo`th combo of `action`.
+    Action { action: String, combo: usize },
+    /// The button pushing an emulated axis towards positive.
+    Positive(String),
+    /// The button pushing an emulated axis towards negative.
+    Negative(String),
+}
+
+impl BindingSlot {
+    /// What the slot is called in the controls menu.
+    pub fn name(&self) -> String {
+        match self {
+            BindingSlot::Action { action, combo: 0 } => action.clone(),
+            BindingSlot::Action { action, combo } => format!("{} ({})", action, combo + 1),
+            BindingSlot::Positive(axis) => format!("{} +", axis),
+            BindingSlot::Negative(axis) => format!("{} -", axis),
+        }
+    }
+
+    /// The buttons the slot is bound to in `bindings`, or `None` if it isn't there.
+    pub fn binding(&self, bindings: &Bindings<StringBindings>) -> Option<Vec<Button>> {
+        match self {
+            BindingSlot::Action { action, combo } => bindings
+                .action_bindings(action)
+                .nth(*combo)
+                .map(<[Button]>::to_vec),
+            BindingSlot::Positive(axis) => match bindings.axis(axis) {
+                Some(Axis::Emulated { pos, .. }) => Some(vec![*pos]),
+                _ => None,
+            },
+            BindingSlot::Negative(axis) => match bindings.axis(axis) {
+                Some(Axis::Emulated { neg, .. }) => Some(vec![*neg]),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Every slot of `bindings`: the directions of the emulated axes, then the combos of the
+/// actions, each by name.
+pub fn slots(bindings: &Bindings<StringBindings>) -> Vec<BindingSlot> {
+    let mut axes: Vec<&String> = bindings
+        .axes()
+        .filter(|&axis| matches!(bindings.axis(axis), Some(Axis::Emulated { .. })))
+        .collect();
+    axes.sort();
+    let mut actions: Vec<&String> = bindings.actions().collect();
+    actions.sort();
+
+    let mut slots = Vec::new();
+    for axis in axes {
+        slots.push(BindingSlot::Positive(axis.clone()));
+        slots.push(BindingSlot::Negative(axis.clone()));
+    }
+    for action in actions {
+        for combo in 0..bindings.action_bindings(action).count() {
+            slots.push(BindingSlot::Action {
+                action: action.clone(),
+                combo,
+            });
+        }
+    }
+    slots
+}
+
+/// The slots other than `slot` bound to `button`, alone or in a combo, which binding `slot` to
+/// it would clash with.
+pub fn conflicts(
+    bindings: &Bindings<StringBindings>,
+    slot: &BindingSlot,
+    button: Button,
+) -> Vec<BindingSlot> {
+    slots(bindings)
+        .into_iter()
+        .filter(|other| {
+            other != slot
+                && other
+                    .binding(bindings)
+                    .is_some_and(|bound| bound.contains(&button))
+        })
+        .collect()
+}
+
+/// Fails on the first combo of `bindings` holding a button another slot is bound to alone.
+pub fn check_combos(bindings: &Bindings<StringBindings>) -> Result<(), failure::Error> {
+    let bound: Vec<(BindingSlot, Vec<Button>)> = slots(bindings)
+        .into_iter()
+        .filter_map(|slot| {
+            let buttons = slot.binding(bindings)?;
+            Some((slot, buttons))
+        })
+        .collect();
+    for (combo, buttons) in bound.iter().filter(|(_, buttons)| buttons.len() > 1) {
+        let alone = bound.iter().find(|(other, single)| {
+            other != combo && single.len() == 1 && buttons.contains(&single[0])
+        });
+        if let Some((other, single)) = alone {
+            return Err(format_err!(
+                "{} is bound to a combo with {:?}, which {} is bound to alone",
+                combo.name(),
+                single[0],
+                other.name()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `bindings` with `slot` bound to `button` alone. Slots it conflicts with are given what
+/// `slot` was bound to if `swap` is set, and make it fail otherwise. It also fails if a combo
+/// would be left holding a button bound alone, as swapping several combos can.
+pub fn rebind(
+    bindings: &Bindings<StringBindings>,
+    slot: &BindingSlot,
+    button: Button,
+    swap: bool,
+) -> Result<Bindings<StringBindings>, failure::Error> {
+    let previous = slot
+        .binding(bindings)
+        .ok_or_else(|| format_err!("{} is not bound", slot.name()))?;
+    let clashing = conflicts(bindings, slot, button);
+    if !swap && !clashing.is_empty() {
+        return Err(format_err!(
+            "{:?} is already bound to {}",
+            button,
+            clashing[0].name()
+        ));
+    }
+
+    let mut table = BindingTable::new(bindings);
+    table.set(slot, vec![button])?;
+    for other in &clashing {
+        table.set(other, previous.clone())?;
+    }
+    let rebound = table.build()?;
+    check_combos(&rebound)?;
+    Ok(rebound)
+}
+
+/// A readable name for `button` in the current locale.
+pub fn button_name(button: Button, strings: &Strings) -> String {
+    match button {
+        Button::Key(key) => format!("{:?}", key),
+        Button::ScanCode(code) => strings.format("button.scan_code", &[&code]),
+        Button::Mouse(mouse) => strings.format("button.mouse", &[&format!("{:?}", mouse)]),
+        Button::MouseWheel(direction) => {
+            strings.format("button.wheel", &[&format!("{:?}", direction)])
+        }
+        Button::Controller(controller, button) => {
+            strings.format("button.pad", &[&(controller + 1), &format!("{:?}", button)])
+        }
+    }
+}
+
+/// A readable name for a combo of `buttons` in the current locale, or a placeholder for none.
+pub fn combo_name(buttons: &[Button], strings: &Strings) -> String {
+    let mut names = buttons.iter().map(|&button| button_name(button, strings));
+    let first = match names.next() {
+        Some(first) => first,
+        None => return strings.get("button.none"),
+    };
+    names.fold(first, |combo, name| {
+        strings.format("button.combo", &[&combo, &name])
+    })
+}
+
+/// Bindings taken apart to be edited freely, as `Bindings` refuses any one change that clashes
+/// with what is already bound, even when the next change settles it.
+struct BindingTable {
+    axes: Vec<(String, Axis)>,
+    actions: Vec<(String, Vec<Vec<Button>>)>,
+}
+
+impl BindingTable {
+    fn new(bindings: &Bindings<StringBindings>) -> Self {
+        BindingTable {
+            axes: bindings
+                .axes()
+                .filter_map(|axis| Some((axis.clone(), bindings.axis(axis)?.clone())))
+                .collect(),
+            actions: bindings
+                .actions()
+                .map(|action| {
+                    let combos = bindings
+                        .action_bindings(action)
+                        .map(<[Button]>::to_vec)
+                        .collect();
+                    (action.clone(), combos)
+                })
+                .collect(),
+        }
+    }
+
+    fn set(&mut self, slot: &BindingSlot, buttons: Vec<Button>) -> Result<(), failure::Error> {
+        let missing = || format_err!("{} is not bound", slot.name());
+        match slot {
+            BindingSlot::Action { action, combo } => {
+                let combos = self
+                    .actions
+                    .iter_mut()
+                    .find(|(name, _)| name == action)
+                    .map(|(_, combos)| combos)
+                    .ok_or_else(missing)?;
+                *combos.get_mut(*combo).ok_or_else(missing)? = buttons;
+            }
+            BindingSlot::Positive(axis) | BindingSlot::Negative(axis) => {
+                let button = match buttons[..] {
+                    [button] => button,
+                    _ => {
+                        return Err(format_err!(
+                            "{} can't be bound to the combo {:?}",
+                            slot.name(),
+                            buttons
+                        ))
+                    }
+                };
+                let bound = self
+                    .axes
+                    .iter_mut()
+                    .find(|(name, _)| name == axis)
+                    .map(|(_, bound)| bound);
+                match (bound, slot) {
+                    (Some(Axis::Emulated { pos, .. }), BindingSlot::Positive(_)) => *pos = button,
+                    (Some(Axis::Emulated { neg, .. }), BindingSlot::Negative(_)) => *neg = button,
+                    _ => return Err(missing()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<Bindings<StringBindings>, failure::Error> {
+        let mut bindings = Bindings::new();
+        for (axis, bound) in self.axes {
+            bindings
+                .insert_axis(axis, bound)
+                .map_err(|err| format_err!("{}", err))?;
+        }
+        for (action, combos) in self.actions {
+            for combo in combos {
+                bindings
+                    .insert_action_binding(action.clone(), combo)
+                    .map_err(|err| format_err!("{}", err))?;
+            }
+        }
+        Ok(bindings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::input::VirtualKeyCode;
+
+    use super::*;
+
+    fn bindings(actions: &str) -> Bindings<StringBindings> {
+        ron::de::from_str(&format!(
+            r#"(axes: {{"horizontal": Emulated(pos: Key(D), neg: Key(A))}}, actions: {{{}}})"#,
+            actions
+        ))
+        .unwrap()
+    }
+
+    fn dash() -> BindingSlot {
+        BindingSlot::Action {
+            action: "dash".to_string(),
+            combo: 0,
+        }
+    }
+
+    fn cheat(combo: usize) -> BindingSlot {
+        BindingSlot::Action {
+            action: "cheat".to_string(),
+            combo,
+        }
+    }
+
+    fn key(key: VirtualKeyCode) -> Button {
+        Button::Key(key)
+    }
+
+    const DASH_AND_CHEAT: &str = r#""dash": [[Key(Space)]], "cheat": [[Key(LControl), Key(U)]]"#;
+
+    #[test]
+    fn conflicts_include_combos_holding_the_button() {
+        let bindings = bindings(DASH_AND_CHEAT);
+        assert_eq!(
+            conflicts(&bindings, &dash(), key(VirtualKeyCode::U)),
+            [cheat(0)]
+        );
+        assert_eq!(
+            conflicts(&bindings, &dash(), key(VirtualKeyCode::D)),
+            [BindingSlot::Positive("horizontal".to_string())]
+        );
+        assert!(conflicts(&bindings, &dash(), key(VirtualKeyCode::Q)).is_empty());
+    }
+
+    #[test]
+    fn conflicts_are_swapped_or_refused() {
+        let bindings = bindings(DASH_AND_CHEAT);
+        assert!(rebind(&bindings, &dash(), key(VirtualKeyCode::D), false).is_err());
+
+        let swapped = rebind(&bindings, &dash(), key(VirtualKeyCode::D), true).unwrap();
+        assert_eq!(dash().binding(&swapped), Some(vec![key(VirtualKeyCode::D)]));
+        assert_eq!(
+            BindingSlot::Positive("horizontal".to_string()).binding(&swapped),
+            Some(vec![key(VirtualKeyCode::Space)])
+        );
+    }
+
+    #[test]
+    fn axes_are_turned_around_by_swapping_their_directions() {
+        let bindings = bindings(DASH_AND_CHEAT);
+        let positive = BindingSlot::Positive("horizontal".to_string());
+        let swapped = rebind(&bindings, &positive, key(VirtualKeyCode::A), true).unwrap();
+        assert_eq!(
+            swapped.axis("horizontal"),
+            Some(&Axis::Emulated {
+                pos: key(VirtualKeyCode::A),
+                neg: key(VirtualKeyCode::D),
+            })
+        );
+    }
+
+    #[test]
+    fn no_combo_is_left_holding_a_button_bound_alone() {
+        let swapped = rebind(
+            &bindings(DASH_AND_CHEAT),
+            &dash(),
+            key(VirtualKeyCode::U),
+            true,
+        )
+        .unwrap();
+        assert_eq!(dash().binding(&swapped), Some(vec![key(VirtualKeyCode::U)]));
+        assert_eq!(
+            cheat(0).binding(&swapped),
+            Some(vec![key(VirtualKeyCode::Space)])
+        );
+        assert!(check_combos(&swapped).is_ok());
+
+        // Both combos would be given the space bar alone.
+        let two_combos = bindings(
+            r#""dash": [[Key(Space)]],
+                "cheat": [[Key(LShift), Key(U)]],
+                "no_clip": [[Key(LAlt), Key(U)]]"#,
+        );
+        assert!(rebind(&two_combos, &dash(), key(VirtualKeyCode::U), true).is_err());
+    }
+
+    #[test]
+    fn combos_holding_a_button_bound_alone_are_rejected() {
+        assert!(check_combos(&bindings(DASH_AND_CHEAT)).is_ok());
+        let clashing = bindings(r#""dash": [[Key(U)]], "cheat": [[Key(LControl), Key(U)]]"#);
+        assert!(check_combos(&clashing).is_err());
+        let on_an_axis = bindings(r#""cheat": [[Key(LControl), Key(D)]]"#);
+        assert!(check_combos(&on_an_axis).is_err());
+    }
+}
@@ -0,0 +1,524 @@
+//! Recording the last few seconds of play to a GIF, for bug reports.
+//!
+//! With `recording.ron` enabled, the `FrameReadbackDesc` node copies every finished frame into
+//! memory the CPU can read. A few times a second the `RecordingSystem` asks for one, which is
+//! read back once the GPU is done with it, shrunk to fit `max_width` by `max_height` and kept
+//! in a `FrameRing` of the last `seconds` of them. The `save_recording` action writes the ring
+//! out to a GIF on a thread of its own, so the game doesn't stall while it encodes.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{Read, Resources, System, Write},
+    input::{InputHandler, StringBindings},
+    renderer::{
+        rendy::{
+            command::{
+                CommandBuffer, CommandPool, ExecutableState, Family, MultiShot, PendingState,
+                SimultaneousUse, Submit, Transfer,
+            },
+            factory::Factory,
+            frame::Frames,
+            graph::{
+                gfx_acquire_barriers, gfx_release_barriers, GraphContext, ImageAccess, Node,
+                NodeBuffer, NodeDesc, NodeImage, NodeSubmittable,
+            },
+            hal::{self, format::Format},
+            memory::Download,
+            resource::{Buffer, BufferInfo, Escape},
+        },
+        types::Backend,
+    },
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// How much play is recorded and how small it is kept, read from `recording.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// Whether frames are read back at all. Copying every frame costs a little, so it is off
+    /// unless asked for.
+    pub enabled: bool,
+    /// Seconds of play kept.
+    pub seconds: f32,
+    /// Frames kept per second.
+    pub fps: f32,
+    /// The largest a kept frame may be, in pixels. Frames are shrunk by whole pixels to fit.
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Where recordings are saved, relative to the game's folder.
+    pub directory: PathBuf,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        RecordingConfig {
+            enabled: false,
+            seconds: 5.,
+            fps: 10.,
+            max_width: 320,
+            max_height: 240,
+            directory: PathBuf::from("recordings"),
+        }
+    }
+}
+
+impl RecordingConfig {
+    /// How many frames the ring holds.
+    pub fn capacity(&self) -> usize {
+        (self.seconds.max(0.) * self.fps.max(0.)).ceil() as usize
+    }
+}
+
+/// A frame kept for a recording, as rows of RGBA pixels from the top.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Shrinks a `width` by `height` frame of 4 byte `pixels` to fit within `max_width` by
+/// `max_height`, skipping whole pixels so the GIF stays crisp. `bgra` frames are turned into
+/// RGBA. Every pixel is made opaque.
+pub fn downscale(
+    pixels: &[u8],
+    (width, height): (u32, u32),
+    (max_width, max_height): (u32, u32),
+    bgra: bool,
+) -> RecordedFrame {
+    let step = [
+        1,
+        width.div_ceil(max_width.max(1)),
+        height.div_ceil(max_height.max(1)),
+    ]
+    .iter()
+    .copied()
+    .max()
+    .unwrap_or(1);
+    let (out_width, out_height) = (width / step, height / step);
+    let mut out = Vec::with_capacity((out_width * out_height * 4) as usize);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let at = (((y * step) * width + x * step) * 4) as usize;
+            let pixel = &pixels[at..at + 4];
+            if bgra {
+                out.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+            } else {
+                out.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+            }
+        }
+    }
+    RecordedFrame {
+        width: out_width,
+        height: out_height,
+        pixels: out,
+    }
+}
+
+/// The last `capacity` frames, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct FrameRing {
+    frames: VecDeque<RecordedFrame>,
+    capacity: usize,
+}
+
+impl FrameRing {
+    pub fn new(capacity: usize) -> Self {
+        FrameRing {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Keeps `frame`, dropping the oldest once full. A frame of another size than the ones
+    /// kept, after the window is resized, starts the ring over, as a GIF has only one size.
+    pub fn push(&mut self, frame: RecordedFrame) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self
+            .frames
+            .back()
+            .is_some_and(|last| (last.width, last.height) != (frame.width, frame.height))
+        {
+            self.frames.clear();
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Empties the ring, oldest frame first, to be saved. Recording goes on into the emptied
+    /// ring.
+    pub fn flush(&mut self) -> Vec<RecordedFrame> {
+        self.frames.drain(..).collect()
+    }
+}
+
+/// Writes `frames` to a looping GIF at `path`, `fps` of them a second.
+pub fn save_gif(path: &Path, frames: Vec<RecordedFrame>, fps: f32) -> Result<(), failure::Error> {
+    let first = frames
+        .first()
+        .ok_or_else(|| failure::format_err!("No frames to save"))?;
+    let (width, height) = (first.width as u16, first.height as u16);
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let mut encoder = gif::Encoder::new(File::create(path)?, width, height, &[])?;
+    gif::SetParameter::set(&mut encoder, gif::Repeat::Infinite)?;
+    // GIF delays are in hundredths of a second.
+    let delay = (100. / fps.max(1.)).round() as u16;
+    for mut recorded in frames {
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut recorded.pixels, 10);
+        frame.delay = delay;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+/// Frames on their way from the `FrameReadbackDesc` node to the `RecordingSystem`.
+#[derive(Clone, Debug, Default)]
+pub struct FrameCapture {
+    /// Whether the node should read back the next frame it can.
+    pub wanted: bool,
+    /// The frame read back, until the `RecordingSystem` takes it.
+    pub frame: Option<RecordedFrame>,
+}
+
+/// Keeps frames while `recording.ron` is enabled and saves them on the `save_recording`
+/// action.
+pub struct RecordingSystem {
+    config: RecordingConfig,
+    directory: PathBuf,
+    ring: FrameRing,
+    since_frame: f32,
+    was_pressed: bool,
+}
+
+impl RecordingSystem {
+    /// Saves recordings in `root` joined with the configured directory.
+    pub fn new(config: RecordingConfig, root: &Path) -> Self {
+        RecordingSystem {
+            directory: root.join(&config.directory),
+            ring: FrameRing::new(config.capacity()),
+            config,
+            since_frame: 0.,
+            was_pressed: false,
+        }
+    }
+
+    /// Saves what the ring holds on a thread of its own.
+    fn save(&mut self) {
+        if self.ring.is_empty() {
+            warn!("Nothing recorded yet to save");
+            return;
+        }
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let path = self.directory.join(format!("recording-{}.gif", stamp));
+        let frames = self.ring.flush();
+        let fps = self.config.fps;
+        thread::spawn(move || {
+            let count = frames.len();
+            match save_gif(&path, frames, fps) {
+                Ok(()) => info!("Saved {} frames to {}", count, path.display()),
+                Err(err) => warn!("Failed to save a recording to {}: {}", path.display(), err),
+            }
+        });
+    }
+}
+
+impl<'s> System<'s> for RecordingSystem {
+    type SystemData = (
+        Write<'s, FrameCapture>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (mut capture, input, time): Self::SystemData) {
+        if !self.config.enabled {
+            return;
+        }
+        if let Some(frame) = capture.frame.take() {
+            self.ring.push(frame);
+        }
+        self.since_frame += time.delta_real_seconds();
+        if self.config.fps > 0. && self.since_frame >= 1. / self.config.fps {
+            self.since_frame = 0.;
+            capture.wanted = true;
+        }
+
+        let pressed = input.action_is_down("save_recording").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            self.save();
+        }
+        self.was_pressed = pressed;
+    }
+}
+
+/// Copies the image it is given into memory the CPU can read, and reads a frame back from it
+/// into the `FrameCapture` when one is wanted.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameReadbackDesc {
+    /// The format of the image read back.
+    pub format: Format,
+    /// The largest a frame read back may be, in pixels.
+    pub max_size: (u32, u32),
+}
+
+impl<B: Backend> NodeDesc<B, Resources> for FrameReadbackDesc {
+    type Node = FrameReadback<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: hal::image::Access::TRANSFER_READ,
+            layout: hal::image::Layout::TransferSrcOptimal,
+            usage: hal::image::Usage::TRANSFER_SRC,
+            stages: hal::pso::PipelineStage::TRANSFER,
+        }]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &Resources,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, failure::Error> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 1);
+
+        let source = &images[0];
+        let source_image = ctx.get_image(source.id).expect("Image does not exist");
+        let extent = source_image.kind().extent();
+        let size = u64::from(extent.width) * u64::from(extent.height) * 4;
+
+        let mut pool = factory.create_command_pool(family)?;
+        // One copy per frame in flight, so a copy is only read once its frame is done.
+        let mut copies = Vec::new();
+        for initial in pool.allocate_buffers(ctx.frames_in_flight as usize) {
+            let target = factory.create_buffer(
+                BufferInfo {
+                    size,
+                    usage: hal::buffer::Usage::TRANSFER_DST,
+                },
+                Download,
+            )?;
+            let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+            unsafe {
+                let (stages, barriers) = gfx_acquire_barriers(ctx, None, &images);
+                recording.encoder().pipeline_barrier(
+                    stages,
+                    hal::memory::Dependencies::empty(),
+                    barriers,
+                );
+                // rendy's encoder can't copy an image into a buffer, so it is recorded raw.
+                hal::command::RawCommandBuffer::copy_image_to_buffer(
+                    recording.raw(),
+                    source_image.raw(),
+                    source.layout,
+                    target.raw(),
+                    Some(hal::command::BufferImageCopy {
+                        buffer_offset: 0,
+                        buffer_width: extent.width,
+                        buffer_height: extent.height,
+                        image_layers: hal::image::SubresourceLayers {
+                            aspects: source.range.aspects,
+                            level: 0,
+                            layers: source.range.layers.start..source.range.layers.start + 1,
+                        },
+                        image_offset: hal::image::Offset::ZERO,
+                        image_extent: extent,
+                    }),
+                );
+                let mut encoder = recording.encoder();
+                // Makes the copy visible to the CPU once the frame's fence is signalled.
+                encoder.pipeline_barrier(
+                    hal::pso::PipelineStage::TRANSFER..hal::pso::PipelineStage::HOST,
+                    hal::memory::Dependencies::empty(),
+                    Some(hal::memory::Barrier::Buffer {
+                        states: hal::buffer::Access::TRANSFER_WRITE..hal::buffer::Access::HOST_READ,
+                        target: target.raw(),
+                        families: None,
+                        range: None..None,
+                    }),
+                );
+                let (stages, barriers) = gfx_release_barriers(ctx, None, &images);
+                encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+            }
+            let (submit, buffer) = recording.finish().submit();
+            copies.push(FrameCopy {
+                target,
+                submit,
+                buffer,
+                frame: None,
+            });
+        }
+
+        Ok(FrameReadback {
+            pool,
+            copies,
+            size: (extent.width, extent.height),
+            max_size: self.max_size,
+            bgra: matches!(self.format, Format::Bgra8Srgb | Format::Bgra8Unorm),
+        })
+    }
+}
+
+/// A copy of one frame in flight.
+#[derive(Debug)]
+struct FrameCopy<B: Backend> {
+    target: Escape<Buffer<B>>,
+    submit: Submit<B, SimultaneousUse>,
+    buffer:
+        CommandBuffer<B, hal::QueueType, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>,
+    /// The frame last copied here.
+    frame: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct FrameReadback<B: Backend> {
+    pool: CommandPool<B, hal::QueueType>,
+    copies: Vec<FrameCopy<B>>,
+    size: (u32, u32),
+    max_size: (u32, u32),
+    bgra: bool,
+}
+
+impl<'a, B: Backend> NodeSubmittable<'a, B> for FrameReadback<B> {
+    type Submittable = &'a Submit<B, SimultaneousUse>;
+    type Submittables = Option<&'a Submit<B, SimultaneousUse>>;
+}
+
+impl<B: Backend> Node<B, Resources> for FrameReadback<B> {
+    type Capability = Transfer;
+    type Desc = FrameReadbackDesc;
+
+    fn run<'a>(
+        &'a mut self,
+        _ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        aux: &Resources,
+        frames: &'a Frames<B>,
+    ) -> Option<&'a Submit<B, SimultaneousUse>> {
+        let index = frames.next().index();
+        let count = self.copies.len() as u64;
+        let copy = &mut self.copies[(index % count) as usize];
+
+        // The graph waits for the frame that last used this copy before starting this one.
+        if copy.frame.is_some() {
+            if let Some(mut capture) = aux.try_fetch_mut::<FrameCapture>() {
+                if capture.wanted {
+                    let (size, max_size, bgra) = (self.size, self.max_size, self.bgra);
+                    let bytes = u64::from(size.0) * u64::from(size.1) * 4;
+                    let read =
+                        copy.target
+                            .map(factory.device(), 0..bytes)
+                            .and_then(|mut mapped| {
+                                let pixels =
+                                    unsafe { mapped.read::<u8>(factory.device(), 0..bytes)? };
+                                Ok(downscale(pixels, size, max_size, bgra))
+                            });
+                    match read {
+                        Ok(frame) => capture.frame = Some(frame),
+                        Err(err) => warn!("Failed to read back a frame: {}", err),
+                    }
+                    capture.wanted = false;
+                }
+            }
+        }
+        copy.frame = Some(index);
+        Some(&copy.submit)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &Resources) {
+        self.pool.free_buffers(
+            self.copies
+                .drain(..)
+                .map(|copy| copy.buffer.mark_complete()),
+        );
+        factory.destroy_command_pool(self.pool);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn frame(shade: u8) -> RecordedFrame {
+        RecordedFrame {
+            width: 2,
+            height: 2,
+            pixels: [shade, shade, shade, 255].repeat(4),
+        }
+    }
+
+    #[test]
+    fn the_ring_keeps_only_the_last_seconds_of_frames() {
+        let config = RecordingConfig {
+            seconds: 2.,
+            fps: 3.,
+            ..RecordingConfig::default()
+        };
+        assert_eq!(config.capacity(), 6);
+
+        let mut ring = FrameRing::new(config.capacity());
+        for shade in 0..10 {
+            ring.push(frame(shade));
+        }
+        let kept: Vec<u8> = ring.flush().iter().map(|frame| frame.pixels[0]).collect();
+        assert_eq!(kept, [4, 5, 6, 7, 8, 9]);
+        assert!(ring.is_empty());
+
+        // A frame of another size starts the ring over.
+        ring.push(frame(1));
+        ring.push(RecordedFrame {
+            width: 1,
+            height: 1,
+            pixels: vec![2, 2, 2, 255],
+        });
+        assert_eq!(ring.flush().len(), 1);
+    }
+
+    #[test]
+    fn flushing_saves_what_is_buffered() {
+        let mut ring = FrameRing::new(10);
+        for shade in [10, 128, 250] {
+            ring.push(frame(shade));
+        }
+        let path = env::temp_dir().join(format!("shroud-recording-{}.gif", std::process::id()));
+        save_gif(&path, ring.flush(), 10.).unwrap();
+
+        let mut decoder = gif::Decoder::new(File::open(&path).unwrap())
+            .read_info()
+            .unwrap();
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height, frame.delay), (2, 2, 10));
+            frames += 1;
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(frames, 3);
+        assert!(save_gif(&path, ring.flush(), 10.).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Recovery from a rendering frame that fails, for instance because the window surface was lost
+//! after a driver reset.
+//!
+//! rendy reports a lost surface or device by panicking out of the frame. `RecoveringRenderer`
+//! catches that, and the `RenderingGraph` then rebuilds the graph against a freshly created
+//! surface. Retries back off exponentially and are capped; once the cap is reached the failure
+//! is passed on, since at that point the device itself is most likely gone.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use amethyst::ecs::prelude::{Resources, RunNow};
+use log::{error, warn};
+
+/// How often and how patiently a failing renderer is rebuilt.
+#[derive(Clone, Copy, Debug)]
+pub struct RecoveryPolicy {
+    /// Consecutive failed frames tolerated before giving up.
+    pub max_retries: u32,
+    /// Frames skipped after the first failure. Each further failure doubles it.
+    pub base_backoff: u32,
+    /// Upper limit of frames skipped between two attempts.
+    pub max_backoff: u32,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        RecoveryPolicy {
+            max_retries: 5,
+            base_backoff: 1,
+            max_backoff: 60,
+        }
+    }
+}
+
+impl RecoveryPolicy {
+    /// Frames to skip after the `failures`th failure in a row.
+    pub fn backoff(&self, failures: u32) -> u32 {
+        let doublings = failures.saturating_sub(1).min(31);
+        self.base_backoff
+            .saturating_mul(1 << doublings)
+            .min(self.max_backoff)
+    }
+}
+
+/// Recovery progress, shared between the `RecoveringRenderer` and the graph it runs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderRecovery {
+    failures: u32,
+    wait: u32,
+    rebuild: bool,
+}
+
+impl RenderRecovery {
+    /// Records a failed frame and schedules a rebuild. Returns `false` once `policy` gives up.
+    pub fn record_failure(&mut self, policy: &RecoveryPolicy) -> bool {
+        self.failures += 1;
+        if self.failures > policy.max_retries {
+            return false;
+        }
+        self.wait = policy.backoff(self.failures);
+        self.rebuild = true;
+        true
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Whether to render this frame, counting down the backoff if not.
+    pub fn should_render(&mut self) -> bool {
+        if self.wait > 0 {
+            self.wait -= 1;
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Whether the graph has to be rebuilt, clearing the request.
+    pub fn take_rebuild(&mut self) -> bool {
+        std::mem::replace(&mut self.rebuild, false)
+    }
+}
+
+/// Runs the rendering system, turning a failed frame into a rebuild of the graph instead of a
+/// crash.
+pub struct RecoveringRenderer<S> {
+    inner: S,
+    policy: RecoveryPolicy,
+}
+
+impl<S> RecoveringRenderer<S> {
+    pub fn new(inner: S, policy: RecoveryPolicy) -> Self {
+        RecoveringRenderer { inner, policy }
+    }
+}
+
+impl<'a, S: RunNow<'a>> RunNow<'a> for RecoveringRenderer<S> {
+    fn run_now(&mut self, res: &'a Resources) {
+        if !res.fetch_mut::<RenderRecovery>().should_render() {
+            return;
+        }
+
+        let inner = &mut self.inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.run_now(res))) {
+            Ok(()) => res.fetch_mut::<RenderRecovery>().record_success(),
+            Err(cause) => {
+                let mut recovery = res.fetch_mut::<RenderRecovery>();
+                if recovery.record_failure(&self.policy) {
+                    warn!(
+                        "Rendering failed, rebuilding the graph and surface (attempt {} of {})",
+                        recovery.failures, self.policy.max_retries
+                    );
+                } else {
+                    error!(
+                        "Rendering failed {} times in a row, giving up",
+                        recovery.failures
+                    );
+                    drop(recovery);
+                    panic::resume_unwind(cause);
+                }
+            }
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        res.insert(RenderRecovery::default());
+        self.inner.setup(res);
+    }
+
+    fn dispose(self: Box<Self>, res: &mut Resources) {
+        Box::new(self.inner).dispose(res);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_its_cap() {
+        let policy = RecoveryPolicy {
+            max_retries: 10,
+            base_backoff: 2,
+            max_backoff: 20,
+        };
+        let schedule: Vec<_> = (1..=6).map(|failures| policy.backoff(failures)).collect();
+        assert_eq!(schedule, [2, 4, 8, 16, 20, 20]);
+        assert_eq!(policy.backoff(u32::MAX), 20);
+    }
+
+    #[test]
+    fn failures_skip_their_backoff_until_the_retries_run_out() {
+        let policy = RecoveryPolicy {
+            max_retries: 3,
+            base_backoff: 1,
+            max_backoff: 60,
+        };
+        let mut recovery = RenderRecovery::default();
+        for skipped in &[1, 2, 4] {
+            assert!(recovery.record_failure(&policy));
+            assert!(recovery.take_rebuild());
+            assert!(!recovery.take_rebuild());
+            for _ in 0..*skipped {
+                assert!(!recovery.should_render());
+            }
+            assert!(recovery.should_render());
+        }
+        assert!(!recovery.record_failure(&policy));
+
+        // A frame that renders starts the count over.
+        let mut recovery = RenderRecovery::default();
+        for _ in 0..3 {
+            assert!(recovery.record_failure(&policy));
+            recovery.record_success();
+        }
+        assert!(recovery.record_failure(&policy));
+    }
+
+    /// Fails its first `failures` frames.
+    struct Flaky {
+        failures: u32,
+        frames: u32,
+    }
+
+    impl<'a> RunNow<'a> for Flaky {
+        fn run_now(&mut self, _: &'a Resources) {
+            self.frames += 1;
+            if self.frames <= self.failures {
+                panic!("Surface lost");
+            }
+        }
+
+        fn setup(&mut self, _: &mut Resources) {}
+    }
+
+    #[test]
+    fn the_renderer_recovers_or_gives_up_after_the_limit() {
+        let policy = RecoveryPolicy {
+            max_retries: 2,
+            base_backoff: 1,
+            max_backoff: 60,
+        };
+        let mut res = Resources::new();
+        let mut renderer = RecoveringRenderer::new(
+            Flaky {
+                failures: 2,
+                frames: 0,
+            },
+            policy,
+        );
+        renderer.setup(&mut res);
+        // Fails, skips one, fails, skips two, then renders.
+        for _ in 0..6 {
+            renderer.run_now(&res);
+        }
+        assert_eq!(renderer.inner.frames, 3);
+        assert!(res.fetch_mut::<RenderRecovery>().take_rebuild());
+
+        let mut res = Resources::new();
+        let mut renderer = RecoveringRenderer::new(
+            Flaky {
+                failures: 3,
+                frames: 0,
+            },
+            policy,
+        );
+        renderer.setup(&mut res);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            for _ in 0..6 {
+                renderer.run_now(&res);
+            }
+        }));
+        assert!(result.is_err());
+        assert_eq!(renderer.inner.frames, 3);
+    }
+}
@@ -0,0 +1,250 @@
+//! Dynamic resolution: drawing the scene at a fraction of the window's size.
+//!
+//! While the `RenderScale` is under one, the render graph draws the scene into images scaled
+//! down by it, and `DrawUpscaleDesc` then stretches the result over the whole window before the
+//! UI is drawn on top at the window's own resolution. The graph is rebuilt whenever the scale
+//! or the window's size changes the size the scene is drawn at. The `AdaptiveQualitySystem`
+//! lowers the scale along with the quality tier. With the split-screen UI drawn in each view,
+//! the scene is always drawn at full size, so the UI is too.
+//!
+//! The upscale pass is also drawn at full scale while texel snapping is on, shifting the scene
+//! by the `TexelSnap`'s offset as it samples it.
+
+use amethyst::{
+    ecs::prelude::Resources,
+    renderer::{
+        rendy::{
+            command::{QueueId, RenderPassEncoder},
+            factory::Factory,
+            graph::{
+                render::{Layout, SetLayout, SimpleGraphicsPipeline, SimpleGraphicsPipelineDesc},
+                GraphContext, ImageAccess, NodeBuffer, NodeImage,
+            },
+            hal::{
+                self,
+                device::Device,
+                format::Swizzle,
+                image::{Filter, SamplerInfo, ViewKind, WrapMode},
+                pso::{
+                    BlendState, ColorBlendDesc, ColorMask, DepthStencilDesc, Descriptor,
+                    DescriptorSetLayoutBinding, DescriptorSetWrite, DescriptorType,
+                    ShaderStageFlags,
+                },
+            },
+            resource::{
+                DescriptorSet, DescriptorSetLayout, Escape, Handle, ImageView, ImageViewInfo,
+                Sampler,
+            },
+            shader::{ShaderSet, ShaderSetBuilder, SpirvShader},
+        },
+        types::Backend,
+    },
+};
+
+use crate::texel_snap::TexelSnap;
+
+/// The smallest fraction of the window the scene is drawn at.
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+
+/// The fraction of the window's width and height the scene is drawn at, from
+/// `MIN_RENDER_SCALE` up to one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderScale(pub f32);
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        RenderScale(1.)
+    }
+}
+
+impl RenderScale {
+    /// The scale, kept within `MIN_RENDER_SCALE` and one.
+    pub fn clamped(self) -> f32 {
+        if self.0.is_nan() {
+            return 1.;
+        }
+        self.0.clamp(MIN_RENDER_SCALE, 1.)
+    }
+
+    /// The size in pixels the scene is drawn at in a `width` by `height` window.
+    pub fn scene_size(self, width: u32, height: u32) -> (u32, u32) {
+        let scale = self.clamped();
+        let scaled = |pixels: u32| ((pixels as f32 * scale).round() as u32).clamp(1, pixels.max(1));
+        (scaled(width), scaled(height))
+    }
+}
+
+fn shader(spirv: &[u8], stage: ShaderStageFlags) -> SpirvShader {
+    SpirvShader::new(spirv.to_vec(), stage, "main")
+}
+
+/// Draws the image it is given stretched over the whole target, filtered linearly and shifted by
+/// the `TexelSnap`'s offset.
+///
+/// The image is sampled, so it must be a different one from the subpass's colour attachment.
+#[derive(Clone, Debug, Default)]
+pub struct DrawUpscaleDesc;
+
+impl<B: Backend> SimpleGraphicsPipelineDesc<B, Resources> for DrawUpscaleDesc {
+    type Pipeline = DrawUpscale<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![ImageAccess {
+            access: hal::image::Access::SHADER_READ,
+            layout: hal::image::Layout::ShaderReadOnlyOptimal,
+            usage: hal::image::Usage::SAMPLED,
+            stages: hal::pso::PipelineStage::FRAGMENT_SHADER,
+        }]
+    }
+
+    fn colors(&self) -> Vec<ColorBlendDesc> {
+        vec![ColorBlendDesc(ColorMask::ALL, BlendState::Off)]
+    }
+
+    fn depth_stencil(&self) -> Option<DepthStencilDesc> {
+        None
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: vec![SetLayout {
+                bindings: vec![DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: DescriptorType::CombinedImageSampler,
+                    count: 1,
+                    stage_flags: ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                }],
+            }],
+            // The shift, as a fraction of the target's width and height.
+            push_constants: vec![(ShaderStageFlags::FRAGMENT, 0..2)],
+        }
+    }
+
+    fn load_shader_set(&self, factory: &mut Factory<B>, _res: &Resources) -> ShaderSet<B> {
+        ShaderSetBuilder::default()
+            .with_vertex(&shader(
+                include_bytes!("../shaders/compiled/upscale.vert.spv"),
+                ShaderStageFlags::VERTEX,
+            ))
+            .expect("Upscale vertex shader must load")
+            .with_fragment(&shader(
+                include_bytes!("../shaders/compiled/upscale.frag.spv"),
+                ShaderStageFlags::FRAGMENT,
+            ))
+            .expect("Upscale fragment shader must load")
+            .build(factory, Default::default())
+            .expect("Upscale shaders must build")
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _queue: QueueId,
+        _res: &Resources,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+    ) -> Result<DrawUpscale<B>, failure::Error> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 1);
+
+        let source = &images[0];
+        let image = ctx.get_image(source.id).expect("Image does not exist");
+        let view = factory.create_image_view(
+            image.clone(),
+            ImageViewInfo {
+                view_kind: ViewKind::D2,
+                format: image.format(),
+                swizzle: Swizzle::NO,
+                range: source.range.clone(),
+            },
+        )?;
+        let sampler = factory.create_sampler(SamplerInfo::new(Filter::Linear, WrapMode::Clamp))?;
+        let set = factory.create_descriptor_set(set_layouts[0].clone())?;
+        unsafe {
+            factory
+                .device()
+                .write_descriptor_sets(Some(DescriptorSetWrite {
+                    set: set.raw(),
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(Descriptor::CombinedImageSampler(
+                        view.raw(),
+                        source.layout,
+                        sampler.raw(),
+                    )),
+                }));
+        }
+
+        Ok(DrawUpscale {
+            set,
+            _view: view,
+            _sampler: sampler,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DrawUpscale<B: Backend> {
+    set: Escape<DescriptorSet<B>>,
+    /// Kept alive for as long as `set` refers to them.
+    _view: Escape<ImageView<B>>,
+    _sampler: Escape<Sampler<B>>,
+}
+
+impl<B: Backend> SimpleGraphicsPipeline<B, Resources> for DrawUpscale<B> {
+    type Desc = DrawUpscaleDesc;
+
+    fn draw(
+        &mut self,
+        layout: &B::PipelineLayout,
+        mut encoder: RenderPassEncoder<'_, B>,
+        _index: usize,
+        res: &Resources,
+    ) {
+        let snap = res
+            .try_fetch::<TexelSnap>()
+            .map(|snap| *snap)
+            .unwrap_or_default();
+        unsafe {
+            encoder.bind_graphics_descriptor_sets(layout, 0, Some(self.set.raw()), None);
+            encoder.push_constants(
+                layout,
+                ShaderStageFlags::FRAGMENT,
+                0,
+                &snap.push_constants(),
+            );
+            // One triangle covering the whole target, made up by the vertex shader.
+            encoder.draw(0..3, 0..1);
+        }
+    }
+
+    fn dispose(self, _factory: &mut Factory<B>, _res: &Resources) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_scale_stays_between_a_half_and_one() {
+        assert_eq!(RenderScale(0.75).clamped(), 0.75);
+        assert_eq!(RenderScale(0.1).clamped(), MIN_RENDER_SCALE);
+        assert_eq!(RenderScale(-2.).clamped(), MIN_RENDER_SCALE);
+        assert_eq!(RenderScale(1.5).clamped(), 1.);
+        assert_eq!(RenderScale(f32::NAN).clamped(), 1.);
+    }
+
+    #[test]
+    fn scene_sizes_round_to_whole_pixels() {
+        assert_eq!(RenderScale(1.).scene_size(1280, 720), (1280, 720));
+        assert_eq!(RenderScale(0.7).scene_size(1280, 720), (896, 504));
+        // 0.85 of 1001 by 333 is 850.85 by 283.05.
+        assert_eq!(RenderScale(0.85).scene_size(1001, 333), (851, 283));
+        assert_eq!(RenderScale(0.2).scene_size(1280, 720), (640, 360));
+        // A tiny window still gets a pixel.
+        assert_eq!(RenderScale(0.5).scene_size(1, 1), (1, 1));
+    }
+}
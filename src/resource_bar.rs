@@ -0,0 +1,243 @@
+//! Bars on screen showing a bounded value, such as health, of some entity.
+//!
+//! A bar reads its value from a component of its source entity through an accessor, so one
+//! `ResourceBarSystem` per component type serves every bar drawn from that component.
+
+use std::marker::PhantomData;
+
+use amethyst::{
+    core::math::Vector2,
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entity, Join, ReadStorage, System, World, WriteStorage,
+    },
+    ui::{Anchor, UiImage, UiTransform},
+};
+
+use crate::ui_theme::UiTheme;
+
+/// Which way a bar fills up as its value grows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FillDirection {
+    #[default]
+    LeftToRight,
+    RightToLeft,
+    BottomToTop,
+    TopToBottom,
+}
+
+impl FillDirection {
+    /// The same direction flipped end to end, for a bar on the opposite side of the screen.
+    pub fn mirrored(self) -> Self {
+        match self {
+            FillDirection::LeftToRight => FillDirection::RightToLeft,
+            FillDirection::RightToLeft => FillDirection::LeftToRight,
+            FillDirection::BottomToTop => FillDirection::TopToBottom,
+            FillDirection::TopToBottom => FillDirection::BottomToTop,
+        }
+    }
+}
+
+/// How full a bar showing `current` out of `max` is, from `0` to `1`.
+pub fn fill_fraction(current: f32, max: f32) -> f32 {
+    if max > 0. {
+        (current / max).clamp(0., 1.)
+    } else {
+        0.
+    }
+}
+
+/// The filled part of a bar of `size` at `fraction`, as its bottom-left corner relative to the
+/// bar's and its size.
+pub fn fill_rect(
+    size: Vector2<f32>,
+    fraction: f32,
+    direction: FillDirection,
+) -> (Vector2<f32>, Vector2<f32>) {
+    let (along_x, along_y) = (size.x * fraction, size.y * fraction);
+    match direction {
+        FillDirection::LeftToRight => (Vector2::zeros(), Vector2::new(along_x, size.y)),
+        FillDirection::RightToLeft => (
+            Vector2::new(size.x - along_x, 0.),
+            Vector2::new(along_x, size.y),
+        ),
+        FillDirection::BottomToTop => (Vector2::zeros(), Vector2::new(size.x, along_y)),
+        FillDirection::TopToBottom => (
+            Vector2::new(0., size.y - along_y),
+            Vector2::new(size.x, along_y),
+        ),
+    }
+}
+
+/// The filled part of a bar showing the `T` of `source`.
+pub struct ResourceBar<T> {
+    pub source: Entity,
+    /// Reads the current and maximum value from the source's `T`.
+    pub value: fn(&T) -> (f32, f32),
+    pub direction: FillDirection,
+    /// The bar's bottom-left corner relative to its anchor, and its size, in pixels.
+    position: Vector2<f32>,
+    size: Vector2<f32>,
+}
+
+impl<T: Component> Component for ResourceBar<T> {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Creates a `ResourceBar` and the UI drawing it.
+pub struct ResourceBarBuilder<T> {
+    source: Entity,
+    value: fn(&T) -> (f32, f32),
+    anchor: Anchor,
+    position: Vector2<f32>,
+    size: Vector2<f32>,
+    direction: FillDirection,
+    /// The background and fill colours, or the `UiTheme`'s bar colours if not given.
+    colors: Option<([f32; 4], [f32; 4])>,
+}
+
+impl<T: Component> ResourceBarBuilder<T> {
+    /// A bar showing `value` of `source`'s `T`, 160 by 12 pixels in the bottom-left corner of
+    /// the window, in the theme's bar colours.
+    pub fn new(source: Entity, value: fn(&T) -> (f32, f32)) -> Self {
+        ResourceBarBuilder {
+            source,
+            value,
+            anchor: Anchor::BottomLeft,
+            position: Vector2::new(8., 8.),
+            size: Vector2::new(160., 12.),
+            direction: FillDirection::default(),
+            colors: None,
+        }
+    }
+
+    /// Places the bar's bottom-left corner at `position` pixels from `anchor`.
+    pub fn with_position(mut self, anchor: Anchor, position: Vector2<f32>) -> Self {
+        self.anchor = anchor;
+        self.position = position;
+        self
+    }
+
+    pub fn with_size(mut self, size: Vector2<f32>) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_direction(mut self, direction: FillDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_colors(mut self, background: [f32; 4], fill: [f32; 4]) -> Self {
+        self.colors = Some((background, fill));
+        self
+    }
+
+    /// Creates the bar's background and fill. Returns the fill, which holds the `ResourceBar`.
+    pub fn build(self, world: &mut World) -> Entity {
+        let (background, fill) = self.colors.unwrap_or_else(|| {
+            let theme = world.read_resource::<UiTheme>();
+            (theme.bar_background, theme.bar_fill)
+        });
+        let transform = |id: &str, z: f32| {
+            UiTransform::new(
+                id.to_string(),
+                self.anchor.clone(),
+                Anchor::BottomLeft,
+                self.position.x,
+                self.position.y,
+                z,
+                self.size.x,
+                self.size.y,
+            )
+        };
+        world
+            .create_entity()
+            .with(transform("resource_bar_background", 1.))
+            .with(UiImage::SolidColor(background))
+            .build();
+        world
+            .create_entity()
+            .with(transform("resource_bar_fill", 2.))
+            .with(UiImage::SolidColor(fill))
+            .with(ResourceBar {
+                source: self.source,
+                value: self.value,
+                direction: self.direction,
+                position: self.position,
+                size: self.size,
+            })
+            .build()
+    }
+}
+
+/// Resizes the fill of every bar reading a `T`. A bar whose source no longer has a `T` shows
+/// empty.
+pub struct ResourceBarSystem<T> {
+    marker: PhantomData<T>,
+}
+
+impl<T> Default for ResourceBarSystem<T> {
+    fn default() -> Self {
+        ResourceBarSystem {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'s, T: Component> System<'s> for ResourceBarSystem<T> {
+    type SystemData = (
+        ReadStorage<'s, T>,
+        ReadStorage<'s, ResourceBar<T>>,
+        WriteStorage<'s, UiTransform>,
+    );
+
+    fn run(&mut self, (sources, bars, mut transforms): Self::SystemData) {
+        for (bar, transform) in (&bars, &mut transforms).join() {
+            let fraction = sources.get(bar.source).map_or(0., |source| {
+                let (current, max) = (bar.value)(source);
+                fill_fraction(current, max)
+            });
+            let (offset, size) = fill_rect(bar.size, fraction, bar.direction);
+            transform.local_x = bar.position.x + offset.x;
+            transform.local_y = bar.position.y + offset.y;
+            transform.width = size.x;
+            transform.height = size.y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_runs_from_empty_to_full_and_stops_there() {
+        assert_eq!(fill_fraction(0., 10.), 0.);
+        assert_eq!(fill_fraction(2.5, 10.), 0.25);
+        assert_eq!(fill_fraction(10., 10.), 1.);
+        assert_eq!(fill_fraction(15., 10.), 1.);
+        assert_eq!(fill_fraction(-3., 10.), 0.);
+        assert_eq!(fill_fraction(5., 0.), 0.);
+    }
+
+    #[test]
+    fn the_filled_part_starts_from_the_directions_edge() {
+        let size = Vector2::new(100., 20.);
+        assert_eq!(
+            fill_rect(size, 0.25, FillDirection::LeftToRight),
+            (Vector2::zeros(), Vector2::new(25., 20.))
+        );
+        assert_eq!(
+            fill_rect(size, 0.25, FillDirection::RightToLeft),
+            (Vector2::new(75., 0.), Vector2::new(25., 20.))
+        );
+        assert_eq!(
+            fill_rect(size, 0.5, FillDirection::TopToBottom),
+            (Vector2::new(0., 10.), Vector2::new(100., 10.))
+        );
+        assert_eq!(
+            fill_rect(size, 1., FillDirection::BottomToTop),
+            (Vector2::zeros(), size)
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Rewinding the game a little, for debugging.
+//!
+//! After every gameplay step the `RewindRecordSystem` snapshots the same state the world hash
+//! covers: the position, velocity and health of everything that moves or can be hurt, and the
+//! gameplay `Rng`. The `rewind` action puts that state back as it was `rewind_seconds` ago.
+//! Entities spawned since are left as they are, and those deleted since can't come back.
+
+use std::collections::VecDeque;
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Entities, Entity, Join, Read, ReadExpect, ReadStorage, System, Write, WriteExpect,
+        WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::GameClock, combat::Health, movement::Velocity, rng::Rng};
+
+/// How much gameplay is kept to rewind to, read from `rewind.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RewindConfig {
+    pub enabled: bool,
+    /// Seconds of gameplay kept.
+    pub buffer_seconds: f32,
+    /// Most steps kept, however short they are, to bound the memory the buffer takes.
+    pub max_snapshots: usize,
+    /// Seconds one press of `rewind` goes back.
+    pub rewind_seconds: f32,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        RewindConfig {
+            enabled: true,
+            buffer_seconds: 5.,
+            max_snapshots: 600,
+            rewind_seconds: 1.,
+        }
+    }
+}
+
+/// One entity's part of a `Snapshot`.
+#[derive(Clone, Copy, Debug)]
+pub struct EntityState {
+    pub entity: Entity,
+    pub position: Vector2<f32>,
+    pub velocity: Option<Vector2<f32>>,
+    pub health: Option<Health>,
+}
+
+/// The gameplay state after one step.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub tick: u64,
+    /// Game time of the step, in seconds.
+    pub elapsed: f64,
+    pub entities: Vec<EntityState>,
+    pub rng: Rng,
+}
+
+/// The most recent snapshots, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct RewindBuffer {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl RewindBuffer {
+    /// Moves the entities of every snapshot by `offset`, so rewinding puts them back where
+    /// they were with the world moved.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        for snapshot in &mut self.snapshots {
+            for state in &mut snapshot.entities {
+                state.position += offset;
+            }
+        }
+    }
+
+    /// Keeps `snapshot`, dropping those that have fallen out of what `config` keeps.
+    pub fn record(&mut self, snapshot: Snapshot, config: &RewindConfig) {
+        let oldest = snapshot.elapsed - f64::from(config.buffer_seconds);
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > config.max_snapshots.max(1)
+            || self
+                .snapshots
+                .front()
+                .is_some_and(|snapshot| snapshot.elapsed < oldest)
+        {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Takes the latest snapshot at least `seconds` older than the newest, or the oldest kept
+    /// if none go that far back, dropping it and everything after it.
+    pub fn rewind(&mut self, seconds: f32) -> Option<Snapshot> {
+        let newest = self.snapshots.back()?.elapsed;
+        let target = newest - f64::from(seconds);
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.elapsed <= target)
+            .unwrap_or(0);
+        self.snapshots.truncate(index + 1);
+        self.snapshots.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+}
+
+/// Snapshots the gameplay state into the `RewindBuffer`. Runs last among the gameplay systems.
+pub struct RewindRecordSystem;
+
+impl<'s> System<'s> for RewindRecordSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Velocity>,
+        ReadStorage<'s, Health>,
+        ReadExpect<'s, Rng>,
+        Read<'s, GameClock>,
+        Read<'s, RewindConfig>,
+        Write<'s, RewindBuffer>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, transforms, velocities, healths, rng, clock, config, mut buffer): Self::SystemData,
+    ) {
+        if !config.enabled {
+            return;
+        }
+        let states = (&entities, &transforms, velocities.maybe(), healths.maybe())
+            .join()
+            .filter(|(_, _, velocity, health)| velocity.is_some() || health.is_some())
+            .map(|(entity, transform, velocity, health)| {
+                let translation = transform.translation();
+                EntityState {
+                    entity,
+                    position: Vector2::new(translation.x.as_f32(), translation.y.as_f32()),
+                    velocity: velocity.map(|velocity| velocity.0),
+                    health: health.copied(),
+                }
+            })
+            .collect();
+        buffer.record(
+            Snapshot {
+                tick: clock.ticks(),
+                elapsed: clock.elapsed_seconds(),
+                entities: states,
+                rng: *rng,
+            },
+            &config,
+        );
+    }
+}
+
+/// Puts the gameplay state back `rewind_seconds` when the `rewind` action is pressed.
+#[derive(Default)]
+pub struct RewindSystem {
+    was_pressed: bool,
+}
+
+impl<'s> System<'s> for RewindSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Velocity>,
+        WriteStorage<'s, Health>,
+        Option<WriteExpect<'s, Rng>>,
+        Write<'s, RewindBuffer>,
+        Read<'s, RewindConfig>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut transforms,
+            mut velocities,
+            mut healths,
+            rng,
+            mut buffer,
+            config,
+            input,
+        ): Self::SystemData,
+    ) {
+        let pressed = input.action_is_down("rewind").unwrap_or(false);
+        let rewind = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+        if !rewind || !config.enabled {
+            return;
+        }
+        let (snapshot, mut rng) = match (buffer.rewind(config.rewind_seconds), rng) {
+            (Some(snapshot), Some(rng)) => (snapshot, rng),
+            _ => return,
+        };
+        for state in &snapshot.entities {
+            if !entities.is_alive(state.entity) {
+                continue;
+            }
+            if let Some(transform) = transforms.get_mut(state.entity) {
+                transform.set_translation_x(state.position.x);
+                transform.set_translation_y(state.position.y);
+            }
+            if let (Some(velocity), Some(saved)) =
+                (velocities.get_mut(state.entity), state.velocity)
+            {
+                velocity.0 = saved;
+            }
+            if let (Some(health), Some(saved)) = (healths.get_mut(state.entity), state.health) {
+                *health = saved;
+            }
+        }
+        *rng = snapshot.rng;
+        info!(
+            "Rewound to tick {}, {} steps left to rewind",
+            snapshot.tick,
+            buffer.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        ecs::prelude::{Builder, RunNow, SystemData, World},
+        winit::VirtualKeyCode,
+    };
+
+    use super::*;
+    use crate::player::tests::{bind, press_key};
+
+    fn snapshot(tick: u64) -> Snapshot {
+        Snapshot {
+            tick,
+            elapsed: tick as f64 * 0.1,
+            entities: Vec::new(),
+            rng: Rng::new(0),
+        }
+    }
+
+    #[test]
+    fn the_buffer_keeps_at_most_max_snapshots() {
+        let config = RewindConfig {
+            buffer_seconds: 100.,
+            max_snapshots: 8,
+            ..RewindConfig::default()
+        };
+        let mut buffer = RewindBuffer::default();
+        for tick in 0..20 {
+            buffer.record(snapshot(tick), &config);
+        }
+        assert_eq!(buffer.len(), 8);
+        assert_eq!(
+            buffer.snapshots.front().map(|snapshot| snapshot.tick),
+            Some(12)
+        );
+
+        // Snapshots older than `buffer_seconds` go too, however few there are.
+        let short = RewindConfig {
+            buffer_seconds: 0.35,
+            ..config
+        };
+        let mut buffer = RewindBuffer::default();
+        for tick in 0..20 {
+            buffer.record(snapshot(tick), &short);
+        }
+        assert_eq!(buffer.len(), 4);
+    }
+
+    #[test]
+    fn rewinding_restores_the_state_from_rewind_seconds_ago() {
+        let mut world = World::new();
+        let mut recording = RewindRecordSystem;
+        let mut rewinding = RewindSystem::default();
+        world.add_resource(Rng::new(7));
+        <RewindRecordSystem as System<'_>>::SystemData::setup(&mut world.res);
+        <RewindSystem as System<'_>>::SystemData::setup(&mut world.res);
+        world.add_resource(RewindConfig {
+            rewind_seconds: 1.,
+            ..RewindConfig::default()
+        });
+        bind(&mut world, r#"(axes: {}, actions: {"rewind": [[Key(R)]]})"#);
+        let walker = world
+            .create_entity()
+            .with(Transform::default())
+            .with(Velocity(Vector2::new(40., 0.)))
+            .with(Health::new(100.))
+            .build();
+
+        // Ten quarter second steps, each walking 10 units and taking 5 health.
+        let mut rng_after = Vec::new();
+        for _ in 0..10 {
+            world.write_resource::<GameClock>().advance(0.25);
+            world
+                .write_storage::<Transform>()
+                .get_mut(walker)
+                .unwrap()
+                .prepend_translation_x(10.);
+            world
+                .write_storage::<Health>()
+                .get_mut(walker)
+                .unwrap()
+                .current -= 5.;
+            world.write_resource::<Rng>().next_u64();
+            recording.run_now(&world.res);
+            rng_after.push(world.read_resource::<Rng>().state());
+        }
+
+        press_key(&mut world, VirtualKeyCode::R, true);
+        rewinding.run_now(&world.res);
+
+        // The newest snapshot is step 10, so a second back is step 6.
+        let x = world
+            .read_storage::<Transform>()
+            .get(walker)
+            .unwrap()
+            .translation()
+            .x;
+        assert_eq!(x.as_f32(), 60.);
+        assert_eq!(
+            world.read_storage::<Health>().get(walker).unwrap().current,
+            70.
+        );
+        assert_eq!(world.read_resource::<Rng>().state(), rng_after[5]);
+        assert_eq!(world.read_resource::<RewindBuffer>().len(), 5);
+    }
+}
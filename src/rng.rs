@@ -0,0 +1,85 @@
+/// A small, fast xorshift random number generator.
+///
+/// The sequence depends only on the seed, so anything driven by it replays identically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift gets stuck on zero, so nudge a zero seed onto a fixed odd constant.
+        Rng {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    /// A generator of its own for `stream`, such as one particle emitter, drawn from `seed`.
+    /// Each stream's sequence depends only on the seed and the stream, however many others
+    /// are drawn from and in whatever order.
+    pub fn stream(seed: u64, stream: u64) -> Self {
+        // Splitmix64's finaliser, so neighbouring streams start far apart.
+        let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Rng::new(z ^ (z >> 31))
+    }
+
+    /// The generator's internal state, enough to resume the sequence from here.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        // The top 24 bits fill an f32 mantissa exactly.
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// A number in `[min, max)`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take(mut rng: Rng, count: usize) -> Vec<u64> {
+        (0..count).map(|_| rng.next_u64()).collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_sequence() {
+        assert_eq!(take(Rng::new(7), 5), take(Rng::new(7), 5));
+        assert_ne!(take(Rng::new(7), 5), take(Rng::new(8), 5));
+        assert!(take(Rng::new(0), 5).iter().all(|&value| value != 0));
+    }
+
+    #[test]
+    fn streams_depend_only_on_the_seed_and_stream() {
+        assert_eq!(take(Rng::stream(7, 2), 5), take(Rng::stream(7, 2), 5));
+        assert_ne!(take(Rng::stream(7, 1), 5), take(Rng::stream(7, 2), 5));
+    }
+
+    #[test]
+    fn ranges_stay_within_their_bounds() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let value = rng.range(-2., 3.);
+            assert!((-2. ..3.).contains(&value));
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Gamepad rumble on hits and explosions.
+//!
+//! Gameplay sends `RumbleEvent`s, which the `RumbleSystem` turns into the effect `rumble.ron`
+//! sets for their kind and hands to the `Rumbler`'s device. The input backend has no way to
+//! rumble a gamepad, so the device is `NoRumble` unless one that can is put in its place; a
+//! gamepad that turns out not to rumble is left alone from then on.
+
+use std::collections::HashSet;
+
+use amethyst::{
+    ecs::prelude::{Read, Resources, System, SystemData, Write},
+    shrev::{EventChannel, ReaderId},
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::{CollisionCallbacks, CollisionFilter},
+    enemy::Enemy,
+    player::Player,
+};
+
+/// What set off a rumble.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RumbleKind {
+    /// A player was hurt.
+    Hit,
+    /// Something burst apart.
+    Explosion,
+    /// A player ran into an enemy.
+    Bump,
+}
+
+/// Asks for the gamepad of `player`, or every gamepad without one, to rumble for `kind`.
+#[derive(Clone, Copy, Debug)]
+pub struct RumbleEvent {
+    pub kind: RumbleKind,
+    pub player: Option<usize>,
+}
+
+/// How hard and how long a gamepad rumbles.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RumbleEffect {
+    /// From `0` for none to `1` for the strongest the gamepad can.
+    pub intensity: f32,
+    /// Seconds.
+    pub duration: f32,
+}
+
+/// The rumble of each kind of event, read from `rumble.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RumbleConfig {
+    /// Whether gamepads rumble at all, switched in the options menu.
+    pub enabled: bool,
+    pub hit: RumbleEffect,
+    pub explosion: RumbleEffect,
+    pub bump: RumbleEffect,
+}
+
+impl Default for RumbleConfig {
+    fn default() -> Self {
+        RumbleConfig {
+            enabled: true,
+            hit: RumbleEffect {
+                intensity: 0.5,
+                duration: 0.15,
+            },
+            explosion: RumbleEffect {
+                intensity: 0.8,
+                duration: 0.3,
+            },
+            bump: RumbleEffect {
+                intensity: 0.25,
+                duration: 0.1,
+            },
+        }
+    }
+}
+
+impl RumbleConfig {
+    /// The rumble for `kind`, or `None` with rumble off or nothing to feel.
+    pub fn effect(&self, kind: RumbleKind) -> Option<RumbleEffect> {
+        if !self.enabled {
+            return None;
+        }
+        let effect = match kind {
+            RumbleKind::Hit => self.hit,
+            RumbleKind::Explosion => self.explosion,
+            RumbleKind::Bump => self.bump,
+        };
+        let effect = RumbleEffect {
+            intensity: effect.intensity.clamp(0., 1.),
+            duration: effect.duration.max(0.),
+        };
+        if effect.intensity > 0. && effect.duration > 0. {
+            Some(effect)
+        } else {
+            None
+        }
+    }
+}
+
+/// Registers the rumble of a player running into an enemy, felt as they first touch.
+pub fn register_bump_rumble(callbacks: &mut CollisionCallbacks) {
+    callbacks.register(
+        CollisionFilter::with::<Player>(),
+        CollisionFilter::with::<Enemy>(),
+        |world, collision| {
+            if !collision.started {
+                return;
+            }
+            let player = world
+                .read_storage::<Player>()
+                .get(collision.first)
+                .map(|player| player.index);
+            world
+                .write_resource::<EventChannel<RumbleEvent>>()
+                .single_write(RumbleEvent {
+                    kind: RumbleKind::Bump,
+                    player,
+                });
+        },
+    );
+}
+
+/// Something that can rumble the players' gamepads.
+pub trait RumbleDevice: Send + Sync {
+    /// Rumbles the gamepad of `player`, or every gamepad without one. Returns whether it could.
+    fn rumble(&mut self, player: Option<usize>, effect: RumbleEffect) -> bool;
+}
+
+/// A device for gamepads that can't rumble.
+pub struct NoRumble;
+
+impl RumbleDevice for NoRumble {
+    fn rumble(&mut self, _: Option<usize>, _: RumbleEffect) -> bool {
+        false
+    }
+}
+
+/// The device rumbles are played on.
+pub struct Rumbler(pub Box<dyn RumbleDevice>);
+
+impl Default for Rumbler {
+    fn default() -> Self {
+        Rumbler(Box::new(NoRumble))
+    }
+}
+
+/// Plays `RumbleEvent`s on the `Rumbler` as `RumbleConfig` says.
+#[derive(Default)]
+pub struct RumbleSystem {
+    reader: Option<ReaderId<RumbleEvent>>,
+    /// Gamepads known not to rumble, with `None` for all of them at once.
+    unsupported: HashSet<Option<usize>>,
+}
+
+impl<'s> System<'s> for RumbleSystem {
+    type SystemData = (
+        Read<'s, EventChannel<RumbleEvent>>,
+        Read<'s, RumbleConfig>,
+        Write<'s, Rumbler>,
+    );
+
+    fn run(&mut self, (events, config, mut rumbler): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("RumbleSystem is set up");
+        for event in events.read(reader) {
+            let effect = match config.effect(event.kind) {
+                Some(effect) => effect,
+                None => continue,
+            };
+            if self.unsupported.contains(&event.player) {
+                continue;
+            }
+            if !rumbler.0.rumble(event.player, effect) {
+                debug!("Gamepad {:?} can't rumble, leaving it be", event.player);
+                self.unsupported.insert(event.player);
+            }
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<RumbleEvent>>()
+                .register_reader(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use amethyst::ecs::prelude::{RunNow, World};
+
+    use super::*;
+
+    type Sent = Arc<Mutex<Vec<(Option<usize>, RumbleEffect)>>>;
+
+    /// A device that keeps every rumble it is asked for.
+    struct Recording {
+        sent: Sent,
+        supported: bool,
+    }
+
+    impl RumbleDevice for Recording {
+        fn rumble(&mut self, player: Option<usize>, effect: RumbleEffect) -> bool {
+            self.sent.lock().unwrap().push((player, effect));
+            self.supported
+        }
+    }
+
+    fn world(config: RumbleConfig, supported: bool) -> (World, RumbleSystem, Sent) {
+        let mut world = World::new();
+        let mut system = RumbleSystem::default();
+        System::setup(&mut system, &mut world.res);
+        let sent = Sent::default();
+        world.add_resource(config);
+        world.add_resource(Rumbler(Box::new(Recording {
+            sent: sent.clone(),
+            supported,
+        })));
+        (world, system, sent)
+    }
+
+    fn send(world: &mut World, kind: RumbleKind, player: Option<usize>) {
+        world
+            .write_resource::<EventChannel<RumbleEvent>>()
+            .single_write(RumbleEvent { kind, player });
+    }
+
+    #[test]
+    fn each_kind_rumbles_as_configured() {
+        let config = RumbleConfig::default();
+        let (mut world, mut system, sent) = world(config, true);
+        send(&mut world, RumbleKind::Hit, Some(0));
+        send(&mut world, RumbleKind::Explosion, None);
+        send(&mut world, RumbleKind::Bump, Some(1));
+        system.run_now(&world.res);
+
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![
+                (Some(0), config.hit),
+                (None, config.explosion),
+                (Some(1), config.bump),
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_is_sent_with_rumble_off() {
+        let config = RumbleConfig {
+            enabled: false,
+            ..RumbleConfig::default()
+        };
+        let (mut world, mut system, sent) = world(config, true);
+        send(&mut world, RumbleKind::Hit, Some(0));
+        send(&mut world, RumbleKind::Explosion, None);
+        system.run_now(&world.res);
+        assert!(sent.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_gamepad_that_cant_rumble_is_asked_once() {
+        let (mut world, mut system, sent) = world(RumbleConfig::default(), false);
+        for _ in 0..3 {
+            send(&mut world, RumbleKind::Hit, Some(0));
+            system.run_now(&world.res);
+        }
+        assert_eq!(sent.lock().unwrap().len(), 1);
+    }
+}
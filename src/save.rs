@@ -0,0 +1,930 @@
+//! Saved games, kept in numbered slots under `resources/saves`.
+//!
+//! Each slot is three files: the game itself in `slot_N.ron`, what the slot menu shows about it
+//! in `slot_N.meta.ron`, and a thumbnail of the level in `slot_N.png`. The thumbnail is drawn
+//! from the tiles' sprites as they were when the game was saved, as map pictures are but at
+//! `thumbnail_scale` pixels a tile, with the players marked in white.
+//!
+//! The game file starts with a comment line giving the version of the save format and a
+//! checksum of that version and the rest of the file. A game whose checksum doesn't match, or
+//! that starts with a comment that isn't such a line, is corrupt, and is rejected as a whole
+//! rather than loaded as far as it reads. Files from before checksums were kept, version 0,
+//! start straight away with the game and load unchecked. Saving over a slot keeps what was in
+//! it as `slot_N.bak.ron`, `slot_N.bak.meta.ron` and `slot_N.bak.png`, so a slot that has since
+//! been corrupted can still be loaded as it was saved the time before, once the slot menu has
+//! offered it.
+//!
+//! A save keeps the level as it was edited, the entities tagged with a `SaveTag`, how long the
+//! game had been played and the flags the players' dialogue answers have set. Only the players are tagged: enemies, effects and the like are
+//! left out, and the level's enemies start over when it is loaded. Of the tagged entities only
+//! the components `saves.ron` lists are kept.
+//!
+//! `UnsavedProgress` tracks whether the game has changed since it was last saved or loaded,
+//! from what happens in play: a tagged entity ending a step somewhere else on the map, anything
+//! being hurt, the level being edited or a dialogue answer setting flags. Walking into a wall
+//! or the world being moved back to its origin changes nothing a save keeps, so doesn't count. Quitting a game with unsaved
+//! progress asks first whether to save it, unless `saves.ron` says not to.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use failure::format_err;
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, Entities, Entity, Join, NullStorage, Read, ReadStorage, Resources, System,
+        SystemData, World, Write,
+    },
+    shrev::{EventChannel, ReaderId},
+};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::Playtime,
+    combat::{DamageTaken, Health},
+    dialogue::DialogueFlags,
+    difficulty::{Difficulty, DifficultyLevel},
+    map_export::{render_map, TileAtlas},
+    movement::world_position,
+    navigation::NavGrid,
+    player::Player,
+    tile_map::{TileEdit, TileMap},
+    world_hash::StateHasher,
+};
+
+/// The version of the save format written now, which the checksum covers with the game.
+pub const SAVE_VERSION: u32 = 1;
+
+/// The version of game files from before checksums were kept, which have no checksum line.
+const UNCHECKED_VERSION: u32 = 0;
+
+/// How the line holding a game file's version and checksum starts.
+const CHECKSUM_PREFIX: &str = "// checksum: v";
+
+/// The components of tagged entities that saves keep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SavedComponent {
+    /// Which player the entity is, without which players start over.
+    Player,
+    /// Where the entity stands.
+    Position,
+    /// How hurt the entity is.
+    Health,
+}
+
+/// How many slots there are, how big their thumbnails are and what saves keep, read from
+/// `saves.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SaveConfig {
+    pub slots: usize,
+    /// Width and height in pixels of each tile in the thumbnails.
+    pub thumbnail_scale: u32,
+    pub components: Vec<SavedComponent>,
+    /// Whether quitting a game with unsaved progress asks to save it first.
+    pub prompt_on_quit: bool,
+    /// Whether games are checked against their checksum when read. Without it, a corrupt game
+    /// is only found out when it fails to parse.
+    pub verify_checksums: bool,
+    /// Whether saving over a slot keeps what was in it as the slot's backup.
+    pub keep_backups: bool,
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        SaveConfig {
+            slots: 3,
+            thumbnail_scale: 4,
+            components: vec![
+                SavedComponent::Player,
+                SavedComponent::Position,
+                SavedComponent::Health,
+            ],
+            prompt_on_quit: true,
+            verify_checksums: true,
+            keep_backups: true,
+        }
+    }
+}
+
+/// Marks an entity to be kept in saves.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SaveTag;
+
+impl Component for SaveTag {
+    type Storage = NullStorage<Self>;
+}
+
+/// A tagged entity as it was saved, with only the components that were kept.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SavedEntity {
+    /// The index of the player the entity is.
+    pub player: Option<usize>,
+    pub position: Option<(f32, f32)>,
+    pub health: Option<f32>,
+}
+
+/// A player as saves from before entities were tagged kept them.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct SavedPlayer {
+    pub index: usize,
+    pub position: (f32, f32),
+    pub health: f32,
+}
+
+/// Everything needed to pick a game up where it was saved.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SaveGame {
+    /// Seed the loaded game's `Rng` starts from.
+    pub seed: u64,
+    /// The level, with any edits made to it.
+    pub map: TileMap,
+    /// The entities tagged to be saved.
+    #[serde(default)]
+    pub entities: Vec<SavedEntity>,
+    /// The players of saves from before entities were tagged, which are no longer written.
+    #[serde(default, skip_serializing)]
+    pub players: Vec<SavedPlayer>,
+    /// Seconds the game had been played, as in `Playtime`. Saves from before playtime was kept
+    /// start from nothing.
+    #[serde(default)]
+    pub playtime: f64,
+    /// The level the game is played at. Saves from before there was a choice were played at
+    /// `Normal`.
+    #[serde(default)]
+    pub difficulty: DifficultyLevel,
+    /// The flags set by dialogue answers. Saves from before they were kept have none set.
+    #[serde(default)]
+    pub dialogue_flags: DialogueFlags,
+}
+
+impl SaveGame {
+    /// The game being played in `world`, started from `seed`, keeping the `components` of
+    /// every entity with a `SaveTag`.
+    pub fn capture(world: &World, seed: u64, components: &[SavedComponent]) -> Self {
+        let keeps = |component| components.contains(&component);
+        // Saved games start over with the map at the world's origin, wherever it had got to.
+        let mut map = world.read_resource::<TileMap>().clone();
+        let origin = std::mem::take(&mut map.origin);
+        let (players, transforms, healths) = (
+            world.read_storage::<Player>(),
+            world.read_storage::<Transform>(),
+            world.read_storage::<Health>(),
+        );
+        let entities = (&world.read_storage::<SaveTag>(), &world.entities())
+            .join()
+            .map(|(_, entity)| SavedEntity {
+                player: players
+                    .get(entity)
+                    .filter(|_| keeps(SavedComponent::Player))
+                    .map(|player| player.index),
+                position: transforms
+                    .get(entity)
+                    .filter(|_| keeps(SavedComponent::Position))
+                    .map(|transform| {
+                        let position = world_position(transform);
+                        (position.x - origin.0, position.y - origin.1)
+                    }),
+                health: healths
+                    .get(entity)
+                    .filter(|_| keeps(SavedComponent::Health))
+                    .map(|health| health.current),
+            })
+            .collect();
+        SaveGame {
+            seed,
+            map,
+            entities,
+            players: Vec::new(),
+            playtime: world.read_resource::<Playtime>().seconds(),
+            difficulty: world.read_resource::<Difficulty>().level,
+            dialogue_flags: world.read_resource::<DialogueFlags>().clone(),
+        }
+    }
+
+    /// The saved entity of the player at `index`, from a save of either kind.
+    pub fn player(&self, index: usize) -> Option<SavedEntity> {
+        self.entities
+            .iter()
+            .find(|entity| entity.player == Some(index))
+            .copied()
+            .or_else(|| {
+                self.players
+                    .iter()
+                    .find(|player| player.index == index)
+                    .map(|player| SavedEntity {
+                        player: Some(player.index),
+                        position: Some(player.position),
+                        health: Some(player.health),
+                    })
+            })
+    }
+}
+
+/// What the slot menu shows about a saved game.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SlotMetadata {
+    /// Name of the level the game was saved in.
+    pub level: String,
+    /// Width and height of the level in tiles, which the thumbnail is drawn at.
+    pub map_size: (usize, usize),
+    /// Seconds the game had been played.
+    pub playtime: f64,
+    /// When the game was saved, in seconds since the Unix epoch.
+    pub saved_at: u64,
+}
+
+impl SlotMetadata {
+    /// Describes `game`, in `level`, saved just now.
+    pub fn now(game: &SaveGame, level: &str) -> Self {
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        SlotMetadata {
+            level: level.to_string(),
+            map_size: (game.map.width, game.map.height),
+            playtime: game.playtime,
+            saved_at,
+        }
+    }
+
+    /// How long the game had been played, as `H:MM:SS`.
+    pub fn playtime_text(&self) -> String {
+        let seconds = self.playtime.max(0.) as u64;
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    }
+
+    /// When the game was saved, as `YYYY-MM-DD HH:MM` in UTC.
+    pub fn timestamp(&self) -> String {
+        let (days, seconds) = (self.saved_at / 86_400, self.saved_at % 86_400);
+        let (year, month, day) = civil_date(days as i64);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds % 3600 / 60
+        )
+    }
+}
+
+/// The year, month and day `days` after 1970-01-01, by Howard Hinnant's `civil_from_days`.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// What is in a slot.
+#[derive(Clone, Debug)]
+pub enum SlotStatus {
+    Empty,
+    /// The slot's files are there but can't be read, with what its backup holds if it has one
+    /// that can.
+    Corrupt(Option<SlotMetadata>),
+    Saved(SlotMetadata),
+}
+
+/// The save slots, read and written in `dir`.
+#[derive(Clone, Debug, Default)]
+pub struct SaveSlots {
+    dir: PathBuf,
+    config: SaveConfig,
+}
+
+impl SaveSlots {
+    pub fn new(dir: PathBuf, config: SaveConfig) -> Self {
+        SaveSlots { dir, config }
+    }
+
+    pub fn count(&self) -> usize {
+        self.config.slots
+    }
+
+    /// Whether quitting with `progress` asks to save first.
+    pub fn prompts_on_quit(&self, progress: &UnsavedProgress) -> bool {
+        self.config.prompt_on_quit && progress.is_dirty()
+    }
+
+    /// The slot to save `progress` to without asking: the one it was last saved to or loaded
+    /// from, or else the first empty one.
+    pub fn quick_save_slot(&self, progress: &UnsavedProgress) -> Option<usize> {
+        progress
+            .slot()
+            .filter(|&slot| slot < self.count())
+            .or_else(|| {
+                self.list()
+                    .iter()
+                    .position(|status| matches!(status, SlotStatus::Empty))
+            })
+    }
+
+    /// The components of tagged entities that saves keep.
+    pub fn saved_components(&self) -> &[SavedComponent] {
+        &self.config.components
+    }
+
+    fn path(&self, slot: usize, extension: &str) -> PathBuf {
+        self.dir.join(format!("slot_{}.{}", slot + 1, extension))
+    }
+
+    /// The game and metadata files of `slot`, or of its backup.
+    fn files(&self, slot: usize, backup: bool) -> (PathBuf, PathBuf) {
+        if backup {
+            (self.path(slot, "bak.ron"), self.path(slot, "bak.meta.ron"))
+        } else {
+            (self.path(slot, "ron"), self.path(slot, "meta.ron"))
+        }
+    }
+
+    /// The thumbnail of `slot`, or of its backup, relative to the `resources` directory assets
+    /// load from.
+    pub fn thumbnail_asset(&self, slot: usize, backup: bool) -> String {
+        let extension = if backup { "bak.png" } else { "png" };
+        format!("saves/slot_{}.{}", slot + 1, extension)
+    }
+
+    /// What is in each slot. A slot is corrupt if its metadata or its game can't be read, its
+    /// game fails its checksum, or only one of them is there.
+    pub fn list(&self) -> Vec<SlotStatus> {
+        (0..self.count()).map(|slot| self.status(slot)).collect()
+    }
+
+    fn status(&self, slot: usize) -> SlotStatus {
+        match self.readable(slot, false) {
+            Readable::Empty => SlotStatus::Empty,
+            Readable::Saved(metadata) => SlotStatus::Saved(metadata),
+            Readable::Corrupt => SlotStatus::Corrupt(match self.readable(slot, true) {
+                Readable::Saved(metadata) => Some(metadata),
+                _ => None,
+            }),
+        }
+    }
+
+    /// Whether the files of `slot`, or of its backup, are there and can be read.
+    fn readable(&self, slot: usize, backup: bool) -> Readable {
+        let (game, metadata) = self.files(slot, backup);
+        match (game.exists(), metadata.exists()) {
+            (false, false) => Readable::Empty,
+            (true, true) => match (self.read_game(&game), read::<SlotMetadata>(&metadata)) {
+                (Ok(_), Ok(metadata)) => Readable::Saved(metadata),
+                _ => Readable::Corrupt,
+            },
+            _ => Readable::Corrupt,
+        }
+    }
+
+    /// The game saved in `slot`.
+    pub fn load(&self, slot: usize) -> Result<SaveGame, failure::Error> {
+        self.read_game(&self.path(slot, "ron"))
+    }
+
+    /// The game that was in `slot` before it was last saved over.
+    pub fn load_backup(&self, slot: usize) -> Result<SaveGame, failure::Error> {
+        self.read_game(&self.path(slot, "bak.ron"))
+    }
+
+    fn read_game(&self, path: &Path) -> Result<SaveGame, failure::Error> {
+        decode_game(&fs::read_to_string(path)?, self.config.verify_checksums)
+            .map_err(|err| format_err!("{} is corrupt: {}", path.display(), err))
+    }
+
+    /// Saves `game` to `slot` with its `metadata` and `thumbnail`, replacing what was there.
+    pub fn save(
+        &self,
+        slot: usize,
+        game: &SaveGame,
+        metadata: &SlotMetadata,
+        thumbnail: &RgbaImage,
+    ) -> Result<(), failure::Error> {
+        fs::create_dir_all(&self.dir)?;
+        // A corrupt save isn't worth keeping, so the backup stays as it was.
+        if self.config.keep_backups {
+            if let Readable::Saved(_) = self.readable(slot, false) {
+                let ((game, metadata), (game_backup, metadata_backup)) =
+                    (self.files(slot, false), self.files(slot, true));
+                fs::rename(game, game_backup)?;
+                fs::rename(metadata, metadata_backup)?;
+                let (thumbnail, thumbnail_backup) =
+                    (self.path(slot, "png"), self.path(slot, "bak.png"));
+                if thumbnail.exists() {
+                    fs::rename(thumbnail, thumbnail_backup)?;
+                } else if thumbnail_backup.exists() {
+                    // Not left showing a thumbnail of an older game than the backup.
+                    fs::remove_file(thumbnail_backup)?;
+                }
+            }
+        }
+        fs::write(self.path(slot, "ron"), encode_game(game)?)?;
+        write(&self.path(slot, "meta.ron"), metadata)?;
+        thumbnail.save(self.path(slot, "png"))?;
+        Ok(())
+    }
+
+    /// Empties `slot`, backup and all.
+    pub fn delete(&self, slot: usize) -> Result<(), failure::Error> {
+        for extension in &[
+            "ron",
+            "meta.ron",
+            "png",
+            "bak.ron",
+            "bak.meta.ron",
+            "bak.png",
+        ] {
+            let path = self.path(slot, extension);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A thumbnail of the level in `world` as it is now, with the players marked on it.
+    pub fn thumbnail(&self, world: &World) -> RgbaImage {
+        let scale = self.config.thumbnail_scale.max(1);
+        let map = world.read_resource::<TileMap>();
+        let (width, height) = (map.width as u32, map.height as u32);
+        let mut image = render_map(
+            &map,
+            &world.read_resource::<TileAtlas>(),
+            (width * scale, height * scale),
+        );
+        let mut fill = |(column, row): (usize, usize), color: Rgba<u8>| {
+            // Images run from the top, tile rows from the bottom.
+            let top = (height - 1 - row as u32) * scale;
+            for y in top..(top + scale).min(image.height()) {
+                for x in column as u32 * scale..((column as u32 + 1) * scale).min(image.width()) {
+                    image.put_pixel(x, y, color);
+                }
+            }
+        };
+
+        let grid = world.read_resource::<NavGrid>();
+        for (_, transform) in (
+            &world.read_storage::<Player>(),
+            &world.read_storage::<Transform>(),
+        )
+            .join()
+        {
+            if let Some(tile) = grid.tile_at(world_position(transform)) {
+                fill(tile, Rgba { data: [255; 4] });
+            }
+        }
+        image
+    }
+}
+
+/// Whether one set of a slot's files is there and can be read.
+enum Readable {
+    Empty,
+    Corrupt,
+    Saved(SlotMetadata),
+}
+
+/// The checksum of a game file's `body` written in save format `version`.
+fn checksum(version: u32, body: &str) -> u64 {
+    let mut hasher = StateHasher::default();
+    hasher.write_u32(version);
+    body.bytes().for_each(|byte| hasher.write_u8(byte));
+    hasher.finish()
+}
+
+/// `game` as a game file, headed by its version and checksum.
+fn encode_game(game: &SaveGame) -> Result<String, failure::Error> {
+    let body = ron::ser::to_string_pretty(game, Default::default())?;
+    Ok(format!(
+        "{}{} {:016x}\n{}",
+        CHECKSUM_PREFIX,
+        SAVE_VERSION,
+        checksum(SAVE_VERSION, &body),
+        body
+    ))
+}
+
+/// The version and checksum a game file `text` was saved with, and the rest of the file.
+/// Files with no checksum line are `UNCHECKED_VERSION`, and have no checksum.
+fn split_header(text: &str) -> Result<(u32, Option<u64>, &str), failure::Error> {
+    // Only files from before checksums start with the game itself; any comment in their place
+    // is a checksum line that has been damaged.
+    if !text.trim_start().starts_with("//") {
+        return Ok((UNCHECKED_VERSION, None, text));
+    }
+    let rest = text
+        .strip_prefix(CHECKSUM_PREFIX)
+        .ok_or_else(|| format_err!("it starts with a comment that isn't its checksum line"))?;
+    let (header, body) = rest
+        .split_once('\n')
+        .ok_or_else(|| format_err!("it ends in its checksum line"))?;
+    let mut fields = header.split_whitespace();
+    let version = fields
+        .next()
+        .and_then(|version| version.parse::<u32>().ok())
+        .filter(|&version| version != UNCHECKED_VERSION)
+        .ok_or_else(|| format_err!("its checksum line has no version"))?;
+    let stored = fields
+        .next()
+        .and_then(|stored| u64::from_str_radix(stored, 16).ok())
+        .ok_or_else(|| format_err!("its checksum line has no checksum"))?;
+    if fields.next().is_some() {
+        return Err(format_err!("its checksum line runs on past the checksum"));
+    }
+    Ok((version, Some(stored), body))
+}
+
+/// The game in the game file `text`, checked against its checksum if `verify`.
+fn decode_game(text: &str, verify: bool) -> Result<SaveGame, failure::Error> {
+    let (version, stored, body) = split_header(text)?;
+    if version > SAVE_VERSION {
+        return Err(format_err!(
+            "it was saved in format {}, newer than this game's {}",
+            version,
+            SAVE_VERSION
+        ));
+    }
+    if let Some(stored) = stored {
+        let computed = checksum(version, body);
+        if verify && computed != stored {
+            return Err(format_err!(
+                "its checksum is {:016x} but it was saved with {:016x}",
+                computed,
+                stored
+            ));
+        }
+    }
+    Ok(ron::de::from_str(body)?)
+}
+
+/// The average colour of every sprite in the main sprite sheet, by sprite number, for drawing
+/// the minimap.
+#[derive(Clone, Debug, Default)]
+pub struct SpriteColors(Vec<Rgba<u8>>);
+
+impl SpriteColors {
+    /// Averages the opaque pixels of each sprite in `atlas`.
+    pub fn from_atlas(atlas: &TileAtlas) -> Self {
+        let image = atlas.image();
+        SpriteColors(
+            atlas
+                .sprites()
+                .iter()
+                .map(|sprite| {
+                    let mut sum = [0u64; 3];
+                    let mut count = 0;
+                    for y in sprite.y..(sprite.y + sprite.height).min(image.height()) {
+                        for x in sprite.x..(sprite.x + sprite.width).min(image.width()) {
+                            let [r, g, b, a] = image.get_pixel(x, y).data;
+                            if a > 0 {
+                                for (sum, channel) in sum.iter_mut().zip(&[r, g, b]) {
+                                    *sum += u64::from(*channel);
+                                }
+                                count += 1;
+                            }
+                        }
+                    }
+                    let average = |sum: u64| (sum / count.max(1)) as u8;
+                    Rgba {
+                        data: [average(sum[0]), average(sum[1]), average(sum[2]), 255],
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// The colour of `sprite_number`, or black if the sheet has no such sprite.
+    pub fn get(&self, sprite_number: usize) -> Rgba<u8> {
+        self.0.get(sprite_number).copied().unwrap_or(Rgba {
+            data: [0, 0, 0, 255],
+        })
+    }
+}
+
+fn read<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, failure::Error> {
+    Ok(ron::de::from_reader(File::open(path)?)?)
+}
+
+fn write<T: Serialize>(path: &Path, value: &T) -> Result<(), failure::Error> {
+    let text = ron::ser::to_string_pretty(value, Default::default())?;
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Whether the game being played has changed since it was last saved or loaded, and which slot
+/// that was.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnsavedProgress {
+    dirty: bool,
+    slot: Option<usize>,
+}
+
+impl UnsavedProgress {
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The slot the game was last saved to or loaded from.
+    pub fn slot(&self) -> Option<usize> {
+        self.slot
+    }
+
+    /// Notes that the game has changed.
+    pub fn mark(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Notes that the game is as saved in `slot`.
+    pub fn saved(&mut self, slot: usize) {
+        self.dirty = false;
+        self.slot = Some(slot);
+    }
+}
+
+/// Marks `UnsavedProgress` whenever a step moves a tagged entity, hurts anything or edits the
+/// level. Runs with the gameplay systems; dialogue answers are marked by the dialogue menu.
+#[derive(Default)]
+pub struct UnsavedProgressSystem {
+    damage_reader: Option<ReaderId<DamageTaken>>,
+    edit_reader: Option<ReaderId<TileEdit>>,
+    /// Where each tagged entity was on the map after the last step.
+    positions: BTreeMap<Entity, Vector2<f32>>,
+}
+
+impl<'s> System<'s> for UnsavedProgressSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, EventChannel<DamageTaken>>,
+        Read<'s, EventChannel<TileEdit>>,
+        ReadStorage<'s, SaveTag>,
+        ReadStorage<'s, Transform>,
+        Read<'s, TileMap>,
+        Write<'s, UnsavedProgress>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, taken, edits, tags, transforms, map, mut progress): Self::SystemData,
+    ) {
+        // Every event is read, so none are left over for the next step.
+        let damage_reader = self
+            .damage_reader
+            .as_mut()
+            .expect("UnsavedProgressSystem is set up");
+        let hurt = taken
+            .read(damage_reader)
+            .fold(false, |hurt, event| hurt | (event.amount > 0.));
+        let edit_reader = self
+            .edit_reader
+            .as_mut()
+            .expect("UnsavedProgressSystem is set up");
+        let edited = edits.read(edit_reader).count() > 0;
+
+        // Positions are kept from the map's corner, as saves keep them, so rebasing isn't a move.
+        let origin = map.origin();
+        let positions: BTreeMap<Entity, Vector2<f32>> = (&entities, &tags, &transforms)
+            .join()
+            .map(|(entity, _, transform)| (entity, world_position(transform) - origin))
+            .collect();
+        let moved = positions.iter().any(|(entity, position)| {
+            self.positions
+                .get(entity)
+                .is_some_and(|last| last != position)
+        });
+        self.positions = positions;
+
+        if hurt || edited || moved {
+            progress.mark();
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.damage_reader = Some(
+            res.fetch_mut::<EventChannel<DamageTaken>>()
+                .register_reader(),
+        );
+        self.edit_reader = Some(res.fetch_mut::<EventChannel<TileEdit>>().register_reader());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, RunNow};
+
+    use super::*;
+    use crate::{
+        combat::{DamageKind, DamageType},
+        tile_map::Tile,
+    };
+
+    fn game() -> SaveGame {
+        SaveGame {
+            seed: 7,
+            map: TileMap {
+                width: 2,
+                height: 1,
+                tile_size: 16.,
+                ..TileMap::default()
+            },
+            entities: vec![SavedEntity {
+                player: Some(0),
+                position: Some((24., 8.)),
+                health: Some(3.),
+            }],
+            players: Vec::new(),
+            playtime: 61.5,
+            difficulty: DifficultyLevel::default(),
+            dialogue_flags: DialogueFlags::default(),
+        }
+    }
+
+    #[test]
+    fn saved_games_read_back() {
+        let mut game = game();
+        game.dialogue_flags.set("met_the_keeper");
+        game.difficulty = DifficultyLevel::Hard;
+        let text = encode_game(&game).unwrap();
+        let read = decode_game(&text, true).unwrap();
+        assert_eq!(read.seed, 7);
+        assert_eq!(
+            read.player(0).and_then(|player| player.position),
+            Some((24., 8.))
+        );
+        assert_eq!(read.playtime, 61.5);
+        assert_eq!(read.difficulty, DifficultyLevel::Hard);
+        assert!(read.dialogue_flags.is_set("met_the_keeper"));
+        assert!(!read.dialogue_flags.is_set("left_the_keeper"));
+    }
+
+    #[test]
+    fn a_tampered_byte_is_corrupt() {
+        let text = encode_game(&game()).unwrap();
+        let tampered = text.replacen("seed: 7", "seed: 8", 1);
+        assert_ne!(tampered, text);
+        assert!(decode_game(&tampered, true).is_err());
+        // Unverified, the tampered game still reads.
+        assert_eq!(decode_game(&tampered, false).unwrap().seed, 8);
+    }
+
+    #[test]
+    fn a_damaged_checksum_line_is_corrupt() {
+        let text = encode_game(&game()).unwrap();
+        let body = text.split_once('\n').unwrap().1;
+        for header in &["// checksum v1 0\n", "// saved\n", "// checksum: v0 0\n"] {
+            let damaged = format!("{}{}", header, body);
+            assert!(decode_game(&damaged, true).is_err(), "{:?} read", header);
+            assert!(decode_game(&damaged, false).is_err(), "{:?} read", header);
+        }
+    }
+
+    #[test]
+    fn games_without_a_checksum_line_read_unchecked() {
+        let text = encode_game(&game()).unwrap();
+        let body = text.split_once('\n').unwrap().1;
+        assert_eq!(decode_game(body, true).unwrap().seed, 7);
+    }
+
+    #[test]
+    fn newer_formats_are_rejected() {
+        let body = ron::ser::to_string(&game()).unwrap();
+        let version = SAVE_VERSION + 1;
+        let text = format!(
+            "{}{} {:016x}\n{}",
+            CHECKSUM_PREFIX,
+            version,
+            checksum(version, &body),
+            body
+        );
+        assert!(decode_game(&text, true).is_err());
+    }
+
+    #[test]
+    fn saving_clears_progress_until_it_is_marked_again() {
+        let mut progress = UnsavedProgress::default();
+        assert!(!progress.is_dirty());
+        progress.mark();
+        assert!(progress.is_dirty());
+        progress.saved(2);
+        assert!(!progress.is_dirty());
+        assert_eq!(progress.slot(), Some(2));
+        progress.mark();
+        assert!(progress.is_dirty());
+        assert_eq!(progress.slot(), Some(2));
+    }
+
+    #[test]
+    fn quitting_prompts_only_with_unsaved_progress() {
+        let slots = |prompt_on_quit| {
+            SaveSlots::new(
+                PathBuf::new(),
+                SaveConfig {
+                    prompt_on_quit,
+                    ..SaveConfig::default()
+                },
+            )
+        };
+        let mut progress = UnsavedProgress::default();
+        assert!(!slots(true).prompts_on_quit(&progress));
+        progress.mark();
+        assert!(slots(true).prompts_on_quit(&progress));
+        assert!(!slots(false).prompts_on_quit(&progress));
+        progress.saved(0);
+        assert!(!slots(true).prompts_on_quit(&progress));
+    }
+
+    /// A world with one tagged entity at `x, 0`, set up for the `UnsavedProgressSystem`.
+    fn tagged(x: f32) -> (World, UnsavedProgressSystem, Entity) {
+        let mut world = World::new();
+        world.register::<SaveTag>();
+        world.register::<Transform>();
+        world.add_resource(TileMap::default());
+        let mut system = UnsavedProgressSystem::default();
+        System::setup(&mut system, &mut world.res);
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(x, 0., 0.);
+        let entity = world.create_entity().with(SaveTag).with(transform).build();
+        (world, system, entity)
+    }
+
+    fn step(world: &World, system: &mut UnsavedProgressSystem) -> bool {
+        system.run_now(&world.res);
+        world.read_resource::<UnsavedProgress>().is_dirty()
+    }
+
+    #[test]
+    fn standing_still_is_not_progress() {
+        let (world, mut system, _) = tagged(4.);
+        assert!(!step(&world, &mut system));
+        assert!(!step(&world, &mut system));
+    }
+
+    #[test]
+    fn moving_a_tagged_entity_is_progress() {
+        let (world, mut system, entity) = tagged(4.);
+        step(&world, &mut system);
+        world
+            .write_storage::<Transform>()
+            .get_mut(entity)
+            .unwrap()
+            .set_translation_x(5.);
+        assert!(step(&world, &mut system));
+    }
+
+    #[test]
+    fn only_damage_that_hurts_is_progress() {
+        let (world, mut system, entity) = tagged(4.);
+        let hit = |amount| DamageTaken {
+            source: None,
+            target: entity,
+            amount,
+            kind: DamageKind::Hitbox,
+            damage_type: DamageType::default(),
+        };
+        step(&world, &mut system);
+        world
+            .write_resource::<EventChannel<DamageTaken>>()
+            .single_write(hit(0.));
+        assert!(!step(&world, &mut system));
+        world
+            .write_resource::<EventChannel<DamageTaken>>()
+            .single_write(hit(1.));
+        assert!(step(&world, &mut system));
+    }
+
+    #[test]
+    fn editing_the_level_is_progress() {
+        let (world, mut system, _) = tagged(4.);
+        step(&world, &mut system);
+        world
+            .write_resource::<EventChannel<TileEdit>>()
+            .single_write(TileEdit {
+                coord: (0, 0),
+                tile: Tile::Static(0),
+            });
+        assert!(step(&world, &mut system));
+    }
+}
@@ -0,0 +1,351 @@
+//! Level events set off by the players walking into areas of the map.
+//!
+//! A map's `triggers` each name one of its `scripts`. When a player enters a trigger's area,
+//! the `ScriptTriggerSystem` sends a `ScriptEvent` naming that script, and the game runs it.
+//! One-shot triggers only ever fire once; repeatable ones fire again each time a player enters
+//! after all of them have left. A trigger that `requires` a dialogue flag doesn't fire until an
+//! answer has set it, and then only once a player next enters.
+
+use std::collections::BTreeMap;
+
+use amethyst::{
+    assets::{AssetStorage, Handle, Loader},
+    core::{math::Vector2, timing::Time},
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, System,
+        World, Write, WriteStorage,
+    },
+    prelude::{GameData, StateEvent, Trans, TransEvent},
+    renderer::SpriteSheet,
+    shrev::EventChannel,
+    ui::{Anchor, FontAsset, UiText, UiTransform},
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::Aabb,
+    cutscene::{Cutscene, Cutscenes},
+    dialogue::DialogueFlags,
+    disabled::{disable, disable_for, enable, Disabled},
+    enemy::{spawn_enemies, EnemySpawn},
+    menu::DialogueState,
+    navigation::TileCoord,
+    player::Player,
+    sound::{play_sound, play_sound_at},
+    spatial::SpatialGrid,
+    strings::{LocalizedText, Strings},
+    tile_map::{Tile, TileEdit, TileMap},
+    ui_theme::{ThemedText, UiTheme},
+};
+
+/// A behaviour a trigger can set off.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ScriptAction {
+    /// Spawns these enemies.
+    SpawnWave(Vec<EnemySpawn>),
+    /// Replaces the tiles of a gate with `open`.
+    OpenGate { tiles: Vec<TileCoord>, open: Tile },
+    /// Plays the ogg or wav file at this path, relative to `resources`.
+    PlaySound(String),
+    /// Plays the sound at `path` like `PlaySound`, from `x` and `y` on the map, where walls
+    /// between it and the players muffle it.
+    PlaySoundAt { path: String, x: f32, y: f32 },
+    /// Shows `text` at the top of the window for `duration` seconds.
+    ShowMessage { text: String, duration: f32 },
+    /// Holds every player still, as for a cutscene, for this many seconds or until
+    /// `UnfreezePlayers` runs.
+    FreezePlayers(Option<f32>),
+    /// Lets frozen players move again.
+    UnfreezePlayers,
+    /// Holds the conversation starting from this node of the `DialogueGraph`.
+    StartDialogue(String),
+    /// Plays the cutscene of this name from `cutscenes.ron`.
+    PlayCutscene(String),
+}
+
+/// The scripted behaviours of a level, by the names triggers call them by.
+pub type Scripts = BTreeMap<String, ScriptAction>;
+
+/// An area of the map that runs the script `on_enter` when a player walks into it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScriptTrigger {
+    /// Bottom-left corner and size of the area in world units, from the map's bottom-left
+    /// corner.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub on_enter: String,
+    /// Whether the trigger fires every time it is entered rather than only the first.
+    #[serde(default)]
+    pub repeat: bool,
+    /// The dialogue flag that has to be set for the trigger to fire.
+    #[serde(default)]
+    pub requires: Option<String>,
+}
+
+impl ScriptTrigger {
+    pub fn area(&self) -> Aabb {
+        Aabb {
+            min: Vector2::new(self.x, self.y),
+            max: Vector2::new(self.x + self.width, self.y + self.height),
+        }
+    }
+}
+
+/// Asks for the script named `action` to be run.
+#[derive(Clone, Debug)]
+pub struct ScriptEvent {
+    pub action: String,
+}
+
+/// Sends a `ScriptEvent` whenever a player enters one of the `TileMap`'s triggers.
+#[derive(Default)]
+pub struct ScriptTriggerSystem {
+    /// Whether any player was inside each trigger at the last step.
+    occupied: Vec<bool>,
+    /// Whether each trigger has fired.
+    fired: Vec<bool>,
+}
+
+impl<'s> System<'s> for ScriptTriggerSystem {
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        Read<'s, SpatialGrid>,
+        Read<'s, TileMap>,
+        Read<'s, DialogueFlags>,
+        Write<'s, EventChannel<ScriptEvent>>,
+    );
+
+    fn run(&mut self, (players, spatial, map, flags, mut events): Self::SystemData) {
+        let triggers = &map.triggers;
+        self.occupied.resize(triggers.len(), false);
+        self.fired.resize(triggers.len(), false);
+        for (index, trigger) in triggers.iter().enumerate() {
+            let occupied = !spatial
+                .query_rect(&trigger.area().translated(map.origin()), |entity| {
+                    players.contains(entity)
+                })
+                .is_empty();
+            let entered = occupied && !self.occupied[index];
+            self.occupied[index] = occupied;
+            let unlocked = trigger
+                .requires
+                .as_ref()
+                .is_none_or(|flag| flags.is_set(flag));
+            if entered && unlocked && (trigger.repeat || !self.fired[index]) {
+                self.fired[index] = true;
+                events.single_write(ScriptEvent {
+                    action: trigger.on_enter.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn players(world: &World) -> Vec<Entity> {
+    (&world.entities(), &world.read_storage::<Player>())
+        .join()
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+/// Runs the script named `action` from the level's `scripts`, spawning any enemies from
+/// `sprite_sheet`.
+pub fn run_script(world: &mut World, action: &str, sprite_sheet: &Handle<SpriteSheet>) {
+    let script = world
+        .read_resource::<TileMap>()
+        .scripts
+        .get(action)
+        .cloned();
+    match script {
+        Some(ScriptAction::SpawnWave(spawns)) => {
+            spawn_enemies(world, &spawns, sprite_sheet.clone());
+        }
+        Some(ScriptAction::OpenGate { tiles, open }) => {
+            let mut edits = world.write_resource::<EventChannel<TileEdit>>();
+            for coord in tiles {
+                edits.single_write(TileEdit { coord, tile: open });
+            }
+        }
+        Some(ScriptAction::PlaySound(path)) => play_sound(world, &path),
+        Some(ScriptAction::PlaySoundAt { path, x, y }) => {
+            let origin = world.read_resource::<TileMap>().origin();
+            play_sound_at(world, &path, Vector2::new(x, y) + origin)
+        }
+        Some(ScriptAction::ShowMessage { text, duration }) => show_message(world, text, duration),
+        Some(ScriptAction::FreezePlayers(duration)) => {
+            let mut disabled = world.write_storage::<Disabled>();
+            for player in players(world) {
+                match duration {
+                    Some(seconds) => disable_for(&mut disabled, player, seconds),
+                    None => disable(&mut disabled, player),
+                }
+            }
+        }
+        Some(ScriptAction::UnfreezePlayers) => {
+            let mut disabled = world.write_storage::<Disabled>();
+            for player in players(world) {
+                enable(&mut disabled, player);
+            }
+        }
+        Some(ScriptAction::StartDialogue(node)) => world
+            .write_resource::<EventChannel<TransEvent<GameData<'static, 'static>, StateEvent>>>()
+            .single_write(Box::new(move || {
+                Trans::Push(Box::new(DialogueState::new(node.clone())))
+            })),
+        Some(ScriptAction::PlayCutscene(name)) => {
+            let cutscenes = world.read_resource::<Cutscenes>();
+            world.write_resource::<Cutscene>().start(&name, &cutscenes);
+        }
+        None => warn!("Triggered unknown script {}", action),
+    }
+}
+
+/// Removes a scripted message once `remaining` runs out.
+#[derive(Clone, Copy, Debug)]
+pub struct ScriptMessage {
+    pub remaining: f32,
+}
+
+impl Component for ScriptMessage {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Shows `text`, a string id or the text itself, at the top of the window for `duration`
+/// seconds.
+pub fn show_message(world: &mut World, text: String, duration: f32) {
+    let theme = world.read_resource::<UiTheme>().clone();
+    let font = theme.font(
+        &world.read_resource::<Loader>(),
+        &world.read_resource::<AssetStorage<FontAsset>>(),
+    );
+    let localized = LocalizedText::new(text);
+    let mut text = UiText::new(
+        font,
+        world.read_resource::<Strings>().get_or(&localized.id),
+        theme.text_color,
+        theme.font_size * 1.5,
+    );
+    let mut transform = UiTransform::new(
+        "script_message".to_string(),
+        Anchor::TopMiddle,
+        Anchor::TopMiddle,
+        0.,
+        -48.,
+        20.,
+        480.,
+        32.,
+    );
+    let themed = ThemedText::new(&text, &transform);
+    themed.apply(&theme, &mut text, &mut transform);
+    world
+        .create_entity()
+        .with(transform)
+        .with(text)
+        .with(themed)
+        .with(localized)
+        .with(ScriptMessage {
+            remaining: duration,
+        })
+        .build();
+}
+
+/// Counts down each `ScriptMessage` and deletes it when its time is up.
+pub struct ScriptMessageSystem;
+
+impl<'s> System<'s> for ScriptMessageSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, ScriptMessage>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (entities, mut messages, time): Self::SystemData) {
+        for (entity, message) in (&entities, &mut messages).join() {
+            message.remaining -= time.delta_seconds();
+            if message.remaining <= 0. {
+                entities.delete(entity).expect("Message is alive");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{ecs::prelude::RunNow, shrev::ReaderId};
+
+    use super::*;
+
+    fn trigger(on_enter: &str, x: f32, repeat: bool) -> ScriptTrigger {
+        ScriptTrigger {
+            x,
+            y: 0.,
+            width: 32.,
+            height: 32.,
+            on_enter: on_enter.to_string(),
+            repeat,
+            requires: None,
+        }
+    }
+
+    /// Moves `player` to `x` and runs `system`, returning the scripts it fired.
+    fn walk_to(
+        world: &mut World,
+        system: &mut ScriptTriggerSystem,
+        reader: &mut ReaderId<ScriptEvent>,
+        player: Entity,
+        x: f32,
+    ) -> Vec<String> {
+        {
+            let mut spatial = world.write_resource::<SpatialGrid>();
+            spatial.clear();
+            spatial.insert(player, Vector2::new(x, 16.));
+        }
+        system.run_now(&world.res);
+        world
+            .read_resource::<EventChannel<ScriptEvent>>()
+            .read(reader)
+            .map(|event| event.action.clone())
+            .collect()
+    }
+
+    #[test]
+    fn a_one_shot_trigger_fires_once_and_never_again() {
+        let mut world = World::new();
+        let mut system = ScriptTriggerSystem::default();
+        System::setup(&mut system, &mut world.res);
+        world.add_resource(SpatialGrid::new(64.));
+        world.add_resource(TileMap {
+            triggers: vec![trigger("ambush", 0., false), trigger("chime", 100., true)],
+            ..TileMap::default()
+        });
+        let mut reader = world
+            .write_resource::<EventChannel<ScriptEvent>>()
+            .register_reader();
+        let player = world
+            .create_entity()
+            .with(Player {
+                index: 0,
+                speed: 100.,
+            })
+            .build();
+        let mut walk = |world: &mut World, x| walk_to(world, &mut system, &mut reader, player, x);
+
+        assert!(walk(&mut world, -50.).is_empty());
+        assert_eq!(walk(&mut world, 16.), vec!["ambush"]);
+        // Staying inside doesn't count as entering again.
+        assert!(walk(&mut world, 20.).is_empty());
+        for _ in 0..3 {
+            assert!(walk(&mut world, -50.).is_empty());
+            assert!(walk(&mut world, 16.).is_empty());
+        }
+
+        // The repeatable trigger beside it fires on every entry.
+        for _ in 0..3 {
+            assert_eq!(walk(&mut world, 116.), vec!["chime"]);
+            assert!(walk(&mut world, 60.).is_empty());
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Soft drop shadows that ground sprites on the map.
+//!
+//! Each entity with a `Shadow` gets a dark, squashed ellipse drawn beneath it with debug lines
+//! on the `ShadowOverlay` entity. Shadows sit between the tiles and the sprites, so they always
+//! sort below their owner and everything standing on top of it. The `toggle_shadows` action
+//! turns them all off or back on.
+
+use amethyst::{
+    assets::AssetStorage,
+    core::{
+        math::{Point3, Vector2},
+        transform::Transform,
+    },
+    ecs::prelude::{
+        Component, DenseVecStorage, Join, NullStorage, Read, ReadStorage, System, Write,
+        WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::{debug_drawing::DebugLinesComponent, palette::Srgba, SpriteRender, SpriteSheet},
+};
+
+use crate::{movement::world_position, quality::QualityTier};
+
+/// Depth of the shadows, in front of the tiles and behind the sprites.
+const SHADOW_DEPTH: f32 = -0.5;
+
+/// How much flatter than it is wide an unscaled shadow is.
+const SQUASH: f32 = 0.35;
+
+/// A shadow cast on the ground below the entity's sprite.
+#[derive(Clone, Copy, Debug)]
+pub struct Shadow {
+    /// Where the centre of the shadow is, relative to the entity, in world units.
+    pub offset: Vector2<f32>,
+    pub opacity: f32,
+    /// Size of the shadow relative to the sprite's width, horizontally and vertically.
+    pub scale: Vector2<f32>,
+}
+
+impl Default for Shadow {
+    /// A shadow at the feet of a 32 unit tall sprite.
+    fn default() -> Self {
+        Shadow {
+            offset: Vector2::new(0., -14.),
+            opacity: 0.4,
+            scale: Vector2::new(0.8, 1.),
+        }
+    }
+}
+
+impl Shadow {
+    /// The centre and the horizontal and vertical radius of this shadow under a sprite
+    /// `sprite_width` units wide at `position`.
+    pub fn ellipse(
+        &self,
+        position: Vector2<f32>,
+        sprite_width: f32,
+    ) -> (Vector2<f32>, Vector2<f32>) {
+        let half_width = sprite_width / 2. * self.scale.x;
+        (
+            position + self.offset,
+            Vector2::new(half_width, half_width * SQUASH * self.scale.y),
+        )
+    }
+}
+
+impl Component for Shadow {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Whether shadows are drawn at all.
+#[derive(Clone, Copy, Debug)]
+pub struct Shadows {
+    pub enabled: bool,
+}
+
+impl Default for Shadows {
+    fn default() -> Self {
+        Shadows { enabled: true }
+    }
+}
+
+impl Shadows {
+    /// Whether shadows are drawn at `quality`.
+    pub fn drawn(&self, quality: &QualityTier) -> bool {
+        self.enabled && quality.shadows
+    }
+}
+
+/// Marks the entity whose debug lines draw the shadows.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShadowOverlay;
+
+impl Component for ShadowOverlay {
+    type Storage = NullStorage<Self>;
+}
+
+/// Redraws the shadow of every entity with a `Shadow` and a sprite. Shows or hides them all on
+/// the `toggle_shadows` action, and hides them while the `QualityTier` leaves them out.
+#[derive(Default)]
+pub struct ShadowSystem {
+    was_pressed: bool,
+}
+
+impl<'s> System<'s> for ShadowSystem {
+    type SystemData = (
+        ReadStorage<'s, Shadow>,
+        ReadStorage<'s, SpriteRender>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, ShadowOverlay>,
+        WriteStorage<'s, DebugLinesComponent>,
+        Write<'s, Shadows>,
+        Read<'s, AssetStorage<SpriteSheet>>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, QualityTier>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            shadows,
+            sprites,
+            transforms,
+            overlays,
+            mut lines,
+            mut settings,
+            sheets,
+            input,
+            quality,
+        ): Self::SystemData,
+    ) {
+        let pressed = input.action_is_down("toggle_shadows").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            settings.enabled = !settings.enabled;
+        }
+        self.was_pressed = pressed;
+
+        for (_, lines) in (&overlays, &mut lines).join() {
+            lines.clear();
+            if !settings.drawn(&quality) {
+                continue;
+            }
+            for (shadow, sprite, transform) in (&shadows, &sprites, &transforms).join() {
+                let width = match sheets
+                    .get(&sprite.sprite_sheet)
+                    .and_then(|sheet| sheet.sprites.get(sprite.sprite_number))
+                {
+                    Some(sprite) => sprite.width,
+                    None => continue,
+                };
+                let (center, radius) = shadow.ellipse(world_position(transform), width);
+                // Fill the ellipse with one horizontal line per world unit of height, each
+                // darkest in the middle and fading out towards the rim.
+                let edge = Srgba::new(0., 0., 0., 0.);
+                let rows = radius.y.ceil() as i32;
+                for row in -rows..=rows {
+                    let y = row as f32;
+                    let across = 1. - (y / radius.y).powi(2);
+                    if across <= 0. {
+                        continue;
+                    }
+                    let middle = Srgba::new(0., 0., 0., shadow.opacity * across.sqrt());
+                    let half = radius.x * across.sqrt();
+                    let point = |x: f32| Point3::new(center.x + x, center.y + y, SHADOW_DEPTH);
+                    lines.add_gradient_line(point(-half), point(0.), edge, middle);
+                    lines.add_gradient_line(point(0.), point(half), middle, edge);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quality::QualityConfig;
+
+    #[test]
+    fn the_ellipse_follows_the_offset_and_scale() {
+        let shadow = Shadow {
+            offset: Vector2::new(3., -10.),
+            opacity: 0.5,
+            scale: Vector2::new(0.5, 2.),
+        };
+        let (center, radius) = shadow.ellipse(Vector2::new(100., 50.), 40.);
+        assert_eq!(center, Vector2::new(103., 40.));
+        assert_eq!(radius, Vector2::new(10., 10. * SQUASH * 2.));
+
+        // Doubling the horizontal scale widens and deepens it alike.
+        let wide = Shadow {
+            scale: Vector2::new(1., 2.),
+            ..shadow
+        };
+        let (_, wide_radius) = wide.ellipse(Vector2::new(100., 50.), 40.);
+        assert_eq!(wide_radius, radius * 2.);
+    }
+
+    #[test]
+    fn shadows_are_off_at_a_tier_without_them_and_when_toggled_off() {
+        let tiers = QualityConfig::default().tiers;
+        let (high, low) = (&tiers[0], &tiers[2]);
+        assert!(Shadows::default().drawn(high));
+        assert!(!Shadows::default().drawn(low));
+
+        let off = Shadows { enabled: false };
+        assert!(!off.drawn(high));
+        assert!(!off.drawn(low));
+    }
+}
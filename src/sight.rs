@@ -0,0 +1,155 @@
+//! Line of sight over the map's opaque tiles.
+
+use amethyst::core::math::Vector2;
+
+use crate::tile_map::{MapProjection, TileMap};
+
+/// Which tiles of the map block sight. Everything outside the map is see-through.
+#[derive(Clone, Debug, Default)]
+pub struct SightGrid {
+    width: usize,
+    height: usize,
+    tile_size: f32,
+    projection: MapProjection,
+    /// Where the map's bottom-left corner is in the world.
+    origin: (f32, f32),
+    opaque: Vec<bool>,
+}
+
+impl SightGrid {
+    fn origin(&self) -> Vector2<f32> {
+        Vector2::new(self.origin.0, self.origin.1)
+    }
+
+    pub fn from_map(map: &TileMap) -> Self {
+        let opaque = map.tile_mask(&map.opaque);
+        SightGrid {
+            width: map.width,
+            height: map.height,
+            tile_size: map.tile_size,
+            projection: map.projection,
+            origin: map.origin,
+            opaque,
+        }
+    }
+
+    fn is_opaque(&self, column: i64, row: i64) -> bool {
+        column >= 0
+            && row >= 0
+            && (column as usize) < self.width
+            && (row as usize) < self.height
+            && self.opaque[row as usize * self.width + column as usize]
+    }
+
+    /// Whether the straight line from `from` to `to` crosses no opaque tile.
+    ///
+    /// Walks every tile the line passes through, so sight can't slip between two opaque tiles
+    /// that only meet at a corner. The tiles the line starts and ends in don't count.
+    pub fn line_of_sight(&self, from: Vector2<f32>, to: Vector2<f32>) -> bool {
+        self.count_opaque(from, to, 1) == 0
+    }
+
+    /// How many opaque tiles the straight line from `from` to `to` crosses, walked as for
+    /// `line_of_sight`.
+    pub fn opaque_between(&self, from: Vector2<f32>, to: Vector2<f32>) -> usize {
+        self.count_opaque(from, to, usize::MAX)
+    }
+
+    /// The opaque tiles the line crosses, counting no further than `limit`.
+    fn count_opaque(&self, from: Vector2<f32>, to: Vector2<f32>, limit: usize) -> usize {
+        if self.tile_size <= 0. {
+            return 0;
+        }
+        // Both projections keep lines straight, so the line can be walked in tile space.
+        let (start, end) = (
+            self.projection
+                .to_tile(from - self.origin(), self.tile_size),
+            self.projection.to_tile(to - self.origin(), self.tile_size),
+        );
+        let mut tile = (start.x.floor() as i64, start.y.floor() as i64);
+        let last = (end.x.floor() as i64, end.y.floor() as i64);
+        let direction = end - start;
+        let step = (direction.x.signum() as i64, direction.y.signum() as i64);
+
+        // Distance along the line, as a fraction of it, to the next tile edge on each axis
+        // and between two edges on each axis.
+        let next_edge = |position: f32, tile: i64, delta: f32| {
+            if delta > 0. {
+                (tile as f32 + 1. - position) / delta
+            } else if delta < 0. {
+                (position - tile as f32) / -delta
+            } else {
+                f32::INFINITY
+            }
+        };
+        let mut to_edge = Vector2::new(
+            next_edge(start.x, tile.0, direction.x),
+            next_edge(start.y, tile.1, direction.y),
+        );
+        let between_edges = Vector2::new(1. / direction.x.abs(), 1. / direction.y.abs());
+
+        let mut opaque = 0;
+        while tile != last && opaque < limit {
+            if to_edge.x.min(to_edge.y) > 1. {
+                // Rounding put the end of the line in this tile after all.
+                break;
+            }
+            if to_edge.x < to_edge.y {
+                tile.0 += step.0;
+                to_edge.x += between_edges.x;
+            } else {
+                tile.1 += step.1;
+                to_edge.y += between_edges.y;
+            }
+            if tile != last && self.is_opaque(tile.0, tile.1) {
+                opaque += 1;
+            }
+        }
+        opaque
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tile_map::Tile;
+
+    use super::*;
+
+    /// A grid of 16 unit tiles from `tiles`, opaque where they are `1`.
+    fn grid(width: usize, height: usize, tiles: Vec<Tile>) -> SightGrid {
+        SightGrid::from_map(&TileMap {
+            width,
+            height,
+            tile_size: 16.,
+            tiles,
+            opaque: vec![Tile::Static(1)],
+            ..TileMap::default()
+        })
+    }
+
+    #[test]
+    fn walls_between_block_sight() {
+        let (floor, wall) = (Tile::Static(0), Tile::Static(1));
+        let grid = grid(4, 1, vec![floor, wall, wall, floor]);
+        assert!(!grid.line_of_sight(Vector2::new(8., 8.), Vector2::new(56., 8.)));
+        assert_eq!(
+            grid.opaque_between(Vector2::new(8., 8.), Vector2::new(56., 8.)),
+            2
+        );
+        // The tile the line ends in doesn't count.
+        assert!(grid.line_of_sight(Vector2::new(8., 8.), Vector2::new(24., 8.)));
+    }
+
+    #[test]
+    fn sight_does_not_slip_between_corners() {
+        let (floor, wall) = (Tile::Static(0), Tile::Static(1));
+        let grid = grid(2, 2, vec![wall, floor, floor, wall]);
+        assert!(!grid.line_of_sight(Vector2::new(8., 8.), Vector2::new(24., 24.)));
+    }
+
+    #[test]
+    fn everything_off_the_map_can_be_seen_through() {
+        let grid = grid(1, 1, vec![Tile::Static(1)]);
+        assert!(grid.line_of_sight(Vector2::new(-40., 8.), Vector2::new(-8., 40.)));
+    }
+}
@@ -0,0 +1,217 @@
+//! Putting enemies far from every player to sleep, so hordes out of sight don't cost anything
+//! to simulate.
+//!
+//! With sleeping enabled in `sleep.ron`, an enemy further than `sleep_distance` from the nearest
+//! player is given `Asleep`, which the chasing, movement, attacking, hitbox and animation
+//! systems skip the way they skip `Disabled` entities, leaving everything about it as it was.
+//! It wakes once a player comes within `wake_distance`, a little nearer, so one at the edge
+//! doesn't nod off and wake every step. Enemies given `NeverSleep`, such as bosses, always stay awake.
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, Entities, Join, NullStorage, Read, ReadStorage, System, WriteStorage,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{death::Dead, enemy::Enemy, movement::world_position, player::Player};
+
+/// When enemies are put to sleep, read from `sleep.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SleepConfig {
+    pub enabled: bool,
+    /// How far from the nearest player an enemy falls asleep, in world units.
+    pub sleep_distance: f32,
+    /// How near the nearest player has to come to wake it again, in world units.
+    pub wake_distance: f32,
+}
+
+impl Default for SleepConfig {
+    fn default() -> Self {
+        SleepConfig {
+            enabled: false,
+            sleep_distance: 1200.,
+            wake_distance: 1000.,
+        }
+    }
+}
+
+impl SleepConfig {
+    /// Whether an enemy that is `asleep` or not is after this step, with the nearest player
+    /// `distance` away.
+    pub fn sleeps(&self, asleep: bool, distance: f32) -> bool {
+        if asleep {
+            distance > self.wake_distance.min(self.sleep_distance)
+        } else {
+            distance > self.sleep_distance
+        }
+    }
+}
+
+/// Marks an enemy that has been put to sleep.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Asleep;
+
+impl Component for Asleep {
+    type Storage = NullStorage<Self>;
+}
+
+/// Keeps an enemy awake however far it is from the players.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NeverSleep;
+
+impl Component for NeverSleep {
+    type Storage = NullStorage<Self>;
+}
+
+/// Puts enemies to sleep and wakes them as the players move, as the module describes. With no
+/// player left, enemies stay as they are. Disabling sleeping wakes every enemy.
+pub struct SleepSystem;
+
+impl<'s> System<'s> for SleepSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Asleep>,
+        ReadStorage<'s, Enemy>,
+        ReadStorage<'s, NeverSleep>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Dead>,
+        ReadStorage<'s, Transform>,
+        Read<'s, SleepConfig>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut asleep, enemies, never, players, dead, transforms, config): Self::SystemData,
+    ) {
+        if !config.enabled {
+            asleep.clear();
+            return;
+        }
+        let players: Vec<Vector2<f32>> = (&players, &transforms, !&dead)
+            .join()
+            .map(|(_, transform, _)| world_position(transform))
+            .collect();
+        if players.is_empty() {
+            return;
+        }
+
+        for (entity, _, transform) in (&entities, &enemies, &transforms).join() {
+            let was_asleep = asleep.contains(entity);
+            let sleeps = !never.contains(entity) && {
+                let position = world_position(transform);
+                let distance = players
+                    .iter()
+                    .map(|player| (player - position).norm())
+                    .fold(f32::INFINITY, f32::min);
+                config.sleeps(was_asleep, distance)
+            };
+            if sleeps && !was_asleep {
+                asleep.insert(entity, Asleep).expect("Enemy is alive");
+            } else if !sleeps && was_asleep {
+                asleep.remove(entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, Entity, RunNow, World};
+
+    use super::*;
+    use crate::{
+        clock::GameClock,
+        movement::{MovementSystem, Velocity},
+    };
+
+    fn spawn_at(world: &mut World, x: f32) -> Entity {
+        let mut transform = Transform::default();
+        transform.set_translation_x(x);
+        world.create_entity().with(transform).build()
+    }
+
+    fn x(world: &World, entity: Entity) -> f32 {
+        world_position(world.read_storage().get(entity).unwrap()).x
+    }
+
+    #[test]
+    fn the_wake_distance_is_nearer_than_the_sleep_distance() {
+        let config = SleepConfig::default();
+        assert!(!config.sleeps(false, 1100.));
+        assert!(config.sleeps(false, 1300.));
+        assert!(config.sleeps(true, 1100.));
+        assert!(!config.sleeps(true, 900.));
+    }
+
+    #[test]
+    fn far_enemies_sleep_in_place_until_a_player_comes_near() {
+        let mut world = World::new();
+        let mut sleep = SleepSystem;
+        let mut movement = MovementSystem;
+        System::setup(&mut sleep, &mut world.res);
+        System::setup(&mut movement, &mut world.res);
+        world.write_resource::<SleepConfig>().enabled = true;
+        let player = spawn_at(&mut world, 0.);
+        world
+            .write_storage()
+            .insert(
+                player,
+                Player {
+                    index: 0,
+                    speed: 1.,
+                },
+            )
+            .unwrap();
+        let far = spawn_at(&mut world, 2000.);
+        let boss = spawn_at(&mut world, 3000.);
+        for &enemy in &[far, boss] {
+            world.write_storage().insert(enemy, Enemy).unwrap();
+            world
+                .write_storage()
+                .insert(enemy, Velocity(Vector2::new(10., 0.)))
+                .unwrap();
+        }
+        world.write_storage().insert(boss, NeverSleep).unwrap();
+
+        let step = |world: &mut World, sleep: &mut SleepSystem, movement: &mut MovementSystem| {
+            world.write_resource::<GameClock>().advance(1.);
+            sleep.run_now(&world.res);
+            movement.run_now(&world.res);
+            world.read_storage::<Asleep>().contains(far)
+        };
+        assert!(step(&mut world, &mut sleep, &mut movement));
+        assert!(step(&mut world, &mut sleep, &mut movement));
+        assert_eq!(x(&world, far), 2000.);
+        assert_eq!(x(&world, boss), 3020.);
+        assert_eq!(
+            world.read_storage::<Velocity>().get(far).unwrap().0,
+            Vector2::new(10., 0.)
+        );
+
+        // Past the sleep distance but not yet within the wake distance, it sleeps on.
+        world
+            .write_storage::<Transform>()
+            .get_mut(player)
+            .unwrap()
+            .set_translation_x(900.);
+        assert!(step(&mut world, &mut sleep, &mut movement));
+        world
+            .write_storage::<Transform>()
+            .get_mut(player)
+            .unwrap()
+            .set_translation_x(1500.);
+        assert!(!step(&mut world, &mut sleep, &mut movement));
+        assert_eq!(x(&world, far), 2010.);
+
+        world.write_resource::<SleepConfig>().enabled = false;
+        world
+            .write_storage::<Transform>()
+            .get_mut(player)
+            .unwrap()
+            .set_translation_x(-5000.);
+        assert!(!step(&mut world, &mut sleep, &mut movement));
+    }
+}
@@ -0,0 +1,281 @@
+//! One-off sounds, played once they have loaded.
+//!
+//! Sounds go out through the default audio device. Without one they are dropped quietly, so
+//! the game still runs on machines without audio.
+//!
+//! A sound may be played from a point in the world. With occlusion enabled in
+//! `sound_occlusion.ron`, such a sound is muffled by each opaque tile between it and the
+//! player it is clearest to, so a sound behind a wall is quieter than one as far away in the
+//! open, unless another player can hear it around the wall.
+//! Sounds played from nowhere in particular are heard as they are.
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    audio::{output::Output, OggFormat, Source, SourceHandle, WavFormat},
+    core::{math::Vector2, timing::Time, transform::Transform},
+    ecs::prelude::{Join, Read, ReadStorage, System, World, Write},
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{movement::world_position, player::Player, sight::SightGrid};
+
+/// Seconds a sound may take to load before it is given up on.
+const LOAD_TIMEOUT: f32 = 5.;
+
+/// How walls muffle sounds played from a point, read from `sound_occlusion.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SoundOcclusionConfig {
+    pub enabled: bool,
+    /// The fraction of a sound's volume that gets through each opaque tile in the way.
+    pub occlusion: f32,
+}
+
+impl Default for SoundOcclusionConfig {
+    fn default() -> Self {
+        SoundOcclusionConfig {
+            enabled: true,
+            occlusion: 0.4,
+        }
+    }
+}
+
+impl SoundOcclusionConfig {
+    /// `volume` once muffled by `walls` opaque tiles.
+    pub fn occluded_volume(&self, volume: f32, walls: usize) -> f32 {
+        if !self.enabled || walls == 0 {
+            return volume;
+        }
+        let kept = self.occlusion.clamp(0., 1.);
+        volume * kept.powi(walls.min(i32::MAX as usize) as i32)
+    }
+}
+
+/// A sound waiting to load.
+struct QueuedSound {
+    path: String,
+    handle: SourceHandle,
+    volume: f32,
+    /// Where in the world it is played from, if anywhere.
+    position: Option<Vector2<f32>>,
+    /// Seconds it has waited to load.
+    waited: f32,
+}
+
+/// Sounds waiting to load.
+#[derive(Default)]
+pub struct SoundQueue(Vec<QueuedSound>);
+
+impl SoundQueue {
+    /// Moves where the waiting sounds are played from by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        for position in self
+            .0
+            .iter_mut()
+            .filter_map(|sound| sound.position.as_mut())
+        {
+            *position += offset;
+        }
+    }
+}
+
+/// Loads the sound at `path`, relative to the `resources` directory, and queues it to play.
+/// Ogg and wav files are supported.
+pub fn play_sound(world: &World, path: &str) {
+    queue_sound(
+        &world.read_resource(),
+        &world.read_resource(),
+        &mut world.write_resource(),
+        path,
+        1.,
+    );
+}
+
+/// Plays the sound at `path` like `play_sound` does, from `position` in the world.
+pub fn play_sound_at(world: &World, path: &str, position: Vector2<f32>) {
+    queue_sound_at(
+        &world.read_resource(),
+        &world.read_resource(),
+        &mut world.write_resource(),
+        path,
+        1.,
+        position,
+    );
+}
+
+/// Loads the sound at `path` like `play_sound` does, and queues it to play at `volume`, from
+/// `0` for silent to `1` for as loud as it was recorded. For systems without the `World`.
+pub fn queue_sound(
+    loader: &Loader,
+    storage: &AssetStorage<Source>,
+    queue: &mut SoundQueue,
+    path: &str,
+    volume: f32,
+) {
+    enqueue(loader, storage, queue, path, volume, None);
+}
+
+/// Queues the sound at `path` like `queue_sound` does, played from `position` in the world.
+pub fn queue_sound_at(
+    loader: &Loader,
+    storage: &AssetStorage<Source>,
+    queue: &mut SoundQueue,
+    path: &str,
+    volume: f32,
+    position: Vector2<f32>,
+) {
+    enqueue(loader, storage, queue, path, volume, Some(position));
+}
+
+fn enqueue(
+    loader: &Loader,
+    storage: &AssetStorage<Source>,
+    queue: &mut SoundQueue,
+    path: &str,
+    volume: f32,
+    position: Option<Vector2<f32>>,
+) {
+    let handle = if path.ends_with(".ogg") {
+        loader.load(path, OggFormat, (), storage)
+    } else if path.ends_with(".wav") {
+        loader.load(path, WavFormat, (), storage)
+    } else {
+        warn!("Can't play {}: only ogg and wav sounds are supported", path);
+        return;
+    };
+    queue.0.push(QueuedSound {
+        path: path.to_string(),
+        handle,
+        volume,
+        position,
+        waited: 0.,
+    });
+}
+
+/// How many opaque tiles are between `position` and whichever of `listeners` has the fewest
+/// in the way, or none without any listeners.
+pub fn fewest_walls(
+    sight: &SightGrid,
+    position: Vector2<f32>,
+    listeners: &[Vector2<f32>],
+) -> usize {
+    listeners
+        .iter()
+        .map(|&listener| sight.opaque_between(position, listener))
+        .min()
+        .unwrap_or(0)
+}
+
+/// Plays each queued sound as soon as it has loaded, muffled as the module describes.
+pub struct SoundSystem {
+    config: SoundOcclusionConfig,
+}
+
+impl SoundSystem {
+    pub fn new(config: SoundOcclusionConfig) -> Self {
+        SoundSystem { config }
+    }
+}
+
+impl<'s> System<'s> for SoundSystem {
+    type SystemData = (
+        Write<'s, SoundQueue>,
+        Read<'s, AssetStorage<Source>>,
+        Option<Read<'s, Output>>,
+        Read<'s, Time>,
+        Read<'s, SightGrid>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Transform>,
+    );
+
+    fn run(
+        &mut self,
+        (mut queue, sources, output, time, sight, players, transforms): Self::SystemData,
+    ) {
+        let listeners: Vec<_> = (&players, &transforms)
+            .join()
+            .map(|(_, transform)| world_position(transform))
+            .collect();
+        let config = self.config;
+        queue.0.retain_mut(|sound| {
+            if let Some(source) = sources.get(&sound.handle) {
+                if let Some(output) = output.as_ref() {
+                    let walls = sound
+                        .position
+                        .map_or(0, |position| fewest_walls(&sight, position, &listeners));
+                    output.play_once(source, config.occluded_volume(sound.volume, walls));
+                }
+                return false;
+            }
+            sound.waited += time.delta_real_seconds();
+            if sound.waited > LOAD_TIMEOUT {
+                warn!("Gave up on playing {}, which didn't load", sound.path);
+                return false;
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile_map::{Tile, TileMap};
+
+    /// A row of six tiles with a wall second from the left.
+    fn walled() -> SightGrid {
+        let floor = Tile::Static(0);
+        let wall = Tile::Static(1);
+        SightGrid::from_map(&TileMap {
+            width: 6,
+            height: 1,
+            tile_size: 16.,
+            tiles: vec![floor, wall, floor, floor, floor, floor],
+            opaque: vec![wall],
+            ..TileMap::default()
+        })
+    }
+
+    #[test]
+    fn each_wall_muffles_a_sound_further() {
+        let config = SoundOcclusionConfig {
+            enabled: true,
+            occlusion: 0.5,
+        };
+        let volumes: Vec<f32> = (0..3)
+            .map(|walls| config.occluded_volume(0.8, walls))
+            .collect();
+        assert_eq!(volumes, [0.8, 0.4, 0.2]);
+        let disabled = SoundOcclusionConfig {
+            enabled: false,
+            ..config
+        };
+        assert_eq!(disabled.occluded_volume(0.8, 2), 0.8);
+    }
+
+    #[test]
+    fn a_sound_behind_a_wall_is_quieter_than_one_in_the_open() {
+        let sight = walled();
+        let config = SoundOcclusionConfig::default();
+        let sound = Vector2::new(40., 8.);
+        // Both listeners are two tiles from the sound, one of them through the wall.
+        let behind = Vector2::new(8., 8.);
+        let open = Vector2::new(72., 8.);
+        let muffled = config.occluded_volume(1., fewest_walls(&sight, sound, &[behind]));
+        let clear = config.occluded_volume(1., fewest_walls(&sight, sound, &[open]));
+        assert_eq!(clear, 1.);
+        assert!(muffled < clear);
+    }
+
+    #[test]
+    fn sounds_are_heard_by_the_player_with_the_fewest_walls_in_the_way() {
+        let sight = walled();
+        let sound = Vector2::new(40., 8.);
+        // The nearer listener is behind the wall.
+        let listeners = [Vector2::new(8., 8.), Vector2::new(88., 8.)];
+        assert_eq!(fewest_walls(&sight, sound, &listeners), 0);
+        assert_eq!(fewest_walls(&sight, sound, &listeners[..1]), 1);
+        assert_eq!(fewest_walls(&sight, sound, &[]), 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! A uniform grid of every collider's position, rebuilt each gameplay step, for finding what
+//! is near a point without checking every entity.
+//!
+//! Systems looking for colliders near a point or in an area ask the grid with `query_radius`
+//! or `query_rect`, narrowing what they get with a filter, rather than checking every entity
+//! themselves. The cell size is read from `spatial.ron`.
+
+use std::collections::HashMap;
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{Entities, Entity, Join, ReadStorage, System, Write},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::{Aabb, Collider},
+    movement::world_position,
+};
+
+/// How the `SpatialGrid` is laid out, read from `spatial.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpatialConfig {
+    /// World units across a cell. Queries much larger than a cell check many cells, queries
+    /// much smaller check many entities they don't want.
+    pub cell_size: f32,
+}
+
+impl Default for SpatialConfig {
+    fn default() -> Self {
+        SpatialConfig { cell_size: 64. }
+    }
+}
+
+/// The entities in one cell, with their positions.
+type Cell = Vec<(Entity, Vector2<f32>)>;
+
+/// Entities bucketed by the square cell of `cell_size` world units their position falls in.
+#[derive(Clone, Debug)]
+pub struct SpatialGrid {
+    pub cell_size: f32,
+    cells: HashMap<(i32, i32), Cell>,
+}
+
+impl Default for SpatialGrid {
+    fn default() -> Self {
+        SpatialGrid::new(64.)
+    }
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialGrid {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, position: Vector2<f32>) -> (i32, i32) {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vector2<f32>) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push((entity, position));
+    }
+
+    /// Every entity in the cells overlapping the area from `min` to `max`, with its position.
+    fn around(
+        &self,
+        min: Vector2<f32>,
+        max: Vector2<f32>,
+    ) -> impl Iterator<Item = (Entity, Vector2<f32>)> + '_ {
+        let (min, max) = (self.cell(min), self.cell(max));
+        (min.1..=max.1)
+            .flat_map(move |y| (min.0..=max.0).map(move |x| (x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+
+    /// Every entity within `radius` of `center`, with its position. The order is unspecified.
+    pub fn within(
+        &self,
+        center: Vector2<f32>,
+        radius: f32,
+    ) -> impl Iterator<Item = (Entity, Vector2<f32>)> + '_ {
+        let reach = Vector2::new(radius, radius);
+        self.around(center - reach, center + reach)
+            .filter(move |(_, position)| (position - center).norm() <= radius)
+    }
+
+    /// The entities within `radius` of `center` that pass `filter`, in entity order.
+    pub fn query_radius(
+        &self,
+        center: Vector2<f32>,
+        radius: f32,
+        filter: impl Fn(Entity) -> bool,
+    ) -> Vec<Entity> {
+        sorted(
+            self.within(center, radius).map(|(entity, _)| entity),
+            filter,
+        )
+    }
+
+    /// The entities whose positions are inside `rect`, edges included, that pass `filter`, in
+    /// entity order.
+    pub fn query_rect(&self, rect: &Aabb, filter: impl Fn(Entity) -> bool) -> Vec<Entity> {
+        let inside = |position: Vector2<f32>| {
+            position.x >= rect.min.x
+                && position.x <= rect.max.x
+                && position.y >= rect.min.y
+                && position.y <= rect.max.y
+        };
+        sorted(
+            self.around(rect.min, rect.max)
+                .filter(|&(_, position)| inside(position))
+                .map(|(entity, _)| entity),
+            filter,
+        )
+    }
+}
+
+/// The `entities` that pass `filter`, ordered by id so the results don't depend on how the
+/// cells are stored.
+fn sorted(entities: impl Iterator<Item = Entity>, filter: impl Fn(Entity) -> bool) -> Vec<Entity> {
+    let mut found: Vec<_> = entities.filter(|&entity| filter(entity)).collect();
+    found.sort_by_key(|entity| entity.id());
+    found
+}
+
+/// Refills the `SpatialGrid` with every entity that has a `Collider`.
+pub struct SpatialGridSystem;
+
+impl<'s> System<'s> for SpatialGridSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Collider>,
+        ReadStorage<'s, Transform>,
+        Write<'s, SpatialGrid>,
+    );
+
+    fn run(&mut self, (entities, colliders, transforms, mut grid): Self::SystemData) {
+        grid.clear();
+        for (entity, _, transform) in (&entities, &colliders, &transforms).join() {
+            grid.insert(entity, world_position(transform));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, World};
+
+    use super::*;
+
+    #[test]
+    fn queries_find_what_is_near_across_cells() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..3).map(|_| world.create_entity().build()).collect();
+        let mut grid = SpatialGrid::new(10.);
+        grid.insert(entities[0], Vector2::new(9., 0.));
+        grid.insert(entities[1], Vector2::new(12., 0.));
+        grid.insert(entities[2], Vector2::new(40., 0.));
+
+        assert_eq!(
+            grid.query_radius(Vector2::new(10., 0.), 3., |_| true),
+            [entities[0], entities[1]]
+        );
+        assert_eq!(
+            grid.query_radius(Vector2::new(10., 0.), 3., |entity| entity != entities[0]),
+            [entities[1]]
+        );
+        let rect = Aabb {
+            min: Vector2::new(12., -1.),
+            max: Vector2::new(40., 1.),
+        };
+        assert_eq!(grid.query_rect(&rect, |_| true), [entities[1], entities[2]]);
+
+        grid.clear();
+        assert!(grid.query_rect(&rect, |_| true).is_empty());
+    }
+}
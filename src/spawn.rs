@@ -0,0 +1,273 @@
+//! Finding room for entities to spawn in, clear of solid tiles and of each other.
+//!
+//! Spawners ask `spawn_position` where to put an entity before creating it. A position that is
+//! already clear is used as it is. Otherwise the entity goes to the centre of the nearest tile
+//! with room for it, within the `SpawnConfig`'s search radius. When there is no such tile the
+//! spawner is told so, and enemies are left out rather than spawned in a wall or on someone.
+//!
+//! Players arriving in a level are protected for a moment, so they aren't swarmed before they
+//! have their bearings: they are invulnerable, and enemies spawning near them are moved out to
+//! the edge of the `protection_radius` around them, or further. Enemies the map itself places
+//! are left where the map puts them.
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{Entity, Join, Read, System, World, Write},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock,
+    collision::{Aabb, Collider},
+    combat::grant_invulnerability,
+    movement::world_position,
+    navigation::NavGrid,
+};
+
+/// How far spawns may be moved to find room, read from `spawn.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpawnConfig {
+    /// The furthest, in world units, an entity is moved from where it was meant to spawn.
+    pub search_radius: f32,
+    /// How near players arriving in a level enemies may not spawn, in world units.
+    pub protection_radius: f32,
+    /// Seconds of game time players arriving in a level are protected for. `0` protects them
+    /// not at all.
+    pub protection_duration: f32,
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        SpawnConfig {
+            search_radius: 96.,
+            protection_radius: 160.,
+            protection_duration: 2.,
+        }
+    }
+}
+
+/// An area enemies may not spawn in, of `radius` around `center`.
+#[derive(Clone, Copy, Debug)]
+pub struct KeepOut {
+    pub center: Vector2<f32>,
+    pub radius: f32,
+}
+
+impl KeepOut {
+    pub fn contains(&self, point: Vector2<f32>) -> bool {
+        (point - self.center).norm() < self.radius
+    }
+}
+
+/// The players still protected after arriving in the level, with the seconds each has left.
+#[derive(Clone, Debug, Default)]
+pub struct SpawnProtection {
+    protected: Vec<(Entity, f32)>,
+}
+
+impl SpawnProtection {
+    /// Protects `player` for `duration` seconds, keeping any longer protection it has.
+    pub fn protect(&mut self, player: Entity, duration: f32) {
+        match self
+            .protected
+            .iter_mut()
+            .find(|(entity, _)| *entity == player)
+        {
+            Some((_, remaining)) => *remaining = remaining.max(duration),
+            None if duration > 0. => self.protected.push((player, duration)),
+            None => {}
+        }
+    }
+
+    /// Counts the protection down by `delta` seconds, dropping what has run out.
+    pub fn step(&mut self, delta: f32) {
+        for (_, remaining) in &mut self.protected {
+            *remaining -= delta;
+        }
+        self.protected.retain(|&(_, remaining)| remaining > 0.);
+    }
+
+    /// The players still protected.
+    pub fn players(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.protected.iter().map(|&(entity, _)| entity)
+    }
+}
+
+/// Protects `player`, just arrived in the level, as the module describes.
+pub fn protect_spawn(world: &mut World, player: Entity) {
+    let duration = world.read_resource::<SpawnConfig>().protection_duration;
+    grant_invulnerability(&mut world.write_storage(), player, duration);
+    world
+        .write_resource::<SpawnProtection>()
+        .protect(player, duration);
+}
+
+/// Counts down the protection of players arriving in the level.
+pub struct SpawnProtectionSystem;
+
+impl<'s> System<'s> for SpawnProtectionSystem {
+    type SystemData = (Write<'s, SpawnProtection>, Read<'s, GameClock>);
+
+    fn run(&mut self, (mut protection, clock): Self::SystemData) {
+        protection.step(clock.delta_seconds());
+    }
+}
+
+/// The position nearest `desired`, and no further than `radius` from it, where a box of
+/// `half_extents` stands only on walkable tiles, overlaps none of the `occupied` boxes and is
+/// outside every `keep_out` area. `desired` itself if it will do, and otherwise the centre of a
+/// tile.
+pub fn find_valid_spawn(
+    grid: &NavGrid,
+    occupied: &[Aabb],
+    keep_out: &[KeepOut],
+    desired: Vector2<f32>,
+    half_extents: Vector2<f32>,
+    radius: f32,
+) -> Option<Vector2<f32>> {
+    let fits = |center: Vector2<f32>| {
+        let area = Aabb::from_center(center, half_extents);
+        grid.is_open(&area)
+            && !occupied.iter().any(|other| other.overlaps(&area))
+            && !keep_out.iter().any(|zone| zone.contains(center))
+    };
+    if fits(desired) {
+        return Some(desired);
+    }
+    grid.tiles()
+        .map(|tile| grid.tile_center(tile))
+        .map(|center| (center, (center - desired).norm()))
+        .filter(|&(center, distance)| distance <= radius && fits(center))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(center, _)| center)
+}
+
+/// Where an entity with `collider` meant to spawn at `desired` should go, clear of the
+/// `NavGrid`'s solid tiles and of every collider already in `world`, or `None` when there is
+/// no room within the search radius.
+pub fn spawn_position(
+    world: &World,
+    desired: Vector2<f32>,
+    collider: &Collider,
+) -> Option<Vector2<f32>> {
+    position_clear_of(world, desired, collider, &[])
+}
+
+/// Like `spawn_position`, for an enemy, kept away from protected players too. The search
+/// reaches past the edge of the protected areas, so an enemy meant to spawn in one still can.
+pub fn enemy_spawn_position(
+    world: &World,
+    desired: Vector2<f32>,
+    collider: &Collider,
+) -> Option<Vector2<f32>> {
+    let radius = world.read_resource::<SpawnConfig>().protection_radius;
+    let transforms = world.read_storage::<Transform>();
+    let keep_out: Vec<_> = world
+        .read_resource::<SpawnProtection>()
+        .players()
+        .filter_map(|player| transforms.get(player))
+        .map(|transform| KeepOut {
+            center: world_position(transform),
+            radius,
+        })
+        .collect();
+    position_clear_of(world, desired, collider, &keep_out)
+}
+
+fn position_clear_of(
+    world: &World,
+    desired: Vector2<f32>,
+    collider: &Collider,
+    keep_out: &[KeepOut],
+) -> Option<Vector2<f32>> {
+    let occupied: Vec<_> = (
+        &world.read_storage::<Transform>(),
+        &world.read_storage::<Collider>(),
+    )
+        .join()
+        .map(|(transform, other)| Aabb::from_center(world_position(transform), other.half_extents))
+        .collect();
+    let reach = keep_out.iter().map(|zone| zone.radius).fold(0., f32::max);
+    let radius = world.read_resource::<SpawnConfig>().search_radius + reach;
+    find_valid_spawn(
+        &world.read_resource::<NavGrid>(),
+        &occupied,
+        keep_out,
+        desired,
+        collider.half_extents,
+        radius,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile_map::{Tile, TileMap};
+
+    const WALL: Tile = Tile::Static(1);
+
+    /// A map of 16-unit tiles, rows from the top, with `#` a wall and `.` floor.
+    fn grid(rows: &[&str]) -> NavGrid {
+        NavGrid::from_map(&TileMap {
+            width: rows[0].len(),
+            height: rows.len(),
+            tile_size: 16.,
+            tiles: rows
+                .iter()
+                .flat_map(|row| row.chars())
+                .map(|tile| if tile == '#' { WALL } else { Tile::Static(0) })
+                .collect(),
+            solid: vec![WALL],
+            ..TileMap::default()
+        })
+    }
+
+    fn half() -> Vector2<f32> {
+        Vector2::new(6., 6.)
+    }
+
+    #[test]
+    fn clear_positions_are_kept() {
+        let grid = grid(&["...", "...", "..."]);
+        let desired = Vector2::new(20., 20.);
+        assert_eq!(
+            find_valid_spawn(&grid, &[], &[], desired, half(), 32.),
+            Some(desired)
+        );
+    }
+
+    #[test]
+    fn spawns_in_walls_move_to_the_nearest_open_tile() {
+        let grid = grid(&["###", "##.", "###"]);
+        let found = find_valid_spawn(&grid, &[], &[], Vector2::new(24., 24.), half(), 32.);
+        assert_eq!(found, Some(grid.tile_center((2, 1))));
+    }
+
+    #[test]
+    fn spawns_with_no_room_are_rejected() {
+        let walled_in = grid(&["###", "###", "###"]);
+        let desired = Vector2::new(24., 24.);
+        assert_eq!(
+            find_valid_spawn(&walled_in, &[], &[], desired, half(), 64.),
+            None
+        );
+
+        let open = grid(&["...", "...", "..."]);
+        let everywhere = KeepOut {
+            center: desired,
+            radius: 100.,
+        };
+        assert_eq!(
+            find_valid_spawn(&open, &[], &[everywhere], desired, half(), 64.),
+            None
+        );
+
+        let taken = Aabb::from_center(desired, Vector2::new(6., 6.));
+        let open_tile = grid(&["###", "#.#", "###"]);
+        assert_eq!(
+            find_valid_spawn(&open_tile, &[taken], &[], desired, half(), 64.),
+            None
+        );
+    }
+}
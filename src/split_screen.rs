@@ -0,0 +1,472 @@
+//! Two-player split-screen: each half of the window is rendered through its own camera.
+//!
+//! Each view is drawn into its own half-width image by render groups wrapped in a
+//! `ViewGroupDesc`, which points `ActiveCamera` at the view's camera while the group prepares
+//! its uniforms. The `SplitScreenCompositeDesc` node then copies both halves side by side into
+//! the image that gets presented.
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Entity, Join, Read, ReadStorage, Resources,
+        System, SystemData, World, Write,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::{
+        camera::{ActiveCamera, Camera},
+        rendy::{
+            command::{
+                CommandBuffer, CommandPool, ExecutableState, Family, MultiShot, PendingState,
+                QueueId, RenderPassEncoder, SimultaneousUse, Submit, Transfer,
+            },
+            factory::Factory,
+            frame::Frames,
+            graph::{
+                gfx_acquire_barriers, gfx_release_barriers,
+                render::{PrepareResult, RenderGroup, RenderGroupDesc},
+                BufferAccess, GraphContext, ImageAccess, Node, NodeBuffer, NodeDesc, NodeImage,
+                NodeSubmittable,
+            },
+            hal,
+        },
+        types::Backend,
+    },
+    window::ScreenDimensions,
+};
+
+use crate::{
+    camera::{centered_projection, CameraFollow, CameraZoom, Room, RoomBounds, RoomBoundsConfig},
+    player::Player,
+};
+
+/// Whether the window is currently split, and where the UI is drawn when it is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SplitScreen {
+    pub enabled: bool,
+    pub ui: UiPlacement,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UiPlacement {
+    /// The UI is drawn once across the whole window, on top of both views.
+    #[default]
+    Shared,
+    /// The UI is drawn inside each view.
+    PerView,
+}
+
+/// Marks the camera rendering view `0` (left) or `1` (right) while split-screen is enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitView(pub usize);
+
+impl Component for SplitView {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Pixel widths of the left and right views for a window `total` pixels wide.
+pub fn split_widths(total: u32) -> (u32, u32) {
+    let left = total / 2;
+    (left, total - left)
+}
+
+/// The size in pixels of `view` when a `screen`-sized window is split in two.
+pub fn view_size(view: usize, screen: Vector2<f32>) -> Vector2<f32> {
+    let (left, right) = split_widths(screen.x as u32);
+    let width = if view == 0 { left } else { right };
+    Vector2::new(width as f32, screen.y)
+}
+
+/// Flips `SplitScreen::enabled` when the `toggle_split_screen` action is pressed.
+#[derive(Default)]
+pub struct SplitScreenToggleSystem {
+    was_pressed: bool,
+}
+
+impl<'s> System<'s> for SplitScreenToggleSystem {
+    type SystemData = (
+        Write<'s, SplitScreen>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(&mut self, (mut split_screen, input): Self::SystemData) {
+        let pressed = input.action_is_down("toggle_split_screen").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            split_screen.enabled = !split_screen.enabled;
+        }
+        self.was_pressed = pressed;
+    }
+}
+
+/// Creates a split-screen camera for each of the first two players, each following its own
+/// player. These only render while `SplitScreen` is enabled.
+pub fn create_split_views(world: &mut World, rooms: &[Room]) {
+    let (width, height) = {
+        let dim = world.read_resource::<ScreenDimensions>();
+        (dim.width(), dim.height())
+    };
+
+    let players: Vec<_> = {
+        let entities = world.entities();
+        let players = world.read_storage::<Player>();
+        (&entities, &players)
+            .join()
+            .filter(|(_, player)| player.index < 2)
+            .map(|(entity, player)| (entity, player.index))
+            .collect()
+    };
+
+    let room_bounds = world.read_resource::<RoomBoundsConfig>().enabled && !rooms.is_empty();
+    for (player, view) in players {
+        let zoom = CameraZoom::new(1., 0.5, 1.);
+        let mut camera_transform = Transform::default();
+        camera_transform.set_translation_xyz(width / 2., height / 2., 1.);
+
+        let builder = world
+            .create_entity()
+            .with(camera_transform)
+            .with(Camera::from(centered_projection(
+                view_size(view, Vector2::new(width, height)),
+                zoom.level(),
+            )))
+            .with(zoom)
+            .with(SplitView(view))
+            .with(CameraFollow::new(player, 6.));
+        if room_bounds {
+            builder
+                .with(RoomBounds::new(player, rooms.to_vec()))
+                .build();
+        } else {
+            builder.build();
+        }
+    }
+}
+
+fn view_camera(res: &Resources, view: usize) -> Option<Entity> {
+    let (entities, views) = <(Entities<'_>, ReadStorage<'_, SplitView>)>::fetch(res);
+    (&entities, &views)
+        .join()
+        .find(|(_, split_view)| split_view.0 == view)
+        .map(|(entity, _)| entity)
+}
+
+/// Wraps a render group so it renders through the camera assigned to `view`.
+///
+/// If no camera is assigned to the view the shared `ActiveCamera` is used instead.
+#[derive(Debug)]
+pub struct ViewGroupDesc<D> {
+    view: usize,
+    inner: D,
+}
+
+impl<D> ViewGroupDesc<D> {
+    pub fn new(view: usize, inner: D) -> Self {
+        ViewGroupDesc { view, inner }
+    }
+}
+
+impl<B, D> RenderGroupDesc<B, Resources> for ViewGroupDesc<D>
+where
+    B: Backend,
+    D: RenderGroupDesc<B, Resources>,
+{
+    fn buffers(&self) -> Vec<BufferAccess> {
+        self.inner.buffers()
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        self.inner.images()
+    }
+
+    fn colors(&self) -> usize {
+        self.inner.colors()
+    }
+
+    fn depth(&self) -> bool {
+        self.inner.depth()
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        res: &Resources,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+        subpass: hal::pass::Subpass<'_, B>,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Box<dyn RenderGroup<B, Resources>>, failure::Error> {
+        let inner = self.inner.build(
+            ctx,
+            factory,
+            queue,
+            res,
+            framebuffer_width,
+            framebuffer_height,
+            subpass,
+            buffers,
+            images,
+        )?;
+        Ok(Box::new(ViewGroup {
+            view: self.view,
+            inner,
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct ViewGroup<B: Backend> {
+    view: usize,
+    inner: Box<dyn RenderGroup<B, Resources>>,
+}
+
+impl<B: Backend> RenderGroup<B, Resources> for ViewGroup<B> {
+    fn prepare(
+        &mut self,
+        factory: &Factory<B>,
+        queue: QueueId,
+        index: usize,
+        subpass: hal::pass::Subpass<'_, B>,
+        res: &Resources,
+    ) -> PrepareResult {
+        let camera = view_camera(res, self.view);
+        let previous = res.try_fetch_mut::<ActiveCamera>().map(|mut active| {
+            let previous = active.entity;
+            active.entity = camera.or(previous);
+            previous
+        });
+
+        let result = self.inner.prepare(factory, queue, index, subpass, res);
+
+        if let (Some(previous), Some(mut active)) = (previous, res.try_fetch_mut::<ActiveCamera>())
+        {
+            active.entity = previous;
+        }
+        result
+    }
+
+    fn draw_inline(
+        &mut self,
+        encoder: RenderPassEncoder<'_, B>,
+        index: usize,
+        subpass: hal::pass::Subpass<'_, B>,
+        res: &Resources,
+    ) {
+        self.inner.draw_inline(encoder, index, subpass, res)
+    }
+
+    fn dispose(self: Box<Self>, factory: &mut Factory<B>, res: &Resources) {
+        self.inner.dispose(factory, res)
+    }
+}
+
+/// Copies the left and right view images side by side into a window-sized image.
+///
+/// Images must be added in the order left view, right view, target.
+#[derive(Debug, Default)]
+pub struct SplitScreenCompositeDesc;
+
+fn transfer_access(write: bool) -> ImageAccess {
+    if write {
+        ImageAccess {
+            access: hal::image::Access::TRANSFER_WRITE,
+            layout: hal::image::Layout::TransferDstOptimal,
+            usage: hal::image::Usage::TRANSFER_DST,
+            stages: hal::pso::PipelineStage::TRANSFER,
+        }
+    } else {
+        ImageAccess {
+            access: hal::image::Access::TRANSFER_READ,
+            layout: hal::image::Layout::TransferSrcOptimal,
+            usage: hal::image::Usage::TRANSFER_SRC,
+            stages: hal::pso::PipelineStage::TRANSFER,
+        }
+    }
+}
+
+fn color_layers(image: &NodeImage) -> hal::image::SubresourceLayers {
+    hal::image::SubresourceLayers {
+        aspects: image.range.aspects,
+        level: 0,
+        layers: image.range.layers.start..image.range.layers.start + 1,
+    }
+}
+
+impl<B: Backend, T: ?Sized> NodeDesc<B, T> for SplitScreenCompositeDesc {
+    type Node = SplitScreenComposite<B>;
+
+    fn images(&self) -> Vec<ImageAccess> {
+        vec![
+            transfer_access(false),
+            transfer_access(false),
+            transfer_access(true),
+        ]
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        _queue: usize,
+        _aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, failure::Error> {
+        assert!(buffers.is_empty());
+        assert_eq!(images.len(), 3);
+
+        let mut pool = factory.create_command_pool(family)?;
+        let initial = pool
+            .allocate_buffers(1)
+            .pop()
+            .expect("Command pool must allocate the requested buffer");
+        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+        let mut encoder = recording.encoder();
+
+        let target = &images[2];
+        let target_image = ctx.get_image(target.id).expect("Image does not exist");
+
+        unsafe {
+            let (stages, barriers) = gfx_acquire_barriers(ctx, None, &images);
+            encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+
+            let mut offset_x = 0;
+            for source in &images[..2] {
+                let source_image = ctx.get_image(source.id).expect("Image does not exist");
+                let extent = source_image.kind().extent();
+                encoder.copy_image(
+                    source_image.raw(),
+                    source.layout,
+                    target_image.raw(),
+                    target.layout,
+                    Some(hal::command::ImageCopy {
+                        src_subresource: color_layers(source),
+                        src_offset: hal::image::Offset::ZERO,
+                        dst_subresource: color_layers(target),
+                        dst_offset: hal::image::Offset {
+                            x: offset_x,
+                            y: 0,
+                            z: 0,
+                        },
+                        extent,
+                    }),
+                );
+                offset_x += extent.width as i32;
+            }
+
+            let (stages, barriers) = gfx_release_barriers(ctx, None, &images);
+            encoder.pipeline_barrier(stages, hal::memory::Dependencies::empty(), barriers);
+        }
+
+        let (submit, buffer) = recording.finish().submit();
+        Ok(SplitScreenComposite {
+            pool,
+            submit,
+            buffer,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SplitScreenComposite<B: Backend> {
+    pool: CommandPool<B, hal::QueueType>,
+    submit: Submit<B, SimultaneousUse>,
+    buffer:
+        CommandBuffer<B, hal::QueueType, PendingState<ExecutableState<MultiShot<SimultaneousUse>>>>,
+}
+
+impl<'a, B: Backend> NodeSubmittable<'a, B> for SplitScreenComposite<B> {
+    type Submittable = &'a Submit<B, SimultaneousUse>;
+    type Submittables = Option<&'a Submit<B, SimultaneousUse>>;
+}
+
+impl<B: Backend, T: ?Sized> Node<B, T> for SplitScreenComposite<B> {
+    type Capability = Transfer;
+    type Desc = SplitScreenCompositeDesc;
+
+    fn run<'a>(
+        &'a mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &Factory<B>,
+        _aux: &T,
+        _frames: &'a Frames<B>,
+    ) -> Option<&'a Submit<B, SimultaneousUse>> {
+        Some(&self.submit)
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, _aux: &T) {
+        self.pool.free_buffers(Some(self.buffer.mark_complete()));
+        factory.destroy_command_pool(self.pool);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{core::timing::Time, ecs::prelude::RunNow};
+
+    use super::*;
+    use crate::{camera::CameraFollowSystem, movement::world_position};
+
+    #[test]
+    fn odd_widths_give_the_spare_pixel_to_the_right() {
+        assert_eq!(split_widths(801), (400, 401));
+        assert_eq!(
+            view_size(1, Vector2::new(801., 600.)),
+            Vector2::new(401., 600.)
+        );
+        assert_eq!(
+            view_size(0, Vector2::new(801., 600.)),
+            Vector2::new(400., 600.)
+        );
+    }
+
+    #[test]
+    fn each_view_tracks_its_own_player_as_they_move_apart() {
+        let mut world = World::new();
+        let mut follow = CameraFollowSystem;
+        System::setup(&mut follow, &mut world.res);
+        world.add_resource(ScreenDimensions::new(800, 600, 1.));
+        world.add_resource(RoomBoundsConfig::default());
+        world.register::<Camera>();
+        world.register::<CameraZoom>();
+        world.register::<SplitView>();
+        world.write_resource::<Time>().set_delta_seconds(1. / 60.);
+        let players: Vec<_> = [-10., 10.]
+            .iter()
+            .enumerate()
+            .map(|(index, &x)| {
+                let mut transform = Transform::default();
+                transform.set_translation_xyz(400. + x, 300., 0.);
+                world
+                    .create_entity()
+                    .with(transform)
+                    .with(Player { index, speed: 120. })
+                    .build()
+            })
+            .collect();
+        create_split_views(&mut world, &[]);
+
+        let position = |world: &World, entity| {
+            world_position(world.read_storage::<Transform>().get(entity).unwrap())
+        };
+        for frame in 0..360 {
+            // Apart for two seconds, then still while the cameras settle.
+            if frame < 120 {
+                let mut transforms = world.write_storage::<Transform>();
+                transforms.get_mut(players[0]).unwrap().move_left(2.);
+                transforms.get_mut(players[1]).unwrap().move_right(2.);
+            }
+            follow.run_now(&world.res);
+        }
+        for (view, &player) in players.iter().enumerate() {
+            let camera = view_camera(&world.res, view).unwrap();
+            assert!((position(&world, camera) - position(&world, player)).norm() < 0.5);
+        }
+        assert_eq!(
+            position(&world, players[1]).x - position(&world, players[0]).x,
+            500.
+        );
+    }
+}
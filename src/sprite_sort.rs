@@ -0,0 +1,189 @@
+//! Which sprites are drawn, in what order, without coplanar sprites flickering.
+//!
+//! This does what the engine's sprite visibility sorting does: sprites behind the camera or
+//! hidden are left out, and `Transparent` sprites are drawn back to front by their `z`. The
+//! engine compares depths measured from the camera, so two sprites at the same `z` may swap
+//! places as rounding moves with the camera. Here depths are rounded to `precision` and ties
+//! are broken by entity: of two sprites at the same depth, the one with the lower entity id is
+//! drawn first, behind the other. No two live entities share an id, so that order holds frame
+//! after frame for as long as both live.
+
+use std::cmp::Ordering;
+
+use amethyst::{
+    core::{math::Vector3, transform::Transform, Hidden, HiddenPropagate},
+    ecs::prelude::{Entities, Entity, Join, Read, ReadStorage, System, Write},
+    renderer::{
+        camera::{ActiveCamera, Camera},
+        sprite_visibility::SpriteVisibility,
+        transparent::Transparent,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// How close sprites' depths have to be to count as the same, read from `sprite_sort.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpriteSortConfig {
+    /// Depths are rounded to a multiple of this before they are compared, in world units.
+    pub precision: f32,
+}
+
+impl Default for SpriteSortConfig {
+    fn default() -> Self {
+        SpriteSortConfig { precision: 1e-4 }
+    }
+}
+
+/// Orders two sprites at depths `a` and `b` so the one further back is drawn first, with ties
+/// at `precision` broken by entity.
+pub fn draw_order(a: (Entity, f32), b: (Entity, f32), precision: f32) -> Ordering {
+    let round = |depth: f32| {
+        if precision > 0. {
+            (depth / precision).round()
+        } else {
+            depth
+        }
+    };
+    round(a.1)
+        .total_cmp(&round(b.1))
+        .then(a.0.id().cmp(&b.0.id()))
+}
+
+/// Fills `SpriteVisibility` with the visible sprites, the transparent ones in `draw_order`.
+/// Stands in for the engine's `SpriteVisibilitySortingSystem`.
+#[derive(Default)]
+pub struct SpriteSortSystem {
+    transparent: Vec<(Entity, f32)>,
+}
+
+impl<'s> System<'s> for SpriteSortSystem {
+    type SystemData = (
+        Entities<'s>,
+        Write<'s, SpriteVisibility>,
+        ReadStorage<'s, Hidden>,
+        ReadStorage<'s, HiddenPropagate>,
+        Read<'s, ActiveCamera>,
+        ReadStorage<'s, Camera>,
+        ReadStorage<'s, Transparent>,
+        ReadStorage<'s, Transform>,
+        Read<'s, SpriteSortConfig>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut visibility,
+            hidden,
+            hidden_propagate,
+            active,
+            cameras,
+            transparent,
+            transforms,
+            config,
+        ): Self::SystemData,
+    ) {
+        let column = |transform: &Transform, index: usize| {
+            let column = transform.global_matrix().column(index);
+            Vector3::new(column[0].as_f32(), column[1].as_f32(), column[2].as_f32())
+        };
+        let camera = active
+            .entity
+            .and_then(|entity| transforms.get(entity))
+            .or_else(|| (&cameras, &transforms).join().map(|(_, t)| t).next());
+        let backward = camera.map_or_else(Vector3::z, |camera| column(camera, 2));
+        let origin = camera.map_or_else(Vector3::zeros, |camera| column(camera, 3));
+
+        visibility.visible_unordered.clear();
+        self.transparent.clear();
+        for (entity, transform, _, _) in
+            (&entities, &transforms, !&hidden, !&hidden_propagate).join()
+        {
+            let position = column(transform, 3);
+            // Sprites behind the camera can't be seen.
+            if (position - origin).dot(&backward) >= 0. {
+                continue;
+            }
+            if transparent.contains(entity) {
+                self.transparent.push((entity, position.z));
+            } else {
+                visibility.visible_unordered.add(entity.id());
+            }
+        }
+
+        let precision = config.precision;
+        self.transparent
+            .sort_by(|&a, &b| draw_order(a, b, precision));
+        visibility.visible_ordered.clear();
+        visibility
+            .visible_ordered
+            .extend(self.transparent.iter().map(|&(entity, _)| entity));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        core::{SystemBundle, TransformBundle},
+        ecs::prelude::{Builder, DispatcherBuilder, World},
+    };
+
+    use super::*;
+
+    #[test]
+    fn sprites_further_back_are_drawn_first_and_ties_by_entity() {
+        let world = World::new();
+        let (a, b) = (world.entities().create(), world.entities().create());
+        assert_eq!(draw_order((b, -1.), (a, 0.), 1e-4), Ordering::Less);
+        assert_eq!(draw_order((a, 0.), (b, 0.00001), 1e-4), Ordering::Less);
+        assert_eq!(draw_order((b, 0.00001), (a, 0.), 1e-4), Ordering::Greater);
+        assert_eq!(draw_order((b, 0.00001), (a, 0.), 0.), Ordering::Greater);
+        assert_eq!(draw_order((b, 0.), (a, 0.00001), 0.), Ordering::Less);
+    }
+
+    #[test]
+    fn sprites_at_the_same_depth_keep_their_order_as_the_camera_moves() {
+        let mut builder = DispatcherBuilder::new();
+        TransformBundle::new().build(&mut builder).unwrap();
+        builder.add(
+            SpriteSortSystem::default(),
+            "sprite_sort_system",
+            &["transform_system"],
+        );
+        let mut dispatcher = builder.build();
+        let mut world = World::new();
+        dispatcher.setup(&mut world.res);
+
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(0., 0., 10.);
+        let camera = world.create_entity().with(transform).build();
+        world.add_resource(ActiveCamera {
+            entity: Some(camera),
+        });
+        let sprite = |world: &mut World, x: f32, z: f32| {
+            let mut transform = Transform::default();
+            transform.set_translation_xyz(x, 0., z);
+            world
+                .create_entity()
+                .with(transform)
+                .with(Transparent)
+                .build()
+        };
+        let front = sprite(&mut world, 0., 1.);
+        let (first, second) = (sprite(&mut world, 5., 0.), sprite(&mut world, -5., 0.));
+
+        for step in 0..10 {
+            world
+                .write_storage::<Transform>()
+                .get_mut(camera)
+                .unwrap()
+                .set_translation_xyz(step as f32 * 0.37, step as f32 * -1.3, 10.);
+            dispatcher.dispatch(&world.res);
+            assert_eq!(
+                world.read_resource::<SpriteVisibility>().visible_ordered,
+                [first, second, front]
+            );
+        }
+    }
+}
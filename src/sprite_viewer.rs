@@ -0,0 +1,260 @@
+//! Looking through every sprite of the loaded sheets, to check a newly sliced sheet by eye.
+//!
+//! The `toggle_sprite_viewer` action puts a single sprite in the middle of the view, scaled up
+//! as `sprite_viewer.ron` says, with its sheet, number and size written under it. Sheets and
+//! sprites are numbered from zero there, as `SpriteRender::sprite_number` and the configs
+//! giving sprites number them.
+//! `next_sprite` and `previous_sprite` step through the sheet, wrapping around at either end,
+//! and `next_sprite_sheet` and `previous_sprite_sheet` move to the other sheets the level
+//! loaded, keeping the sprite number within the new sheet's count.
+
+use amethyst::{
+    assets::{AssetStorage, Handle, Loader},
+    core::{math::Vector3, transform::Transform},
+    ecs::prelude::{Entities, Entity, Read, ReadExpect, System, WriteStorage},
+    input::{InputHandler, StringBindings},
+    renderer::{camera::ActiveCamera, SpriteRender, SpriteSheet},
+    ui::{Anchor, FontAsset, UiText, UiTransform},
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    movement::world_position,
+    strings::Strings,
+    ui_theme::{ThemedText, UiTheme},
+};
+
+/// Depth at which the sprite is drawn, in front of the level.
+const VIEWER_DEPTH: f32 = 0.6;
+
+/// How the sprite viewer shows sprites, read from `sprite_viewer.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpriteViewerConfig {
+    /// How many times its own size the sprite is drawn.
+    pub scale: f32,
+    pub color: [f32; 4],
+}
+
+impl Default for SpriteViewerConfig {
+    fn default() -> Self {
+        SpriteViewerConfig {
+            scale: 4.,
+            color: [1., 1., 1., 1.],
+        }
+    }
+}
+
+/// Every sprite sheet the level loaded, in the order it loaded them.
+#[derive(Clone, Debug, Default)]
+pub struct LoadedSpriteSheets(pub Vec<Handle<SpriteSheet>>);
+
+/// The index one step on from `index` among `count`, forwards or backwards, wrapping around at
+/// either end.
+pub fn cycle(index: usize, count: usize, forwards: bool) -> usize {
+    if count == 0 {
+        return 0;
+    }
+    let index = clamp_index(index, count);
+    if forwards {
+        (index + 1) % count
+    } else {
+        (index + count - 1) % count
+    }
+}
+
+/// `index` kept within `count`, the last index if it is past the end.
+pub fn clamp_index(index: usize, count: usize) -> usize {
+    index.min(count.saturating_sub(1))
+}
+
+/// The actions the viewer steps on, each of which is acted on once per press.
+const ACTIONS: [&str; 5] = [
+    "toggle_sprite_viewer",
+    "next_sprite",
+    "previous_sprite",
+    "next_sprite_sheet",
+    "previous_sprite_sheet",
+];
+
+/// Shows one sprite of the loaded sheets and steps through them, as the module describes.
+pub struct SpriteViewerSystem {
+    config: SpriteViewerConfig,
+    shown: bool,
+    sheet: usize,
+    sprite: usize,
+    was_pressed: [bool; 5],
+    /// The sprite drawn and the label under it, while shown.
+    entities: Option<(Entity, Entity)>,
+}
+
+impl SpriteViewerSystem {
+    pub fn new(config: SpriteViewerConfig) -> Self {
+        SpriteViewerSystem {
+            config,
+            shown: false,
+            sheet: 0,
+            sprite: 0,
+            was_pressed: [false; 5],
+            entities: None,
+        }
+    }
+}
+
+impl<'s> System<'s> for SpriteViewerSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiText>,
+        WriteStorage<'s, ThemedText>,
+        Read<'s, LoadedSpriteSheets>,
+        Read<'s, AssetStorage<SpriteSheet>>,
+        Read<'s, ActiveCamera>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<FontAsset>>,
+        Read<'s, UiTheme>,
+        ReadExpect<'s, Strings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut transforms,
+            mut sprites,
+            mut ui_transforms,
+            mut ui_texts,
+            mut themed_texts,
+            sheets,
+            sheet_storage,
+            active_camera,
+            input,
+            loader,
+            fonts,
+            theme,
+            strings,
+        ): Self::SystemData,
+    ) {
+        let mut triggered = [false; 5];
+        for (number, &action) in ACTIONS.iter().enumerate() {
+            let pressed = input.action_is_down(action).unwrap_or(false);
+            triggered[number] = pressed && !self.was_pressed[number];
+            self.was_pressed[number] = pressed;
+        }
+        let [toggle, next, previous, next_sheet, previous_sheet] = triggered;
+        if toggle {
+            self.shown = !self.shown;
+        }
+
+        // The sprite and label go with everything else when a game ends.
+        if let Some((sprite, label)) = self.entities {
+            if !self.shown || !entities.is_alive(sprite) || !entities.is_alive(label) {
+                // Either may already be gone, which is as good as deleted.
+                let _ = entities.delete(sprite);
+                let _ = entities.delete(label);
+                self.entities = None;
+            }
+        }
+        let sheet_count = sheets.0.len();
+        if !self.shown || sheet_count == 0 {
+            return;
+        }
+
+        if next_sheet || previous_sheet {
+            self.sheet = cycle(self.sheet, sheet_count, next_sheet);
+        }
+        self.sheet = clamp_index(self.sheet, sheet_count);
+        let handle = &sheets.0[self.sheet];
+        let sheet = sheet_storage.get(handle);
+        let sprite_count = sheet.map_or(0, |sheet| sheet.sprites.len());
+        if next || previous {
+            self.sprite = cycle(self.sprite, sprite_count, next);
+        }
+        self.sprite = clamp_index(self.sprite, sprite_count);
+
+        let color = self.config.color;
+        let (sprite_entity, label) = *self.entities.get_or_insert_with(|| {
+            let font = theme.font(&loader, &fonts);
+            let mut text = UiText::new(font, String::new(), color, theme.font_size);
+            text.align = Anchor::Middle;
+            let mut transform = UiTransform::new(
+                "sprite_viewer".to_string(),
+                Anchor::BottomMiddle,
+                Anchor::BottomMiddle,
+                0.,
+                40.,
+                10.,
+                400.,
+                20.,
+            );
+            let themed = ThemedText::new(&text, &transform);
+            themed.apply(&theme, &mut text, &mut transform);
+            let label = entities
+                .build_entity()
+                .with(transform, &mut ui_transforms)
+                .with(text, &mut ui_texts)
+                .with(themed, &mut themed_texts)
+                .build();
+            let sprite = entities
+                .build_entity()
+                .with(Transform::default(), &mut transforms)
+                .build();
+            (sprite, label)
+        });
+
+        let center = active_camera
+            .entity
+            .and_then(|camera| transforms.get(camera))
+            .map(world_position);
+        if let (Some(center), Some(transform)) = (center, transforms.get_mut(sprite_entity)) {
+            transform.set_translation_xyz(center.x, center.y, VIEWER_DEPTH);
+            transform.set_scale(Vector3::new(self.config.scale, self.config.scale, 1.));
+        }
+        if sprite_count == 0 {
+            sprites.remove(sprite_entity);
+        } else {
+            let render = SpriteRender {
+                sprite_sheet: handle.clone(),
+                sprite_number: self.sprite,
+            };
+            if let Err(err) = sprites.insert(sprite_entity, render) {
+                error!("Failed to show sprite {}: {}", self.sprite, err);
+            }
+        }
+        if let Some(ui_text) = ui_texts.get_mut(label) {
+            let last_sheet = clamp_index(usize::MAX, sheet_count);
+            ui_text.text = match sheet.and_then(|sheet| sheet.sprites.get(self.sprite)) {
+                Some(sprite) => strings.format(
+                    "sprite_viewer.sprite",
+                    &[
+                        &self.sheet,
+                        &last_sheet,
+                        &self.sprite,
+                        &clamp_index(usize::MAX, sprite_count),
+                        &sprite.width,
+                        &sprite.height,
+                    ],
+                ),
+                None => strings.format("sprite_viewer.no_sprites", &[&self.sheet, &last_sheet]),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_wrap_around_from_zero() {
+        assert_eq!(cycle(2, 3, true), 0);
+        assert_eq!(cycle(0, 3, false), 2);
+        assert_eq!(cycle(7, 3, true), 0);
+        assert_eq!(clamp_index(usize::MAX, 3), 2);
+        assert_eq!(cycle(0, 0, true), 0);
+    }
+}
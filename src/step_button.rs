@@ -0,0 +1,73 @@
+//! The button in the top right of the window for stepping the game while `FrameStep` holds it,
+//! as the `step_frame` action does.
+
+use amethyst::{
+    ecs::prelude::{
+        Component, Join, NullStorage, Read, ReadStorage, Resources, System, SystemData, World,
+        Write, WriteStorage,
+    },
+    shrev::{EventChannel, ReaderId},
+    ui::Anchor,
+};
+
+use crate::{
+    clock::FrameStep,
+    ui_theme::{ButtonClick, ThemedButton, ThemedButtonBuilder, UiTheme},
+};
+
+/// Marks the button that requests a single step, enabled only while `FrameStep` is paused.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StepButton;
+
+impl Component for StepButton {
+    type Storage = NullStorage<Self>;
+}
+
+/// Builds the step button, disabled until the game is held.
+pub fn spawn_step_button(world: &mut World) {
+    let padding = world.read_resource::<UiTheme>().padding;
+    let button = ThemedButtonBuilder::new("Step")
+        .with_position(Anchor::TopRight, -padding, -padding)
+        .with_enabled(false)
+        .build(world);
+    world
+        .write_storage::<StepButton>()
+        .insert(button, StepButton)
+        .expect("Step button is alive");
+}
+
+/// Requests a step whenever the `StepButton` is clicked, and enables it while `FrameStep` is
+/// paused.
+#[derive(Default)]
+pub struct StepButtonSystem {
+    reader: Option<ReaderId<ButtonClick>>,
+}
+
+impl<'s> System<'s> for StepButtonSystem {
+    type SystemData = (
+        Write<'s, FrameStep>,
+        Read<'s, EventChannel<ButtonClick>>,
+        ReadStorage<'s, StepButton>,
+        WriteStorage<'s, ThemedButton>,
+    );
+
+    fn run(&mut self, (mut frame_step, clicks, step_buttons, mut buttons): Self::SystemData) {
+        let reader = self.reader.as_mut().expect("StepButtonSystem is set up");
+        for click in clicks.read(reader) {
+            if step_buttons.contains(click.button) {
+                frame_step.request_step();
+            }
+        }
+        for (_, button) in (&step_buttons, &mut buttons).join() {
+            button.enabled = frame_step.is_paused();
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(
+            res.fetch_mut::<EventChannel<ButtonClick>>()
+                .register_reader(),
+        );
+    }
+}
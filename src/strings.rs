@@ -0,0 +1,329 @@
+//! The text the game shows, looked up by id in the table of the current locale, so it can be
+//! translated without touching the code.
+//!
+//! Each locale's table is `resources/locales/<locale>.ron`, a map from string ids to text.
+//! Text can take arguments, filled in where it says `{0}`, `{1}` and so on, so a translation
+//! can put them in whatever order its language needs. An id missing from the current locale's
+//! table falls back to the `fallback` locale's, with a warning the first time, and an id
+//! missing from both shows as itself.
+//!
+//! Text written into levels, such as signs and scripted messages, is looked up too, but shown
+//! as written when it isn't an id, so levels can use either.
+//!
+//! `cycle_locale` switches to the next of the `locales` while the game runs. Menus are rebuilt
+//! in the new locale when they are next shown, and text given a `LocalizedText` is rewritten
+//! straight away.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use amethyst::{
+    config::Config,
+    ecs::prelude::{
+        Component, DenseVecStorage, Join, ReadExpect, ReadStorage, System, WriteStorage,
+    },
+    ui::UiText,
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Which locales there are and which is used, read from `strings.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StringsConfig {
+    /// The locale the game starts in.
+    pub locale: String,
+    /// The locale whose text is shown for ids the current one is missing.
+    pub fallback: String,
+    /// The locales `cycle_locale` switches between, in order.
+    pub locales: Vec<String>,
+}
+
+impl Default for StringsConfig {
+    fn default() -> Self {
+        StringsConfig {
+            locale: "en".to_string(),
+            fallback: "en".to_string(),
+            locales: vec!["en".to_string()],
+        }
+    }
+}
+
+/// A locale's text by string id.
+type StringTable = BTreeMap<String, String>;
+
+/// The text of the current locale and the fallback one, by string id.
+#[derive(Debug)]
+pub struct Strings {
+    dir: PathBuf,
+    locales: Vec<String>,
+    locale: String,
+    table: StringTable,
+    fallback_locale: String,
+    fallback: StringTable,
+    /// Counts the locale switches, for telling when text needs rewriting.
+    revision: u64,
+    /// The ids already warned about, so each is only warned about once.
+    missing: Mutex<BTreeSet<String>>,
+}
+
+impl Strings {
+    /// Reads the tables of the configured locale and the fallback one from `dir`.
+    pub fn load(dir: PathBuf, config: StringsConfig) -> Self {
+        let fallback = read_table(&dir, &config.fallback);
+        let table = if config.locale == config.fallback {
+            fallback.clone()
+        } else {
+            read_table(&dir, &config.locale)
+        };
+        Strings {
+            dir,
+            locales: config.locales,
+            locale: config.locale,
+            table,
+            fallback_locale: config.fallback,
+            fallback,
+            revision: 0,
+            missing: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// How many times the locale has been switched.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Switches to `locale`, reading its table.
+    pub fn set_locale(&mut self, locale: &str) {
+        self.table = if locale == self.fallback_locale {
+            self.fallback.clone()
+        } else {
+            read_table(&self.dir, locale)
+        };
+        self.locale = locale.to_string();
+        self.missing
+            .lock()
+            .expect("Missing strings lock is poisoned")
+            .clear();
+        self.revision += 1;
+    }
+
+    /// Switches to the locale after the current one in the config's `locales`, wrapping back
+    /// to the first.
+    pub fn cycle_locale(&mut self) {
+        let next = match self
+            .locales
+            .iter()
+            .position(|locale| *locale == self.locale)
+        {
+            Some(index) => self.locales.get(index + 1).or_else(|| self.locales.first()),
+            None => self.locales.first(),
+        };
+        if let Some(next) = next.cloned() {
+            self.set_locale(&next);
+        }
+    }
+
+    /// The text of `id` in the current locale, or else in the fallback one.
+    pub fn get(&self, id: &str) -> String {
+        if let Some(text) = self.table.get(id) {
+            return text.clone();
+        }
+        let fallback = self.fallback.get(id);
+        let first = self
+            .missing
+            .lock()
+            .expect("Missing strings lock is poisoned")
+            .insert(id.to_string());
+        if first {
+            match fallback {
+                Some(_) => warn!(
+                    "String {:?} is missing from locale {:?}, showing {:?}'s instead",
+                    id, self.locale, self.fallback_locale
+                ),
+                None => warn!(
+                    "String {:?} is missing from locales {:?} and {:?}",
+                    id, self.locale, self.fallback_locale
+                ),
+            }
+        }
+        fallback.cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    /// The text of `id`, as for `get`, with `{0}`, `{1}` and so on replaced by `args`.
+    pub fn format(&self, id: &str, args: &[&dyn Display]) -> String {
+        fill(&self.get(id), args)
+    }
+
+    /// The text of `text` if it is an id in either locale, or `text` itself if it isn't, for
+    /// text written into levels.
+    pub fn get_or(&self, text: &str) -> String {
+        self.table
+            .get(text)
+            .or_else(|| self.fallback.get(text))
+            .cloned()
+            .unwrap_or_else(|| text.to_string())
+    }
+}
+
+/// `text` with each `{N}` replaced by the `N`th of `args`. Placeholders without an argument
+/// are left as they are, and arguments are put in as they are, even if they look like
+/// placeholders themselves.
+pub fn fill(text: &str, args: &[&dyn Display]) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let arg = after.find('}').and_then(|end| {
+            let index: usize = after[..end].parse().ok()?;
+            Some((end, args.get(index)?))
+        });
+        match arg {
+            Some((end, arg)) => {
+                filled.push_str(&arg.to_string());
+                rest = &after[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = after;
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// The table of `locale` in `dir`, or an empty one if it can't be read.
+fn read_table(dir: &Path, locale: &str) -> StringTable {
+    let path = dir.join(format!("{}.ron", locale));
+    match StringTable::load_no_fallback(&path) {
+        Ok(table) => table,
+        Err(err) => {
+            warn!(
+                "Failed to read locale {:?} from {}: {}",
+                locale,
+                path.display(),
+                err
+            );
+            StringTable::new()
+        }
+    }
+}
+
+/// A UI text showing the string `id`, rewritten whenever the locale is switched.
+#[derive(Clone, Debug)]
+pub struct LocalizedText {
+    pub id: String,
+}
+
+impl LocalizedText {
+    pub fn new(id: impl Into<String>) -> Self {
+        LocalizedText { id: id.into() }
+    }
+}
+
+impl Component for LocalizedText {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Rewrites the text of every `LocalizedText` in the new locale after it is switched. Its ids
+/// are looked up as for `Strings::get_or`, as they can come from levels.
+#[derive(Default)]
+pub struct LocalizedTextSystem {
+    revision: u64,
+}
+
+impl<'s> System<'s> for LocalizedTextSystem {
+    type SystemData = (
+        ReadStorage<'s, LocalizedText>,
+        WriteStorage<'s, UiText>,
+        ReadExpect<'s, Strings>,
+    );
+
+    fn run(&mut self, (localized, mut texts, strings): Self::SystemData) {
+        if strings.revision() == self.revision {
+            return;
+        }
+        self.revision = strings.revision();
+        for (localized, text) in (&localized, &mut texts).join() {
+            text.text = strings.get_or(&localized.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(table: &[(&str, &str)], fallback: &[(&str, &str)]) -> Strings {
+        let to_table = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(id, text)| (id.to_string(), text.to_string()))
+                .collect()
+        };
+        Strings {
+            dir: PathBuf::new(),
+            locales: vec!["fr".to_string(), "en".to_string()],
+            locale: "fr".to_string(),
+            table: to_table(table),
+            fallback_locale: "en".to_string(),
+            fallback: to_table(fallback),
+            revision: 0,
+            missing: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    fn missing(strings: &Strings) -> Vec<String> {
+        strings.missing.lock().unwrap().iter().cloned().collect()
+    }
+
+    #[test]
+    fn arguments_go_in_by_their_number() {
+        assert_eq!(fill("{1} of {0}", &[&3, &"two"]), "two of 3");
+        assert_eq!(fill("{0}, {0}", &[&"again"]), "again, again");
+    }
+
+    #[test]
+    fn arguments_are_not_filled_in_themselves() {
+        assert_eq!(fill("{0} then {1}", &[&"{1}", &"last"]), "{1} then last");
+    }
+
+    #[test]
+    fn placeholders_without_an_argument_are_left() {
+        assert_eq!(fill("{0} {2} {x} {", &[&"a", &"b"]), "a {2} {x} {");
+        assert_eq!(fill("{{0}}", &[&"a"]), "{a}");
+    }
+
+    #[test]
+    fn missing_ids_fall_back_with_one_warning() {
+        let strings = strings(
+            &[("shared", "commun")],
+            &[("shared", "shared"), ("only", "Only")],
+        );
+        assert_eq!(strings.get("shared"), "commun");
+        assert!(missing(&strings).is_empty());
+
+        assert_eq!(strings.get("only"), "Only");
+        assert_eq!(strings.get("only"), "Only");
+        assert_eq!(missing(&strings), ["only"]);
+
+        assert_eq!(strings.get("nowhere"), "nowhere");
+        assert_eq!(missing(&strings), ["nowhere", "only"]);
+    }
+
+    #[test]
+    fn switching_locale_forgets_the_warnings() {
+        let mut strings = strings(&[], &[("only", "Only")]);
+        strings.get("only");
+        strings.set_locale("en");
+        assert!(missing(&strings).is_empty());
+        assert_eq!(strings.get("only"), "Only");
+        assert!(missing(&strings).is_empty());
+    }
+}
@@ -0,0 +1,428 @@
+//! A record of what happens in play, for balancing sessions.
+//!
+//! With `telemetry.ron` enabled, hits, kills, player deaths, waves and the time spent on each
+//! level are logged as rows of CSV. `Telemetry` hands each row to a thread of its own, which
+//! writes them out through a buffer, so the game never waits on the disk. The file is flushed
+//! as the game exits. Disabled, as it is by default, nothing is recorded and no file is made.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+};
+
+use amethyst::{
+    ecs::prelude::{Entity, Read, ReadStorage, Resources, System, SystemData, Write},
+    shrev::{EventChannel, ReaderId},
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock,
+    combat::{DamageKind, DamageTaken, Health},
+    player::Player,
+    wave::WaveEvent,
+};
+
+/// The first line of every telemetry file.
+pub const CSV_HEADER: &str = "time,event,source,target,amount,detail";
+
+/// Whether and where play is logged, read from `telemetry.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// Where the log is written, relative to the game's folder. Each run starts it over.
+    pub path: PathBuf,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            enabled: false,
+            path: PathBuf::from("telemetry.csv"),
+        }
+    }
+}
+
+/// Something worth a row of the log.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TelemetryEvent {
+    LevelStarted {
+        level: String,
+    },
+    /// After `seconds` of game time on `level`.
+    LevelEnded {
+        level: String,
+        seconds: f64,
+    },
+    WaveStarted(usize),
+    WaveCleared(usize),
+    /// Hit points lost by `target`, a player if `to_player` is set.
+    Damage {
+        source: Option<Entity>,
+        target: Entity,
+        amount: f32,
+        kind: DamageKind,
+        to_player: bool,
+    },
+    /// A hit leaving `victim`, not a player, without health.
+    Kill {
+        killer: Option<Entity>,
+        victim: Entity,
+    },
+    /// A hit leaving the `player`th player without health.
+    Death {
+        killer: Option<Entity>,
+        target: Entity,
+        player: usize,
+    },
+}
+
+/// `event`, at `time` seconds of game time, as a row of CSV under `CSV_HEADER`.
+pub fn csv_row(time: f64, event: &TelemetryEvent) -> String {
+    let id = |entity: Option<Entity>| entity.map_or_else(String::new, |e| e.id().to_string());
+    let (name, source, target, amount, detail) = match event {
+        TelemetryEvent::LevelStarted { level } => {
+            ("level_started", None, None, String::new(), level.clone())
+        }
+        TelemetryEvent::LevelEnded { level, seconds } => (
+            "level_ended",
+            None,
+            None,
+            format!("{:.3}", seconds),
+            level.clone(),
+        ),
+        TelemetryEvent::WaveStarted(wave) => {
+            ("wave_started", None, None, String::new(), wave.to_string())
+        }
+        TelemetryEvent::WaveCleared(wave) => {
+            ("wave_cleared", None, None, String::new(), wave.to_string())
+        }
+        TelemetryEvent::Damage {
+            source,
+            target,
+            amount,
+            kind,
+            to_player,
+        } => (
+            if *to_player {
+                "damage_taken"
+            } else {
+                "damage_dealt"
+            },
+            *source,
+            Some(*target),
+            amount.to_string(),
+            format!("{:?}", kind),
+        ),
+        TelemetryEvent::Kill { killer, victim } => {
+            ("kill", *killer, Some(*victim), String::new(), String::new())
+        }
+        TelemetryEvent::Death {
+            killer,
+            target,
+            player,
+        } => (
+            "death",
+            *killer,
+            Some(*target),
+            String::new(),
+            player.to_string(),
+        ),
+    };
+    format!(
+        "{:.3},{},{},{},{},{}",
+        time,
+        name,
+        id(source),
+        id(target),
+        amount,
+        csv_field(&detail)
+    )
+}
+
+/// `text` quoted for CSV if it holds anything that would break the row.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Where rows are logged. Each is handed to a thread of its own, which writes them out; dropped
+/// as the game exits, it waits for the thread to write and flush the rest.
+#[derive(Default)]
+pub struct Telemetry {
+    rows: Option<Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+    /// The level being played, and the game time it started at.
+    level: Option<(String, f64)>,
+}
+
+impl Telemetry {
+    /// Writes to `root` joined with the configured path, or nowhere if telemetry is disabled.
+    pub fn start(config: &TelemetryConfig, root: &Path) -> Self {
+        if !config.enabled {
+            return Telemetry::default();
+        }
+        let path = root.join(&config.path);
+        let (sender, receiver) = mpsc::channel::<String>();
+        let writer = thread::spawn(move || {
+            let written = File::create(&path)
+                .map_err(failure::Error::from)
+                .and_then(|file| {
+                    let mut out = BufWriter::new(file);
+                    writeln!(out, "{}", CSV_HEADER)?;
+                    for row in receiver {
+                        writeln!(out, "{}", row)?;
+                    }
+                    out.flush()?;
+                    Ok(())
+                });
+            if let Err(err) = written {
+                warn!("Failed to write telemetry to {}: {}", path.display(), err);
+            }
+        });
+        Telemetry {
+            rows: Some(sender),
+            writer: Some(writer),
+            level: None,
+        }
+    }
+
+    /// Logs `event` at `time` seconds of game time, unless telemetry is disabled.
+    pub fn record(&mut self, time: f64, event: TelemetryEvent) {
+        if let Some(rows) = &self.rows {
+            // Once the writer has given up, rows are dropped.
+            let _ = rows.send(csv_row(time, &event));
+        }
+    }
+
+    /// Logs the start of `level`, timing it until `end_level`.
+    pub fn start_level(&mut self, time: f64, level: &str) {
+        self.level = Some((level.to_string(), time));
+        self.record(
+            time,
+            TelemetryEvent::LevelStarted {
+                level: level.to_string(),
+            },
+        );
+    }
+
+    /// Logs the end of the level being played, with how long it was played for.
+    pub fn end_level(&mut self, time: f64) {
+        if let Some((level, started)) = self.level.take() {
+            let seconds = (time - started).max(0.);
+            self.record(time, TelemetryEvent::LevelEnded { level, seconds });
+        }
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        self.rows.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// The targets of `hits` left without health by them, as `is_dead` says, each once with the
+/// source of the last hit that took any health from it, which is the one that finished it.
+fn fatal_hits<'a>(
+    hits: impl IntoIterator<Item = &'a DamageTaken>,
+    is_dead: impl Fn(Entity) -> bool,
+) -> Vec<(Entity, Option<Entity>)> {
+    let mut fatal: Vec<(Entity, Option<Entity>)> = Vec::new();
+    for hit in hits {
+        if hit.amount <= 0. || !is_dead(hit.target) {
+            continue;
+        }
+        match fatal.iter_mut().find(|(target, _)| *target == hit.target) {
+            Some((_, killer)) => *killer = hit.source,
+            None => fatal.push((hit.target, hit.source)),
+        }
+    }
+    fatal
+}
+
+/// Logs hits, kills, player deaths and waves to `Telemetry`. Runs after the `DamageSystem`, so
+/// the health hits leave is known. A target hit several times in the step it dies is killed
+/// once, by the hit that finished it.
+#[derive(Default)]
+pub struct TelemetrySystem {
+    damage: Option<ReaderId<DamageTaken>>,
+    waves: Option<ReaderId<WaveEvent>>,
+}
+
+impl<'s> System<'s> for TelemetrySystem {
+    type SystemData = (
+        Read<'s, EventChannel<DamageTaken>>,
+        Read<'s, EventChannel<WaveEvent>>,
+        ReadStorage<'s, Health>,
+        ReadStorage<'s, Player>,
+        Write<'s, Telemetry>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(&mut self, (damage, waves, healths, players, mut telemetry, clock): Self::SystemData) {
+        let time = clock.elapsed_seconds();
+        let reader = self.damage.as_mut().expect("TelemetrySystem is set up");
+        let hits: Vec<&DamageTaken> = damage.read(reader).collect();
+        for hit in &hits {
+            let player = players.get(hit.target).map(|player| player.index);
+            telemetry.record(
+                time,
+                TelemetryEvent::Damage {
+                    source: hit.source,
+                    target: hit.target,
+                    amount: hit.amount,
+                    kind: hit.kind,
+                    to_player: player.is_some(),
+                },
+            );
+        }
+        let is_dead = |target| {
+            healths
+                .get(target)
+                .is_some_and(|health| health.current <= 0.)
+        };
+        for (target, killer) in fatal_hits(hits.iter().copied(), is_dead) {
+            telemetry.record(
+                time,
+                match players.get(target) {
+                    Some(player) => TelemetryEvent::Death {
+                        killer,
+                        target,
+                        player: player.index,
+                    },
+                    None => TelemetryEvent::Kill {
+                        killer,
+                        victim: target,
+                    },
+                },
+            );
+        }
+        let reader = self.waves.as_mut().expect("TelemetrySystem is set up");
+        for event in waves.read(reader) {
+            telemetry.record(
+                time,
+                match *event {
+                    WaveEvent::Started(wave) => TelemetryEvent::WaveStarted(wave),
+                    WaveEvent::Cleared(wave) => TelemetryEvent::WaveCleared(wave),
+                },
+            );
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.damage = Some(
+            res.fetch_mut::<EventChannel<DamageTaken>>()
+                .register_reader(),
+        );
+        self.waves = Some(res.fetch_mut::<EventChannel<WaveEvent>>().register_reader());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::ecs::prelude::{Builder, World};
+
+    use super::*;
+    use crate::combat::DamageType;
+
+    /// The fields of a row of CSV, unquoted.
+    fn fields(row: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let (mut quoted, mut chars) = (false, row.chars().peekable());
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    fields.last_mut().unwrap().push('"');
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => fields.push(String::new()),
+                (c, _) => fields.last_mut().unwrap().push(c),
+            }
+        }
+        fields
+    }
+
+    fn hit(source: Option<Entity>, target: Entity, amount: f32) -> DamageTaken {
+        DamageTaken {
+            source,
+            target,
+            amount,
+            kind: DamageKind::Hitbox,
+            damage_type: DamageType::default(),
+        }
+    }
+
+    #[test]
+    fn rows_read_back_under_the_header() {
+        let level = "Caves, \"lower\"".to_string();
+        let row = csv_row(
+            12.5,
+            &TelemetryEvent::LevelEnded {
+                level: level.clone(),
+                seconds: 3.,
+            },
+        );
+        let header: Vec<&str> = CSV_HEADER.split(',').collect();
+        let fields = fields(&row);
+        assert_eq!(fields.len(), header.len());
+        assert_eq!(
+            fields,
+            ["12.500", "level_ended", "", "", "3.000", level.as_str()]
+        );
+    }
+
+    #[test]
+    fn hits_name_their_source_and_target() {
+        let mut world = World::new();
+        let (source, target) = (world.create_entity().build(), world.create_entity().build());
+        let row = csv_row(
+            1.,
+            &TelemetryEvent::Damage {
+                source: Some(source),
+                target,
+                amount: 2.5,
+                kind: DamageKind::Hitbox,
+                to_player: true,
+            },
+        );
+        let fields = fields(&row);
+        assert_eq!(fields[1], "damage_taken");
+        assert_eq!(fields[2], source.id().to_string());
+        assert_eq!(fields[3], target.id().to_string());
+        assert_eq!(fields[4], "2.5");
+        assert_eq!(fields[5], "Hitbox");
+    }
+
+    #[test]
+    fn a_target_hit_several_times_as_it_dies_is_killed_once() {
+        let mut world = World::new();
+        let (first, second, victim, survivor) = (
+            world.create_entity().build(),
+            world.create_entity().build(),
+            world.create_entity().build(),
+            world.create_entity().build(),
+        );
+        let hits = [
+            hit(Some(first), victim, 5.),
+            hit(Some(first), survivor, 5.),
+            hit(Some(second), victim, 5.),
+            // Past the last of its health, taking nothing.
+            hit(Some(first), victim, 0.),
+        ];
+        let fatal = fatal_hits(&hits, |target| target == victim);
+        assert_eq!(fatal, vec![(victim, Some(second))]);
+    }
+}
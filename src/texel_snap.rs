@@ -0,0 +1,169 @@
+//! Texel snapping: keeping pixel art on its own grid while the camera glides between texels.
+//!
+//! A camera moving less than a texel at a time shows sprites sampled between their texels, so
+//! they shimmer as it moves. With snapping on, the `TexelSnapSystem` moves each zoomed camera
+//! onto the nearest multiple of `texel_size` before the frame is drawn, so the scene is always
+//! drawn on the texel grid, and keeps what it moved by. The upscale pass then shifts the whole
+//! drawn scene back by that much, rounded to whole window pixels, so the view still moves
+//! smoothly. The snap is taken off again at the start of the next frame, along with the shake,
+//! so camera behaviours and everything placing things on screen see the camera where it
+//! really is.
+//!
+//! Shifting the scene leaves the window's edges showing its outermost pixels stretched over the
+//! width of the shift, up to half a texel. With both halves of split-screen composited into one
+//! image, each moved differently, the snap is left off.
+
+use amethyst::{
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Component, DenseVecStorage, Entities, Join, Read, ReadExpect, ReadStorage, System, Write,
+        WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    window::ScreenDimensions,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::CameraZoom, movement::world_position, split_screen::SplitScreen};
+
+/// Whether cameras are snapped to the texel grid, read from `texel_snap.ron`. Also holds the
+/// shift the upscale pass draws the scene with.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TexelSnap {
+    pub enabled: bool,
+    /// The size of one of the art's texels in world units.
+    pub texel_size: f32,
+    /// How far the drawn scene is shifted, as a fraction of the window's width and height.
+    #[serde(skip)]
+    pub offset: [f32; 2],
+}
+
+impl Default for TexelSnap {
+    fn default() -> Self {
+        TexelSnap {
+            enabled: false,
+            texel_size: 1.,
+            offset: [0., 0.],
+        }
+    }
+}
+
+impl TexelSnap {
+    /// The shift laid out as the upscale shader's push constants.
+    pub fn push_constants(&self) -> [u32; 2] {
+        [self.offset[0].to_bits(), self.offset[1].to_bits()]
+    }
+}
+
+/// `position` moved to the nearest multiple of `texel_size` on each axis.
+pub fn snap_to_texels(position: Vector2<f32>, texel_size: f32) -> Vector2<f32> {
+    if texel_size <= 0. {
+        return position;
+    }
+    position.map(|value| (value / texel_size).round() * texel_size)
+}
+
+/// How far a camera at `position` is from where it is snapped to, in whole screen pixels at
+/// `zoom` pixels per world unit.
+pub fn sub_texel_offset(position: Vector2<f32>, texel_size: f32, zoom: f32) -> Vector2<f32> {
+    ((position - snap_to_texels(position, texel_size)) * zoom).map(f32::round)
+}
+
+/// How far a camera was moved onto the texel grid this frame, taken off again before anything
+/// moves it next frame.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraSnap {
+    pub offset: Vector2<f32>,
+}
+
+impl Component for CameraSnap {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Turns snapping on and off on the `toggle_texel_snap` action, and snaps the cameras as the
+/// module describes. Runs after everything placing things by where the camera is.
+#[derive(Default)]
+pub struct TexelSnapSystem {
+    was_pressed: bool,
+    /// The shift for the frame snapped this time. The renderer draws the cameras where they
+    /// were as the frame began, so the shift drawn with is always the one before.
+    pending: [f32; 2],
+}
+
+impl<'s> System<'s> for TexelSnapSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, CameraZoom>,
+        WriteStorage<'s, CameraSnap>,
+        WriteStorage<'s, Transform>,
+        Write<'s, TexelSnap>,
+        Read<'s, SplitScreen>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, ScreenDimensions>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, zooms, mut snaps, mut transforms, mut snap, split_screen, input, dimensions): Self::SystemData,
+    ) {
+        let pressed = input.action_is_down("toggle_texel_snap").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            snap.enabled = !snap.enabled;
+            info!("Texel snapping {}", if snap.enabled { "on" } else { "off" });
+        }
+        self.was_pressed = pressed;
+
+        snap.offset = self.pending;
+        self.pending = [0., 0.];
+        if !snap.enabled || split_screen.enabled {
+            return;
+        }
+
+        let window = Vector2::new(dimensions.width(), dimensions.height());
+        for (camera, zoom, transform) in (&entities, &zooms, &mut transforms).join() {
+            let position = world_position(transform);
+            let snapped = snap_to_texels(position, snap.texel_size);
+            transform.set_translation_x(snapped.x);
+            transform.set_translation_y(snapped.y);
+            snaps
+                .insert(
+                    camera,
+                    CameraSnap {
+                        offset: position - snapped,
+                    },
+                )
+                .expect("Camera is alive");
+            // The scene is sampled further along for a camera ahead of its snap, and upwards in
+            // the world is towards the top of the window, where the scene's `v` is zero.
+            let pixels = sub_texel_offset(position, snap.texel_size, zoom.level());
+            self.pending = [pixels.x / window.x, -pixels.y / window.y];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_snap_to_the_nearest_texel() {
+        assert_eq!(
+            snap_to_texels(Vector2::new(2.6, -2.6), 0.5),
+            Vector2::new(2.5, -2.5)
+        );
+        assert_eq!(
+            snap_to_texels(Vector2::new(2.6, 1.), 0.),
+            Vector2::new(2.6, 1.)
+        );
+    }
+
+    #[test]
+    fn offsets_are_whole_screen_pixels() {
+        assert_eq!(
+            sub_texel_offset(Vector2::new(2.6, 2.5), 0.5, 10.),
+            Vector2::new(1., 0.)
+        );
+    }
+}
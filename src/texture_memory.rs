@@ -0,0 +1,181 @@
+//! An estimate of how much video memory the loaded textures take, to catch an accidentally huge
+//! texture before it becomes a problem on smaller GPUs.
+//!
+//! Sprite sheets that name the same image share one texture, so the report also shows how well
+//! each atlas is reused.
+
+use amethyst::renderer::{rendy::hal::format::Format, types::Backend, Texture};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+const MIB: u64 = 1024 * 1024;
+
+/// The texture memory the game is allowed before it warns, read from `texture_budget.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TextureBudget {
+    pub budget_mib: u64,
+}
+
+impl Default for TextureBudget {
+    fn default() -> Self {
+        TextureBudget { budget_mib: 256 }
+    }
+}
+
+/// Bytes taken by a `width` by `height` texture of `format` with `levels` mip levels.
+pub fn texture_bytes(width: u32, height: u32, levels: u8, format: Format) -> u64 {
+    let bytes_per_texel = u64::from(format.surface_desc().bits / 8);
+    (0..u32::from(levels.max(1)))
+        .map(|level| {
+            let width = u64::from((width >> level).max(1));
+            let height = u64::from((height >> level).max(1));
+            width * height * bytes_per_texel
+        })
+        .sum()
+}
+
+/// One loaded texture.
+#[derive(Clone, Debug)]
+pub struct TextureUsage {
+    /// Image path, relative to the `resources` directory.
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub levels: u8,
+    pub format: Format,
+    /// Number of sprite sheets drawing from this texture.
+    pub sheets: usize,
+}
+
+impl TextureUsage {
+    /// Measures a loaded texture. `None` if it isn't a texture of backend `B`.
+    pub fn measure<B: Backend>(path: String, texture: &Texture, sheets: usize) -> Option<Self> {
+        let image = B::unwrap_texture(texture)?.image();
+        let extent = image.kind().extent();
+        Some(TextureUsage {
+            path,
+            width: extent.width,
+            height: extent.height,
+            levels: image.levels(),
+            format: image.format(),
+            sheets,
+        })
+    }
+
+    pub fn bytes(&self) -> u64 {
+        texture_bytes(self.width, self.height, self.levels, self.format)
+    }
+}
+
+/// Every loaded texture and the budget they are held to.
+#[derive(Clone, Debug, Default)]
+pub struct TextureMemory {
+    pub budget: TextureBudget,
+    textures: Vec<TextureUsage>,
+}
+
+impl TextureMemory {
+    pub fn new(budget: TextureBudget) -> Self {
+        TextureMemory {
+            budget,
+            textures: Vec::new(),
+        }
+    }
+
+    /// Adds a texture, warning if it takes the total over budget.
+    pub fn record(&mut self, usage: TextureUsage) {
+        let was_over = self.over_budget();
+        self.textures.push(usage);
+        if !was_over && self.over_budget() {
+            warn!(
+                "Textures take {:.1} MiB, over the budget of {} MiB; {} is the largest",
+                self.total_bytes() as f64 / MIB as f64,
+                self.budget.budget_mib,
+                self.textures
+                    .iter()
+                    .max_by_key(|texture| texture.bytes())
+                    .map_or("none", |texture| texture.path.as_str()),
+            );
+        }
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.textures.iter().map(TextureUsage::bytes).sum()
+    }
+
+    pub fn over_budget(&self) -> bool {
+        self.total_bytes() > self.budget.budget_mib * MIB
+    }
+
+    /// A summary for the debug overlay: the total, then every texture largest first.
+    pub fn report(&self) -> String {
+        let mut textures: Vec<_> = self.textures.iter().collect();
+        textures.sort_by_key(|texture| std::cmp::Reverse(texture.bytes()));
+
+        let mut report = format!(
+            "Textures: {:.1} of {} MiB{}",
+            self.total_bytes() as f64 / MIB as f64,
+            self.budget.budget_mib,
+            if self.over_budget() {
+                " (over budget)"
+            } else {
+                ""
+            },
+        );
+        for texture in textures {
+            report.push_str(&format!(
+                "\n  {} {}x{} {:?}: {:.1} MiB, {} sheet(s)",
+                texture.path,
+                texture.width,
+                texture.height,
+                texture.format,
+                texture.bytes() as f64 / MIB as f64,
+                texture.sheets,
+            ));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(path: &str, size: u32, levels: u8) -> TextureUsage {
+        TextureUsage {
+            path: path.to_string(),
+            width: size,
+            height: size,
+            levels,
+            format: Format::Rgba8Srgb,
+            sheets: 1,
+        }
+    }
+
+    #[test]
+    fn mip_levels_add_a_shrinking_share() {
+        assert_eq!(texture_bytes(1024, 1024, 1, Format::Rgba8Srgb), 4 * MIB);
+        // Each level halves both sides, down to a texel.
+        assert_eq!(texture_bytes(4, 2, 3, Format::Rgba8Srgb), (8 + 2 + 1) * 4);
+        assert_eq!(texture_bytes(64, 64, 0, Format::R8Unorm), 64 * 64);
+    }
+
+    #[test]
+    fn textures_add_up_against_the_budget() {
+        let mut memory = TextureMemory::new(TextureBudget { budget_mib: 8 });
+        memory.record(texture("sprites.png", 1024, 1));
+        memory.record(texture("tiles.png", 1024, 1));
+        assert_eq!(memory.total_bytes(), 8 * MIB);
+        assert!(!memory.over_budget());
+        assert!(!memory.report().contains("over budget"));
+
+        memory.record(texture("portrait.png", 16, 1));
+        assert!(memory.over_budget());
+        let report = memory.report();
+        assert!(report.starts_with("Textures: 8.0 of 8 MiB (over budget)"));
+        // Largest first, so the smallest texture comes last.
+        assert!(report.lines().last().unwrap().contains("portrait.png"));
+        assert_eq!(report.lines().count(), 4);
+    }
+}
@@ -0,0 +1,254 @@
+//! A cursor that snaps to the tile under the mouse, for placing things on the map.
+//!
+//! The tile is outlined with debug lines on the `TileCursorOverlay` entity and its coordinates
+//! are shown next to the mouse. Both follow the camera as it moves and zooms, and disappear
+//! while the mouse is off the map.
+//!
+//! Left-clicking with the cursor shown paints the map's first auto-tiled terrain onto the tile,
+//! or erases it if it is already there.
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::math::{Point3, Vector2},
+    ecs::prelude::{
+        Component, Entities, Entity, Join, NullStorage, Read, ReadExpect, ReadStorage, System,
+        Write, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::{debug_drawing::DebugLinesComponent, palette::Srgba},
+    shrev::EventChannel,
+    ui::{Anchor, FontAsset, UiText, UiTransform},
+    window::ScreenDimensions,
+    winit::MouseButton,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{mouse_pixel, CameraMatrices},
+    navigation::{NavGrid, TileCoord},
+    tile_map::{TileEdit, TileMap},
+    ui_theme::{ThemedText, UiTheme},
+};
+
+/// Depth at which the cursor is drawn, in front of everything.
+const CURSOR_DEPTH: f32 = 0.6;
+
+/// How the tile cursor looks, read from `tile_cursor.ron`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TileCursorConfig {
+    /// Whether the cursor is shown before it is first toggled with `toggle_tile_cursor`.
+    pub enabled: bool,
+    pub color: [f32; 4],
+    pub show_coordinates: bool,
+    /// Pixels from the mouse to the bottom-left corner of the coordinates.
+    pub label_offset: [f32; 2],
+}
+
+impl Default for TileCursorConfig {
+    fn default() -> Self {
+        TileCursorConfig {
+            enabled: true,
+            color: [1., 1., 0.4, 0.9],
+            show_coordinates: true,
+            label_offset: [16., 16.],
+        }
+    }
+}
+
+/// Marks the entity whose debug lines draw the tile cursor.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TileCursorOverlay;
+
+impl Component for TileCursorOverlay {
+    type Storage = NullStorage<Self>;
+}
+
+/// The tile of `grid` shown at `pixel` by `camera`, or `None` off the map.
+pub fn tile_under(
+    grid: &NavGrid,
+    camera: &CameraMatrices,
+    pixel: Vector2<f32>,
+) -> Option<TileCoord> {
+    grid.tile_at(camera.screen_to_world(pixel))
+}
+
+/// Outlines the tile under the mouse and labels it with its coordinates, and paints the tile
+/// on a left click. Shows or hides the cursor on the `toggle_tile_cursor` action.
+pub struct TileCursorSystem {
+    pub config: TileCursorConfig,
+    enabled: bool,
+    was_pressed: bool,
+    was_clicked: bool,
+    label: Option<Entity>,
+}
+
+impl TileCursorSystem {
+    pub fn new(config: TileCursorConfig) -> Self {
+        TileCursorSystem {
+            config,
+            enabled: config.enabled,
+            was_pressed: false,
+            was_clicked: false,
+            label: None,
+        }
+    }
+}
+
+impl<'s> System<'s> for TileCursorSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, TileCursorOverlay>,
+        WriteStorage<'s, DebugLinesComponent>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiText>,
+        WriteStorage<'s, ThemedText>,
+        Read<'s, NavGrid>,
+        Read<'s, TileMap>,
+        Write<'s, EventChannel<TileEdit>>,
+        Read<'s, CameraMatrices>,
+        Read<'s, InputHandler<StringBindings>>,
+        ReadExpect<'s, ScreenDimensions>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<FontAsset>>,
+        Read<'s, UiTheme>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            overlays,
+            mut debug_lines,
+            mut ui_transforms,
+            mut ui_texts,
+            mut themed_texts,
+            grid,
+            map,
+            mut edits,
+            camera,
+            input,
+            dimensions,
+            loader,
+            fonts,
+            theme,
+        ): Self::SystemData,
+    ) {
+        let pressed = input.action_is_down("toggle_tile_cursor").unwrap_or(false);
+        if pressed && !self.was_pressed {
+            self.enabled = !self.enabled;
+        }
+        self.was_pressed = pressed;
+
+        let pixel = input
+            .mouse_position()
+            .map(|mouse| mouse_pixel(mouse, dimensions.height()));
+        let tile = match (self.enabled, pixel) {
+            (true, Some(pixel)) => tile_under(&grid, &camera, pixel),
+            _ => None,
+        };
+
+        let clicked = input.mouse_button_is_down(MouseButton::Left);
+        if clicked && !self.was_clicked {
+            if let (Some(coord), Some(set)) = (tile, map.auto_tiles.first()) {
+                let painted = map.tile(coord) == Some(set.terrain);
+                edits.single_write(TileEdit {
+                    coord,
+                    tile: if painted { set.base } else { set.terrain },
+                });
+            }
+        }
+        self.was_clicked = clicked;
+
+        let [r, g, b, a] = self.config.color;
+        let color = Srgba::new(r, g, b, a);
+        for (_, lines) in (&overlays, &mut debug_lines).join() {
+            lines.clear();
+            if let Some(tile) = tile {
+                let corners = grid
+                    .tile_corners(tile)
+                    .map(|corner| Point3::new(corner.x, corner.y, CURSOR_DEPTH));
+                for (index, &from) in corners.iter().enumerate() {
+                    lines.add_line(from, corners[(index + 1) % corners.len()], color);
+                }
+            }
+        }
+
+        if !self.config.show_coordinates {
+            return;
+        }
+        let label_color = self.config.color;
+        // The label goes with everything else when a game ends, and is made again in the next.
+        if self.label.is_some_and(|label| !entities.is_alive(label)) {
+            self.label = None;
+        }
+        let label = *self.label.get_or_insert_with(|| {
+            let font = theme.font(&loader, &fonts);
+            let mut text = UiText::new(font, String::new(), label_color, theme.font_size);
+            text.align = Anchor::BottomLeft;
+            let mut transform = UiTransform::new(
+                "tile_cursor".to_string(),
+                Anchor::BottomLeft,
+                Anchor::BottomLeft,
+                0.,
+                0.,
+                10.,
+                160.,
+                20.,
+            );
+            let themed = ThemedText::new(&text, &transform);
+            themed.apply(&theme, &mut text, &mut transform);
+            entities
+                .build_entity()
+                .with(transform, &mut ui_transforms)
+                .with(text, &mut ui_texts)
+                .with(themed, &mut themed_texts)
+                .build()
+        });
+        if let (Some(ui_transform), Some(pixel)) = (ui_transforms.get_mut(label), pixel) {
+            ui_transform.local_x = pixel.x + self.config.label_offset[0];
+            ui_transform.local_y = pixel.y + self.config.label_offset[1];
+        }
+        if let Some(ui_text) = ui_texts.get_mut(label) {
+            ui_text.text = match tile {
+                Some((column, row)) => format!("{}, {}", column, row),
+                None => String::new(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{core::transform::Transform, renderer::camera::Camera};
+
+    use super::*;
+    use crate::{camera::centered_projection, tile_map::Tile};
+
+    #[test]
+    fn picking_stops_at_the_edges_of_the_map() {
+        let grid = NavGrid::from_map(&TileMap {
+            width: 4,
+            height: 3,
+            tile_size: 32.,
+            tiles: vec![Tile::Static(0); 12],
+            ..TileMap::default()
+        });
+        let screen = Vector2::new(640., 360.);
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(64., 48., 10.);
+        let camera = CameraMatrices::new(
+            transform.view_matrix().map(|value| value.as_f32()),
+            *Camera::from(centered_projection(screen, 2.)).as_matrix(),
+            screen,
+        );
+        let pick = |x, y| tile_under(&grid, &camera, camera.world_to_screen(Vector2::new(x, y)));
+        assert_eq!(pick(0.5, 0.5), Some((0, 0)));
+        assert_eq!(pick(127.5, 95.5), Some((3, 2)));
+        assert_eq!(pick(127.5, 0.5), Some((3, 0)));
+        assert_eq!(pick(-0.5, 10.), None);
+        assert_eq!(pick(10., -0.5), None);
+        assert_eq!(pick(128.5, 10.), None);
+        assert_eq!(pick(10., 96.5), None);
+    }
+}
@@ -0,0 +1,591 @@
+//! Tile maps loaded from RON files and drawn as one sprite per tile.
+//!
+//! Static tiles are plain sprites that nothing touches after they are spawned. Animated tiles
+//! also get an `AnimatedTile`, and only those are rewritten each frame by the
+//! `TileAnimationSystem`. Tiles of an auto-tiled terrain are drawn with the sprite their
+//! neighbours call for instead.
+//!
+//! Tiles are edited through `TileEdit`s, which redraw the edited tile and its neighbours.
+//!
+//! The map's `overhead` tiles, like roofs, are drawn over the sprites rather than under them.
+//!
+//! Maps are laid out top-down by default, with square tiles and the bottom-left corner at the
+//! map's `origin`, the world origin until the world is moved back towards it (see `origin`). A
+//! `MapProjection::Isometric` map lays its tiles out as diamonds instead, with columns running
+//! up and to the right and rows up and to the left. Everything finding tiles in the world goes
+//! through the map's projection, so the rest of the game works the same either way.
+
+use std::path::Path;
+
+use amethyst::{
+    assets::Handle,
+    config::Config,
+    core::{math::Vector2, transform::Transform},
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Join, Read, ReadStorage, Resources, System,
+        SystemData, World, Write, WriteStorage,
+    },
+    renderer::{SpriteRender, SpriteSheet},
+    shrev::{EventChannel, ReaderId},
+};
+use failure::format_err;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auto_tile::{bitmask, AutoTileSet},
+    camera::Room,
+    clock::GameClock,
+    collision::{TileColliders, TileCollisionConfig},
+    enemy::EnemySpawn,
+    footsteps::{Terrain, TerrainTiles},
+    intro::IntroShot,
+    lighting::{LightGrid, LightingConfig},
+    loading::SpriteSheetAsset,
+    navigation::{NavGrid, TileCoord},
+    script::{ScriptTrigger, Scripts},
+    sight::SightGrid,
+    weather::WeatherConfig,
+    world_text::Sign,
+};
+
+/// Depth at which tiles are drawn, behind everything standing on them.
+const TILE_DEPTH: f32 = -1.;
+
+/// Depth at which overhead tiles are drawn, over everything standing under them.
+const OVERHEAD_DEPTH: f32 = 0.4;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TileMap {
+    /// Width of the map in tiles.
+    pub width: usize,
+    /// Height of the map in tiles.
+    pub height: usize,
+    /// Size of one square tile in world units.
+    pub tile_size: f32,
+    /// The tiles row by row, starting from the top-left corner.
+    pub tiles: Vec<Tile>,
+    /// Animations referred to by `Tile::Animated`.
+    #[serde(default)]
+    pub animations: Vec<TileAnimation>,
+    #[serde(default)]
+    pub weather: WeatherConfig,
+    /// Sprite sheets to get ready while loading, besides the main one.
+    #[serde(default)]
+    pub warmup: Vec<SpriteSheetAsset>,
+    /// Rooms the camera snaps between. Without any the camera keeps every player in view.
+    #[serde(default)]
+    pub rooms: Vec<Room>,
+    #[serde(default)]
+    pub signs: Vec<Sign>,
+    /// Tiles that can't be walked on.
+    #[serde(default)]
+    pub solid: Vec<Tile>,
+    /// Tiles that block line of sight.
+    #[serde(default)]
+    pub opaque: Vec<Tile>,
+    /// Tiles drawn over whatever stands under them, like roofs and treetops.
+    #[serde(default)]
+    pub overhead: Vec<Tile>,
+    #[serde(default)]
+    pub enemies: Vec<EnemySpawn>,
+    /// Terrains whose sprites are picked from their neighbours.
+    #[serde(default)]
+    pub auto_tiles: Vec<AutoTileSet>,
+    #[serde(default)]
+    pub projection: MapProjection,
+    #[serde(default)]
+    pub lighting: LightingConfig,
+    /// Areas running one of the `scripts` when a player enters them.
+    #[serde(default)]
+    pub triggers: Vec<ScriptTrigger>,
+    #[serde(default)]
+    pub scripts: Scripts,
+    /// What the tiles are made of, for the sound of walking on them.
+    #[serde(default)]
+    pub terrain: Vec<TerrainTiles>,
+    /// The shots of the camera sweep played when the level starts.
+    #[serde(default)]
+    pub intro: Vec<IntroShot>,
+    /// Where the map's bottom-left corner is in the world. Everything the map places, from its
+    /// tiles to its rooms and triggers, is placed from here.
+    #[serde(skip)]
+    pub origin: (f32, f32),
+}
+
+/// How the map's tiles are laid out in the world.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MapProjection {
+    /// Square tiles `tile_size` across, seen from above.
+    #[default]
+    TopDown,
+    /// Diamond tiles `tile_size` wide and half as high, with tile `(0, 0)` at the bottom and
+    /// the map's left and bottom edges running out from it.
+    Isometric,
+}
+
+impl MapProjection {
+    /// The world position of `tile`, a position in tiles with the corners of tile `(0, 0)` at
+    /// whole numbers from `(0, 0)` to `(1, 1)`.
+    pub fn to_world(self, tile: Vector2<f32>, tile_size: f32) -> Vector2<f32> {
+        match self {
+            MapProjection::TopDown => tile * tile_size,
+            MapProjection::Isometric => Vector2::new(
+                (tile.x - tile.y) * tile_size / 2.,
+                (tile.x + tile.y) * tile_size / 4.,
+            ),
+        }
+    }
+
+    /// The position in tiles of `point`, undoing `to_world`.
+    pub fn to_tile(self, point: Vector2<f32>, tile_size: f32) -> Vector2<f32> {
+        match self {
+            MapProjection::TopDown => point / tile_size,
+            MapProjection::Isometric => Vector2::new(
+                point.x / tile_size + 2. * point.y / tile_size,
+                2. * point.y / tile_size - point.x / tile_size,
+            ),
+        }
+    }
+
+    pub fn tile_center(self, (column, row): TileCoord, tile_size: f32) -> Vector2<f32> {
+        self.to_world(
+            Vector2::new(column as f32 + 0.5, row as f32 + 0.5),
+            tile_size,
+        )
+    }
+
+    /// The corners of the tile at `(column, row)`, going around it from its bottom-left one
+    /// in tile space.
+    pub fn tile_corners(self, (column, row): TileCoord, tile_size: f32) -> [Vector2<f32>; 4] {
+        let (column, row) = (column as f32, row as f32);
+        [(0., 0.), (1., 0.), (1., 1.), (0., 1.)]
+            .map(|(x, y)| self.to_world(Vector2::new(column + x, row + y), tile_size))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Tile {
+    /// Always drawn with this sprite from the map's sprite sheet.
+    Static(usize),
+    /// Loops through the animation at this index in `TileMap::animations`.
+    Animated(usize),
+}
+
+/// A looping list of sprites, each shown for `frame_duration` seconds of game time.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TileAnimation {
+    pub frames: Vec<usize>,
+    pub frame_duration: f32,
+}
+
+impl TileAnimation {
+    /// The sprite to show `elapsed` seconds into the animation.
+    pub fn frame_at(&self, elapsed: f64) -> Option<usize> {
+        if self.frame_duration <= 0. {
+            return self.frames.first().copied();
+        }
+        let frame = (elapsed / f64::from(self.frame_duration)) as usize;
+        self.frames.get(frame % self.frames.len().max(1)).copied()
+    }
+}
+
+impl TileMap {
+    pub fn origin(&self) -> Vector2<f32> {
+        Vector2::new(self.origin.0, self.origin.1)
+    }
+
+    /// The corners of the smallest rectangle of the world holding the whole map, bottom-left
+    /// and top-right.
+    pub fn world_bounds(&self) -> (Vector2<f32>, Vector2<f32>) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let corners = [(0., 0.), (width, 0.), (width, height), (0., height)]
+            .map(|(x, y)| self.projection.to_world(Vector2::new(x, y), self.tile_size));
+        let (min, max) = corners
+            .iter()
+            .fold((corners[0], corners[0]), |(min, max), corner| {
+                (
+                    Vector2::new(min.x.min(corner.x), min.y.min(corner.y)),
+                    Vector2::new(max.x.max(corner.x), max.y.max(corner.y)),
+                )
+            });
+        (min + self.origin(), max + self.origin())
+    }
+
+    /// Loads a map and checks that its tiles fill it and only refer to animations it defines.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, failure::Error> {
+        let map = TileMap::load_no_fallback(path)?;
+        if map.tiles.len() != map.width * map.height {
+            return Err(format_err!(
+                "Tile map is {}x{} but has {} tiles",
+                map.width,
+                map.height,
+                map.tiles.len()
+            ));
+        }
+        for tile in &map.tiles {
+            if let Tile::Animated(animation) = *tile {
+                if map.animations.get(animation).is_none() {
+                    return Err(format_err!(
+                        "Tile refers to animation {} but the map has {}",
+                        animation,
+                        map.animations.len()
+                    ));
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// The tile at `coord`, or `None` outside the map.
+    pub fn tile(&self, (column, row): TileCoord) -> Option<Tile> {
+        if column < self.width && row < self.height {
+            // The map lists its rows from the top.
+            self.tiles
+                .get((self.height - 1 - row) * self.width + column)
+                .copied()
+        } else {
+            None
+        }
+    }
+
+    /// Whether each tile is one of `tiles`, row by row from the bottom-left corner, as the
+    /// grids built from the map keep them.
+    pub fn tile_mask(&self, tiles: &[Tile]) -> Vec<bool> {
+        let mut mask = vec![false; self.width * self.height];
+        for (index, tile) in self.tiles.iter().enumerate() {
+            // The map lists its rows from the top.
+            let (column, row) = (index % self.width, self.height - 1 - index / self.width);
+            mask[row * self.width + column] = tiles.contains(tile);
+        }
+        mask
+    }
+
+    /// What the tile at `coord` is made of, or `None` for no terrain in particular.
+    pub fn terrain(&self, coord: TileCoord) -> Option<Terrain> {
+        let tile = self.tile(coord)?;
+        self.terrain
+            .iter()
+            .find(|terrain| terrain.tiles.contains(&tile))
+            .map(|terrain| terrain.terrain)
+    }
+
+    /// Whether the tile at `coord` is drawn over whatever stands under it.
+    pub fn is_overhead(&self, coord: TileCoord) -> bool {
+        self.tile(coord)
+            .is_some_and(|tile| self.overhead.contains(&tile))
+    }
+
+    /// The depth the tile at `coord` is drawn at.
+    fn depth(&self, coord: TileCoord) -> f32 {
+        if self.is_overhead(coord) {
+            OVERHEAD_DEPTH
+        } else {
+            TILE_DEPTH
+        }
+    }
+
+    /// Replaces the tile at `coord`. Returns whether it was on the map.
+    pub fn set_tile(&mut self, (column, row): TileCoord, tile: Tile) -> bool {
+        if column < self.width && row < self.height {
+            self.tiles[(self.height - 1 - row) * self.width + column] = tile;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The sprite to draw at `coord`, and the animation it follows if it has one.
+    ///
+    /// Auto-tiled terrain treats the area outside the map as more of itself, so it runs on
+    /// past the edges.
+    pub fn sprite_at(&self, coord: TileCoord) -> Option<(usize, Option<TileAnimation>)> {
+        let tile = self.tile(coord)?;
+        if let Some(set) = self.auto_tiles.iter().find(|set| set.terrain == tile) {
+            let mask = bitmask(set.neighbourhood, |x, y| {
+                let (column, row) = (coord.0 as i64 + x, coord.1 as i64 + y);
+                if column < 0 || row < 0 {
+                    return true;
+                }
+                self.tile((column as usize, row as usize))
+                    .is_none_or(|neighbour| neighbour == tile)
+            });
+            return Some((set.sprite(mask), None));
+        }
+        Some(match tile {
+            Tile::Static(sprite) => (sprite, None),
+            Tile::Animated(animation) => {
+                let animation = self.animations.get(animation)?.clone();
+                (animation.frame_at(0.).unwrap_or(0), Some(animation))
+            }
+        })
+    }
+}
+
+/// Marks a tile whose sprite follows a `TileAnimation`.
+#[derive(Clone, Debug)]
+pub struct AnimatedTile(pub TileAnimation);
+
+impl Component for AnimatedTile {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The position of a tile entity on the map.
+#[derive(Clone, Copy, Debug)]
+pub struct MapTile(pub TileCoord);
+
+impl Component for MapTile {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Creates an entity for every tile of `map`, with the bottom-left corner of the map at its
+/// `origin`.
+pub fn spawn_tile_map(world: &mut World, map: &TileMap, sprite_sheet: Handle<SpriteSheet>) {
+    for row in 0..map.height {
+        for column in 0..map.width {
+            let center = map.projection.tile_center((column, row), map.tile_size) + map.origin();
+            let mut transform = Transform::default();
+            transform.set_translation_xyz(center.x, center.y, map.depth((column, row)));
+
+            let (sprite_number, animation) = map.sprite_at((column, row)).unwrap_or((0, None));
+            let builder = world
+                .create_entity()
+                .with(transform)
+                .with(SpriteRender {
+                    sprite_sheet: sprite_sheet.clone(),
+                    sprite_number,
+                })
+                .with(MapTile((column, row)));
+            match animation {
+                Some(animation) => builder.with(AnimatedTile(animation)).build(),
+                None => builder.build(),
+            };
+        }
+    }
+}
+
+/// Asks for the tile at `coord` to be replaced with `tile`.
+#[derive(Clone, Copy, Debug)]
+pub struct TileEdit {
+    pub coord: TileCoord,
+    pub tile: Tile,
+}
+
+/// Applies `TileEdit`s to the `TileMap` resource and redraws the edited tiles and their
+/// neighbours, whose auto-tiling may have changed. Rebuilds the `NavGrid`, `SightGrid`,
+/// `LightGrid` and `TileColliders` to match.
+#[derive(Default)]
+pub struct TileEditSystem {
+    reader: Option<ReaderId<TileEdit>>,
+}
+
+impl<'s> System<'s> for TileEditSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, MapTile>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, AnimatedTile>,
+        WriteStorage<'s, Transform>,
+        Read<'s, EventChannel<TileEdit>>,
+        Write<'s, TileMap>,
+        Write<'s, NavGrid>,
+        Write<'s, SightGrid>,
+        Write<'s, LightGrid>,
+        Write<'s, TileColliders>,
+        Read<'s, TileCollisionConfig>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            map_tiles,
+            mut sprites,
+            mut animated,
+            mut transforms,
+            edits,
+            mut map,
+            mut nav_grid,
+            mut sight_grid,
+            mut light_grid,
+            mut walls,
+            collision,
+        ): Self::SystemData,
+    ) {
+        let reader = self.reader.as_mut().expect("TileEditSystem is set up");
+        let edited: Vec<TileCoord> = edits
+            .read(reader)
+            .filter(|edit| map.set_tile(edit.coord, edit.tile))
+            .map(|edit| edit.coord)
+            .collect();
+        if edited.is_empty() {
+            return;
+        }
+
+        let near_edit = |(column, row): TileCoord| {
+            edited.iter().any(|&(edited_column, edited_row)| {
+                (column as i64 - edited_column as i64).abs() <= 1
+                    && (row as i64 - edited_row as i64).abs() <= 1
+            })
+        };
+        for (entity, &MapTile(coord), sprite, transform) in
+            (&entities, &map_tiles, &mut sprites, &mut transforms).join()
+        {
+            if !near_edit(coord) {
+                continue;
+            }
+            transform.set_translation_z(map.depth(coord));
+            let (sprite_number, animation) = match map.sprite_at(coord) {
+                Some(drawn) => drawn,
+                None => continue,
+            };
+            sprite.sprite_number = sprite_number;
+            match animation {
+                Some(animation) => {
+                    animated
+                        .insert(entity, AnimatedTile(animation))
+                        .expect("Tile entity is alive");
+                }
+                None => {
+                    animated.remove(entity);
+                }
+            }
+        }
+
+        *nav_grid = NavGrid::from_map(&map);
+        *sight_grid = SightGrid::from_map(&map);
+        light_grid.rebuild(&map);
+        *walls = TileColliders::from_map(&map, &collision);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(res.fetch_mut::<EventChannel<TileEdit>>().register_reader());
+    }
+}
+
+/// Advances the sprite of every `AnimatedTile` with the `GameClock`.
+pub struct TileAnimationSystem;
+
+impl<'s> System<'s> for TileAnimationSystem {
+    type SystemData = (
+        ReadStorage<'s, AnimatedTile>,
+        WriteStorage<'s, SpriteRender>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(&mut self, (animated, mut sprites, clock): Self::SystemData) {
+        let elapsed = clock.elapsed_seconds();
+        for (animated, sprite) in (&animated, &mut sprites).join() {
+            if let Some(frame) = animated.0.frame_at(elapsed) {
+                if sprite.sprite_number != frame {
+                    sprite.sprite_number = frame;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_start_from_the_bottom_row() {
+        let (floor, wall) = (Tile::Static(0), Tile::Static(1));
+        let map = TileMap {
+            width: 2,
+            height: 2,
+            tiles: vec![wall, floor, floor, floor],
+            ..TileMap::default()
+        };
+        assert_eq!(map.tile_mask(&[wall]), [false, false, true, false]);
+        assert_eq!(map.tile((0, 1)), Some(wall));
+    }
+
+    #[test]
+    fn isometric_maps_are_bounded_by_their_corners() {
+        let mut map = TileMap {
+            width: 2,
+            height: 1,
+            tile_size: 16.,
+            ..TileMap::default()
+        };
+        map.origin = (100., 0.);
+        assert_eq!(
+            map.world_bounds(),
+            (Vector2::new(100., 0.), Vector2::new(132., 16.))
+        );
+        map.projection = MapProjection::Isometric;
+        assert_eq!(
+            map.world_bounds(),
+            (Vector2::new(92., 0.), Vector2::new(116., 12.))
+        );
+    }
+
+    #[test]
+    fn animation_frames_cycle_and_wrap() {
+        let animation = TileAnimation {
+            frames: vec![4, 5, 6],
+            frame_duration: 0.25,
+        };
+        let frames: Vec<_> = [0., 0.125, 0.25, 0.5, 0.75, 1., 2.625]
+            .iter()
+            .map(|&elapsed| animation.frame_at(elapsed))
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                Some(4),
+                Some(4),
+                Some(5),
+                Some(6),
+                Some(4),
+                Some(5),
+                Some(5)
+            ]
+        );
+
+        let still = TileAnimation {
+            frame_duration: 0.,
+            ..animation
+        };
+        assert_eq!(still.frame_at(10.), Some(4));
+        let empty = TileAnimation {
+            frames: Vec::new(),
+            frame_duration: 0.25,
+        };
+        assert_eq!(empty.frame_at(1.), None);
+    }
+
+    #[test]
+    fn isometric_tiles_round_trip_through_the_world() {
+        let projection = MapProjection::Isometric;
+        for &(x, y) in &[
+            (0., 0.),
+            (3., 1.),
+            (-2., 5.),
+            (4., -3.),
+            (-6., -1.),
+            (-1.5, 2.25),
+        ] {
+            let tile = Vector2::new(x, y);
+            let world = projection.to_world(tile, 32.);
+            assert!((projection.to_tile(world, 32.) - tile).norm() < 1e-5);
+        }
+        // A step along a row goes down and to the right on screen, and along a column down
+        // and to the left.
+        assert_eq!(
+            projection.to_world(Vector2::new(1., 0.), 32.),
+            Vector2::new(16., 8.)
+        );
+        assert_eq!(
+            projection.to_world(Vector2::new(0., -1.), 32.),
+            Vector2::new(16., -8.)
+        );
+        for &(x, y) in &[(-40., -12.), (25., -30.), (-7.5, 61.)] {
+            let point = Vector2::new(x, y);
+            let tile = projection.to_tile(point, 32.);
+            assert!((projection.to_world(tile, 32.) - point).norm() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,707 @@
+//! The look shared by every piece of UI, read from `ui_theme.ron`.
+//!
+//! Everything that builds UI takes its font, colours and spacing from the `UiTheme` resource,
+//! so editing the theme restyles the whole game. Buttons built with `ThemedButtonBuilder` also
+//! take their look in each `ButtonState` from it.
+//!
+//! For low-vision players the theme also scales all text up or down, growing the UI holding it
+//! to match, and can draw all text in high contrast. Both can be changed in game with the
+//! `increase_text_scale`, `decrease_text_scale` and `toggle_high_contrast` actions.
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::Parent,
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Entity, Join, Read, ReadExpect, ReadStorage,
+        Resources, System, SystemData, World, Write, WriteStorage,
+    },
+    input::{InputHandler, StringBindings},
+    renderer::{ImageFormat, Texture},
+    shrev::{EventChannel, ReaderId},
+    ui::{
+        get_default_font, Anchor, FontAsset, FontHandle, Interactable, Stretch, TtfFormat, UiEvent,
+        UiEventType, UiImage, UiText, UiTransform,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// The smallest and largest `UiTheme::text_scale` the actions can pick, and how far apart the
+/// scales they step through are.
+const MIN_TEXT_SCALE: f32 = 0.5;
+const MAX_TEXT_SCALE: f32 = 3.;
+const TEXT_SCALE_STEP: f32 = 0.25;
+
+/// How a button looks in one `ButtonState`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ButtonLook {
+    pub background: [f32; 4],
+    pub text: [f32; 4],
+    /// Image drawn instead of `background`, relative to `resources`.
+    #[serde(default)]
+    pub image: Option<String>,
+}
+
+impl ButtonLook {
+    fn new(background: [f32; 4], text: [f32; 4]) -> Self {
+        ButtonLook {
+            background,
+            text,
+            image: None,
+        }
+    }
+
+    fn ui_image(&self, loader: &Loader, textures: &AssetStorage<Texture>) -> UiImage {
+        match &self.image {
+            Some(path) => {
+                UiImage::Texture(loader.load(path.as_str(), ImageFormat::default(), (), textures))
+            }
+            None => UiImage::SolidColor(self.background),
+        }
+    }
+}
+
+/// What a button is doing, which decides how it looks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonState {
+    Normal,
+    Hover,
+    Pressed,
+    Disabled,
+}
+
+/// How buttons look in each `ButtonState`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ButtonTheme {
+    pub normal: ButtonLook,
+    pub hover: ButtonLook,
+    pub pressed: ButtonLook,
+    pub disabled: ButtonLook,
+}
+
+impl ButtonTheme {
+    pub fn look(&self, state: ButtonState) -> &ButtonLook {
+        match state {
+            ButtonState::Normal => &self.normal,
+            ButtonState::Hover => &self.hover,
+            ButtonState::Pressed => &self.pressed,
+            ButtonState::Disabled => &self.disabled,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UiTheme {
+    /// TrueType font relative to `resources`. The default UI font is used without one.
+    pub font: Option<String>,
+    pub font_size: f32,
+    pub text_color: [f32; 4],
+    /// Pixels between UI and the edges of the window, and between neighbouring UI.
+    pub padding: f32,
+    pub bar_background: [f32; 4],
+    pub bar_fill: [f32; 4],
+    pub button: ButtonTheme,
+    /// Multiplies the size of all text, and of the UI holding it.
+    pub text_scale: f32,
+    /// Draws all text fully opaque in black or white, whichever its colour is closer to.
+    pub high_contrast: bool,
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        UiTheme {
+            font: None,
+            font_size: 14.,
+            text_color: [1., 1., 1., 1.],
+            padding: 8.,
+            bar_background: [0.15, 0.15, 0.15, 0.8],
+            bar_fill: [0.8, 0.15, 0.15, 1.],
+            button: ButtonTheme {
+                normal: ButtonLook::new([0.2, 0.2, 0.25, 0.9], [1., 1., 1., 1.]),
+                hover: ButtonLook::new([0.3, 0.3, 0.4, 0.9], [1., 1., 1., 1.]),
+                pressed: ButtonLook::new([0.1, 0.1, 0.15, 0.9], [0.8, 0.8, 0.8, 1.]),
+                disabled: ButtonLook::new([0.2, 0.2, 0.2, 0.5], [0.5, 0.5, 0.5, 1.]),
+            },
+            text_scale: 1.,
+            high_contrast: false,
+        }
+    }
+}
+
+impl UiTheme {
+    pub fn font(&self, loader: &Loader, fonts: &AssetStorage<FontAsset>) -> FontHandle {
+        match &self.font {
+            Some(path) => loader.load(path.as_str(), TtfFormat, (), fonts),
+            None => get_default_font(loader, fonts),
+        }
+    }
+
+    /// The size text `size` pixels high at a text scale of 1 is drawn at.
+    pub fn text_size(&self, size: f32) -> f32 {
+        size * self.text_scale
+    }
+
+    /// Makes text `steps` steps bigger, or smaller for negative `steps`, within the scales the
+    /// actions can pick.
+    pub fn step_text_scale(&mut self, steps: f32) {
+        self.text_scale =
+            (self.text_scale + steps * TEXT_SCALE_STEP).clamp(MIN_TEXT_SCALE, MAX_TEXT_SCALE);
+    }
+
+    /// Makes text one step bigger, going back to the smallest scale after the largest.
+    pub fn cycle_text_scale(&mut self) {
+        if self.text_scale >= MAX_TEXT_SCALE {
+            self.text_scale = MIN_TEXT_SCALE;
+        } else {
+            self.step_text_scale(1.);
+        }
+    }
+
+    /// The colour and size of the label of a button in `state`.
+    pub fn button_label(&self, state: ButtonState) -> ([f32; 4], f32) {
+        (
+            self.text_color(self.button.look(state).text),
+            self.text_size(self.font_size),
+        )
+    }
+
+    /// The colour text meant to be `color` is drawn in.
+    pub fn text_color(&self, color: [f32; 4]) -> [f32; 4] {
+        if !self.high_contrast {
+            return color;
+        }
+        let luminance = 0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2];
+        if luminance >= 0.5 {
+            [1., 1., 1., 1.]
+        } else {
+            [0., 0., 0., 1.]
+        }
+    }
+}
+
+/// Text whose size, colour and box follow the `UiTheme`'s text scale and contrast. Holds them
+/// as they are at a text scale of 1 without high contrast.
+#[derive(Clone, Copy, Debug)]
+pub struct ThemedText {
+    pub font_size: f32,
+    pub color: [f32; 4],
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ThemedText {
+    pub fn new(text: &UiText, transform: &UiTransform) -> Self {
+        ThemedText {
+            font_size: text.font_size,
+            color: text.color,
+            width: transform.width,
+            height: transform.height,
+        }
+    }
+
+    /// Sizes and colours `text` and its box for `theme`. The box grows with the text, so text
+    /// that fitted at a text scale of 1 still fits.
+    pub fn apply(&self, theme: &UiTheme, text: &mut UiText, transform: &mut UiTransform) {
+        text.font_size = theme.text_size(self.font_size);
+        text.color = theme.text_color(self.color);
+        self.resize(theme, transform);
+    }
+
+    /// Sizes the box alone for `theme`.
+    pub fn resize(&self, theme: &UiTheme, transform: &mut UiTransform) {
+        transform.width = self.width * theme.text_scale;
+        transform.height = self.height * theme.text_scale;
+    }
+}
+
+impl Component for ThemedText {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Steps the text scale with the `increase_text_scale` and `decrease_text_scale` actions and
+/// toggles high contrast with `toggle_high_contrast`. Restyles every `ThemedText` when either
+/// changes.
+#[derive(Default)]
+pub struct TextAccessibilitySystem {
+    was_increase_pressed: bool,
+    was_decrease_pressed: bool,
+    was_contrast_pressed: bool,
+    /// The text scale and contrast the `ThemedText`s were last styled for.
+    applied: Option<(f32, bool)>,
+}
+
+impl<'s> System<'s> for TextAccessibilitySystem {
+    type SystemData = (
+        ReadStorage<'s, ThemedText>,
+        WriteStorage<'s, UiText>,
+        WriteStorage<'s, UiTransform>,
+        Write<'s, UiTheme>,
+        Read<'s, InputHandler<StringBindings>>,
+    );
+
+    fn run(&mut self, (themed, mut texts, mut transforms, mut theme, input): Self::SystemData) {
+        let increase = input.action_is_down("increase_text_scale").unwrap_or(false);
+        if increase && !self.was_increase_pressed {
+            theme.step_text_scale(1.);
+        }
+        self.was_increase_pressed = increase;
+
+        let decrease = input.action_is_down("decrease_text_scale").unwrap_or(false);
+        if decrease && !self.was_decrease_pressed {
+            theme.step_text_scale(-1.);
+        }
+        self.was_decrease_pressed = decrease;
+
+        let contrast = input
+            .action_is_down("toggle_high_contrast")
+            .unwrap_or(false);
+        if contrast && !self.was_contrast_pressed {
+            theme.high_contrast = !theme.high_contrast;
+        }
+        self.was_contrast_pressed = contrast;
+
+        // Newly built text is styled by whatever built it, so only a change needs restyling.
+        let applied = (theme.text_scale, theme.high_contrast);
+        if self.applied == Some(applied) {
+            return;
+        }
+        self.applied = Some(applied);
+        for (themed, text, transform) in (&themed, &mut texts, &mut transforms).join() {
+            themed.apply(&theme, text, transform);
+        }
+    }
+}
+
+/// A button styled by the `UiTheme`. Its label is a child entity filling it.
+#[derive(Clone, Debug)]
+pub struct ThemedButton {
+    /// A disabled button ignores the mouse and sends no `ButtonClick`s.
+    pub enabled: bool,
+    /// Drawn as if hovered. Set on the button picked with the keyboard or a gamepad.
+    pub focused: bool,
+    label: Entity,
+    hovered: bool,
+    pressed: bool,
+    /// The button's size at a text scale of 1.
+    width: f32,
+    height: f32,
+}
+
+impl ThemedButton {
+    pub fn state(&self) -> ButtonState {
+        if !self.enabled {
+            ButtonState::Disabled
+        } else if self.pressed {
+            ButtonState::Pressed
+        } else if self.hovered || self.focused {
+            ButtonState::Hover
+        } else {
+            ButtonState::Normal
+        }
+    }
+
+    /// The entity holding the button's `UiText`.
+    pub fn label(&self) -> Entity {
+        self.label
+    }
+}
+
+impl Component for ThemedButton {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Sent when an enabled `ThemedButton` is clicked.
+#[derive(Clone, Copy, Debug)]
+pub struct ButtonClick {
+    pub button: Entity,
+}
+
+/// Creates a `ThemedButton` with its label.
+pub struct ThemedButtonBuilder {
+    text: String,
+    anchor: Anchor,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    enabled: bool,
+}
+
+impl ThemedButtonBuilder {
+    /// An enabled 96 by 28 pixel button in the middle of the window.
+    pub fn new(text: impl Into<String>) -> Self {
+        ThemedButtonBuilder {
+            text: text.into(),
+            anchor: Anchor::Middle,
+            x: 0.,
+            y: 0.,
+            width: 96.,
+            height: 28.,
+            enabled: true,
+        }
+    }
+
+    /// Places the button's `anchor` corner or edge `x` and `y` pixels from the same point of the
+    /// window, so the button grows away from it as the text scale goes up.
+    pub fn with_position(mut self, anchor: Anchor, x: f32, y: f32) -> Self {
+        self.anchor = anchor;
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    /// Sizes the button at a text scale of 1.
+    pub fn with_size(mut self, width: f32, height: f32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn build(self, world: &mut World) -> Entity {
+        let theme = world.read_resource::<UiTheme>().clone();
+        let button = ThemedButton {
+            enabled: self.enabled,
+            focused: false,
+            label: world.create_entity().build(),
+            hovered: false,
+            pressed: false,
+            width: self.width,
+            height: self.height,
+        };
+        let look = theme.button.look(button.state());
+        let (image, font) = {
+            let loader = world.read_resource::<Loader>();
+            (
+                look.ui_image(&loader, &world.read_resource::<AssetStorage<Texture>>()),
+                theme.font(&loader, &world.read_resource::<AssetStorage<FontAsset>>()),
+            )
+        };
+        let label = button.label;
+        let (color, size) = theme.button_label(button.state());
+        let text = UiText::new(font, self.text.clone(), color, size);
+        let entity = world
+            .create_entity()
+            .with(UiTransform::new(
+                format!("button_{}", self.text),
+                self.anchor.clone(),
+                self.anchor.clone(),
+                self.x,
+                self.y,
+                10.,
+                self.width * theme.text_scale,
+                self.height * theme.text_scale,
+            ))
+            .with(image)
+            .with(Interactable)
+            .with(button)
+            .build();
+
+        let mut transforms = world.write_storage::<UiTransform>();
+        transforms
+            .insert(
+                label,
+                UiTransform::new(
+                    format!("button_{}_label", self.text),
+                    Anchor::Middle,
+                    Anchor::Middle,
+                    0.,
+                    0.,
+                    1.,
+                    self.width,
+                    self.height,
+                )
+                .with_stretch(Stretch::XY {
+                    x_margin: 0.,
+                    y_margin: 0.,
+                    keep_aspect_ratio: false,
+                })
+                .as_transparent(),
+            )
+            .expect("Button label is alive");
+        world
+            .write_storage::<UiText>()
+            .insert(label, text)
+            .expect("Button label is alive");
+        world
+            .write_storage::<Parent>()
+            .insert(label, Parent { entity })
+            .expect("Button label is alive");
+        entity
+    }
+}
+
+/// Tracks the mouse over every `ThemedButton`, restyles buttons whose `ButtonState` changed and
+/// sends a `ButtonClick` for each click on an enabled button. Resizes and restyles every button
+/// when the text scale or contrast changes.
+#[derive(Default)]
+pub struct ThemedButtonSystem {
+    reader: Option<ReaderId<UiEvent>>,
+    /// The state each button was last drawn in.
+    drawn: Vec<(Entity, ButtonState)>,
+    /// The text scale and contrast the buttons were last drawn with.
+    applied: Option<(f32, bool)>,
+}
+
+impl<'s> System<'s> for ThemedButtonSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, ThemedButton>,
+        WriteStorage<'s, UiImage>,
+        WriteStorage<'s, UiText>,
+        WriteStorage<'s, UiTransform>,
+        Read<'s, EventChannel<UiEvent>>,
+        Write<'s, EventChannel<ButtonClick>>,
+        Read<'s, UiTheme>,
+        Read<'s, AssetStorage<Texture>>,
+        ReadExpect<'s, Loader>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut buttons,
+            mut images,
+            mut texts,
+            mut transforms,
+            events,
+            mut clicks,
+            theme,
+            textures,
+            loader,
+        ): Self::SystemData,
+    ) {
+        let reader = self.reader.as_mut().expect("ThemedButtonSystem is set up");
+        for event in events.read(reader) {
+            let button = match buttons.get_mut(event.target) {
+                Some(button) => button,
+                None => continue,
+            };
+            match event.event_type {
+                UiEventType::HoverStart => button.hovered = true,
+                UiEventType::HoverStop => {
+                    button.hovered = false;
+                    button.pressed = false;
+                }
+                UiEventType::ClickStart => button.pressed = true,
+                UiEventType::ClickStop => button.pressed = false,
+                UiEventType::Click if button.enabled => clicks.single_write(ButtonClick {
+                    button: event.target,
+                }),
+                _ => {}
+            }
+        }
+
+        let applied = (theme.text_scale, theme.high_contrast);
+        if self.applied != Some(applied) {
+            self.applied = Some(applied);
+            self.drawn.clear();
+            for (button, transform) in (&buttons, &mut transforms).join() {
+                transform.width = button.width * theme.text_scale;
+                transform.height = button.height * theme.text_scale;
+                if let Some(text) = texts.get_mut(button.label) {
+                    text.font_size = theme.text_size(theme.font_size);
+                }
+            }
+        }
+
+        self.drawn.retain(|&(entity, _)| entities.is_alive(entity));
+        for (entity, button) in (&entities, &buttons).join() {
+            let state = button.state();
+            match self.drawn.iter_mut().find(|(drawn, _)| *drawn == entity) {
+                Some((_, drawn)) if *drawn == state => continue,
+                Some((_, drawn)) => *drawn = state,
+                None => self.drawn.push((entity, state)),
+            }
+            let look = theme.button.look(state);
+            images
+                .insert(entity, look.ui_image(&loader, &textures))
+                .expect("Button is alive");
+            if let Some(text) = texts.get_mut(button.label) {
+                text.color = theme.button_label(state).0;
+            }
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.reader = Some(res.fetch_mut::<EventChannel<UiEvent>>().register_reader());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{ecs::prelude::RunNow, winit::VirtualKeyCode};
+
+    use super::*;
+    use crate::player::tests::{bind, press_key};
+
+    fn theme() -> UiTheme {
+        ron::de::from_str(
+            r#"(
+                font: Some("fonts/round.ttf"),
+                font_size: 20.0,
+                text_scale: 1.5,
+                button: (
+                    normal: (background: (0.1, 0.2, 0.3, 1.0), text: (0.9, 0.9, 0.8, 1.0)),
+                    hover: (background: (0.2, 0.3, 0.4, 1.0), text: (1.0, 1.0, 0.0, 1.0)),
+                    pressed: (background: (0.0, 0.1, 0.2, 1.0), text: (0.7, 0.7, 0.7, 1.0)),
+                    disabled: (
+                        background: (0.3, 0.3, 0.3, 0.5),
+                        text: (0.4, 0.4, 0.4, 1.0),
+                        image: Some("textures/ui/disabled.png"),
+                    ),
+                ),
+            )"#,
+        )
+        .unwrap()
+    }
+
+    fn button(enabled: bool, hovered: bool, pressed: bool) -> ThemedButton {
+        let mut world = World::new();
+        ThemedButton {
+            enabled,
+            focused: false,
+            label: world.create_entity().build(),
+            hovered,
+            pressed,
+            width: 96.,
+            height: 28.,
+        }
+    }
+
+    #[test]
+    fn a_themed_button_takes_the_themes_colours_and_font() {
+        let theme = theme();
+        assert_eq!(theme.font.as_deref(), Some("fonts/round.ttf"));
+
+        let cases = [
+            (
+                button(true, false, false),
+                [0.9, 0.9, 0.8, 1.],
+                [0.1, 0.2, 0.3, 1.],
+            ),
+            (
+                button(true, true, false),
+                [1., 1., 0., 1.],
+                [0.2, 0.3, 0.4, 1.],
+            ),
+            (
+                button(true, true, true),
+                [0.7, 0.7, 0.7, 1.],
+                [0., 0.1, 0.2, 1.],
+            ),
+            (
+                button(false, true, true),
+                [0.4, 0.4, 0.4, 1.],
+                [0.3, 0.3, 0.3, 0.5],
+            ),
+        ];
+        for (button, text, background) in &cases {
+            let state = button.state();
+            assert_eq!(theme.button_label(state), (*text, 30.));
+            assert_eq!(theme.button.look(state).background, *background);
+        }
+        assert_eq!(
+            theme.button.disabled.image.as_deref(),
+            Some("textures/ui/disabled.png")
+        );
+        assert_eq!(theme.button.normal.image, None);
+
+        // High contrast overrides the theme's label colours.
+        let contrast = UiTheme {
+            high_contrast: true,
+            ..theme
+        };
+        assert_eq!(
+            contrast.button_label(ButtonState::Disabled).0,
+            [0., 0., 0., 1.]
+        );
+        assert_eq!(
+            contrast.button_label(ButtonState::Hover).0,
+            [1., 1., 1., 1.]
+        );
+    }
+
+    fn accessibility() -> (World, TextAccessibilitySystem) {
+        let mut world = World::new();
+        let mut system = TextAccessibilitySystem::default();
+        System::setup(&mut system, &mut world.res);
+        bind(
+            &mut world,
+            r#"(
+                axes: {},
+                actions: {
+                    "increase_text_scale": [[Key(Equals)]],
+                    "decrease_text_scale": [[Key(Minus)]],
+                },
+            )"#,
+        );
+        (world, system)
+    }
+
+    /// Taps `key` once, running `system` with it down and then up.
+    fn tap(world: &mut World, system: &mut TextAccessibilitySystem, key: VirtualKeyCode) {
+        press_key(world, key, true);
+        system.run_now(&world.res);
+        press_key(world, key, false);
+        system.run_now(&world.res);
+    }
+
+    #[test]
+    fn doubling_the_text_scale_doubles_every_size() {
+        let (mut world, mut system) = accessibility();
+        let themed = ThemedText {
+            font_size: 14.,
+            color: [1., 1., 1., 1.],
+            width: 120.,
+            height: 20.,
+        };
+        let mut transform = UiTransform::new(
+            "label".to_string(),
+            Anchor::Middle,
+            Anchor::Middle,
+            0.,
+            0.,
+            1.,
+            120.,
+            20.,
+        );
+
+        for _ in 0..4 {
+            tap(&mut world, &mut system, VirtualKeyCode::Equals);
+        }
+        let theme = world.read_resource::<UiTheme>().clone();
+        assert_eq!(theme.text_scale, 2.);
+        for &size in &[10., 14., 32.] {
+            assert_eq!(theme.text_size(size), size * 2.);
+        }
+        assert_eq!(theme.button_label(ButtonState::Normal).1, 28.);
+        themed.resize(&theme, &mut transform);
+        assert_eq!((transform.width, transform.height), (240., 40.));
+    }
+
+    #[test]
+    fn the_text_scale_stays_within_its_limits() {
+        let (mut world, mut system) = accessibility();
+        for _ in 0..20 {
+            tap(&mut world, &mut system, VirtualKeyCode::Equals);
+        }
+        assert_eq!(world.read_resource::<UiTheme>().text_scale, MAX_TEXT_SCALE);
+        for _ in 0..20 {
+            tap(&mut world, &mut system, VirtualKeyCode::Minus);
+        }
+        assert_eq!(world.read_resource::<UiTheme>().text_scale, MIN_TEXT_SCALE);
+
+        let mut theme = UiTheme::default();
+        theme.step_text_scale(100.);
+        assert_eq!(theme.text_scale, MAX_TEXT_SCALE);
+        theme.cycle_text_scale();
+        assert_eq!(theme.text_scale, MIN_TEXT_SCALE);
+    }
+}
@@ -0,0 +1,385 @@
+//! Waves of enemies for arena and survival play.
+//!
+//! The `WaveSystem` works through the waves in `waves.ron`: it spawns the enemies of a wave one
+//! at a time, waits for them to be cleared or for the wave's timeout, pauses, and starts the
+//! next. With `endless` set, the last wave comes back bigger and tougher each time after the
+//! list runs out.
+
+use amethyst::{
+    ecs::prelude::{Component, Join, NullStorage, Read, ReadStorage, System, Write},
+    shrev::EventChannel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::GameClock, death::Dead, difficulty::Difficulty, enemy::EnemySpawn};
+
+/// One wave of enemies.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Wave {
+    /// The enemies making up the wave, where they spawn.
+    pub enemies: Vec<EnemySpawn>,
+    /// How many times `enemies` are spawned.
+    #[serde(default = "one")]
+    pub count: usize,
+    /// Seconds between one enemy spawning and the next.
+    #[serde(default)]
+    pub spawn_delay: f32,
+    /// Seconds after the wave starts at which the next comes, even if this one isn't cleared.
+    #[serde(default)]
+    pub timeout: Option<f32>,
+}
+
+fn one() -> usize {
+    1
+}
+
+/// How the last wave grows each time it comes back once the list has run out.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EndlessWaves {
+    /// Extra repeats of the wave's enemies each time.
+    pub count_growth: f32,
+    /// Multiplies the enemies' health each time.
+    pub health_growth: f32,
+}
+
+impl Default for EndlessWaves {
+    fn default() -> Self {
+        EndlessWaves {
+            count_growth: 0.5,
+            health_growth: 1.2,
+        }
+    }
+}
+
+/// The waves of a game, read from `waves.ron`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WaveConfig {
+    pub enabled: bool,
+    pub waves: Vec<Wave>,
+    /// Seconds before the first wave and between one wave ending and the next starting.
+    pub pause: f32,
+    pub endless: Option<EndlessWaves>,
+}
+
+impl Default for WaveConfig {
+    fn default() -> Self {
+        WaveConfig {
+            enabled: false,
+            waves: Vec::new(),
+            pause: 3.,
+            endless: None,
+        }
+    }
+}
+
+impl WaveConfig {
+    /// The wave at `index`, counting from zero, with the enemies it spawns in order, its count
+    /// multiplied by `count_scale`. `None` once the waves have run out.
+    pub fn wave(&self, index: usize, count_scale: f32) -> Option<(Wave, Vec<EnemySpawn>)> {
+        let last = self.waves.len().checked_sub(1)?;
+        let mut wave = match (self.waves.get(index), self.endless) {
+            (Some(wave), _) => wave.clone(),
+            (None, Some(endless)) => {
+                let repeats = (index - last) as f32;
+                let mut wave = self.waves[last].clone();
+                wave.count += (endless.count_growth * repeats).round() as usize;
+                let health = endless.health_growth.powf(repeats);
+                for enemy in &mut wave.enemies {
+                    enemy.health *= health;
+                }
+                wave
+            }
+            (None, None) => return None,
+        };
+        wave.count = ((wave.count as f32 * count_scale).round() as usize).max(1);
+        let spawns = (0..wave.count)
+            .flat_map(|_| wave.enemies.iter().cloned())
+            .collect();
+        Some((wave, spawns))
+    }
+}
+
+/// Sent as waves begin and end, numbered from one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaveEvent {
+    Started(usize),
+    /// Sent when every enemy of the wave is dead, but not when the wave timed out.
+    Cleared(usize),
+}
+
+/// Marks an enemy spawned by a wave.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WaveMember;
+
+impl Component for WaveMember {
+    type Storage = NullStorage<Self>;
+}
+
+/// Enemies a wave wants spawned, taken by the game, which can place them in the world.
+#[derive(Clone, Debug, Default)]
+pub struct WaveSpawns(pub Vec<EnemySpawn>);
+
+#[derive(Clone, Debug)]
+enum Stage {
+    /// Waiting `remaining` seconds before the wave at `next` starts.
+    Pause { next: usize, remaining: f32 },
+    /// Wave `index` is on, with `queued` still to spawn, the next in `next_spawn` seconds.
+    Running {
+        index: usize,
+        wave: Wave,
+        queued: Vec<EnemySpawn>,
+        next_spawn: f32,
+        elapsed: f32,
+    },
+    /// The waves have run out.
+    Finished,
+}
+
+/// Where a game is in its waves.
+#[derive(Clone, Debug)]
+pub struct WaveScheduler {
+    stage: Stage,
+}
+
+impl WaveScheduler {
+    pub fn new(config: &WaveConfig) -> Self {
+        WaveScheduler {
+            stage: Stage::Pause {
+                next: 0,
+                remaining: config.pause,
+            },
+        }
+    }
+
+    /// Advances the waves by `delta` seconds with `alive` wave enemies still standing, scaling
+    /// the size of waves by `count_scale`. Adds the enemies to spawn to `spawns` and what
+    /// happened to `events`.
+    pub fn step(
+        &mut self,
+        config: &WaveConfig,
+        count_scale: f32,
+        delta: f32,
+        alive: usize,
+        spawns: &mut Vec<EnemySpawn>,
+        events: &mut Vec<WaveEvent>,
+    ) {
+        match &mut self.stage {
+            Stage::Pause { next, remaining } => {
+                *remaining -= delta;
+                if *remaining > 0. {
+                    return;
+                }
+                let index = *next;
+                self.stage = match config.wave(index, count_scale) {
+                    Some((wave, mut queued)) => {
+                        events.push(WaveEvent::Started(index + 1));
+                        // Spawned from the front.
+                        queued.reverse();
+                        Stage::Running {
+                            index,
+                            wave,
+                            queued,
+                            next_spawn: 0.,
+                            elapsed: 0.,
+                        }
+                    }
+                    None => Stage::Finished,
+                };
+                // The first enemy comes in the same step the wave starts.
+                self.step(config, count_scale, 0., alive, spawns, events);
+            }
+            Stage::Running {
+                index,
+                wave,
+                queued,
+                next_spawn,
+                elapsed,
+            } => {
+                *elapsed += delta;
+                *next_spawn -= delta;
+                while *next_spawn <= 0. {
+                    match queued.pop() {
+                        Some(spawn) => spawns.push(spawn),
+                        None => break,
+                    }
+                    *next_spawn += wave.spawn_delay;
+                }
+                if !queued.is_empty() {
+                    return;
+                }
+                let cleared = alive == 0 && spawns.is_empty();
+                let timed_out = wave.timeout.is_some_and(|timeout| *elapsed >= timeout);
+                if cleared || timed_out {
+                    if cleared {
+                        events.push(WaveEvent::Cleared(*index + 1));
+                    }
+                    self.stage = Stage::Pause {
+                        next: *index + 1,
+                        remaining: config.pause,
+                    };
+                }
+            }
+            Stage::Finished => {}
+        }
+    }
+}
+
+/// Runs the `WaveScheduler` each gameplay step, queueing enemies in `WaveSpawns` and sending
+/// `WaveEvent`s.
+#[derive(Default)]
+pub struct WaveSystem {
+    scheduler: Option<WaveScheduler>,
+}
+
+impl<'s> System<'s> for WaveSystem {
+    type SystemData = (
+        ReadStorage<'s, WaveMember>,
+        ReadStorage<'s, Dead>,
+        Write<'s, WaveSpawns>,
+        Write<'s, EventChannel<WaveEvent>>,
+        Read<'s, WaveConfig>,
+        Read<'s, Difficulty>,
+        Read<'s, GameClock>,
+    );
+
+    fn run(
+        &mut self,
+        (members, dead, mut spawns, mut channel, config, difficulty, clock): Self::SystemData,
+    ) {
+        if !config.enabled {
+            return;
+        }
+        let scheduler = self
+            .scheduler
+            .get_or_insert_with(|| WaveScheduler::new(&config));
+        let alive = (&members, !&dead).join().count();
+        let mut events = Vec::new();
+        scheduler.step(
+            &config,
+            difficulty.multipliers().enemy_count,
+            clock.delta_seconds(),
+            alive,
+            &mut spawns.0,
+            &mut events,
+        );
+        channel.iter_write(events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two waves a second apart: two goblins, cleared to carry on, then an ogre that holds
+    /// out five seconds at most. The ogre comes back after them if the waves are endless.
+    fn config(endless: bool) -> WaveConfig {
+        let mut config: WaveConfig = ron::de::from_str(
+            "(
+                enabled: true,
+                pause: 1.0,
+                waves: [
+                    (enemies: [(x: 0.0, y: 0.0, health: 2.0)], count: 2, spawn_delay: 0.5),
+                    (enemies: [(x: 8.0, y: 0.0, health: 10.0)], timeout: Some(5.0)),
+                ],
+            )",
+        )
+        .unwrap();
+        if endless {
+            config.endless = Some(EndlessWaves {
+                count_growth: 1.,
+                health_growth: 2.,
+            });
+        }
+        config
+    }
+
+    /// Steps `scheduler` by `delta` seconds with `alive` enemies standing, returning what it
+    /// spawned and sent.
+    fn step(
+        scheduler: &mut WaveScheduler,
+        config: &WaveConfig,
+        delta: f32,
+        alive: usize,
+    ) -> (usize, Vec<WaveEvent>) {
+        let (mut spawns, mut events) = (Vec::new(), Vec::new());
+        scheduler.step(config, 1., delta, alive, &mut spawns, &mut events);
+        (spawns.len(), events)
+    }
+
+    #[test]
+    fn waves_start_after_the_pause_and_spawn_one_enemy_at_a_time() {
+        let config = config(false);
+        let mut scheduler = WaveScheduler::new(&config);
+        assert_eq!(step(&mut scheduler, &config, 0.5, 0), (0, vec![]));
+        assert_eq!(
+            step(&mut scheduler, &config, 0.5, 0),
+            (1, vec![WaveEvent::Started(1)])
+        );
+        assert_eq!(step(&mut scheduler, &config, 0.25, 1), (0, vec![]));
+        assert_eq!(step(&mut scheduler, &config, 0.25, 1), (1, vec![]));
+    }
+
+    #[test]
+    fn a_cleared_wave_is_followed_by_the_next() {
+        let config = config(false);
+        let mut scheduler = WaveScheduler::new(&config);
+        step(&mut scheduler, &config, 1., 0);
+        step(&mut scheduler, &config, 0.5, 1);
+        // Not cleared while its enemies stand, however long it takes.
+        assert_eq!(step(&mut scheduler, &config, 60., 2), (0, vec![]));
+        assert_eq!(
+            step(&mut scheduler, &config, 0.1, 0),
+            (0, vec![WaveEvent::Cleared(1)])
+        );
+        assert_eq!(
+            step(&mut scheduler, &config, 1., 0),
+            (1, vec![WaveEvent::Started(2)])
+        );
+    }
+
+    #[test]
+    fn a_wave_that_times_out_moves_on_uncleared() {
+        let config = config(true);
+        let mut scheduler = WaveScheduler::new(&config);
+        step(&mut scheduler, &config, 1., 0);
+        step(&mut scheduler, &config, 0.5, 1);
+        step(&mut scheduler, &config, 0.1, 0);
+        step(&mut scheduler, &config, 1., 0);
+        assert_eq!(step(&mut scheduler, &config, 4., 1), (0, vec![]));
+        assert_eq!(step(&mut scheduler, &config, 1., 1), (0, vec![]));
+        // The ogres of the endless wave after it come without a delay, both at once.
+        assert_eq!(
+            step(&mut scheduler, &config, 1., 1),
+            (2, vec![WaveEvent::Started(3)])
+        );
+    }
+
+    #[test]
+    fn nothing_comes_once_the_waves_run_out() {
+        let config = config(false);
+        let mut scheduler = WaveScheduler::new(&config);
+        for _ in 0..4 {
+            step(&mut scheduler, &config, 1., 0);
+        }
+        assert_eq!(
+            step(&mut scheduler, &config, 1., 0),
+            (0, vec![WaveEvent::Cleared(2)])
+        );
+        assert_eq!(step(&mut scheduler, &config, 60., 0), (0, vec![]));
+        assert_eq!(step(&mut scheduler, &config, 60., 0), (0, vec![]));
+    }
+
+    #[test]
+    fn endless_waves_grow_each_time_the_last_comes_back() {
+        let config = config(true);
+        assert!(self::config(false).wave(2, 1.).is_none());
+        let (wave, spawns) = config.wave(3, 1.).unwrap();
+        assert_eq!(wave.count, 3);
+        let health: Vec<f32> = spawns.iter().map(|spawn| spawn.health).collect();
+        assert_eq!(health, [40., 40., 40.]);
+        // Scaled waves keep at least one of each enemy.
+        assert_eq!(config.wave(1, 0.1).unwrap().1.len(), 1);
+    }
+}
@@ -0,0 +1,355 @@
+//! Rain and snow drawn over the world, below the UI.
+//!
+//! Particles live in world space inside an emission area that covers every camera currently
+//! being rendered, plus a margin. They are spawned along the top of that area, upwind of the
+//! view, so they drift in from off screen instead of appearing in view, and are culled once they
+//! leave it. They are drawn as debug lines on the `WeatherOverlay` entity.
+
+use amethyst::{
+    core::{
+        math::{Point3, Vector2},
+        timing::Time,
+        transform::Transform,
+    },
+    ecs::prelude::{
+        Component, Join, NullStorage, Read, ReadExpect, ReadStorage, System, Write, WriteStorage,
+    },
+    renderer::{debug_drawing::DebugLinesComponent, palette::Srgba},
+    window::ScreenDimensions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{view_half_extents, viewport_size, CameraZoom},
+    movement::world_position,
+    quality::QualityTier,
+    rng::Rng,
+    split_screen::{SplitScreen, SplitView},
+};
+
+/// Depth of the particles, in front of the tiles and sprites.
+const WEATHER_DEPTH: f32 = 0.5;
+
+/// The seed the particle positions are drawn from. Weather is purely cosmetic, so it doesn't
+/// share the gameplay seed.
+const WEATHER_SEED: u64 = 0x0057_4541_5448_4552;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum WeatherKind {
+    #[default]
+    Clear,
+    Rain,
+    Snow,
+}
+
+impl WeatherKind {
+    /// How fast particles fall, in world units per second.
+    fn fall_speed(self) -> f32 {
+        match self {
+            WeatherKind::Clear => 0.,
+            WeatherKind::Rain => 600.,
+            WeatherKind::Snow => 60.,
+        }
+    }
+
+    /// How far a particle reaches from its position, in world units.
+    fn particle_size(self) -> f32 {
+        match self {
+            WeatherKind::Clear => 0.,
+            WeatherKind::Rain => 12.,
+            WeatherKind::Snow => 2.,
+        }
+    }
+
+    fn color(self) -> Srgba {
+        match self {
+            WeatherKind::Clear => Srgba::new(0., 0., 0., 0.),
+            WeatherKind::Rain => Srgba::new(0.7, 0.75, 0.9, 0.6),
+            WeatherKind::Snow => Srgba::new(1., 1., 1., 0.9),
+        }
+    }
+}
+
+/// The weather of a level, as given in its tile map.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct WeatherConfig {
+    pub kind: WeatherKind,
+    /// Particles spawned per second for each world unit of emission width.
+    pub density: f32,
+    /// Horizontal wind speed in world units per second; positive blows to the right.
+    #[serde(default)]
+    pub wind: f32,
+}
+
+/// The world-space rectangle particles are spawned into and culled from.
+#[derive(Clone, Copy, Debug)]
+pub struct EmissionArea {
+    pub min: Vector2<f32>,
+    pub max: Vector2<f32>,
+}
+
+/// The current weather and its live particles.
+#[derive(Debug)]
+pub struct Weather {
+    pub config: WeatherConfig,
+    /// The most particles alive at once.
+    pub particle_cap: usize,
+    particles: Vec<Vector2<f32>>,
+    /// Fractional particles owed from previous frames, so low densities still spawn steadily.
+    spawn_debt: f32,
+    rng: Rng,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather::new(WeatherConfig::default())
+    }
+}
+
+impl Weather {
+    pub fn new(config: WeatherConfig) -> Self {
+        Weather {
+            config,
+            particle_cap: usize::MAX,
+            particles: Vec::new(),
+            spawn_debt: 0.,
+            rng: Rng::new(WEATHER_SEED),
+        }
+    }
+
+    /// Moves the live particles by `offset`.
+    pub fn translate(&mut self, offset: Vector2<f32>) {
+        for particle in &mut self.particles {
+            *particle += offset;
+        }
+    }
+
+    fn velocity(&self) -> Vector2<f32> {
+        Vector2::new(self.config.wind, -self.config.kind.fall_speed())
+    }
+
+    /// The horizontal range particles may occupy in `area`. It reaches upwind by however far the
+    /// wind carries a particle while it falls through the area, so the whole view stays covered.
+    fn horizontal_range(&self, area: &EmissionArea) -> (f32, f32) {
+        let fall_speed = self.config.kind.fall_speed();
+        let drift = if fall_speed > 0. {
+            self.config.wind * (area.max.y - area.min.y) / fall_speed
+        } else {
+            0.
+        };
+        (area.min.x - drift.max(0.), area.max.x - drift.min(0.))
+    }
+
+    /// Moves the particles on by `delta` seconds, culls those that left `area` and spawns new ones
+    /// along its top edge.
+    pub fn update(&mut self, area: &EmissionArea, delta: f32) {
+        if self.config.kind == WeatherKind::Clear {
+            self.particles.clear();
+            self.spawn_debt = 0.;
+            return;
+        }
+
+        let motion = self.velocity() * delta;
+        let (left, right) = self.horizontal_range(area);
+        self.particles.retain(|particle| {
+            let moved = particle + motion;
+            moved.y >= area.min.y && moved.x >= left && moved.x <= right
+        });
+        for particle in &mut self.particles {
+            *particle += motion;
+        }
+
+        self.spawn_debt += self.config.density.max(0.) * (right - left) * delta;
+        let count = self.spawn_debt.floor();
+        self.spawn_debt -= count;
+        let room = self.particle_cap.saturating_sub(self.particles.len());
+        for _ in 0..(count as usize).min(room) {
+            let x = self.rng.range(left, right);
+            self.particles.push(Vector2::new(x, area.max.y));
+        }
+    }
+
+    fn draw(&self, lines: &mut DebugLinesComponent) {
+        let kind = self.config.kind;
+        let (size, color) = (kind.particle_size(), kind.color());
+        let point = |position: Vector2<f32>| Point3::new(position.x, position.y, WEATHER_DEPTH);
+        match kind {
+            WeatherKind::Clear => {}
+            WeatherKind::Rain => {
+                // Streak back along the direction of travel.
+                let tail = -self.velocity().normalize() * size;
+                for particle in &self.particles {
+                    lines.add_line(point(*particle), point(particle + tail), color);
+                }
+            }
+            WeatherKind::Snow => {
+                for particle in &self.particles {
+                    let (across, down) = (Vector2::new(size, 0.), Vector2::new(0., size));
+                    lines.add_line(point(particle - across), point(particle + across), color);
+                    lines.add_line(point(particle - down), point(particle + down), color);
+                }
+            }
+        }
+    }
+}
+
+/// Marks the entity whose debug lines draw the weather.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WeatherOverlay;
+
+impl Component for WeatherOverlay {
+    type Storage = NullStorage<Self>;
+}
+
+/// Updates the `Weather` around the cameras being rendered, with no more particles than the
+/// `QualityTier` allows, and redraws it.
+pub struct WeatherSystem;
+
+impl<'s> System<'s> for WeatherSystem {
+    type SystemData = (
+        ReadStorage<'s, CameraZoom>,
+        ReadStorage<'s, SplitView>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, WeatherOverlay>,
+        WriteStorage<'s, DebugLinesComponent>,
+        Write<'s, Weather>,
+        Read<'s, SplitScreen>,
+        ReadExpect<'s, ScreenDimensions>,
+        Read<'s, QualityTier>,
+        Read<'s, Time>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            zooms,
+            split_views,
+            transforms,
+            overlays,
+            mut lines,
+            mut weather,
+            split_screen,
+            dimensions,
+            quality,
+            time,
+        ): Self::SystemData,
+    ) {
+        let screen = Vector2::new(dimensions.width(), dimensions.height());
+        let margin = weather.config.kind.particle_size();
+        let area = (&zooms, split_views.maybe(), &transforms)
+            .join()
+            // Split views are only drawn while split-screen is on, and the others only while it
+            // is off.
+            .filter(|(_, split_view, _)| split_view.is_some() == split_screen.enabled)
+            .map(|(zoom, split_view, transform)| {
+                let half = view_half_extents(
+                    viewport_size(split_view, &split_screen, screen),
+                    zoom.level(),
+                );
+                let center = world_position(transform);
+                (center - half, center + half)
+            })
+            .fold(None, |area: Option<EmissionArea>, (min, max)| {
+                Some(match area {
+                    Some(area) => EmissionArea {
+                        min: Vector2::new(area.min.x.min(min.x), area.min.y.min(min.y)),
+                        max: Vector2::new(area.max.x.max(max.x), area.max.y.max(max.y)),
+                    },
+                    None => EmissionArea { min, max },
+                })
+            });
+        let area = match area {
+            Some(area) => EmissionArea {
+                min: area.min - Vector2::new(margin, margin),
+                max: area.max + Vector2::new(margin, margin),
+            },
+            None => return,
+        };
+
+        weather.particle_cap = quality.max_particles;
+        weather.update(&area, time.delta_seconds());
+        for (_, lines) in (&overlays, &mut lines).join() {
+            lines.clear();
+            weather.draw(lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(width: f32, height: f32) -> EmissionArea {
+        EmissionArea {
+            min: Vector2::new(0., 0.),
+            max: Vector2::new(width, height),
+        }
+    }
+
+    fn weather(kind: WeatherKind, density: f32, wind: f32) -> Weather {
+        Weather::new(WeatherConfig {
+            kind,
+            density,
+            wind,
+        })
+    }
+
+    #[test]
+    fn particles_spawn_at_the_density_along_the_top() {
+        // Snow falls a hundredth of the way down the area in a second.
+        let (tall, mut snow) = (area(100., 6000.), weather(WeatherKind::Snow, 0.5, 0.));
+        for _ in 0..8 {
+            snow.update(&tall, 0.125);
+        }
+        assert_eq!(snow.particles.len(), 50);
+
+        // Too thin to spawn one a frame, it still spawns one every four.
+        let mut thin = weather(WeatherKind::Snow, 0.0625, 0.);
+        let counts: Vec<_> = (0..8)
+            .map(|_| {
+                thin.update(&area(16., 6000.), 0.25);
+                thin.particles.len()
+            })
+            .collect();
+        assert_eq!(counts, [0, 0, 0, 1, 1, 1, 1, 2]);
+
+        let mut capped = weather(WeatherKind::Snow, 0.5, 0.);
+        capped.particle_cap = 20;
+        capped.update(&tall, 1.);
+        assert_eq!(capped.particles.len(), 20);
+    }
+
+    #[test]
+    fn particles_leaving_the_area_are_culled() {
+        // Rain falls through 300 units in half a second.
+        let (area, mut rain) = (area(100., 300.), weather(WeatherKind::Rain, 0.5, 0.));
+        rain.update(&area, 0.25);
+        let spawned = rain.particles.len();
+        assert!(spawned > 0);
+        rain.config.density = 0.;
+        rain.update(&area, 0.25);
+        rain.update(&area, 0.25);
+        // Just at the bottom edge, then past it.
+        assert_eq!(rain.particles.len(), spawned);
+        rain.update(&area, 0.25);
+        assert!(rain.particles.is_empty());
+
+        // Blown sideways faster than it falls, some leaves by the side before reaching the
+        // bottom.
+        let mut windy = weather(WeatherKind::Rain, 0.5, 900.);
+        windy.update(&area, 0.125);
+        windy.config.density = 0.;
+        let spawned = windy.particles.len();
+        let (_, right) = windy.horizontal_range(&area);
+        windy.update(&area, 0.125);
+        windy.update(&area, 0.125);
+        assert!(windy.particles.len() < spawned);
+        assert!(windy.particles.iter().all(|particle| particle.x <= right));
+
+        let mut clear = weather(WeatherKind::Rain, 0.5, 0.);
+        clear.update(&area, 0.25);
+        clear.config.kind = WeatherKind::Clear;
+        clear.update(&area, 0.25);
+        assert!(clear.particles.is_empty());
+    }
+}
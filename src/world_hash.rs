@@ -0,0 +1,202 @@
+//! A stable hash of the gameplay state, taken after every fixed step, for spotting the step at
+//! which two runs that should be identical drift apart.
+//!
+//! The hash only covers what decides how the game plays out: the position, velocity and health
+//! of everything that moves or can be hurt, and the state of the gameplay `Rng`. Two runs from
+//! the same seed fed the same inputs produce the same sequence of hashes.
+
+use amethyst::{
+    core::transform::Transform,
+    ecs::prelude::{Entities, Join, Read, ReadExpect, ReadStorage, System, Write},
+};
+use log::debug;
+
+use crate::{clock::GameClock, combat::Health, movement::Velocity, rng::Rng};
+
+/// 64-bit FNV-1a. Unlike the standard library's hashers its output is fixed forever, so hashes
+/// can be compared across builds and machines.
+#[derive(Clone, Copy, Debug)]
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        StateHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StateHasher {
+    pub fn write_u8(&mut self, byte: u8) {
+        self.0 ^= u64::from(byte);
+        self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        value
+            .to_le_bytes()
+            .iter()
+            .for_each(|byte| self.write_u8(*byte));
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        value
+            .to_le_bytes()
+            .iter()
+            .for_each(|byte| self.write_u8(*byte));
+    }
+
+    /// Hashes the exact bit pattern, so even the smallest difference in a value shows up.
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The hash of the gameplay state after the step `tick`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorldHash {
+    pub tick: u64,
+    pub value: u64,
+}
+
+/// Hashes the gameplay state into `WorldHash` and logs it. Runs last among the gameplay systems.
+pub struct WorldHashSystem;
+
+impl<'s> System<'s> for WorldHashSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Velocity>,
+        ReadStorage<'s, Health>,
+        ReadExpect<'s, Rng>,
+        Read<'s, GameClock>,
+        Write<'s, WorldHash>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, transforms, velocities, healths, rng, clock, mut world_hash): Self::SystemData,
+    ) {
+        let mut hasher = StateHasher::default();
+        // Joins visit entities in index order, which is the same for the same sequence of spawns.
+        for (entity, transform, velocity, health) in
+            (&entities, &transforms, velocities.maybe(), healths.maybe()).join()
+        {
+            if velocity.is_none() && health.is_none() {
+                continue;
+            }
+            hasher.write_u32(entity.id());
+            let translation = transform.translation();
+            hasher.write_f32(translation.x.as_f32());
+            hasher.write_f32(translation.y.as_f32());
+            // Tag which optional parts are present so a missing part can't alias a present one.
+            match velocity {
+                Some(velocity) => {
+                    hasher.write_u8(1);
+                    hasher.write_f32(velocity.0.x);
+                    hasher.write_f32(velocity.0.y);
+                }
+                None => hasher.write_u8(0),
+            }
+            match health {
+                Some(health) => {
+                    hasher.write_u8(1);
+                    hasher.write_f32(health.current);
+                    hasher.write_f32(health.max);
+                }
+                None => hasher.write_u8(0),
+            }
+        }
+        hasher.write_u64(rng.state());
+
+        *world_hash = WorldHash {
+            tick: clock.ticks(),
+            value: hasher.finish(),
+        };
+        debug!(
+            "World hash at tick {}: {:016x}",
+            world_hash.tick, world_hash.value
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::{
+        ecs::prelude::{Builder, RunNow, World},
+        input::VirtualKeyCode,
+    };
+
+    use super::*;
+    use crate::{
+        movement::MovementSystem,
+        player::{
+            tests::{bind, press_key},
+            Player, PlayerMovementSystem,
+        },
+    };
+
+    /// The hashes of a run from `seed` with a player walking right, who turns up at `turn`,
+    /// over ten steps of a sixtieth of a second.
+    fn run(seed: u64, turn: usize) -> Vec<u64> {
+        let mut world = World::new();
+        let mut player_movement = PlayerMovementSystem;
+        let mut movement = MovementSystem;
+        let mut hashing = WorldHashSystem;
+        System::setup(&mut player_movement, &mut world.res);
+        System::setup(&mut movement, &mut world.res);
+        world.add_resource(Rng::new(seed));
+        System::setup(&mut hashing, &mut world.res);
+        bind(
+            &mut world,
+            r#"(
+                axes: {
+                    "horizontal": Emulated(pos: Key(D), neg: Key(A)),
+                    "vertical": Emulated(pos: Key(W), neg: Key(S)),
+                },
+                actions: {},
+            )"#,
+        );
+        world
+            .create_entity()
+            .with(Transform::default())
+            .with(Velocity::default())
+            .with(Health::new(10.))
+            .with(Player {
+                index: 0,
+                speed: 60.,
+            })
+            .build();
+
+        press_key(&mut world, VirtualKeyCode::D, true);
+        (0..10)
+            .map(|tick| {
+                if tick == turn {
+                    press_key(&mut world, VirtualKeyCode::D, false);
+                    press_key(&mut world, VirtualKeyCode::W, true);
+                }
+                world.write_resource::<GameClock>().advance(1. / 60.);
+                world.write_resource::<Rng>().next_u64();
+                player_movement.run_now(&world.res);
+                movement.run_now(&world.res);
+                hashing.run_now(&world.res);
+                world.read_resource::<WorldHash>().value
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_same_seed_and_inputs_hash_the_same() {
+        assert_eq!(run(7, 4), run(7, 4));
+    }
+
+    #[test]
+    fn other_inputs_or_seeds_hash_differently() {
+        let (first, turned_later) = (run(7, 4), run(7, 6));
+        assert_eq!(first[..4], turned_later[..4]);
+        assert!(first[4..].iter().all(|hash| !turned_later.contains(hash)));
+        assert!(run(8, 4).iter().zip(&first).all(|(a, b)| a != b));
+    }
+}
@@ -0,0 +1,306 @@
+//! Text placed in the world, such as signs and labels, that scrolls and scales with the camera.
+//!
+//! Unlike a plain `UiText`, a `WorldText` is positioned and sized in world units. Each one is
+//! drawn through a UI text of its own that the `WorldTextSystem` moves to where the text's
+//! entity appears on screen and scales with the camera's zoom every frame.
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::transform::Transform,
+    ecs::prelude::{
+        Builder, Component, DenseVecStorage, Entities, Entity, Join, Read, ReadExpect, ReadStorage,
+        System, World, WriteStorage,
+    },
+    ui::{Anchor, FontAsset, FontHandle, LineMode, UiText, UiTransform},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::CameraMatrices, interaction::Interactable, movement::world_position, strings::Strings,
+    ui_theme::UiTheme,
+};
+
+/// Approximate glyph sizes of a font, as fractions of the font size.
+#[derive(Clone, Copy, Debug)]
+pub struct GlyphMetrics {
+    /// Average horizontal advance of one character.
+    pub advance: f32,
+    /// Distance between two lines.
+    pub line_height: f32,
+}
+
+impl Default for GlyphMetrics {
+    /// Roughly the metrics of the default UI font.
+    fn default() -> Self {
+        GlyphMetrics {
+            advance: 0.5,
+            line_height: 1.2,
+        }
+    }
+}
+
+impl GlyphMetrics {
+    /// How many characters of text `size` units high fit in `width` units. At least one, so
+    /// wrapping always makes progress.
+    pub fn chars_per_line(&self, size: f32, width: f32) -> usize {
+        ((width / (size * self.advance)).floor() as usize).max(1)
+    }
+}
+
+/// Breaks `content` into lines of at most `max_chars` characters.
+///
+/// Lines break between words where possible; a word longer than a whole line is split. Line
+/// breaks already in `content` are kept.
+pub fn wrap_lines(content: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in content.lines() {
+        let mut line = String::new();
+        let mut line_len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            if line_len > 0 && line_len + 1 + word.len() > max_chars {
+                lines.push(std::mem::take(&mut line));
+                line_len = 0;
+            }
+            while word.len() > max_chars {
+                let rest = word.split_off(max_chars);
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+            if line_len > 0 {
+                line.push(' ');
+                line_len += 1;
+            }
+            line_len += word.len();
+            line.extend(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Text drawn at its entity's position, `size` world units high.
+#[derive(Clone, Debug)]
+pub struct WorldText {
+    /// A string id, shown in the current locale, or else the text itself.
+    pub content: String,
+    pub size: f32,
+    /// Width in world units at which lines wrap. `None` keeps each line of `content` whole.
+    pub wrap_width: Option<f32>,
+    /// The UI text this is drawn through, once created.
+    label: Option<Entity>,
+}
+
+impl WorldText {
+    pub fn new(content: String, size: f32, wrap_width: Option<f32>) -> Self {
+        WorldText {
+            content,
+            size,
+            wrap_width,
+            label: None,
+        }
+    }
+}
+
+impl Component for WorldText {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Marks the UI text drawing the `WorldText` of `owner`.
+#[derive(Clone, Copy, Debug)]
+pub struct WorldTextLabel {
+    pub owner: Entity,
+}
+
+impl Component for WorldTextLabel {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// A sign placed by the map.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Sign {
+    /// Centre of the text in world units.
+    pub x: f32,
+    pub y: f32,
+    pub text: String,
+    #[serde(default = "Sign::default_size")]
+    pub size: f32,
+    #[serde(default)]
+    pub wrap_width: Option<f32>,
+}
+
+impl Sign {
+    fn default_size() -> f32 {
+        12.
+    }
+}
+
+/// Creates an entity with a `WorldText` for every sign, which the players can read up close.
+pub fn spawn_signs(world: &mut World, signs: &[Sign]) {
+    for sign in signs {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(sign.x, sign.y, 0.);
+        world
+            .create_entity()
+            .with(transform)
+            .with(WorldText::new(
+                sign.text.clone(),
+                sign.size,
+                sign.wrap_width,
+            ))
+            .with(Interactable::default())
+            .build();
+    }
+}
+
+/// Keeps the UI text of every `WorldText` over its entity and sized to the camera's zoom.
+pub struct WorldTextSystem {
+    pub metrics: GlyphMetrics,
+    font: Option<FontHandle>,
+}
+
+impl WorldTextSystem {
+    pub fn new(metrics: GlyphMetrics) -> Self {
+        WorldTextSystem {
+            metrics,
+            font: None,
+        }
+    }
+}
+
+impl<'s> System<'s> for WorldTextSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, WorldText>,
+        WriteStorage<'s, WorldTextLabel>,
+        ReadStorage<'s, Transform>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiText>,
+        Read<'s, CameraMatrices>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<FontAsset>>,
+        Read<'s, UiTheme>,
+        ReadExpect<'s, Strings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut world_texts,
+            mut labels,
+            transforms,
+            mut ui_transforms,
+            mut ui_texts,
+            camera,
+            loader,
+            fonts,
+            theme,
+            strings,
+        ): Self::SystemData,
+    ) {
+        let zoom = camera.pixels_per_unit();
+
+        for (owner, world_text, transform) in (&entities, &mut world_texts, &transforms).join() {
+            let label = match world_text.label {
+                Some(label) => label,
+                None => {
+                    let font = self
+                        .font
+                        .get_or_insert_with(|| theme.font(&loader, &fonts))
+                        .clone();
+                    let mut text = UiText::new(font, String::new(), theme.text_color, 1.);
+                    text.line_mode = LineMode::Wrap;
+                    let label = entities
+                        .build_entity()
+                        .with(
+                            UiTransform::new(
+                                "world_text".to_string(),
+                                Anchor::BottomLeft,
+                                Anchor::Middle,
+                                0.,
+                                0.,
+                                0.,
+                                0.,
+                                0.,
+                            ),
+                            &mut ui_transforms,
+                        )
+                        .with(text, &mut ui_texts)
+                        .with(WorldTextLabel { owner }, &mut labels)
+                        .build();
+                    world_text.label = Some(label);
+                    label
+                }
+            };
+
+            let content = strings.get_or(&world_text.content);
+            let lines = match world_text.wrap_width {
+                Some(width) => wrap_lines(
+                    &content,
+                    self.metrics
+                        .chars_per_line(theme.text_size(world_text.size), width),
+                ),
+                None => content.lines().map(str::to_string).collect(),
+            };
+            let longest = lines
+                .iter()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0);
+
+            let position = camera.world_to_screen(world_position(transform));
+            let font_size = theme.text_size(world_text.size) * zoom;
+            if let Some(ui_transform) = ui_transforms.get_mut(label) {
+                ui_transform.local_x = position.x;
+                ui_transform.local_y = position.y;
+                // A little wider than the longest line, so the UI never wraps on its own.
+                ui_transform.width = (longest as f32 + 1.) * font_size * self.metrics.advance;
+                ui_transform.height = lines.len() as f32 * font_size * self.metrics.line_height;
+            }
+            if let Some(ui_text) = ui_texts.get_mut(label) {
+                ui_text.font_size = font_size;
+                ui_text.color = theme.text_color(theme.text_color);
+                ui_text.text = lines.join("\n");
+            }
+        }
+
+        // Labels whose text is gone go with it.
+        for (label, WorldTextLabel { owner }) in (&entities, &labels).join() {
+            if !world_texts.contains(*owner) {
+                entities.delete(label).expect("World text label is alive");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_lines_break_between_words() {
+        assert_eq!(
+            wrap_lines("the old mill is north of here", 12),
+            ["the old mill", "is north of", "here"]
+        );
+        assert_eq!(wrap_lines("short", 12), ["short"]);
+        assert_eq!(wrap_lines("two\nlines", 12), ["two", "lines"]);
+    }
+
+    #[test]
+    fn a_word_longer_than_a_line_is_split() {
+        assert_eq!(
+            wrap_lines("see abcdefghijklmn now", 5),
+            ["see", "abcde", "fghij", "klmn", "now"]
+        );
+        assert_eq!(wrap_lines("abcdefghij", 5), ["abcde", "fghij"]);
+    }
+
+    #[test]
+    fn at_least_one_character_fits_a_line() {
+        let metrics = GlyphMetrics::default();
+        assert_eq!(metrics.chars_per_line(2., 10.), 10);
+        assert_eq!(metrics.chars_per_line(20., 1.), 1);
+    }
+}